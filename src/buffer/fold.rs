@@ -0,0 +1,195 @@
+//! Code folding
+//!
+//! A fold is a collapsed `(start, end)` line range, inclusive: `start` stays
+//! visible as the fold's header and the lines after it up to `end` are
+//! hidden. Folds are kept in `Buffer::folds` and follow edits that add or
+//! remove lines.
+
+use super::Buffer;
+
+impl Buffer {
+    /// The outermost fold hiding `line`, if any
+    pub fn fold_hiding(&self, line: usize) -> Option<(usize, usize)> {
+        self.folds
+            .iter()
+            .filter(|&&(start, end)| start < line && line <= end)
+            .min_by_key(|&&(start, _)| start)
+            .copied()
+    }
+
+    /// The collapsed fold whose header is `line`
+    pub fn fold_at(&self, line: usize) -> Option<(usize, usize)> {
+        self.folds
+            .iter()
+            .filter(|&&(start, _)| start == line)
+            .max_by_key(|&&(_, end)| end)
+            .copied()
+    }
+
+    /// First visible line after `line` (`line_count()` past the end)
+    pub fn next_visible_line(&self, line: usize) -> usize {
+        let mut next = line + 1;
+        while let Some((_, end)) = self.fold_hiding(next) {
+            next = end + 1;
+        }
+        next.min(self.line_count())
+    }
+
+    /// Last visible line before `line`, if there is one
+    pub fn prev_visible_line(&self, line: usize) -> Option<usize> {
+        let mut prev = line.checked_sub(1)?;
+        while let Some((start, _)) = self.fold_hiding(prev) {
+            prev = start;
+        }
+        Some(prev)
+    }
+
+    /// Screen row of `line` when `viewport_line` is the top row; None if the
+    /// line is above the viewport or hidden in a fold
+    pub fn screen_row(&self, viewport_line: usize, line: usize) -> Option<usize> {
+        if line < viewport_line || self.fold_hiding(line).is_some() {
+            return None;
+        }
+        if self.folds.is_empty() {
+            return Some(line - viewport_line);
+        }
+        let mut row = 0;
+        let mut current = viewport_line;
+        while current < line {
+            current = self.next_visible_line(current);
+            row += 1;
+        }
+        Some(row)
+    }
+
+    /// Line shown `row` rows below `viewport_line` (may be past the last line)
+    pub fn line_at_row(&self, viewport_line: usize, row: usize) -> usize {
+        if self.folds.is_empty() {
+            return viewport_line + row;
+        }
+        let mut line = viewport_line;
+        for _ in 0..row {
+            if line >= self.line_count() {
+                return line + 1;
+            }
+            line = self.next_visible_line(line);
+        }
+        line
+    }
+
+    /// Foldable ranges from indentation: a line followed by more deeply
+    /// indented lines folds up to the last of them (trailing blank lines stay
+    /// visible)
+    pub fn indent_fold_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        // Lines whose block is still open, with their indent
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        let mut last_text_line = 0;
+        for line in 0..self.line_count() {
+            let text = self.line_str(line).unwrap_or_default();
+            if text.trim().is_empty() {
+                continue;
+            }
+            let indent = indent_width(&text);
+            while let Some(&(start, start_indent)) = open.last() {
+                if start_indent < indent {
+                    break;
+                }
+                open.pop();
+                if last_text_line > start {
+                    ranges.push((start, last_text_line));
+                }
+            }
+            open.push((line, indent));
+            last_text_line = line;
+        }
+        for (start, _) in open {
+            if last_text_line > start {
+                ranges.push((start, last_text_line));
+            }
+        }
+        ranges.sort_unstable();
+        ranges
+    }
+
+    /// Keep folds in place after `added` lines were inserted at `line`
+    pub(super) fn shift_folds_for_insert(&mut self, line: usize, added: usize) {
+        for fold in &mut self.folds {
+            if fold.0 > line {
+                fold.0 += added;
+                fold.1 += added;
+            } else if fold.1 >= line {
+                fold.1 += added;
+            }
+        }
+    }
+
+    /// Keep folds in place after the line breaks between `first` and `last`
+    /// were deleted; folds the deletion cuts into are dropped
+    pub(super) fn shift_folds_for_delete(&mut self, first: usize, last: usize) {
+        let removed = last - first;
+        self.folds.retain_mut(|fold| {
+            if fold.0 > last {
+                fold.0 -= removed;
+                fold.1 -= removed;
+                true
+            } else if fold.1 < first {
+                true
+            } else if fold.0 <= first && last <= fold.1 && fold.1 - removed > fold.0 {
+                fold.1 -= removed;
+                true
+            } else {
+                false
+            }
+        });
+    }
+}
+
+/// Indentation in columns, counting a tab as four
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "fn main() {\n    if x {\n        y();\n    }\n\n    z();\n}\n";
+
+    #[test]
+    fn test_indent_fold_ranges() {
+        let buffer = Buffer::from_str(SOURCE);
+        assert_eq!(buffer.indent_fold_ranges(), vec![(0, 5), (1, 2)]);
+    }
+
+    #[test]
+    fn test_visible_lines_skip_folds() {
+        let mut buffer = Buffer::from_str(SOURCE);
+        buffer.folds.push((1, 2));
+        assert_eq!(buffer.next_visible_line(1), 3);
+        assert_eq!(buffer.prev_visible_line(3), Some(1));
+        assert_eq!(buffer.screen_row(0, 3), Some(2));
+        assert_eq!(buffer.screen_row(0, 2), None);
+        assert_eq!(buffer.line_at_row(0, 2), 3);
+    }
+
+    #[test]
+    fn test_folds_follow_edits() {
+        let mut buffer = Buffer::from_str(SOURCE);
+        buffer.folds.push((1, 2));
+        buffer.insert(0, "// header\n");
+        assert_eq!(buffer.folds, vec![(2, 3)]);
+        let start = buffer.line_col_to_char(0, 0);
+        let end = buffer.line_col_to_char(1, 0);
+        buffer.delete(start, end);
+        assert_eq!(buffer.folds, vec![(1, 2)]);
+        // Deleting the fold's header line drops it
+        let start = buffer.line_col_to_char(0, 11);
+        let end = buffer.line_col_to_char(1, 10);
+        buffer.delete(start, end);
+        assert!(buffer.folds.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+//! Indentation style
+//!
+//! A buffer indents with tabs or with some number of spaces. Loading a file
+//! guesses which from its indented lines and keeps the guess in
+//! `Buffer::indent`; a file with no indented lines follows the workspace's
+//! `use_spaces` and `tab_width`.
+
+use std::collections::HashMap;
+
+/// Lines looked at when guessing a file's indentation
+const DETECT_LINES: usize = 1000;
+
+/// Widest space indentation step taken as one level
+const MAX_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+impl Indent {
+    /// What one level of indentation inserts
+    pub fn unit(&self) -> String {
+        match self {
+            Indent::Tabs => "\t".to_string(),
+            Indent::Spaces(width) => " ".repeat(*width),
+        }
+    }
+
+    /// Status bar label, e.g. "Spaces: 4"
+    pub fn label(&self) -> String {
+        match self {
+            Indent::Tabs => "Tabs".to_string(),
+            Indent::Spaces(width) => format!("Spaces: {}", width),
+        }
+    }
+
+    /// Guess from `text`'s indented lines: tabs if most of them start with
+    /// one, otherwise spaces by the most common step in indentation between
+    /// neighbouring lines. None if no line is indented.
+    pub fn detect(text: &str) -> Option<Self> {
+        let (mut tab_lines, mut space_lines) = (0, 0);
+        let mut steps: HashMap<usize, usize> = HashMap::new();
+        let mut previous = 0;
+        for line in text.lines().take(DETECT_LINES) {
+            let rest = line.trim_start_matches([' ', '\t']);
+            // Blank lines, and the ` * ` of block comments, say nothing
+            if rest.is_empty() || rest.starts_with('*') {
+                continue;
+            }
+            if line.starts_with('\t') {
+                tab_lines += 1;
+                continue;
+            }
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            if spaces > 0 {
+                space_lines += 1;
+            }
+            let step = spaces.abs_diff(previous);
+            if (2..=MAX_WIDTH).contains(&step) {
+                *steps.entry(step).or_default() += 1;
+            }
+            previous = spaces;
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            return None;
+        }
+        if tab_lines > space_lines {
+            return Some(Indent::Tabs);
+        }
+        // Most common step, the narrower one on a tie
+        let width = steps
+            .into_iter()
+            .max_by_key(|&(step, count)| (count, std::cmp::Reverse(step)))
+            .map_or(4, |(step, _)| step);
+        Some(Indent::Spaces(width))
+    }
+}
+
+/// `leading` whitespace rewritten in the `to` style, where one level is
+/// `width` columns (a tab reaches the next multiple of `width`); columns
+/// short of a whole level stay spaces
+pub fn convert_leading(leading: &str, width: usize, to: Indent) -> String {
+    let width = width.max(1);
+    let columns = leading.chars().fold(0, |col, c| if c == '\t' { (col / width + 1) * width } else { col + 1 });
+    match to {
+        Indent::Tabs => format!("{}{}", "\t".repeat(columns / width), " ".repeat(columns % width)),
+        Indent::Spaces(_) => " ".repeat(columns),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Indent::detect("fn main() {\n    if x {\n        y();\n    }\n}\n"), Some(Indent::Spaces(4)));
+        assert_eq!(Indent::detect("a:\n  b:\n    c: 1\n  d: 2\n"), Some(Indent::Spaces(2)));
+        assert_eq!(Indent::detect("int main() {\n\tif (x) {\n\t\ty();\n\t}\n}\n"), Some(Indent::Tabs));
+        assert_eq!(Indent::detect("/**\n * Doc\n */\nfn f() {\n    g();\n}\n"), Some(Indent::Spaces(4)));
+        assert_eq!(Indent::detect("no\nindent\n"), None);
+    }
+
+    #[test]
+    fn test_convert_leading() {
+        assert_eq!(convert_leading("        ", 4, Indent::Tabs), "\t\t");
+        assert_eq!(convert_leading("      ", 4, Indent::Tabs), "\t  ");
+        assert_eq!(convert_leading("\t\t", 2, Indent::Spaces(2)), "    ");
+        assert_eq!(convert_leading("  \t", 4, Indent::Spaces(4)), "    ");
+    }
+}
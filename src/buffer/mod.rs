@@ -1,3 +1,7 @@
+mod fold;
+mod indent;
 mod rope;
 
-pub use rope::Buffer;
+pub use indent::{convert_leading, Indent};
+#[allow(unused_imports)]
+pub use rope::{Buffer, Edit, LineEnding};
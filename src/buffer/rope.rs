@@ -1,12 +1,59 @@
 use anyhow::Result;
-use ropey::Rope;
+use ropey::{Rope, RopeBuilder};
+use std::cell::OnceCell;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashSet;
 use std::fs::File;
 use std::hash::{Hash, Hasher};
-use std::io::{BufReader, BufWriter};
+use std::io::{BufWriter, Read, Write};
 use std::path::Path;
 
+use super::Indent;
+
+/// Files larger than this open in large-file mode (see `Buffer::large`)
+pub const LARGE_FILE_BYTES: u64 = 32 * 1024 * 1024;
+
+/// Bytes read at a time when loading a large file
+const CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Edits kept for `take_edits` before the log gives up
+const MAX_EDITS: usize = 256;
+
+/// One insertion or deletion, for telling a language server only what
+/// changed. Positions are (line, column in UTF-16 code units) in the text as
+/// it was just before the edit, the way the LSP counts them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    /// Inserted text, empty for a deletion
+    pub text: String,
+}
+
+/// Line terminator used when saving. Text is always held with `\n` only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// Detect from the first line break in `text` (platform default if none)
+    pub fn detect(text: &str) -> Self {
+        match text.find('\n') {
+            Some(i) if i > 0 && text.as_bytes()[i - 1] == b'\r' => LineEnding::CrLf,
+            Some(_) => LineEnding::Lf,
+            None => LineEnding::default(),
+        }
+    }
+}
+
+impl Default for LineEnding {
+    fn default() -> Self {
+        if cfg!(windows) { LineEnding::CrLf } else { LineEnding::Lf }
+    }
+}
+
 /// Text buffer using rope data structure for efficient editing
 #[derive(Debug)]
 pub struct Buffer {
@@ -14,6 +61,24 @@ pub struct Buffer {
     pub modified: bool,
     /// Cached content hash (invalidated on modification)
     cached_hash: Option<u64>,
+    /// Line terminator written on save (detected on load)
+    pub line_ending: LineEnding,
+    /// Indentation detected on load, see `indent.rs` (None: the workspace
+    /// default applies)
+    pub indent: Option<Indent>,
+    /// Collapsed fold regions (start_line, end_line inclusive), see `fold.rs`
+    pub folds: Vec<(usize, usize)>,
+    /// Loaded from a file over `LARGE_FILE_BYTES`. It was read in chunks,
+    /// and the editor skips work over the whole text for it: only the lines
+    /// on screen are highlighted, search looks near the cursor, and there's
+    /// no language server sync, bracket matching or word completion.
+    pub large: bool,
+    /// Edits since `take_edits` was last called, in order; None once there
+    /// were too many to keep, or the whole text was replaced
+    edits: Option<Vec<Edit>>,
+    /// Length of every line, built on the first `line_len` and patched by
+    /// each edit after that, so cursor movement doesn't walk the rope
+    line_lens: OnceCell<Vec<usize>>,
 }
 
 impl Default for Buffer {
@@ -28,6 +93,12 @@ impl Buffer {
             text: Rope::new(),
             modified: false,
             cached_hash: None,
+            line_ending: LineEnding::default(),
+            indent: None,
+            folds: Vec::new(),
+            large: false,
+            edits: Some(Vec::new()),
+            line_lens: OnceCell::new(),
         }
     }
 
@@ -37,32 +108,93 @@ impl Buffer {
             text: Rope::from_str(s),
             modified: false,
             cached_hash: None,
+            line_ending: LineEnding::default(),
+            indent: None,
+            folds: Vec::new(),
+            large: false,
+            edits: Some(Vec::new()),
+            line_lens: OnceCell::new(),
         }
     }
 
+    /// Load a file, normalizing CRLF to LF (restored on save)
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let text = Rope::from_reader(reader)?;
+        let path = path.as_ref();
+        if std::fs::metadata(path)?.len() > LARGE_FILE_BYTES {
+            return Self::load_large(path);
+        }
+        let content = std::fs::read_to_string(path)?;
+        let line_ending = LineEnding::detect(&content);
+        let indent = Indent::detect(&content);
+        let text = if content.contains('\r') {
+            Rope::from_str(&content.replace("\r\n", "\n"))
+        } else {
+            Rope::from_str(&content)
+        };
         Ok(Self {
             text,
             modified: false,
             cached_hash: None,
+            line_ending,
+            indent,
+            folds: Vec::new(),
+            large: false,
+            edits: Some(Vec::new()),
+            line_lens: OnceCell::new(),
+        })
+    }
+
+    /// Load a large file a chunk at a time, so it's never held twice;
+    /// line endings and indentation are detected from the first chunk
+    fn load_large(path: &Path) -> Result<Self> {
+        let (text, first_chunk) = read_chunked(File::open(path)?, CHUNK_BYTES)?;
+        Ok(Self {
+            text,
+            modified: false,
+            cached_hash: None,
+            line_ending: LineEnding::detect(&first_chunk),
+            indent: Indent::detect(&first_chunk),
+            folds: Vec::new(),
+            large: true,
+            edits: Some(Vec::new()),
+            line_lens: OnceCell::new(),
         })
     }
 
     pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         let file = File::create(path)?;
-        let writer = BufWriter::new(file);
-        self.text.write_to(writer)?;
+        let mut writer = BufWriter::new(file);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
         self.modified = false;
         Ok(())
     }
 
+    /// Write the text as it goes on disk, with the file's line endings
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self.line_ending {
+            LineEnding::Lf => self.text.write_to(writer)?,
+            LineEnding::CrLf => {
+                for chunk in self.text.chunks() {
+                    writer.write_all(chunk.replace('\n', "\r\n").as_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Insert text at character index
     pub fn insert(&mut self, char_idx: usize, text: &str) {
         let idx = char_idx.min(self.text.len_chars());
+        let added = text.matches('\n').count();
+        let at = self.edit_position(idx);
+        if added > 0 && !self.folds.is_empty() {
+            self.shift_folds_for_insert(at.0, added);
+        }
+        self.log_edit(Edit { start: at, end: at, text: text.to_string() });
+        let lines_before = self.text.len_lines();
         self.text.insert(idx, text);
+        self.patch_line_lens(at.0, at.0, lines_before);
         self.modified = true;
         self.cached_hash = None; // Invalidate hash cache
     }
@@ -72,7 +204,15 @@ impl Buffer {
         let start = start.min(self.text.len_chars());
         let end = end.min(self.text.len_chars());
         if start < end {
+            let edit = Edit { start: self.edit_position(start), end: self.edit_position(end), text: String::new() };
+            let (first, last) = (edit.start.0, edit.end.0);
+            if last > first && !self.folds.is_empty() {
+                self.shift_folds_for_delete(first, last);
+            }
+            self.log_edit(edit);
+            let lines_before = self.text.len_lines();
             self.text.remove(start..end);
+            self.patch_line_lens(first, last, lines_before);
             self.modified = true;
             self.cached_hash = None; // Invalidate hash cache
         }
@@ -108,17 +248,24 @@ impl Buffer {
 
     /// Get character count for a line (excluding newline)
     pub fn line_len(&self, line_idx: usize) -> usize {
-        self.line(line_idx)
-            .map(|l| {
-                let len = l.len_chars();
-                // Subtract 1 for newline if not last line
-                if line_idx + 1 < self.text.len_lines() && len > 0 {
-                    len - 1
-                } else {
-                    len
-                }
-            })
-            .unwrap_or(0)
+        // A large file's lengths would take long to list and much memory
+        if self.large {
+            return rope_line_len(&self.text, line_idx);
+        }
+        let lens = self.line_lens.get_or_init(|| (0..self.text.len_lines()).map(|l| rope_line_len(&self.text, l)).collect());
+        lens.get(line_idx).copied().unwrap_or(0)
+    }
+
+    /// Bring the line lengths up to date after an edit of lines
+    /// `first..=last`, when the text had `lines_before` lines
+    fn patch_line_lens(&mut self, first: usize, last: usize, lines_before: usize) {
+        let Some(lens) = self.line_lens.get_mut() else { return };
+        // The line before can change too: a `\r` it ends with may now pair
+        // with a `\n`, or no longer
+        let first = first.saturating_sub(1);
+        let new_last = (last + self.text.len_lines()).saturating_sub(lines_before);
+        let patched = (first..=new_last).map(|l| rope_line_len(&self.text, l));
+        lens.splice(first..=last.min(lens.len().saturating_sub(1)), patched);
     }
 
     /// Convert (line, col) to absolute char index
@@ -217,8 +364,33 @@ impl Buffer {
         hash
     }
 
+    /// Edits made since the last call, oldest first, and start a new log.
+    /// None if they weren't all kept: only the whole text will do.
+    pub fn take_edits(&mut self) -> Option<Vec<Edit>> {
+        self.edits.replace(Vec::new())
+    }
+
+    fn log_edit(&mut self, edit: Edit) {
+        if let Some(edits) = &mut self.edits {
+            if edits.len() < MAX_EDITS {
+                edits.push(edit);
+            } else {
+                self.edits = None;
+            }
+        }
+    }
+
+    /// Line and UTF-16 column of `char_idx`
+    fn edit_position(&self, char_idx: usize) -> (usize, usize) {
+        let line = self.text.char_to_line(char_idx);
+        let line_start = self.text.line_to_char(line);
+        (line, self.text.char_to_utf16_cu(char_idx) - self.text.char_to_utf16_cu(line_start))
+    }
+
     /// Replace entire buffer content (used for backup restoration)
     pub fn set_contents(&mut self, content: &str) {
+        self.edits = None;
+        self.line_lens = OnceCell::new();
         self.text = Rope::from_str(content);
         self.modified = true;
         self.cached_hash = None; // Invalidate hash cache
@@ -337,10 +509,93 @@ impl Buffer {
     }
 }
 
+/// Character count of line `line_idx` of `text`, without its newline
+fn rope_line_len(text: &Rope, line_idx: usize) -> usize {
+    if line_idx >= text.len_lines() {
+        return 0;
+    }
+    let len = text.line(line_idx).len_chars();
+    // Subtract 1 for newline if not last line
+    if line_idx + 1 < text.len_lines() && len > 0 {
+        len - 1
+    } else {
+        len
+    }
+}
+
+/// Build a rope from `reader` `chunk_bytes` at a time, normalizing CRLF to
+/// LF; also returns the first chunk's text
+fn read_chunked(mut reader: impl Read, chunk_bytes: usize) -> Result<(Rope, String)> {
+    let mut builder = RopeBuilder::new();
+    let mut first_chunk = None;
+    let mut chunk = vec![0; chunk_bytes];
+    // Read but not yet added: the start of a character split between
+    // chunks, or a '\r' that may begin a CRLF
+    let mut pending: Vec<u8> = Vec::new();
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        pending.extend_from_slice(&chunk[..read]);
+        let mut end = match std::str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(e.into()),
+        };
+        if pending[..end].ends_with(b"\r") {
+            end -= 1;
+        }
+        let text = std::str::from_utf8(&pending[..end])?;
+        if text.contains('\r') {
+            builder.append(&text.replace("\r\n", "\n"));
+        } else {
+            builder.append(text);
+        }
+        if first_chunk.is_none() && !text.is_empty() {
+            first_chunk = Some(text.to_string());
+        }
+        pending.drain(..end);
+    }
+    builder.append(std::str::from_utf8(&pending)?);
+    Ok((builder.finish(), first_chunk.unwrap_or_default()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_line_lens_follow_edits() {
+        let mut buffer = Buffer::from_str("one\ntwo\r\nthree\n");
+        assert_eq!(buffer.line_len(1), 4);
+        buffer.insert(2, "x\ny\n");
+        buffer.delete(0, 1);
+        buffer.insert(buffer.len_chars(), "tail");
+        buffer.delete(9, 10); // the \r of "two\r"
+        buffer.insert(12, "\r");
+        buffer.delete(3, 14);
+        for line in 0..buffer.line_count() + 1 {
+            assert_eq!(buffer.line_len(line), rope_line_len(&buffer.text, line), "line {}", line);
+        }
+        assert_eq!(buffer.line_lens.get().map(Vec::len), Some(buffer.line_count()));
+    }
+
+    #[test]
+    fn test_take_edits() {
+        let mut buffer = Buffer::from_str("a😀b\ncd\n");
+        buffer.insert(3, "x");
+        buffer.delete(1, 6);
+        let edits = buffer.take_edits().unwrap();
+        assert_eq!(edits[0], Edit { start: (0, 4), end: (0, 4), text: "x".to_string() });
+        assert_eq!(edits[1], Edit { start: (0, 1), end: (1, 1), text: String::new() });
+        assert_eq!(buffer.take_edits(), Some(Vec::new()));
+
+        buffer.set_contents("new");
+        assert_eq!(buffer.take_edits(), None);
+        assert_eq!(buffer.take_edits(), Some(Vec::new()));
+    }
+
     #[test]
     fn test_new_buffer() {
         let buf = Buffer::new();
@@ -377,6 +632,33 @@ mod tests {
         assert_eq!(buf.char_to_line_col(6), (1, 0));
     }
 
+    #[test]
+    fn test_crlf_round_trip() {
+        let path = std::env::temp_dir().join(format!("fackr-crlf-{}.txt", std::process::id()));
+        std::fs::write(&path, "one\r\ntwo\r\n").unwrap();
+
+        let mut buf = Buffer::load(&path).unwrap();
+        assert_eq!(buf.line_ending, LineEnding::CrLf);
+        assert_eq!(buf.line_str(0), Some("one".to_string()));
+        assert_eq!(buf.line_len(0), 3);
+
+        buf.insert(buf.line_col_to_char(1, 3), "\nthree");
+        buf.save(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\r\ntwo\r\nthree\r\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_read_chunked() {
+        let text = "one\r\ntwo é\r\n€\r\n";
+        for chunk_bytes in 1..8 {
+            let (rope, first_chunk) = read_chunked(text.as_bytes(), chunk_bytes).unwrap();
+            assert_eq!(rope.to_string(), "one\ntwo é\n€\n");
+            assert!(text.starts_with(&first_chunk) && !first_chunk.is_empty());
+        }
+        assert!(read_chunked(&b"ok\xff"[..], 2).is_err());
+    }
+
     #[test]
     fn test_delete() {
         let mut buf = Buffer::from_str("Hello World");
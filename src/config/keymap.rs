@@ -0,0 +1,477 @@
+//! Key chord to command bindings
+//!
+//! The editor dispatches key presses through a `Keymap` instead of matching
+//! chords directly. Bindings start from `DEFAULT_BINDINGS` and are then
+//! overridden by `~/.config/fackr/keymap.toml` and the workspace's
+//! `.fackr/keymap.toml`, in that order:
+//!
+//! ```toml
+//! [bindings]
+//! "Ctrl+W" = "close-pane"     # rebind a chord
+//! "Alt+Q" = ""                # unbind a default
+//! F6 = "search-files"
+//! ```
+//!
+//! Commands are the IDs shown in the command palette.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::toml::{self, Value};
+use crate::input::{Key, Modifiers};
+
+/// Built-in bindings; several chords may map to one command (the first is
+/// the one shown in the palette and help menu)
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    // System
+    ("Ctrl+Q", "quit"),
+    ("Ctrl+S", "save"),
+    ("Alt+R", "refresh-document"),
+    ("Alt+Z", "suspend"),
+    ("Ctrl+B", "toggle-explorer"),
+    ("F3", "toggle-explorer"),
+
+    // Undo/Redo
+    ("Ctrl+Z", "undo"),
+    ("Ctrl+]", "redo"),
+    ("Ctrl+Shift+Z", "redo"),
+    ("Ctrl+5", "redo"), // Ctrl+] reports as Ctrl+5 on some terminals
+
+    // Clipboard
+    ("Ctrl+C", "copy"),
+    ("Ctrl+X", "cut"),
+    ("Ctrl+V", "paste"),
+    ("Ctrl+Alt+V", "paste-special"),
+
+    // Multi-cursor
+    ("Ctrl+Alt+Up", "cursor-above"),
+    ("Ctrl+Alt+Down", "cursor-below"),
+    ("Alt+U", "cursor-remove-last"),
+    ("Ctrl+Alt+Right", "cursor-rotate-next"),
+    ("Ctrl+Alt+Left", "cursor-rotate-prev"),
+    ("Ctrl+Alt+B", "block-select"),
+
+    // Lines
+    ("Alt+Up", "move-line-up"),
+    ("Alt+Down", "move-line-down"),
+    ("Alt+Shift+Down", "duplicate-line"),
+    ("Alt+Shift+Up", "duplicate-line-up"),
+    ("Ctrl+Shift+K", "delete-line"),
+    ("Ctrl+Shift+D", "duplicate-selection"),
+    ("Ctrl+J", "join-lines"),
+    ("Ctrl+/", "toggle-comment"),
+    ("Ctrl+_", "toggle-comment"),
+    ("Ctrl+7", "toggle-comment"),
+
+    // Movement
+    ("Alt+B", "word-left"),
+    ("Alt+F", "word-right"),
+    ("Alt+Shift+Left", "select-word-left"),
+    ("Alt+Shift+Right", "select-word-right"),
+    ("Ctrl+A", "line-start"),
+    ("Ctrl+Shift+A", "select-line-start"),
+    ("Ctrl+E", "line-end"),
+    ("Ctrl+Shift+E", "select-line-end"),
+    ("Ctrl+G", "goto-line"),
+    ("F5", "goto-line"),
+    ("Alt+M", "set-mark"),
+    ("Alt+;", "jump-mark-line"),
+    ("Alt+`", "jump-mark"),
+    ("Ctrl+Alt+K", "toggle-bookmark"),
+    ("Ctrl+Alt+L", "next-bookmark"),
+    ("Ctrl+Alt+J", "prev-bookmark"),
+
+    // Selection
+    ("Ctrl+L", "select-line"),
+    ("Ctrl+D", "select-word"),
+    ("Ctrl+Shift+L", "select-all-occurrences"),
+    ("Alt+Shift+I", "cursors-to-line-ends"),
+
+    // Search
+    ("Ctrl+F", "find"),
+    ("Ctrl+R", "replace"),
+    ("Ctrl+Alt+H", "replace"),
+    ("Shift+F3", "find-prev"),
+    ("F4", "search-files"),
+    ("Shift+F4", "live-grep"),
+
+    // Files
+    ("Ctrl+O", "open"),
+    ("Ctrl+P", "command-palette"),
+    ("Ctrl+Shift+P", "quick-open"),
+    ("Alt+O", "alternate-file"),
+    ("Ctrl+Alt+N", "project-notes"),
+
+    // Editing
+    ("Alt+Enter", "open-link"),
+    ("Ctrl+H", "delete-backward"),
+    ("Ctrl+W", "delete-word-backward"),
+    ("Alt+Backspace", "delete-word-backward"),
+    ("Alt+D", "delete-word-forward"),
+    ("Alt+!", "filter-command"),
+    ("Alt+|", "insert-command-output"),
+    ("Ctrl+K", "kill-to-line-end"),
+    ("Ctrl+U", "kill-to-line-start"),
+    ("Ctrl+Y", "yank"),
+    ("Alt+Y", "yank-cycle"),
+    ("Ctrl+T", "transpose"),
+    ("F7", "macro-record"),
+    ("F8", "macro-replay"),
+
+    // Brackets & quotes
+    ("Alt+[", "jump-bracket"),
+    ("Alt+]", "jump-bracket"),
+    ("Alt+'", "cycle-quotes"),
+    ("Alt+\"", "remove-surrounding"),
+    ("Alt+(", "cycle-brackets"),
+    ("Alt+)", "remove-surrounding-brackets"),
+
+    // Folding
+    ("Alt+-", "toggle-fold"),
+    ("Alt+_", "fold-all"),
+    ("Alt+0", "unfold-all"),
+    ("Alt+W", "toggle-wrap"),
+
+    // Panes
+    ("Alt+V", "split-vertical"),
+    ("Alt+S", "split-horizontal"),
+    ("Alt+Q", "close-pane"),
+    ("Alt+H", "pane-left"),
+    ("Alt+J", "pane-down"),
+    ("Alt+K", "pane-up"),
+    ("Alt+L", "pane-right"),
+    ("Alt+N", "next-pane"),
+    ("Alt+P", "prev-pane"),
+    ("Alt+Left", "pane-back"),
+    ("Alt+Right", "pane-forward"),
+    ("Ctrl+Alt+-", "navigate-back"),
+    ("Ctrl+Alt+=", "navigate-forward"),
+
+    // Tabs
+    ("Alt+1", "tab-1"),
+    ("Alt+2", "tab-2"),
+    ("Alt+3", "tab-3"),
+    ("Alt+4", "tab-4"),
+    ("Alt+5", "tab-5"),
+    ("Alt+6", "tab-6"),
+    ("Alt+7", "tab-7"),
+    ("Alt+8", "tab-8"),
+    ("Alt+9", "tab-9"),
+    ("Alt+.", "next-tab"),
+    ("Alt+,", "prev-tab"),
+    ("Alt+>", "next-workspace"),
+    ("Alt+<", "prev-workspace"),
+    ("Alt+T", "new-tab"),
+
+    // LSP
+    ("F12", "goto-definition"),
+    ("Shift+F12", "find-references"),
+    ("F1", "hover"),
+    ("Ctrl+N", "completion"),
+    ("F2", "rename"),
+    ("F9", "code-lens"),
+    ("Ctrl+.", "code-actions"),
+    ("Alt+E", "diagnostics-panel"),
+    ("Ctrl+Alt+M", "server-manager"),
+
+    // Git
+    ("Alt+F5", "next-hunk"),
+    ("Alt+Shift+F5", "prev-hunk"),
+    ("F6", "compare-head"),
+    ("Shift+F6", "compare-backup"),
+
+    // Help
+    ("Shift+F1", "help"),
+];
+
+/// A key together with its modifiers, e.g. `Ctrl+Shift+Z`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chord {
+    pub key: Key,
+    pub mods: Modifiers,
+}
+
+impl Chord {
+    /// Chord for a key press, folding terminal quirks into the canonical form
+    pub fn from_key(key: &Key, mods: &Modifiers) -> Self {
+        let ctrl = Modifiers { ctrl: true, ..Default::default() };
+        match key {
+            // Raw control characters some terminals send instead of a chord
+            Key::Char('\x1d') => Self { key: Key::Char(']'), mods: ctrl },
+            Key::Char('\x1f') => Self { key: Key::Char('/'), mods: ctrl },
+            Key::Char(c) if (mods.ctrl || mods.alt) && c.is_ascii_uppercase() => Self {
+                key: Key::Char(c.to_ascii_lowercase()),
+                mods: *mods,
+            },
+            _ => Self { key: key.clone(), mods: *mods },
+        }
+    }
+
+    /// Parse a chord such as `Ctrl+S`, `alt+shift+up` or `F12`
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        // A trailing "++" means the key itself is '+'
+        let (prefix, key_name) = match text.strip_suffix("++") {
+            Some(rest) => (rest, "+"),
+            None => match text.rfind('+') {
+                Some(i) if i + 1 < text.len() => (&text[..i], &text[i + 1..]),
+                _ => ("", text),
+            },
+        };
+
+        let mut mods = Modifiers::default();
+        for part in prefix.split('+').filter(|p| !p.is_empty()) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => mods.ctrl = true,
+                "alt" | "meta" | "option" => mods.alt = true,
+                "shift" => mods.shift = true,
+                _ => return None,
+            }
+        }
+
+        let key = parse_key(key_name)?;
+        let key = match key {
+            Key::Char(c) if mods.ctrl || mods.alt => Key::Char(c.to_ascii_lowercase()),
+            key => key,
+        };
+        Some(Self { key, mods })
+    }
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Char(c));
+    }
+    let key = match name.to_ascii_lowercase().as_str() {
+        "space" => Key::Char(' '),
+        "enter" | "return" => Key::Enter,
+        "tab" => Key::Tab,
+        "backtab" => Key::BackTab,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "escape" | "esc" => Key::Escape,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        lower => {
+            let n: u8 = lower.strip_prefix('f')?.parse().ok()?;
+            Key::F(n)
+        }
+    };
+    Some(key)
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.mods.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.mods.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.mods.shift {
+            write!(f, "Shift+")?;
+        }
+        match &self.key {
+            Key::Char(' ') => write!(f, "Space"),
+            Key::Char(c) => write!(f, "{}", c.to_uppercase()),
+            Key::F(n) => write!(f, "F{}", n),
+            Key::BackTab => write!(f, "Shift+Tab"),
+            Key::Null => write!(f, "Null"),
+            key => write!(f, "{:?}", key),
+        }
+    }
+}
+
+/// Chord-to-command lookup table
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// Bindings in display order: user bindings first, then defaults
+    bindings: Vec<(Chord, String)>,
+    /// Number of user bindings at the front of `bindings`
+    user_count: usize,
+    /// Problems found while reading keymap files, for the status bar
+    pub warnings: Vec<String>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .filter_map(|(chord, command)| Some((Chord::parse(chord)?, command.to_string())))
+            .collect();
+        Self { bindings, user_count: 0, warnings: Vec::new() }
+    }
+}
+
+impl Keymap {
+    /// Default bindings overridden by the user keymap, then the workspace's
+    pub fn load(workspace_keymap: &Path) -> Self {
+        let mut keymap = Self::default();
+        for path in [user_keymap_path(), workspace_keymap.to_path_buf()] {
+            if let Ok(content) = fs::read_to_string(&path) {
+                keymap.apply(&content, &path);
+            }
+        }
+        keymap
+    }
+
+    /// Apply bindings from keymap.toml content
+    pub fn apply(&mut self, content: &str, source: &Path) {
+        let name = source.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        let (entries, mut bad_lines) = toml::parse(content);
+        for entry in entries {
+            match (Chord::parse(&entry.key), entry.value) {
+                (Some(chord), Value::String(command)) if entry.section.is_empty() || entry.section == "bindings" => {
+                    self.bind(chord, command)
+                }
+                _ => bad_lines.push(entry.line),
+            }
+        }
+        bad_lines.sort_unstable();
+        for line in bad_lines {
+            self.warnings.push(format!("{}:{}: expected \"chord\" = \"command\"", name, line));
+        }
+    }
+
+    /// Bind `chord` to `command` ahead of the defaults; an empty command unbinds it
+    pub fn bind(&mut self, chord: Chord, command: String) {
+        if let Some(pos) = self.bindings.iter().position(|(c, _)| *c == chord) {
+            self.bindings.remove(pos);
+            if pos < self.user_count {
+                self.user_count -= 1;
+            }
+        }
+        if !command.is_empty() {
+            self.bindings.insert(self.user_count, (chord, command));
+            self.user_count += 1;
+        }
+    }
+
+    /// Command bound to a key press
+    pub fn lookup(&self, key: &Key, mods: &Modifiers) -> Option<&str> {
+        let chord = Chord::from_key(key, mods);
+        self.find(&chord).or_else(|| {
+            // Shifted symbols (Alt+" and friends) carry Shift on some terminals
+            match chord.key {
+                Key::Char(_) if chord.mods.shift => self.find(&Chord {
+                    mods: Modifiers { shift: false, ..chord.mods },
+                    ..chord
+                }),
+                _ => None,
+            }
+        })
+    }
+
+    fn find(&self, chord: &Chord) -> Option<&str> {
+        self.bindings
+            .iter()
+            .find(|(c, _)| c == chord)
+            .map(|(_, command)| command.as_str())
+    }
+
+    /// Chords bound to `command`, in display order
+    pub fn chords_for(&self, command: &str) -> Vec<&Chord> {
+        self.bindings
+            .iter()
+            .filter(|(_, c)| c == command)
+            .map(|(chord, _)| chord)
+            .collect()
+    }
+
+    /// Display string of the primary chord for `command`
+    pub fn shortcut(&self, command: &str) -> Option<String> {
+        self.chords_for(command).first().map(|c| c.to_string())
+    }
+
+    /// Whether the keymap decides `command`'s shortcut (it has, or had by
+    /// default, a binding), as opposed to keys handled outside the keymap
+    pub fn covers(&self, command: &str) -> bool {
+        DEFAULT_BINDINGS.iter().any(|(_, c)| *c == command)
+            || self.bindings.iter().any(|(_, c)| c == command)
+    }
+}
+
+/// Path of the per-user keymap file
+fn user_keymap_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("fackr")
+        .join("keymap.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctrl() -> Modifiers {
+        Modifiers { ctrl: true, ..Default::default() }
+    }
+
+    fn alt() -> Modifiers {
+        Modifiers { alt: true, ..Default::default() }
+    }
+
+    #[test]
+    fn test_default_bindings_all_parse() {
+        for (chord, _) in DEFAULT_BINDINGS {
+            assert!(Chord::parse(chord).is_some(), "bad default chord {}", chord);
+        }
+    }
+
+    #[test]
+    fn test_parse_and_display_chords() {
+        let chord = Chord::parse("ctrl+shift+z").unwrap();
+        assert_eq!(chord.key, Key::Char('z'));
+        assert!(chord.mods.ctrl && chord.mods.shift && !chord.mods.alt);
+        assert_eq!(chord.to_string(), "Ctrl+Shift+Z");
+
+        assert_eq!(Chord::parse("Ctrl++").unwrap().key, Key::Char('+'));
+        assert_eq!(Chord::parse("Shift+F12").unwrap().to_string(), "Shift+F12");
+        assert_eq!(Chord::parse("Alt+PageDown").unwrap().key, Key::PageDown);
+        assert!(Chord::parse("Hyper+X").is_none());
+    }
+
+    #[test]
+    fn test_user_bindings_override_defaults() {
+        let mut keymap = Keymap::default();
+        keymap.apply(
+            "[bindings]\n\"Ctrl+S\" = \"find\"  # reassign\nAlt+Q = \"\"\nF6 = \"save\"\n",
+            Path::new("keymap.toml"),
+        );
+        assert!(keymap.warnings.is_empty());
+        assert_eq!(keymap.lookup(&Key::Char('s'), &ctrl()), Some("find"));
+        assert_eq!(keymap.lookup(&Key::Char('q'), &alt()), None);
+        assert_eq!(keymap.shortcut("save").as_deref(), Some("F6"));
+        assert_eq!(keymap.shortcut("find").as_deref(), Some("Ctrl+S"));
+        assert!(keymap.covers("close-pane"));
+        assert!(!keymap.covers("terminal-height"));
+    }
+
+    #[test]
+    fn test_lookup_folds_terminal_quirks() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.lookup(&Key::Char('\x1d'), &Modifiers::default()), Some("redo"));
+        assert_eq!(keymap.lookup(&Key::Char('\x1f'), &Modifiers::default()), Some("toggle-comment"));
+        let ctrl_shift = Modifiers { shift: true, ..ctrl() };
+        assert_eq!(keymap.lookup(&Key::Char('Z'), &ctrl_shift), Some("redo"));
+        let alt_shift = Modifiers { shift: true, ..alt() };
+        assert_eq!(keymap.lookup(&Key::Char('"'), &alt_shift), Some("remove-surrounding"));
+        assert_eq!(keymap.lookup(&Key::Char('x'), &Modifiers::default()), None);
+    }
+
+    #[test]
+    fn test_malformed_lines_warn() {
+        let mut keymap = Keymap::default();
+        keymap.apply("\"Ctrl+S\" = save\n", Path::new("keymap.toml"));
+        assert_eq!(keymap.warnings, vec!["keymap.toml:1: expected \"chord\" = \"command\"".to_string()]);
+        assert_eq!(keymap.lookup(&Key::Char('s'), &ctrl()), Some("save"));
+    }
+}
@@ -0,0 +1,14 @@
+//! User configuration loaded from `~/.config/fackr/` and `.fackr/`
+//!
+//! Workspace settings live in `.fackr/config.json` (see `WorkspaceConfig`);
+//! this module holds configuration that layers a per-user file under a
+//! per-workspace one.
+
+mod keymap;
+mod settings;
+pub mod toml;
+
+pub use keymap::Keymap;
+pub use settings::Settings;
+#[cfg(any(test, feature = "harness"))]
+pub use keymap::Chord;
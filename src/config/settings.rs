@@ -0,0 +1,115 @@
+//! Per-user settings in `~/.config/fackr/config.toml`
+//!
+//! Written by the setup wizard on first launch, and fine to edit by hand:
+//!
+//! ```toml
+//! theme = "fackr-dark"
+//! tab_width = 4
+//! use_spaces = true
+//! mouse = true        # false leaves the mouse to the terminal
+//! escape_time = 5     # ms to wait after Escape for the rest of an Alt chord
+//! ```
+//!
+//! The theme and indentation are defaults for workspaces whose
+//! `.fackr/config.json` doesn't set them; `FAC_ESCAPE_TIME` overrides
+//! `escape_time`.
+
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+
+use super::toml::{self, Value};
+use crate::render::DEFAULT_THEME;
+
+/// Milliseconds to wait after Escape when nothing says otherwise, like
+/// vim's ttimeoutlen or tmux's escape-time
+pub const DEFAULT_ESCAPE_TIME: u64 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    pub theme: String,
+    pub tab_width: usize,
+    pub use_spaces: bool,
+    /// Capture the mouse for clicking, dragging and scrolling in the editor
+    pub mouse: bool,
+    pub escape_time: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: DEFAULT_THEME.to_string(),
+            tab_width: 4,
+            use_spaces: true,
+            mouse: true,
+            escape_time: DEFAULT_ESCAPE_TIME,
+        }
+    }
+}
+
+impl Settings {
+    /// Path of the per-user config file
+    pub fn path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("fackr")
+            .join("config.toml")
+    }
+
+    /// The user's settings; defaults when there is no config file
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path()).map(|content| Self::parse(&content)).unwrap_or_default()
+    }
+
+    /// Settings from config.toml content; unknown keys and values of the
+    /// wrong type are left at their defaults
+    pub fn parse(content: &str) -> Self {
+        let mut settings = Self::default();
+        for entry in toml::parse(content).0.into_iter().filter(|e| e.section.is_empty()) {
+            match (entry.key.as_str(), entry.value) {
+                ("theme", Value::String(name)) => settings.theme = name,
+                ("tab_width", Value::Integer(n)) if n > 0 => settings.tab_width = n as usize,
+                ("use_spaces", Value::Boolean(b)) => settings.use_spaces = b,
+                ("mouse", Value::Boolean(b)) => settings.mouse = b,
+                ("escape_time", Value::Integer(n)) if n >= 0 => settings.escape_time = n as u64,
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    /// The settings as config.toml content
+    pub fn to_toml(&self) -> String {
+        format!(
+            "theme = \"{}\"\ntab_width = {}\nuse_spaces = {}\nmouse = {}\nescape_time = {}\n",
+            self.theme.replace('\\', "\\\\").replace('"', "\\\""),
+            self.tab_width,
+            self.use_spaces,
+            self.mouse,
+            self.escape_time,
+        )
+    }
+
+    /// Write the settings to the per-user config file
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_toml())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let settings = Settings { theme: "solar".to_string(), tab_width: 2, use_spaces: false, mouse: false, escape_time: 25 };
+        assert_eq!(Settings::parse(&settings.to_toml()), settings);
+        let partial = Settings::parse("tab_width = 8\nmouse = \"no\"\n");
+        assert_eq!(partial, Settings { tab_width: 8, ..Settings::default() });
+    }
+}
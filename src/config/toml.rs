@@ -0,0 +1,151 @@
+//! Reader for the flat subset of TOML used by fackr's config files
+//!
+//! Supports `[section]` headers, `key = value` pairs with quoted or bare keys,
+//! basic strings, integers and booleans, and `#` comments. Bare keys are
+//! taken verbatim up to the `=` so chords like `Alt+Q` need no quoting.
+
+/// A parsed value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+/// A `key = value` pair and where it was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// 1-based line number
+    pub line: usize,
+    /// Enclosing `[section]`, empty at the top level
+    pub section: String,
+    pub key: String,
+    pub value: Value,
+}
+
+/// Parse `content`, returning the entries and the 1-based numbers of lines
+/// that could not be read
+pub fn parse(content: &str) -> (Vec<Entry>, Vec<usize>) {
+    let mut entries = Vec::new();
+    let mut bad_lines = Vec::new();
+    let mut section = String::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+        match parse_pair(line) {
+            Some((key, value)) => entries.push(Entry {
+                line: index + 1,
+                section: section.clone(),
+                key,
+                value,
+            }),
+            None => bad_lines.push(index + 1),
+        }
+    }
+
+    (entries, bad_lines)
+}
+
+/// Drop a trailing `# comment` that isn't inside a quoted string
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_pair(line: &str) -> Option<(String, Value)> {
+    let (key, rest) = if line.starts_with('"') {
+        let (key, rest) = parse_string(line)?;
+        (key, rest.trim_start().strip_prefix('=')?)
+    } else {
+        let (key, rest) = line.split_once('=')?;
+        (key.trim().to_string(), rest)
+    };
+    if key.is_empty() {
+        return None;
+    }
+
+    let rest = rest.trim();
+    let value = if rest.starts_with('"') {
+        let (value, trailing) = parse_string(rest)?;
+        if !trailing.trim().is_empty() {
+            return None;
+        }
+        Value::String(value)
+    } else if rest == "true" || rest == "false" {
+        Value::Boolean(rest == "true")
+    } else {
+        Value::Integer(rest.replace('_', "").parse().ok()?)
+    };
+    Some((key, value))
+}
+
+/// Parse a leading basic string, returning it and the remaining input
+fn parse_string(input: &str) -> Option<(String, &str)> {
+    let body = input.strip_prefix('"')?;
+    let mut value = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &body[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                '"' => value.push('"'),
+                '\\' => value.push('\\'),
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                _ => return None,
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections_keys_and_values() {
+        let (entries, bad) = parse(
+            "name = \"Night # owl\" # comment\n\n[ui]\nbg = 234\n\"Alt+\\\"\" = true\nAlt+Q = \"\"\n",
+        );
+        assert!(bad.is_empty());
+        let pairs: Vec<_> = entries
+            .iter()
+            .map(|e| (e.line, e.section.as_str(), e.key.as_str(), e.value.clone()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                (1, "", "name", Value::String("Night # owl".to_string())),
+                (4, "ui", "bg", Value::Integer(234)),
+                (5, "ui", "Alt+\"", Value::Boolean(true)),
+                (6, "ui", "Alt+Q", Value::String(String::new())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bad_lines_are_reported() {
+        let (entries, bad) = parse("ok = 1\nmissing equals\nbare = word\n= 3\n");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(bad, vec![2, 3, 4]);
+    }
+}
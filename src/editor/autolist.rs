@@ -0,0 +1,158 @@
+//! List items in Markdown and plain text
+//!
+//! Recognizes `- `, `* ` and `+ ` bullets, `1. ` and `1) ` numbers and
+//! `[ ]` / `[x]` task boxes after either, so Enter can start the next item
+//! and Tab / Shift+Tab can move an item between nesting levels.
+
+/// A line's list marker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListItem {
+    /// Leading spaces and tabs
+    pub indent: String,
+    pub marker: Marker,
+    /// Mark in the task box after the marker (` ` or `x`), if there is one
+    pub checkbox: Option<char>,
+    /// Character column where the item's text starts
+    pub content_start: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    Bullet(char),
+    /// Number and the `.` or `)` after it
+    Number(u64, char),
+}
+
+/// The list item `line` starts, if any
+pub fn parse(line: &str) -> Option<ListItem> {
+    let indent: String = line.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+    let rest = &line[indent.len()..];
+
+    let (marker, marker_len) = match rest.chars().next()? {
+        c @ ('-' | '*' | '+') => (Marker::Bullet(c), 1),
+        c if c.is_ascii_digit() => {
+            let digits = rest.chars().take_while(char::is_ascii_digit).count();
+            let delimiter = rest[digits..].chars().next().filter(|c| matches!(c, '.' | ')'))?;
+            (Marker::Number(rest[..digits].parse().ok()?, delimiter), digits + 1)
+        }
+        _ => return None,
+    };
+    let after = &rest[marker_len..];
+    if !after.starts_with(' ') {
+        return None;
+    }
+    let after = after.trim_start_matches(' ');
+    let boxed = ["[ ] ", "[x] ", "[X] "].iter().any(|b| after.starts_with(b)) || matches!(after, "[ ]" | "[x]" | "[X]");
+    let checkbox = after.chars().nth(1).filter(|_| boxed);
+    let content = if boxed { after[3..].trim_start_matches(' ') } else { after };
+    Some(ListItem { content_start: line.chars().count() - content.chars().count(), indent, marker, checkbox })
+}
+
+impl ListItem {
+    /// Prefix for the item after this one: the same bullet or the next
+    /// number, with an unticked box if this one has a box
+    pub fn next(&self) -> String {
+        let marker = match self.marker {
+            Marker::Number(n, delimiter) => Marker::Number(n + 1, delimiter),
+            bullet => bullet,
+        };
+        self.prefix(&self.indent, marker, self.checkbox.map(|_| ' '))
+    }
+
+    /// Whether the item has no text yet
+    pub fn is_empty(&self, line: &str) -> bool {
+        line.chars().skip(self.content_start).all(char::is_whitespace)
+    }
+
+    fn prefix(&self, indent: &str, marker: Marker, checkbox: Option<char>) -> String {
+        let marker = match marker {
+            Marker::Bullet(c) => c.to_string(),
+            Marker::Number(n, delimiter) => format!("{}{}", n, delimiter),
+        };
+        let checkbox = checkbox.map(|mark| format!("[{}] ", mark)).unwrap_or_default();
+        format!("{}{} {}", indent, marker, checkbox)
+    }
+
+    /// Columns of the marker and the space after it, which items nested
+    /// under this one are indented by
+    fn marker_width(&self) -> usize {
+        match self.marker {
+            Marker::Bullet(_) => 2,
+            Marker::Number(n, _) => n.to_string().len() + 2,
+        }
+    }
+}
+
+/// The prefix (up to `content_start`) for `item` moved one level deeper
+/// or shallower, given the lines above it, nearest first. Deeper lines up
+/// under the item above's text; shallower goes to its parent's level.
+/// Numbers restart at 1, or follow the item before them at the new level.
+/// None if the item can't move that way.
+pub fn renested(line: &str, above: &[&str], deeper: bool) -> Option<String> {
+    let item = parse(line)?;
+    let level = item.indent.chars().count();
+    // The list ends at a blank line; wrapped text between items is skipped
+    let items: Vec<ListItem> = above.iter().take_while(|l| !l.trim().is_empty()).filter_map(|l| parse(l)).collect();
+
+    let indent = if deeper {
+        let width = items
+            .iter()
+            .find(|i| i.indent.chars().count() == level)
+            .map_or(item.marker_width(), ListItem::marker_width);
+        format!("{}{}", item.indent, " ".repeat(width))
+    } else if let Some(parent) = items.iter().find(|i| i.indent.chars().count() < level) {
+        parent.indent.clone()
+    } else if level > 0 {
+        String::new()
+    } else {
+        return None;
+    };
+
+    let new_level = indent.chars().count();
+    let marker = match item.marker {
+        Marker::Number(_, delimiter) => {
+            let previous = items
+                .iter()
+                .take_while(|i| i.indent.chars().count() >= new_level)
+                .find(|i| i.indent.chars().count() == new_level);
+            match previous.map(|i| i.marker) {
+                Some(Marker::Number(n, _)) => Marker::Number(n + 1, delimiter),
+                _ => Marker::Number(1, delimiter),
+            }
+        }
+        bullet => bullet,
+    };
+    Some(item.prefix(&indent, marker, item.checkbox))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let item = parse("  - [x] done").unwrap();
+        assert_eq!((item.indent.as_str(), item.marker, item.checkbox, item.content_start), ("  ", Marker::Bullet('-'), Some('x'), 8));
+        assert_eq!(parse("12) twelve").unwrap().marker, Marker::Number(12, ')'));
+        assert!(parse("-not a list").is_none());
+        assert!(parse("1.5 million").is_none());
+        assert!(parse("plain").is_none());
+    }
+
+    #[test]
+    fn test_next() {
+        assert_eq!(parse("* one").unwrap().next(), "* ");
+        assert_eq!(parse("   9. nine").unwrap().next(), "   10. ");
+        assert_eq!(parse("- [x] task").unwrap().next(), "- [ ] ");
+        assert!(parse("1. ").unwrap().is_empty("1. "));
+    }
+
+    #[test]
+    fn test_renested() {
+        assert_eq!(renested("- b", &["- a"], true).as_deref(), Some("  - "));
+        assert_eq!(renested("2. b", &["1. a"], true).as_deref(), Some("   1. "));
+        assert_eq!(renested("   2. c", &["   1. b", "1. a"], false).as_deref(), Some("2. "));
+        assert_eq!(renested("  - [ ] c", &["    wrapped", "- b"], false).as_deref(), Some("- [ ] "));
+        assert_eq!(renested("- a", &[], false), None);
+    }
+}
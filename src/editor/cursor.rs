@@ -1,3 +1,5 @@
+use crate::buffer::Buffer;
+
 /// A position in the buffer (0-indexed)
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct Position {
@@ -164,15 +166,19 @@ pub struct Cursors {
     cursors: Vec<Cursor>,
     /// Index of the primary cursor (receives special treatment in some operations)
     primary: usize,
+    /// Creation order of each cursor, parallel to `cursors` (higher = added later)
+    serials: Vec<u64>,
+    /// Serial to hand out to the next added cursor
+    next_serial: u64,
+    /// Corners (anchor, head) of the block selection the cursors were laid
+    /// out from; None once they've been changed any other way
+    block: Option<(Position, Position)>,
 }
 
 #[allow(dead_code)]
 impl Cursors {
     pub fn new() -> Self {
-        Self {
-            cursors: vec![Cursor::new()],
-            primary: 0,
-        }
+        Self::from_cursor(Cursor::new())
     }
 
     /// Create a Cursors container from a single cursor
@@ -180,9 +186,20 @@ impl Cursors {
         Self {
             cursors: vec![cursor],
             primary: 0,
+            serials: vec![0],
+            next_serial: 1,
+            block: None,
         }
     }
 
+    /// Append a cursor, recording its creation order
+    fn push(&mut self, cursor: Cursor) {
+        self.block = None;
+        self.cursors.push(cursor);
+        self.serials.push(self.next_serial);
+        self.next_serial += 1;
+    }
+
     /// Get the primary cursor
     pub fn primary(&self) -> &Cursor {
         &self.cursors[self.primary]
@@ -228,7 +245,7 @@ impl Cursors {
             return false;
         }
 
-        self.cursors.push(new_cursor);
+        self.push(new_cursor);
         self.sort_and_dedupe();
         true
     }
@@ -242,13 +259,7 @@ impl Cursors {
         }
 
         if let Some(idx) = self.cursors.iter().position(|c| c.line == line && c.col == col) {
-            self.cursors.remove(idx);
-            // Adjust primary index if needed
-            if self.primary >= self.cursors.len() {
-                self.primary = self.cursors.len() - 1;
-            } else if self.primary > idx {
-                self.primary -= 1;
-            }
+            self.remove(idx);
             return true;
         }
         false
@@ -278,36 +289,73 @@ impl Cursors {
         new_cursor.anchor_col = anchor_col;
         new_cursor.selecting = true;
 
-        self.cursors.push(new_cursor);
+        self.push(new_cursor);
         self.sort_and_dedupe();
         true
     }
 
     /// Remove secondary cursors, keeping only the primary
     pub fn collapse_to_primary(&mut self) {
-        let primary = self.cursors[self.primary].clone();
-        self.cursors.clear();
-        self.cursors.push(primary);
+        self.block = None;
+        let primary = self.cursors[self.primary];
+        let serial = self.serials[self.primary];
+        self.cursors = vec![primary];
+        self.serials = vec![serial];
         self.primary = 0;
     }
 
     /// Remove cursor at the given index
     pub fn remove(&mut self, index: usize) {
         if self.cursors.len() > 1 && index < self.cursors.len() {
+            self.block = None;
             self.cursors.remove(index);
-            if self.primary >= self.cursors.len() {
-                self.primary = self.cursors.len() - 1;
+            self.serials.remove(index);
+            if index == self.primary {
+                // Hand primary to the most recently added survivor
+                self.primary = self.newest_index();
+            } else if self.primary > index {
+                self.primary -= 1;
             }
         }
     }
 
+    /// Index of the most recently added cursor
+    fn newest_index(&self) -> usize {
+        self.serials.iter()
+            .enumerate()
+            .max_by_key(|(_, serial)| **serial)
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
+
+    /// Remove the most recently added cursor (never the last one)
+    /// Returns true if a cursor was removed
+    pub fn remove_last_added(&mut self) -> bool {
+        if self.cursors.len() <= 1 {
+            return false;
+        }
+        self.remove(self.newest_index());
+        true
+    }
+
+    /// Make the next cursor in document order primary (wrapping around)
+    pub fn rotate_primary(&mut self, forward: bool) {
+        let len = self.cursors.len();
+        self.primary = if forward {
+            (self.primary + 1) % len
+        } else {
+            (self.primary + len - 1) % len
+        };
+    }
+
     /// Sort cursors by position and remove duplicates
     pub fn sort_and_dedupe(&mut self) {
         // Remember primary cursor's position
         let primary_pos = (self.cursors[self.primary].line, self.cursors[self.primary].col);
 
-        // Sort by line, then by column
-        self.cursors.sort_by(|a, b| {
+        // Sort by line, then by column, keeping each cursor's serial alongside it
+        let mut pairs: Vec<(Cursor, u64)> = self.cursors.drain(..).zip(self.serials.drain(..)).collect();
+        pairs.sort_by(|(a, _), (b, _)| {
             match a.line.cmp(&b.line) {
                 std::cmp::Ordering::Equal => a.col.cmp(&b.col),
                 ord => ord,
@@ -315,7 +363,8 @@ impl Cursors {
         });
 
         // Remove duplicates (same position)
-        self.cursors.dedup_by(|a, b| a.line == b.line && a.col == b.col);
+        pairs.dedup_by(|(a, _), (b, _)| a.line == b.line && a.col == b.col);
+        (self.cursors, self.serials) = pairs.into_iter().unzip();
 
         // Find primary cursor's new index
         self.primary = self.cursors.iter()
@@ -348,6 +397,7 @@ impl Cursors {
 
     /// Clear selection on all cursors
     pub fn clear_selections(&mut self) {
+        self.block = None;
         for cursor in &mut self.cursors {
             cursor.clear_selection();
         }
@@ -369,6 +419,51 @@ impl Cursors {
         // TODO: Merge overlapping selections (for now, just dedupe)
     }
 
+    /// Pull cursors and selection anchors back inside `buffer`, whose text
+    /// may have changed since they were placed
+    pub fn clamp_to(&mut self, buffer: &Buffer) {
+        let last_line = buffer.line_count().saturating_sub(1);
+        let clamp = |line: usize, col: usize| {
+            let line = line.min(last_line);
+            (line, col.min(buffer.line_len(line)))
+        };
+        for cursor in &mut self.cursors {
+            (cursor.line, cursor.col) = clamp(cursor.line, cursor.col);
+            (cursor.anchor_line, cursor.anchor_col) = clamp(cursor.anchor_line, cursor.anchor_col);
+        }
+        self.sort_and_dedupe();
+    }
+
+    /// Lay the cursors out as a block selection between the `anchor` and
+    /// `head` corners: one per line, selecting the columns in between.
+    /// Lines ending left of the block get no cursor, except the head's, which
+    /// becomes primary. `line_len` gives the length of a line.
+    pub fn set_block(&mut self, anchor: Position, head: Position, line_len: impl Fn(usize) -> usize) {
+        let left = anchor.col.min(head.col);
+        let (top, bottom) = (anchor.line.min(head.line), anchor.line.max(head.line));
+        self.cursors.clear();
+        self.serials.clear();
+        for line in top..=bottom {
+            let len = line_len(line);
+            if len < left && line != head.line {
+                continue;
+            }
+            let mut cursor = Cursor::at(line, head.col.min(len));
+            cursor.anchor_col = anchor.col.min(len);
+            cursor.selecting = true;
+            if line == head.line {
+                self.primary = self.cursors.len();
+            }
+            self.push(cursor);
+        }
+        self.block = Some((anchor, head));
+    }
+
+    /// Corners (anchor, head) of the current block selection
+    pub fn block(&self) -> Option<(Position, Position)> {
+        self.block
+    }
+
     /// Set cursors from a list of positions (for undo/redo)
     /// Primary cursor becomes the first position in the list
     pub fn set_from_positions(&mut self, positions: &[Position]) {
@@ -377,8 +472,9 @@ impl Cursors {
         }
 
         self.cursors.clear();
+        self.serials.clear();
         for pos in positions {
-            self.cursors.push(Cursor::at(pos.line, pos.col));
+            self.push(Cursor::at(pos.line, pos.col));
         }
         self.primary = 0;
         self.sort_and_dedupe();
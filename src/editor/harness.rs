@@ -0,0 +1,660 @@
+//! Scripted smoke tests
+//!
+//! Drives an editor on an offscreen screen from a small script, one command
+//! per line, so interactive flows (prompts, modals, multiple cursors) can be
+//! tested without a terminal:
+//!
+//! ```text
+//! # comments and blank lines are skipped
+//! file "notes.txt" "one\ntwo\n"
+//! open "notes.txt"
+//! type "hello"
+//! press Ctrl+S
+//! expect_line 0 "helloone"
+//! expect_file "notes.txt" "helloone\ntwo\n"
+//! ```
+//!
+//! `type` sends each character as a key (`\n` as Enter, `\t` as Tab);
+//! `press` takes chords as keymap.toml spells them, several per line. The
+//! checks are `expect_line <line> "text"`, `expect_cursor <line> <col>` (the
+//! primary cursor, 0-indexed like lines), `expect_cursors <count>`,
+//! `expect_message "text"` and `expect_screen "text"` (contained in the
+//! status message, or in a row of the last frame drawn) and
+//! `expect_file "path" "contents"`. A frame is drawn after every command.
+//!
+//! Scripts run in a fresh workspace under the temp directory. With the
+//! `harness` feature, `fackr --script <file>` runs one and exits non-zero at
+//! the first failing line.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use unicode_width::UnicodeWidthChar;
+
+use crate::config::Chord;
+use crate::input::{Key, Modifiers};
+use crate::render::Screen;
+
+use super::Editor;
+
+/// Size of the offscreen screen
+const COLS: u16 = 100;
+const ROWS: u16 = 30;
+
+/// Harnesses created by this process, for unique workspace directories
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// An editor in a scratch workspace, driven by script commands
+pub struct Harness {
+    /// Dropped before the workspace directory is removed
+    editor: Option<Editor>,
+    root: PathBuf,
+    grid: Grid,
+}
+
+impl Harness {
+    /// An editor in a new, empty workspace under the temp directory
+    pub fn new() -> Result<Self> {
+        let n = CREATED.fetch_add(1, Ordering::Relaxed);
+        let root = std::env::temp_dir().join(format!("fackr-script-{}-{}", std::process::id(), n));
+        std::fs::create_dir_all(&root)?;
+        let editor = Editor::new_with_screen_and_workspace(Screen::offscreen(COLS, ROWS), root.clone())?;
+        Ok(Self { editor: Some(editor), root, grid: Grid::new(COLS, ROWS) })
+    }
+
+    /// Run `script`, stopping at the first command that fails
+    pub fn run(&mut self, script: &str) -> Result<()> {
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.step(line).with_context(|| format!("line {}: {}", i + 1, line))?;
+        }
+        Ok(())
+    }
+
+    fn editor(&mut self) -> &mut Editor {
+        self.editor.as_mut().expect("editor lives as long as the harness")
+    }
+
+    fn step(&mut self, line: &str) -> Result<()> {
+        let words = tokenize(line)?;
+        let (command, args) = words.split_first().ok_or_else(|| anyhow!("empty command"))?;
+        match (command.as_str(), args) {
+            ("type", [text]) => {
+                for c in text.chars() {
+                    let key = match c {
+                        '\n' => Key::Enter,
+                        '\t' => Key::Tab,
+                        c => Key::Char(c),
+                    };
+                    self.editor().press(key, Modifiers::default())?;
+                }
+            }
+            ("press", chords) if !chords.is_empty() => {
+                for text in chords {
+                    let chord = Chord::parse(text).ok_or_else(|| anyhow!("not a key: {}", text))?;
+                    self.editor().press(chord.key, chord.mods)?;
+                }
+            }
+            ("file", [path, contents]) => {
+                let path = self.root.join(path);
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(path, contents)?;
+            }
+            ("open", [path]) => self.editor().open_relative(path)?,
+            ("expect_line", [line, text]) => {
+                let found = self.editor().line_text(number(line)?);
+                if found.as_deref() != Some(text.as_str()) {
+                    bail!("expected {:?}, found {:?}", text, found.unwrap_or_default());
+                }
+            }
+            ("expect_cursor", [line, col]) => {
+                let expected = (number(line)?, number(col)?);
+                let found = self.editor().cursor_positions()[0];
+                if found != expected {
+                    bail!("expected the cursor at {:?}, found it at {:?}", expected, found);
+                }
+            }
+            ("expect_cursors", [count]) => {
+                let found = self.editor().cursor_positions().len();
+                if found != number(count)? {
+                    bail!("expected {} cursors, found {}", count, found);
+                }
+            }
+            ("expect_message", [text]) => {
+                let found = self.editor().message().unwrap_or_default();
+                if !found.contains(text.as_str()) {
+                    bail!("expected a message with {:?}, found {:?}", text, found);
+                }
+            }
+            ("expect_screen", [text]) => {
+                let message = self.editor().message().unwrap_or_default();
+                if !message.contains(text.as_str()) && !self.grid.rows().iter().any(|row| row.contains(text.as_str())) {
+                    bail!("{:?} isn't on screen:\n{}", text, self.grid.rows().join("\n"));
+                }
+            }
+            ("expect_file", [path, contents]) => {
+                let found = std::fs::read_to_string(self.root.join(path))?;
+                if found != *contents {
+                    bail!("expected {:?}, found {:?}", contents, found);
+                }
+            }
+            _ => bail!("unknown command, or wrong arguments for it"),
+        }
+        let frame = self.editor().draw()?;
+        self.grid.feed(&frame);
+        Ok(())
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        self.editor = None;
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// `fackr --script <file>`: run a script in a scratch workspace
+#[cfg_attr(test, allow(dead_code))]
+pub fn run_script(path: &Path) -> Result<()> {
+    let script = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    Harness::new()?.run(&script)
+}
+
+fn number(word: &str) -> Result<usize> {
+    word.parse().map_err(|_| anyhow!("not a number: {}", word))
+}
+
+/// Split a script line into words and quoted strings; `\n`, `\t`, `\"`
+/// and `\\` are escapes inside quotes
+fn tokenize(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut word = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => word.push('\n'),
+                        Some('t') => word.push('\t'),
+                        Some(c @ ('"' | '\\')) => word.push(c),
+                        other => bail!("unknown escape \\{}", other.map(String::from).unwrap_or_default()),
+                    },
+                    Some(c) => word.push(c),
+                    None => bail!("unterminated string"),
+                }
+            }
+            words.push(word);
+        } else {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+/// The offscreen terminal as text: follows cursor moves and clears in what
+/// the screen writes, and ignores colors and the rest
+struct Grid {
+    cells: Vec<Vec<char>>,
+    row: usize,
+    col: usize,
+}
+
+/// Second half of a wide character
+const WIDE_TAIL: char = '\0';
+
+impl Grid {
+    fn new(cols: u16, rows: u16) -> Self {
+        Self { cells: vec![vec![' '; cols as usize]; rows as usize], row: 0, col: 0 }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) {
+        let text = String::from_utf8_lossy(bytes);
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => match chars.next() {
+                    Some('[') => {
+                        let mut params = String::new();
+                        for c in chars.by_ref() {
+                            if ('@'..='~').contains(&c) {
+                                self.csi(&params, c);
+                                break;
+                            }
+                            params.push(c);
+                        }
+                    }
+                    // Title, working directory and the like, up to BEL or ESC \
+                    Some(']') => {
+                        while let Some(c) = chars.next() {
+                            if c == '\x07' {
+                                break;
+                            }
+                            if c == '\x1b' {
+                                chars.next();
+                                break;
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                '\r' => self.col = 0,
+                '\n' => self.row += 1,
+                c if c.is_control() => {}
+                c => self.put(c),
+            }
+        }
+    }
+
+    fn csi(&mut self, params: &str, action: char) {
+        let arg = |i: usize| params.split(';').nth(i).and_then(|n| n.parse::<usize>().ok()).unwrap_or(1).max(1);
+        match action {
+            'H' | 'f' => (self.row, self.col) = (arg(0) - 1, arg(1) - 1),
+            'G' => self.col = arg(0) - 1,
+            'J' if params == "2" => self.cells.iter_mut().for_each(|row| row.fill(' ')),
+            'K' => {
+                if let Some(row) = self.cells.get_mut(self.row) {
+                    row.iter_mut().skip(self.col).for_each(|cell| *cell = ' ');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        let width = c.width().unwrap_or(0);
+        if width == 0 {
+            return;
+        }
+        if let Some(row) = self.cells.get_mut(self.row) {
+            if self.col + width <= row.len() {
+                row[self.col] = c;
+                if width == 2 {
+                    row[self.col + 1] = WIDE_TAIL;
+                }
+            }
+        }
+        self.col += width;
+    }
+
+    fn rows(&self) -> Vec<String> {
+        self.cells.iter().map(|row| row.iter().filter(|&&c| c != WIDE_TAIL).collect()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(script: &str) -> Result<()> {
+        Harness::new()?.run(script)
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize(r#"expect_line 0 "a \"b\"\n""#).unwrap(), ["expect_line", "0", "a \"b\"\n"]);
+        assert!(tokenize(r#"type "open"#).is_err());
+    }
+
+    #[test]
+    fn test_grid() {
+        let mut grid = Grid::new(10, 2);
+        grid.feed(b"\x1b]0;title\x07\x1b[2;3Hhi\x1b[38;5;1m!\x1b[1;1Hab");
+        assert_eq!(grid.rows(), ["ab        ", "  hi!     "]);
+    }
+
+    #[test]
+    fn test_edit_and_save() {
+        run(r#"
+            file "notes.txt" "one\ntwo\n"
+            open "notes.txt"
+            type "hello\n"
+            expect_line 0 "hello"
+            expect_line 1 "one"
+            expect_cursor 1 0
+            press Ctrl+S
+            expect_file "notes.txt" "hello\none\ntwo\n"
+            expect_screen "notes.txt"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_goto_line_prompt() {
+        run(r#"
+            type "a\nb\nc\nd"
+            press Ctrl+G
+            type "3\n"
+            expect_cursor 2 0
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_navigate_back_and_forward() {
+        run(r#"
+            file "a.txt" "1\n2\n3\n4\n5\n"
+            file "b.txt" "b\n"
+            open "a.txt"
+            press Ctrl+G
+            type "4\n"
+            expect_cursor 3 0
+            press Ctrl+Alt+-
+            expect_cursor 0 0
+            press Ctrl+Alt+=
+            expect_cursor 3 0
+            open "b.txt"
+            expect_line 0 "b"
+            press Ctrl+Alt+-
+            expect_line 0 "1"
+            expect_cursor 3 0
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_set_mark_and_server_manager_keys() {
+        run(r#"
+            type "one\ntwo"
+            press Alt+M
+            type "a"
+            expect_message "Mark 'a' set at line 2"
+            press Ctrl+Alt+M
+            expect_screen "Language Server Manager"
+            press Ctrl+Alt+M
+            press Alt+M
+            type "b"
+            expect_message "Mark 'b' set at line 2"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_palette_shows_terminal_keys_as_terminal_only() {
+        run(r#"
+            press Ctrl+P
+            type "maximize terminal"
+            expect_screen "Alt+M (in terminal)"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_pane_history_keeps_alt_arrows() {
+        run(r#"
+            press Alt+Left
+            expect_message "No earlier buffer in this pane"
+            press Alt+Right
+            expect_message "No later buffer in this pane"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_pinned_clipboard_entry_comes_first() {
+        run(r#"
+            type "one\ntwo\nthree\n"
+            press Up Up Up Shift+End Ctrl+C
+            press Down Home Shift+End Ctrl+C
+            press Ctrl+Alt+V Down *
+            expect_message "Pinned"
+            expect_screen "1 pinned one"
+            press Escape Down Home Shift+End Ctrl+C
+            press Down Ctrl+Alt+V 1
+            expect_line 3 "one"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_bookmarks_across_files() {
+        run(r#"
+            file "a.txt" "1\n2\n3\n"
+            file "b.txt" "x\ny\n"
+            open "a.txt"
+            press Down Ctrl+Alt+K
+            expect_message "Bookmarked line 2"
+            open "b.txt"
+            press Down Ctrl+Alt+K
+            press Up Ctrl+Alt+J
+            expect_line 0 "1"
+            expect_cursor 1 0
+            press Ctrl+Alt+L
+            expect_line 0 "x"
+            expect_cursor 1 0
+            press Ctrl+Alt+K
+            expect_message "Removed bookmark"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_minimap() {
+        run(r#"
+            file "main.rs" "fn main() {\n    x\n}\n"
+            open "main.rs"
+            press Ctrl+P
+            type "toggle minimap\n"
+            expect_message "Minimap on"
+            expect_screen " ▀█▀ "
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_indent_guides_and_whitespace() {
+        run(r#"
+            file "main.rs" "fn main() {\n        a  \n\n    b\n}\n"
+            open "main.rs"
+            press Ctrl+P
+            type "toggle indent guides\n"
+            expect_message "Indent guides on"
+            expect_screen "│   │   a  "
+            expect_screen "3 │  "
+            press Ctrl+P
+            type "cycle whitespace\n"
+            expect_message "Show whitespace: trailing"
+            expect_screen "│   │   a··"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_fortress_typed_path() {
+        run(r#"
+            file "docs/guide/notes.md" "typed path\n"
+            press Ctrl+O
+            type "docs/guide/notes.md\n"
+            expect_line 0 "typed path"
+            press Ctrl+O
+            type "docs/missing.md\n"
+            expect_message "No such file or directory"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_toggle_current_line_highlight() {
+        run(r#"
+            type "one\ntwo"
+            press Ctrl+P
+            type "toggle current line\n"
+            expect_message "Current line highlight off"
+            expect_screen "2 two"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_live_grep_modal() {
+        run(r#"
+            press Shift+F4
+            type "fn \\w+"
+            press Alt+X
+            expect_screen "Live Grep (regex)"
+            press Tab
+            type "rs"
+            expect_screen "Files:  rs"
+            press Escape
+            expect_line 0 ""
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_closing_bracket_outdents() {
+        run(r#"
+            file "a.rs" "fn f() {\n    if x {\n            \n    }\n}\n"
+            open "a.rs"
+            press Ctrl+G
+            type "3\n"
+            press End
+            type "}"
+            expect_line 2 "    }"
+            expect_cursor 2 5
+            press Ctrl+Z
+            expect_line 2 "            "
+            expect_cursor 2 12
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_closing_bracket_outdents_at_each_cursor() {
+        run(r#"
+            file "b.rs" "a {\n    x\nb {\n    x\n"
+            open "b.rs"
+            press Ctrl+G
+            type "2\n"
+            press End Shift+Left
+            press Ctrl+Shift+L
+            expect_cursors 2
+            type "}"
+            expect_line 1 "}"
+            expect_line 3 "}"
+            press Ctrl+Z
+            expect_line 1 "    "
+            expect_line 3 "    "
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_code_lenses_above_their_lines() -> Result<()> {
+        let mut harness = Harness::new()?;
+        let text: String = (1..=40).map(|n| format!("fn f{}() {{}}\n", n)).collect();
+        std::fs::write(harness.root.join("lib.rs"), text)?;
+        harness.run(r#"open "lib.rs""#)?;
+        harness.editor().set_code_lenses(&[1, 39], "Run | Debug");
+        harness.run("press Down")?;
+        let rows = harness.grid.rows();
+        let lens = rows.iter().position(|row| row.contains("Run | Debug")).expect("lens drawn");
+        assert!(rows[lens - 1].contains("1 fn f1() {}"));
+        assert!(rows[lens + 1].contains("2 fn f2() {}"));
+        assert!(rows[lens].trim_start().starts_with("Run | Debug"));
+
+        // Scrolling to the last line leaves room for its lens
+        harness.run(r#"
+            press Ctrl+G
+            type "40\n"
+            expect_cursor 39 0
+        "#)?;
+        let rows = harness.grid.rows();
+        let last = rows.iter().position(|row| row.contains("40 fn f40() {}")).expect("last line shown");
+        assert!(rows[last - 1].contains("Run | Debug"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolved_completion_edits_follow_the_insertion() -> Result<()> {
+        use crate::lsp::{Position, Range, TextEdit};
+
+        let mut harness = Harness::new()?;
+        harness.run(r#"
+            file "main.rs" "fn main() {\n    let m = ; // map\n}\n"
+            open "main.rs"
+            press Down End Left Left Left Left Left Left Left Left
+            type "Hash"
+            expect_cursor 1 16
+        "#)?;
+        // Positions are in the text from before "Hash" became "HashMap"
+        let edit = |start: (u32, u32), end: (u32, u32), text: &str| TextEdit {
+            range: Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+            new_text: text.to_string(),
+        };
+        harness.editor().accept_resolved_completion("HashMap", vec![
+            edit((0, 0), (0, 0), "use std::collections::HashMap;\n"),
+            edit((1, 18), (1, 24), "// HashMap"),
+        ]);
+        harness.run(r#"
+            expect_line 0 "use std::collections::HashMap;"
+            expect_line 1 "fn main() {"
+            expect_line 2 "    let m = HashMap; // HashMap"
+            expect_cursor 2 19
+            expect_message "Imported for HashMap"
+
+            # One undo takes back the import along with the completion
+            press Ctrl+Z
+            expect_line 0 "fn main() {"
+            expect_line 1 "    let m = Hash; // map"
+            expect_cursor 1 16
+        "#)
+    }
+
+    #[test]
+    fn test_server_edit_is_answered_with_its_outcome() -> Result<()> {
+        use crate::lsp::{path_to_uri, Position, Range, TextEdit, WorkspaceEdit};
+
+        let mut harness = Harness::new()?;
+        harness.run(r#"
+            file "notes.txt" "one\n"
+            open "notes.txt"
+        "#)?;
+        let insert = |path: &Path| {
+            let edit = TextEdit { range: Range::point(Position::new(0, 0)), new_text: "zero\n".to_string() };
+            WorkspaceEdit { changes: [(path_to_uri(&path.to_string_lossy()), vec![edit])].into(), ..Default::default() }
+        };
+
+        let edit = insert(&harness.root.join("notes.txt"));
+        let answer = harness.editor().server_edit(edit);
+        assert_eq!(answer.try_recv()?, Ok(()));
+        harness.run(r#"expect_line 0 "zero""#)?;
+
+        let edit = insert(&harness.root.join("no/such/file.txt"));
+        let answer = harness.editor().server_edit(edit);
+        assert!(answer.try_recv()?.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_cursors() {
+        run(r#"
+            type "x = 1\nx = 2\nx = 3"
+            press Ctrl+Home Shift+Right
+            press Ctrl+Shift+L
+            expect_cursors 3
+            type "y"
+            expect_line 2 "y = 3"
+        "#)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_failure_names_the_line() {
+        let error = run("type \"abc\"\n\nexpect_line 0 \"abd\"").unwrap_err();
+        assert!(format!("{:#}", error).starts_with("line 3: expect_line 0 \"abd\""));
+    }
+}
@@ -81,6 +81,8 @@ pub struct History {
     current_group: OperationGroup,
     /// Whether we're in the middle of a group (e.g., typing a word)
     grouping: bool,
+    /// Bumped whenever a group is committed, undone or redone
+    version: usize,
 }
 
 impl History {
@@ -120,6 +122,19 @@ impl History {
         self.current_group.set_cursors_after(positions);
     }
 
+    /// Continue (or start) a multi-cursor typing session.
+    /// Consecutive insertions made with the same number of cursors share one
+    /// undo group until `maybe_break_group` sees a non-typing key, matching
+    /// how single-cursor typing is grouped.
+    pub fn begin_typing_session(&mut self, cursors_before: Vec<Position>) {
+        if !self.current_group.is_empty()
+            && self.current_group.cursors_before.len() != cursors_before.len()
+        {
+            self.commit_group();
+        }
+        self.current_group.set_cursors_before(cursors_before);
+    }
+
     /// Record an insert operation
     pub fn record_insert(
         &mut self,
@@ -157,7 +172,26 @@ impl History {
         if !self.current_group.is_empty() {
             let group = std::mem::take(&mut self.current_group);
             self.undo_stack.push(group);
+            self.version += 1;
+        }
+    }
+
+    /// Names the last committed group while it is still the latest change
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Open the last committed group again for more operations, if nothing
+    /// was committed, undone or redone since `version` was taken and nothing
+    /// is being recorded. Returns whether it was reopened.
+    pub fn reopen_group(&mut self, version: usize) -> bool {
+        if version != self.version || !self.current_group.is_empty() {
+            return false;
         }
+        let Some(group) = self.undo_stack.pop() else { return false };
+        self.current_group = group;
+        self.grouping = true;
+        true
     }
 
     /// Check if we should break the current group (e.g., on non-typing command)
@@ -179,6 +213,7 @@ impl History {
                 vec![group.ops.first().map(|op| op.cursor_before()).unwrap_or_default()]
             };
             self.redo_stack.push(group.clone());
+            self.version += 1;
             Some((group.ops, cursor_positions))
         } else {
             None
@@ -195,6 +230,7 @@ impl History {
                 vec![group.ops.last().map(|op| op.cursor_after()).unwrap_or_default()]
             };
             self.undo_stack.push(group.clone());
+            self.version += 1;
             Some((group.ops, cursor_positions))
         } else {
             None
@@ -266,4 +302,49 @@ mod tests {
         assert_eq!(positions.len(), 1);
         assert_eq!(positions[0], after);
     }
+
+    #[test]
+    fn test_multi_cursor_typing_session() {
+        let mut history = History::new();
+        let start = vec![Position::new(0, 0), Position::new(1, 0)];
+
+        // Two keystrokes with two cursors each
+        for i in 0..2 {
+            let cursors = vec![Position::new(0, i), Position::new(1, i)];
+            history.begin_typing_session(cursors);
+            history.record_insert(i, "a".to_string(), Position::new(0, i), Position::new(0, i));
+            history.record_insert(i + 4, "a".to_string(), Position::new(0, i), Position::new(0, i));
+            history.set_cursors_after(vec![Position::new(0, i + 1), Position::new(1, i + 1)]);
+        }
+        history.maybe_break_group();
+
+        let (ops, positions) = history.undo().unwrap();
+        assert_eq!(ops.len(), 4);
+        assert_eq!(positions, start);
+        assert!(!history.can_undo());
+
+        let (_, positions) = history.redo().unwrap();
+        assert_eq!(positions, vec![Position::new(0, 2), Position::new(1, 2)]);
+    }
+
+    #[test]
+    fn test_reopen_group() {
+        let mut history = History::new();
+        let pos = Position::new(0, 0);
+
+        history.record_insert(0, "a".to_string(), pos, pos);
+        history.end_group();
+        let version = history.version();
+        assert!(history.reopen_group(version));
+        history.record_insert(1, "b".to_string(), pos, pos);
+        history.end_group();
+        assert_eq!(history.undo().unwrap().0.len(), 2);
+
+        // Undone since: the group is gone from the top of the stack
+        history.record_insert(0, "c".to_string(), pos, pos);
+        history.end_group();
+        let version = history.version();
+        history.undo();
+        assert!(!history.reopen_group(version));
+    }
 }
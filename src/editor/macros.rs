@@ -0,0 +1,168 @@
+//! Keyboard macros
+//!
+//! F7 starts recording the keys handled into a register (a-z, `q` when none
+//! is named) and F7 again stops; F8 replays the last macro recorded or
+//! replayed, and Replay Macro takes a register and a count, e.g. `a 5`.
+//! Keys are recorded as the editor sees them, after the Escape-prefix Alt
+//! handling, so a replay goes through the same bindings. The last macro
+//! recorded is kept in the workspace state dir (`macro.json`) and comes back
+//! with the workspace.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::input::{Key, Modifiers};
+
+/// Register recorded into when none is named
+pub const DEFAULT_REGISTER: char = 'q';
+
+/// Keys recorded into one macro at most
+const MAX_KEYS: usize = 10_000;
+
+/// Times one replay runs a macro at most
+pub const MAX_COUNT: usize = 1000;
+
+/// A recorded key sequence
+pub type Keys = Vec<(Key, Modifiers)>;
+
+/// What `macro.json` holds
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedMacro {
+    register: char,
+    keys: Keys,
+}
+
+#[derive(Debug, Default)]
+pub struct Macros {
+    registers: HashMap<char, Keys>,
+    /// Register being recorded into and the keys so far
+    recording: Option<(char, Keys)>,
+    /// Where the keys that opened the command palette start, while it's
+    /// open during a recording; stopping from the palette drops them
+    palette_from: Option<usize>,
+    /// Register F8 replays
+    pub last: Option<char>,
+    /// Whether a macro is being replayed (nothing is recorded meanwhile)
+    pub replaying: bool,
+}
+
+impl Macros {
+    /// Registers with the macro saved at `path`, if any, as the last one
+    pub fn load(path: &Path) -> Self {
+        let mut macros = Self::default();
+        macros.load_saved(path);
+        macros
+    }
+
+    /// Take in the macro saved at `path`, if any, as the last one
+    pub fn load_saved(&mut self, path: &Path) {
+        let saved = std::fs::read_to_string(path).ok().and_then(|json| serde_json::from_str::<SavedMacro>(&json).ok());
+        if let Some(saved) = saved {
+            self.registers.insert(saved.register, saved.keys);
+            self.last = Some(saved.register);
+        }
+    }
+
+    /// Write the last macro to `path`
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let Some((register, keys)) = self.last.and_then(|r| Some((r, self.registers.get(&r)?))) else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let saved = SavedMacro { register, keys: keys.clone() };
+        std::fs::write(path, serde_json::to_string(&saved)?)?;
+        Ok(())
+    }
+
+    /// Register being recorded into
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    pub fn start(&mut self, register: char) {
+        self.recording = Some((register, Vec::new()));
+        self.palette_from = None;
+    }
+
+    /// Stop recording and keep the keys in their register; returns the
+    /// register and how many keys it holds
+    pub fn stop(&mut self) -> Option<(char, usize)> {
+        let (register, mut keys) = self.recording.take()?;
+        if let Some(from) = self.palette_from.take() {
+            keys.truncate(from);
+        }
+        let len = keys.len();
+        self.registers.insert(register, keys);
+        self.last = Some(register);
+        Some((register, len))
+    }
+
+    /// Add a handled key to the recording; `in_palette` when the command
+    /// palette was open before or after it
+    pub fn record(&mut self, key: Key, mods: Modifiers, in_palette: bool) {
+        let Some((_, keys)) = &mut self.recording else { return };
+        if in_palette {
+            self.palette_from.get_or_insert(keys.len());
+        } else {
+            self.palette_from = None;
+        }
+        if keys.len() < MAX_KEYS {
+            keys.push((key, mods));
+        }
+    }
+
+    pub fn get(&self, register: char) -> Option<&Keys> {
+        self.registers.get(&register)
+    }
+}
+
+/// Register and count from Replay Macro's input: `a`, `a 5`, `5` (the last
+/// register five times) or nothing (the last register once)
+pub fn parse_replay(input: &str, last: Option<char>) -> Option<(char, usize)> {
+    let mut register = None;
+    let mut count = None;
+    for part in input.split_whitespace() {
+        match part.parse::<usize>() {
+            Ok(n) if count.is_none() => count = Some(n),
+            _ => {
+                let mut chars = part.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_lowercase() && register.is_none() => register = Some(c),
+                    _ => return None,
+                }
+            }
+        }
+    }
+    Some((register.or(last)?, count.unwrap_or(1).clamp(1, MAX_COUNT)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_replay() {
+        assert_eq!(parse_replay("a 5", None), Some(('a', 5)));
+        assert_eq!(parse_replay("3", Some('q')), Some(('q', 3)));
+        assert_eq!(parse_replay("", Some('b')), Some(('b', 1)));
+        assert_eq!(parse_replay("", None), None);
+        assert_eq!(parse_replay("ab", Some('q')), None);
+    }
+
+    #[test]
+    fn test_stop_from_palette_drops_its_keys() {
+        let mut macros = Macros::default();
+        let ctrl = Modifiers { ctrl: true, ..Default::default() };
+        macros.start('a');
+        macros.record(Key::Char('x'), Modifiers::default(), false);
+        macros.record(Key::Char('p'), ctrl, true);
+        macros.record(Key::Char('m'), Modifiers::default(), true);
+        assert_eq!(macros.stop(), Some(('a', 1)));
+        assert_eq!(macros.get('a'), Some(&vec![(Key::Char('x'), Modifiers::default())]));
+        assert_eq!(macros.last, Some('a'));
+    }
+}
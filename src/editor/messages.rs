@@ -0,0 +1,141 @@
+//! Status messages
+//!
+//! Messages queue up instead of overwriting each other. Infos go away after
+//! a few seconds, errors stay until dismissed with Escape, and the question
+//! of an open prompt stays until the prompt is answered or a message is
+//! posted in its place. Progress notes
+//! ("Saving...") give way to whatever is posted next. The newest message
+//! shows in the status bar and older ones stack above it.
+
+use std::time::{Duration, Instant};
+
+use crate::render::MessageLevel;
+
+/// How long an info message stays up
+const INFO_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Most messages kept at once; the oldest are dropped first
+const MAX_MESSAGES: usize = 5;
+
+#[derive(Debug)]
+struct Message {
+    text: String,
+    level: MessageLevel,
+    posted: Instant,
+    /// Replaced by the next message
+    progress: bool,
+}
+
+/// Queue of status messages, oldest first
+#[derive(Debug, Default)]
+pub struct Messages {
+    queue: Vec<Message>,
+    /// Question of the open prompt, shown ahead of the queue
+    prompt: Option<String>,
+}
+
+impl Messages {
+    /// Post a message that times out
+    pub fn info(&mut self, text: String) {
+        self.push(text, MessageLevel::Info, false);
+    }
+
+    /// Post a message that stays until dismissed
+    pub fn error(&mut self, text: String) {
+        self.push(text, MessageLevel::Error, false);
+    }
+
+    /// Post a note about work in progress, which times out like an info but
+    /// also goes as soon as anything else is posted
+    pub fn progress(&mut self, text: String) {
+        self.push(text, MessageLevel::Info, true);
+    }
+
+    /// Show a prompt's question until `clear_prompt`
+    pub fn prompt(&mut self, text: String) {
+        self.prompt = Some(text);
+    }
+
+    pub fn clear_prompt(&mut self) {
+        self.prompt = None;
+    }
+
+    fn push(&mut self, text: String, level: MessageLevel, progress: bool) {
+        self.prompt = None;
+        // Posting a message again moves it to the front instead of stacking a copy
+        self.queue.retain(|m| m.text != text && !m.progress);
+        self.queue.push(Message { text, level, posted: Instant::now(), progress });
+        if self.queue.len() > MAX_MESSAGES {
+            self.queue.remove(0);
+        }
+    }
+
+    /// Drop infos that have timed out; true if any were
+    pub fn expire(&mut self) -> bool {
+        let before = self.queue.len();
+        self.queue.retain(|m| m.level == MessageLevel::Error || m.posted.elapsed() < INFO_TIMEOUT);
+        self.queue.len() != before
+    }
+
+    /// Dismiss every queued message; true if there were any
+    pub fn dismiss(&mut self) -> bool {
+        let any = !self.queue.is_empty();
+        self.queue.clear();
+        any
+    }
+
+    /// Message for the status bar: the prompt's question, else the newest
+    pub fn current(&self) -> Option<(&str, MessageLevel)> {
+        match &self.prompt {
+            Some(text) => Some((text, MessageLevel::Info)),
+            None => self.queue.last().map(|m| (m.text.as_str(), m.level)),
+        }
+    }
+
+    /// Messages to stack above the status bar, newest first
+    pub fn stacked(&self) -> Vec<(&str, MessageLevel)> {
+        let skip = usize::from(self.prompt.is_none());
+        self.queue.iter().rev().skip(skip).map(|m| (m.text.as_str(), m.level)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_stack_newest_first() {
+        let mut messages = Messages::default();
+        messages.error("Backup failed".to_string());
+        messages.info("Saved".to_string());
+        assert_eq!(messages.current(), Some(("Saved", MessageLevel::Info)));
+        assert_eq!(messages.stacked(), vec![("Backup failed", MessageLevel::Error)]);
+
+        // Reposting moves a message to the front
+        messages.error("Backup failed".to_string());
+        assert_eq!(messages.current(), Some(("Backup failed", MessageLevel::Error)));
+        assert_eq!(messages.stacked(), vec![("Saved", MessageLevel::Info)]);
+    }
+
+    #[test]
+    fn test_progress_gives_way() {
+        let mut messages = Messages::default();
+        messages.progress("Saving...".to_string());
+        messages.info("Saved".to_string());
+        assert_eq!(messages.current(), Some(("Saved", MessageLevel::Info)));
+        assert!(messages.stacked().is_empty());
+    }
+
+    #[test]
+    fn test_prompt_goes_first_and_errors_persist() {
+        let mut messages = Messages::default();
+        messages.error("LSP error: timeout".to_string());
+        messages.prompt("Unsaved changes. [S]ave / [D]iscard / [C]ancel".to_string());
+        assert_eq!(messages.stacked().len(), 1);
+        messages.clear_prompt();
+        assert!(!messages.expire());
+        assert_eq!(messages.current(), Some(("LSP error: timeout", MessageLevel::Error)));
+        assert!(messages.dismiss());
+        assert_eq!(messages.current(), None);
+    }
+}
@@ -1,9 +1,23 @@
+mod autolist;
 mod cursor;
+#[cfg(any(test, feature = "harness"))]
+mod harness;
 mod history;
+mod macros;
+mod messages;
+mod paste;
+mod recording;
+mod setup;
+mod snippet;
 mod state;
+mod vim;
 mod welcome;
 
 pub use cursor::{Cursor, Cursors, Position};
+#[cfg(feature = "harness")]
+pub use harness::run_script;
 pub use history::{History, Operation};
+pub use messages::Messages;
+pub use setup::SetupWizard;
 pub use state::Editor;
 pub use welcome::WelcomeMenu;
@@ -0,0 +1,110 @@
+//! Paste variants
+//!
+//! A normal paste of several lines re-indents them to fit where they land:
+//! the block's common indentation is swapped for the cursor line's, keeping
+//! the lines' indentation relative to each other. Pasting as plain text
+//! inserts the clipboard exactly as it is.
+
+/// How a paste inserts the clipboard text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasteMode {
+    /// Re-indented to the cursor's line
+    Indented,
+    /// Exactly as copied
+    Plain,
+    /// Re-indented, then selected
+    Select,
+    /// Each selected line replaced by a clipboard line, cycling through them
+    OverLines,
+}
+
+/// `text` re-indented for a paste at a cursor with `before` to its left on
+/// its line. When `before` is all whitespace the first line is indented
+/// along with the rest; otherwise it continues the line as copied.
+pub fn reindent(text: &str, before: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    if lines.len() < 2 {
+        return text.to_string();
+    }
+    let at_indent = before.chars().all(char::is_whitespace);
+    let indent: String = before.chars().take_while(|c| c.is_whitespace()).collect();
+
+    let skip = if at_indent { 0 } else { 1 };
+    let common = lines
+        .iter()
+        .skip(skip)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| leading_whitespace(line))
+        .min()
+        .unwrap_or(0);
+
+    let mut out = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if i < skip {
+            out.push_str(line);
+        } else if !line.trim().is_empty() {
+            let rest: String = line.chars().skip(common).collect();
+            // The cursor already sits after the first line's indentation
+            if i > 0 {
+                out.push_str(&indent);
+            }
+            out.push_str(&rest);
+        }
+    }
+    out
+}
+
+/// Text for `count` lines pasted over, cycling through the clipboard's
+/// lines (a trailing newline doesn't add an empty one)
+pub fn cycle_lines(text: &str, count: usize) -> String {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    let lines: Vec<&str> = text.split('\n').collect();
+    (0..count).map(|i| lines[i % lines.len()]).collect::<Vec<_>>().join("\n")
+}
+
+/// First line of a clipboard entry for a list, noting how many follow
+pub fn preview(text: &str) -> String {
+    let mut lines = text.trim_end_matches('\n').lines();
+    let first = lines.next().unwrap_or("").trim().to_string();
+    match lines.count() {
+        0 => first,
+        more => format!("{} (+{} lines)", first, more),
+    }
+}
+
+fn leading_whitespace(line: &str) -> usize {
+    line.chars().take_while(|c| *c == ' ' || *c == '\t').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reindent_at_indentation() {
+        let text = "        if x {\n            y();\n        }\n";
+        assert_eq!(reindent(text, "    "), "if x {\n        y();\n    }\n");
+        assert_eq!(reindent("one line", "    "), "one line");
+    }
+
+    #[test]
+    fn test_reindent_mid_line() {
+        let text = "foo(\n\t\ta,\n\n\t\tb)";
+        assert_eq!(reindent(text, "    let x = "), "foo(\n    a,\n\n    b)");
+    }
+
+    #[test]
+    fn test_cycle_lines() {
+        assert_eq!(cycle_lines("a\nb\n", 5), "a\nb\na\nb\na");
+        assert_eq!(cycle_lines("x", 2), "x\nx");
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview("  fn main() {\n}\n"), "fn main() { (+1 lines)");
+        assert_eq!(preview("word"), "word");
+    }
+}
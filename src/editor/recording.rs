@@ -0,0 +1,126 @@
+//! Input recording and replay
+//!
+//! A recording is a JSON-lines file with one terminal event per line and the
+//! milliseconds since recording started, e.g.
+//! `{"t":1520,"event":{"Key":{"code":{"Char":"a"},...}}}`.
+//! Escape-prefixed Alt keys are stored already combined, so a replay doesn't
+//! depend on the original key timing.
+
+use anyhow::{Context, Result};
+use crossterm::event::Event;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// One line of a recording file
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedEvent {
+    /// Milliseconds since recording started
+    t: u64,
+    event: Event,
+}
+
+/// Writes input events to a recording file as they are handled
+pub struct Recorder {
+    writer: BufWriter<File>,
+    started: Instant,
+    pub path: PathBuf,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            started: Instant::now(),
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Append an event. Flushed immediately so a crash still leaves a usable log.
+    pub fn record(&mut self, event: &Event) -> Result<()> {
+        let line = RecordedEvent {
+            t: self.started.elapsed().as_millis() as u64,
+            event: event.clone(),
+        };
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Feeds a recording back with its original timing
+pub struct Replay {
+    events: VecDeque<(u64, Event)>,
+    started: Instant,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut events = VecDeque::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let recorded: RecordedEvent = serde_json::from_str(&line)
+                .with_context(|| format!("{}:{}: invalid event", path.display(), i + 1))?;
+            events.push_back((recorded.t, recorded.event));
+        }
+        Ok(Self { events, started: Instant::now() })
+    }
+
+    /// Events whose recorded time has been reached, in order
+    pub fn due(&mut self) -> Vec<Event> {
+        let now = self.started.elapsed().as_millis() as u64;
+        let mut due = Vec::new();
+        while self.events.front().is_some_and(|(t, _)| *t <= now) {
+            if let Some((_, event)) = self.events.pop_front() {
+                due.push(event);
+            }
+        }
+        due
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    #[test]
+    fn test_recording_round_trip() {
+        let path = std::env::temp_dir().join(format!("fackr-recording-{}.jsonl", std::process::id()));
+        let events = vec![
+            Event::Key(KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE)),
+            Event::Key(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::ALT)),
+            Event::Resize(80, 24),
+        ];
+
+        let mut recorder = Recorder::create(&path).unwrap();
+        for event in &events {
+            recorder.record(event).unwrap();
+        }
+        drop(recorder);
+
+        let mut replay = Replay::load(&path).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(replay.due(), events);
+        assert!(replay.is_done());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,307 @@
+//! First-run setup wizard
+//!
+//! Shown in the welcome flow when there is no `~/.config/fackr/config.toml`
+//! yet. A few steps pick the theme (previewed while choosing), indentation,
+//! whether to capture the mouse, how long to wait after Escape for the rest
+//! of an Alt chord (guessed from tmux/ssh), and language servers to install.
+//! The choices go to the per-user config; the server manager installs the
+//! servers once the editor is up. Escape skips the remaining steps, keeping
+//! their defaults, so the wizard doesn't come back next time.
+
+use anyhow::Result;
+use crossterm::event::{self, Event};
+
+use crate::config::Settings;
+use crate::input::{Key, Modifiers};
+use crate::lsp::server_manager::{detect_installed_servers, KnownServer};
+use crate::render::{Screen, Theme};
+
+/// Indentation choices: label, tab width, spaces
+const INDENTS: &[(&str, usize, bool)] = &[
+    ("4 spaces", 4, true),
+    ("2 spaces", 2, true),
+    ("8 spaces", 8, true),
+    ("Tabs, 4 columns wide", 4, false),
+    ("Tabs, 8 columns wide", 8, false),
+];
+
+/// Escape wait choices in milliseconds, with where each suits
+const ESCAPE_TIMES: &[(u64, &str)] = &[
+    (5, "a local terminal"),
+    (25, "tmux or screen"),
+    (50, "ssh"),
+    (100, "slow or high-latency links"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Theme,
+    Indent,
+    Mouse,
+    EscapeTime,
+    Servers,
+}
+
+const STEPS: [Step; 5] = [Step::Theme, Step::Indent, Step::Mouse, Step::EscapeTime, Step::Servers];
+
+/// What the wizard settled on
+#[derive(Debug)]
+pub struct Setup {
+    pub settings: Settings,
+    /// Names of language servers to install
+    pub servers: Vec<String>,
+}
+
+pub struct SetupWizard {
+    step: usize,
+    /// Selected option in each step
+    choices: [usize; STEPS.len()],
+    scroll: usize,
+    themes: Vec<String>,
+    /// Servers not installed yet that have an install command
+    servers: Vec<KnownServer>,
+    /// Servers ticked for installing
+    ticked: Vec<bool>,
+}
+
+impl SetupWizard {
+    pub fn new() -> Self {
+        let defaults = Settings::default();
+        let themes: Vec<String> = Theme::available().into_iter().map(|(name, _)| name).collect();
+        let servers: Vec<KnownServer> = detect_installed_servers()
+            .into_iter()
+            .filter(|s| !s.is_installed && !s.install_cmd.starts_with('#'))
+            .collect();
+        let ticked = vec![false; servers.len()];
+        let theme = themes.iter().position(|t| *t == defaults.theme).unwrap_or(0);
+        Self { step: 0, choices: [theme, 0, 0, guess_escape_time(), 0], scroll: 0, themes, servers, ticked }
+    }
+
+    fn current(&self) -> Step {
+        STEPS[self.step]
+    }
+
+    /// The settings chosen so far, with defaults for steps not reached
+    pub fn settings(&self) -> Settings {
+        let [theme, indent, mouse, escape_time, _] = self.choices;
+        let (_, tab_width, use_spaces) = INDENTS[indent];
+        Settings {
+            theme: self.themes.get(theme).cloned().unwrap_or_else(|| Settings::default().theme),
+            tab_width,
+            use_spaces,
+            mouse: mouse == 0,
+            escape_time: ESCAPE_TIMES[escape_time].0,
+        }
+    }
+
+    /// Theme to preview while choosing one
+    pub fn previewed_theme(&self) -> Option<&str> {
+        if self.current() != Step::Theme {
+            return None;
+        }
+        self.themes.get(self.choices[0]).map(String::as_str)
+    }
+
+    fn finish(&self) -> Setup {
+        let servers = self
+            .servers
+            .iter()
+            .zip(&self.ticked)
+            .filter(|(_, &ticked)| ticked)
+            .map(|(s, _)| s.name.to_string())
+            .collect();
+        Setup { settings: self.settings(), servers }
+    }
+
+    /// Title and one-line explanation of the current step
+    pub fn heading(&self) -> (String, &'static str) {
+        let name = match self.current() {
+            Step::Theme => "Color theme",
+            Step::Indent => "Indentation",
+            Step::Mouse => "Mouse",
+            Step::EscapeTime => "Escape key timing",
+            Step::Servers => "Language servers",
+        };
+        let help = match self.current() {
+            Step::Servers => "Space: tick  Enter: finish  Bksp: back  Esc: skip",
+            _ => "Enter: next  Backspace: back  Esc: skip",
+        };
+        (format!("Setup {}/{}: {}", self.step + 1, STEPS.len(), name), help)
+    }
+
+    /// Options of the current step, as (label, detail, is_selected, is_ticked)
+    pub fn items(&self) -> Vec<(String, String, bool, bool)> {
+        let selected = self.choices[self.step];
+        let options: Vec<(String, String, bool)> = match self.current() {
+            Step::Theme => self
+                .themes
+                .iter()
+                .map(|t| (t.clone(), "Previewed now; Select Color Theme changes it later".to_string(), false))
+                .collect(),
+            Step::Indent => INDENTS
+                .iter()
+                .map(|(label, _, _)| (label.to_string(), "Default for workspaces that don't set their own".to_string(), false))
+                .collect(),
+            Step::Mouse => vec![
+                ("Capture the mouse".to_string(), "Click, drag and scroll in the editor".to_string(), false),
+                ("Leave it to the terminal".to_string(), "The terminal's own selection and copy keep working".to_string(), false),
+            ],
+            Step::EscapeTime => ESCAPE_TIMES
+                .iter()
+                .map(|(ms, suits)| (format!("{} ms", ms), format!("Suits {}; FAC_ESCAPE_TIME overrides it", suits), false))
+                .collect(),
+            Step::Servers if self.servers.is_empty() => {
+                vec![("Nothing to install".to_string(), "Every known server is installed already".to_string(), false)]
+            }
+            Step::Servers => self
+                .servers
+                .iter()
+                .zip(&self.ticked)
+                .map(|(s, &ticked)| {
+                    let label = format!("[{}] {} ({})", if ticked { "x" } else { " " }, s.name, s.language);
+                    (label, s.install_cmd.to_string(), ticked)
+                })
+                .collect(),
+        };
+        options
+            .into_iter()
+            .enumerate()
+            .map(|(i, (label, detail, ticked))| (format!(" {}", label), detail, i == selected, ticked))
+            .collect()
+    }
+
+    fn option_count(&self) -> usize {
+        match self.current() {
+            Step::Theme => self.themes.len(),
+            Step::Indent => INDENTS.len(),
+            Step::Mouse => 2,
+            Step::EscapeTime => ESCAPE_TIMES.len(),
+            Step::Servers => self.servers.len().max(1),
+        }
+    }
+
+    /// Keep the selection within `visible_rows` of the list
+    pub fn update_viewport(&mut self, visible_rows: usize) {
+        let selected = self.choices[self.step];
+        if visible_rows == 0 {
+            return;
+        }
+        if selected < self.scroll {
+            self.scroll = selected;
+        } else if selected >= self.scroll + visible_rows {
+            self.scroll = selected + 1 - visible_rows;
+        }
+    }
+
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    fn go_to(&mut self, step: usize) {
+        self.step = step;
+        self.scroll = 0;
+    }
+
+    /// Handle a key press; returns the setup once the wizard is done
+    pub fn handle_key(&mut self, key: Key, _mods: Modifiers) -> Option<Setup> {
+        let selected = self.choices[self.step];
+        match key {
+            Key::Up | Key::Char('k') => self.choices[self.step] = selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.choices[self.step] = (selected + 1).min(self.option_count() - 1),
+            Key::Char(' ') if self.current() == Step::Servers => {
+                if let Some(ticked) = self.ticked.get_mut(selected) {
+                    *ticked = !*ticked;
+                }
+            }
+            Key::Enter if self.step + 1 < STEPS.len() => self.go_to(self.step + 1),
+            Key::Enter => return Some(self.finish()),
+            Key::Backspace | Key::Left if self.step > 0 => self.go_to(self.step - 1),
+            Key::Escape => {
+                self.ticked.fill(false);
+                return Some(self.finish());
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Run the wizard. Assumes the screen is already in raw mode.
+    pub fn run(screen: &mut Screen) -> Result<Setup> {
+        let mut wizard = SetupWizard::new();
+        let mut previewed = None;
+
+        loop {
+            if let Some(name) = wizard.previewed_theme() {
+                if previewed.as_deref() != Some(name) {
+                    screen.theme = Theme::load(name).0;
+                    previewed = Some(name.to_string());
+                }
+            }
+            let visible_rows = screen.rows.saturating_sub(10) as usize;
+            wizard.update_viewport(visible_rows);
+
+            let (title, help) = wizard.heading();
+            screen.render_welcome(&title, help, &wizard.items(), wizard.scroll())?;
+
+            if let Event::Key(key_event) = event::read()? {
+                let (key, mods) = Key::from_crossterm(key_event);
+                if let Some(setup) = wizard.handle_key(key, mods) {
+                    return Ok(setup);
+                }
+            }
+        }
+    }
+}
+
+/// Escape wait to suggest: longer inside a multiplexer or over ssh, where
+/// an Alt chord's bytes can arrive apart
+fn guess_escape_time() -> usize {
+    let set = |name: &str| std::env::var_os(name).is_some();
+    if set("SSH_CONNECTION") || set("SSH_TTY") {
+        2
+    } else if set("TMUX") || set("STY") {
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(wizard: &mut SetupWizard, key: Key) -> Option<Setup> {
+        wizard.handle_key(key, Modifiers::default())
+    }
+
+    #[test]
+    fn test_choices_become_settings() {
+        let mut wizard = SetupWizard::new();
+        wizard.choices[3] = 0;
+        press(&mut wizard, Key::Enter);
+        press(&mut wizard, Key::Down);
+        press(&mut wizard, Key::Enter);
+        press(&mut wizard, Key::Down);
+        press(&mut wizard, Key::Backspace);
+        assert_eq!(wizard.current(), Step::Indent);
+        press(&mut wizard, Key::Enter);
+        press(&mut wizard, Key::Enter);
+        press(&mut wizard, Key::Down);
+        press(&mut wizard, Key::Enter);
+
+        let setup = press(&mut wizard, Key::Enter).expect("last step finishes");
+        assert_eq!(setup.settings.tab_width, 2);
+        assert!(setup.settings.use_spaces);
+        assert!(!setup.settings.mouse);
+        assert_eq!(setup.settings.escape_time, 25);
+        assert!(setup.servers.is_empty());
+    }
+
+    #[test]
+    fn test_escape_keeps_defaults() {
+        let mut wizard = SetupWizard::new();
+        wizard.choices[3] = 0;
+        let setup = press(&mut wizard, Key::Escape).unwrap();
+        assert_eq!(setup.settings, Settings::default());
+    }
+}
@@ -0,0 +1,271 @@
+//! Snippets
+//!
+//! The LSP (TextMate) snippet syntax: `$1` and `${1:placeholder}` tabstops,
+//! with placeholders that may hold further tabstops, `${1|one,two|}`
+//! choices (the first one is inserted), `$0` for where the cursor ends up,
+//! and `$NAME` / `${NAME:default}` variables. A tabstop number used more
+//! than once is mirrored: its copies show the same placeholder and are
+//! edited together.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Expanded snippet text with its tabstops
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub text: String,
+    /// Character ranges in `text` of each tabstop's copies, in the order
+    /// Tab visits them; the final position (`$0`, or the end) comes last
+    pub stops: Vec<Vec<Range<usize>>>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Tabstop(u32, Vec<Node>),
+}
+
+/// Expand a snippet body; `variable` gives the value of a variable, or
+/// None for unknown ones (which use their default)
+pub fn parse(body: &str, variable: impl Fn(&str) -> Option<String>) -> Snippet {
+    let chars: Vec<char> = body.chars().collect();
+    let mut i = 0;
+    let nodes = parse_nodes(&chars, &mut i, false, &variable);
+
+    // A mirror without a placeholder shows the first one given
+    let mut placeholders: BTreeMap<u32, Vec<Node>> = BTreeMap::new();
+    collect_placeholders(&nodes, &mut placeholders);
+
+    let mut snippet = Snippet { text: String::new(), stops: Vec::new() };
+    let mut stops: BTreeMap<u32, Vec<Range<usize>>> = BTreeMap::new();
+    let mut len = 0;
+    render(&nodes, &placeholders, true, &mut snippet.text, &mut len, &mut stops);
+
+    let last = stops.remove(&0).unwrap_or_else(|| std::iter::once(len..len).collect());
+    snippet.stops = stops.into_values().collect();
+    snippet.stops.push(last);
+    snippet
+}
+
+impl Snippet {
+    /// The snippet as inserted on a line indented by `indent`: later lines
+    /// get the indent too, and tabs become `tab`
+    pub fn adapted(&self, indent: &str, tab: &str) -> Snippet {
+        let mut text = String::new();
+        // New position of each old character index
+        let mut map = Vec::with_capacity(self.text.len() + 1);
+        let mut len = 0;
+        for c in self.text.chars() {
+            map.push(len);
+            let piece: String = match c {
+                '\t' => tab.to_string(),
+                '\n' => format!("\n{}", indent),
+                c => c.to_string(),
+            };
+            len += piece.chars().count();
+            text.push_str(&piece);
+        }
+        map.push(len);
+        let stops = self
+            .stops
+            .iter()
+            .map(|ranges| ranges.iter().map(|r| map[r.start]..map[r.end]).collect())
+            .collect();
+        Snippet { text, stops }
+    }
+}
+
+fn parse_nodes(chars: &[char], i: &mut usize, nested: bool, variable: &impl Fn(&str) -> Option<String>) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+    while *i < chars.len() {
+        let c = chars[*i];
+        match c {
+            '\\' if matches!(chars.get(*i + 1), Some('$' | '}' | '\\')) => {
+                text.push(chars[*i + 1]);
+                *i += 2;
+            }
+            '}' if nested => break,
+            '$' => {
+                let start = *i;
+                *i += 1;
+                match parse_dollar(chars, i, variable) {
+                    Some(mut parsed) => {
+                        if !text.is_empty() {
+                            nodes.push(Node::Text(std::mem::take(&mut text)));
+                        }
+                        nodes.append(&mut parsed);
+                    }
+                    None => {
+                        // Not a tabstop or variable: a literal dollar sign
+                        *i = start + 1;
+                        text.push('$');
+                    }
+                }
+            }
+            _ => {
+                text.push(c);
+                *i += 1;
+            }
+        }
+    }
+    if !text.is_empty() {
+        nodes.push(Node::Text(text));
+    }
+    nodes
+}
+
+/// What follows a `$`, with `i` just after it
+fn parse_dollar(chars: &[char], i: &mut usize, variable: &impl Fn(&str) -> Option<String>) -> Option<Vec<Node>> {
+    if chars.get(*i)?.is_ascii_digit() {
+        return Some(vec![Node::Tabstop(number(chars, i)?, Vec::new())]);
+    }
+    if chars[*i] != '{' {
+        let name = name(chars, i)?;
+        return Some(variable(&name).map(Node::Text).into_iter().collect());
+    }
+
+    *i += 1;
+    if chars.get(*i)?.is_ascii_digit() {
+        let n = number(chars, i)?;
+        let children = match chars.get(*i)? {
+            '}' => Vec::new(),
+            ':' => {
+                *i += 1;
+                parse_nodes(chars, i, true, variable)
+            }
+            '|' => {
+                *i += 1;
+                let end = (*i..chars.len()).find(|&j| chars[j] == '|' && chars.get(j + 1) == Some(&'}'))?;
+                let choices: String = chars[*i..end].iter().collect();
+                *i = end + 1;
+                let first = choices.split(',').next().unwrap_or("").to_string();
+                vec![Node::Text(first)]
+            }
+            _ => return None,
+        };
+        (chars.get(*i) == Some(&'}')).then_some(())?;
+        *i += 1;
+        return Some(vec![Node::Tabstop(n, children)]);
+    }
+
+    let name = name(chars, i)?;
+    let default = match chars.get(*i)? {
+        '}' => Vec::new(),
+        ':' => {
+            *i += 1;
+            parse_nodes(chars, i, true, variable)
+        }
+        _ => return None,
+    };
+    (chars.get(*i) == Some(&'}')).then_some(())?;
+    *i += 1;
+    Some(match variable(&name) {
+        Some(value) => vec![Node::Text(value)],
+        None => default,
+    })
+}
+
+fn number(chars: &[char], i: &mut usize) -> Option<u32> {
+    let start = *i;
+    while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+        *i += 1;
+    }
+    chars[start..*i].iter().collect::<String>().parse().ok()
+}
+
+fn name(chars: &[char], i: &mut usize) -> Option<String> {
+    let start = *i;
+    while chars.get(*i).is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_') {
+        *i += 1;
+    }
+    let name: String = chars[start..*i].iter().collect();
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_').then_some(name)
+}
+
+fn collect_placeholders(nodes: &[Node], placeholders: &mut BTreeMap<u32, Vec<Node>>) {
+    for node in nodes {
+        if let Node::Tabstop(n, children) = node {
+            if !children.is_empty() {
+                placeholders.entry(*n).or_insert_with(|| children.clone());
+            }
+            collect_placeholders(children, placeholders);
+        }
+    }
+}
+
+/// Append `nodes` to `text`, recording tabstop ranges; a mirror's copied
+/// placeholder records none of its own
+fn render(
+    nodes: &[Node],
+    placeholders: &BTreeMap<u32, Vec<Node>>,
+    record: bool,
+    text: &mut String,
+    len: &mut usize,
+    stops: &mut BTreeMap<u32, Vec<Range<usize>>>,
+) {
+    for node in nodes {
+        match node {
+            Node::Text(s) => {
+                text.push_str(s);
+                *len += s.chars().count();
+            }
+            Node::Tabstop(n, children) => {
+                let start = *len;
+                if children.is_empty() {
+                    let copied = placeholders.get(n).map(Vec::as_slice).unwrap_or_default();
+                    render(copied, placeholders, false, text, len, stops);
+                } else {
+                    render(children, placeholders, record, text, len, stops);
+                }
+                if record {
+                    stops.entry(*n).or_default().push(start..*len);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(body: &str) -> Snippet {
+        parse(body, |name| (name == "TM_FILENAME").then(|| "main.rs".to_string()))
+    }
+
+    #[test]
+    fn test_tabstops_and_final_position() {
+        let snippet = expand("for ${1:item} in ${2:items} {\n\t$0\n}");
+        assert_eq!(snippet.text, "for item in items {\n\t\n}");
+        assert_eq!(snippet.stops, vec![vec![4..8], vec![12..17], vec![21..21]]);
+
+        let snippet = expand("print($1)");
+        assert_eq!(snippet.stops, vec![vec![6..6], vec![7..7]]);
+        assert_eq!(expand("plain text").stops.len(), 1);
+    }
+
+    #[test]
+    fn test_mirrors_nesting_and_choices() {
+        let snippet = expand("${1:name}: ${2:Vec<${3:T}>} = $1;");
+        assert_eq!(snippet.text, "name: Vec<T> = name;");
+        assert_eq!(snippet.stops[0], vec![0..4, 15..19]);
+        assert_eq!(snippet.stops[1], vec![6..12]);
+        assert_eq!(snippet.stops[2], vec![10..11]);
+
+        assert_eq!(expand("${1|pub,pub(crate)|} fn").text, "pub fn");
+    }
+
+    #[test]
+    fn test_variables_and_escapes() {
+        assert_eq!(expand("// $TM_FILENAME ${UNKNOWN:none}").text, "// main.rs none");
+        assert_eq!(expand("cost: \\$5 {\\}} $ 1").text, "cost: $5 {}} $ 1");
+    }
+
+    #[test]
+    fn test_adapted() {
+        let snippet = expand("if $1 {\n\t$0\n}").adapted("    ", "  ");
+        assert_eq!(snippet.text, "if  {\n      \n    }");
+        assert_eq!(snippet.stops, vec![vec![3..3], vec![12..12]]);
+    }
+}
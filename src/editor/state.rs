@@ -1,20 +1,80 @@
 use anyhow::Result;
 use arboard::Clipboard;
-use crossterm::event::{self, Event, KeyEvent, MouseEvent};
+use crossterm::event::{self, Event, KeyEvent, KeyEventKind, MouseEvent};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
 use std::time::{Duration, Instant};
 
-use crate::buffer::Buffer;
+use crate::buffer::{self, Buffer, Indent};
+use crate::config::{Keymap, Settings};
+use crate::git::{self, blame_file, relative_time, BlameLine, Commit, FileHunks, LineDiff};
 use crate::input::{Key, Modifiers, Mouse, Button};
-use crate::lsp::{CompletionItem, Diagnostic, HoverInfo, Location, ServerManagerPanel};
-use crate::render::{PaneBounds as RenderPaneBounds, PaneInfo, Screen, TabInfo};
+use crate::lsp::{
+    path_to_uri, uri_to_path, ApplyEditResult, CodeAction, CodeLens, Command, CompletionItem, Diagnostic, DiagnosticSeverity, DocumentLink, HoverInfo,
+    Location, Position as LspPosition, PrepareRename, Range as LspRange, ServerManagerPanel, SignatureHelp, TextEdit,
+    WorkspaceEdit,
+};
+use crate::render::image::{self, GraphicsProtocol};
+use crate::render::{minimap, wrap};
+use crate::render::{
+    Edge, Layout, LiveGrepView, MessageLevel, PaneBounds as RenderPaneBounds, PaneInfo, Placement, Screen, Side, TabInfo, Theme,
+    VirtualTextStyle,
+};
 use crate::terminal::TerminalPanel;
-use crate::workspace::{PaneDirection, Tab, Workspace};
+use crate::syntax::{ansi, HighlightState, TokenType};
+use crate::util::status::{self, Status};
+use crate::util::{calc, elevate, fuzzy, logging};
+use crate::util::shell::shell_command;
+use crate::util::path::{is_rooted, resolve_path};
+use crate::workspace::{find_in_lines, line_changes, parse_extensions, Alternate, Bookmark, Generation, GlobalMark, Jump, LockInfo, LockMode, PaneDirection, Pattern, Search, SearchHit, SessionStats, Tab, VirtualDocument, Workspace};
 
-use super::{Cursor, Cursors, History, Operation, Position};
+use super::autolist;
+use super::macros::{self, Macros};
+use super::paste::{self, PasteMode};
+use super::recording::{Recorder, Replay};
+use super::snippet::{self, Snippet};
+use super::vim::{self, InsertAt, Mode as VimMode, Motion, Operator, Target, TextObject, Vim, VimAction};
+use super::{Cursor, Cursors, History, Messages, Operation, Position};
 
-/// How long to wait after last edit before writing idle backup (seconds)
-const BACKUP_IDLE_SECS: u64 = 30;
+/// How long to wait after last edit before refreshing code lenses and links (milliseconds)
+const ANNOTATION_DEBOUNCE_MS: u64 = 750;
+
+/// Handling an event for longer than this (milliseconds) queues whatever was typed meanwhile
+const SLOW_INPUT_MS: u64 = 100;
+
+/// How long after the last edit project notes are saved
+const NOTES_SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// How long live grep waits for typing to pause before searching
+const LIVE_GREP_DELAY: Duration = Duration::from_millis(150);
+
+/// Lines around the cursor searched by find in a large file
+const LARGE_FILE_SEARCH_LINES: usize = 100_000;
+
+/// How long the editor sits without input before the workspace is analysed
+/// (with insights on)
+const INSIGHTS_IDLE: Duration = Duration::from_secs(30);
+
+/// Files of a references panel prefetched at most
+const PREFETCH_REFERENCES: usize = 16;
+
+/// Files created or deleted outside the editor show in the explorer after
+/// this long, so a burst of them refreshes it once
+const TREE_REFRESH_DELAY: Duration = Duration::from_millis(300);
+
+/// Pastes bigger than this, and ten times the buffer, ask first
+const LARGE_PASTE_BYTES: usize = 1024 * 1024;
+
+/// Cursors Select All Occurrences places at most
+const MAX_OCCURRENCE_CURSORS: usize = 1000;
+
+/// A confirmed large paste goes in this much at a time, with progress shown
+const PASTE_CHUNK_BYTES: usize = 1024 * 1024;
 
 /// Which input field is active in find/replace
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -38,34 +98,56 @@ struct FortressEntry {
 #[derive(Debug, Clone, PartialEq)]
 struct PaletteCommand {
     /// Display name (e.g., "Save File")
-    name: &'static str,
-    /// Keyboard shortcut (e.g., "Ctrl+S")
-    shortcut: &'static str,
+    name: Cow<'static, str>,
+    /// Keyboard shortcut (e.g., "Ctrl+S"), taken from the keymap when it binds `id`
+    shortcut: Cow<'static, str>,
     /// Category for grouping (e.g., "File", "Edit")
     category: &'static str,
-    /// Unique command identifier
-    id: &'static str,
+    /// Unique command identifier; contributed commands are `action:argument`
+    id: Cow<'static, str>,
     /// Fuzzy match score (computed during filtering)
     score: i32,
 }
 
 impl PaletteCommand {
     const fn new(name: &'static str, shortcut: &'static str, category: &'static str, id: &'static str) -> Self {
-        Self { name, shortcut, category, id, score: 0 }
+        Self { name: Cow::Borrowed(name), shortcut: Cow::Borrowed(shortcut), category, id: Cow::Borrowed(id), score: 0 }
+    }
+
+    /// A command for what can be done right now, e.g. switching to a
+    /// particular branch (see `Editor::context_commands`)
+    fn contributed(name: String, category: &'static str, action: &str, argument: &str) -> Self {
+        let id = format!("{}:{}", action, argument);
+        Self { name: Cow::Owned(name), shortcut: Cow::Borrowed(""), category, id: Cow::Owned(id), score: 0 }
     }
 }
 
+/// Recently opened files offered for reopening in the palette at most
+const PALETTE_RECENT_FILES: usize = 10;
+
 /// All available commands for the command palette
 const ALL_COMMANDS: &[PaletteCommand] = &[
     // File operations
     PaletteCommand::new("Save File", "Ctrl+S", "File", "save"),
+    PaletteCommand::new("Save As...", "", "File", "save-as"),
     PaletteCommand::new("Save All", "", "File", "save-all"),
     PaletteCommand::new("Open File Browser", "Ctrl+O", "File", "open"),
     PaletteCommand::new("New Tab", "Alt+T", "File", "new-tab"),
     PaletteCommand::new("Close Tab", "Alt+Q", "File", "close-tab"),
     PaletteCommand::new("Next Tab", "Alt+.", "File", "next-tab"),
     PaletteCommand::new("Previous Tab", "Alt+,", "File", "prev-tab"),
+    PaletteCommand::new("Open Workspace...", "", "File", "open-workspace"),
+    PaletteCommand::new("Close Workspace", "", "File", "close-workspace"),
+    PaletteCommand::new("Next Workspace", "Alt+>", "File", "next-workspace"),
+    PaletteCommand::new("Previous Workspace", "Alt+<", "File", "prev-workspace"),
     PaletteCommand::new("Quit", "Ctrl+Q", "File", "quit"),
+    PaletteCommand::new("Suspend to Shell", "Alt+Z", "File", "suspend"),
+    PaletteCommand::new("Refresh Document", "Alt+R", "File", "refresh-document"),
+    PaletteCommand::new("Start/Stop Input Recording", "", "File", "toggle-recording"),
+    PaletteCommand::new("Copy Path", "", "File", "copy-path"),
+    PaletteCommand::new("Copy Relative Path", "", "File", "copy-relative-path"),
+    PaletteCommand::new("Copy Path:Line", "", "File", "copy-path-line"),
+    PaletteCommand::new("Copy Filename", "", "File", "copy-filename"),
 
     // Edit operations
     PaletteCommand::new("Undo", "Ctrl+Z", "Edit", "undo"),
@@ -73,18 +155,32 @@ const ALL_COMMANDS: &[PaletteCommand] = &[
     PaletteCommand::new("Cut", "Ctrl+X", "Edit", "cut"),
     PaletteCommand::new("Copy", "Ctrl+C", "Edit", "copy"),
     PaletteCommand::new("Paste", "Ctrl+V", "Edit", "paste"),
+    PaletteCommand::new("Paste Special", "Ctrl+Alt+V", "Edit", "paste-special"),
+    PaletteCommand::new("Paste as Plain Text", "", "Edit", "paste-plain"),
+    PaletteCommand::new("Paste and Select", "", "Edit", "paste-select"),
+    PaletteCommand::new("Paste Over Selected Lines", "", "Edit", "paste-over-lines"),
     PaletteCommand::new("Select All", "Ctrl+A", "Edit", "select-all"),
     PaletteCommand::new("Select Line", "Ctrl+L", "Edit", "select-line"),
     PaletteCommand::new("Select Word", "Ctrl+D", "Edit", "select-word"),
+    PaletteCommand::new("Select All Occurrences of Selection", "Ctrl+Shift+L", "Selection", "select-all-occurrences"),
+    PaletteCommand::new("Add Cursors to Line Ends", "Alt+Shift+I", "Selection", "cursors-to-line-ends"),
     PaletteCommand::new("Toggle Line Comment", "Ctrl+/", "Edit", "toggle-comment"),
     PaletteCommand::new("Join Lines", "Ctrl+J", "Edit", "join-lines"),
+    PaletteCommand::new("Toggle Vim Mode", "", "Edit", "toggle-vim"),
+    PaletteCommand::new("Filter Through Command", "Alt+!", "Edit", "filter-command"),
+    PaletteCommand::new("Insert Command Output", "Alt+|", "Edit", "insert-command-output"),
     PaletteCommand::new("Duplicate Line", "Alt+Shift+Down", "Edit", "duplicate-line"),
+    PaletteCommand::new("Duplicate Line Up", "Alt+Shift+Up", "Edit", "duplicate-line-up"),
+    PaletteCommand::new("Duplicate Selection", "Ctrl+Shift+D", "Edit", "duplicate-selection"),
     PaletteCommand::new("Move Line Up", "Alt+Up", "Edit", "move-line-up"),
     PaletteCommand::new("Move Line Down", "Alt+Down", "Edit", "move-line-down"),
-    PaletteCommand::new("Delete Line", "", "Edit", "delete-line"),
+    PaletteCommand::new("Delete Line", "Ctrl+Shift+K", "Edit", "delete-line"),
     PaletteCommand::new("Indent", "Tab", "Edit", "indent"),
     PaletteCommand::new("Outdent", "Shift+Tab", "Edit", "outdent"),
     PaletteCommand::new("Transpose Characters", "Ctrl+T", "Edit", "transpose"),
+    PaletteCommand::new("Strip ANSI Escape Codes", "", "Edit", "strip-ansi"),
+    PaletteCommand::new("Convert Indentation to Spaces", "", "Edit", "indent-spaces"),
+    PaletteCommand::new("Convert Indentation to Tabs", "", "Edit", "indent-tabs"),
 
     // Search operations
     PaletteCommand::new("Find", "Ctrl+F", "Search", "find"),
@@ -92,6 +188,10 @@ const ALL_COMMANDS: &[PaletteCommand] = &[
     PaletteCommand::new("Find Next", "F3", "Search", "find-next"),
     PaletteCommand::new("Find Previous", "Shift+F3", "Search", "find-prev"),
     PaletteCommand::new("Search in Files", "F4", "Search", "search-files"),
+    PaletteCommand::new("Live Grep", "Shift+F4", "Search", "live-grep"),
+    PaletteCommand::new("Find in Open Files", "", "Search", "search-open-files"),
+    PaletteCommand::new("Go to File", "Ctrl+Shift+P", "Navigation", "quick-open"),
+    PaletteCommand::new("Find String Literals", "", "Search", "extract-strings"),
 
     // Navigation
     PaletteCommand::new("Go to Line", "Ctrl+G", "Navigation", "goto-line"),
@@ -100,19 +200,67 @@ const ALL_COMMANDS: &[PaletteCommand] = &[
     PaletteCommand::new("Go to Matching Bracket", "Ctrl+M", "Navigation", "goto-bracket"),
     PaletteCommand::new("Page Up", "PageUp", "Navigation", "page-up"),
     PaletteCommand::new("Page Down", "PageDown", "Navigation", "page-down"),
+    PaletteCommand::new("Set Mark", "Alt+M", "Navigation", "set-mark"),
+    PaletteCommand::new("Jump to Mark Line", "Alt+;", "Navigation", "jump-mark-line"),
+    PaletteCommand::new("Jump to Mark", "Alt+`", "Navigation", "jump-mark"),
+    PaletteCommand::new("List Marks", "", "Navigation", "list-marks"),
+    PaletteCommand::new("Toggle Bookmark", "Ctrl+Alt+K", "Navigation", "toggle-bookmark"),
+    PaletteCommand::new("Bookmark Line with Label...", "", "Navigation", "label-bookmark"),
+    PaletteCommand::new("Next Bookmark", "Ctrl+Alt+L", "Navigation", "next-bookmark"),
+    PaletteCommand::new("Previous Bookmark", "Ctrl+Alt+J", "Navigation", "prev-bookmark"),
+    PaletteCommand::new("List Bookmarks", "", "Navigation", "list-bookmarks"),
+    PaletteCommand::new("Alternate File", "Alt+O", "Navigation", "alternate-file"),
+    PaletteCommand::new("Project Notes", "Ctrl+Alt+N", "File", "project-notes"),
+    PaletteCommand::new("Recover Previous Version...", "", "File", "recover-version"),
+    PaletteCommand::new("Reload File from Disk", "", "File", "reload-file"),
 
     // Selection
     PaletteCommand::new("Expand Selection to Brackets", "", "Selection", "select-brackets"),
+    PaletteCommand::new("Select Inside Quotes/Brackets", "", "Selection", "select-inside"),
+    PaletteCommand::new("Select Around Quotes/Brackets", "", "Selection", "select-around"),
+    PaletteCommand::new("Delete Inside Quotes/Brackets", "", "Edit", "delete-inside"),
+    PaletteCommand::new("Delete Around Quotes/Brackets", "", "Edit", "delete-around"),
     PaletteCommand::new("Add Cursor Above", "Ctrl+Alt+Up", "Selection", "cursor-above"),
+    PaletteCommand::new("Toggle Block Selection", "Ctrl+Alt+B", "Selection", "block-select"),
     PaletteCommand::new("Add Cursor Below", "Ctrl+Alt+Down", "Selection", "cursor-below"),
+    PaletteCommand::new("Remove Last Added Cursor", "Alt+U", "Selection", "cursor-remove-last"),
+    PaletteCommand::new("Next Primary Cursor", "Ctrl+Alt+Right", "Selection", "cursor-rotate-next"),
+    PaletteCommand::new("Previous Primary Cursor", "Ctrl+Alt+Left", "Selection", "cursor-rotate-prev"),
 
     // View / Panes
     PaletteCommand::new("Split Pane Vertical", "Alt+V", "View", "split-vertical"),
     PaletteCommand::new("Split Pane Horizontal", "Alt+S", "View", "split-horizontal"),
     PaletteCommand::new("Close Pane", "Alt+Q", "View", "close-pane"),
+    PaletteCommand::new("Cycle Terminal Height", "Alt+= (in terminal)", "View", "terminal-height"),
+    PaletteCommand::new("Maximize Terminal", "Alt+M (in terminal)", "View", "terminal-maximize"),
     PaletteCommand::new("Focus Next Pane", "Alt+N", "View", "next-pane"),
     PaletteCommand::new("Focus Previous Pane", "Alt+P", "View", "prev-pane"),
+    PaletteCommand::new("Navigate Back", "Ctrl+Alt+-", "View", "navigate-back"),
+    PaletteCommand::new("Navigate Forward", "Ctrl+Alt+=", "View", "navigate-forward"),
+    PaletteCommand::new("Go Back in Pane", "Alt+Left", "View", "pane-back"),
+    PaletteCommand::new("Go Forward in Pane", "Alt+Right", "View", "pane-forward"),
+    PaletteCommand::new("Toggle Fold", "Alt+-", "View", "toggle-fold"),
+    PaletteCommand::new("Unfold", "", "View", "unfold"),
+    PaletteCommand::new("Fold All", "Alt+_", "View", "fold-all"),
+    PaletteCommand::new("Unfold All", "Alt+0", "View", "unfold-all"),
+    PaletteCommand::new("Toggle Word Wrap", "Alt+W", "View", "toggle-wrap"),
+    PaletteCommand::new("Toggle Minimap", "", "View", "toggle-minimap"),
+    PaletteCommand::new("Toggle Indent Guides", "", "View", "toggle-indent-guides"),
+    PaletteCommand::new("Cycle Whitespace Display", "", "View", "cycle-whitespace"),
+    PaletteCommand::new("Toggle Current Line Highlight", "", "View", "toggle-current-line"),
     PaletteCommand::new("Toggle File Explorer", "Ctrl+B", "View", "toggle-explorer"),
+    PaletteCommand::new("Toggle Auto-Hide Panels", "", "View", "toggle-auto-hide"),
+    PaletteCommand::new("Pin File Explorer", "", "View", "pin-explorer"),
+    PaletteCommand::new("Pin Terminal", "", "View", "pin-terminal"),
+    PaletteCommand::new("Select Color Theme", "", "View", "select-theme"),
+    PaletteCommand::new("View Editor Log", "", "View", "view-log"),
+    PaletteCommand::new("Cycle Log Level", "", "View", "cycle-log-level"),
+    PaletteCommand::new("Toggle Inline Blame", "", "View", "toggle-inline-blame"),
+    PaletteCommand::new("Toggle ANSI Color View", "", "View", "toggle-ansi-colors"),
+    PaletteCommand::new("Show Statistics", "", "View", "show-statistics"),
+    PaletteCommand::new("Toggle Statistics", "", "View", "toggle-statistics"),
+    PaletteCommand::new("Workspace Insights", "", "View", "show-insights"),
+    PaletteCommand::new("Toggle Idle Workspace Analysis", "", "View", "toggle-insights"),
 
     // LSP / Code Intelligence
     PaletteCommand::new("Go to Definition", "F12", "LSP", "goto-definition"),
@@ -120,7 +268,26 @@ const ALL_COMMANDS: &[PaletteCommand] = &[
     PaletteCommand::new("Rename Symbol", "F2", "LSP", "rename"),
     PaletteCommand::new("Show Hover Info", "Ctrl+K Ctrl+I", "LSP", "hover"),
     PaletteCommand::new("Trigger Completion", "Ctrl+Space", "LSP", "completion"),
-    PaletteCommand::new("LSP Server Manager", "Alt+M", "LSP", "server-manager"),
+    PaletteCommand::new("Expand Snippet", "Tab", "Edit", "expand-snippet"),
+    PaletteCommand::new("Start/Stop Macro Recording", "F7", "Edit", "macro-record"),
+    PaletteCommand::new("Replay Last Macro", "F8", "Edit", "macro-replay"),
+    PaletteCommand::new("Replay Macro...", "", "Edit", "macro-replay-prompt"),
+    PaletteCommand::new("Run Code Lens", "F9", "LSP", "code-lens"),
+    PaletteCommand::new("Code Actions", "Ctrl+.", "LSP", "code-actions"),
+    PaletteCommand::new("Refresh Code Lenses", "", "LSP", "refresh-code-lens"),
+    PaletteCommand::new("Open Link Under Cursor", "Alt+Enter", "LSP", "open-link"),
+    PaletteCommand::new("Toggle Error Lens", "", "LSP", "toggle-error-lens"),
+    PaletteCommand::new("Workspace Diagnostics", "Alt+E", "LSP", "diagnostics-panel"),
+    PaletteCommand::new("LSP Server Manager", "Ctrl+Alt+M", "LSP", "server-manager"),
+
+    // Git
+    PaletteCommand::new("Next Hunk", "Alt+F5", "Git", "next-hunk"),
+    PaletteCommand::new("Previous Hunk", "Alt+Shift+F5", "Git", "prev-hunk"),
+    PaletteCommand::new("Stage Hunk", "", "Git", "stage-hunk"),
+    PaletteCommand::new("Revert Hunk", "", "Git", "revert-hunk"),
+    PaletteCommand::new("Compare with HEAD", "F6", "Git", "compare-head"),
+    PaletteCommand::new("Compare with Backup", "Shift+F6", "Git", "compare-backup"),
+    PaletteCommand::new("Git Log", "", "Git", "git-log"),
 
     // Bracket/Quote operations
     PaletteCommand::new("Jump to Bracket", "Alt+]", "Brackets", "jump-bracket"),
@@ -136,9 +303,11 @@ const ALL_COMMANDS: &[PaletteCommand] = &[
 #[derive(Debug, Clone, PartialEq)]
 struct HelpKeybind {
     /// Keyboard shortcut (e.g., "Ctrl+S")
-    shortcut: &'static str,
+    shortcut: Cow<'static, str>,
     /// Alternative shortcut (shown when "/" is held)
-    alt_shortcut: &'static str,
+    alt_shortcut: Cow<'static, str>,
+    /// Command whose keymap chords fill in the shortcuts ("" for fixed keys)
+    command: &'static str,
     /// Description of what the keybind does
     description: &'static str,
     /// Category for grouping
@@ -147,109 +316,176 @@ struct HelpKeybind {
 
 impl HelpKeybind {
     const fn new(shortcut: &'static str, description: &'static str, category: &'static str) -> Self {
-        Self { shortcut, alt_shortcut: "", description, category }
+        Self::with_alt(shortcut, "", description, category)
     }
 
     const fn with_alt(shortcut: &'static str, alt_shortcut: &'static str, description: &'static str, category: &'static str) -> Self {
-        Self { shortcut, alt_shortcut, description, category }
+        Self {
+            shortcut: Cow::Borrowed(shortcut),
+            alt_shortcut: Cow::Borrowed(alt_shortcut),
+            command: "",
+            description,
+            category,
+        }
+    }
+
+    /// Entry for a keymap command; its first two chords become the shortcuts
+    const fn bound(command: &'static str, description: &'static str, category: &'static str) -> Self {
+        Self {
+            shortcut: Cow::Borrowed(""),
+            alt_shortcut: Cow::Borrowed(""),
+            command,
+            description,
+            category,
+        }
     }
 }
 
 /// All keybindings for the help menu - comprehensive list
 const ALL_KEYBINDS: &[HelpKeybind] = &[
     // File Operations
-    HelpKeybind::new("Ctrl+S", "Save file", "File"),
-    HelpKeybind::new("Ctrl+O", "Open file browser (Fortress)", "File"),
-    HelpKeybind::new("Ctrl+Q", "Quit editor", "File"),
-    HelpKeybind::with_alt("Ctrl+B", "F3", "Toggle file explorer", "File"),
+    HelpKeybind::bound("save", "Save file", "File"),
+    HelpKeybind::bound("open", "Open file browser (Fortress)", "File"),
+    HelpKeybind::bound("quick-open", "Go to file (fuzzy)", "File"),
+    HelpKeybind::bound("alternate-file", "Switch to header/source or test file", "File"),
+    HelpKeybind::bound("project-notes", "Open the workspace's notes (.fackr/notes.md)", "File"),
+    HelpKeybind::bound("quit", "Quit editor", "File"),
+    HelpKeybind::bound("suspend", "Suspend to shell (resume with fg)", "File"),
+    HelpKeybind::bound("refresh-document", "Refresh diff/log document", "File"),
+    HelpKeybind::bound("toggle-explorer", "Toggle file explorer", "File"),
 
     // Tabs
-    HelpKeybind::new("Alt+T", "New tab", "Tabs"),
-    HelpKeybind::new("Alt+Q", "Close tab/pane", "Tabs"),
-    HelpKeybind::new("Alt+.", "Next tab", "Tabs"),
-    HelpKeybind::new("Alt+,", "Previous tab", "Tabs"),
+    HelpKeybind::bound("new-tab", "New tab", "Tabs"),
+    HelpKeybind::bound("close-pane", "Close tab/pane", "Tabs"),
+    HelpKeybind::bound("next-tab", "Next tab", "Tabs"),
+    HelpKeybind::bound("prev-tab", "Previous tab", "Tabs"),
     HelpKeybind::new("Alt+1-9", "Switch to tab 1-9", "Tabs"),
+    HelpKeybind::bound("next-workspace", "Next open workspace", "Tabs"),
+    HelpKeybind::bound("prev-workspace", "Previous open workspace", "Tabs"),
 
     // Panes
-    HelpKeybind::new("Alt+V", "Split vertical", "Panes"),
-    HelpKeybind::new("Alt+S", "Split horizontal", "Panes"),
+    HelpKeybind::bound("split-vertical", "Split vertical", "Panes"),
+    HelpKeybind::bound("split-horizontal", "Split horizontal", "Panes"),
     HelpKeybind::new("Alt+H/J/K/L", "Navigate panes (vim-style)", "Panes"),
-    HelpKeybind::new("Alt+N", "Next pane", "Panes"),
-    HelpKeybind::new("Alt+P", "Previous pane", "Panes"),
+    HelpKeybind::bound("next-pane", "Next pane", "Panes"),
+    HelpKeybind::bound("prev-pane", "Previous pane", "Panes"),
+    HelpKeybind::bound("pane-back", "Back to pane's previous buffer", "Panes"),
+    HelpKeybind::bound("pane-forward", "Forward in pane's buffers", "Panes"),
+    HelpKeybind::bound("navigate-back", "Back to where the cursor jumped from", "Panes"),
+    HelpKeybind::bound("navigate-forward", "Forward again after going back", "Panes"),
+    HelpKeybind::bound("toggle-wrap", "Toggle word wrap in pane", "Panes"),
+    HelpKeybind::bound("toggle-fold", "Fold / unfold block at cursor", "Folding"),
+    HelpKeybind::bound("fold-all", "Fold all top-level blocks", "Folding"),
+    HelpKeybind::bound("unfold-all", "Unfold everything", "Folding"),
+    HelpKeybind::new("Ctrl+`", "Toggle terminal", "Terminal"),
+    HelpKeybind::new("Alt+= (in terminal)", "Cycle height 25/50/75%", "Terminal"),
+    HelpKeybind::new("Alt+M (in terminal)", "Maximize / restore terminal", "Terminal"),
 
     // Editing
-    HelpKeybind::new("Ctrl+Z", "Undo", "Edit"),
-    HelpKeybind::with_alt("Ctrl+]", "Ctrl+Shift+Z", "Redo", "Edit"),
-    HelpKeybind::new("Ctrl+C", "Copy", "Edit"),
-    HelpKeybind::new("Ctrl+X", "Cut", "Edit"),
-    HelpKeybind::new("Ctrl+V", "Paste", "Edit"),
-    HelpKeybind::new("Ctrl+J", "Join lines", "Edit"),
-    HelpKeybind::new("Ctrl+/", "Toggle line comment", "Edit"),
-    HelpKeybind::new("Ctrl+T", "Transpose characters", "Edit"),
+    HelpKeybind::bound("undo", "Undo", "Edit"),
+    HelpKeybind::bound("redo", "Redo", "Edit"),
+    HelpKeybind::bound("copy", "Copy", "Edit"),
+    HelpKeybind::bound("cut", "Cut", "Edit"),
+    HelpKeybind::bound("paste", "Paste", "Edit"),
+    HelpKeybind::bound("paste-special", "Paste from the clipboard ring", "Edit"),
+    HelpKeybind::bound("join-lines", "Join lines", "Edit"),
+    HelpKeybind::bound("filter-command", "Filter selection/buffer through command", "Edit"),
+    HelpKeybind::bound("insert-command-output", "Insert command output at cursors", "Edit"),
+    HelpKeybind::bound("toggle-comment", "Toggle line comment", "Edit"),
+    HelpKeybind::bound("transpose", "Transpose characters", "Edit"),
     HelpKeybind::new("Tab", "Indent", "Edit"),
     HelpKeybind::new("Shift+Tab", "Outdent", "Edit"),
     HelpKeybind::new("Backspace", "Delete backward", "Edit"),
     HelpKeybind::new("Delete", "Delete forward", "Edit"),
-    HelpKeybind::new("Ctrl+W", "Delete word backward", "Edit"),
-    HelpKeybind::new("Alt+D", "Delete word forward", "Edit"),
-    HelpKeybind::new("Alt+Backspace", "Delete word backward", "Edit"),
-    HelpKeybind::new("Ctrl+K", "Kill to end of line", "Edit"),
-    HelpKeybind::new("Ctrl+U", "Kill to start of line", "Edit"),
-    HelpKeybind::new("Ctrl+Y", "Yank (paste from kill ring)", "Edit"),
-    HelpKeybind::new("Alt+Y", "Cycle yank stack", "Edit"),
+    HelpKeybind::bound("delete-word-backward", "Delete word backward", "Edit"),
+    HelpKeybind::bound("delete-word-forward", "Delete word forward", "Edit"),
+    HelpKeybind::bound("kill-to-line-end", "Kill to end of line", "Edit"),
+    HelpKeybind::bound("kill-to-line-start", "Kill to start of line", "Edit"),
+    HelpKeybind::bound("yank", "Yank (paste from kill ring)", "Edit"),
+    HelpKeybind::bound("yank-cycle", "Cycle yank stack", "Edit"),
+    HelpKeybind::bound("macro-record", "Start/stop recording a macro", "Edit"),
+    HelpKeybind::bound("macro-replay", "Replay the last macro", "Edit"),
 
     // Line Operations
-    HelpKeybind::new("Alt+Up", "Move line up", "Lines"),
-    HelpKeybind::new("Alt+Down", "Move line down", "Lines"),
-    HelpKeybind::new("Alt+Shift+Up", "Duplicate line up", "Lines"),
-    HelpKeybind::new("Alt+Shift+Down", "Duplicate line down", "Lines"),
+    HelpKeybind::bound("move-line-up", "Move line or selected lines up", "Lines"),
+    HelpKeybind::bound("move-line-down", "Move line or selected lines down", "Lines"),
+    HelpKeybind::bound("duplicate-line-up", "Duplicate line up", "Lines"),
+    HelpKeybind::bound("duplicate-line", "Duplicate line down", "Lines"),
+    HelpKeybind::bound("duplicate-selection", "Duplicate selection", "Lines"),
+    HelpKeybind::bound("delete-line", "Delete line or selected lines", "Lines"),
 
     // Movement
     HelpKeybind::new("Arrow keys", "Move cursor", "Movement"),
     HelpKeybind::with_alt("Home", "Ctrl+A", "Go to line start (smart)", "Movement"),
     HelpKeybind::with_alt("End", "Ctrl+E", "Go to line end", "Movement"),
-    HelpKeybind::with_alt("Alt+Left", "Alt+B", "Move word left", "Movement"),
-    HelpKeybind::with_alt("Alt+Right", "Alt+F", "Move word right", "Movement"),
+    HelpKeybind::bound("word-left", "Move word left", "Movement"),
+    HelpKeybind::bound("word-right", "Move word right", "Movement"),
     HelpKeybind::new("PageUp", "Page up", "Movement"),
     HelpKeybind::new("PageDown", "Page down", "Movement"),
-    HelpKeybind::with_alt("Ctrl+G", "F5", "Go to line", "Movement"),
+    HelpKeybind::new("Alt+M <letter>", "Set mark (A-Z are global)", "Movement"),
+    HelpKeybind::new("Alt+; <letter>", "Jump to mark line", "Movement"),
+    HelpKeybind::new("Alt+` <letter>", "Jump to exact mark position", "Movement"),
+    HelpKeybind::bound("toggle-bookmark", "Toggle bookmark on line", "Movement"),
+    HelpKeybind::bound("next-bookmark", "Next bookmark (any file)", "Movement"),
+    HelpKeybind::bound("prev-bookmark", "Previous bookmark (any file)", "Movement"),
+    HelpKeybind::bound("goto-line", "Go to line", "Movement"),
 
     // Selection
     HelpKeybind::new("Shift+Arrow", "Extend selection", "Selection"),
-    HelpKeybind::new("Ctrl+L", "Select line", "Selection"),
-    HelpKeybind::new("Ctrl+D", "Select word / next occurrence", "Selection"),
-    HelpKeybind::new("Escape", "Clear selection / collapse cursors", "Selection"),
-    HelpKeybind::new("Ctrl+Alt+Up", "Add cursor above", "Selection"),
-    HelpKeybind::new("Ctrl+Alt+Down", "Add cursor below", "Selection"),
+    HelpKeybind::bound("select-line", "Select line", "Selection"),
+    HelpKeybind::bound("select-word", "Select word / next occurrence", "Selection"),
+    HelpKeybind::bound("select-all-occurrences", "Select all occurrences", "Selection"),
+    HelpKeybind::bound("cursors-to-line-ends", "Split selection into lines", "Selection"),
+    HelpKeybind::new("Escape", "Clear selection / collapse cursors / dismiss messages", "Selection"),
+    HelpKeybind::bound("cursor-above", "Add cursor above", "Selection"),
+    HelpKeybind::bound("block-select", "Toggle block selection", "Selection"),
+    HelpKeybind::bound("cursor-below", "Add cursor below", "Selection"),
+    HelpKeybind::bound("cursor-remove-last", "Remove last added cursor", "Selection"),
+    HelpKeybind::new("Ctrl+Alt+Left/Right", "Rotate primary cursor", "Selection"),
 
     // Search
-    HelpKeybind::new("Ctrl+F", "Find", "Search"),
-    HelpKeybind::new("Ctrl+R", "Find and replace", "Search"),
-    HelpKeybind::new("F3", "Find next", "Search"),
-    HelpKeybind::new("Shift+F3", "Find previous", "Search"),
-    HelpKeybind::new("F4", "Search in files", "Search"),
+    HelpKeybind::bound("find", "Find", "Search"),
+    HelpKeybind::bound("replace", "Find and replace", "Search"),
+    HelpKeybind::bound("find-next", "Find next", "Search"),
+    HelpKeybind::bound("find-prev", "Find previous", "Search"),
+    HelpKeybind::bound("search-files", "Search in files", "Search"),
+    HelpKeybind::bound("live-grep", "Search in files as you type", "Search"),
     HelpKeybind::new("Alt+I", "Toggle case sensitivity (in find)", "Search"),
     HelpKeybind::new("Alt+X", "Toggle regex mode (in find)", "Search"),
+    HelpKeybind::new("$1 / ${name}", "Capture group in replacement (regex mode)", "Search"),
     HelpKeybind::new("Alt+Enter", "Replace all (in find)", "Search"),
 
     // Brackets & Quotes
-    HelpKeybind::with_alt("Alt+[", "Alt+]", "Jump to matching bracket", "Brackets"),
-    HelpKeybind::new("Alt+'", "Cycle quote type (\"/'/`)", "Brackets"),
-    HelpKeybind::new("Alt+\"", "Remove surrounding quotes", "Brackets"),
-    HelpKeybind::new("Alt+(", "Cycle bracket type (/{/[)", "Brackets"),
-    HelpKeybind::new("Alt+)", "Remove surrounding brackets", "Brackets"),
+    HelpKeybind::bound("jump-bracket", "Jump to matching bracket", "Brackets"),
+    HelpKeybind::bound("cycle-quotes", "Cycle quote type (\"/'/`)", "Brackets"),
+    HelpKeybind::bound("remove-surrounding", "Remove surrounding quotes", "Brackets"),
+    HelpKeybind::bound("cycle-brackets", "Cycle bracket type (/{/[)", "Brackets"),
+    HelpKeybind::bound("remove-surrounding-brackets", "Remove surrounding brackets", "Brackets"),
 
     // LSP / Code Intelligence
-    HelpKeybind::new("F1", "Show hover info", "LSP"),
-    HelpKeybind::new("F2", "Rename symbol", "LSP"),
-    HelpKeybind::new("F12", "Go to definition", "LSP"),
-    HelpKeybind::new("Shift+F12", "Find references", "LSP"),
-    HelpKeybind::new("Ctrl+N", "Trigger completion", "LSP"),
-    HelpKeybind::new("Alt+M", "LSP server manager", "LSP"),
+    HelpKeybind::bound("hover", "Show hover info", "LSP"),
+    HelpKeybind::bound("rename", "Rename symbol", "LSP"),
+    HelpKeybind::bound("goto-definition", "Go to definition", "LSP"),
+    HelpKeybind::bound("find-references", "Find references", "LSP"),
+    HelpKeybind::bound("completion", "Trigger completion", "LSP"),
+    HelpKeybind::bound("code-lens", "Run code lens on current line", "LSP"),
+    HelpKeybind::bound("code-actions", "Show code actions / quick fixes", "LSP"),
+    HelpKeybind::bound("open-link", "Follow link under cursor", "LSP"),
+    HelpKeybind::bound("diagnostics-panel", "Errors and warnings in all files", "LSP"),
+    HelpKeybind::new("Alt+Click", "Follow link at mouse position", "LSP"),
+    HelpKeybind::bound("server-manager", "LSP server manager", "LSP"),
+
+    // Git
+    HelpKeybind::bound("next-hunk", "Next unstaged hunk", "Git"),
+    HelpKeybind::bound("prev-hunk", "Previous unstaged hunk", "Git"),
+    HelpKeybind::bound("compare-head", "Compare with HEAD side by side", "Git"),
+    HelpKeybind::bound("compare-backup", "Compare with latest backup side by side", "Git"),
+    HelpKeybind::new("Enter / c / p", "Git log: show, checkout, cherry-pick", "Git"),
 
     // Help & Commands
-    HelpKeybind::new("Ctrl+P", "Command palette", "Help"),
-    HelpKeybind::new("Shift+F1", "Help / keybindings", "Help"),
+    HelpKeybind::bound("command-palette", "Command palette", "Help"),
+    HelpKeybind::bound("help", "Help / keybindings", "Help"),
+    HelpKeybind::new("= 1920/3", "Calculate (in command palette)", "Help"),
 
     // File Explorer (Fortress/Fuss mode)
     HelpKeybind::new("Up/Down", "Navigate files", "Explorer"),
@@ -267,6 +503,8 @@ const ALL_KEYBINDS: &[HelpKeybind] = &[
     HelpKeybind::new("l", "Open in vertical split", "Explorer"),
     HelpKeybind::new("Alt+G", "Git status", "Explorer"),
     HelpKeybind::new("Alt+.", "Toggle hidden files", "Explorer"),
+    HelpKeybind::new("Alt+X", "Exclude directory from quick open and search", "Explorer"),
+    HelpKeybind::new("Alt+C", "Copy relative path", "Explorer"),
 ];
 
 /// Prompt state for quit confirmation
@@ -280,6 +518,8 @@ enum PromptState {
     CloseBufferConfirm,
     /// Restore prompt: Restore/Discard
     RestoreBackup,
+    /// Another instance holds the workspace lock (holder's PID)
+    WorkspaceLocked(u32),
     /// Text input prompt (label, current input buffer)
     TextInput { label: String, buffer: String, action: TextInputAction },
     /// LSP rename modal with original name shown
@@ -290,12 +530,93 @@ enum PromptState {
         line: u32,
         col: u32,
     },
+    /// Workspace edit targets documents that changed since it was computed
+    WorkspaceEditConflict {
+        edit: WorkspaceEdit,
+        verb: String,
+        conflicts: Vec<String>,
+    },
+    /// Waiting for a mark letter (a-z local, A-Z file-global)
+    MarkKey(MarkAction),
+    /// Marks list panel: (mark, "file:line", preview)
+    MarksPanel {
+        entries: Vec<(char, String, String)>,
+        selected_index: usize,
+    },
+    /// Bookmarks list panel, each with a preview of its line
+    BookmarksPanel {
+        entries: Vec<(Bookmark, String)>,
+        selected_index: usize,
+    },
+    /// LSP code actions for the cursor or selection
+    CodeActions {
+        actions: Vec<CodeAction>,
+        selected_index: usize,
+    },
+    /// String literals in the file or selection, with a replace template
+    StringsPanel {
+        literals: Vec<StringLiteral>,
+        selected_index: usize,
+        /// Replacement text; `{}` stands for the literal
+        template: String,
+    },
+    /// The workspace is huge: scan it anyway for the command? Yes/No
+    DeepScan(String),
+    /// The file is colored terminal output: Color view/Strip/Keep
+    AnsiCodes,
+    /// The alternate file doesn't exist yet: Create/Cancel
+    CreateAlternate(Alternate),
+    /// Lines changed by the last Replace All, each revertible on its own
+    ReplacedLines {
+        lines: Vec<ReplacedLine>,
+        selected_index: usize,
+    },
+    /// Commit history, newest first
+    GitLog {
+        commits: Vec<Commit>,
+        selected_index: usize,
+        /// Panel title: the file or directory the history is limited to
+        title: String,
+    },
+    /// Diagnostics of every file the language servers reported on, most
+    /// severe first, with the URI of the file each belongs to
+    DiagnosticsPanel {
+        diagnostics: Vec<(String, Diagnostic)>,
+        selected_index: usize,
+    },
+    /// Clipboard entries to paste, pinned ones first (see
+    /// `Workspace::clipboard_entries`)
+    ClipboardRing {
+        selected_index: usize,
+    },
+    /// An open file changed on disk while its buffer had unsaved edits:
+    /// Reload/Keep/Diff
+    DiskConflict(PathBuf),
+    /// Saving a file someone else wrote since it was loaded:
+    /// Overwrite/Diff/Save as/Cancel
+    SaveConflict(PathBuf),
+    /// Saving a file the user may not write: Sudo/Save as/Cancel
+    SavePermission(PathBuf),
+    /// A paste far bigger than the buffer: Paste/Scratch buffer/Cancel
+    LargePaste {
+        text: String,
+        mode: PasteMode,
+    },
+    /// Backup generations of the current file, newest first, with their
+    /// content
+    RecoverVersion {
+        versions: Vec<(Generation, String)>,
+        selected_index: usize,
+    },
     /// LSP references panel
     ReferencesPanel {
         locations: Vec<Location>,
         selected_index: usize,
         /// Search query being typed (for filtering)
         query: String,
+        /// For Find in Open Files: what was searched for, and the stamp of
+        /// the open buffers it was found in, to search again when they change
+        live_search: Option<(String, u64)>,
     },
     /// Find/Replace dialog in status bar
     FindReplace {
@@ -327,19 +648,41 @@ enum PromptState {
     FileSearch {
         /// Search query
         query: String,
-        /// Search results: (file_path, line_number, line_content)
-        results: Vec<FileSearchResult>,
+        /// Search results, as they arrive
+        results: Vec<SearchHit>,
         /// Currently selected index
         selected_index: usize,
         /// Scroll offset for long lists
         scroll_offset: usize,
         /// Whether search is in progress
         searching: bool,
+        /// Search the open buffers, unsaved changes included, instead of
+        /// the files on disk (Tab switches)
+        open_files: bool,
+    },
+    /// Live grep (Shift+F4): searches the files again as the query is typed
+    LiveGrep {
+        query: String,
+        /// Extensions of the files searched, e.g. "rs, toml" (all when empty)
+        types: String,
+        /// Typing goes to the file types (Tab switches)
+        editing_types: bool,
+        /// The query is a regex (Alt+X switches)
+        regex: bool,
+        /// Search results, as they arrive
+        results: Vec<SearchHit>,
+        selected_index: usize,
+        scroll_offset: usize,
+        searching: bool,
+        /// The query isn't a valid regex
+        invalid: bool,
     },
     /// Command palette (Ctrl+P)
     CommandPalette {
         /// Search/filter query (with > prefix)
         query: String,
+        /// Commands contributed for the moment the palette opened
+        context: Vec<PaletteCommand>,
         /// Filtered commands matching query
         filtered: Vec<PaletteCommand>,
         /// Currently selected index
@@ -347,6 +690,32 @@ enum PromptState {
         /// Scroll offset for long lists
         scroll_offset: usize,
     },
+    /// Quick open (Ctrl+Shift+P): fuzzy-find a workspace file by name
+    QuickOpen {
+        /// Search/filter query
+        query: String,
+        /// Every indexed file, relative to the workspace root
+        files: Vec<PathBuf>,
+        /// Files matching the query, best match first
+        filtered: Vec<PathBuf>,
+        /// Currently selected index
+        selected_index: usize,
+        /// Scroll offset for long lists
+        scroll_offset: usize,
+    },
+    /// Color theme picker; the selection is previewed as it moves
+    ThemePicker {
+        /// Search/filter query
+        query: String,
+        /// Themes matching the query: (name, is_builtin)
+        filtered: Vec<(String, bool)>,
+        /// Currently selected index
+        selected_index: usize,
+        /// Scroll offset for long lists
+        scroll_offset: usize,
+        /// Theme to restore if the picker is cancelled
+        original: String,
+    },
     /// Help menu (Shift+F1)
     HelpMenu {
         /// Search/filter query
@@ -394,15 +763,25 @@ enum HitRegion {
     None,
 }
 
-/// A single result from multi-file search
-#[derive(Debug, Clone, PartialEq)]
-struct FileSearchResult {
-    /// Relative path to file
-    path: PathBuf,
-    /// Line number (1-indexed for display)
-    line_num: usize,
-    /// The matching line content (trimmed)
-    line_content: String,
+/// What the Copy Path commands put on the clipboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PathForm {
+    Absolute,
+    /// Relative to the workspace root
+    Relative,
+    /// Relative, with the cursor's line or the selected lines, e.g. `src/main.rs:12-14`
+    WithLine,
+    FileName,
+}
+
+/// Where quick open shows the chosen file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenTarget {
+    /// The active pane, keeping its back history
+    Pane,
+    VerticalSplit,
+    HorizontalSplit,
+    NewTab,
 }
 
 /// Action to perform when text input is complete
@@ -414,8 +793,56 @@ enum TextInputAction {
     GitTag,
     /// Go to line (and optionally column)
     GotoLine,
+    /// Pipe the selection (or buffer) through a shell command
+    FilterCommand,
+    /// Insert a shell command's output at every cursor
+    InsertCommandOutput,
+    /// Open a directory as another workspace
+    OpenWorkspace,
+    /// Save the buffer to another file
+    SaveAs,
+    /// Record a keyboard macro into the named register
+    RecordMacro,
+    /// Replay a keyboard macro, e.g. `a 5`
+    ReplayMacro,
+    /// Bookmark the cursor's line with a label
+    BookmarkLabel,
+}
+
+impl TextInputAction {
+    /// Whether the prompt takes a shell command (and browses shell history)
+    fn is_shell_command(&self) -> bool {
+        matches!(self, TextInputAction::FilterCommand | TextInputAction::InsertCommandOutput)
+    }
+}
+
+/// What to do with the letter typed after a mark key
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MarkAction {
+    /// Set a mark at the cursor
+    Set,
+    /// Jump to the mark's line (first non-blank column)
+    JumpLine,
+    /// Jump to the mark's exact position
+    JumpExact,
+}
+
+/// The question a large paste asks before going in
+fn large_paste_question(text: &str) -> String {
+    let lines = text.lines().count();
+    format!(
+        "Paste {} ({} lines)? [P]aste / [S]cratch buffer / [C]ancel",
+        image::format_bytes(text.len() as u64),
+        lines
+    )
 }
 
+/// Question asked when a file turns out to be colored terminal output
+const ANSI_PROMPT: &str = "File has ANSI color codes. [C]olor view / [S]trip / [K]eep as is";
+
+/// Maximum number of remembered shell commands
+const SHELL_HISTORY_LIMIT: usize = 50;
+
 /// LSP UI state
 #[derive(Debug, Default)]
 struct LspState {
@@ -423,6 +850,8 @@ struct LspState {
     hover: Option<HoverInfo>,
     /// Whether hover popup is visible
     hover_visible: bool,
+    /// Signature of the call being typed, shown until `)` or Escape
+    signature_help: Option<SignatureHelp>,
     /// Original unfiltered completion list from LSP
     completions_original: Vec<CompletionItem>,
     /// Current filtered completion list
@@ -441,15 +870,103 @@ struct LspState {
     definition_locations: Vec<Location>,
     /// Pending request IDs (to match responses)
     pending_hover: Option<i64>,
+    pending_signature_help: Option<i64>,
+    /// prepareRename request, with the rename modal to open (named by the
+    /// word under the cursor) if the server leaves the symbol to the editor
+    pending_prepare_rename: Option<(i64, PromptState)>,
     pending_completion: Option<i64>,
+    /// Where to send the outcome of a server's workspace/applyEdit that is
+    /// waiting on the edit conflict prompt
+    edit_reply: Option<Sender<ApplyEditResult>>,
+    /// completionItem/resolve for an accepted item, with the file it was
+    /// accepted in and where its text went
+    pending_completion_resolve: Option<(i64, PathBuf, CompletionInsertion)>,
     pending_definition: Option<i64>,
     pending_references: Option<i64>,
+    pending_code_actions: Option<i64>,
+    /// Code lenses for the active file
+    code_lenses: Vec<CodeLens>,
+    pending_code_lens: Option<i64>,
+    /// Document links for the active file
+    document_links: Vec<DocumentLink>,
+    pending_document_links: Option<i64>,
+    /// Foldable line ranges reported by the server for the current file
+    folding_ranges: Vec<(usize, usize)>,
+    pending_folding_ranges: Option<i64>,
+    /// Resolve request whose target should be opened when it arrives
+    pending_link_open: Option<i64>,
+    /// When the buffer last changed, if code lenses and links need refreshing
+    annotations_stale_since: Option<Instant>,
     /// Last known buffer hash (to detect changes)
     last_buffer_hash: Option<u64>,
     /// Last file path that was synced to LSP
     last_synced_path: Option<PathBuf>,
 }
 
+/// Where an accepted completion replaced columns `start..end` of `line`.
+/// Edits the server sent along with the item refer to the text from before.
+#[derive(Debug, Clone, Copy)]
+struct CompletionInsertion {
+    line: usize,
+    start: usize,
+    end: usize,
+    /// Lines the inserted text added
+    added_lines: usize,
+    /// Column the rest of the line (after `end`) starts at now, on the
+    /// insertion's last line
+    new_end: usize,
+    /// History version once the completion was recorded, for adding the
+    /// edits to its undo group
+    group: usize,
+}
+
+impl CompletionInsertion {
+    /// Where `pos` in the text from before the insertion is now; a position
+    /// inside the replaced word goes to its start
+    fn map(&self, pos: LspPosition) -> LspPosition {
+        let (line, col) = (pos.line as usize, pos.character as usize);
+        if line < self.line || (line == self.line && col <= self.start) {
+            pos
+        } else if line > self.line {
+            LspPosition::new((line + self.added_lines) as u32, pos.character)
+        } else if col >= self.end {
+            LspPosition::new((line + self.added_lines) as u32, (col - self.end + self.new_end) as u32)
+        } else {
+            LspPosition::new(pos.line, self.start as u32)
+        }
+    }
+
+    /// `edits` moved past the insertion
+    fn shift(&self, edits: &[TextEdit]) -> Vec<TextEdit> {
+        edits.iter().map(|edit| TextEdit {
+            range: LspRange::new(self.map(edit.range.start), self.map(edit.range.end)),
+            new_text: edit.new_text.clone(),
+        }).collect()
+    }
+}
+
+/// A string literal listed in the string extraction panel
+#[derive(Debug, Clone, PartialEq)]
+struct StringLiteral {
+    line: usize,
+    start_col: usize,
+    end_col: usize,
+    /// Source text including quotes and prefixes
+    text: String,
+    /// Whether the templated replace applies to it
+    included: bool,
+}
+
+/// A line changed by Replace All, listed for review
+#[derive(Debug, Clone, PartialEq)]
+struct ReplacedLine {
+    line: usize,
+    before: String,
+    after: String,
+    /// Whether the line has been put back to `before`
+    reverted: bool,
+}
+
 /// A search match position
 #[derive(Debug, Clone, PartialEq)]
 struct SearchMatch {
@@ -510,10 +1027,36 @@ struct GhostTextState {
     all_buffer_words_cache: Vec<String>,
 }
 
+/// An expanded snippet whose tabstops Tab and Shift+Tab move between
+#[derive(Debug, Clone)]
+struct SnippetSession {
+    /// Buffer character ranges of each tabstop's copies, in visiting order;
+    /// the last is the final cursor position
+    stops: Vec<Vec<Range<usize>>>,
+    /// Tabstop the cursors are on
+    current: usize,
+    /// Tab and buffer index the snippet was inserted in
+    buffer: (usize, usize),
+}
+
+/// A workspace open in the background, with the editor state that belongs
+/// to it rather than to the window
+struct ParkedWorkspace {
+    workspace: Workspace,
+    terminal: TerminalPanel,
+    lsp_state: LspState,
+    search_state: SearchState,
+    last_edit_time: Option<Instant>,
+    last_backup_time: Option<Instant>,
+    disk_conflicts: Vec<PathBuf>,
+}
+
 /// Main editor state
 pub struct Editor {
     /// The workspace (owns tabs, panes, fuss mode, and config)
     workspace: Workspace,
+    /// Other open workspaces, the next one first
+    parked: VecDeque<ParkedWorkspace>,
     /// Terminal screen
     screen: Screen,
     /// Is the editor running?
@@ -522,14 +1065,20 @@ pub struct Editor {
     clipboard: Option<Clipboard>,
     /// Fallback internal clipboard if system clipboard unavailable
     internal_clipboard: String,
-    /// Message to display in status bar
-    message: Option<String>,
+    /// Text last copied from a block selection, which pastes as a block
+    block_clipboard: Option<String>,
+    /// Shift+arrows and dragging select a block instead of running text
+    block_select: bool,
+    /// Messages to display in the status bar
+    messages: Messages,
     /// Escape key timeout in milliseconds (for Alt key detection)
     escape_time: u64,
     /// Current prompt state
     prompt: PromptState,
     /// Time of last edit (for idle backup timing), None if no pending backup
     last_edit_time: Option<Instant>,
+    /// Time the last backup was written (shown in the status bar while modified)
+    last_backup_time: Option<Instant>,
     /// LSP-related UI state
     lsp_state: LspState,
     /// LSP server manager panel
@@ -540,6 +1089,8 @@ pub struct Editor {
     bracket_cache: BracketMatchCache,
     /// Ghost text inline autocomplete state
     ghost_text: GhostTextState,
+    /// Snippet being filled in, if any
+    snippet: Option<SnippetSession>,
     /// Yank stack (kill ring) - separate from system clipboard
     yank_stack: Vec<String>,
     /// Current index in yank stack when cycling with Alt+Y
@@ -554,8 +1105,66 @@ pub struct Editor {
     terminal_resize_start_y: u16,
     /// Terminal resize: starting height when drag began
     terminal_resize_start_height: u16,
+    /// Dragging in a pane's minimap scrolls that pane
+    minimap_dragging: bool,
+    /// Line whose number was clicked, while dragging out a range of lines
+    gutter_drag_line: Option<usize>,
+    /// Auto-hidden panel shown while the pointer is over it (FussMode or
+    /// Terminal)
+    peeking: Option<Focus>,
+    /// Where the cursor was after the last event, to notice file switches
+    /// (see `note_file_switch`)
+    last_place: Option<Jump>,
+    /// How the panes comparing a file with another version of it match up,
+    /// with the content hashes of both sides it was worked out for
+    comparison: Option<((u64, u64), LineDiff)>,
+    /// Status last written for shell prompts (`fackr --status`)
+    published_status: Option<Status>,
+    /// Input that arrived while a slow operation blocked the loop, replayed in order
+    input_queue: VecDeque<Event>,
+    /// Whether the event being handled came from `input_queue`
+    replaying_input: bool,
+    /// Active input recording (see `recording.rs`)
+    recorder: Option<Recorder>,
+    /// Event to write to the recording once handled; the Escape-prefix
+    /// logic replaces it with the combined Alt key
+    recording_event: Option<Event>,
+    /// Recording being fed back into `input_queue`
+    replay: Option<Replay>,
+    /// Keyboard macro registers and any recording (see `macros.rs`)
+    macros: Macros,
+    /// Recent shell commands (filter / insert output), most recent last
+    shell_history: Vec<String>,
+    /// Position while browsing shell history in a prompt
+    shell_history_pos: Option<usize>,
     /// Current keyboard focus target
     focus: Focus,
+    /// Size of the followed log when its tab was last refreshed
+    log_len: u64,
+    /// What the last prefetch was for: the current file, the explorer's
+    /// selected file and the number of references listed
+    prefetch_key: (Option<PathBuf>, Option<PathBuf>, usize),
+    /// When the last input event arrived
+    last_input_time: Instant,
+    /// The F4 or live grep search under way
+    file_search: Option<Search>,
+    /// When live grep's query last changed, until its search starts
+    live_grep_due: Option<Instant>,
+    /// Lines of the file previewed in live grep
+    grep_preview: Option<(PathBuf, Vec<String>)>,
+    /// A workspace analysis was started and its report isn't shown yet
+    insights_pending: bool,
+    /// When the explorer first missed files created or deleted outside
+    /// the editor
+    tree_stale_since: Option<Instant>,
+    /// Files changed on disk under unsaved edits, waiting to be asked about
+    disk_conflicts: Vec<PathBuf>,
+    /// Last encoded image preview: (path, cols, rows, escape sequence)
+    image_cache: Option<(PathBuf, u16, u16, String)>,
+    /// Kitty keeps images until told to delete them
+    kitty_images_shown: bool,
+    /// Vim emulation state, used while `vim_mode` is on in the config
+    vim: Vim,
 }
 
 impl Editor {
@@ -572,12 +1181,12 @@ impl Editor {
     }
 
     pub fn new_with_screen_and_workspace(screen: Screen, workspace_root: PathBuf) -> Result<Self> {
-        // Read escape timeout from environment, default to 5ms
+        // Read escape timeout from environment, else the user's settings
         // Similar to vim's ttimeoutlen or tmux's escape-time
         let escape_time = std::env::var("FAC_ESCAPE_TIME")
             .ok()
             .and_then(|s| s.parse().ok())
-            .unwrap_or(5);
+            .unwrap_or_else(|| Settings::load().escape_time);
 
         // Try to initialize system clipboard, fall back to internal if unavailable
         let clipboard = Clipboard::new().ok();
@@ -585,40 +1194,85 @@ impl Editor {
         let workspace = Workspace::open(workspace_root)?;
 
         // Check if there are backups to restore
+        let macros = Macros::load(&workspace.macro_file());
         let has_backups = workspace.has_backups();
 
         // Create terminal panel with screen dimensions
-        let terminal = TerminalPanel::new(screen.cols, screen.rows);
+        let mut terminal = TerminalPanel::new(screen.cols, screen.rows);
+        terminal.set_docked_right(workspace.config.layout.terminal == Edge::Right);
+        if let Some(percent) = workspace.terminal_height {
+            terminal.set_height_percent(percent);
+        }
 
         let mut editor = Self {
             workspace,
+            parked: VecDeque::new(),
             screen,
             running: true,
             clipboard,
             internal_clipboard: String::new(),
-            message: None,
+            block_clipboard: None,
+            block_select: false,
+            messages: Messages::default(),
             escape_time,
             prompt: PromptState::None,
             last_edit_time: None, // No pending backup initially
+            last_backup_time: None,
             lsp_state: LspState::default(),
             server_manager: ServerManagerPanel::new(),
             search_state: SearchState::default(),
             bracket_cache: BracketMatchCache::default(),
             ghost_text: GhostTextState::default(),
+            snippet: None,
             yank_stack: Vec::with_capacity(32),
             yank_index: None,
             last_yank_len: 0,
             terminal,
             terminal_resize_dragging: false,
+            minimap_dragging: false,
+            gutter_drag_line: None,
+            peeking: None,
+            last_place: None,
+            comparison: None,
             terminal_resize_start_y: 0,
             terminal_resize_start_height: 0,
+            published_status: None,
+            input_queue: VecDeque::new(),
+            replaying_input: false,
+            recorder: None,
+            recording_event: None,
+            replay: None,
+            macros,
+            shell_history: Vec::new(),
+            shell_history_pos: None,
             focus: Focus::Editor,
+            log_len: 0,
+            prefetch_key: (None, None, 0),
+            last_input_time: Instant::now(),
+            file_search: None,
+            live_grep_due: None,
+            grep_preview: None,
+            insights_pending: false,
+            tree_stale_since: None,
+            disk_conflicts: Vec::new(),
+            image_cache: None,
+            vim: Vim::new(),
+            kitty_images_shown: false,
         };
 
-        // If there are backups, show restore prompt
-        if has_backups {
+        editor.apply_theme();
+        editor.apply_logging();
+        editor.note_keymap_warnings();
+        editor.note_degraded_workspace();
+        editor.note_huge_workspace();
+
+        // Another instance owns this workspace: ask before touching its state
+        if let Some(holder) = editor.workspace.lock_conflict.take() {
+            editor.prompt_workspace_locked(holder);
+        } else if has_backups {
+            // If there are backups, show restore prompt
             editor.prompt = PromptState::RestoreBackup;
-            editor.message = Some("Recovered unsaved changes. [R]estore / [D]iscard / [Esc]".to_string());
+            editor.messages.prompt("Recovered unsaved changes. [R]estore / [D]iscard / [Esc]".to_string());
         }
 
         Ok(editor)
@@ -635,7 +1289,16 @@ impl Editor {
         if is_initial {
             // Replace workspace with one detected from the file path
             // This finds existing .fackr/ in parent dirs or uses file's parent
+            self.workspace.release_lock();
             self.workspace = Workspace::open_with_file(&file_path)?;
+            self.apply_theme();
+            self.apply_logging();
+            self.note_keymap_warnings();
+            self.note_degraded_workspace();
+            self.note_huge_workspace();
+            if let Some(holder) = self.workspace.lock_conflict.take() {
+                self.prompt_workspace_locked(holder);
+            }
         } else {
             // Just open the file in the current workspace
             self.workspace.open_file(&file_path)?;
@@ -644,6 +1307,79 @@ impl Editor {
         Ok(())
     }
 
+    fn prompt_workspace_locked(&mut self, holder: LockInfo) {
+        self.prompt = PromptState::WorkspaceLocked(holder.pid);
+        self.messages.prompt(workspace_locked_message(holder.pid));
+    }
+
+    /// Reason edits are refused in the active buffer, if any
+    fn read_only_reason(&self) -> Option<&'static str> {
+        if self.buffer_entry().is_read_only() {
+            Some("Read-only document (Alt+R to refresh)")
+        } else if self.workspace.lock_mode == LockMode::ReadOnly {
+            Some("Workspace opened read-only (locked by another fackr)")
+        } else {
+            None
+        }
+    }
+
+    /// Status bar note about where (or whether) workspace state is saved,
+    /// or that the file is open in large-file mode
+    fn state_note(&self) -> Option<&'static str> {
+        match self.workspace.lock_mode {
+            LockMode::ReadOnly => Some("read-only: locked"),
+            LockMode::NoPersistence => Some("state not saved"),
+            LockMode::Owner if self.workspace.degraded => Some(".fackr read-only"),
+            LockMode::Owner => self.buffer().large.then_some("large file"),
+        }
+    }
+
+    /// Load the theme named in the workspace config, reporting any problem
+    fn apply_theme(&mut self) {
+        let (theme, warnings) = Theme::load(&self.workspace.config.theme);
+        self.screen.theme = theme;
+        if let Some(warning) = warnings.first() {
+            self.messages.info(format!("Theme: {}", warning));
+        }
+    }
+
+    /// Point the editor log at this workspace and apply its configured level
+    fn apply_logging(&mut self) {
+        if let Err(e) = logging::set_file(&self.workspace.log_file()) {
+            self.messages.error(format!("Editor log unavailable: {}", e));
+        }
+        if !logging::level_from_env() && !logging::set_level(&self.workspace.config.log_level) {
+            self.messages.info(format!("Unknown log level: {}", self.workspace.config.log_level));
+        }
+        tracing::info!("opened workspace {}", self.workspace.root.display());
+    }
+
+    /// Report the first problem found in the keymap.toml files
+    fn note_keymap_warnings(&mut self) {
+        if let Some(warning) = self.workspace.keymap.warnings.first() {
+            self.messages.info(format!("Keymap: {}", warning));
+        }
+    }
+
+    /// Tell the user where state goes when `.fackr/` isn't writable
+    fn note_degraded_workspace(&mut self) {
+        if self.workspace.degraded && self.workspace.persists() {
+            self.messages.info(format!(
+                ".fackr/ is not writable; state and backups are kept in {}",
+                self.workspace.state_dir.display()
+            ));
+        }
+    }
+
+    fn note_huge_workspace(&mut self) {
+        if self.workspace.scan_limited {
+            self.messages.info(format!(
+                "{} is very large; git status is off and quick open and search ask first",
+                self.workspace.root.display()
+            ));
+        }
+    }
+
     // ============================================================
     // ACCESSOR METHODS - These provide access to current tab/pane/buffer
     // ============================================================
@@ -676,6 +1412,24 @@ impl Editor {
         &mut tab.buffers[buffer_idx].buffer
     }
 
+    /// How the current buffer indents: as detected in its file, otherwise
+    /// the workspace default
+    fn indent(&self) -> Indent {
+        self.buffer().indent.unwrap_or(if self.workspace.config.use_spaces {
+            Indent::Spaces(self.workspace.config.tab_width)
+        } else {
+            Indent::Tabs
+        })
+    }
+
+    /// Columns one level of indentation spans in the current buffer
+    fn indent_width(&self) -> usize {
+        match self.indent() {
+            Indent::Spaces(width) => width,
+            Indent::Tabs => self.workspace.config.tab_width,
+        }
+    }
+
     /// Invalidate syntax highlight cache from a given line onward.
     /// Call this when buffer content changes at or after the specified line.
     #[inline]
@@ -695,6 +1449,10 @@ impl Editor {
     /// Get cached bracket match for current cursor position.
     /// Computes and caches the result if needed.
     fn get_bracket_match(&mut self) -> Option<(usize, usize)> {
+        // An unmatched bracket would scan the whole of a large file
+        if self.buffer().large {
+            return None;
+        }
         let cursor = self.cursor();
         let cursor_pos = (cursor.line, cursor.col);
 
@@ -802,6 +1560,7 @@ impl Editor {
         // Initial render
         self.screen.refresh_size()?;
         self.render()?;
+        self.publish_status(false);
 
         while self.running {
             // Track whether we need to re-render
@@ -809,32 +1568,22 @@ impl Editor {
 
             // Poll with a short timeout to allow LSP processing
             // This balances responsiveness with CPU usage
-            if event::poll(Duration::from_millis(50))? {
-                match event::read()? {
-                    Event::Key(key_event) => self.process_key(key_event)?,
-                    Event::Mouse(mouse_event) => self.process_mouse(mouse_event)?,
-                    Event::Resize(cols, rows) => {
-                        self.screen.cols = cols;
-                        self.screen.rows = rows;
-                        self.terminal.update_screen_size(cols, rows);
-                    }
-                    _ => {}
+            self.feed_replay();
+            if !self.input_queue.is_empty() || event::poll(Duration::from_millis(50))? {
+                // Process queued and pending events before rendering
+                while let Some(event) = self.next_pending_event()? {
+                    self.handle_event(event)?;
+                    self.last_input_time = Instant::now();
                 }
                 needs_render = true;
+            }
 
-                // Process any additional queued events before rendering
-                while event::poll(Duration::from_millis(0))? {
-                    match event::read()? {
-                        Event::Key(key_event) => self.process_key(key_event)?,
-                        Event::Mouse(mouse_event) => self.process_mouse(mouse_event)?,
-                        Event::Resize(cols, rows) => {
-                            self.screen.cols = cols;
-                            self.screen.rows = rows;
-                            self.terminal.update_screen_size(cols, rows);
-                        }
-                        _ => {}
-                    }
-                }
+            // Read ahead the files a jump is likely to open next
+            self.prefetch_likely_files();
+
+            // Take in changes made to files outside the editor
+            if self.handle_file_events() {
+                needs_render = true;
             }
 
             // Poll terminal for output (only render if data received)
@@ -852,91 +1601,602 @@ impl Editor {
                 needs_render = true;
             }
 
+            // Keep the server manager's running states current
+            if self.server_manager.visible {
+                let running = self.workspace.lsp.server_statuses();
+                if running != self.server_manager.running {
+                    self.server_manager.running = running;
+                    needs_render = true;
+                }
+            }
+
+            // Project notes save themselves shortly after each edit
+            if self.last_edit_time.is_some_and(|t| t.elapsed() >= NOTES_SAVE_DELAY) {
+                match self.workspace.save_notes() {
+                    Ok(saved) => needs_render |= saved,
+                    Err(e) => self.messages.error(format!("Failed to save notes: {}", e)),
+                }
+            }
+
             // Check if it's time for idle backup
             self.maybe_idle_backup();
 
+            // Write editing statistics now and then
+            self.workspace.save_stats(false);
+
+            // Start live grep's search once typing pauses, and take in
+            // search results as workers find them
+            self.start_live_grep_when_due();
+            if self.poll_file_search() {
+                needs_render = true;
+            }
+
+            // Keep Find in Open Files results up with the buffers
+            if self.refresh_open_files_search() {
+                needs_render = true;
+            }
+
+            // Analyse the workspace once idle, and show the report when done
+            if self.poll_insights() {
+                needs_render = true;
+            }
+
+            // Start the servers of the workspace's main languages once known
+            self.workspace.poll_warmup();
+
+            // Let timed-out messages go
+            if self.messages.expire() {
+                needs_render = true;
+            }
+
+            // Pick up new lines when an editor or server log tab is showing
+            if self.follow_log() {
+                needs_render = true;
+            }
+
+            // Offer a colored view of a log file full of escape codes
+            if self.offer_ansi_view() {
+                needs_render = true;
+            }
+
+            // Keep the workspace locks alive; notice if another instance took one
+            if !self.workspace.heartbeat() {
+                self.messages.error("Another fackr took over this workspace; state is no longer saved".to_string());
+                needs_render = true;
+            }
+            for parked in &mut self.parked {
+                if !parked.workspace.heartbeat() {
+                    let name = parked.workspace.repo_name();
+                    self.messages.error(format!("Another fackr took over workspace {}; its state is no longer saved", name));
+                    needs_render = true;
+                }
+            }
+
             // Only render if something changed
             if needs_render {
                 self.screen.refresh_size()?;
                 self.render()?;
+                self.publish_status(false);
             }
         }
+        status::withdraw();
 
         // Save workspace state before exiting
-        if let Err(e) = self.workspace.save() {
-            eprintln!("Warning: Failed to save workspace state: {}", e);
+        for workspace in self.workspaces_mut() {
+            workspace.save_stats(true);
+            if let Err(e) = workspace.save() {
+                tracing::warn!("failed to save workspace state: {}", e);
+            }
+            workspace.release_lock();
         }
 
         self.screen.leave_raw_mode()?;
         Ok(())
     }
 
-    /// Write idle backups if enough time has passed since last edit
-    fn maybe_idle_backup(&mut self) {
-        if let Some(last_edit) = self.last_edit_time {
-            if last_edit.elapsed() >= Duration::from_secs(BACKUP_IDLE_SECS) {
-                if self.workspace.has_unsaved_changes() {
-                    let _ = self.workspace.backup_all_modified();
-                    // Mark all modified buffers as backed up
-                    for tab in &mut self.workspace.tabs {
-                        for buffer_entry in &mut tab.buffers {
-                            if buffer_entry.is_modified() {
-                                buffer_entry.backed_up = true;
-                            }
-                        }
-                    }
-                }
-                self.last_edit_time = None; // Reset until next edit
+    /// Dispatch one input event. If handling it blocked for a while, whatever
+    /// the user typed meanwhile is pulled into `input_queue` right away so it
+    /// replays in order instead of racing the escape-prefix detection.
+    fn handle_event(&mut self, event: Event) -> Result<()> {
+        let started = Instant::now();
+        if self.recorder.is_some() {
+            self.recording_event = Some(event.clone());
+        }
+        match event {
+            // Windows reports key releases too; only presses are input
+            Event::Key(key_event) if key_event.kind == KeyEventKind::Release => {}
+            Event::Key(key_event) => {
+                let before = self.stats_snapshot();
+                self.process_key(key_event)?;
+                self.record_stats(before);
+            }
+            Event::Mouse(mouse_event) => self.process_mouse(mouse_event)?,
+            Event::Paste(text) => self.handle_paste(&text)?,
+            Event::Resize(cols, rows) => {
+                self.screen.cols = cols;
+                self.screen.rows = rows;
+                self.terminal.update_screen_size(cols, rows);
+            }
+            _ => {}
+        }
+        self.auto_hide_panels();
+        self.note_file_switch();
+        self.sync_compare_scroll();
+        if let (Some(recorder), Some(event)) = (&mut self.recorder, self.recording_event.take()) {
+            if let Err(e) = recorder.record(&event) {
+                self.recorder = None;
+                self.messages.error(format!("Recording stopped: {}", e));
             }
         }
+        if started.elapsed() >= Duration::from_millis(SLOW_INPUT_MS) {
+            while event::poll(Duration::from_millis(0))? {
+                self.input_queue.push_back(event::read()?);
+            }
+        }
+        Ok(())
     }
 
-    /// Called after key handling - triggers backup if buffer was modified
-    fn on_buffer_edit(&mut self) {
-        // Check buffer state
-        let (is_modified, needs_first_backup) = {
-            let buffer_entry = self.buffer_entry_mut();
-            (buffer_entry.is_modified(), !buffer_entry.backed_up && buffer_entry.is_modified())
-        };
+    /// File and line count before a key press, when statistics are on
+    fn stats_snapshot(&mut self) -> Option<(Option<PathBuf>, usize)> {
+        self.workspace.stats.as_ref()?;
+        Some((self.filename(), self.buffer().line_count()))
+    }
 
-        // Update edit time if buffer has unsaved changes (for idle backup)
-        if is_modified {
-            self.last_edit_time = Some(Instant::now());
+    /// Count a key press for the statistics; lines added or removed only
+    /// count while the same file stays active
+    fn record_stats(&mut self, before: Option<(Option<PathBuf>, usize)>) {
+        let Some((file, lines_before)) = before else { return };
+        let same_file = self.filename() == file;
+        let line_delta = if same_file { self.buffer().line_count() as isize - lines_before as isize } else { 0 };
+        let file = file.map(|f| f.to_string_lossy().into_owned());
+        if let Some(stats) = self.workspace.stats.as_mut() {
+            stats.record_key(file.as_deref(), line_delta);
         }
+    }
 
-        // First edit since save/load - backup immediately
-        if needs_first_backup {
-            let backup_info: Option<(PathBuf, String)> = {
-                let buffer_entry = self.buffer_entry();
-                buffer_entry.path.as_ref().map(|path| {
-                    let full_path = if buffer_entry.is_orphan {
-                        path.clone()
-                    } else {
-                        self.workspace.root.join(path)
-                    };
-                    let content = buffer_entry.buffer.contents();
-                    (full_path, content)
-                })
-            };
+    /// Next event to handle without blocking: queued input first, then the terminal
+    fn next_pending_event(&mut self) -> Result<Option<Event>> {
+        if let Some(event) = self.input_queue.pop_front() {
+            self.replaying_input = true;
+            return Ok(Some(event));
+        }
+        self.replaying_input = false;
+        if event::poll(Duration::from_millis(0))? {
+            return Ok(Some(event::read()?));
+        }
+        Ok(None)
+    }
 
-            if let Some((full_path, content)) = backup_info {
-                let _ = self.workspace.write_backup(&full_path, &content);
-                self.buffer_entry_mut().backed_up = true;
-            }
+    /// Record input events to `path` until stopped
+    pub fn start_recording(&mut self, path: &Path) -> Result<()> {
+        self.recorder = Some(Recorder::create(path)?);
+        self.messages.info(format!("Recording input to {}", self.workspace.display_path(path)));
+        Ok(())
+    }
+
+    /// Start or stop recording into the workspace state dir (.fackr/recordings/)
+    fn toggle_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            self.messages.info(format!("Saved recording {}", self.workspace.display_path(&recorder.path)));
+            return;
+        }
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = self.workspace.state_dir.join("recordings").join(format!("rec-{}.jsonl", stamp));
+        if let Err(e) = self.start_recording(&path) {
+            self.messages.error(format!("Failed to start recording: {}", e));
         }
     }
 
-    /// Process LSP messages. Returns true if any messages were processed.
-    fn process_lsp_messages(&mut self) -> bool {
-        use crate::lsp::LspResponse;
+    /// F7: stop the macro being recorded, or ask for a register to record into
+    fn toggle_macro_recording(&mut self) {
+        if let Some((register, len)) = self.macros.stop() {
+            if self.workspace.persists() {
+                if let Err(e) = self.macros.save(&self.workspace.macro_file()) {
+                    self.messages.error(format!("Failed to save macro: {}", e));
+                    return;
+                }
+            }
+            self.messages.info(format!("Recorded {} keys into @{} (F8 replays)", len, register));
+            return;
+        }
+        self.prompt = PromptState::TextInput {
+            label: "Record macro into register (a-z, Enter for q): ".to_string(),
+            buffer: String::new(),
+            action: TextInputAction::RecordMacro,
+        };
+        self.messages.info("Record macro into register: ".to_string());
+    }
 
-        // Process pending messages from language servers
-        self.workspace.lsp.process_messages();
+    fn start_macro_recording(&mut self, input: &str) {
+        let register = match input.trim() {
+            "" => macros::DEFAULT_REGISTER,
+            name => match name.chars().next() {
+                Some(c) if name.len() == 1 && c.is_ascii_lowercase() => c,
+                _ => {
+                    self.messages.error(format!("Not a register: {} (a-z)", name));
+                    return;
+                }
+            },
+        };
+        self.macros.start(register);
+        self.messages.info(format!("Recording macro @{} (F7 stops)", register));
+    }
 
-        let mut had_response = false;
+    /// Ask for a register and count to replay
+    fn open_replay_macro(&mut self) {
+        let label = match self.macros.last {
+            Some(register) => format!("Replay macro (register and count, Enter for @{}): ", register),
+            None => "Replay macro (register and count, e.g. a 5): ".to_string(),
+        };
+        self.prompt = PromptState::TextInput { label, buffer: String::new(), action: TextInputAction::ReplayMacro };
+        self.messages.info("Replay macro: ".to_string());
+    }
 
-        // Handle any responses that came in
-        while let Some(response) = self.workspace.lsp.poll_response() {
+    /// Run the macro in `register` `count` times, stopping at the first
+    /// error; a macro can't replay one in turn
+    fn replay_macro(&mut self, register: char, count: usize) {
+        if self.macros.replaying {
+            return;
+        }
+        if self.macros.recording() == Some(register) {
+            self.messages.error(format!("Macro @{} is still being recorded", register));
+            return;
+        }
+        let Some(keys) = self.macros.get(register).cloned() else {
+            self.messages.info(format!("Register @{} is empty", register));
+            return;
+        };
+        self.macros.last = Some(register);
+        self.macros.replaying = true;
+        let mut result = Ok(());
+        'replay: for _ in 0..count {
+            for (key, mods) in &keys {
+                result = self.handle_key_with_mods(key.clone(), *mods);
+                if result.is_err() || !self.running {
+                    break 'replay;
+                }
+            }
+        }
+        self.macros.replaying = false;
+        if let Err(e) = result {
+            self.messages.error(format!("Macro @{} stopped: {}", register, e));
+        }
+    }
+
+    /// Feed a recording back as input, with its original timing
+    pub fn start_replay(&mut self, path: &Path) -> Result<()> {
+        self.replay = Some(Replay::load(path)?);
+        Ok(())
+    }
+
+    /// Queue replayed events that are due
+    fn feed_replay(&mut self) {
+        if let Some(replay) = &mut self.replay {
+            self.input_queue.extend(replay.due());
+            if replay.is_done() {
+                self.replay = None;
+                self.messages.info("Replay finished".to_string());
+            }
+        }
+    }
+
+    /// Show a busy message immediately, before starting a blocking operation
+    fn show_busy(&mut self, label: &str) {
+        self.messages.progress(if self.input_queue.is_empty() {
+            format!("{}...", label)
+        } else {
+            format!("{}... ({} queued)", label, self.input_queue.len())
+        });
+        let _ = self.render();
+    }
+
+    /// Queue the files likely to be opened next for prefetching whenever
+    /// what points at them changes: the current file's siblings, the
+    /// explorer's selected file and the references panel's files
+    fn prefetch_likely_files(&mut self) {
+        let current = self.current_file_path().filter(|_| self.buffer_entry().virtual_doc.is_none());
+        let selected = if self.workspace.fuss.active { self.workspace.fuss.selected_file() } else { None };
+        let references = match &self.prompt {
+            PromptState::ReferencesPanel { locations, .. } => locations.len(),
+            _ => 0,
+        };
+        let key = (current, selected, references);
+        if key == self.prefetch_key {
+            return;
+        }
+
+        let mut paths: Vec<PathBuf> = key.1.iter().cloned().collect();
+        if let PromptState::ReferencesPanel { locations, .. } = &self.prompt {
+            for path in locations.iter().filter_map(|l| uri_to_path(&l.uri)).map(PathBuf::from) {
+                if paths.len() >= PREFETCH_REFERENCES {
+                    break;
+                }
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        let siblings_of = key.0.as_deref().filter(|_| key.0 != self.prefetch_key.0);
+        self.workspace.prefetch(siblings_of, &paths);
+        self.prefetch_key = key;
+    }
+
+    /// Take in changes made outside the editor: clean buffers reload, ones
+    /// with unsaved edits ask what to do, and the explorer picks up created
+    /// and deleted files. Returns true if anything shown changed.
+    fn handle_file_events(&mut self) -> bool {
+        let events = self.workspace.file_events();
+        let mut changed = false;
+        if events.tree_changed {
+            self.tree_stale_since.get_or_insert_with(Instant::now);
+        }
+        if self.tree_stale_since.is_some_and(|since| since.elapsed() >= TREE_REFRESH_DELAY) {
+            self.tree_stale_since = None;
+            self.workspace.fuss.refresh_from_disk();
+            changed = true;
+        }
+        for path in &events.changed {
+            changed |= self.take_disk_change(path);
+        }
+        if self.prompt == PromptState::None && !self.disk_conflicts.is_empty() {
+            let path = self.disk_conflicts.remove(0);
+            self.messages.prompt(self.disk_conflict_question(&path));
+            self.prompt = PromptState::DiskConflict(path);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Reload the clean buffers of a file changed on disk and queue the
+    /// ones with unsaved edits for asking. Returns true if any reloaded.
+    fn take_disk_change(&mut self, path: &Path) -> bool {
+        let buffers = self.workspace.buffers_of_file(path);
+        if buffers.is_empty() {
+            return false;
+        }
+        // Deleted or not fully replaced yet: the next event tells
+        let Ok(mut disk) = Buffer::load(path) else {
+            return false;
+        };
+        let mut reloaded = false;
+        for (tab_idx, buffer_idx) in buffers {
+            let entry = &mut self.workspace.tabs[tab_idx].buffers[buffer_idx];
+            // Our own save, or a write that changed nothing
+            if entry.saved_as(&mut disk) {
+                continue;
+            }
+            if !entry.is_modified() {
+                self.workspace.tabs[tab_idx].reload_buffer(buffer_idx, &disk.contents());
+                reloaded = true;
+            } else if entry.buffer.contents() == disk.contents() {
+                entry.mark_saved();
+            } else if !self.disk_conflicts.iter().any(|p| p == path) {
+                self.disk_conflicts.push(path.to_path_buf());
+            }
+        }
+        if reloaded {
+            self.invalidate_bracket_cache();
+            self.messages.info(format!("Reloaded {} (changed on disk)", self.display_path(path)));
+        }
+        reloaded
+    }
+
+    fn disk_conflict_question(&self, path: &Path) -> String {
+        format!("{} changed on disk. [R]eload / [K]eep your edits / [D]iff", self.display_path(path))
+    }
+
+    /// Replace the buffers of `path` with its contents on disk; undo
+    /// brings the replaced text back
+    fn reload_from_disk(&mut self, path: &Path) -> Result<()> {
+        let text = Buffer::load(path)?.contents();
+        for (tab_idx, buffer_idx) in self.workspace.buffers_of_file(path) {
+            self.workspace.tabs[tab_idx].reload_buffer(buffer_idx, &text);
+        }
+        self.invalidate_bracket_cache();
+        Ok(())
+    }
+
+    /// Reload the current file from disk, dropping unsaved edits (undoably)
+    fn reload_current_file(&mut self) {
+        let Some(path) = self.current_file_path().filter(|_| self.buffer_entry().virtual_doc.is_none()) else {
+            self.messages.info("No file to reload".to_string());
+            return;
+        };
+        match self.reload_from_disk(&path) {
+            Ok(()) => self.messages.info(format!("Reloaded {}", self.display_path(&path))),
+            Err(e) => self.messages.error(format!("Failed to reload: {}", e)),
+        }
+    }
+
+    /// Show how the file at `path` on disk differs from its edited buffer
+    fn diff_with_disk(&mut self, path: &Path) {
+        let Some(&(tab_idx, buffer_idx)) = self.workspace.buffers_of_file(path).first() else {
+            return;
+        };
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+        let snapshot = std::env::temp_dir().join(format!("fackr-{}-{}", std::process::id(), name));
+        let text = self.workspace.tabs[tab_idx].buffers[buffer_idx].buffer.contents();
+        if let Err(e) = std::fs::write(&snapshot, text) {
+            self.messages.error(format!("Failed to diff: {}", e));
+            return;
+        }
+        self.open_virtual_document(VirtualDocument::DiskChanges { path: path.to_path_buf(), snapshot });
+    }
+
+    /// Refresh the active log tab if the file changed size, staying at the
+    /// bottom when the cursor was on the last line. Returns true if refreshed.
+    fn follow_log(&mut self) -> bool {
+        let tab_idx = self.workspace.active_tab;
+        let buffer_idx = self.workspace.active_tab().active_pane().buffer_idx;
+        let Some(path) = self.buffer_entry().virtual_doc.as_ref().and_then(|doc| doc.followed_log()).map(Path::to_path_buf) else {
+            return false;
+        };
+        let len = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if len == self.log_len {
+            return false;
+        }
+        self.log_len = len;
+
+        let at_end = self.cursor().line + 1 >= self.buffer().line_count();
+        if self.workspace.refresh_virtual(tab_idx, buffer_idx).is_err() {
+            return false;
+        }
+        if at_end {
+            let last_line = self.buffer().line_count().saturating_sub(1);
+            self.cursor_mut().set(last_line, 0);
+            self.scroll_to_cursor();
+        }
+        true
+    }
+
+    /// Cycle the editor log level and save it to the workspace config
+    fn cycle_log_level(&mut self) {
+        let current = logging::level();
+        let next = logging::LEVELS
+            .iter()
+            .position(|l| *l == current)
+            .map_or(logging::DEFAULT_LEVEL, |i| logging::LEVELS[(i + 1) % logging::LEVELS.len()]);
+        logging::set_level(next);
+        self.workspace.config.log_level = next.to_string();
+        let _ = self.workspace.save_config();
+        self.messages.info(format!("Log level: {}", next));
+    }
+
+    /// Write idle backups if enough time has passed since last edit
+    fn maybe_idle_backup(&mut self) {
+        if let Some(last_edit) = self.last_edit_time {
+            if last_edit.elapsed() >= Duration::from_secs(self.workspace.config.backup_idle_secs) {
+                self.backup_modified();
+            }
+        }
+    }
+
+    /// Back up the modified buffers now rather than once idle
+    fn backup_modified(&mut self) {
+        if self.workspace.has_unsaved_changes() {
+            match self.workspace.backup_all_modified() {
+                Ok(skipped) => {
+                    self.last_backup_time = Some(Instant::now());
+                    if let Some(path) = skipped.first() {
+                        self.messages.info(format!(
+                            "Backup skipped for {} (larger than backup_max_bytes)",
+                            self.workspace.display_path(path)
+                        ));
+                    }
+                }
+                Err(e) => self.messages.error(format!("Backup failed: {}", e)),
+            }
+            // Mark all modified buffers as backed up
+            for tab in &mut self.workspace.tabs {
+                for buffer_entry in &mut tab.buffers {
+                    if buffer_entry.is_modified() {
+                        buffer_entry.backed_up = true;
+                    }
+                }
+            }
+        }
+        self.last_edit_time = None; // Reset until next edit
+    }
+
+    /// Window title: `filename [+] — workspace (fackr)`
+    fn window_title(&mut self) -> String {
+        let name = self.buffer_entry().display_name();
+        let modified = if self.buffer_entry_mut().is_modified() { " [+]" } else { "" };
+        format!("{}{} \u{2014} {} (fackr)", name, modified, self.workspace.repo_name())
+    }
+
+    /// Tell shell prompts and the terminal the workspace and how many
+    /// buffers are unsaved, when either changed
+    fn publish_status(&mut self, suspended: bool) {
+        let unsaved = self.workspaces_mut().map(|workspace| workspace.modified_buffers().len()).sum();
+        let current = Status { pid: std::process::id(), workspace: self.workspace.root.clone(), unsaved, suspended };
+        if self.published_status.as_ref() == Some(&current) {
+            return;
+        }
+        if let Err(e) = status::publish(&current) {
+            tracing::debug!("failed to write status file: {}", e);
+        }
+        let vars = [
+            ("fackr_workspace", self.workspace.repo_name()),
+            ("fackr_unsaved", unsaved.to_string()),
+        ];
+        let _ = self.screen.set_user_vars(&vars);
+        self.published_status = Some(current);
+    }
+
+    /// Status bar text for the last backup, e.g. "backup 2m ago"
+    fn backup_status(&mut self) -> Option<String> {
+        let written = self.last_backup_time?;
+        if !self.buffer_entry_mut().is_modified() || !self.buffer_entry().backed_up {
+            return None;
+        }
+        let secs = written.elapsed().as_secs();
+        Some(match secs {
+            0..=4 => "backup just now".to_string(),
+            5..=59 => format!("backup {}s ago", secs),
+            60..=3599 => format!("backup {}m ago", secs / 60),
+            _ => format!("backup {}h ago", secs / 3600),
+        })
+    }
+
+    /// Called after key handling - triggers backup if buffer was modified
+    fn on_buffer_edit(&mut self) {
+        // Check buffer state
+        let (is_modified, needs_first_backup) = {
+            let buffer_entry = self.buffer_entry_mut();
+            (buffer_entry.is_modified(), !buffer_entry.backed_up && buffer_entry.is_modified())
+        };
+
+        // Update edit time if buffer has unsaved changes (for idle backup)
+        if is_modified {
+            self.last_edit_time = Some(Instant::now());
+        }
+
+        // First edit since save/load - backup immediately
+        if needs_first_backup {
+            let backup_info: Option<(PathBuf, String)> = {
+                let buffer_entry = self.buffer_entry();
+                buffer_entry.path.as_ref().map(|path| {
+                    let full_path = if buffer_entry.is_orphan {
+                        path.clone()
+                    } else {
+                        self.workspace.root.join(path)
+                    };
+                    let content = buffer_entry.buffer.contents();
+                    (full_path, content)
+                })
+            };
+
+            if let Some((full_path, content)) = backup_info {
+                match self.workspace.write_backup(&full_path, &content) {
+                    Ok(true) => self.last_backup_time = Some(Instant::now()),
+                    Ok(false) => {
+                        self.messages.info(format!(
+                            "Backup skipped for {} (larger than backup_max_bytes)",
+                            self.workspace.display_path(&full_path)
+                        ));
+                    }
+                    Err(e) => self.messages.error(format!("Backup failed: {}", e)),
+                }
+                self.buffer_entry_mut().backed_up = true;
+            }
+        }
+    }
+
+    /// Process LSP messages. Returns true if any messages were processed.
+    fn process_lsp_messages(&mut self) -> bool {
+        use crate::lsp::LspResponse;
+
+        // Process pending messages from language servers
+        self.workspace.lsp.process_messages();
+
+        let mut had_response = false;
+
+        // Handle any responses that came in
+        while let Some(response) = self.workspace.lsp.poll_response() {
             had_response = true;
             match response {
                 LspResponse::Completions(id, items) => {
@@ -950,14 +2210,53 @@ impl Editor {
                         self.lsp_state.pending_completion = None;
                     }
                 }
+                LspResponse::CompletionResolved(id, item) => {
+                    if let Some((pending_id, ref path, insertion)) = self.lsp_state.pending_completion_resolve {
+                        if pending_id == id {
+                            let same_file = self.current_file_path().as_ref() == Some(path);
+                            self.lsp_state.pending_completion_resolve = None;
+                            if same_file && !item.additional_text_edits.is_empty() {
+                                self.apply_completion_edits(&insertion, &item.additional_text_edits);
+                                self.messages.info(format!("Imported for {}", item.label));
+                            }
+                        }
+                    }
+                }
                 LspResponse::Hover(id, info) => {
                     if self.lsp_state.pending_hover == Some(id) {
                         self.lsp_state.hover = info;
                         self.lsp_state.hover_visible = self.lsp_state.hover.is_some();
                         self.lsp_state.pending_hover = None;
                         if self.lsp_state.hover.is_none() {
-                            self.message = Some("No hover info available".to_string());
+                            self.messages.info("No hover info available".to_string());
+                        }
+                    }
+                }
+                LspResponse::SignatureHelp(id, help) => {
+                    if self.lsp_state.pending_signature_help == Some(id) {
+                        self.lsp_state.pending_signature_help = None;
+                        self.lsp_state.signature_help = help;
+                    }
+                }
+                LspResponse::PrepareRename(id, answer) => {
+                    if self.lsp_state.pending_prepare_rename.as_ref().is_some_and(|(p, _)| *p == id) {
+                        let Some((_, mut modal)) = self.lsp_state.pending_prepare_rename.take() else { continue };
+                        match answer {
+                            None => {
+                                self.messages.info("Nothing to rename here".to_string());
+                                continue;
+                            }
+                            Some(PrepareRename::Range { range, placeholder }) => {
+                                let name = placeholder.unwrap_or_else(|| self.range_text(&range));
+                                if let PromptState::RenameModal { ref mut original_name, .. } = modal {
+                                    if !name.is_empty() {
+                                        *original_name = name;
+                                    }
+                                }
+                            }
+                            Some(PrepareRename::DefaultBehavior) => {}
                         }
+                        self.open_rename_modal(modal);
                     }
                 }
                 LspResponse::Definition(id, locations) => {
@@ -968,7 +2267,7 @@ impl Editor {
                         if let Some(loc) = locations.first() {
                             self.goto_location(loc);
                         } else {
-                            self.message = Some("No definition found".to_string());
+                            self.messages.info("No definition found".to_string());
                         }
                     }
                 }
@@ -976,7 +2275,7 @@ impl Editor {
                     if self.lsp_state.pending_references == Some(id) {
                         self.lsp_state.pending_references = None;
                         if locations.is_empty() {
-                            self.message = Some("No references found".to_string());
+                            self.messages.info("No references found".to_string());
                         } else if locations.len() == 1 {
                             // Single reference - just go there
                             self.goto_location(&locations[0]);
@@ -986,8 +2285,9 @@ impl Editor {
                                 locations,
                                 selected_index: 0,
                                 query: String::new(),
+                                live_search: None,
                             };
-                            self.message = None;
+                            self.messages.clear_prompt();
                         }
                     }
                 }
@@ -1002,45 +2302,87 @@ impl Editor {
                 }
                 LspResponse::Rename(_id, workspace_edit) => {
                     // Apply rename edits across all affected files
-                    let mut total_edits = 0;
-                    let mut files_changed = 0;
-
-                    for (uri, edits) in &workspace_edit.changes {
-                        if let Some(path_str) = crate::lsp::uri_to_path(uri) {
-                            // Check if we have this file open
-                            let path = std::path::PathBuf::from(&path_str);
-                            if let Some(tab_idx) = self.workspace.find_tab_by_path(&path) {
-                                // Apply edits to the open buffer (in reverse order to preserve positions)
-                                let mut sorted_edits = edits.clone();
-                                sorted_edits.sort_by(|a, b| {
-                                    // Sort by start position, descending
-                                    b.range.start.line.cmp(&a.range.start.line)
-                                        .then(b.range.start.character.cmp(&a.range.start.character))
-                                });
-
-                                for edit in sorted_edits {
-                                    self.workspace.apply_text_edit(tab_idx, &edit);
-                                    total_edits += 1;
-                                }
-                                files_changed += 1;
-                            } else {
-                                // File not open - would need to open, edit, and save
-                                self.message = Some(format!("Note: {} not open, skipped", path_str));
-                            }
+                    self.apply_workspace_edit(workspace_edit, "Renamed");
+                }
+                LspResponse::CodeActions(id, mut actions) => {
+                    if self.lsp_state.pending_code_actions == Some(id) {
+                        self.lsp_state.pending_code_actions = None;
+                        if actions.is_empty() {
+                            self.messages.info("No code actions available".to_string());
+                        } else if self.prompt == PromptState::None {
+                            // Preferred fixes first, otherwise in server order
+                            actions.sort_by_key(|a| !a.is_preferred);
+                            self.prompt = PromptState::CodeActions { actions, selected_index: 0 };
+                            self.messages.clear_prompt();
                         }
                     }
-
-                    if total_edits > 0 {
-                        self.message = Some(format!("Renamed: {} edits in {} file(s)", total_edits, files_changed));
-                    } else {
-                        self.message = Some("No rename edits to apply".to_string());
+                }
+                LspResponse::CodeLens(id, lenses) => {
+                    if self.lsp_state.pending_code_lens == Some(id) {
+                        self.lsp_state.pending_code_lens = None;
+                        self.lsp_state.code_lenses = lenses;
+                        self.resolve_code_lenses();
+                    }
+                }
+                LspResponse::CodeLensResolved(_id, resolved) => {
+                    if let Some(lens) = self.lsp_state.code_lenses.iter_mut()
+                        .find(|l| l.command.is_none() && l.range == resolved.range)
+                    {
+                        *lens = resolved;
+                    }
+                }
+                LspResponse::ExecuteCommand(_id) => {}
+                LspResponse::DocumentLinks(id, links) => {
+                    if self.lsp_state.pending_document_links == Some(id) {
+                        self.lsp_state.pending_document_links = None;
+                        self.lsp_state.document_links = links;
+                    }
+                }
+                LspResponse::FoldingRanges(id, ranges) => {
+                    if self.lsp_state.pending_folding_ranges == Some(id) {
+                        self.lsp_state.pending_folding_ranges = None;
+                        self.lsp_state.folding_ranges = ranges;
+                    }
+                }
+                LspResponse::DocumentLinkResolved(id, resolved) => {
+                    if let Some(link) = self.lsp_state.document_links.iter_mut()
+                        .find(|l| l.range == resolved.range)
+                    {
+                        *link = resolved.clone();
+                    }
+                    if self.lsp_state.pending_link_open == Some(id) {
+                        self.lsp_state.pending_link_open = None;
+                        match resolved.target {
+                            Some(ref target) => self.open_link_target(target),
+                            None => self.messages.info("Link has no target".to_string()),
+                        }
                     }
                 }
-                LspResponse::CodeActions(id, actions) => {
-                    // TODO: Show code actions menu
-                    let _ = (id, actions);
+                LspResponse::ApplyEdit(workspace_edit, reply) => {
+                    match self.apply_workspace_edit(workspace_edit, "Applied") {
+                        Some(outcome) => {
+                            let _ = reply.send(outcome);
+                        }
+                        // Answered once the user applies or cancels
+                        None => self.lsp_state.edit_reply = Some(reply),
+                    }
                 }
                 LspResponse::Error(id, message) => {
+                    if self.lsp_state.pending_code_lens == Some(id) {
+                        self.lsp_state.pending_code_lens = None;
+                    }
+                    if self.lsp_state.pending_document_links == Some(id) {
+                        self.lsp_state.pending_document_links = None;
+                    }
+                    if self.lsp_state.pending_folding_ranges == Some(id) {
+                        self.lsp_state.pending_folding_ranges = None;
+                    }
+                    if self.lsp_state.pending_link_open == Some(id) {
+                        self.lsp_state.pending_link_open = None;
+                    }
+                    if self.lsp_state.pending_completion_resolve.as_ref().is_some_and(|(p, _, _)| *p == id) {
+                        self.lsp_state.pending_completion_resolve = None;
+                    }
                     // Clear any pending state for this request
                     if self.lsp_state.pending_completion == Some(id) {
                         self.lsp_state.pending_completion = None;
@@ -1048,6 +2390,15 @@ impl Editor {
                     if self.lsp_state.pending_hover == Some(id) {
                         self.lsp_state.pending_hover = None;
                     }
+                    if self.lsp_state.pending_signature_help == Some(id) {
+                        self.lsp_state.pending_signature_help = None;
+                    }
+                    // A failed prepareRename leaves the rename to the word under the cursor
+                    if self.lsp_state.pending_prepare_rename.as_ref().is_some_and(|(p, _)| *p == id) {
+                        if let Some((_, modal)) = self.lsp_state.pending_prepare_rename.take() {
+                            self.open_rename_modal(modal);
+                        }
+                    }
                     if self.lsp_state.pending_definition == Some(id) {
                         self.lsp_state.pending_definition = None;
                     }
@@ -1056,7 +2407,7 @@ impl Editor {
                     }
                     // Optionally show error
                     if !message.is_empty() {
-                        self.message = Some(format!("LSP: {}", message));
+                        self.messages.info(format!("LSP: {}", message));
                     }
                 }
             }
@@ -1071,12 +2422,81 @@ impl Editor {
         // Sync document changes to LSP if buffer has changed
         self.sync_document_to_lsp();
 
+        // Refresh code lenses and links once typing has settled
+        if let Some(since) = self.lsp_state.annotations_stale_since {
+            if since.elapsed() >= Duration::from_millis(ANNOTATION_DEBOUNCE_MS) {
+                self.request_code_lens();
+                self.request_document_links();
+                self.request_folding_ranges();
+            }
+        }
+
         had_response
     }
 
+    /// Apply a server-provided workspace edit, asking first if any of the
+    /// targeted documents changed after the server computed it.
+    /// `verb` prefixes the summary message ("Renamed", "Applied", ...).
+    /// Returns the outcome, or None while the user is being asked.
+    fn apply_workspace_edit(&mut self, edit: WorkspaceEdit, verb: &str) -> Option<ApplyEditResult> {
+        // A server edit still waiting on an earlier prompt is refused
+        self.lsp_state.edit_reply = None;
+        if edit.is_empty() {
+            self.messages.info(format!("{}: no changes", verb));
+            return Some(Ok(()));
+        }
+
+        let conflicts = self.workspace.workspace_edit_conflicts(&edit);
+        if conflicts.is_empty() {
+            Some(self.commit_workspace_edit(&edit, verb))
+        } else {
+            self.messages.prompt(Self::workspace_edit_conflict_message(&conflicts));
+            self.prompt = PromptState::WorkspaceEditConflict {
+                edit,
+                verb: verb.to_string(),
+                conflicts,
+            };
+            None
+        }
+    }
+
+    /// Apply a workspace edit unconditionally and show a single summary;
+    /// the edit failed if any of its changes were skipped
+    fn commit_workspace_edit(&mut self, edit: &WorkspaceEdit, verb: &str) -> ApplyEditResult {
+        let summary = self.workspace.apply_workspace_edit(edit);
+        self.invalidate_bracket_cache();
+        self.messages.info(summary.message(verb));
+        if summary.skipped.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("skipped {}", summary.skipped.join(", ")))
+        }
+    }
+
+    fn workspace_edit_conflict_message(conflicts: &[String]) -> String {
+        let files = if conflicts.len() == 1 {
+            conflicts[0].clone()
+        } else {
+            format!("{} files", conflicts.len())
+        };
+        format!("{} changed since the edit was computed. [A]pply anyway / [C]ancel", files)
+    }
+
     /// Sync document changes to LSP server
     fn sync_document_to_lsp(&mut self) {
         let current_path = self.filename();
+
+        // Large files aren't sent: every change would mean the whole text
+        if self.buffer().large {
+            if current_path != self.lsp_state.last_synced_path {
+                if let Some(ref old_path) = self.lsp_state.last_synced_path {
+                    let _ = self.workspace.lsp.close_document(&old_path.to_string_lossy());
+                }
+                self.lsp_state.last_synced_path = current_path;
+                self.lsp_state.last_buffer_hash = None;
+            }
+            return;
+        }
         let current_hash = self.buffer_mut().content_hash();
 
         // Check if we switched files
@@ -1107,9 +2527,18 @@ impl Editor {
                 let content = self.buffer().contents();
                 let _ = self.workspace.lsp.open_document(&path_str, &content);
             }
+            // The servers have the whole text now; edits count from here
+            self.buffer_mut().take_edits();
 
             self.lsp_state.last_synced_path = current_path;
             self.lsp_state.last_buffer_hash = Some(current_hash);
+            self.dismiss_signature_help();
+            self.lsp_state.code_lenses.clear();
+            self.lsp_state.document_links.clear();
+            self.lsp_state.folding_ranges.clear();
+            self.request_code_lens();
+            self.request_document_links();
+            self.request_folding_ranges();
         } else if content_changed {
             // Content changed - send didChange notification
             if let Some(ref path) = current_path {
@@ -1123,23 +2552,34 @@ impl Editor {
                     self.workspace.root.join(path)
                 };
                 let path_str = full_path.to_string_lossy();
-                let content = self.buffer().contents();
-                let _ = self.workspace.lsp.document_changed(&path_str, &content);
+                let edits: Option<Vec<TextEdit>> = self.buffer_mut().take_edits().map(|edits| {
+                    edits.into_iter().map(|edit| TextEdit {
+                        range: LspRange {
+                            start: LspPosition::new(edit.start.0 as u32, edit.start.1 as u32),
+                            end: LspPosition::new(edit.end.0 as u32, edit.end.1 as u32),
+                        },
+                        new_text: edit.text,
+                    }).collect()
+                });
+                // The tab is borrowed by field so the client can be borrowed mutably
+                let tab = &self.workspace.tabs[self.workspace.active_tab];
+                let buffer = &tab.buffers[tab.panes[tab.active_pane].buffer_idx].buffer;
+                let _ = self.workspace.lsp.document_changed(&path_str, edits.as_deref(), || buffer.contents());
             }
 
             self.lsp_state.last_buffer_hash = Some(current_hash);
+            self.lsp_state.annotations_stale_since = Some(Instant::now());
         }
     }
 
     /// Navigate to an LSP location
     fn goto_location(&mut self, location: &Location) {
-        use crate::lsp::uri_to_path;
-
         if let Some(path) = uri_to_path(&location.uri) {
+            self.remember_jump();
             let path_buf = PathBuf::from(&path);
             // Open the file if not already open
             if let Err(e) = self.workspace.open_file(&path_buf) {
-                self.message = Some(format!("Failed to open {}: {}", path, e));
+                self.messages.error(format!("Failed to open {}: {}", path, e));
                 return;
             }
 
@@ -1181,14 +2621,14 @@ impl Editor {
             match self.workspace.lsp.request_definition(&path_str, line, col) {
                 Ok(id) => {
                     self.lsp_state.pending_definition = Some(id);
-                    self.message = Some("Finding definition...".to_string());
+                    self.messages.progress("Finding definition...".to_string());
                 }
                 Err(e) => {
-                    self.message = Some(format!("LSP error: {}", e));
+                    self.messages.error(format!("LSP error: {}", e));
                 }
             }
         } else {
-            self.message = Some("No file open".to_string());
+            self.messages.info("No file open".to_string());
         }
     }
 
@@ -1202,14 +2642,14 @@ impl Editor {
             match self.workspace.lsp.request_references(&path_str, line, col, true) {
                 Ok(id) => {
                     self.lsp_state.pending_references = Some(id);
-                    self.message = Some("Finding references...".to_string());
+                    self.messages.progress("Finding references...".to_string());
                 }
                 Err(e) => {
-                    self.message = Some(format!("LSP error: {}", e));
+                    self.messages.error(format!("LSP error: {}", e));
                 }
             }
         } else {
-            self.message = Some("No file open".to_string());
+            self.messages.info("No file open".to_string());
         }
     }
 
@@ -1223,17 +2663,36 @@ impl Editor {
             match self.workspace.lsp.request_hover(&path_str, line, col) {
                 Ok(id) => {
                     self.lsp_state.pending_hover = Some(id);
-                    self.message = Some("Loading hover info...".to_string());
+                    self.messages.progress("Loading hover info...".to_string());
                 }
                 Err(e) => {
-                    self.message = Some(format!("LSP error: {}", e));
+                    self.messages.error(format!("LSP error: {}", e));
                 }
             }
         } else {
-            self.message = Some("No file open".to_string());
+            self.messages.info("No file open".to_string());
+        }
+    }
+
+    /// LSP: Ask for the signature of the call around the cursor. Triggered
+    /// by typing, so failures pass silently.
+    fn lsp_signature_help(&mut self) {
+        let Some(path) = self.current_file_path() else { return };
+        // The server must see the character just typed
+        self.sync_document_to_lsp();
+        let path_str = path.to_string_lossy().to_string();
+        let line = self.cursor().line as u32;
+        let col = self.cursor().col as u32;
+        if let Ok(id) = self.workspace.lsp.request_signature_help(&path_str, line, col) {
+            self.lsp_state.pending_signature_help = Some(id);
         }
     }
 
+    fn dismiss_signature_help(&mut self) {
+        self.lsp_state.signature_help = None;
+        self.lsp_state.pending_signature_help = None;
+    }
+
     /// LSP: Trigger completion
     fn lsp_complete(&mut self) {
         if let Some(path) = self.current_file_path() {
@@ -1244,46 +2703,337 @@ impl Editor {
             match self.workspace.lsp.request_completions(&path_str, line, col) {
                 Ok(id) => {
                     self.lsp_state.pending_completion = Some(id);
-                    self.message = Some("Loading completions...".to_string());
+                    self.messages.progress("Loading completions...".to_string());
                 }
                 Err(e) => {
-                    self.message = Some(format!("LSP error: {}", e));
+                    self.messages.error(format!("LSP error: {}", e));
                 }
             }
         } else {
-            self.message = Some("No file open".to_string());
+            self.messages.info("No file open".to_string());
         }
     }
 
-    /// Toggle the LSP server manager panel
-    fn toggle_server_manager(&mut self) {
-        if self.server_manager.visible {
-            self.server_manager.hide();
-            self.return_focus();
-        } else {
-            self.server_manager.show();
-            self.focus = Focus::ServerManager;
+    /// LSP: Request code actions for the selection, or the cursor position
+    fn lsp_code_actions(&mut self) {
+        let Some(path) = self.current_file_path() else {
+            self.messages.info("No file open".to_string());
+            return;
+        };
+        let path_str = path.to_string_lossy().to_string();
+        let cursor = self.cursor();
+        let (start, end) = cursor.selection_bounds().unwrap_or((
+            Position { line: cursor.line, col: cursor.col },
+            Position { line: cursor.line, col: cursor.col },
+        ));
+
+        match self.workspace.lsp.request_code_actions(
+            &path_str,
+            start.line as u32,
+            start.col as u32,
+            end.line as u32,
+            end.col as u32,
+        ) {
+            Ok(id) => {
+                self.lsp_state.pending_code_actions = Some(id);
+                self.messages.progress("Loading code actions...".to_string());
+            }
+            Err(e) => {
+                self.messages.error(format!("LSP error: {}", e));
+            }
         }
     }
 
-    /// Handle key input when server manager panel is visible
-    fn handle_server_manager_key(&mut self, key: Key, mods: Modifiers) -> Result<()> {
-        let max_visible = 10; // Should match screen.rs
+    /// Apply a code action's edit, then run its command
+    fn apply_code_action(&mut self, action: CodeAction) {
+        if let Some(edit) = action.edit {
+            self.apply_workspace_edit(edit, &action.title);
+            // Hold the command back while the user resolves an edit conflict
+            if self.prompt != PromptState::None {
+                return;
+            }
+        }
+        if let Some(command) = action.command {
+            self.run_lsp_command(command);
+        }
+    }
 
-        // Alt+M toggles the panel closed
-        if key == Key::Char('m') && mods.alt {
-            self.server_manager.hide();
-            self.return_focus();
-            return Ok(());
+    /// LSP: Request code lenses for the current file
+    fn request_code_lens(&mut self) {
+        self.lsp_state.annotations_stale_since = None;
+        let Some(path) = self.current_file_path() else {
+            return;
+        };
+        let path_str = path.to_string_lossy().to_string();
+        if !self.workspace.lsp.has_server_for_file(&path_str) {
+            return;
+        }
+        if let Ok(id) = self.workspace.lsp.request_code_lens(&path_str) {
+            self.lsp_state.pending_code_lens = Some(id);
         }
+    }
 
-        // Handle confirm mode
-        if self.server_manager.confirm_mode {
-            match key {
-                Key::Char('y') | Key::Char('Y') => {
-                    // Start install in background thread (non-blocking)
-                    self.server_manager.start_install();
-                }
+    /// Resolve any code lenses that arrived without a command
+    fn resolve_code_lenses(&mut self) {
+        let Some(path) = self.current_file_path() else {
+            return;
+        };
+        let path_str = path.to_string_lossy().to_string();
+        let unresolved: Vec<CodeLens> = self.lsp_state.code_lenses.iter()
+            .filter(|l| l.command.is_none())
+            .cloned()
+            .collect();
+        for lens in unresolved {
+            let _ = self.workspace.lsp.resolve_code_lens(&path_str, &lens);
+        }
+    }
+
+    /// LSP: Run the code lens on the cursor line
+    fn run_code_lens(&mut self) {
+        let line = self.cursor().line as u32;
+        let command = self.lsp_state.code_lenses.iter()
+            .filter(|l| l.range.start.line == line)
+            .find_map(|l| l.command.clone());
+
+        match command {
+            Some(command) => self.run_lsp_command(command),
+            None => self.messages.info("No code lens on this line".to_string()),
+        }
+    }
+
+    /// Run an LSP command, handling well-known client-side commands locally
+    fn run_lsp_command(&mut self, command: Command) {
+        match command.command.as_str() {
+            // rust-analyzer expects the client to run these itself
+            "rust-analyzer.runSingle" | "rust-analyzer.debugSingle" => {
+                let Some(args) = command.arguments.first().and_then(|a| a.get("args")) else {
+                    self.messages.info(format!("Malformed command: {}", command.title));
+                    return;
+                };
+                let strings = |key: &str| -> Vec<String> {
+                    args.get(key)
+                        .and_then(|v| v.as_array())
+                        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+                        .unwrap_or_default()
+                };
+                let mut shell_cmd = format!("cargo {}", strings("cargoArgs").join(" "));
+                let executable_args = strings("executableArgs");
+                if !executable_args.is_empty() {
+                    shell_cmd.push_str(" -- ");
+                    shell_cmd.push_str(&executable_args.join(" "));
+                }
+                self.run_in_terminal(&shell_cmd);
+            }
+            "rust-analyzer.showReferences" => {
+                let locations = command.arguments.get(2)
+                    .map(crate::lsp::parse_locations)
+                    .unwrap_or_default();
+                match locations.len() {
+                    0 => self.messages.info(command.title.clone()),
+                    1 => self.goto_location(&locations[0]),
+                    _ => {
+                        self.prompt = PromptState::ReferencesPanel {
+                            locations,
+                            selected_index: 0,
+                            query: String::new(),
+                            live_search: None,
+                        };
+                    }
+                }
+            }
+            _ => {
+                let Some(path) = self.current_file_path() else {
+                    return;
+                };
+                let path_str = path.to_string_lossy().to_string();
+                match self.workspace.lsp.execute_command(&path_str, &command) {
+                    Ok(_) => self.messages.info(format!("Running: {}", command.title)),
+                    Err(e) => self.messages.error(format!("LSP error: {}", e)),
+                }
+            }
+        }
+    }
+
+    /// LSP: Request document links for the current file
+    fn request_document_links(&mut self) {
+        let Some(path) = self.current_file_path() else {
+            return;
+        };
+        let path_str = path.to_string_lossy().to_string();
+        if !self.workspace.lsp.has_server_for_file(&path_str) {
+            return;
+        }
+        if let Ok(id) = self.workspace.lsp.request_document_links(&path_str) {
+            self.lsp_state.pending_document_links = Some(id);
+        }
+    }
+
+    /// LSP: Request foldable ranges for the current file
+    fn request_folding_ranges(&mut self) {
+        let Some(path) = self.current_file_path() else {
+            return;
+        };
+        let path_str = path.to_string_lossy().to_string();
+        if !self.workspace.lsp.has_server_for_file(&path_str) {
+            return;
+        }
+        if let Ok(id) = self.workspace.lsp.request_folding_ranges(&path_str) {
+            self.lsp_state.pending_folding_ranges = Some(id);
+        }
+    }
+
+    /// LSP: Follow the document link under the cursor, resolving it first if needed
+    fn open_link_at_cursor(&mut self) {
+        let line = self.cursor().line as u32;
+        let col = self.cursor().col as u32;
+        let link = self.lsp_state.document_links.iter()
+            .find(|l| {
+                let r = l.range;
+                (r.start.line, r.start.character) <= (line, col)
+                    && (line, col) <= (r.end.line, r.end.character)
+            })
+            .cloned();
+
+        let Some(link) = link else {
+            self.messages.info("No link under cursor".to_string());
+            return;
+        };
+
+        if let Some(ref target) = link.target {
+            self.open_link_target(target);
+            return;
+        }
+
+        let Some(path) = self.current_file_path() else {
+            return;
+        };
+        let path_str = path.to_string_lossy().to_string();
+        match self.workspace.lsp.resolve_document_link(&path_str, &link) {
+            Ok(id) => {
+                self.lsp_state.pending_link_open = Some(id);
+                self.messages.progress("Resolving link...".to_string());
+            }
+            Err(e) => self.messages.error(format!("LSP error: {}", e)),
+        }
+    }
+
+    /// Open a link target: file URIs open in the editor, anything else goes to the system opener
+    fn open_link_target(&mut self, target: &str) {
+        if target.starts_with("file://") {
+            // Targets may carry a line fragment, e.g. file:///a/b.rs#L12
+            let (uri, fragment) = match target.split_once('#') {
+                Some((uri, fragment)) => (uri, Some(fragment)),
+                None => (target, None),
+            };
+            let line = fragment
+                .map(|f| f.trim_start_matches('L'))
+                .and_then(|f| f.split(|c: char| !c.is_ascii_digit()).next())
+                .and_then(|n| n.parse::<u32>().ok())
+                .map(|n| n.saturating_sub(1))
+                .unwrap_or(0);
+            let pos = crate::lsp::Position::new(line, 0);
+            self.goto_location(&Location {
+                uri: uri.to_string(),
+                range: crate::lsp::Range::point(pos),
+            });
+            return;
+        }
+
+        let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+        let result = std::process::Command::new(opener)
+            .arg(target)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn();
+        match result {
+            Ok(_) => self.messages.info(format!("Opened {}", target)),
+            Err(e) => self.messages.error(format!("Failed to open {}: {}", target, e)),
+        }
+    }
+
+    /// Show the terminal panel (spawning a session if needed) and focus it
+    fn show_terminal(&mut self) -> bool {
+        if !self.terminal.visible {
+            if let Err(e) = self.terminal.toggle() {
+                self.messages.error(format!("Failed to open terminal: {}", e));
+                return false;
+            }
+        }
+        self.focus = Focus::Terminal;
+        true
+    }
+
+    /// Cycle the terminal through the 25/50/75% height presets
+    fn cycle_terminal_height(&mut self) {
+        if self.show_terminal() {
+            let percent = self.terminal.cycle_height_preset();
+            self.workspace.terminal_height = Some(percent);
+            self.messages.info(format!("Terminal height {}%", percent));
+        }
+    }
+
+    /// Toggle the terminal between full screen and its remembered height
+    fn toggle_terminal_maximize(&mut self) {
+        if self.show_terminal() {
+            let maximized = self.terminal.toggle_maximize();
+            self.messages.info(if maximized { "Terminal maximized" } else { "Terminal restored" }.to_string());
+        }
+    }
+
+    /// Show the terminal panel and type a command into it
+    fn run_in_terminal(&mut self, shell_cmd: &str) {
+        if !self.show_terminal() {
+            return;
+        }
+        let _ = self.terminal.send_input(format!("{}\r", shell_cmd).as_bytes());
+    }
+
+    /// Toggle the LSP server manager panel
+    fn toggle_server_manager(&mut self) {
+        if self.server_manager.visible {
+            self.server_manager.hide();
+            self.return_focus();
+        } else {
+            self.server_manager.running = self.workspace.lsp.server_statuses();
+            self.server_manager.show();
+            self.focus = Focus::ServerManager;
+        }
+    }
+
+    /// Install the language servers picked in the setup wizard, with their
+    /// progress in the server manager
+    pub fn install_servers(&mut self, names: &[String]) {
+        if names.is_empty() {
+            return;
+        }
+        self.server_manager.running = self.workspace.lsp.server_statuses();
+        self.server_manager.show();
+        self.focus = Focus::ServerManager;
+        for name in names {
+            self.server_manager.install_named(name);
+        }
+    }
+
+    /// Handle key input when server manager panel is visible
+    fn handle_server_manager_key(&mut self, key: Key, mods: Modifiers) -> Result<()> {
+        let docked = self.workspace.config.layout.server_manager == Placement::Docked;
+        let max_visible = self.screen.server_manager_rows(docked);
+
+        // The key that opens the panel (Ctrl+Alt+M) closes it; Alt+M sets marks
+        if self.workspace.keymap.lookup(&key, &mods) == Some("server-manager") {
+            self.server_manager.hide();
+            self.return_focus();
+            return Ok(());
+        }
+
+        // Handle confirm mode
+        if self.server_manager.confirm_mode {
+            match key {
+                Key::Char('y') | Key::Char('Y') => {
+                    // Start install in background thread (non-blocking)
+                    self.server_manager.start_install();
+                }
                 Key::Char('n') | Key::Char('N') | Key::Escape => {
                     self.server_manager.cancel_confirm();
                 }
@@ -1333,6 +3083,9 @@ impl Editor {
             Key::Char('r') | Key::Char('R') => {
                 self.server_manager.refresh();
             }
+            Key::Char('s') => self.start_stop_selected_server(),
+            Key::Char('t') => self.restart_selected_server(),
+            Key::Char('l') => self.open_selected_server_log(),
             Key::Escape | Key::Char('q') => {
                 self.server_manager.hide();
             }
@@ -1342,6 +3095,65 @@ impl Editor {
         Ok(())
     }
 
+    /// Program the server manager's selected server runs as
+    fn selected_server_command(&self) -> Option<String> {
+        self.server_manager.selected_server().map(|server| server.check_cmd.to_string())
+    }
+
+    /// Text of every open file, by the full path the language servers know
+    /// it by
+    fn open_document_contents(&self) -> HashMap<String, String> {
+        let mut contents = HashMap::new();
+        for entry in self.workspace.tabs.iter().flat_map(|tab| &tab.buffers) {
+            let Some(path) = entry.path.as_ref().filter(|_| entry.virtual_doc.is_none()) else { continue };
+            let full_path = if entry.is_orphan { path.clone() } else { self.workspace.root.join(path) };
+            contents.insert(full_path.to_string_lossy().into_owned(), entry.buffer.contents());
+        }
+        contents
+    }
+
+    /// Stop the selected server if it runs, else start it
+    fn start_stop_selected_server(&mut self) {
+        let Some(command) = self.selected_server_command() else { return };
+        let message = if self.server_manager.running.iter().any(|s| s.command == command) {
+            self.workspace.lsp.stop_server(&command);
+            format!("Stopped {}", command)
+        } else {
+            let contents = self.open_document_contents();
+            match self.workspace.lsp.start_server(&command, &contents) {
+                Ok(_) => format!("Started {}", command),
+                Err(e) => format!("Failed to start {}: {}", command, e),
+            }
+        };
+        self.server_manager.status_message = Some(message);
+        self.server_manager.running = self.workspace.lsp.server_statuses();
+    }
+
+    /// Stop the selected server and start it again
+    fn restart_selected_server(&mut self) {
+        let Some(command) = self.selected_server_command() else { return };
+        let contents = self.open_document_contents();
+        let message = match self.workspace.lsp.restart_server(&command, &contents) {
+            Ok(_) => format!("Restarted {}", command),
+            Err(e) => format!("Failed to restart {}: {}", command, e),
+        };
+        self.server_manager.status_message = Some(message);
+        self.server_manager.running = self.workspace.lsp.server_statuses();
+    }
+
+    /// Open the selected server's stderr log in a tab that follows it
+    fn open_selected_server_log(&mut self) {
+        let Some(command) = self.selected_server_command() else { return };
+        let Some(path) = self.workspace.lsp.server_log(&command) else {
+            self.server_manager.status_message = Some(format!("No log for {} yet", command));
+            return;
+        };
+        self.server_manager.hide();
+        self.return_focus();
+        self.log_len = 0;
+        self.open_virtual_document(VirtualDocument::ServerLog { name: command, path });
+    }
+
     /// LSP: Rename symbol - opens prompt for new name
     fn lsp_rename(&mut self) {
         if let Some(path) = self.current_file_path() {
@@ -1379,21 +3191,50 @@ impl Editor {
                 String::new()
             };
 
-            if current_word.is_empty() {
-                self.message = Some("No symbol under cursor".to_string());
-                return;
-            }
-
-            self.prompt = PromptState::RenameModal {
+            let modal = PromptState::RenameModal {
                 original_name: current_word,
                 new_name: String::new(),
-                path: path_str,
+                path: path_str.clone(),
                 line,
                 col,
             };
+
+            // The server knows the symbol's exact extent (lifetimes, raw
+            // identifiers, macros) when it supports prepareRename
+            if self.workspace.lsp.supports_prepare_rename(&path_str) {
+                if let Ok(id) = self.workspace.lsp.request_prepare_rename(&path_str, line, col) {
+                    self.lsp_state.pending_prepare_rename = Some((id, modal));
+                    self.messages.progress("Preparing rename...".to_string());
+                    return;
+                }
+            }
+            self.open_rename_modal(modal);
         } else {
-            self.message = Some("No file open".to_string());
+            self.messages.info("No file open".to_string());
+        }
+    }
+
+    /// Show a rename modal, unless it has no symbol to rename
+    fn open_rename_modal(&mut self, modal: PromptState) {
+        if matches!(modal, PromptState::RenameModal { ref original_name, .. } if original_name.is_empty()) {
+            self.messages.info("No symbol under cursor".to_string());
+            return;
+        }
+        self.messages.clear_prompt();
+        self.prompt = modal;
+    }
+
+    /// Text of the active buffer within a single-line LSP range
+    fn range_text(&self, range: &crate::lsp::Range) -> String {
+        let line = range.start.line as usize;
+        if range.end.line as usize != line {
+            return String::new();
         }
+        let Some(text) = self.buffer().line_str(line) else { return String::new() };
+        text.chars()
+            .skip(range.start.character as usize)
+            .take((range.end.character as usize).saturating_sub(range.start.character as usize))
+            .collect()
     }
 
     /// Accept the currently selected completion and insert it
@@ -1403,6 +3244,30 @@ impl Editor {
         }
 
         let completion = self.lsp_state.completions[self.lsp_state.completion_index].clone();
+        let insertion = self.insert_completion(&completion);
+
+        // Auto-import: apply additional edits now, or fetch them lazily via resolve
+        if !completion.additional_text_edits.is_empty() {
+            self.apply_completion_edits(&insertion, &completion.additional_text_edits);
+        } else if completion.needs_import() {
+            if let Some(path) = self.current_file_path() {
+                let path_str = path.to_string_lossy().to_string();
+                if let Ok(id) = self.workspace.lsp.resolve_completion(&path_str, &completion) {
+                    self.lsp_state.pending_completion_resolve = Some((id, path, insertion));
+                }
+            }
+        }
+
+        // Clear completion state
+        self.dismiss_completion();
+    }
+
+    /// Replace the word before the cursor with `completion`'s text, as an
+    /// undo group of its own
+    fn insert_completion(&mut self, completion: &CompletionItem) -> CompletionInsertion {
+        let cursors_before = self.all_cursor_positions();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(cursors_before);
 
         // Determine the text to insert
         let insert_text = if let Some(ref text_edit) = completion.text_edit {
@@ -1445,12 +3310,50 @@ impl Editor {
         }
 
         // Insert the completion text (this will replace selection if any)
-        for ch in insert_text.chars() {
-            self.insert_char(ch);
+        let (line_count, line_len) = (self.buffer().line_count(), self.buffer().line_len(line_idx));
+        if completion.is_snippet && self.cursors().is_single() {
+            let snippet = snippet::parse(&insert_text, |name| self.snippet_variable(name));
+            self.insert_snippet(&snippet);
+        } else {
+            for ch in insert_text.chars() {
+                self.insert_char(ch);
+            }
         }
 
-        // Clear completion state
-        self.dismiss_completion();
+        // The rest of the line now ends the insertion's last line
+        let added_lines = self.buffer().line_count().saturating_sub(line_count);
+        let new_end = self.buffer().line_len(line_idx + added_lines).saturating_sub(line_len - cursor_col);
+
+        let cursors_after = self.all_cursor_positions();
+        self.history_mut().set_cursors_after(cursors_after);
+        self.history_mut().end_group();
+        let group = self.history_mut().version();
+        CompletionInsertion { line: line_idx, start: word_start, end: cursor_col, added_lines, new_end, group }
+    }
+
+    /// Apply LSP text edits (e.g. auto-import lines) to the current buffer as one undo group,
+    /// keeping cursors on the same text
+    fn apply_additional_edits(&mut self, edits: &[crate::lsp::TextEdit]) {
+        let tab_idx = self.workspace.active_tab;
+        let buffer_idx = self.workspace.active_tab().active_pane().buffer_idx;
+        self.workspace.apply_text_edits(tab_idx, buffer_idx, edits);
+        self.invalidate_bracket_cache();
+    }
+
+    /// Apply a completion's additional edits (e.g. auto-import lines) like
+    /// `apply_additional_edits`. They join the completion's undo group while
+    /// it is the latest change, so one undo takes back both.
+    fn apply_completion_edits(&mut self, insertion: &CompletionInsertion, edits: &[TextEdit]) {
+        // The server placed the edits in the text from before the insertion
+        let edits = insertion.shift(edits);
+        if !self.history_mut().reopen_group(insertion.group) {
+            self.history_mut().begin_group();
+        }
+        let tab_idx = self.workspace.active_tab;
+        let buffer_idx = self.workspace.active_tab().active_pane().buffer_idx;
+        self.workspace.apply_text_edits_in_group(tab_idx, buffer_idx, &edits);
+        self.history_mut().end_group();
+        self.invalidate_bracket_cache();
     }
 
     /// Dismiss the completion popup
@@ -1543,7 +3446,10 @@ impl Editor {
             }
         }
 
-        // Priority 2: Current buffer words
+        // Priority 2: Current buffer words (too many to gather in a large file)
+        if self.buffer().large {
+            return None;
+        }
         let buffer_hash = self.buffer_mut().content_hash();
         let needs_refresh = self
             .ghost_text
@@ -1596,6 +3502,228 @@ impl Editor {
         words.into_iter().collect()
     }
 
+    /// Expand the user snippet named by the word before the cursor
+    fn expand_snippet(&mut self) -> bool {
+        if !self.cursors().is_single() || self.cursor().has_selection() {
+            return false;
+        }
+        let Some(language) = self.buffer_entry().highlighter.language_name().map(str::to_lowercase) else {
+            return false;
+        };
+        let (line, col) = (self.cursor().line, self.cursor().col);
+        let before: String = self.buffer().line_str(line).unwrap_or_default().chars().take(col).collect();
+        let word_len = before.chars().rev().take_while(|c| c.is_alphanumeric() || *c == '_').count();
+        let prefix: String = before.chars().skip(col - word_len).collect();
+        if prefix.is_empty() {
+            return false;
+        }
+        let Some(body) = self.workspace.config.snippets.get(&language).and_then(|s| s.get(&prefix)).cloned() else {
+            return false;
+        };
+
+        let cursor = self.cursor_mut();
+        cursor.anchor_line = line;
+        cursor.anchor_col = col - word_len;
+        cursor.selecting = true;
+        let snippet = snippet::parse(&body, |name| self.snippet_variable(name));
+        self.insert_snippet(&snippet);
+        true
+    }
+
+    /// Value of a snippet variable (`$TM_FILENAME`, ...)
+    fn snippet_variable(&self, name: &str) -> Option<String> {
+        let path = self.buffer_entry().path.clone();
+        match name {
+            "TM_FILENAME" => Some(path?.file_name()?.to_string_lossy().into_owned()),
+            "TM_FILENAME_BASE" => Some(path?.file_stem()?.to_string_lossy().into_owned()),
+            "TM_LINE_NUMBER" => Some((self.cursor().line + 1).to_string()),
+            "TM_SELECTED_TEXT" => self.get_selection_text(),
+            _ => None,
+        }
+    }
+
+    /// Insert a snippet at the cursor, replacing any selection, and select
+    /// its first tabstop
+    fn insert_snippet(&mut self, snippet: &Snippet) {
+        let cursor_before = self.cursor_pos();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(vec![cursor_before]);
+        self.delete_selection();
+
+        let (line, col) = (self.cursor().line, self.cursor().col);
+        let indent: String = self
+            .buffer()
+            .line_str(line)
+            .unwrap_or_default()
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        let snippet = snippet.adapted(&indent, &self.indent().unit());
+        let start = self.buffer().line_col_to_char(line, col);
+
+        self.invalidate_highlight_cache(line);
+        self.invalidate_bracket_cache();
+        let cursor_before = self.cursor_pos();
+        self.buffer_mut().insert(start, &snippet.text);
+        self.history_mut().record_insert(start, snippet.text.clone(), cursor_before, cursor_before);
+
+        let stops = snippet
+            .stops
+            .iter()
+            .map(|ranges| ranges.iter().map(|r| start + r.start..start + r.end).collect())
+            .collect();
+        let tab = self.workspace.active_tab();
+        let buffer = (self.workspace.active_tab, tab.panes[tab.active_pane].buffer_idx);
+        self.snippet = Some(SnippetSession { stops, current: 0, buffer });
+        self.select_snippet_stop(0);
+
+        let cursors_after = self.all_cursor_positions();
+        self.history_mut().set_cursors_after(cursors_after);
+        self.history_mut().end_group();
+    }
+
+    /// Put cursors on the copies of tabstop `index`, selecting their text.
+    /// Reaching the final position ends the snippet.
+    fn select_snippet_stop(&mut self, index: usize) {
+        let Some(session) = self.snippet.as_mut() else { return };
+        session.current = index;
+        let ranges = session.stops[index].clone();
+        if index + 1 == session.stops.len() {
+            self.snippet = None;
+        }
+
+        self.cursors_mut().collapse_to_primary();
+        for (i, range) in ranges.iter().enumerate() {
+            let (anchor_line, anchor_col) = self.buffer().char_to_line_col(range.start);
+            let (line, col) = self.buffer().char_to_line_col(range.end);
+            if i == 0 {
+                let cursor = self.cursor_mut();
+                cursor.set(line, col);
+                cursor.anchor_line = anchor_line;
+                cursor.anchor_col = anchor_col;
+                cursor.selecting = range.start != range.end;
+            } else {
+                self.cursors_mut().add_with_selection(line, col, anchor_line, anchor_col);
+            }
+        }
+    }
+
+    /// Handle a key while a snippet is being filled in; false if the key
+    /// is left to the usual handling (which ends the snippet)
+    fn handle_snippet_key(&mut self, key: &Key, mods: &Modifiers) -> bool {
+        let Some(session) = self.snippet.as_ref() else { return false };
+        let tab = self.workspace.active_tab();
+        if session.buffer != (self.workspace.active_tab, tab.panes[tab.active_pane].buffer_idx) {
+            self.snippet = None;
+            return false;
+        }
+        let current = session.current;
+        let handled = match (key, mods) {
+            (Key::Tab, _) => {
+                self.select_snippet_stop(current + 1);
+                true
+            }
+            (Key::BackTab, _) => {
+                self.select_snippet_stop(current.saturating_sub(1));
+                true
+            }
+            (Key::Char(c), Modifiers { ctrl: false, alt: false, .. }) => self.type_in_snippet(Some(*c)),
+            (Key::Backspace, _) => self.type_in_snippet(None),
+            _ => false,
+        };
+        if !handled && self.snippet.is_some() {
+            self.snippet = None;
+            self.cursors_mut().collapse_to_primary();
+        }
+        handled
+    }
+
+    /// Type `c` (or backspace, for None) into every copy of the current
+    /// tabstop, replacing its text while it is still selected
+    fn type_in_snippet(&mut self, c: Option<char>) -> bool {
+        let Some(session) = self.snippet.clone() else { return false };
+        let ranges = &session.stops[session.current];
+        let cursor = self.buffer().line_col_to_char(self.cursor().line, self.cursor().col);
+        let selected = self.cursor().has_selection();
+        let Some(primary) = ranges.iter().find(|r| r.start <= cursor && cursor <= r.end) else {
+            return false;
+        };
+        let offset = cursor - primary.start;
+        let text = c.map(String::from).unwrap_or_default();
+
+        // (from, to, inserted text) per copy, in buffer order
+        let mut edits: Vec<(usize, usize, String)> = Vec::new();
+        for range in ranges {
+            let pos = (range.start + offset).min(range.end);
+            let edit = match (selected, c) {
+                (true, _) => (range.start, range.end, text.clone()),
+                (false, Some(_)) => (pos, pos, text.clone()),
+                (false, None) if pos > range.start => (pos - 1, pos, String::new()),
+                (false, None) => return false,
+            };
+            edits.push(edit);
+        }
+        edits.sort_by_key(|(from, _, _)| *from);
+
+        let first_line = self.buffer().char_to_line_col(edits[0].0).0;
+        self.invalidate_highlight_cache(first_line);
+        self.invalidate_bracket_cache();
+        let cursor_before = self.cursor_pos();
+        let cursors_before = self.all_cursor_positions();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(cursors_before);
+        // Last copy first, so earlier indices stay valid
+        for (from, to, inserted) in edits.iter().rev() {
+            if to > from {
+                let deleted: String = self.buffer().slice(*from, *to).chars().collect();
+                self.buffer_mut().delete(*from, *to);
+                self.history_mut().record_delete(*from, deleted, cursor_before, cursor_before);
+            }
+            if !inserted.is_empty() {
+                self.buffer_mut().insert(*from, inserted);
+                self.history_mut().record_insert(*from, inserted.clone(), cursor_before, cursor_before);
+            }
+        }
+
+        // Move every tabstop over the edits. The current copies grow at
+        // their end; other tabstops touching an edit keep clear of it.
+        let stops = session
+            .stops
+            .iter()
+            .enumerate()
+            .map(|(i, ranges)| {
+                ranges
+                    .iter()
+                    .map(|r| {
+                        if i == session.current {
+                            map_snippet_pos(r.start, &edits, false)..map_snippet_pos(r.end, &edits, true)
+                        } else {
+                            let start = map_snippet_pos(r.start, &edits, true);
+                            start..map_snippet_pos(r.end, &edits, false).max(start)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        self.snippet = Some(SnippetSession { stops, ..session });
+
+        // A cursor after the text typed into each copy
+        self.cursors_mut().collapse_to_primary();
+        for (i, (from, _, inserted)) in edits.iter().enumerate() {
+            let idx = map_snippet_pos(*from, &edits, false) + inserted.chars().count();
+            let (line, col) = self.buffer().char_to_line_col(idx);
+            if i == 0 {
+                self.cursor_mut().set(line, col);
+            } else {
+                self.cursors_mut().add(line, col);
+            }
+        }
+        let cursors_after = self.all_cursor_positions();
+        self.history_mut().set_cursors_after(cursors_after);
+        self.history_mut().end_group();
+        true
+    }
+
     /// Accept the current ghost text suggestion
     fn accept_ghost_text(&mut self) {
         if let Some(suffix) = self.ghost_text.suggestion.take() {
@@ -1681,6 +3809,16 @@ impl Editor {
                         self.terminal.prev_session();
                         return Ok(());
                     }
+                    // Alt+=: Cycle height presets
+                    KeyCode::Char('=') => {
+                        self.cycle_terminal_height();
+                        return Ok(());
+                    }
+                    // Alt+M: Maximize / restore
+                    KeyCode::Char('m') => {
+                        self.toggle_terminal_maximize();
+                        return Ok(());
+                    }
                     // Alt+1-9: Switch to specific tab
                     KeyCode::Char(c @ '1'..='9') => {
                         let idx = (c as usize) - ('1' as usize);
@@ -1705,20 +3843,46 @@ impl Editor {
             return Ok(());
         }
 
-        // Check if this is a bare Escape key (potential Alt prefix)
-        if key_event.code == KeyCode::Esc && key_event.modifiers.is_empty() {
+        // Check if this is a bare Escape key (potential Alt prefix).
+        // Replayed input arrived as one burst, so timing can't tell a prefix
+        // from a real Escape; terminals send Alt sequences atomically anyway.
+        // The Windows console and the kitty keyboard protocol report Alt as a
+        // modifier, never as a prefix.
+        if key_event.code == KeyCode::Esc
+            && key_event.modifiers.is_empty()
+            && !self.replaying_input
+            && !self.screen.keyboard_enhanced()
+            && !cfg!(windows)
+        {
             // Check if more data is available within escape_time
             // Escape sequences from terminals arrive together, so short timeouts work
             let timeout = Duration::from_millis(self.escape_time);
 
             if event::poll(timeout)? {
-                if let Event::Key(next_event) = event::read()? {
+                let next = event::read()?;
+                let mut next_event_other = None;
+                let next_key = match next {
+                    Event::Key(k) => Some(k),
+                    other => {
+                        next_event_other = Some(other);
+                        None
+                    }
+                };
+                if let Some(next_event) = next_key {
                     // Check for CSI sequences (ESC [ ...) which are arrow keys etc.
                     if next_event.code == KeyCode::Char('[') {
                         // CSI sequence - read the rest
                         if event::poll(timeout)? {
                             if let Event::Key(csi_event) = event::read()? {
                                 let mods = Modifiers { alt: true, ..Default::default() };
+                                let arrow = match csi_event.code {
+                                    KeyCode::Char('A') => Some(KeyCode::Up),
+                                    KeyCode::Char('B') => Some(KeyCode::Down),
+                                    KeyCode::Char('C') => Some(KeyCode::Right),
+                                    KeyCode::Char('D') => Some(KeyCode::Left),
+                                    _ => None,
+                                };
+                                self.recording_event = arrow.map(|code| Event::Key(KeyEvent::new(code, KeyModifiers::ALT)));
                                 return match csi_event.code {
                                     KeyCode::Char('A') => self.handle_key_with_mods(Key::Up, mods),
                                     KeyCode::Char('B') => self.handle_key_with_mods(Key::Down, mods),
@@ -1732,9 +3896,16 @@ impl Editor {
                     }
 
                     // Regular Alt+key (ESC followed by a normal key)
+                    self.recording_event = Some(Event::Key(KeyEvent::new(
+                        next_event.code,
+                        next_event.modifiers | KeyModifiers::ALT,
+                    )));
                     let (key, mut mods) = Key::from_crossterm(next_event);
                     mods.alt = true;
                     return self.handle_key_with_mods(key, mods);
+                } else if let Some(other) = next_event_other.take() {
+                    // Not a key (mouse, resize): keep it for after the Escape
+                    self.input_queue.push_front(other);
                 }
             }
             // No key followed - it's a real Escape
@@ -1746,12 +3917,56 @@ impl Editor {
         self.handle_key_with_mods(key, mods)
     }
 
-    /// Process a mouse event
-    fn process_mouse(&mut self, mouse_event: MouseEvent) -> Result<()> {
-        if let Some(mouse) = Mouse::from_crossterm(mouse_event) {
-            self.handle_mouse(mouse)?;
+    /// Bracketed paste: in the text it goes in as one edit and one undo
+    /// step, past auto-pairing and auto-indent; a prompt gets its first line
+    /// as if typed, the terminal panel gets it as a paste of its own
+    fn handle_paste(&mut self, text: &str) -> Result<()> {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        if self.focus == Focus::Terminal && self.terminal.visible {
+            let _ = self.terminal.paste(&text);
+            return Ok(());
         }
-        Ok(())
+        if self.prompt != PromptState::None {
+            let line = text.lines().next().unwrap_or_default();
+            for c in line.chars().filter(|c| !c.is_control()) {
+                self.handle_key_with_mods(Key::Char(c), Modifiers::default())?;
+            }
+            return Ok(());
+        }
+        if self.focus != Focus::Editor {
+            return Ok(());
+        }
+        if let Some(reason) = self.read_only_reason() {
+            self.messages.info(reason.to_string());
+            return Ok(());
+        }
+
+        self.dismiss_completion();
+        self.dismiss_ghost_text();
+        self.history_mut().maybe_break_group();
+        self.paste_text(&text, PasteMode::Plain);
+        self.on_buffer_edit();
+        self.scroll_to_cursor();
+        Ok(())
+    }
+
+    /// Process a mouse event
+    fn process_mouse(&mut self, mouse_event: MouseEvent) -> Result<()> {
+        if let Some(mouse) = Mouse::from_crossterm(mouse_event) {
+            self.handle_mouse(mouse)?;
+        }
+        Ok(())
+    }
+
+    /// Where the panels go this frame, from the workspace's layout config
+    fn layout(&self) -> Layout {
+        let explorer_width = if self.workspace.fuss.active {
+            self.workspace.fuss.width(self.screen.cols)
+        } else {
+            0
+        };
+        let terminal_size = self.terminal.visible.then(|| self.terminal.size());
+        Layout::compute(&self.workspace.config.layout, self.screen.cols, self.screen.rows, explorer_width, terminal_size)
     }
 
     /// Hit test to determine which UI region contains a screen coordinate
@@ -1762,44 +3977,113 @@ impl Editor {
             return HitRegion::Prompt;
         }
 
-        // Check server manager panel (right side overlay)
+        let layout = self.layout();
+
+        // Check server manager panel (right side overlay, or its dock)
         if self.server_manager.visible {
-            let panel_width = 50.min(self.screen.cols / 2);
-            let panel_start_col = self.screen.cols.saturating_sub(panel_width);
-            if col >= panel_start_col {
-                return HitRegion::ServerManager;
+            if self.workspace.config.layout.server_manager == Placement::Docked {
+                if layout.dock.contains(col, row) {
+                    return HitRegion::ServerManager;
+                }
+            } else {
+                let panel_width = 50.min(self.screen.cols / 2);
+                let panel_start_col = self.screen.cols.saturating_sub(panel_width);
+                if col >= panel_start_col {
+                    return HitRegion::ServerManager;
+                }
             }
         }
 
-        // Check terminal (bottom region)
-        if self.terminal.visible {
-            let terminal_start_row = self.terminal.render_start_row(self.screen.rows);
-            if row >= terminal_start_row {
-                // Terminal shrinks when fuss mode is active
-                let fuss_width = if self.workspace.fuss.active {
-                    self.workspace.fuss.width(self.screen.cols)
-                } else {
-                    0
-                };
-                if col >= fuss_width {
-                    return HitRegion::Terminal;
-                }
-            }
+        if layout.terminal.is_some_and(|rect| rect.contains(col, row)) {
+            return HitRegion::Terminal;
         }
 
-        // Check fuss sidebar (left side)
-        if self.workspace.fuss.active {
-            let fuss_width = self.workspace.fuss.width(self.screen.cols);
-            if col < fuss_width {
-                return HitRegion::FussMode;
-            }
+        if layout.explorer.is_some_and(|rect| rect.contains(col, row)) {
+            return HitRegion::FussMode;
         }
 
         // Otherwise it's the editor - determine which pane
-        let pane_index = self.workspace.pane_at_position(col, row, self.screen.cols, self.screen.rows);
+        let pane_index = self.workspace.pane_at_position(col, row, layout.editor, self.screen.rows);
         HitRegion::Editor { pane_index }
     }
 
+    /// With `layout.auto_hide` on, hide the explorer and the terminal once
+    /// focus has left them, unless they're pinned or the pointer is over one
+    fn auto_hide_panels(&mut self) {
+        if !self.workspace.config.layout.auto_hide || self.prompt != PromptState::None {
+            return;
+        }
+        let keep = |panel: Focus| self.focus == panel || self.peeking == Some(panel);
+        if self.workspace.fuss.active && !self.workspace.fuss.pinned && !keep(Focus::FussMode) {
+            self.workspace.fuss.deactivate();
+        }
+        if self.terminal.visible && !self.terminal.pinned && !keep(Focus::Terminal) {
+            self.terminal.visible = false;
+            self.terminal_resize_dragging = false;
+        }
+    }
+
+    /// With `layout.auto_hide` on, show a hidden explorer or terminal while
+    /// the pointer is at its edge of the screen, until the pointer leaves it
+    fn peek_panels(&mut self, col: u16, row: u16) {
+        if !self.workspace.config.layout.auto_hide {
+            return;
+        }
+        let layout = self.layout();
+        let over = match self.peeking {
+            Some(Focus::FussMode) => layout.explorer,
+            Some(Focus::Terminal) => layout.terminal,
+            _ => None,
+        };
+        if !over.is_some_and(|rect| rect.contains(col, row)) {
+            self.peeking = None;
+        }
+        if self.peeking.is_some() || self.prompt != PromptState::None {
+            return;
+        }
+
+        let (cols, rows) = (self.screen.cols, self.screen.rows);
+        let explorer_edge = match self.workspace.config.layout.explorer {
+            Side::Left => col == 0,
+            Side::Right => col + 1 == cols,
+        };
+        let terminal_edge = match self.workspace.config.layout.terminal {
+            Edge::Bottom => row + 1 == rows,
+            Edge::Right => col + 1 == cols,
+        };
+        if explorer_edge && !self.workspace.fuss.active {
+            self.workspace.fuss.activate(&self.workspace.root);
+            self.peeking = Some(Focus::FussMode);
+        } else if terminal_edge && !self.terminal.visible {
+            match self.terminal.toggle() {
+                Ok(()) => self.peeking = Some(Focus::Terminal),
+                Err(e) => self.messages.error(format!("Failed to open terminal: {}", e)),
+            }
+        }
+    }
+
+    /// Turn auto-hiding of the explorer and the terminal on or off
+    fn toggle_auto_hide(&mut self) {
+        let layout = &mut self.workspace.config.layout;
+        layout.auto_hide = !layout.auto_hide;
+        let state = if layout.auto_hide { "on" } else { "off" };
+        let _ = self.workspace.save_config();
+        self.messages.info(format!("Auto-hide panels {}", state));
+    }
+
+    /// Keep the explorer open when focus leaves it, or stop keeping it
+    fn toggle_pin_explorer(&mut self) {
+        let fuss = &mut self.workspace.fuss;
+        fuss.pinned = !fuss.pinned;
+        self.messages.info(if fuss.pinned { "Explorer pinned" } else { "Explorer unpinned" }.to_string());
+    }
+
+    /// Keep the terminal open when focus leaves it, or stop keeping it
+    fn toggle_pin_terminal(&mut self) {
+        self.terminal.pinned = !self.terminal.pinned;
+        self.messages.info(if self.terminal.pinned { "Terminal pinned" } else { "Terminal unpinned" }.to_string());
+    }
+
     /// Return focus to a sensible default after closing a component
     fn return_focus(&mut self) {
         // Return focus to the most recently visible component, defaulting to editor
@@ -1808,12 +4092,13 @@ impl Editor {
 
     /// Handle mouse input
     fn handle_mouse(&mut self, mouse: Mouse) -> Result<()> {
-        // Calculate offsets for fuss mode and tab bar
-        let left_offset = if self.workspace.fuss.active {
-            self.workspace.fuss.width(self.screen.cols) as usize
-        } else {
-            0
-        };
+        if let Mouse::Moved { col, row } = mouse {
+            self.peek_panels(col, row);
+            return Ok(());
+        }
+
+        // Calculate offsets for the explorer and tab bar
+        let left_offset = self.layout().editor.x as usize;
         // Tab bar is always rendered (takes 1 row)
         let top_offset = 1;
 
@@ -1850,8 +4135,8 @@ impl Editor {
             }
         }
 
-        // Handle terminal resize dragging
-        if self.terminal.visible {
+        // Handle terminal resize dragging (bottom dock only)
+        if self.terminal.visible && !self.terminal.is_docked_right() {
             let title_row = self.screen.rows.saturating_sub(self.terminal.height);
 
             match mouse {
@@ -1870,26 +4155,62 @@ impl Editor {
                     return Ok(());
                 }
                 Mouse::Up { button: Button::Left, .. } if self.terminal_resize_dragging => {
-                    // Stop dragging
+                    // Stop dragging and remember the height for this workspace
                     self.terminal_resize_dragging = false;
+                    self.workspace.terminal_height = Some(self.terminal.height_percent());
                     return Ok(());
                 }
                 _ => {}
             }
         }
 
+        // Clicking or dragging in a pane's minimap scrolls the pane there
+        match mouse {
+            Mouse::Click { button: Button::Left, col, row, .. } if self.focus == Focus::Editor => {
+                let pane_index = self.workspace.active_tab().active_pane;
+                if self.minimap_area(pane_index).is_some_and(|(cols, _, _)| cols.contains(&col)) {
+                    self.minimap_dragging = true;
+                    self.scroll_to_minimap_row(pane_index, row);
+                    return Ok(());
+                }
+            }
+            Mouse::Drag { button: Button::Left, row, .. } if self.minimap_dragging => {
+                let pane_index = self.workspace.active_tab().active_pane;
+                self.scroll_to_minimap_row(pane_index, row);
+                return Ok(());
+            }
+            Mouse::Up { button: Button::Left, .. } => self.minimap_dragging = false,
+            _ => {}
+        }
+
         match mouse {
             Mouse::Click { button: Button::Left, col, row, modifiers } => {
                 // Convert screen coordinates to buffer coordinates
                 let screen_row = row as usize;
                 let screen_col = col as usize;
 
-                // Check if click is in the text area (not line numbers, not status bar, not fuss pane)
+                // Gutter: the marker column toggles a mark, a line number selects its line
+                self.gutter_drag_line = None;
                 let status_row = self.screen.rows.saturating_sub(1) as usize;
+                if screen_row >= top_offset && screen_row < status_row && (left_offset..text_start_col).contains(&screen_col) {
+                    let buffer_line = self.screen.text_row(screen_row - top_offset)
+                        .map_or_else(|| self.buffer().line_at_row(self.viewport_line(), screen_row - top_offset), |(line, _)| line);
+                    if buffer_line < self.buffer().line_count() {
+                        if screen_col == left_offset {
+                            self.toggle_line_mark(buffer_line);
+                        } else {
+                            self.select_lines(buffer_line, buffer_line);
+                            self.gutter_drag_line = Some(buffer_line);
+                        }
+                    }
+                }
+
+                // Check if click is in the text area (not line numbers, not status bar, not fuss pane)
                 if screen_row >= top_offset && screen_row < status_row && screen_col >= text_start_col {
                     // Calculate buffer position (accounting for top_offset)
-                    let buffer_line = self.viewport_line() + (screen_row - top_offset);
-                    let buffer_col = screen_col - text_start_col;
+                    let (buffer_line, row_start) = self.screen.text_row(screen_row - top_offset)
+                        .unwrap_or_else(|| (self.buffer().line_at_row(self.viewport_line(), screen_row - top_offset), 0));
+                    let buffer_col = row_start + screen_col - text_start_col;
 
                     // Clamp to valid positions
                     if buffer_line < self.buffer().line_count() {
@@ -1899,6 +4220,18 @@ impl Editor {
                         if modifiers.ctrl {
                             // Ctrl+click: add or remove cursor at position
                             self.toggle_cursor_at(buffer_line, clamped_col);
+                        } else if (modifiers.alt && modifiers.shift) || self.block_select {
+                            // Alt+Shift+click: start a block selection
+                            let at = Position::new(buffer_line, clamped_col);
+                            self.select_block(at, at);
+                        } else if modifiers.alt {
+                            // Alt+click: follow the document link at position
+                            self.cursors_mut().collapse_to_primary();
+                            self.cursor_mut().line = buffer_line;
+                            self.cursor_mut().col = clamped_col;
+                            self.cursor_mut().desired_col = clamped_col;
+                            self.cursor_mut().clear_selection();
+                            self.open_link_at_cursor();
                         } else {
                             // Normal click: move cursor to clicked position
                             self.cursors_mut().collapse_to_primary();
@@ -1910,6 +4243,22 @@ impl Editor {
                     }
                 }
             }
+            Mouse::Drag { button: Button::Left, row, .. } if self.gutter_drag_line.is_some() => {
+                // Extend the selection line by line from the clicked line number
+                let screen_row = row as usize;
+                let status_row = self.screen.rows.saturating_sub(1) as usize;
+                if screen_row >= top_offset && screen_row < status_row {
+                    let buffer_line = self.screen.text_row(screen_row - top_offset)
+                        .map_or_else(|| self.buffer().line_at_row(self.viewport_line(), screen_row - top_offset), |(line, _)| line)
+                        .min(self.buffer().line_count().saturating_sub(1));
+                    if let Some(start) = self.gutter_drag_line {
+                        self.select_lines(start, buffer_line);
+                    }
+                }
+            }
+            Mouse::Up { button: Button::Left, .. } => {
+                self.gutter_drag_line = None;
+            }
             Mouse::Drag { button: Button::Left, col, row, .. } => {
                 // Extend selection while dragging
                 let screen_row = row as usize;
@@ -1917,10 +4266,16 @@ impl Editor {
 
                 let status_row = self.screen.rows.saturating_sub(1) as usize;
                 if screen_row >= top_offset && screen_row < status_row && screen_col >= text_start_col {
-                    let buffer_line = self.viewport_line() + (screen_row - top_offset);
-                    let buffer_col = screen_col - text_start_col;
-
-                    if buffer_line < self.buffer().line_count() {
+                    let (buffer_line, row_start) = self.screen.text_row(screen_row - top_offset)
+                        .unwrap_or_else(|| (self.buffer().line_at_row(self.viewport_line(), screen_row - top_offset), 0));
+                    let buffer_col = row_start + screen_col - text_start_col;
+
+                    if let Some((anchor, _)) = self.cursors().block() {
+                        // Dragging out a block: the head follows the pointer
+                        // past the ends of short lines
+                        let line = buffer_line.min(self.buffer().line_count().saturating_sub(1));
+                        self.select_block(anchor, Position::new(line, buffer_col));
+                    } else if buffer_line < self.buffer().line_count() {
                         let line_len = self.buffer().line_len(buffer_line);
                         let clamped_col = buffer_col.min(line_len);
 
@@ -1940,6 +4295,7 @@ impl Editor {
                 // Scroll up 3 lines
                 let new_line = self.viewport_line().saturating_sub(3);
                 self.set_viewport_line(new_line);
+                self.fix_folded_viewport();
             }
             Mouse::ScrollDown { .. } => {
                 // Scroll down 3 lines
@@ -1948,8 +4304,9 @@ impl Editor {
                 let visible_rows = (self.screen.rows as usize).saturating_sub(2 + top_offset);
                 // Max viewport is when the last line is at the bottom of visible area
                 let max_viewport = self.buffer().line_count().saturating_sub(visible_rows).max(0);
-                let new_line = (self.viewport_line() + 3).min(max_viewport);
+                let new_line = self.buffer().line_at_row(self.viewport_line(), 3).min(max_viewport);
                 self.set_viewport_line(new_line);
+                self.fix_folded_viewport();
             }
             _ => {}
         }
@@ -1957,13 +4314,81 @@ impl Editor {
         Ok(())
     }
 
-    fn render(&mut self) -> Result<()> {
-        // Calculate fuss pane width if active
-        let fuss_width = if self.workspace.fuss.active {
-            self.workspace.fuss.width(self.screen.cols)
-        } else {
-            0
+    /// Columns, first row and height of a pane's minimap, when it has one
+    fn minimap_area(&self, pane_index: usize) -> Option<(std::ops::Range<u16>, u16, u16)> {
+        let editor = self.layout().editor;
+        // Tab bar is always rendered (takes 1 row)
+        let top_offset = 1;
+        let available_width = editor.right().min(self.screen.cols).saturating_sub(editor.x) as f32;
+        let available_height = self.screen.rows.saturating_sub(2 + top_offset) as f32;
+        let bounds = &self.workspace.active_tab().panes.get(pane_index)?.bounds;
+        let x = editor.x + (bounds.x_start * available_width) as u16;
+        let y = top_offset + (bounds.y_start * available_height) as u16;
+        let width = ((bounds.x_end - bounds.x_start) * available_width) as u16;
+        let height = ((bounds.y_end - bounds.y_start) * available_height) as u16;
+        let map_width = minimap::width(self.workspace.config.minimap, width as usize) as u16;
+        (map_width > 0).then(|| (x + width - map_width..x + width, y, height))
+    }
+
+    /// Scroll a pane so the lines under minimap row `row` are in the middle
+    fn scroll_to_minimap_row(&mut self, pane_index: usize, row: u16) {
+        let Some((_, y, height)) = self.minimap_area(pane_index) else {
+            return;
         };
+        let height = height as usize;
+        let tab = self.workspace.active_tab_mut();
+        let pane = &tab.panes[pane_index];
+        let buffer = &tab.buffers[pane.buffer_idx].buffer;
+        let line_count = buffer.line_count();
+        let first = minimap::first_line(line_count, pane.viewport_line, height, height);
+        let line = minimap::line_at_row(first, (row.saturating_sub(y) as usize).min(height.saturating_sub(1)));
+        let top = line.saturating_sub(height / 2).min(line_count.saturating_sub(height));
+        let top = buffer.fold_hiding(top).map_or(top, |(start, _)| start);
+        tab.panes[pane_index].viewport_line = top;
+    }
+
+    /// Show or hide the minimap in wide panes and persist the setting
+    fn toggle_minimap(&mut self) {
+        self.workspace.config.minimap = !self.workspace.config.minimap;
+        let _ = self.workspace.save_config();
+        self.messages.info(if self.workspace.config.minimap { "Minimap on" } else { "Minimap off" }.to_string());
+        self.scroll_to_cursor();
+    }
+
+    /// Show or hide indent guides and persist the setting
+    fn toggle_indent_guides(&mut self) {
+        self.workspace.config.indent_guides = !self.workspace.config.indent_guides;
+        let _ = self.workspace.save_config();
+        self.messages.info(if self.workspace.config.indent_guides { "Indent guides on" } else { "Indent guides off" }.to_string());
+    }
+
+    /// Show no whitespace, then trailing whitespace, then all of it, and
+    /// persist the setting
+    fn cycle_whitespace(&mut self) {
+        let next = self.workspace.config.whitespace.next();
+        self.workspace.config.whitespace = next;
+        let _ = self.workspace.save_config();
+        self.messages.info(format!("Show whitespace: {}", next.label()));
+    }
+
+    /// Turn the current line's background on or off and persist the setting
+    fn toggle_current_line_highlight(&mut self) {
+        self.workspace.config.highlight_current_line = !self.workspace.config.highlight_current_line;
+        let _ = self.workspace.save_config();
+        self.messages.info(if self.workspace.config.highlight_current_line { "Current line highlight on" } else { "Current line highlight off" }.to_string());
+    }
+
+    fn render(&mut self) -> Result<()> {
+        // Drop kitty images from the previous frame; the preview redraws its own
+        if self.kitty_images_shown {
+            self.screen.render_graphics(image::kitty_clear(), 0, 0)?;
+            self.kitty_images_shown = false;
+        }
+
+        // Place the panels; the text starts right of a left-hand explorer
+        self.terminal.set_docked_right(self.workspace.config.layout.terminal == Edge::Right);
+        self.screen.layout = self.layout();
+        let fuss_width = self.screen.layout.editor.x;
 
         // Update fuss mode viewport (actual rendering happens after terminal)
         if self.workspace.fuss.active {
@@ -1977,6 +4402,7 @@ impl Editor {
                 name: tab.display_name(),
                 is_active: i == self.workspace.active_tab,
                 is_modified: tab.is_modified(),
+                is_orphan: tab.is_outside_workspace(),
                 index: i,
             }
         }).collect();
@@ -1984,6 +4410,21 @@ impl Editor {
         // Render tab bar (returns height: 1 if multiple tabs, 0 if single tab)
         let top_offset = self.screen.render_tab_bar(&tabs, fuss_width)?;
 
+        self.screen.backup_status = self.backup_status();
+        self.screen.state_note = self.state_note();
+        self.screen.indent = self.indent().label();
+        self.screen.macro_recording = self.macros.recording();
+        self.screen.minimap = self.workspace.config.minimap;
+        self.screen.indent_guides = self.workspace.config.indent_guides.then(|| self.indent_width());
+        self.screen.whitespace = self.workspace.config.whitespace;
+        self.screen.rulers.clone_from(&self.workspace.config.rulers);
+        self.screen.highlight_current_line = self.workspace.config.highlight_current_line;
+        self.screen.vim_mode = self.workspace.config.vim_mode
+            .then(|| format!("{} {}", self.vim.mode.label(), self.vim.pending_keys()).trim_end().to_string());
+        let title = self.window_title();
+        self.screen.set_title(&title)?;
+        self.screen.set_working_directory(&self.workspace.root)?;
+
         // Get pane count and filename before potentially getting mutable reference
         let pane_count = {
             let tab = self.workspace.active_tab();
@@ -1992,10 +4433,21 @@ impl Editor {
         let filename = {
             let tab = self.workspace.active_tab();
             let pane = &tab.panes[tab.active_pane];
-            tab.buffers[pane.buffer_idx].path.as_ref().and_then(|p| p.to_str()).map(|s| s.to_string())
+            let entry = &tab.buffers[pane.buffer_idx];
+            match &entry.virtual_doc {
+                Some(doc) => Some(format!("{} (read-only)", doc.uri())),
+                None => entry.path.as_ref().and_then(|p| p.to_str()).map(|s| s.to_string()),
+            }
         };
         let filename_ref = filename.as_deref();
 
+        let message = self.messages.current().map(|(text, _)| text.to_string());
+        self.screen.message_level = self.messages.current().map_or(MessageLevel::Info, |(_, level)| level);
+        self.screen.stacked_messages = self.messages.stacked()
+            .into_iter()
+            .map(|(text, level)| (text.to_string(), level))
+            .collect();
+
         // Use multi-pane rendering if we have more than one pane
         if pane_count > 1 {
             // Pre-compute is_modified for each buffer (needs mutable access)
@@ -2004,6 +4456,7 @@ impl Editor {
                 tab.buffers.iter_mut().map(|be| be.is_modified()).collect()
             };
 
+            let compare = self.update_comparison();
             let tab = self.workspace.active_tab();
             // Build PaneInfo for each pane
             let pane_infos: Vec<PaneInfo> = tab.panes.iter().enumerate().map(|(i, pane)| {
@@ -2025,13 +4478,19 @@ impl Editor {
                     is_active: i == tab.active_pane,
                     bracket_match,
                     is_modified: buffer_modified[pane.buffer_idx],
+                    wrap: pane.wrap,
+                    compare: match (compare, &self.comparison) {
+                        (Some((old_pane, _)), Some((_, diff))) if i == old_pane => Some((&diff.old_changed, true)),
+                        (Some((_, new_pane)), Some((_, diff))) if i == new_pane => Some((&diff.new_changed, false)),
+                        _ => None,
+                    },
                 }
             }).collect();
 
             self.screen.render_panes(
                 &pane_infos,
                 filename_ref,
-                self.message.as_deref(),
+                message.as_deref(),
                 fuss_width,
                 top_offset,
             )
@@ -2047,6 +4506,8 @@ impl Editor {
                 tab.buffers[pane.buffer_idx].is_modified()
             };
 
+            self.screen.code_lenses = self.code_lens_lines();
+
             // Get values we need before mutable borrow for highlighter
             let (viewport_line, viewport_col, cursors, line_count) = {
                 let tab = self.workspace.active_tab();
@@ -2054,6 +4515,7 @@ impl Editor {
                 let buffer_entry = &tab.buffers[pane.buffer_idx];
                 let buffer = &buffer_entry.buffer;
                 let cursors = pane.cursors.clone();
+                self.screen.wrap = pane.wrap;
                 (pane.viewport_line, pane.viewport_col, cursors, buffer.line_count())
             };
 
@@ -2070,7 +4532,7 @@ impl Editor {
                     viewport_line,
                     viewport_col,
                     filename_ref,
-                    self.message.as_deref(),
+                    message.as_deref(),
                     bracket_match,
                     fuss_width,
                     top_offset,
@@ -2080,23 +4542,92 @@ impl Editor {
                 )?;
             }
 
-            // Render diagnostics markers in gutter
+            // Local marks in the gutter; diagnostics draw over them
+            let marks: Vec<(char, usize)> = self.workspace.active_tab().active_pane().marks
+                .iter()
+                .map(|(&name, &(line, _))| (name, line))
+                .collect();
+            if !marks.is_empty() {
+                self.screen.render_marks_gutter(&marks, fuss_width, top_offset)?;
+            }
+
+            // Render diagnostics markers in gutter and underline their ranges
             if !self.lsp_state.diagnostics.is_empty() {
                 self.screen.render_diagnostics_gutter(
                     &self.lsp_state.diagnostics,
-                    viewport_line,
                     fuss_width,
                     top_offset,
                 )?;
+                let tab = self.workspace.active_tab();
+                let buffer = &tab.buffers[tab.panes[tab.active_pane].buffer_idx].buffer;
+                self.screen.render_diagnostic_underlines(
+                    buffer,
+                    &self.lsp_state.diagnostics,
+                    cursors.primary().line,
+                    fuss_width,
+                    top_offset,
+                )?;
+            }
+
+            // Color the level column of the editor log
+            let showing_log = matches!(self.buffer_entry().virtual_doc, Some(VirtualDocument::EditorLog { .. }));
+            if showing_log {
+                let tab = self.workspace.active_tab();
+                let buffer = &tab.buffers[tab.panes[tab.active_pane].buffer_idx].buffer;
+                self.screen.render_log_levels(buffer, fuss_width, top_offset)?;
+            }
+
+            // Highlight the changed words of a diff
+            if !self.buffer_entry().word_diff.is_empty() {
+                let tab = self.workspace.active_tab();
+                let entry = &tab.buffers[tab.panes[tab.active_pane].buffer_idx];
+                self.screen.render_word_diff(&entry.buffer, &entry.word_diff, fuss_width, top_offset)?;
+            }
+
+            // Draw an image tab's picture below its details
+            self.render_image_preview(fuss_width, top_offset)?;
+
+            // Render error lens messages as virtual text after their lines
+            let mut annotations = self.virtual_text_annotations();
+            // Blame describes the saved file, so it waits while there are unsaved edits
+            if !is_modified {
+                let line = cursors.primary().line;
+                if let Some(blame) = self.blame_for_line(line) {
+                    annotations.push((line, blame.annotation(), VirtualTextStyle::Blame));
+                }
+            }
+            if !annotations.is_empty() {
+                let tab = self.workspace.active_tab();
+                let buffer = &tab.buffers[tab.panes[tab.active_pane].buffer_idx].buffer;
+                self.screen.render_virtual_text(
+                    buffer,
+                    &annotations,
+                    cursors.primary().line,
+                    fuss_width,
+                    top_offset,
+                )?;
+            }
+
+            // The minimap goes over anything drawn past the end of the text
+            {
+                let tab = self.workspace.active_tab();
+                let buffer = &tab.buffers[tab.panes[tab.active_pane].buffer_idx].buffer;
+                self.screen.render_minimap(buffer, fuss_width, top_offset)?;
             }
 
+            // Screen row of the primary cursor, for popups anchored to it
+            let (cursor_screen_row, cursor_row_start) = self.screen
+                .text_position(cursors.primary().line, cursors.primary().col)
+                .unwrap_or((0, viewport_col));
+            let cursor_screen_row = cursor_screen_row as u16;
+
             // Render completion popup if visible
             if self.lsp_state.completion_visible && !self.lsp_state.completions.is_empty() {
                 let cursor = cursors.primary();
                 // Calculate cursor screen position
-                let cursor_row = (cursor.line.saturating_sub(viewport_line)) as u16 + top_offset;
+                let cursor_row = cursor_screen_row + top_offset;
                 let line_num_width = self.screen.line_number_width(line_count) as u16;
-                let cursor_col = cursor.col as u16 + line_num_width + 1;
+                let cursor_col = cursor.col.saturating_sub(cursor_row_start) as u16 + line_num_width + 1;
 
                 self.screen.render_completion_popup(
                     &self.lsp_state.completions,
@@ -2111,9 +4642,9 @@ impl Editor {
             if self.lsp_state.hover_visible {
                 if let Some(ref hover) = self.lsp_state.hover {
                     let cursor = cursors.primary();
-                    let cursor_row = (cursor.line.saturating_sub(viewport_line)) as u16 + top_offset;
+                    let cursor_row = cursor_screen_row + top_offset;
                     let line_num_width = self.screen.line_number_width(line_count) as u16;
-                    let cursor_col = cursor.col as u16 + line_num_width + 1;
+                    let cursor_col = cursor.col.saturating_sub(cursor_row_start) as u16 + line_num_width + 1;
 
                     self.screen.render_hover_popup(
                         hover,
@@ -2124,14 +4655,25 @@ impl Editor {
                 }
             }
 
+            // Render signature help above the cursor
+            if let Some(ref help) = self.lsp_state.signature_help {
+                let cursor = cursors.primary();
+                let cursor_row = cursor_screen_row + top_offset;
+                let line_num_width = self.screen.line_number_width(line_count) as u16;
+                let cursor_col = cursor.col.saturating_sub(cursor_row_start) as u16 + line_num_width + 1;
+
+                self.screen.render_signature_help(help, cursor_row, cursor_col, fuss_width, top_offset)?;
+            }
+
             // Render server manager panel if visible (on top of everything)
             if self.server_manager.visible {
-                self.screen.render_server_manager_panel(&self.server_manager)?;
+                let docked = self.workspace.config.layout.server_manager == Placement::Docked;
+                self.screen.render_server_manager_panel(&self.server_manager, docked)?;
             }
 
             // Render terminal panel if visible (overlays editor content)
             if self.terminal.visible {
-                self.screen.render_terminal(&self.terminal, fuss_width)?;
+                self.screen.render_terminal(&self.terminal)?;
             }
 
             // Render fuss mode sidebar if active (after terminal so it paints on top)
@@ -2157,9 +4699,131 @@ impl Editor {
                 self.screen.render_rename_modal(original_name, new_name)?;
             }
 
+            // Render code actions popup at the cursor
+            if let PromptState::CodeActions { ref actions, selected_index } = self.prompt {
+                let cursor = cursors.primary();
+                let cursor_row = cursor_screen_row + top_offset;
+                let line_num_width = self.screen.line_number_width(line_count) as u16;
+                let cursor_col = cursor.col.saturating_sub(cursor_row_start) as u16 + line_num_width + 1;
+                self.screen.render_code_actions_popup(actions, selected_index, cursor_row, cursor_col, fuss_width)?;
+            }
+
             // Render references panel if active
-            if let PromptState::ReferencesPanel { ref locations, selected_index, ref query } = self.prompt {
-                self.screen.render_references_panel(locations, selected_index, query, &self.workspace.root)?;
+            if let PromptState::ReferencesPanel { ref locations, selected_index, ref query, ref live_search } = self.prompt {
+                let title = match live_search {
+                    Some((search, _)) => format!("Open Files: {}", search),
+                    None => "References".to_string(),
+                };
+                self.screen.render_references_panel(&title, locations, selected_index, query, &self.workspace.root)?;
+            }
+
+            if let PromptState::ReplacedLines { ref lines, selected_index } = self.prompt {
+                let rows: Vec<Vec<String>> = lines
+                    .iter()
+                    .map(|l| {
+                        let change = if l.reverted {
+                            format!("(reverted) {}", l.before.trim())
+                        } else {
+                            format!("{} → {}", l.before.trim(), l.after.trim())
+                        };
+                        vec![format!("{}", l.line + 1), change]
+                    })
+                    .collect();
+                let title = format!("Replaced in {}", self.buffer_entry().display_name());
+                let help = "↑↓:nav  Enter:go  r:revert line  Esc:close";
+                self.screen.render_list_panel(&title, &rows, selected_index, help)?;
+            }
+
+            if let PromptState::DiagnosticsPanel { ref diagnostics, selected_index } = self.prompt {
+                let rows: Vec<Vec<String>> = diagnostics
+                    .iter()
+                    .map(|(uri, d)| {
+                        let path = uri_to_path(uri).map_or_else(|| uri.clone(), |p| self.display_path(Path::new(&p)));
+                        let severity = match d.severity {
+                            Some(DiagnosticSeverity::Error) => "error",
+                            Some(DiagnosticSeverity::Warning) | None => "warning",
+                            Some(DiagnosticSeverity::Information) => "info",
+                            Some(DiagnosticSeverity::Hint) => "hint",
+                        };
+                        let location = format!("{}:{}:{}", path, d.range.start.line + 1, d.range.start.character + 1);
+                        vec![severity.to_string(), location, d.message.lines().next().unwrap_or("").to_string()]
+                    })
+                    .collect();
+                let title = format!("Diagnostics ({})", diagnostics.len());
+                let help = "↑↓:nav  Enter:go  Esc:close";
+                if self.workspace.config.layout.problems == Placement::Docked {
+                    let dock = self.screen.layout.dock;
+                    self.screen.render_list_panel_in(dock, &title, &rows, selected_index, help)?;
+                } else {
+                    self.screen.render_list_panel(&title, &rows, selected_index, help)?;
+                }
+            }
+
+            if let PromptState::ClipboardRing { selected_index } = self.prompt {
+                let rows: Vec<Vec<String>> = self
+                    .workspace
+                    .clipboard_entries()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (text, pinned))| {
+                        let pin = if *pinned { "pinned" } else { "" };
+                        vec![format!("{}", i + 1), pin.to_string(), paste::preview(text)]
+                    })
+                    .collect();
+                let title = format!("Clipboard Ring ({})", rows.len());
+                let help = "↑↓:nav  Enter/1-9:paste  p:plain  s:select  l:over lines  *:pin  Esc:close";
+                self.screen.render_list_panel(&title, &rows, selected_index, help)?;
+            }
+
+            if let PromptState::RecoverVersion { ref versions, selected_index } = self.prompt {
+                let current = self.buffer().contents();
+                let rows: Vec<Vec<String>> = versions
+                    .iter()
+                    .map(|(generation, text)| {
+                        let changes = match line_changes(&current, text) {
+                            (0, 0) => "same as buffer".to_string(),
+                            (added, removed) => format!("+{} -{} lines", added, removed),
+                        };
+                        vec![relative_time(generation.age()), changes]
+                    })
+                    .collect();
+                let title = format!("Previous Versions ({})", rows.len());
+                let help = "↑↓:nav  Enter:restore  Esc:close";
+                self.screen.render_list_panel(&title, &rows, selected_index, help)?;
+            }
+
+            if let PromptState::GitLog { ref commits, selected_index, ref title } = self.prompt {
+                let rows: Vec<Vec<String>> = commits
+                    .iter()
+                    .map(|c| vec![c.short.clone(), c.date.clone(), c.author.clone(), c.subject.clone()])
+                    .collect();
+                let help = "↑↓:nav  Enter:show  c:checkout  p:cherry-pick  Esc:close";
+                self.screen.render_list_panel(title, &rows, selected_index, help)?;
+            }
+
+            // Render marks panel if active
+            if let PromptState::StringsPanel { ref literals, selected_index, ref template } = self.prompt {
+                let entries: Vec<(String, String, bool)> = literals
+                    .iter()
+                    .map(|l| (format!("{}:{}", l.line + 1, l.start_col + 1), l.text.clone(), l.included))
+                    .collect();
+                self.screen.render_strings_panel(&entries, selected_index, template)?;
+            }
+
+            if let PromptState::MarksPanel { ref entries, selected_index } = self.prompt {
+                self.screen.render_marks_panel(entries, selected_index)?;
+            }
+
+            if let PromptState::BookmarksPanel { ref entries, selected_index } = self.prompt {
+                let rows: Vec<Vec<String>> = entries
+                    .iter()
+                    .map(|(bookmark, preview)| {
+                        let place = format!("{}:{}", bookmark.path.display(), bookmark.line + 1);
+                        vec![place, bookmark.label.clone().unwrap_or_default(), preview.trim().to_string()]
+                    })
+                    .collect();
+                let help = "↑↓:nav  Enter:go  Del:remove  Esc:close";
+                self.screen.render_list_panel("Bookmarks", &rows, selected_index, help)?;
             }
 
             // Render fortress modal if active
@@ -2192,6 +4856,7 @@ impl Editor {
                 selected_index,
                 scroll_offset,
                 searching,
+                open_files,
             } = self.prompt {
                 // Convert results to tuple format for render function
                 let results_tuples: Vec<(PathBuf, usize, String)> = results
@@ -2204,67 +4869,176 @@ impl Editor {
                     selected_index,
                     scroll_offset,
                     searching,
+                    open_files,
                 )?;
                 return Ok(()); // Modal handles cursor
             }
 
+            // Render live grep if active, previewing the selected match
+            self.update_grep_preview();
+            if let PromptState::LiveGrep {
+                ref query,
+                ref types,
+                editing_types,
+                regex,
+                ref results,
+                selected_index,
+                scroll_offset,
+                searching,
+                invalid,
+            } = self.prompt {
+                let results: Vec<(PathBuf, usize, String)> = results
+                    .iter()
+                    .map(|r| (r.path.clone(), r.line_num, r.line_content.clone()))
+                    .collect();
+                let preview = match (&self.grep_preview, results.get(selected_index)) {
+                    (Some((_, lines)), Some((_, line_num, _))) => Some((lines.as_slice(), line_num.saturating_sub(1))),
+                    _ => None,
+                };
+                self.screen.render_live_grep_modal(&LiveGrepView {
+                    query,
+                    types,
+                    editing_types,
+                    regex,
+                    results: &results,
+                    selected_index,
+                    scroll_offset,
+                    searching,
+                    invalid,
+                    preview,
+                })?;
+                return Ok(()); // Modal handles cursor
+            }
+
             // Render command palette if active
             if let PromptState::CommandPalette {
                 ref query,
                 ref filtered,
                 selected_index,
                 scroll_offset,
+                ..
             } = self.prompt {
                 // Convert commands to tuple format for render function
-                let commands_tuples: Vec<(String, String, String, String)> = filtered
+                let mut commands_tuples: Vec<(String, String, String, String)> = filtered
                     .iter()
                     .map(|c| (c.name.to_string(), c.shortcut.to_string(), c.category.to_string(), c.id.to_string()))
                     .collect();
+                // Show a calculation's result (or why it failed) inline
+                if let Some(expr) = query.strip_prefix('=') {
+                    match calc::evaluate(expr) {
+                        Ok(result) => {
+                            for row in &mut commands_tuples {
+                                row.0 = format!("{}: {}", row.0, result);
+                            }
+                        }
+                        Err(_) if expr.trim().is_empty() => {}
+                        Err(error) => commands_tuples.push((error, String::new(), "Calc".to_string(), String::new())),
+                    }
+                }
                 self.screen.render_command_palette(
                     query,
                     &commands_tuples,
                     selected_index,
                     scroll_offset,
+                    "commands",
+                    "↑↓:select  Enter:run  Esc:close",
                 )?;
                 return Ok(()); // Modal handles cursor
             }
 
-            // Render help menu if active
-            if let PromptState::HelpMenu {
+            // Render quick open if active
+            if let PromptState::QuickOpen {
                 ref query,
                 ref filtered,
                 selected_index,
                 scroll_offset,
-                show_alt,
+                ..
             } = self.prompt {
-                // Convert keybinds to tuple format for render function
-                // Use alt_shortcut when show_alt is true (for entries that have one)
-                let keybinds_tuples: Vec<(String, String, String)> = filtered
+                let recent = &self.workspace.recent_files;
+                let file_tuples: Vec<(String, String, String, String)> = filtered
                     .iter()
-                    .map(|kb| {
-                        let shortcut = if show_alt && !kb.alt_shortcut.is_empty() {
-                            kb.alt_shortcut.to_string()
-                        } else {
-                            kb.shortcut.to_string()
-                        };
-                        (shortcut, kb.description.to_string(), kb.category.to_string())
+                    .map(|path| {
+                        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                        let dir = path.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+                        let tag = if recent.contains(path) { "recent" } else { "" };
+                        (name, dir, tag.to_string(), path.to_string_lossy().to_string())
                     })
                     .collect();
-                self.screen.render_help_menu(
+                self.screen.render_command_palette(
                     query,
-                    &keybinds_tuples,
+                    &file_tuples,
                     selected_index,
                     scroll_offset,
-                    show_alt,
+                    "files",
+                    "Enter:open  ^V/^S:split  ^T:tab  Esc:close",
                 )?;
                 return Ok(()); // Modal handles cursor
             }
 
-            // Render find/replace bar if active (replaces status bar)
-            if let PromptState::FindReplace {
-                ref find_query,
-                ref replace_text,
-                active_field,
+            // Render theme picker if active
+            if let PromptState::ThemePicker {
+                ref query,
+                ref filtered,
+                selected_index,
+                scroll_offset,
+                ref original,
+            } = self.prompt {
+                let theme_tuples: Vec<(String, String, String, String)> = filtered
+                    .iter()
+                    .map(|(name, builtin)| {
+                        let marker = if name == original { "current" } else { "" };
+                        let source = if *builtin { "Built-in" } else { "User" };
+                        (name.clone(), marker.to_string(), source.to_string(), name.clone())
+                    })
+                    .collect();
+                self.screen.render_command_palette(
+                    query,
+                    &theme_tuples,
+                    selected_index,
+                    scroll_offset,
+                    "themes",
+                    "↑↓:select  Enter:run  Esc:close",
+                )?;
+                return Ok(()); // Modal handles cursor
+            }
+
+            // Render help menu if active
+            if let PromptState::HelpMenu {
+                ref query,
+                ref filtered,
+                selected_index,
+                scroll_offset,
+                show_alt,
+            } = self.prompt {
+                // Convert keybinds to tuple format for render function
+                // Use alt_shortcut when show_alt is true (for entries that have one)
+                let keybinds_tuples: Vec<(String, String, String)> = filtered
+                    .iter()
+                    .map(|kb| {
+                        let shortcut = if show_alt && !kb.alt_shortcut.is_empty() {
+                            kb.alt_shortcut.to_string()
+                        } else {
+                            kb.shortcut.to_string()
+                        };
+                        (shortcut, kb.description.to_string(), kb.category.to_string())
+                    })
+                    .collect();
+                self.screen.render_help_menu(
+                    query,
+                    &keybinds_tuples,
+                    selected_index,
+                    scroll_offset,
+                    show_alt,
+                )?;
+                return Ok(()); // Modal handles cursor
+            }
+
+            // Render find/replace bar if active (replaces status bar)
+            let replace_preview = self.replace_preview();
+            if let PromptState::FindReplace {
+                ref find_query,
+                ref replace_text,
+                active_field,
                 case_insensitive,
                 regex_mode,
             } = self.prompt {
@@ -2277,6 +5051,7 @@ impl Editor {
                     regex_mode,
                     self.search_state.matches.len(),
                     self.search_state.current_match,
+                    replace_preview.as_deref(),
                     fuss_width,
                 )?;
                 return Ok(()); // Skip cursor repositioning, bar handles it
@@ -2285,723 +5060,1587 @@ impl Editor {
             // After all overlays are rendered, reposition cursor to the correct location
             // (overlays may have moved the terminal cursor position)
             let cursor = cursors.primary();
-            let cursor_row = (cursor.line.saturating_sub(viewport_line)) as u16 + top_offset;
+            let cursor_row = cursor_screen_row + top_offset;
             let line_num_width = self.screen.line_number_width(line_count) as u16;
-            // Account for horizontal scroll offset
-            let cursor_screen_col = fuss_width + line_num_width + 1 + (cursor.col.saturating_sub(viewport_col)) as u16;
+            // Account for horizontal scroll offset and wrapping
+            let cursor_screen_col = fuss_width + line_num_width + 1 + cursor.col.saturating_sub(cursor_row_start) as u16;
             self.screen.show_cursor_at(cursor_screen_col, cursor_row)?;
 
             Ok(())
         }
     }
 
-    fn handle_key_with_mods(&mut self, key: Key, mods: Modifiers) -> Result<()> {
-        // Handle Ctrl+F/Ctrl+R specially - they can toggle/switch even when in FindReplace prompt
-        if let PromptState::FindReplace { .. } = &self.prompt {
-            match (&key, &mods) {
-                (Key::Char('f'), Modifiers { ctrl: true, .. }) => {
-                    self.open_find();
-                    return Ok(());
-                }
-                (Key::Char('r'), Modifiers { ctrl: true, .. }) => {
-                    self.open_replace();
-                    return Ok(());
-                }
-                // Alt+I: toggle case insensitivity
-                (Key::Char('i'), Modifiers { alt: true, .. }) => {
-                    self.toggle_case_sensitivity();
-                    return Ok(());
-                }
-                // Alt+X: toggle regex mode
-                (Key::Char('x'), Modifiers { alt: true, .. }) => {
-                    self.toggle_regex_mode();
-                    return Ok(());
-                }
-                // Alt+Enter or Ctrl+Shift+Enter: replace all
-                (Key::Enter, Modifiers { alt: true, .. }) |
-                (Key::Enter, Modifiers { ctrl: true, shift: true, .. }) => {
-                    self.replace_all();
-                    return Ok(());
+    /// Code lens titles by line, several on a line joined with " | "
+    fn code_lens_lines(&self) -> Vec<(usize, String)> {
+        let mut lines: Vec<(usize, String)> = Vec::new();
+        for lens in &self.lsp_state.code_lenses {
+            let Some(ref command) = lens.command else { continue };
+            let line = lens.range.start.line as usize;
+            match lines.iter_mut().find(|(l, _)| *l == line) {
+                Some((_, text)) => {
+                    text.push_str(" | ");
+                    text.push_str(&command.title);
                 }
-                _ => {}
+                None => lines.push((line, command.title.clone())),
             }
         }
+        lines
+    }
 
-        // Handle active prompts first
-        if self.prompt != PromptState::None {
-            return self.handle_prompt_key(key);
-        }
+    /// Collect end-of-line virtual text: error lens messages
+    fn virtual_text_annotations(&self) -> Vec<(usize, String, VirtualTextStyle)> {
 
-        // Focus-based routing for server manager
-        if self.focus == Focus::ServerManager && self.server_manager.visible {
-            return self.handle_server_manager_key(key, mods);
+        let mut annotations: Vec<(usize, String, VirtualTextStyle)> = Vec::new();
+
+        if self.workspace.config.error_lens {
+            // Most severe diagnostic per line; earlier diagnostics win ties
+            let rank = |sev: Option<DiagnosticSeverity>| match sev {
+                Some(DiagnosticSeverity::Error) => 0,
+                Some(DiagnosticSeverity::Warning) | None => 1,
+                Some(DiagnosticSeverity::Information) => 2,
+                Some(DiagnosticSeverity::Hint) => 3,
+            };
+            let mut per_line: Vec<(usize, &Diagnostic)> = Vec::new();
+            for diag in &self.lsp_state.diagnostics {
+                let line = diag.range.start.line as usize;
+                match per_line.iter_mut().find(|(l, _)| *l == line) {
+                    Some((_, best)) if rank(diag.severity) < rank(best.severity) => *best = diag,
+                    Some(_) => {}
+                    None => per_line.push((line, diag)),
+                }
+            }
+            for (line, diag) in per_line {
+                let style = match diag.severity {
+                    Some(DiagnosticSeverity::Error) => VirtualTextStyle::Error,
+                    Some(DiagnosticSeverity::Warning) | None => VirtualTextStyle::Warning,
+                    Some(DiagnosticSeverity::Information) => VirtualTextStyle::Info,
+                    Some(DiagnosticSeverity::Hint) => VirtualTextStyle::Hint,
+                };
+                let message = diag.message.lines().next().unwrap_or("").to_string();
+                annotations.push((line, message, style));
+            }
         }
 
-        // Clear message on any key
-        self.message = None;
+        annotations
+    }
 
-        // Toggle fuss mode: Ctrl+B or F3 (global shortcut that sets focus)
-        if matches!((&key, &mods), (Key::Char('b'), Modifiers { ctrl: true, .. }) | (Key::F(3), _)) {
-            self.toggle_fuss_mode();
-            return Ok(());
-        }
+    /// Toggle error lens (inline diagnostic messages) and persist the setting
+    fn toggle_error_lens(&mut self) {
+        self.workspace.config.error_lens = !self.workspace.config.error_lens;
+        let _ = self.workspace.save_config();
+        self.messages.info(if self.workspace.config.error_lens {
+            "Error lens on".to_string()
+        } else {
+            "Error lens off".to_string()
+        });
+    }
 
-        // Focus-based routing for fuss mode
-        if self.focus == Focus::FussMode && self.workspace.fuss.active {
-            return self.handle_fuss_key(key, mods);
+    /// List the diagnostics of all files in a panel
+    fn open_diagnostics_panel(&mut self) {
+        let mut diagnostics: Vec<(String, Diagnostic)> = self
+            .workspace
+            .lsp
+            .get_all_diagnostics()
+            .into_iter()
+            .flat_map(|(uri, diags)| diags.into_iter().map(move |d| (uri.clone(), d)))
+            .collect();
+        if diagnostics.is_empty() {
+            self.messages.info("No diagnostics".to_string());
+            return;
         }
+        let rank = |sev: Option<DiagnosticSeverity>| match sev {
+            Some(DiagnosticSeverity::Error) => 0,
+            Some(DiagnosticSeverity::Warning) | None => 1,
+            Some(DiagnosticSeverity::Information) => 2,
+            Some(DiagnosticSeverity::Hint) => 3,
+        };
+        diagnostics.sort_by(|(a_uri, a), (b_uri, b)| {
+            rank(a.severity)
+                .cmp(&rank(b.severity))
+                .then_with(|| a_uri.cmp(b_uri))
+                .then_with(|| a.range.start.line.cmp(&b.range.start.line))
+        });
+        self.workspace.fuss.deactivate();
+        self.prompt = PromptState::DiagnosticsPanel { diagnostics, selected_index: 0 };
+    }
 
-        // Handle completion popup navigation when visible
-        if self.lsp_state.completion_visible {
-            match (&key, &mods) {
-                // Navigate up in completion list
-                (Key::Up, _) => {
-                    if self.lsp_state.completion_index > 0 {
-                        self.lsp_state.completion_index -= 1;
-                    } else {
-                        // Wrap to bottom
-                        self.lsp_state.completion_index = self.lsp_state.completions.len().saturating_sub(1);
-                    }
-                    return Ok(());
-                }
-                // Navigate down in completion list
-                (Key::Down, _) => {
-                    if self.lsp_state.completion_index < self.lsp_state.completions.len().saturating_sub(1) {
-                        self.lsp_state.completion_index += 1;
-                    } else {
-                        // Wrap to top
-                        self.lsp_state.completion_index = 0;
-                    }
-                    return Ok(());
-                }
-                // Select completion with Enter or Tab
-                (Key::Enter, _) | (Key::Tab, _) => {
-                    self.accept_completion();
-                    return Ok(());
-                }
-                // Dismiss completion popup with Escape
-                (Key::Escape, _) => {
-                    self.dismiss_completion();
-                    return Ok(());
-                }
-                // Backspace: remove from filter (if any) and continue
-                (Key::Backspace, _) => {
-                    if !self.lsp_state.completion_filter.is_empty() {
-                        self.lsp_state.completion_filter.pop();
-                        self.filter_completions();
-                    } else {
-                        // No filter left, dismiss popup
-                        self.dismiss_completion();
-                    }
-                    // Continue to normal backspace handling
-                }
-                // Character input: add to filter and continue typing
-                (Key::Char(c), Modifiers { ctrl: false, alt: false, .. }) => {
-                    self.lsp_state.completion_filter.push(*c);
-                    self.filter_completions();
-                    // If no matches left, dismiss
-                    if self.lsp_state.completions.is_empty() {
-                        self.dismiss_completion();
-                    }
-                    // Continue to normal character handling
-                }
-                // Any other key dismisses popup
-                _ => {
-                    self.dismiss_completion();
-                }
-            }
+    /// Ask what to do with the active file if it is colored terminal
+    /// output; once per file
+    fn offer_ansi_view(&mut self) -> bool {
+        if !matches!(self.prompt, PromptState::None) || !self.buffer_entry().ansi_offer {
+            return false;
         }
+        self.buffer_entry_mut().ansi_offer = false;
+        self.prompt = PromptState::AnsiCodes;
+        self.messages.prompt(ANSI_PROMPT.to_string());
+        true
+    }
 
-        // Dismiss hover popup on any key press
-        if self.lsp_state.hover_visible {
-            self.lsp_state.hover_visible = false;
-            self.lsp_state.hover = None;
-            // Let Escape just dismiss the popup without doing anything else
-            if matches!(key, Key::Escape) {
-                return Ok(());
+    /// Switch between a file and its view colored by ANSI escape codes
+    fn toggle_ansi_colors(&mut self) {
+        if let Some(VirtualDocument::AnsiColors { path }) = self.buffer_entry().virtual_doc.clone() {
+            if let Err(e) = self.open_file(&path) {
+                self.messages.error(format!("Failed to open: {}", e));
             }
+            return;
         }
+        match self.current_file_path().filter(|_| self.buffer_entry().virtual_doc.is_none()) {
+            Some(path) => self.open_virtual_document(VirtualDocument::AnsiColors { path }),
+            None => self.messages.info("No file to color".to_string()),
+        }
+    }
 
-        // Break undo group on any non-character key (movement, commands, etc.)
-        // This ensures each "typing session" is its own undo unit
-        let is_typing = matches!(
-            (&key, &mods),
-            (Key::Char(_), Modifiers { ctrl: false, alt: false, .. })
-        );
-        if !is_typing {
-            self.history_mut().maybe_break_group();
+    /// Remove ANSI escape codes from the buffer, as one undoable edit
+    fn strip_ansi_codes(&mut self) {
+        let text = self.buffer().contents();
+        let stripped = ansi::strip(&text);
+        if stripped == text {
+            self.messages.info("No ANSI escape codes".to_string());
+            return;
         }
 
-        match (&key, &mods) {
-            // === System ===
-            // Quit: Ctrl+Q
-            (Key::Char('q'), Modifiers { ctrl: true, .. }) => {
-                self.try_quit();
-            }
-            // Save: Ctrl+S
-            (Key::Char('s'), Modifiers { ctrl: true, .. }) => {
-                self.save()?;
-            }
-            // Escape: clear selection and collapse to single cursor
-            (Key::Escape, _) => {
-                if self.cursors().len() > 1 {
-                    self.cursors_mut().collapse_to_primary();
-                } else {
-                    self.cursors_mut().primary_mut().clear_selection();
+        self.replace_all_text(text, stripped);
+        self.messages.info("Stripped ANSI escape codes".to_string());
+    }
+
+    /// Rewrite every line's leading whitespace in the `to` style and make it
+    /// the buffer's indentation from now on
+    fn convert_indentation(&mut self, to: Indent) {
+        let to = match to {
+            Indent::Spaces(_) => Indent::Spaces(self.indent_width()),
+            Indent::Tabs => Indent::Tabs,
+        };
+        let width = self.indent_width();
+        let text = self.buffer().contents();
+        let mut changed = 0;
+        let converted: String = text
+            .split_inclusive('\n')
+            .map(|line| {
+                let rest = line.trim_start_matches([' ', '\t']);
+                let leading = &line[..line.len() - rest.len()];
+                // Whitespace-only lines are left alone
+                if rest.trim().is_empty() {
+                    return line.to_string();
                 }
-                self.dismiss_ghost_text();
-            }
+                let new = buffer::convert_leading(leading, width, to);
+                if new != leading {
+                    changed += 1;
+                }
+                format!("{}{}", new, rest)
+            })
+            .collect();
 
-            // === Undo/Redo ===
-            (Key::Char('z'), Modifiers { ctrl: true, shift: false, .. }) => {
-                self.undo();
-            }
-            (Key::Char('z'), Modifiers { ctrl: true, shift: true, .. })
-            | (Key::Char(']'), Modifiers { ctrl: true, .. })
-            | (Key::Char('5'), Modifiers { ctrl: true, .. })  // Ctrl+] reports as Ctrl+5 on some terminals
-            | (Key::Char('\x1d'), _) => {  // 0x1D = Ctrl+] as raw control character
-                self.redo();
-            }
+        self.buffer_mut().indent = Some(to);
+        if changed > 0 {
+            self.replace_all_text(text, converted);
+        }
+        let lines = if changed == 1 { "line" } else { "lines" };
+        self.messages.info(format!("Indenting with {} ({} {} converted)", to.label(), changed, lines));
+    }
 
-            // === Clipboard ===
-            (Key::Char('c'), Modifiers { ctrl: true, .. }) => {
-                self.copy();
-            }
-            (Key::Char('x'), Modifiers { ctrl: true, .. }) => {
-                self.cut();
-            }
-            (Key::Char('v'), Modifiers { ctrl: true, .. }) => {
-                self.paste();
-            }
+    /// Replace the buffer's `text` with `new` as one undoable edit, keeping
+    /// the cursor where it was as far as the new text allows
+    fn replace_all_text(&mut self, text: String, new: String) {
+        self.cursors_mut().collapse_to_primary();
+        let cursor_before = self.cursor_pos();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(vec![cursor_before]);
+        let len = self.buffer().len_chars();
+        self.buffer_mut().delete(0, len);
+        self.history_mut().record_delete(0, text, cursor_before, cursor_before);
+        if !new.is_empty() {
+            self.buffer_mut().insert(0, &new);
+            self.history_mut().record_insert(0, new, cursor_before, cursor_before);
+        }
+
+        let line = cursor_before.line.min(self.buffer().line_count().saturating_sub(1));
+        let col = cursor_before.col.min(self.buffer().line_len(line));
+        self.cursor_mut().set(line, col);
+        let cursor_after = self.cursor_pos();
+        self.history_mut().set_cursors_after(vec![cursor_after]);
+        self.history_mut().end_group();
+        self.invalidate_highlight_cache(0);
+        self.invalidate_bracket_cache();
+    }
 
-            // === Multi-cursor operations (must come before other movement to capture Ctrl+Alt) ===
-            // Add cursor above: Ctrl+Alt+Up
-            (Key::Up, Modifiers { ctrl: true, alt: true, .. }) => self.add_cursor_above(),
-            // Add cursor below: Ctrl+Alt+Down
-            (Key::Down, Modifiers { ctrl: true, alt: true, .. }) => self.add_cursor_below(),
+    /// List the backup generations kept for the current file to restore one
+    fn open_recover_version(&mut self) {
+        let Some(path) = self.current_file_path().filter(|_| self.buffer_entry().virtual_doc.is_none()) else {
+            self.messages.info("No file to recover".to_string());
+            return;
+        };
+        let versions: Vec<(Generation, String)> = self
+            .workspace
+            .backup_generations(&path)
+            .into_iter()
+            .filter_map(|generation| {
+                let text = self.workspace.read_generation(&generation).ok()?;
+                Some((generation, text))
+            })
+            .collect();
+        if versions.is_empty() {
+            self.messages.info(format!("No earlier versions of {} kept yet", self.workspace.display_path(&path)));
+            return;
+        }
+        self.workspace.fuss.deactivate();
+        self.prompt = PromptState::RecoverVersion { versions, selected_index: 0 };
+    }
 
-            // === Line operations (must come before movement to capture Alt+arrows) ===
-            // Move line up/down: Alt+Up/Down
-            (Key::Up, Modifiers { alt: true, shift: false, .. }) => self.move_line_up(),
-            (Key::Down, Modifiers { alt: true, shift: false, .. }) => self.move_line_down(),
-            // Duplicate line: Alt+Shift+Up/Down
-            (Key::Up, Modifiers { alt: true, shift: true, .. }) => self.duplicate_line_up(),
-            (Key::Down, Modifiers { alt: true, shift: true, .. }) => self.duplicate_line_down(),
+    /// Put a backup generation's text in the buffer, undoably
+    fn restore_version(&mut self, generation: &Generation, text: String) {
+        let current = self.buffer().contents();
+        if current == text {
+            self.messages.info("The buffer already matches that version".to_string());
+            return;
+        }
+        self.replace_all_text(current, text);
+        self.scroll_to_cursor();
+        self.messages.info(format!("Restored the version from {}", relative_time(generation.age())));
+    }
 
-            // Word movement: Alt+Left/Right
-            (Key::Left, Modifiers { alt: true, shift, .. }) => self.move_word_left(*shift),
-            (Key::Right, Modifiers { alt: true, shift, .. }) => self.move_word_right(*shift),
-            // Unix-style word movement: Alt+B (back), Alt+F (forward)
-            (Key::Char('b'), Modifiers { alt: true, .. }) => self.move_word_left(false),
-            (Key::Char('f'), Modifiers { alt: true, .. }) => self.move_word_right(false),
+    /// Toggle inline blame for the cursor line and persist the setting
+    fn toggle_inline_blame(&mut self) {
+        self.workspace.config.inline_blame = !self.workspace.config.inline_blame;
+        let _ = self.workspace.save_config();
+        self.messages.info(if self.workspace.config.inline_blame {
+            "Inline blame on".to_string()
+        } else {
+            "Inline blame off".to_string()
+        });
+    }
 
-            // === Movement with selection ===
-            (Key::Up, Modifiers { shift, .. }) => {
-                self.move_up(*shift);
-                self.validate_ghost_text_position();
-            }
-            (Key::Down, Modifiers { shift, .. }) => {
-                self.move_down(*shift);
-                self.validate_ghost_text_position();
-            }
-            (Key::Left, Modifiers { shift, .. }) => {
-                self.move_left(*shift);
-                self.validate_ghost_text_position();
-            }
-            (Key::Right, Modifiers { shift, .. }) => {
-                self.move_right(*shift);
-                self.validate_ghost_text_position();
-            }
+    /// Blame for `line` of the active buffer when inline blame is on, running
+    /// git blame the first time it's needed since the buffer was opened or saved
+    fn blame_for_line(&mut self, line: usize) -> Option<&BlameLine> {
+        if !self.workspace.config.inline_blame || self.buffer_entry().virtual_doc.is_some() {
+            return None;
+        }
+        if self.buffer_entry().blame.is_none() {
+            let path = self.filename()?;
+            let full_path = if self.buffer_entry().is_orphan { path } else { self.workspace.root.join(path) };
+            // Untracked files get an empty blame so git isn't rerun every frame
+            self.buffer_entry_mut().blame = Some(blame_file(&full_path).unwrap_or_default());
+        }
+        self.buffer_entry().blame.as_ref()?.get(line)
+    }
 
-            // Home/End
-            (Key::Home, Modifiers { shift, .. }) => {
-                self.move_home(*shift);
-                self.validate_ghost_text_position();
-            }
-            (Key::End, Modifiers { shift, .. }) => {
-                self.move_end(*shift);
-                self.validate_ghost_text_position();
-            }
-            (Key::Char('a'), Modifiers { ctrl: true, shift, .. }) => self.smart_home(*shift),
-            (Key::Char('e'), Modifiers { ctrl: true, shift, .. }) => self.move_end(*shift),
+    /// Turn editing statistics on or off and persist the setting; turning
+    /// them off saves the session so far
+    fn toggle_statistics(&mut self) {
+        self.workspace.config.stats = !self.workspace.config.stats;
+        let _ = self.workspace.save_config();
+        if self.workspace.config.stats {
+            self.workspace.stats = Some(SessionStats::new());
+            self.messages.info("Statistics on".to_string());
+        } else {
+            self.workspace.save_stats(true);
+            self.workspace.stats = None;
+            self.messages.info("Statistics off".to_string());
+        }
+    }
 
-            // Page movement
-            (Key::PageUp, Modifiers { shift, .. }) => {
-                self.page_up(*shift);
-                self.validate_ghost_text_position();
-            }
-            (Key::PageDown, Modifiers { shift, .. }) => {
-                self.page_down(*shift);
-                self.validate_ghost_text_position();
-            }
+    /// Open the statistics summary, including this session so far
+    fn show_statistics(&mut self) {
+        if self.workspace.stats.is_none() {
+            self.messages.info("Statistics are off; turn them on with Toggle Statistics".to_string());
+        }
+        self.workspace.save_stats(true);
+        self.open_virtual_document(VirtualDocument::Statistics { dir: self.workspace.stats_dir() });
+    }
 
-            // Join lines: Ctrl+J
-            (Key::Char('j'), Modifiers { ctrl: true, .. }) => self.join_lines(),
+    /// Turn idle-time workspace analysis on or off and persist the setting
+    fn toggle_insights(&mut self) {
+        self.workspace.config.insights = !self.workspace.config.insights;
+        let _ = self.workspace.save_config();
+        let state = if self.workspace.config.insights { "on" } else { "off" };
+        self.messages.info(format!("Idle workspace analysis {}", state));
+    }
 
-            // Toggle line comment: Ctrl+/
-            // Different terminals send: Ctrl+/, Ctrl+_, \x1f (ASCII 31), or Ctrl+7
-            (Key::Char('/'), Modifiers { ctrl: true, .. })
-            | (Key::Char('_'), Modifiers { ctrl: true, .. })
-            | (Key::Char('\x1f'), _)
-            | (Key::Char('7'), Modifiers { ctrl: true, .. }) => self.toggle_line_comment(),
-
-            // Select line: Ctrl+L
-            (Key::Char('l'), Modifiers { ctrl: true, .. }) => self.select_line(),
-            // Select word: Ctrl+D (select word at cursor, or next occurrence if already selected)
-            (Key::Char('d'), Modifiers { ctrl: true, .. }) => self.select_word(),
-
-            // === Find/Replace ===
-            // Find: Ctrl+F
-            (Key::Char('f'), Modifiers { ctrl: true, .. }) => self.open_find(),
-            // Replace: Ctrl+R (or Ctrl+H for compatibility)
-            (Key::Char('r'), Modifiers { ctrl: true, .. }) |
-            (Key::Char('h'), Modifiers { ctrl: true, alt: true, .. }) => self.open_replace(),
-            // Find next: F3
-            (Key::F(3), Modifiers { shift: false, .. }) => self.find_next(),
-            // Find previous: Shift+F3
-            (Key::F(3), Modifiers { shift: true, .. }) => self.find_prev(),
-
-            // === File operations ===
-            // Open file browser (Fortress mode): Ctrl+O
-            (Key::Char('o'), Modifiers { ctrl: true, .. }) => self.open_fortress(),
-            // Go to line: Ctrl+G or F5
-            (Key::Char('g'), Modifiers { ctrl: true, .. }) |
-            (Key::F(5), _) => self.open_goto_line(),
-            // Multi-file search: F4
-            (Key::F(4), _) => self.open_file_search(),
-            // Command palette: Ctrl+P
-            (Key::Char('p'), Modifiers { ctrl: true, .. }) => self.open_command_palette(),
-
-            // === Editing ===
-            (Key::Char(c), Modifiers { ctrl: false, alt: false, .. }) => {
-                self.insert_char(*c);
-            }
-            (Key::Enter, _) => {
-                self.insert_newline();
-                self.dismiss_ghost_text();
-            }
-            (Key::Backspace, Modifiers { alt: true, .. }) => {
-                self.delete_word_backward();
-                self.update_ghost_text();
-            }
-            (Key::Backspace, _) | (Key::Char('h'), Modifiers { ctrl: true, .. }) => {
-                self.delete_backward();
-                self.update_ghost_text();
-            }
-            (Key::Delete, _) => {
-                self.delete_forward();
-                self.dismiss_ghost_text();
-            }
-            (Key::Tab, _) => {
-                // Accept ghost text if visible and no selection
-                if self.ghost_text.suggestion.is_some() && !self.cursor().has_selection() {
-                    self.accept_ghost_text();
-                } else {
-                    self.insert_tab();
-                }
-            }
-            (Key::BackTab, _) => self.dedent(),
-
-            // Delete word backward: Ctrl+W
-            (Key::Char('w'), Modifiers { ctrl: true, .. }) => self.delete_word_backward(),
-            // Delete word forward: Alt+D
-            (Key::Char('d'), Modifiers { alt: true, .. }) => self.delete_word_forward(),
-
-            // Unix-style kill commands
-            // Kill to end of line: Ctrl+K
-            (Key::Char('k'), Modifiers { ctrl: true, .. }) => self.kill_to_end_of_line(),
-            // Kill to start of line: Ctrl+U
-            (Key::Char('u'), Modifiers { ctrl: true, .. }) => self.kill_to_start_of_line(),
-            // Yank from kill ring: Ctrl+Y
-            (Key::Char('y'), Modifiers { ctrl: true, .. }) => self.yank(),
-            // Cycle yank stack: Alt+Y
-            (Key::Char('y'), Modifiers { alt: true, .. }) => self.yank_cycle(),
-
-            // Character transpose: Ctrl+T
-            (Key::Char('t'), Modifiers { ctrl: true, .. }) => self.transpose_chars(),
-
-            // === Bracket/Quote operations ===
-            // Jump to matching bracket: Alt+[ or Alt+]
-            (Key::Char('['), Modifiers { alt: true, .. }) |
-            (Key::Char(']'), Modifiers { alt: true, .. }) => self.jump_to_matching_bracket(),
-            // Cycle quotes: Alt+' (cycles " -> ' -> ` -> ")
-            (Key::Char('\''), Modifiers { alt: true, shift: false, .. }) => self.cycle_quotes(),
-            // Remove surrounding quotes/brackets: Alt+Shift+' (Alt+")
-            (Key::Char('"'), Modifiers { alt: true, .. }) => self.remove_surrounding(),
-            // Cycle bracket type: Alt+Shift+9 (cycles ( -> { -> [ -> ()
-            (Key::Char('('), Modifiers { alt: true, .. }) => self.cycle_brackets(),
-            // Remove surrounding brackets: Alt+Shift+0
-            (Key::Char(')'), Modifiers { alt: true, .. }) => self.remove_surrounding_brackets(),
-
-            // === Pane operations ===
-            // Split vertical: Alt+V
-            (Key::Char('v'), Modifiers { alt: true, .. }) => {
-                self.split_vertical();
-            }
-            // Split horizontal: Alt+S
-            (Key::Char('s'), Modifiers { alt: true, .. }) => {
-                self.split_horizontal();
-            }
-            // Close pane/tab: Alt+Q
-            (Key::Char('q'), Modifiers { alt: true, .. }) => {
-                self.close_pane();
-            }
-            // Navigate panes: Alt+H/J/K/L (vim-style)
-            (Key::Char('h'), Modifiers { alt: true, .. }) => {
-                self.navigate_pane_left();
-            }
-            (Key::Char('j'), Modifiers { alt: true, .. }) => {
-                self.navigate_pane_down();
-            }
-            (Key::Char('k'), Modifiers { alt: true, .. }) => {
-                self.navigate_pane_up();
-            }
-            (Key::Char('l'), Modifiers { alt: true, .. }) => {
-                self.navigate_pane_right();
-            }
-            // Next/Prev pane: Alt+N / Alt+P
-            (Key::Char('n'), Modifiers { alt: true, .. }) => {
-                self.next_pane();
-            }
-            (Key::Char('p'), Modifiers { alt: true, .. }) => {
-                self.prev_pane();
-            }
-
-            // === Tab operations ===
-            // Switch to tab by number: Alt+1-9
-            (Key::Char('1'), Modifiers { alt: true, .. }) => self.workspace.switch_to_tab(0),
-            (Key::Char('2'), Modifiers { alt: true, .. }) => self.workspace.switch_to_tab(1),
-            (Key::Char('3'), Modifiers { alt: true, .. }) => self.workspace.switch_to_tab(2),
-            (Key::Char('4'), Modifiers { alt: true, .. }) => self.workspace.switch_to_tab(3),
-            (Key::Char('5'), Modifiers { alt: true, .. }) => self.workspace.switch_to_tab(4),
-            (Key::Char('6'), Modifiers { alt: true, .. }) => self.workspace.switch_to_tab(5),
-            (Key::Char('7'), Modifiers { alt: true, .. }) => self.workspace.switch_to_tab(6),
-            (Key::Char('8'), Modifiers { alt: true, .. }) => self.workspace.switch_to_tab(7),
-            (Key::Char('9'), Modifiers { alt: true, .. }) => self.workspace.switch_to_tab(8),
-            // Next/Prev tab: Alt+. / Alt+,
-            (Key::Char('.'), Modifiers { alt: true, .. }) => self.workspace.next_tab(),
-            (Key::Char(','), Modifiers { alt: true, .. }) => self.workspace.prev_tab(),
-            // New tab: Alt+T
-            (Key::Char('t'), Modifiers { alt: true, .. }) => self.workspace.new_tab(),
-
-            // === LSP operations ===
-            // Go to definition: F12
-            (Key::F(12), Modifiers { shift: false, .. }) => self.lsp_goto_definition(),
-            // Find references: Shift+F12
-            (Key::F(12), Modifiers { shift: true, .. }) => self.lsp_find_references(),
-            // Hover info: F1
-            (Key::F(1), Modifiers { shift: false, .. }) => self.lsp_hover(),
-            // Code completion: Ctrl+N (vim-style)
-            (Key::Char('n'), Modifiers { ctrl: true, .. }) => self.lsp_complete(),
-            // Rename: F2
-            (Key::F(2), _) => self.lsp_rename(),
-            // Server manager: Alt+M
-            (Key::Char('m'), Modifiers { alt: true, .. }) => self.toggle_server_manager(),
-
-            // === Help ===
-            // Help / keybindings: Shift+F1
-            (Key::F(1), Modifiers { shift: true, .. }) => self.open_help_menu(),
-
-            _ => {}
-        }
-
-        // Check if buffer was edited and needs backup
-        self.on_buffer_edit();
-
-        self.scroll_to_cursor();
-        Ok(())
+    /// Open the workspace insights report, analysing the workspace afresh
+    fn show_insights(&mut self) {
+        self.start_insights();
+        self.open_virtual_document(VirtualDocument::Insights);
     }
 
-    // === Cursor helpers ===
-
-    /// Get reference to primary cursor
-    fn cursor(&self) -> &Cursor {
-        self.cursors().primary()
+    fn start_insights(&mut self) {
+        self.workspace.analyse();
+        self.insights_pending = true;
     }
 
-    /// Get mutable reference to primary cursor
-    fn cursor_mut(&mut self) -> &mut Cursor {
-        self.cursors_mut().primary_mut()
+    /// Start the analysis once the editor has been idle (with insights on
+    /// and nothing analysed yet this session); when a started one finishes,
+    /// reload the open report. Returns whether anything changed on screen.
+    fn poll_insights(&mut self) -> bool {
+        if self.workspace.config.insights
+            && !self.workspace.analysed()
+            && self.last_input_time.elapsed() >= INSIGHTS_IDLE
+        {
+            self.start_insights();
+        }
+        if !self.insights_pending || self.workspace.analysing() {
+            return false;
+        }
+        self.insights_pending = false;
+        self.workspace.refresh_insights();
+        true
     }
 
-    // === Multi-cursor operations ===
+    /// Unstaged hunks of the active file. Hunks are line ranges of the saved
+    /// file, so they need the buffer to match it.
+    fn file_hunks(&mut self) -> Option<FileHunks> {
+        let Some(path) = self.current_file_path().filter(|_| self.buffer_entry().virtual_doc.is_none()) else {
+            self.messages.info("Hunks need a file on disk".to_string());
+            return None;
+        };
+        if self.buffer_entry_mut().is_modified() {
+            self.messages.info("Save the file to work with its hunks".to_string());
+            return None;
+        }
+        match FileHunks::load(&path, &self.workspace.config.diff_algorithm) {
+            Ok(hunks) => Some(hunks),
+            Err(e) => {
+                self.messages.error(format!("Git: {}", e));
+                None
+            }
+        }
+    }
 
-    /// Add a cursor on the line above the topmost cursor
-    fn add_cursor_above(&mut self) {
-        // Find the topmost cursor
-        let topmost = self.cursors().all().iter().map(|c| c.line).min().unwrap_or(0);
-        let col = self.cursors().primary().col;
+    /// Move to the start of the next (or previous) unstaged hunk, wrapping around
+    fn goto_hunk(&mut self, next: bool) {
+        let Some(file) = self.file_hunks() else { return };
+        let line = self.cursor().line;
+        let target = if next {
+            file.hunks.iter().find(|h| h.start > line).or(file.hunks.first())
+        } else {
+            file.hunks.iter().rev().find(|h| h.start < line).or(file.hunks.last())
+        };
+        let Some(hunk) = target else {
+            self.messages.info("No unstaged changes".to_string());
+            return;
+        };
+        let index = file.hunks.iter().position(|h| h == hunk).unwrap_or(0);
+        let line = hunk.start.min(self.buffer().line_count().saturating_sub(1));
+        self.cursors_mut().collapse_to_primary();
+        self.cursor_mut().set(line, 0);
+        self.scroll_to_cursor();
+        self.messages.info(format!("Hunk {} of {}", index + 1, file.hunks.len()));
+    }
 
-        if topmost > 0 {
-            let new_line = topmost - 1;
-            let line_len = self.buffer().line_len(new_line);
-            let new_col = col.min(line_len);
-            self.cursors_mut().add(new_line, new_col);
+    /// Add the hunk under the cursor to the git index
+    fn stage_hunk(&mut self) {
+        let Some(file) = self.file_hunks() else { return };
+        let Some(hunk) = file.at_line(self.cursor().line) else {
+            self.messages.info("No unstaged change at the cursor".to_string());
+            return;
+        };
+        match file.stage(hunk) {
+            Ok(()) => self.messages.info("Staged hunk".to_string()),
+            Err(e) => self.messages.error(format!("Stage failed: {}", e)),
         }
     }
 
-    /// Add a cursor on the line below the bottommost cursor
-    fn add_cursor_below(&mut self) {
-        // Find the bottommost cursor
-        let bottommost = self.cursors().all().iter().map(|c| c.line).max().unwrap_or(0);
-        let col = self.cursors().primary().col;
+    /// Put the index's lines back in place of the hunk under the cursor,
+    /// as an undoable edit
+    fn revert_hunk(&mut self) {
+        let Some(file) = self.file_hunks() else { return };
+        let Some(hunk) = file.at_line(self.cursor().line) else {
+            self.messages.info("No unstaged change at the cursor".to_string());
+            return;
+        };
+
         let line_count = self.buffer().line_count();
+        let len = self.buffer().len_chars();
+        let char_at_line = |editor: &Self, line: usize| {
+            if line < line_count { editor.buffer().line_col_to_char(line, 0) } else { len }
+        };
+        let start = char_at_line(self, hunk.start);
+        let end = char_at_line(self, hunk.start + hunk.count);
+        let removed: String = self.buffer().slice(start, end).chars().collect();
+        let mut restored: String = hunk.old_lines.iter().map(|line| format!("{}\n", line)).collect();
+        // The file's last line may have no newline to give back
+        if end == len && !removed.is_empty() && !removed.ends_with('\n') {
+            restored.pop();
+        }
 
-        if bottommost + 1 < line_count {
-            let new_line = bottommost + 1;
-            let line_len = self.buffer().line_len(new_line);
-            let new_col = col.min(line_len);
-            self.cursors_mut().add(new_line, new_col);
+        self.invalidate_highlight_cache(hunk.start.saturating_sub(1));
+        self.invalidate_bracket_cache();
+        let cursor_before = self.cursor_pos();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(vec![cursor_before]);
+        if !removed.is_empty() {
+            self.buffer_mut().delete(start, end);
+            self.history_mut().record_delete(start, removed, cursor_before, cursor_before);
+        }
+        if !restored.is_empty() {
+            self.buffer_mut().insert(start, &restored);
+            self.history_mut().record_insert(start, restored, cursor_before, cursor_before);
         }
-    }
 
-    /// Toggle cursor at position (for Ctrl+click)
-    /// Returns true if cursor was added, false if removed
-    fn toggle_cursor_at(&mut self, line: usize, col: usize) -> bool {
-        self.cursors_mut().toggle_at(line, col)
+        let line = hunk.start.min(self.buffer().line_count().saturating_sub(1));
+        self.cursors_mut().collapse_to_primary();
+        self.cursor_mut().set(line, 0);
+        let cursor_after = self.cursor_pos();
+        self.history_mut().set_cursors_after(vec![cursor_after]);
+        self.history_mut().end_group();
+        self.scroll_to_cursor();
+        self.messages.info("Reverted hunk".to_string());
     }
 
-    // === Movement ===
+    /// Show the active file as committed at HEAD beside it, read-only
+    fn compare_with_head(&mut self) {
+        let Some(path) = self.compare_target() else { return };
+        self.open_compare(VirtualDocument::GitHead { path }, "HEAD".to_string());
+    }
 
-    fn move_up(&mut self, extend_selection: bool) {
-        // Get line lengths we need before borrowing cursors mutably
-        let line_count = self.buffer().line_count();
-        let line_lens: Vec<usize> = (0..line_count).map(|l| self.buffer().line_len(l)).collect();
+    /// Show the active file's latest backup beside it, read-only
+    fn compare_with_backup(&mut self) {
+        let Some(path) = self.compare_target() else { return };
+        let full_path = self.workspace.root.join(&path);
+        let Some(generation) = self.workspace.backup_generations(&full_path).into_iter().next() else {
+            self.messages.info(format!("No backups of {} kept yet", path.display()));
+            return;
+        };
+        let what = format!("backup from {}", relative_time(generation.age()));
+        self.open_compare(VirtualDocument::Backup { path, generation: generation.path }, what);
+    }
 
-        // Apply to all cursors
-        for cursor in self.cursors_mut().all_mut() {
-            if cursor.line > 0 {
-                let new_line = cursor.line - 1;
-                let line_len = line_lens.get(new_line).copied().unwrap_or(0);
-                let new_col = cursor.desired_col.min(line_len);
-                cursor.move_to(new_line, new_col, extend_selection);
-            } else {
-                // On first line, move to start of line
-                cursor.move_to(0, 0, extend_selection);
-            }
+    /// The active file relative to the workspace root, to compare with
+    /// another version of it
+    fn compare_target(&mut self) -> Option<PathBuf> {
+        let path = self.workspace_path().filter(|path| path.is_relative());
+        if path.is_none() {
+            self.messages.info("Only workspace files can be compared".to_string());
         }
-        self.cursors_mut().merge_overlapping();
+        path
     }
 
-    fn move_down(&mut self, extend_selection: bool) {
-        let line_count = self.buffer().line_count();
-        let line_lens: Vec<usize> = (0..line_count).map(|l| self.buffer().line_len(l)).collect();
-
-        for cursor in self.cursors_mut().all_mut() {
-            if cursor.line + 1 < line_count {
-                let new_line = cursor.line + 1;
-                let line_len = line_lens.get(new_line).copied().unwrap_or(0);
-                let new_col = cursor.desired_col.min(line_len);
-                cursor.move_to(new_line, new_col, extend_selection);
-            } else {
-                // On last line, move to end of line
-                let line_len = line_lens.get(cursor.line).copied().unwrap_or(0);
-                cursor.move_to(cursor.line, line_len, extend_selection);
+    fn open_compare(&mut self, doc: VirtualDocument, what: String) {
+        match self.workspace.open_compare(doc) {
+            Ok(()) => {
+                self.workspace.fuss.deactivate();
+                self.sync_compare_scroll();
+                self.messages.info(format!("Comparing with {}", what));
             }
+            Err(e) => self.messages.error(format!("Compare failed: {}", e)),
         }
-        self.cursors_mut().merge_overlapping();
     }
 
-    fn move_left(&mut self, extend_selection: bool) {
-        let line_count = self.buffer().line_count();
-        let line_lens: Vec<usize> = (0..line_count).map(|l| self.buffer().line_len(l)).collect();
-
-        for cursor in self.cursors_mut().all_mut() {
-            if cursor.col > 0 {
-                cursor.move_to(cursor.line, cursor.col - 1, extend_selection);
-                cursor.desired_col = cursor.col;
-            } else if cursor.line > 0 {
-                let new_line = cursor.line - 1;
-                let new_col = line_lens.get(new_line).copied().unwrap_or(0);
-                cursor.move_to(new_line, new_col, extend_selection);
-                cursor.desired_col = cursor.col;
-            }
+    /// Panes of the active tab comparing a file with another version of
+    /// it, as (other version's pane, file's pane)
+    fn compare_panes(&self) -> Option<(usize, usize)> {
+        let tab = self.workspace.active_tab();
+        let (old_pane, path) = tab.panes.iter().enumerate().find_map(|(i, pane)| {
+            let doc = tab.buffers[pane.buffer_idx].virtual_doc.as_ref()?;
+            Some((i, doc.compared_file()?))
+        })?;
+        let new_pane = tab.panes.iter().position(|pane| {
+            let entry = &tab.buffers[pane.buffer_idx];
+            !entry.is_orphan && entry.virtual_doc.is_none() && entry.path.as_deref() == Some(path)
+        })?;
+        Some((old_pane, new_pane))
+    }
+
+    /// Match up the lines of the compared panes again if either side
+    /// changed; returns the panes
+    fn update_comparison(&mut self) -> Option<(usize, usize)> {
+        let Some((old_pane, new_pane)) = self.compare_panes() else {
+            self.comparison = None;
+            return None;
+        };
+        let tab = self.workspace.active_tab_mut();
+        let (old_idx, new_idx) = (tab.panes[old_pane].buffer_idx, tab.panes[new_pane].buffer_idx);
+        let hashes = (tab.buffers[old_idx].buffer.content_hash(), tab.buffers[new_idx].buffer.content_hash());
+        if self.comparison.as_ref().map(|(h, _)| *h) != Some(hashes) {
+            let lines = |buffer: &Buffer| -> Vec<String> {
+                (0..buffer.line_count()).map(|line| buffer.line_str(line).unwrap_or_default()).collect()
+            };
+            let diff = git::diff::line_diff(&lines(&tab.buffers[old_idx].buffer), &lines(&tab.buffers[new_idx].buffer));
+            self.comparison = Some((hashes, diff));
         }
-        self.cursors_mut().merge_overlapping();
+        Some((old_pane, new_pane))
     }
 
-    fn move_right(&mut self, extend_selection: bool) {
-        let line_count = self.buffer().line_count();
-        let line_lens: Vec<usize> = (0..line_count).map(|l| self.buffer().line_len(l)).collect();
-
-        for cursor in self.cursors_mut().all_mut() {
-            let line_len = line_lens.get(cursor.line).copied().unwrap_or(0);
-            if cursor.col < line_len {
-                cursor.move_to(cursor.line, cursor.col + 1, extend_selection);
-                cursor.desired_col = cursor.col;
-            } else if cursor.line + 1 < line_count {
-                cursor.move_to(cursor.line + 1, 0, extend_selection);
-                cursor.desired_col = 0;
-            }
-        }
-        self.cursors_mut().merge_overlapping();
+    /// Scroll the other side of a comparison to the place the active side
+    /// shows
+    fn sync_compare_scroll(&mut self) {
+        let Some((old_pane, new_pane)) = self.update_comparison() else { return };
+        let Some((_, diff)) = &self.comparison else { return };
+        let tab = self.workspace.active_tab_mut();
+        let (from, to) = match tab.active_pane {
+            pane if pane == old_pane => (old_pane, new_pane),
+            pane if pane == new_pane => (new_pane, old_pane),
+            _ => return,
+        };
+        let line = diff.matching_line(tab.panes[from].viewport_line, from == old_pane);
+        let last_line = tab.buffer_for_pane(to).buffer.line_count().saturating_sub(1);
+        tab.panes[to].viewport_line = line.min(last_line);
+    }
+
+    /// Turn vim emulation on or off and persist the setting
+    fn toggle_vim_mode(&mut self) {
+        self.workspace.config.vim_mode = !self.workspace.config.vim_mode;
+        let _ = self.workspace.save_config();
+        self.vim.reset();
+        self.vim.mode = VimMode::Normal;
+        self.cursors_mut().collapse_to_primary();
+        self.cursor_mut().clear_selection();
+        self.messages.info(if self.workspace.config.vim_mode {
+            "Vim mode on".to_string()
+        } else {
+            "Vim mode off".to_string()
+        });
     }
 
-    fn move_word_left(&mut self, extend_selection: bool) {
-        // Collect line data before borrowing cursors mutably
-        let line_count = self.buffer().line_count();
-        let line_lens: Vec<usize> = (0..line_count).map(|l| self.buffer().line_len(l)).collect();
-        let line_strs: Vec<String> = (0..line_count)
-            .map(|l| self.buffer().line_str(l).unwrap_or_default())
-            .collect();
+    // === Vim emulation ===
 
-        for cursor in self.cursors_mut().all_mut() {
-            let (mut line, mut col) = (cursor.line, cursor.col);
+    fn run_vim_action(&mut self, action: VimAction) {
+        let edits = matches!(
+            action,
+            VimAction::Put { .. } | VimAction::Insert(_)
+                | VimAction::Operate { operator: Operator::Delete | Operator::Change, .. }
+                | VimAction::OperateSelection { operator: Operator::Delete | Operator::Change, .. }
+        );
+        if let Some(reason) = self.read_only_reason().filter(|_| edits) {
+            self.messages.info(reason.to_string());
+            return;
+        }
+        if !matches!(action, VimAction::Command(_) | VimAction::Nothing) {
+            self.cursors_mut().collapse_to_primary();
+        }
 
-            // If at start of line, go to end of previous line
-            if col == 0 && line > 0 {
-                line -= 1;
-                col = line_lens.get(line).copied().unwrap_or(0);
+        match action {
+            VimAction::Move { motion, count } => {
+                let extend = self.vim.mode.is_visual();
+                for _ in 0..count {
+                    self.vim_motion(motion, extend);
+                }
+                if self.vim.mode == VimMode::VisualLine {
+                    self.vim_select_whole_lines();
+                }
             }
-
-            if let Some(line_str) = line_strs.get(line) {
-                let chars: Vec<char> = line_str.chars().collect();
-                if col > 0 {
-                    col = col.min(chars.len());
-                    // Skip whitespace
-                    while col > 0 && chars.get(col - 1).map_or(false, |c| c.is_whitespace()) {
-                        col -= 1;
-                    }
-                    // Determine what kind of characters to skip based on char before cursor
-                    if col > 0 {
-                        let prev_char = chars[col - 1];
-                        if is_word_char(prev_char) {
-                            // Skip word characters
-                            while col > 0 && chars.get(col - 1).map_or(false, |c| is_word_char(*c)) {
-                                col -= 1;
-                            }
-                        } else {
-                            // Skip punctuation/symbols
-                            while col > 0 && chars.get(col - 1).map_or(false, |c| !is_word_char(*c) && !c.is_whitespace()) {
-                                col -= 1;
-                            }
-                        }
-                    }
+            VimAction::Operate { operator, target, count, register } => {
+                self.vim_operate(operator, target, count, register);
+            }
+            VimAction::OperateSelection { operator, register } => {
+                let linewise = self.vim.mode == VimMode::VisualLine;
+                let (start, end) = self.cursor().selection_bounds().unwrap_or_else(|| {
+                    let pos = self.cursor().position();
+                    let len = self.buffer().line_len(pos.line);
+                    (pos, Position::new(pos.line, (pos.col + 1).min(len)))
+                });
+                self.vim.mode = VimMode::Normal;
+                self.cursor_mut().clear_selection();
+                self.vim_apply(operator, start, end, linewise, register);
+            }
+            VimAction::SelectObject(object) => {
+                if let Some((start, end)) = self.vim_object_range(object) {
+                    self.vim.mode = VimMode::Visual;
+                    let cursor = self.cursor_mut();
+                    cursor.move_to(start.line, start.col, false);
+                    cursor.move_to(end.line, end.col, true);
                 }
             }
+            VimAction::Put { before, count, register } => self.vim_put(before, count, register),
+            VimAction::Insert(at) => self.vim_insert(at),
+            VimAction::SetMode(mode) => self.vim_set_mode(mode),
+            VimAction::Undo(count) => (0..count).for_each(|_| self.undo()),
+            VimAction::Redo(count) => (0..count).for_each(|_| self.redo()),
+            VimAction::Command(command) => self.execute_command(command),
+            VimAction::Nothing => {}
+        }
 
-            cursor.move_to(line, col, extend_selection);
-            cursor.desired_col = col;
+        // Normal mode rests on a character, never past the end of the line
+        if self.vim.mode == VimMode::Normal {
+            let line = self.cursor().line;
+            let last = self.buffer().line_len(line).saturating_sub(1);
+            if self.cursor().col > last {
+                self.cursor_mut().col = last;
+            }
         }
-        self.cursors_mut().merge_overlapping();
     }
 
-    fn move_word_right(&mut self, extend_selection: bool) {
-        let line_count = self.buffer().line_count();
-        let line_lens: Vec<usize> = (0..line_count).map(|l| self.buffer().line_len(l)).collect();
-        let line_strs: Vec<String> = (0..line_count)
-            .map(|l| self.buffer().line_str(l).unwrap_or_default())
-            .collect();
-
-        for cursor in self.cursors_mut().all_mut() {
-            let (mut line, mut col) = (cursor.line, cursor.col);
-            let line_len = line_lens.get(line).copied().unwrap_or(0);
-
-            // If at end of line, go to start of next line
-            if col >= line_len && line + 1 < line_count {
-                line += 1;
-                col = 0;
+    fn vim_set_mode(&mut self, mode: VimMode) {
+        match (self.vim.mode, mode) {
+            (VimMode::Normal, VimMode::Normal) => {
+                self.cursor_mut().clear_selection();
+                self.dismiss_ghost_text();
             }
-
-            if let Some(line_str) = line_strs.get(line) {
-                let chars: Vec<char> = line_str.chars().collect();
-                if col < chars.len() {
-                    let curr_char = chars[col];
-                    if is_word_char(curr_char) {
-                        // Skip word characters
-                        while col < chars.len() && chars.get(col).map_or(false, |c| is_word_char(*c)) {
-                            col += 1;
-                        }
-                    } else if !curr_char.is_whitespace() {
-                        // Skip punctuation/symbols
-                        while col < chars.len() && chars.get(col).map_or(false, |c| !is_word_char(*c) && !c.is_whitespace()) {
-                            col += 1;
-                        }
-                    }
+            (VimMode::Insert, VimMode::Normal) => {
+                self.dismiss_ghost_text();
+                if self.cursor().col > 0 {
+                    self.move_left(false);
                 }
-                // Skip whitespace
-                while col < chars.len() && chars.get(col).map_or(false, |c| c.is_whitespace()) {
-                    col += 1;
+            }
+            (_, VimMode::Normal | VimMode::Insert) => self.cursor_mut().clear_selection(),
+            (VimMode::VisualLine, VimMode::Visual) => {}
+            (_, VimMode::Visual) => self.cursor_mut().start_selection(),
+            (_, VimMode::VisualLine) => {
+                if !self.vim.mode.is_visual() {
+                    self.cursor_mut().start_selection();
                 }
+                self.vim.mode = mode;
+                self.vim_select_whole_lines();
             }
-
-            cursor.move_to(line, col, extend_selection);
-            cursor.desired_col = col;
         }
-        self.cursors_mut().merge_overlapping();
+        self.vim.mode = mode;
     }
 
-    fn move_home(&mut self, extend_selection: bool) {
-        for cursor in self.cursors_mut().all_mut() {
-            let line = cursor.line;
-            cursor.move_to(line, 0, extend_selection);
-            cursor.desired_col = 0;
+    /// Stretch the visual selection over whole lines (`V`)
+    fn vim_select_whole_lines(&mut self) {
+        let (anchor, line) = (self.cursor().anchor_line, self.cursor().line);
+        let (anchor_len, line_len) = (self.buffer().line_len(anchor), self.buffer().line_len(line));
+        let cursor = self.cursor_mut();
+        cursor.selecting = true;
+        if line >= anchor {
+            cursor.anchor_col = 0;
+            cursor.col = line_len;
+        } else {
+            cursor.anchor_col = anchor_len;
+            cursor.col = 0;
         }
-        self.cursors_mut().merge_overlapping();
     }
 
-    fn smart_home(&mut self, extend_selection: bool) {
-        // Toggle between column 0 and first non-whitespace
-        let line_count = self.buffer().line_count();
-        let line_strs: Vec<String> = (0..line_count)
-            .map(|l| self.buffer().line_str(l).unwrap_or_default())
-            .collect();
+    fn vim_motion(&mut self, motion: Motion, extend: bool) {
+        let (line, col) = (self.cursor().line, self.cursor().col);
+        let last_line = self.buffer().line_count().saturating_sub(1);
+        let first_non_blank = |editor: &Self, line: usize| {
+            editor.buffer().line_str(line)
+                .and_then(|s| s.chars().position(|c| !c.is_whitespace()))
+                .unwrap_or(0)
+        };
+        let target = match motion {
+            Motion::Left if col > 0 => (line, col - 1),
+            Motion::Right if col < self.buffer().line_len(line) => (line, col + 1),
+            Motion::Left | Motion::Right => return,
+            Motion::Up => return self.move_up(extend),
+            Motion::Down => return self.move_down(extend),
+            Motion::WordForward => return self.move_word_right(extend),
+            Motion::WordBackward => return self.move_word_left(extend),
+            Motion::WordEnd => self.vim_word_end(),
+            Motion::LineStart => (line, 0),
+            Motion::FirstNonBlank => (line, first_non_blank(self, line)),
+            Motion::LineEnd => (line, self.buffer().line_len(line)),
+            Motion::FileStart => (0, first_non_blank(self, 0)),
+            Motion::FileEnd => (last_line, first_non_blank(self, last_line)),
+            Motion::Line(n) => {
+                let line = n.saturating_sub(1).min(last_line);
+                (line, first_non_blank(self, line))
+            }
+        };
+        let cursor = self.cursor_mut();
+        cursor.move_to(target.0, target.1, extend);
+        cursor.desired_col = target.1;
+    }
 
-        for cursor in self.cursors_mut().all_mut() {
-            let line = cursor.line;
-            let col = cursor.col;
-            if let Some(line_str) = line_strs.get(line) {
-                let first_non_ws = line_str.chars().position(|c| !c.is_whitespace()).unwrap_or(0);
-                let new_col = if col == first_non_ws || col == 0 {
-                    if col == 0 { first_non_ws } else { 0 }
-                } else {
-                    first_non_ws
-                };
-                cursor.move_to(line, new_col, extend_selection);
-                cursor.desired_col = new_col;
+    /// Position of the last character of the next word end (`e`)
+    fn vim_word_end(&self) -> (usize, usize) {
+        let buffer = self.buffer();
+        let mut idx = buffer.line_col_to_char(self.cursor().line, self.cursor().col) + 1;
+        let len = buffer.len_chars();
+        let class = |c: char| if c.is_alphanumeric() || c == '_' { 1 } else { 2 };
+        while idx < len && buffer.char_at(idx).is_some_and(|c| c.is_whitespace()) {
+            idx += 1;
+        }
+        if let Some(first) = buffer.char_at(idx) {
+            while buffer.char_at(idx + 1).is_some_and(|c| !c.is_whitespace() && class(c) == class(first)) {
+                idx += 1;
             }
         }
-        self.cursors_mut().merge_overlapping();
+        buffer.char_to_line_col(idx.min(len.saturating_sub(1)))
     }
 
-    fn move_end(&mut self, extend_selection: bool) {
-        let line_count = self.buffer().line_count();
-        let line_lens: Vec<usize> = (0..line_count).map(|l| self.buffer().line_len(l)).collect();
-
-        for cursor in self.cursors_mut().all_mut() {
-            let line = cursor.line;
-            let line_len = line_lens.get(line).copied().unwrap_or(0);
-            cursor.move_to(line, line_len, extend_selection);
-            cursor.desired_col = line_len;
-        }
-        self.cursors_mut().merge_overlapping();
+    /// Range of a text object around the cursor
+    fn vim_object_range(&self, object: TextObject) -> Option<(Position, Position)> {
+        let buffer = self.buffer();
+        let text: Vec<char> = buffer.slice(0, buffer.len_chars()).chars().collect();
+        let pos = buffer.line_col_to_char(self.cursor().line, self.cursor().col);
+        let (start, end) = vim::text_object_range(&text, pos, object)?;
+        let (start, end) = (buffer.char_to_line_col(start), buffer.char_to_line_col(end));
+        Some((Position::new(start.0, start.1), Position::new(end.0, end.1)))
+    }
+
+    fn vim_operate(&mut self, operator: Operator, target: Target, count: usize, register: Option<char>) {
+        let start = self.cursor().position();
+        let last_line = self.buffer().line_count().saturating_sub(1);
+        let (from, to, linewise) = match target {
+            Target::Lines => (start, Position::new((start.line + count - 1).min(last_line), 0), true),
+            Target::Chars => {
+                let len = self.buffer().line_len(start.line);
+                (start, Position::new(start.line, (start.col + count).min(len)), false)
+            }
+            Target::Object(object) => match self.vim_object_range(object) {
+                Some((from, to)) => (from, to, false),
+                None => return,
+            },
+            Target::Motion(motion) => {
+                // `cw` changes to the end of the word, like `ce`
+                let motion = match (operator, motion) {
+                    (Operator::Change, Motion::WordForward) => Motion::WordEnd,
+                    _ => motion,
+                };
+                for _ in 0..count {
+                    self.vim_motion(motion, false);
+                }
+                let end = self.cursor().position();
+                let (from, mut to) = if (end.line, end.col) < (start.line, start.col) { (end, start) } else { (start, end) };
+                if motion.is_inclusive() {
+                    to.col = (to.col + 1).min(self.buffer().line_len(to.line));
+                }
+                (from, to, motion.is_linewise())
+            }
+        };
+        self.vim_apply(operator, from, to, linewise, register);
     }
 
-    fn page_up(&mut self, extend_selection: bool) {
-        let page = self.screen.rows.saturating_sub(2) as usize;
-        let line_count = self.buffer().line_count();
-        let line_lens: Vec<usize> = (0..line_count).map(|l| self.buffer().line_len(l)).collect();
+    /// Yank, delete or change `from..to`; linewise ranges cover `from.line..=to.line`
+    fn vim_apply(&mut self, operator: Operator, from: Position, to: Position, linewise: bool, register: Option<char>) {
+        let (text, range) = if linewise {
+            let lines: Vec<String> = (from.line..=to.line)
+                .map(|l| self.buffer().line_str(l).unwrap_or_default())
+                .collect();
+            let last_line = self.buffer().line_count().saturating_sub(1);
+            let range = if operator == Operator::Change {
+                // `cc` keeps the (now empty) line to type on
+                (Position::new(from.line, 0), Position::new(to.line, self.buffer().line_len(to.line)))
+            } else if to.line < last_line {
+                (Position::new(from.line, 0), Position::new(to.line + 1, 0))
+            } else if from.line > 0 {
+                let prev = from.line - 1;
+                (Position::new(prev, self.buffer().line_len(prev)), Position::new(to.line, self.buffer().line_len(to.line)))
+            } else {
+                (Position::new(0, 0), Position::new(to.line, self.buffer().line_len(to.line)))
+            };
+            (lines.join("\n") + "\n", range)
+        } else {
+            let start = self.buffer().line_col_to_char(from.line, from.col);
+            let end = self.buffer().line_col_to_char(to.line, to.col);
+            (self.buffer().slice(start, end).chars().collect(), (from, to))
+        };
 
-        for cursor in self.cursors_mut().all_mut() {
-            let new_line = cursor.line.saturating_sub(page);
-            let line_len = line_lens.get(new_line).copied().unwrap_or(0);
-            let new_col = cursor.desired_col.min(line_len);
-            cursor.move_to(new_line, new_col, extend_selection);
+        let yank = operator == Operator::Yank;
+        self.vim.store(register, text, linewise, yank);
+        if yank {
+            let col = if linewise { self.cursor().col } else { from.col };
+            self.cursor_mut().set(from.line, col);
+            if linewise && to.line > from.line {
+                self.messages.info(format!("{} lines yanked", to.line - from.line + 1));
+            }
+            return;
         }
-        self.cursors_mut().merge_overlapping();
-    }
 
-    fn page_down(&mut self, extend_selection: bool) {
-        let page = self.screen.rows.saturating_sub(2) as usize;
-        let line_count = self.buffer().line_count();
-        let max_line = line_count.saturating_sub(1);
-        let line_lens: Vec<usize> = (0..line_count).map(|l| self.buffer().line_len(l)).collect();
-
-        for cursor in self.cursors_mut().all_mut() {
-            let new_line = (cursor.line + page).min(max_line);
-            let line_len = line_lens.get(new_line).copied().unwrap_or(0);
-            let new_col = cursor.desired_col.min(line_len);
-            cursor.move_to(new_line, new_col, extend_selection);
+        let cursor = self.cursor_mut();
+        cursor.move_to(range.0.line, range.0.col, false);
+        cursor.move_to(range.1.line, range.1.col, true);
+        self.delete_selection();
+        if linewise && operator == Operator::Delete {
+            let line = from.line.min(self.buffer().line_count().saturating_sub(1));
+            self.vim_motion(Motion::Line(line + 1), false);
+        }
+        if operator == Operator::Change {
+            self.vim.mode = VimMode::Insert;
+        }
+    }
+
+    fn vim_put(&mut self, before: bool, count: usize, register: Option<char>) {
+        let Some(contents) = self.vim.register(register).cloned() else {
+            self.messages.info("Register is empty".to_string());
+            return;
+        };
+        let text = contents.text.repeat(count);
+        let (line, col) = (self.cursor().line, self.cursor().col);
+        self.cursor_mut().clear_selection();
+        if contents.linewise {
+            let last_line = self.buffer().line_count().saturating_sub(1);
+            let target = if before { line } else { line + 1 };
+            if target > last_line {
+                // Below the last line there's no line start to insert at
+                let len = self.buffer().line_len(last_line);
+                self.cursor_mut().set(last_line, len);
+                self.insert_text_single(&format!("\n{}", text.strip_suffix('\n').unwrap_or(&text)));
+            } else {
+                self.cursor_mut().set(target, 0);
+                self.insert_text_single(&text);
+            }
+            self.vim_motion(Motion::Line(target.min(self.buffer().line_count() - 1) + 1), false);
+        } else {
+            let len = self.buffer().line_len(line);
+            let at = if before || len == 0 { col } else { (col + 1).min(len) };
+            self.cursor_mut().set(line, at);
+            self.insert_text_single(&text);
+            self.move_left(false);
+        }
+        self.history_mut().maybe_break_group();
+    }
+
+    fn vim_insert(&mut self, at: InsertAt) {
+        let line = self.cursor().line;
+        let len = self.buffer().line_len(line);
+        let indent: String = self.buffer().line_str(line).unwrap_or_default()
+            .chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+        match at {
+            InsertAt::Cursor => {}
+            InsertAt::AfterCursor => {
+                let col = (self.cursor().col + 1).min(len);
+                self.cursor_mut().set(line, col);
+            }
+            InsertAt::FirstNonBlank => self.cursor_mut().set(line, indent.chars().count()),
+            InsertAt::LineEnd => self.cursor_mut().set(line, len),
+            InsertAt::LineBelow => {
+                self.cursor_mut().set(line, len);
+                self.insert_text_single(&format!("\n{}", indent));
+            }
+            InsertAt::LineAbove => {
+                self.cursor_mut().set(line, 0);
+                self.insert_text_single(&format!("{}\n", indent));
+                self.cursor_mut().set(line, indent.chars().count());
+            }
+        }
+        self.vim.mode = VimMode::Insert;
+    }
+
+    /// Handle a key, recording it into the keyboard macro under way. The
+    /// keys that start and stop a recording aren't part of it, and nothing
+    /// is recorded while a macro replays.
+    fn handle_key_with_mods(&mut self, key: Key, mods: Modifiers) -> Result<()> {
+        let recording = self.macros.recording().is_some() && !self.macros.replaying;
+        let in_palette = matches!(self.prompt, PromptState::CommandPalette { .. });
+        let result = self.dispatch_key(key.clone(), mods);
+        if recording && self.macros.recording().is_some() {
+            let in_palette = in_palette || matches!(self.prompt, PromptState::CommandPalette { .. });
+            self.macros.record(key, mods, in_palette);
+        }
+        result
+    }
+
+    fn dispatch_key(&mut self, key: Key, mods: Modifiers) -> Result<()> {
+        // Handle Ctrl+F/Ctrl+R specially - they can toggle/switch even when in FindReplace prompt
+        if let PromptState::FindReplace { .. } = &self.prompt {
+            match (&key, &mods) {
+                (Key::Char('f'), Modifiers { ctrl: true, .. }) => {
+                    self.open_find();
+                    return Ok(());
+                }
+                (Key::Char('r'), Modifiers { ctrl: true, .. }) => {
+                    self.open_replace();
+                    return Ok(());
+                }
+                // Alt+I: toggle case insensitivity
+                (Key::Char('i'), Modifiers { alt: true, .. }) => {
+                    self.toggle_case_sensitivity();
+                    return Ok(());
+                }
+                // Alt+X: toggle regex mode
+                (Key::Char('x'), Modifiers { alt: true, .. }) => {
+                    self.toggle_regex_mode();
+                    return Ok(());
+                }
+                // Alt+Enter or Ctrl+Shift+Enter: replace all
+                (Key::Enter, Modifiers { alt: true, .. }) |
+                (Key::Enter, Modifiers { ctrl: true, shift: true, .. }) => {
+                    self.replace_all();
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        // Live grep: Alt+X switches between text and regex
+        if let (PromptState::LiveGrep { regex, .. }, Key::Char('x'), true) = (&mut self.prompt, &key, mods.alt) {
+            *regex = !*regex;
+            self.live_grep_changed();
+            return Ok(());
+        }
+
+        // Quick open: Ctrl+V / Ctrl+S open the selection in a split, Ctrl+T in a new tab
+        if let PromptState::QuickOpen { ref filtered, selected_index, .. } = self.prompt {
+            let target = match (&key, &mods) {
+                (Key::Char('v'), Modifiers { ctrl: true, .. }) => Some(OpenTarget::VerticalSplit),
+                (Key::Char('s'), Modifiers { ctrl: true, .. }) => Some(OpenTarget::HorizontalSplit),
+                (Key::Char('t'), Modifiers { ctrl: true, .. }) => Some(OpenTarget::NewTab),
+                _ => None,
+            };
+            if let Some(target) = target {
+                let chosen = filtered.get(selected_index).cloned();
+                self.prompt = PromptState::None;
+                if let Some(path) = chosen {
+                    self.quick_open_file(&path, target)?;
+                }
+                return Ok(());
+            }
+        }
+
+        // Handle active prompts first
+        if self.prompt != PromptState::None {
+            return self.handle_prompt_key(key);
+        }
+
+        // Focus-based routing for server manager
+        if self.focus == Focus::ServerManager && self.server_manager.visible {
+            return self.handle_server_manager_key(key, mods);
+        }
+
+        // A leftover prompt question goes on any key; Escape also dismisses
+        // queued messages, errors included
+        self.messages.clear_prompt();
+        if key == Key::Escape {
+            self.messages.dismiss();
+        }
+
+        let command = self.workspace.keymap.lookup(&key, &mods).map(str::to_string);
+
+        // Toggle fuss mode: Ctrl+B or F3 (global shortcut that sets focus)
+        if command.as_deref() == Some("toggle-explorer") {
+            self.toggle_fuss_mode();
+            return Ok(());
+        }
+
+        // Focus-based routing for fuss mode
+        if self.focus == Focus::FussMode && self.workspace.fuss.active {
+            return self.handle_fuss_key(key, mods);
+        }
+
+        // Handle completion popup navigation when visible
+        if self.lsp_state.completion_visible {
+            match (&key, &mods) {
+                // Navigate up in completion list
+                (Key::Up, _) => {
+                    if self.lsp_state.completion_index > 0 {
+                        self.lsp_state.completion_index -= 1;
+                    } else {
+                        // Wrap to bottom
+                        self.lsp_state.completion_index = self.lsp_state.completions.len().saturating_sub(1);
+                    }
+                    return Ok(());
+                }
+                // Navigate down in completion list
+                (Key::Down, _) => {
+                    if self.lsp_state.completion_index < self.lsp_state.completions.len().saturating_sub(1) {
+                        self.lsp_state.completion_index += 1;
+                    } else {
+                        // Wrap to top
+                        self.lsp_state.completion_index = 0;
+                    }
+                    return Ok(());
+                }
+                // Select completion with Enter or Tab
+                (Key::Enter, _) | (Key::Tab, _) => {
+                    self.accept_completion();
+                    return Ok(());
+                }
+                // Dismiss completion popup with Escape
+                (Key::Escape, _) => {
+                    self.dismiss_completion();
+                    return Ok(());
+                }
+                // Backspace: remove from filter (if any) and continue
+                (Key::Backspace, _) => {
+                    if !self.lsp_state.completion_filter.is_empty() {
+                        self.lsp_state.completion_filter.pop();
+                        self.filter_completions();
+                    } else {
+                        // No filter left, dismiss popup
+                        self.dismiss_completion();
+                    }
+                    // Continue to normal backspace handling
+                }
+                // Character input: add to filter and continue typing
+                (Key::Char(c), Modifiers { ctrl: false, alt: false, .. }) => {
+                    self.lsp_state.completion_filter.push(*c);
+                    self.filter_completions();
+                    // If no matches left, dismiss
+                    if self.lsp_state.completions.is_empty() {
+                        self.dismiss_completion();
+                    }
+                    // Continue to normal character handling
+                }
+                // Any other key dismisses popup
+                _ => {
+                    self.dismiss_completion();
+                }
+            }
+        }
+
+        // Escape closes signature help before anything else
+        if matches!(key, Key::Escape) && self.lsp_state.signature_help.is_some() {
+            self.dismiss_signature_help();
+            return Ok(());
+        }
+
+        // Dismiss hover popup on any key press
+        if self.lsp_state.hover_visible {
+            self.lsp_state.hover_visible = false;
+            self.lsp_state.hover = None;
+            // Let Escape just dismiss the popup without doing anything else
+            if matches!(key, Key::Escape) {
+                return Ok(());
+            }
+        }
+
+        // A snippet being filled in takes Tab, Shift+Tab and typing in its tabstops
+        if self.snippet.is_some() && self.handle_snippet_key(&key, &mods) {
+            self.on_buffer_edit();
+            self.scroll_to_cursor();
+            return Ok(());
+        }
+
+        // Vim emulation sees text area keys before the keymap does
+        let vim_action = if self.workspace.config.vim_mode { self.vim.handle_key(&key, &mods) } else { None };
+        if let Some(action) = vim_action {
+            self.history_mut().maybe_break_group();
+            self.run_vim_action(action);
+            self.on_buffer_edit();
+            self.scroll_to_cursor();
+            return Ok(());
+        }
+
+        // Virtual documents (diffs, logs) and read-only workspaces
+        if let Some(reason) = self.read_only_reason().filter(|_| is_edit_key(&key, &mods)) {
+            self.messages.info(reason.to_string());
+            return Ok(());
+        }
+
+        // Break undo group on any non-character key (movement, commands, etc.)
+        // This ensures each "typing session" is its own undo unit
+        let is_typing = matches!(
+            (&key, &mods),
+            (Key::Char(_), Modifiers { ctrl: false, alt: false, .. })
+        );
+        if !is_typing {
+            self.history_mut().maybe_break_group();
+        }
+
+        if let Some(command) = command {
+            // Ctrl+Z doubles as suspend when configured
+            if command == "undo" && key == Key::Char('z') && self.workspace.config.ctrl_z_suspends {
+                self.suspend()?;
+            } else {
+                self.execute_command(&command);
+            }
+        } else {
+            // Keys without a binding: typing, navigation and basic editing
+            match (&key, &mods) {
+                // Escape: clear selection and collapse to single cursor
+                (Key::Escape, _) => {
+                    if self.cursors().len() > 1 {
+                        self.cursors_mut().collapse_to_primary();
+                    } else {
+                        self.cursors_mut().primary_mut().clear_selection();
+                    }
+                    self.dismiss_ghost_text();
+                }
+
+                // === Block selection ===
+                (Key::Up, Modifiers { shift: true, .. }) if self.block_select => self.extend_block(-1, 0),
+                (Key::Down, Modifiers { shift: true, .. }) if self.block_select => self.extend_block(1, 0),
+                (Key::Left, Modifiers { shift: true, .. }) if self.block_select => self.extend_block(0, -1),
+                (Key::Right, Modifiers { shift: true, .. }) if self.block_select => self.extend_block(0, 1),
+
+                // === Movement with selection ===
+                (Key::Up, Modifiers { shift, .. }) => {
+                    self.move_up(*shift);
+                    self.validate_ghost_text_position();
+                }
+                (Key::Down, Modifiers { shift, .. }) => {
+                    self.move_down(*shift);
+                    self.validate_ghost_text_position();
+                }
+                (Key::Left, Modifiers { shift, .. }) => {
+                    self.move_left(*shift);
+                    self.validate_ghost_text_position();
+                }
+                (Key::Right, Modifiers { shift, .. }) => {
+                    self.move_right(*shift);
+                    self.validate_ghost_text_position();
+                }
+
+                // Home/End
+                (Key::Home, Modifiers { shift, .. }) => {
+                    self.move_home(*shift);
+                    self.validate_ghost_text_position();
+                }
+                (Key::End, Modifiers { shift, .. }) => {
+                    self.move_end(*shift);
+                    self.validate_ghost_text_position();
+                }
+
+                // Page movement
+                (Key::PageUp, Modifiers { shift, .. }) => {
+                    self.page_up(*shift);
+                    self.validate_ghost_text_position();
+                }
+                (Key::PageDown, Modifiers { shift, .. }) => {
+                    self.page_down(*shift);
+                    self.validate_ghost_text_position();
+                }
+
+                // === Editing ===
+                (Key::Char(c), Modifiers { ctrl: false, alt: false, .. }) => {
+                    self.insert_char(*c);
+                    match c {
+                        '(' | ',' => self.lsp_signature_help(),
+                        ')' => self.dismiss_signature_help(),
+                        _ => {}
+                    }
+                }
+                (Key::Enter, _) => {
+                    self.insert_newline();
+                    self.dismiss_ghost_text();
+                }
+                (Key::Backspace, _) => {
+                    self.delete_backward();
+                    self.update_ghost_text();
+                }
+                (Key::Delete, _) => {
+                    self.delete_forward();
+                    self.dismiss_ghost_text();
+                }
+                (Key::Tab, _) => {
+                    // Accept ghost text if visible and no selection
+                    if self.ghost_text.suggestion.is_some() && !self.cursor().has_selection() {
+                        self.accept_ghost_text();
+                    } else if !self.expand_snippet() && !self.nest_list_item(true) {
+                        self.insert_tab();
+                    }
+                }
+                (Key::BackTab, _) if !self.nest_list_item(false) => self.dedent(),
+
+                _ => {}
+            }
+        }
+
+        // Check if buffer was edited and needs backup
+        self.on_buffer_edit();
+
+        self.scroll_to_cursor();
+        Ok(())
+    }
+
+    // === Cursor helpers ===
+
+    /// Get reference to primary cursor
+    fn cursor(&self) -> &Cursor {
+        self.cursors().primary()
+    }
+
+    /// Get mutable reference to primary cursor
+    fn cursor_mut(&mut self) -> &mut Cursor {
+        self.cursors_mut().primary_mut()
+    }
+
+    // === Multi-cursor operations ===
+
+    /// Add a cursor on the line above the topmost cursor
+    fn add_cursor_above(&mut self) {
+        // Find the topmost cursor
+        let topmost = self.cursors().all().iter().map(|c| c.line).min().unwrap_or(0);
+        let col = self.cursors().primary().col;
+
+        if topmost > 0 {
+            let new_line = topmost - 1;
+            let line_len = self.buffer().line_len(new_line);
+            let new_col = col.min(line_len);
+            self.cursors_mut().add(new_line, new_col);
+        }
+    }
+
+    /// Add a cursor on the line below the bottommost cursor
+    fn add_cursor_below(&mut self) {
+        // Find the bottommost cursor
+        let bottommost = self.cursors().all().iter().map(|c| c.line).max().unwrap_or(0);
+        let col = self.cursors().primary().col;
+        let line_count = self.buffer().line_count();
+
+        if bottommost + 1 < line_count {
+            let new_line = bottommost + 1;
+            let line_len = self.buffer().line_len(new_line);
+            let new_col = col.min(line_len);
+            self.cursors_mut().add(new_line, new_col);
+        }
+    }
+
+    /// Remove the most recently added cursor
+    fn remove_last_cursor(&mut self) {
+        if self.cursors_mut().remove_last_added() {
+            let count = self.cursors().len();
+            self.messages.info(format!("{} cursor{}", count, if count == 1 { "" } else { "s" }));
+        } else {
+            self.messages.info("Only one cursor".to_string());
+        }
+    }
+
+    /// Make the next (or previous) cursor in document order primary
+    fn rotate_primary_cursor(&mut self, forward: bool) {
+        if self.cursors().is_single() {
+            self.messages.info("Only one cursor".to_string());
+            return;
+        }
+        self.cursors_mut().rotate_primary(forward);
+        self.scroll_to_cursor();
+    }
+
+    /// Switch Shift+arrows and dragging between selecting text and
+    /// selecting a block
+    fn toggle_block_select(&mut self) {
+        self.block_select = !self.block_select;
+        let state = if self.block_select { "on" } else { "off" };
+        self.messages.info(format!("Block selection {}", state));
+    }
+
+    /// Select the block between the `anchor` and `head` corners, a cursor
+    /// on each line
+    fn select_block(&mut self, anchor: Position, head: Position) {
+        let top = anchor.line.min(head.line);
+        let lens: Vec<usize> = (top..=anchor.line.max(head.line)).map(|line| self.buffer().line_len(line)).collect();
+        self.cursors_mut().set_block(anchor, head, |line| lens[line - top]);
+    }
+
+    /// Move the block selection's head corner by `down` lines and `right`
+    /// columns, starting a block at the cursor if there's none. The head
+    /// stops at the end of the longest line the block spans.
+    fn extend_block(&mut self, down: isize, right: isize) {
+        // A block the cursor has since moved away from is left behind
+        let here = self.cursor_pos();
+        let (anchor, head) = self.cursors().block()
+            .filter(|(_, head)| head.line == here.line && head.col.min(self.buffer().line_len(head.line)) == here.col)
+            .unwrap_or((here, here));
+        let last_line = self.buffer().line_count().saturating_sub(1);
+        let line = head.line.saturating_add_signed(down).min(last_line);
+        let widest = (anchor.line.min(line)..=anchor.line.max(line)).map(|l| self.buffer().line_len(l)).max().unwrap_or(0);
+        let col = head.col.saturating_add_signed(right).min(widest);
+        self.select_block(anchor, Position::new(line, col));
+    }
+
+    /// Toggle cursor at position (for Ctrl+click)
+    /// Returns true if cursor was added, false if removed
+    fn toggle_cursor_at(&mut self, line: usize, col: usize) -> bool {
+        self.cursors_mut().toggle_at(line, col)
+    }
+
+    // === Movement ===
+
+    fn move_up(&mut self, extend_selection: bool) {
+        if let Some(width) = self.wrap_width() {
+            let targets: Vec<_> = self.cursors().all().iter()
+                .map(|c| self.wrapped_row_target(c, width, false))
+                .collect();
+            for (cursor, target) in self.cursors_mut().all_mut().iter_mut().zip(targets) {
+                match target {
+                    Some((line, col, desired_col)) => {
+                        cursor.move_to(line, col, extend_selection);
+                        cursor.desired_col = desired_col;
+                    }
+                    None => cursor.move_to(0, 0, extend_selection),
+                }
+            }
+            self.cursors_mut().merge_overlapping();
+            return;
+        }
+
+        // Target lines and their lengths, before borrowing cursors mutably;
+        // folded blocks count as a single line
+        let buffer = self.buffer();
+        let targets: Vec<Option<(usize, usize)>> = self.cursors().all().iter()
+            .map(|c| buffer.prev_visible_line(c.line).map(|line| (line, buffer.line_len(line))))
+            .collect();
+
+        // Apply to all cursors
+        for (cursor, target) in self.cursors_mut().all_mut().iter_mut().zip(targets) {
+            if let Some((new_line, line_len)) = target {
+                let new_col = cursor.desired_col.min(line_len);
+                cursor.move_to(new_line, new_col, extend_selection);
+            } else {
+                // On first line, move to start of line
+                cursor.move_to(0, 0, extend_selection);
+            }
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    fn move_down(&mut self, extend_selection: bool) {
+        if let Some(width) = self.wrap_width() {
+            let targets: Vec<_> = self.cursors().all().iter()
+                .map(|c| self.wrapped_row_target(c, width, true))
+                .collect();
+            let line_ends: Vec<usize> = self.cursors().all().iter()
+                .map(|c| self.buffer().line_len(c.line))
+                .collect();
+            for ((cursor, target), line_end) in self.cursors_mut().all_mut().iter_mut().zip(targets).zip(line_ends) {
+                match target {
+                    Some((line, col, desired_col)) => {
+                        cursor.move_to(line, col, extend_selection);
+                        cursor.desired_col = desired_col;
+                    }
+                    None => cursor.move_to(cursor.line, line_end, extend_selection),
+                }
+            }
+            self.cursors_mut().merge_overlapping();
+            return;
+        }
+
+        let buffer = self.buffer();
+        let line_count = buffer.line_count();
+        // (target line, its length), or None on the last line
+        let targets: Vec<(Option<(usize, usize)>, usize)> = self.cursors().all().iter()
+            .map(|c| {
+                let next = buffer.next_visible_line(c.line);
+                ((next < line_count).then(|| (next, buffer.line_len(next))), buffer.line_len(c.line))
+            })
+            .collect();
+
+        for (cursor, (target, current_len)) in self.cursors_mut().all_mut().iter_mut().zip(targets) {
+            if let Some((new_line, line_len)) = target {
+                let new_col = cursor.desired_col.min(line_len);
+                cursor.move_to(new_line, new_col, extend_selection);
+            } else {
+                // On last line, move to end of line
+                cursor.move_to(cursor.line, current_len, extend_selection);
+            }
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    /// Where `cursor` lands one wrapped row up or down, as line, column and
+    /// the desired column that keeps it at the same screen column; None at
+    /// the top or bottom of the buffer
+    fn wrapped_row_target(&self, cursor: &Cursor, width: usize, down: bool) -> Option<(usize, usize, usize)> {
+        let buffer = self.buffer();
+        let row_starts = |line: usize| wrap::row_starts(&buffer.line_str(line).unwrap_or_default(), width);
+        let starts = row_starts(cursor.line);
+        let row = wrap::row_of(&starts, cursor.col);
+        let x = cursor.desired_col.saturating_sub(starts[row]);
+
+        let (line, starts, row) = if down {
+            if row + 1 < starts.len() {
+                (cursor.line, starts, row + 1)
+            } else {
+                let next = buffer.next_visible_line(cursor.line);
+                if next >= buffer.line_count() {
+                    return None;
+                }
+                (next, row_starts(next), 0)
+            }
+        } else if row > 0 {
+            (cursor.line, starts, row - 1)
+        } else {
+            let prev = buffer.prev_visible_line(cursor.line)?;
+            let starts = row_starts(prev);
+            let last = starts.len() - 1;
+            (prev, starts, last)
+        };
+        let col = (starts[row] + x).min(wrap::row_end(&starts, row, buffer.line_len(line)));
+        Some((line, col, starts[row] + x))
+    }
+
+    fn move_left(&mut self, extend_selection: bool) {
+        let buffer = self.buffer();
+        let prev_lines: Vec<Option<(usize, usize)>> = self.cursors().all().iter()
+            .map(|c| buffer.prev_visible_line(c.line).map(|line| (line, buffer.line_len(line))))
+            .collect();
+
+        for (cursor, prev_line) in self.cursors_mut().all_mut().iter_mut().zip(prev_lines) {
+            if cursor.col > 0 {
+                cursor.move_to(cursor.line, cursor.col - 1, extend_selection);
+                cursor.desired_col = cursor.col;
+            } else if let Some((new_line, new_col)) = prev_line {
+                cursor.move_to(new_line, new_col, extend_selection);
+                cursor.desired_col = cursor.col;
+            }
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    fn move_right(&mut self, extend_selection: bool) {
+        let buffer = self.buffer();
+        let line_count = buffer.line_count();
+        let next_lines: Vec<(usize, usize)> = self.cursors().all().iter()
+            .map(|c| (buffer.line_len(c.line), buffer.next_visible_line(c.line)))
+            .collect();
+
+        for (cursor, (line_len, next_line)) in self.cursors_mut().all_mut().iter_mut().zip(next_lines) {
+            if cursor.col < line_len {
+                cursor.move_to(cursor.line, cursor.col + 1, extend_selection);
+                cursor.desired_col = cursor.col;
+            } else if next_line < line_count {
+                cursor.move_to(next_line, 0, extend_selection);
+                cursor.desired_col = 0;
+            }
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    fn move_word_left(&mut self, extend_selection: bool) {
+        // Work out targets before borrowing cursors mutably
+        let targets: Vec<(usize, usize)> = self.cursors().all().iter()
+            .map(|c| self.word_left_target(c.line, c.col))
+            .collect();
+
+        for (cursor, (line, col)) in self.cursors_mut().all_mut().iter_mut().zip(targets) {
+            cursor.move_to(line, col, extend_selection);
+            cursor.desired_col = col;
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    /// Where moving a word left from (`line`, `col`) lands
+    fn word_left_target(&self, line: usize, col: usize) -> (usize, usize) {
+        let buffer = self.buffer();
+        let (mut line, mut col) = (line, col);
+
+        // If at start of line, go to end of previous line
+        if col == 0 && line > 0 {
+            line -= 1;
+            col = buffer.line_len(line);
+        }
+
+        if let Some(line_str) = buffer.line_str(line) {
+            let chars: Vec<char> = line_str.chars().collect();
+            if col > 0 {
+                col = col.min(chars.len());
+                // Skip whitespace
+                while col > 0 && chars.get(col - 1).map_or(false, |c| c.is_whitespace()) {
+                    col -= 1;
+                }
+                // Determine what kind of characters to skip based on char before cursor
+                if col > 0 {
+                    let prev_char = chars[col - 1];
+                    if is_word_char(prev_char) {
+                        // Skip word characters
+                        while col > 0 && chars.get(col - 1).map_or(false, |c| is_word_char(*c)) {
+                            col -= 1;
+                        }
+                    } else {
+                        // Skip punctuation/symbols
+                        while col > 0 && chars.get(col - 1).map_or(false, |c| !is_word_char(*c) && !c.is_whitespace()) {
+                            col -= 1;
+                        }
+                    }
+                }
+            }
+        }
+        (line, col)
+    }
+
+    fn move_word_right(&mut self, extend_selection: bool) {
+        let targets: Vec<(usize, usize)> = self.cursors().all().iter()
+            .map(|c| self.word_right_target(c.line, c.col))
+            .collect();
+
+        for (cursor, (line, col)) in self.cursors_mut().all_mut().iter_mut().zip(targets) {
+            cursor.move_to(line, col, extend_selection);
+            cursor.desired_col = col;
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    /// Where moving a word right from (`line`, `col`) lands
+    fn word_right_target(&self, line: usize, col: usize) -> (usize, usize) {
+        let buffer = self.buffer();
+        let (mut line, mut col) = (line, col);
+
+        // If at end of line, go to start of next line
+        if col >= buffer.line_len(line) && line + 1 < buffer.line_count() {
+            line += 1;
+            col = 0;
+        }
+
+        if let Some(line_str) = buffer.line_str(line) {
+            let chars: Vec<char> = line_str.chars().collect();
+            if col < chars.len() {
+                let curr_char = chars[col];
+                if is_word_char(curr_char) {
+                    // Skip word characters
+                    while col < chars.len() && chars.get(col).map_or(false, |c| is_word_char(*c)) {
+                        col += 1;
+                    }
+                } else if !curr_char.is_whitespace() {
+                    // Skip punctuation/symbols
+                    while col < chars.len() && chars.get(col).map_or(false, |c| !is_word_char(*c) && !c.is_whitespace()) {
+                        col += 1;
+                    }
+                }
+            }
+            // Skip whitespace
+            while col < chars.len() && chars.get(col).map_or(false, |c| c.is_whitespace()) {
+                col += 1;
+            }
+        }
+        (line, col)
+    }
+
+    fn move_home(&mut self, extend_selection: bool) {
+        for cursor in self.cursors_mut().all_mut() {
+            let line = cursor.line;
+            cursor.move_to(line, 0, extend_selection);
+            cursor.desired_col = 0;
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    fn smart_home(&mut self, extend_selection: bool) {
+        // Toggle between column 0 and first non-whitespace
+        let first_non_ws: Vec<Option<usize>> = self.cursors().all().iter()
+            .map(|c| {
+                let line_str = self.buffer().line_str(c.line)?;
+                Some(line_str.chars().position(|c| !c.is_whitespace()).unwrap_or(0))
+            })
+            .collect();
+
+        for (cursor, first_non_ws) in self.cursors_mut().all_mut().iter_mut().zip(first_non_ws) {
+            let line = cursor.line;
+            let col = cursor.col;
+            if let Some(first_non_ws) = first_non_ws {
+                let new_col = if col == first_non_ws || col == 0 {
+                    if col == 0 { first_non_ws } else { 0 }
+                } else {
+                    first_non_ws
+                };
+                cursor.move_to(line, new_col, extend_selection);
+                cursor.desired_col = new_col;
+            }
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    fn move_end(&mut self, extend_selection: bool) {
+        let line_lens: Vec<usize> = self.cursors().all().iter().map(|c| self.buffer().line_len(c.line)).collect();
+
+        for (cursor, line_len) in self.cursors_mut().all_mut().iter_mut().zip(line_lens) {
+            let line = cursor.line;
+            cursor.move_to(line, line_len, extend_selection);
+            cursor.desired_col = line_len;
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    fn page_up(&mut self, extend_selection: bool) {
+        let page = self.screen.rows.saturating_sub(2) as usize;
+        let targets: Vec<(usize, usize)> = self.cursors().all().iter()
+            .map(|c| {
+                let new_line = c.line.saturating_sub(page);
+                (new_line, self.buffer().line_len(new_line))
+            })
+            .collect();
+
+        for (cursor, (new_line, line_len)) in self.cursors_mut().all_mut().iter_mut().zip(targets) {
+            let new_col = cursor.desired_col.min(line_len);
+            cursor.move_to(new_line, new_col, extend_selection);
+        }
+        self.cursors_mut().merge_overlapping();
+    }
+
+    fn page_down(&mut self, extend_selection: bool) {
+        let page = self.screen.rows.saturating_sub(2) as usize;
+        let max_line = self.buffer().line_count().saturating_sub(1);
+        let targets: Vec<(usize, usize)> = self.cursors().all().iter()
+            .map(|c| {
+                let new_line = (c.line + page).min(max_line);
+                (new_line, self.buffer().line_len(new_line))
+            })
+            .collect();
+
+        for (cursor, (new_line, line_len)) in self.cursors_mut().all_mut().iter_mut().zip(targets) {
+            let new_col = cursor.desired_col.min(line_len);
+            cursor.move_to(new_line, new_col, extend_selection);
         }
         self.cursors_mut().merge_overlapping();
     }
@@ -3018,6 +6657,22 @@ impl Editor {
         self.cursor_mut().selecting = true;
     }
 
+    /// Select whole lines from `start` to `end`, the cursor at the `end`
+    /// side (a single cursor)
+    fn select_lines(&mut self, start: usize, end: usize) {
+        let (anchor_col, col) = if end >= start {
+            (0, self.buffer().line_len(end))
+        } else {
+            (self.buffer().line_len(start), 0)
+        };
+        self.cursors_mut().collapse_to_primary();
+        self.cursor_mut().set(end, col);
+        let cursor = self.cursor_mut();
+        cursor.anchor_line = start;
+        cursor.anchor_col = anchor_col;
+        cursor.selecting = true;
+    }
+
     fn select_word(&mut self) {
         // If primary cursor has a selection, find next occurrence and add cursor there
         if self.cursor().has_selection() {
@@ -3174,7 +6829,84 @@ impl Editor {
         }
 
         // No more occurrences found
-        self.message = Some("No more occurrences".to_string());
+        self.messages.info("No more occurrences".to_string());
+    }
+
+    /// Put a cursor and selection on every occurrence of the selected text
+    /// (or of the word at the cursor), the current one staying primary
+    fn select_all_occurrences(&mut self) {
+        if self.buffer().large {
+            self.messages.info("Not available in large files".to_string());
+            return;
+        }
+        if !self.cursor().has_selection() {
+            self.select_word();
+        }
+        let Some(selected) = self.get_selection_text().filter(|text| !text.is_empty()) else {
+            return;
+        };
+        let primary = self.cursor().selection_bounds();
+
+        // Char offsets of the matches, counted along from the previous one
+        let text = self.buffer().contents();
+        let (mut byte, mut char_idx) = (0, 0);
+        let mut matches = Vec::new();
+        for (at, _) in text.match_indices(&selected) {
+            char_idx += text[byte..at].chars().count();
+            byte = at;
+            matches.push(char_idx);
+            if matches.len() >= MAX_OCCURRENCE_CURSORS {
+                break;
+            }
+        }
+
+        self.cursors_mut().collapse_to_primary();
+        let len = selected.chars().count();
+        for start in matches {
+            let (start_line, start_col) = self.buffer().char_to_line_col(start);
+            let (end_line, end_col) = self.buffer().char_to_line_col(start + len);
+            if primary == Some((Position::new(start_line, start_col), Position::new(end_line, end_col))) {
+                continue;
+            }
+            self.cursors_mut().add_with_selection(end_line, end_col, start_line, start_col);
+        }
+        let count = self.cursors().len();
+        self.messages.info(format!("{} occurrence{} selected", count, if count == 1 { "" } else { "s" }));
+    }
+
+    /// Split each multi-line selection into a cursor per line, at the line's
+    /// end and selecting its part of the selection
+    fn add_cursors_to_line_ends(&mut self) {
+        let mut pieces: Vec<(usize, usize, usize)> = Vec::new();
+        let mut split = false;
+        for cursor in self.cursors().all() {
+            let Some((start, end)) = cursor.selection_bounds().filter(|(start, end)| start.line < end.line) else {
+                pieces.push((cursor.line, cursor.anchor_col, cursor.col));
+                continue;
+            };
+            // A selection ending at the start of a line doesn't take it in
+            let last = if end.col == 0 { end.line - 1 } else { end.line };
+            split = true;
+            for line in start.line..=last {
+                let from = if line == start.line { start.col } else { 0 };
+                let to = if line == end.line { end.col } else { self.buffer().line_len(line) };
+                pieces.push((line, from, to));
+            }
+        }
+        if !split {
+            self.messages.info("No multi-line selection to split".to_string());
+            return;
+        }
+
+        self.cursors_mut().collapse_to_primary();
+        let (line, from, to) = pieces[0];
+        let cursor = self.cursor_mut();
+        *cursor = Cursor::at(line, to);
+        cursor.anchor_col = from;
+        cursor.selecting = from != to;
+        for &(line, from, to) in &pieces[1..] {
+            self.cursors_mut().add_with_selection(line, to, line, from);
+        }
     }
 
     // === Bracket/Quote Operations ===
@@ -3263,22 +6995,124 @@ impl Editor {
         }
     }
 
-    fn remove_surrounding(&mut self) {
-        // Remove surrounding quotes OR brackets (whichever is innermost/closest)
-        let cursor_idx = self.buffer().line_col_to_char(self.cursor().line, self.cursor().col);
-
-        // Find both surrounding quotes and brackets
-        let quotes = self.buffer().find_surrounding_quotes(self.cursor().line, self.cursor().col);
-        let brackets = self.buffer().find_surrounding_brackets(self.cursor().line, self.cursor().col);
+    /// The innermost quotes or brackets around `line`/`col`, as (open index,
+    /// close index, open char, close char)
+    fn surrounding_pair(&self, line: usize, col: usize) -> Option<(usize, usize, char, char)> {
+        let quotes = self.buffer().find_surrounding_quotes(line, col);
+        let brackets = self.buffer().find_surrounding_brackets(line, col);
 
         // Pick whichever has the closer opening (innermost)
-        let (open_idx, close_idx, open_char, close_char) = match (quotes, brackets) {
+        match (quotes, brackets) {
             (Some((qo, qc, qch)), Some((bo, bc, bop, bcl))) => {
-                if qo > bo { (qo, qc, qch, qch) } else { (bo, bc, bop, bcl) }
+                Some(if qo > bo { (qo, qc, qch, qch) } else { (bo, bc, bop, bcl) })
             }
-            (Some((qo, qc, qch)), None) => (qo, qc, qch, qch),
-            (None, Some((bo, bc, bop, bcl))) => (bo, bc, bop, bcl),
-            (None, None) => return,
+            (Some((qo, qc, qch)), None) => Some((qo, qc, qch, qch)),
+            (None, Some(brackets)) => Some(brackets),
+            (None, None) => None,
+        }
+    }
+
+    /// Character range `[start, end)` inside (or `around`, delimiters
+    /// included) the innermost pair around each cursor; None for cursors
+    /// not inside one
+    fn pair_object_ranges(&self, around: bool) -> Vec<Option<(usize, usize)>> {
+        self.cursors()
+            .all()
+            .iter()
+            .map(|cursor| {
+                let (open, close, _, _) = self.surrounding_pair(cursor.line, cursor.col)?;
+                Some(if around { (open, close + 1) } else { (open + 1, close) })
+            })
+            .collect()
+    }
+
+    /// Select inside (or around) the nearest quotes or brackets at every
+    /// cursor. Selecting inside again when that's already the selection
+    /// takes the delimiters in too.
+    fn select_pair(&mut self, around: bool) {
+        let inside = self.pair_object_ranges(false);
+        let already = inside.iter().zip(self.cursors().all()).all(|(range, cursor)| {
+            range.is_some_and(|(start, end)| {
+                cursor.selection_bounds().is_some_and(|(from, to)| {
+                    self.buffer().line_col_to_char(from.line, from.col) == start
+                        && self.buffer().line_col_to_char(to.line, to.col) == end
+                })
+            })
+        });
+        let ranges = if around || already { self.pair_object_ranges(true) } else { inside };
+        if ranges.iter().all(Option::is_none) {
+            self.messages.info("No quotes or brackets around the cursor".to_string());
+            return;
+        }
+
+        let positions: Vec<_> = ranges
+            .iter()
+            .map(|range| range.map(|(start, end)| (self.buffer().char_to_line_col(start), self.buffer().char_to_line_col(end))))
+            .collect();
+        for (cursor, range) in self.cursors_mut().all_mut().iter_mut().zip(positions) {
+            if let Some(((start_line, start_col), (end_line, end_col))) = range {
+                cursor.anchor_line = start_line;
+                cursor.anchor_col = start_col;
+                cursor.line = end_line;
+                cursor.col = end_col;
+                cursor.desired_col = end_col;
+                cursor.selecting = true;
+            }
+        }
+    }
+
+    /// Delete inside (or around) the nearest quotes or brackets at every
+    /// cursor, as one undo step
+    fn delete_pair(&mut self, around: bool) {
+        let ranges = self.pair_object_ranges(around);
+        let mut edits: Vec<(usize, usize, String)> = ranges.iter().flatten().map(|&(start, end)| (start, end, String::new())).collect();
+        edits.sort_by_key(|&(start, _, _)| start);
+        edits.dedup_by(|later, earlier| later.0 < earlier.1);
+        if edits.is_empty() {
+            self.messages.info("No quotes or brackets around the cursor".to_string());
+            return;
+        }
+
+        let indices: Vec<usize> = self
+            .cursors()
+            .all()
+            .iter()
+            .map(|c| self.buffer().line_col_to_char(c.line, c.col))
+            .collect();
+        let cursors_before = self.all_cursor_positions();
+        let cursor_before = self.cursor_pos();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(cursors_before);
+        for &(start, end, _) in edits.iter().rev() {
+            let deleted: String = self.buffer().slice(start, end).chars().collect();
+            self.buffer_mut().delete(start, end);
+            self.history_mut().record_delete(start, deleted, cursor_before, cursor_before);
+        }
+
+        let moved: Vec<(usize, usize)> = indices
+            .iter()
+            .map(|&idx| self.buffer().char_to_line_col(map_snippet_pos(idx, &edits, false)))
+            .collect();
+        for (cursor, (line, col)) in self.cursors_mut().all_mut().iter_mut().zip(moved) {
+            cursor.clear_selection();
+            cursor.line = line;
+            cursor.col = col;
+            cursor.desired_col = col;
+        }
+        let cursors_after = self.all_cursor_positions();
+        self.history_mut().set_cursors_after(cursors_after);
+        self.history_mut().end_group();
+        self.cursors_mut().merge_overlapping();
+        let first_line = self.buffer().char_to_line_col(edits[0].0).0;
+        self.invalidate_highlight_cache(first_line);
+        self.invalidate_bracket_cache();
+    }
+
+    fn remove_surrounding(&mut self) {
+        // Remove surrounding quotes OR brackets (whichever is innermost/closest)
+        let cursor_idx = self.buffer().line_col_to_char(self.cursor().line, self.cursor().col);
+        let Some((open_idx, close_idx, open_char, close_char)) = self.surrounding_pair(self.cursor().line, self.cursor().col) else {
+            return;
         };
 
         let cursor_before = self.cursor_pos();
@@ -3392,6 +7226,13 @@ impl Editor {
             self.insert_text_single(text);
             return;
         }
+        self.insert_each(&vec![text; self.cursors().len()]);
+    }
+
+    /// Insert `texts[i]` at the `i`th cursor in document order, replacing
+    /// any selections first
+    fn insert_each(&mut self, texts: &[&str]) {
+        self.delete_selections_multi();
 
         // Multi-cursor: compute absolute character indices FIRST from a frozen view of the buffer.
         // Then sort by ASCENDING char index, apply edits from start to end,
@@ -3417,12 +7258,11 @@ impl Editor {
         }
         self.invalidate_bracket_cache();
 
-        // Record all cursor positions before the operation
+        // Record all cursor positions before the operation. Keystrokes join the
+        // current typing session; the group is committed on the next non-typing key.
         let cursors_before = self.all_cursor_positions();
-        self.history_mut().begin_group();
-        self.history_mut().set_cursors_before(cursors_before);
+        self.history_mut().begin_typing_session(cursors_before);
 
-        let text_char_count = text.chars().count();
         let cursor_before = self.cursor_pos();
 
         // Step 3: Apply inserts from start to end, tracking cumulative offset
@@ -3430,6 +7270,8 @@ impl Editor {
         let mut new_positions: Vec<(usize, usize, usize)> = Vec::new(); // (cursor_idx, line, col)
 
         for (cursor_idx, original_char_idx) in cursor_char_indices {
+            let text = texts[cursor_idx];
+            let text_char_count = text.chars().count();
             // Adjust position by cumulative offset from previous inserts
             let adjusted_char_idx = original_char_idx + cumulative_offset;
 
@@ -3456,7 +7298,6 @@ impl Editor {
         // Record all cursor positions after the operation
         let cursors_after = self.all_cursor_positions();
         self.history_mut().set_cursors_after(cursors_after);
-        self.history_mut().end_group();
         self.cursors_mut().merge_overlapping();
     }
 
@@ -3501,6 +7342,9 @@ impl Editor {
         // For multi-cursor, use simple insert (skip auto-pair complexity for now)
         if self.cursors().len() > 1 {
             self.insert_text_multi(&c.to_string());
+            if matches!(c, '}' | ')' | ']') {
+                self.outdent_closing_brackets();
+            }
             self.dismiss_ghost_text();
             return;
         }
@@ -3509,7 +7353,9 @@ impl Editor {
         // Check for auto-pair closing: if typing a closing bracket/quote
         // and the next char is the same, just move cursor right
         if let Some(next_char) = self.char_at_cursor() {
-            if c == next_char && (c == ')' || c == ']' || c == '}' || c == '"' || c == '\'' || c == '`') {
+            let closes = matches!(c, ')' | ']' | '}' | '"' | '\'' | '`')
+                || self.buffer_entry().highlighter.closes_pair(c);
+            if c == next_char && closes {
                 self.cursor_mut().col += 1;
                 self.cursor_mut().desired_col = self.cursor().col;
                 self.dismiss_ghost_text();
@@ -3525,12 +7371,12 @@ impl Editor {
             '"' => Some('"'),
             '\'' => Some('\''),
             '`' => Some('`'),
-            _ => None,
+            _ => self.buffer_entry().highlighter.auto_pair(c),
         };
 
         if let Some(close) = pair {
             // For quotes, only auto-pair if not inside a word
-            let should_pair = if c == '"' || c == '\'' || c == '`' {
+            let should_pair = if close == c {
                 // Don't auto-pair if previous char is alphanumeric (e.g., typing apostrophe in "don't")
                 let prev_char = if self.cursor().col > 0 {
                     let idx = self.buffer().line_col_to_char(self.cursor().line, self.cursor().col);
@@ -3561,6 +7407,9 @@ impl Editor {
         }
 
         self.insert_text(&c.to_string());
+        if matches!(c, '}' | ')' | ']') {
+            self.outdent_closing_brackets();
+        }
 
         // Update ghost text after alphanumeric input
         if c.is_alphanumeric() || c == '_' {
@@ -3570,6 +7419,65 @@ impl Editor {
         }
     }
 
+    /// A closing bracket just typed first on its line, at any cursor, takes
+    /// the indentation of the line its opening bracket is on. The change
+    /// joins the typing's undo group, so one undo takes back both.
+    fn outdent_closing_brackets(&mut self) {
+        // Bottom to top, so the char indices of lines above stay valid
+        let mut order: Vec<usize> = (0..self.cursors().len()).collect();
+        order.sort_by_key(|&i| {
+            let c = &self.cursors().all()[i];
+            std::cmp::Reverse((c.line, c.col))
+        });
+        let mut changed = false;
+        for i in order {
+            let (line_idx, col) = (self.cursors().all()[i].line, self.cursors().all()[i].col);
+            let Some((leading, target)) = self.closing_bracket_indent(line_idx, col) else {
+                continue;
+            };
+            let (old_width, new_width) = (leading.chars().count(), target.chars().count());
+            let start = self.buffer().line_col_to_char(line_idx, 0);
+            let before = Position::new(line_idx, col);
+            let after = Position::new(line_idx, col - old_width + new_width);
+            self.invalidate_highlight_cache(line_idx);
+            self.buffer_mut().delete(start, start + old_width);
+            self.history_mut().record_delete(start, leading, before, before);
+            self.buffer_mut().insert(start, &target);
+            self.history_mut().record_insert(start, target, before, after);
+            self.invalidate_bracket_cache();
+            // Cursors further along the same line move with the text
+            for cursor in self.cursors_mut().all_mut().iter_mut().filter(|c| c.line == line_idx && c.col >= col) {
+                cursor.col = cursor.col - old_width + new_width;
+                cursor.desired_col = cursor.col;
+            }
+            changed = true;
+        }
+        if changed {
+            let cursors_after = self.all_cursor_positions();
+            self.history_mut().set_cursors_after(cursors_after);
+        }
+    }
+
+    /// For a closing bracket just typed before column `col` of line
+    /// `line_idx` with only whitespace before it: that whitespace and the
+    /// indentation of the opening bracket's line, when they differ
+    fn closing_bracket_indent(&self, line_idx: usize, col: usize) -> Option<(String, String)> {
+        let line = self.buffer().line_str(line_idx)?;
+        let leading: String = line.chars().take(col.checked_sub(1)?).collect();
+        if leading.chars().any(|c| c != ' ' && c != '\t') {
+            return None;
+        }
+        let (open_line, _) = self.buffer().find_matching_bracket(line_idx, col - 1)?;
+        let target: String = self
+            .buffer()
+            .line_str(open_line)
+            .unwrap_or_default()
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        (open_line != line_idx && target != leading).then_some((leading, target))
+    }
+
     /// Get character at cursor position (if any)
     fn char_at_cursor(&self) -> Option<char> {
         let idx = self.buffer().line_col_to_char(self.cursor().line, self.cursor().col);
@@ -3578,15 +7486,194 @@ impl Editor {
 
     fn insert_newline(&mut self) {
         self.history_mut().maybe_break_group();
-        self.insert_text("\n");
+        if self.continue_list() {
+            self.history_mut().maybe_break_group();
+            return;
+        }
+        if !self.cursors().is_single() {
+            self.insert_text("\n");
+            self.history_mut().maybe_break_group();
+            return;
+        }
+        if let Some(continuation) = self.comment_continuation() {
+            self.insert_text(&format!("\n{}", continuation));
+        } else {
+            self.insert_indented_newline();
+        }
         self.history_mut().maybe_break_group();
     }
 
+    /// Enter keeps the line's indentation, one level deeper after an
+    /// opening bracket (or a block-opening `:` in Python and the like);
+    /// between a bracket pair the closing one goes on a line of its own
+    fn insert_indented_newline(&mut self) {
+        let (line_idx, col) = (self.cursor().line, self.cursor().col);
+        let line = self.buffer().line_str(line_idx).unwrap_or_default();
+        let before: String = line.chars().take(col).collect();
+        let indent: String = before.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+        let opener = before.trim_end().chars().last().filter(|c| match c {
+            '{' | '(' | '[' => true,
+            ':' => self.buffer_entry().highlighter.colon_opens_block(),
+            _ => false,
+        });
+        let Some(opener) = opener.filter(|_| !self.cursor().has_selection()) else {
+            self.insert_text(&format!("\n{}", indent));
+            return;
+        };
+
+        let inner = format!("{}{}", indent, self.indent().unit());
+        let closer = match opener {
+            '{' => Some('}'),
+            '(' => Some(')'),
+            '[' => Some(']'),
+            _ => None,
+        };
+        if closer.is_some() && line.chars().nth(col) == closer {
+            self.insert_text(&format!("\n{}\n{}", inner, indent));
+            self.cursor_mut().set(line_idx + 1, inner.chars().count());
+        } else {
+            self.insert_text(&format!("\n{}", inner));
+        }
+    }
+
+    /// What Enter at the cursor should put at the start of the new line to
+    /// carry on a comment: the line comment prefix (`///`, `//`, `#`...) or,
+    /// inside a `/* */` block comment, a `*` lined up with the one above
+    fn comment_continuation(&mut self) -> Option<String> {
+        let (line_idx, col) = (self.cursor().line, self.cursor().col);
+        let highlighter = &self.buffer_entry().highlighter;
+        let language = highlighter.language_name()?.to_lowercase();
+        if !self.workspace.config.comment_continuation.get(&language).copied().unwrap_or(true) {
+            return None;
+        }
+        let prefixes = highlighter.continued_comments();
+        let block_stars = highlighter.has_c_block_comments();
+
+        let line = self.buffer().line_str(line_idx)?;
+        let line = line.trim_end_matches(['\n', '\r']);
+        let before: String = line.chars().take(col).collect();
+        let indent: String = line.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+        let text = &line[indent.len()..];
+
+        let mut state = self.highlight_state_before(line_idx);
+        if state.in_multiline_string.is_some() {
+            return None;
+        }
+        let starts_in_comment = state.in_block_comment;
+        let _ = self.buffer_entry().highlighter.tokenize_line(&before, &mut state);
+
+        if state.in_block_comment {
+            if !block_stars {
+                return None;
+            }
+            // "/**" puts its stars one column in; later lines keep theirs
+            let lead = if !starts_in_comment && text.starts_with("/*") { format!("{} ", indent) } else { indent };
+            return Some(format!("{}* ", lead));
+        }
+        if starts_in_comment || (line_idx == 0 && text.starts_with("#!")) {
+            return None;
+        }
+        let prefix = prefixes.into_iter().find(|p| text.starts_with(p))?;
+        if before.chars().count() < indent.chars().count() + prefix.len() {
+            return None;
+        }
+        let gap = if text[prefix.len()..].starts_with(' ') { " " } else { "" };
+        Some(format!("{}{}{}", indent, prefix, gap))
+    }
+
+    /// Whether Enter and Tab carry on lists: in Markdown and plain text
+    fn lists_apply(&self) -> bool {
+        self.cursors().is_single()
+            && !self.cursor().has_selection()
+            && matches!(self.buffer_entry().highlighter.language_name(), None | Some("Markdown"))
+    }
+
+    /// Enter in a list item starts the next item; in an empty one it
+    /// removes the marker, ending the list
+    fn continue_list(&mut self) -> bool {
+        if !self.lists_apply() {
+            return false;
+        }
+        let (line_idx, col) = (self.cursor().line, self.cursor().col);
+        let line = self.buffer().line_str(line_idx).unwrap_or_default();
+        let Some(item) = autolist::parse(&line) else {
+            return false;
+        };
+        if col < item.content_start {
+            return false;
+        }
+        if !item.is_empty(&line) {
+            self.insert_text(&format!("\n{}", item.next()));
+            return true;
+        }
+
+        let start = self.buffer().line_col_to_char(line_idx, 0);
+        let cursor_before = self.cursor_pos();
+        self.invalidate_highlight_cache(line_idx);
+        self.buffer_mut().delete(start, start + line.chars().count());
+        self.cursor_mut().set(line_idx, 0);
+        let cursor_after = self.cursor_pos();
+        self.history_mut().record_delete(start, line, cursor_before, cursor_after);
+        true
+    }
+
+    /// Tab in a list item nests it under the item above; Shift+Tab moves
+    /// it back out to its parent's level
+    fn nest_list_item(&mut self, deeper: bool) -> bool {
+        if !self.lists_apply() {
+            return false;
+        }
+        let line_idx = self.cursor().line;
+        let line = self.buffer().line_str(line_idx).unwrap_or_default();
+        let above: Vec<String> = (0..line_idx).rev().map_while(|i| self.buffer().line_str(i)).take(200).collect();
+        let above: Vec<&str> = above.iter().map(String::as_str).collect();
+        let (Some(item), Some(prefix)) = (autolist::parse(&line), autolist::renested(&line, &above, deeper)) else {
+            return false;
+        };
+
+        let old: String = line.chars().take(item.content_start).collect();
+        let start = self.buffer().line_col_to_char(line_idx, 0);
+        let cursor_before = self.cursor_pos();
+        let col = (cursor_before.col.max(item.content_start) + prefix.chars().count()).saturating_sub(item.content_start);
+        self.invalidate_highlight_cache(line_idx);
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(vec![cursor_before]);
+        self.buffer_mut().delete(start, start + item.content_start);
+        self.history_mut().record_delete(start, old, cursor_before, cursor_before);
+        self.buffer_mut().insert(start, &prefix);
+        self.cursor_mut().set(line_idx, col);
+        let cursor_after = self.cursor_pos();
+        self.history_mut().record_insert(start, prefix, cursor_before, cursor_after);
+        self.history_mut().set_cursors_after(vec![cursor_after]);
+        self.history_mut().end_group();
+        true
+    }
+
+    /// Highlighter state at the start of `line_idx`, filling the state
+    /// cache up to it
+    fn highlight_state_before(&mut self, line_idx: usize) -> HighlightState {
+        let tab = self.workspace.active_tab_mut();
+        let entry = &mut tab.buffers[tab.panes[tab.active_pane].buffer_idx];
+        if entry.buffer.large {
+            return entry.highlighter.state_near(line_idx, |idx| entry.buffer.line_str(idx));
+        }
+        let start = entry.highlighter.cache_valid_from().min(line_idx);
+        let mut state = entry.highlighter.get_state_for_line(start);
+        for idx in start..line_idx {
+            if let Some(text) = entry.buffer.line_str(idx) {
+                let _ = entry.highlighter.tokenize_line(&text, &mut state);
+                entry.highlighter.update_cache(idx, &state);
+            }
+        }
+        state
+    }
+
     fn insert_tab(&mut self) {
         if self.cursor().has_selection() {
             self.indent_selection();
         } else {
-            self.insert_text("    ");
+            let unit = self.indent().unit();
+            self.insert_text(&unit);
         }
     }
 
@@ -3594,27 +7681,75 @@ impl Editor {
     fn indent_selection(&mut self) {
         if let Some((start, end)) = self.cursor().selection_bounds() {
             let cursor_before = self.cursor_pos();
+            let indent = self.indent().unit();
             self.history_mut().begin_group();
 
             // Indent each line from start to end (inclusive)
             for line_idx in start.line..=end.line {
                 let line_start = self.buffer().line_col_to_char(line_idx, 0);
-                let indent = "    ";
-                self.buffer_mut().insert(line_start, indent);
-                self.history_mut().record_insert(line_start, indent.to_string(), cursor_before, cursor_before);
+                self.buffer_mut().insert(line_start, &indent);
+                self.history_mut().record_insert(line_start, indent.clone(), cursor_before, cursor_before);
             }
 
             // Adjust selection to cover the indented text
-            self.cursor_mut().anchor_col += 4;
-            self.cursor_mut().col += 4;
+            self.cursor_mut().anchor_col += indent.len();
+            self.cursor_mut().col += indent.len();
             self.cursor_mut().desired_col = self.cursor().col;
 
             self.history_mut().end_group();
         }
     }
 
+    /// Delete every cursor's selection; returns whether there were any
+    fn delete_selections_multi(&mut self) -> bool {
+        let mut selections: Vec<(usize, usize, usize)> = self.cursors().all()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| {
+                let (start, end) = c.selection_bounds()?;
+                Some((i, self.buffer().line_col_to_char(start.line, start.col), self.buffer().line_col_to_char(end.line, end.col)))
+            })
+            .collect();
+        self.cursors_mut().clear_selections();
+        if selections.is_empty() {
+            return false;
+        }
+        selections.sort_by_key(|&(_, start, _)| start);
+
+        let cursors_before = self.all_cursor_positions();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(cursors_before);
+        let cursor_before = self.cursor_pos();
+        let first_line = self.buffer().char_to_line_col(selections[0].1).0;
+        self.invalidate_highlight_cache(first_line);
+        self.invalidate_bracket_cache();
+
+        // Apply deletes from start to end, tracking how far earlier ones
+        // moved the text back
+        let mut removed = 0;
+        for (cursor_idx, start, end) in selections {
+            let (start, end) = (start.saturating_sub(removed), end.saturating_sub(removed));
+            let deleted: String = self.buffer().slice(start, end).chars().collect();
+            self.buffer_mut().delete(start, end);
+            self.history_mut().record_delete(start, deleted, cursor_before, cursor_before);
+            removed += end - start;
+            let (line, col) = self.buffer().char_to_line_col(start);
+            self.cursors_mut().all_mut()[cursor_idx].set(line, col);
+        }
+
+        let cursors_after = self.all_cursor_positions();
+        self.history_mut().set_cursors_after(cursors_after);
+        self.history_mut().end_group();
+        self.cursors_mut().merge_overlapping();
+        true
+    }
+
     /// Delete backward at all cursor positions (multi-cursor)
     fn delete_backward_multi(&mut self) {
+        if self.delete_selections_multi() {
+            return;
+        }
+
         // Multi-cursor: compute absolute character indices FIRST from a frozen view of the buffer.
         // Sort by ASCENDING, process start to end, track cumulative offset.
 
@@ -3685,6 +7820,10 @@ impl Editor {
 
     /// Delete forward at all cursor positions (multi-cursor)
     fn delete_forward_multi(&mut self) {
+        if self.delete_selections_multi() {
+            return;
+        }
+
         // Multi-cursor: compute absolute character indices FIRST from a frozen view of the buffer.
         // Sort by ASCENDING, process start to end, track cumulative offset.
 
@@ -4000,7 +8139,7 @@ impl Editor {
     /// Yank (paste) from yank stack (Ctrl+Y)
     fn yank(&mut self) {
         if self.yank_stack.is_empty() {
-            self.message = Some("Yank stack empty".to_string());
+            self.messages.info("Yank stack empty".to_string());
             return;
         }
 
@@ -4043,13 +8182,13 @@ impl Editor {
         let current_idx = match self.yank_index {
             Some(idx) => idx,
             None => {
-                self.message = Some("No active yank to cycle".to_string());
+                self.messages.info("No active yank to cycle".to_string());
                 return;
             }
         };
 
         if self.yank_stack.len() <= 1 {
-            self.message = Some("Only one item in yank stack".to_string());
+            self.messages.info("Only one item in yank stack".to_string());
             return;
         }
 
@@ -4232,14 +8371,19 @@ impl Editor {
         }
     }
 
-    /// Dedent a single line, returns number of spaces removed
+    /// Dedent a single line by a leading tab or up to a level of spaces,
+    /// returns number of characters removed
     fn dedent_line(&mut self, line_idx: usize) -> usize {
         if let Some(line_str) = self.buffer().line_str(line_idx) {
-            let spaces_to_remove = line_str.chars().take(4).take_while(|c| *c == ' ').count();
+            let spaces_to_remove = if line_str.starts_with('\t') {
+                1
+            } else {
+                line_str.chars().take(self.indent_width()).take_while(|c| *c == ' ').count()
+            };
             if spaces_to_remove > 0 {
                 let cursor_before = self.cursor_pos();
                 let line_start = self.buffer().line_col_to_char(line_idx, 0);
-                let deleted: String = " ".repeat(spaces_to_remove);
+                let deleted: String = line_str.chars().take(spaces_to_remove).collect();
 
                 self.buffer_mut().delete(line_start, line_start + spaces_to_remove);
 
@@ -4289,88 +8433,279 @@ impl Editor {
     // === Line operations ===
 
     fn move_line_up(&mut self) {
-        if self.cursor().line > 0 {
-            let cursor_before = self.cursor_pos();
-            self.history_mut().begin_group();
+        self.move_lines(false);
+    }
+
+    fn move_line_down(&mut self) {
+        self.move_lines(true);
+    }
+
+    /// Move the lines under every cursor up or down by one, swapping each
+    /// block with the line next to it. Selections and cursor columns move
+    /// with their block; a block already at the top or bottom stays put.
+    fn move_lines(&mut self, down: bool) {
+        let line_count = self.buffer().line_count();
+        let blocks: Vec<(usize, usize)> = self.cursor_line_blocks()
+            .into_iter()
+            .filter(|&(first, last)| if down { last + 1 < line_count } else { first > 0 })
+            .collect();
+        let Some(&(first_line, _)) = blocks.first() else {
+            return;
+        };
+        self.invalidate_highlight_cache(first_line.saturating_sub(1));
+        self.invalidate_bracket_cache();
+
+        let cursor_before = self.cursor_pos();
+        let cursors_before = self.all_cursor_positions();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(cursors_before);
+
+        // Each block and its neighbour line are rewritten in swapped order;
+        // the text keeps its length, so other blocks' positions still hold
+        for &(first, last) in &blocks {
+            let (from, to) = if down { (first, last + 1) } else { (first - 1, last) };
+            let mut lines: Vec<String> = (from..=to)
+                .map(|line| self.buffer().line_str(line).unwrap_or_default())
+                .collect();
+            if down {
+                lines.rotate_right(1);
+            } else {
+                lines.rotate_left(1);
+            }
+            let start = self.buffer().line_col_to_char(from, 0);
+            let end = self.buffer().line_col_to_char(to, self.buffer().line_len(to));
+            let deleted: String = self.buffer().slice(start, end).chars().collect();
+            let moved = lines.join("\n");
+            self.buffer_mut().delete(start, end);
+            self.history_mut().record_delete(start, deleted, cursor_before, cursor_before);
+            self.buffer_mut().insert(start, &moved);
+            self.history_mut().record_insert(start, moved, cursor_before, cursor_before);
+        }
 
-            let curr_line = self.cursor().line;
-            let prev_line = curr_line - 1;
+        for cursor in self.cursors_mut().all_mut() {
+            let top = cursor.selection_bounds().map_or(cursor.line, |(start, _)| start.line);
+            if blocks.iter().any(|&(first, last)| first <= top && top <= last) {
+                if down {
+                    cursor.line += 1;
+                    cursor.anchor_line += 1;
+                } else {
+                    cursor.line -= 1;
+                    cursor.anchor_line = cursor.anchor_line.saturating_sub(1);
+                }
+            }
+        }
 
-            let curr_content = self.buffer().line_str(curr_line).unwrap_or_default();
+        let cursors_after = self.all_cursor_positions();
+        self.history_mut().set_cursors_after(cursors_after);
+        self.history_mut().end_group();
+    }
 
-            // Delete current line (including its newline)
-            let curr_start = self.buffer().line_col_to_char(curr_line, 0);
-            let delete_start = curr_start.saturating_sub(1); // Include newline before
-            let delete_end = curr_start + curr_content.len();
-            let deleted: String = self.buffer().slice(delete_start, delete_end).chars().collect();
-            self.buffer_mut().delete(delete_start, delete_end);
-            self.history_mut().record_delete(delete_start, deleted, cursor_before, cursor_before);
+    fn duplicate_line_up(&mut self) {
+        self.copy_lines(false);
+    }
 
-            // Insert current line before previous line
-            let prev_start = self.buffer().line_col_to_char(prev_line, 0);
-            let insert_text = format!("{}\n", curr_content);
-            let cursor_col = self.cursor().col;
-            self.buffer_mut().insert(prev_start, &insert_text);
-            self.history_mut().record_insert(prev_start, insert_text, cursor_before, Position::new(prev_line, cursor_col));
+    fn duplicate_line_down(&mut self) {
+        self.copy_lines(true);
+    }
 
-            self.cursor_mut().line = prev_line;
-            self.history_mut().end_group();
+    /// Lines covered by each cursor as `(first, last)` blocks, sorted and
+    /// merged where they overlap or touch. A multi-line selection ending at
+    /// column 0 leaves that line out, as nothing on it is selected.
+    fn cursor_line_blocks(&self) -> Vec<(usize, usize)> {
+        let mut blocks: Vec<(usize, usize)> = self.cursors().all()
+            .iter()
+            .map(|c| match c.selection_bounds() {
+                Some((start, end)) if end.line > start.line && end.col == 0 => (start.line, end.line - 1),
+                Some((start, end)) => (start.line, end.line),
+                None => (c.line, c.line),
+            })
+            .collect();
+        blocks.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (first, last) in blocks {
+            match merged.last_mut() {
+                Some(prev) if first <= prev.1 + 1 => prev.1 = prev.1.max(last),
+                _ => merged.push((first, last)),
+            }
         }
+        merged
     }
 
-    fn move_line_down(&mut self) {
-        if self.cursor().line + 1 < self.buffer().line_count() {
-            let cursor_before = self.cursor_pos();
-            self.history_mut().begin_group();
+    /// Copy the lines under every cursor above (`down == false`) or below
+    /// them. Cursors and selections end up on the copies, so repeating the
+    /// command keeps stacking copies in the same direction.
+    fn copy_lines(&mut self, down: bool) {
+        let blocks = self.cursor_line_blocks();
+        let Some(&(first_line, _)) = blocks.first() else {
+            return;
+        };
+        self.invalidate_highlight_cache(first_line);
+        self.invalidate_bracket_cache();
+
+        let cursor_before = self.cursor_pos();
+        let cursors_before = self.all_cursor_positions();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(cursors_before);
+
+        // Insert from the bottom up so earlier blocks keep their positions
+        for &(first, last) in blocks.iter().rev() {
+            let lines: Vec<String> = (first..=last)
+                .map(|line| self.buffer().line_str(line).unwrap_or_default())
+                .collect();
+            let text = lines.join("\n");
+            let (idx, insert_text) = if down {
+                let idx = self.buffer().line_col_to_char(last, self.buffer().line_len(last));
+                (idx, format!("\n{}", text))
+            } else {
+                (self.buffer().line_col_to_char(first, 0), format!("{}\n", text))
+            };
+            self.buffer_mut().insert(idx, &insert_text);
+            self.history_mut().record_insert(idx, insert_text, cursor_before, cursor_before);
+        }
+
+        // Shift each cursor by the lines inserted above it: every earlier
+        // block's copy, plus its own block's copy when copying down
+        for cursor in self.cursors_mut().all_mut() {
+            let top = cursor.selection_bounds().map_or(cursor.line, |(start, _)| start.line);
+            let mut shift = 0;
+            for &(first, last) in &blocks {
+                let count = last - first + 1;
+                if last < top {
+                    shift += count;
+                } else if first <= top {
+                    if down {
+                        shift += count;
+                    }
+                    break;
+                }
+            }
+            cursor.line += shift;
+            cursor.anchor_line += shift;
+        }
 
-            let curr_line = self.cursor().line;
-            let next_line = curr_line + 1;
+        let cursors_after = self.all_cursor_positions();
+        self.history_mut().set_cursors_after(cursors_after);
+        self.history_mut().end_group();
+    }
+
+    /// Delete the lines under every cursor as one undo step. Each cursor
+    /// keeps its column on the line that moves up into place, or on the line
+    /// above when the buffer's last lines went.
+    fn delete_lines(&mut self) {
+        let blocks = self.cursor_line_blocks();
+        let Some(&(first_line, _)) = blocks.first() else {
+            return;
+        };
+        self.invalidate_highlight_cache(first_line.saturating_sub(1));
+        self.invalidate_bracket_cache();
+
+        let cursor_before = self.cursor_pos();
+        let cursors_before = self.all_cursor_positions();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(cursors_before);
+
+        // Delete from the bottom up so earlier blocks keep their positions
+        for &(first, last) in blocks.iter().rev() {
+            let line_count = self.buffer().line_count();
+            let (start, end) = if last + 1 < line_count {
+                (self.buffer().line_col_to_char(first, 0), self.buffer().line_col_to_char(last + 1, 0))
+            } else if first > 0 {
+                // The last line has no newline of its own: take the one before it
+                let prev_end = self.buffer().line_col_to_char(first - 1, self.buffer().line_len(first - 1));
+                (prev_end, self.buffer().len_chars())
+            } else {
+                (0, self.buffer().len_chars())
+            };
+            if start < end {
+                let deleted: String = self.buffer().slice(start, end).chars().collect();
+                self.buffer_mut().delete(start, end);
+                self.history_mut().record_delete(start, deleted, cursor_before, cursor_before);
+            }
+        }
 
-            let curr_content = self.buffer().line_str(curr_line).unwrap_or_default();
+        // Each cursor lands where its block started, less the lines deleted above it
+        let line_count = self.buffer().line_count();
+        let targets: Vec<(usize, usize)> = self.cursors().all()
+            .iter()
+            .map(|cursor| {
+                let top = cursor.selection_bounds().map_or(cursor.line, |(start, _)| start.line);
+                let removed_above: usize = blocks.iter()
+                    .filter(|&&(_, last)| last < top)
+                    .map(|&(first, last)| last - first + 1)
+                    .sum();
+                let block_start = blocks.iter()
+                    .find(|&&(first, last)| first <= top && top <= last)
+                    .map_or(top, |&(first, _)| first);
+                let line = (block_start - removed_above).min(line_count.saturating_sub(1));
+                (line, cursor.col.min(self.buffer().line_len(line)))
+            })
+            .collect();
+        for (cursor, (line, col)) in self.cursors_mut().all_mut().iter_mut().zip(targets) {
+            cursor.move_to(line, col, false);
+            cursor.desired_col = col;
+        }
+        self.cursors_mut().merge_overlapping();
 
-            // Delete current line (including newline after)
-            let curr_start = self.buffer().line_col_to_char(curr_line, 0);
-            let next_start = self.buffer().line_col_to_char(next_line, 0);
-            let deleted: String = self.buffer().slice(curr_start, next_start).chars().collect();
-            self.buffer_mut().delete(curr_start, next_start);
-            self.history_mut().record_delete(curr_start, deleted, cursor_before, cursor_before);
+        let cursors_after = self.all_cursor_positions();
+        self.history_mut().set_cursors_after(cursors_after);
+        self.history_mut().end_group();
+    }
 
-            // Insert current line after what was the next line (now at curr_line)
-            let new_line_end = self.buffer().line_col_to_char(curr_line, self.buffer().line_len(curr_line));
-            let insert_text = format!("\n{}", curr_content);
-            let cursor_col = self.cursor().col;
-            self.buffer_mut().insert(new_line_end, &insert_text);
-            self.history_mut().record_insert(new_line_end, insert_text, cursor_before, Position::new(next_line, cursor_col));
+    /// Insert a copy of each selection right after it and select the copy;
+    /// cursors without a selection copy their line below instead
+    fn duplicate_selection(&mut self) {
+        if self.cursors().all().iter().all(|c| !c.has_selection()) {
+            self.copy_lines(true);
+            return;
+        }
 
-            self.cursor_mut().line = next_line;
-            self.history_mut().end_group();
+        let mut ranges: Vec<(usize, usize, usize)> = self.cursors().all()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| {
+                let (start, end) = c.selection_bounds()?;
+                let start_idx = self.buffer().line_col_to_char(start.line, start.col);
+                let end_idx = self.buffer().line_col_to_char(end.line, end.col);
+                Some((i, start_idx, end_idx))
+            })
+            .collect();
+        ranges.sort_by_key(|&(_, start, _)| start);
+        if let Some(&(_, start_idx, _)) = ranges.first() {
+            let (line, _) = self.buffer().char_to_line_col(start_idx);
+            self.invalidate_highlight_cache(line);
         }
-    }
+        self.invalidate_bracket_cache();
 
-    fn duplicate_line_up(&mut self) {
         let cursor_before = self.cursor_pos();
+        let cursors_before = self.all_cursor_positions();
         self.history_mut().begin_group();
-        let content = self.buffer().line_str(self.cursor().line).unwrap_or_default();
-        let line_start = self.buffer().line_col_to_char(self.cursor().line, 0);
-        let insert_text = format!("{}\n", content);
-        self.buffer_mut().insert(line_start, &insert_text);
-        // Cursor stays on same logical line (now shifted down by 1)
-        self.cursor_mut().line += 1;
-        let cursor_after = self.cursor_pos();
-        self.history_mut().record_insert(line_start, insert_text, cursor_before, cursor_after);
-        self.history_mut().end_group();
-    }
+        self.history_mut().set_cursors_before(cursors_before);
 
-    fn duplicate_line_down(&mut self) {
-        let cursor_before = self.cursor_pos();
-        self.history_mut().begin_group();
-        let content = self.buffer().line_str(self.cursor().line).unwrap_or_default();
-        let line_end = self.buffer().line_col_to_char(self.cursor().line, self.buffer().line_len(self.cursor().line));
-        let insert_text = format!("\n{}", content);
-        self.buffer_mut().insert(line_end, &insert_text);
-        self.cursor_mut().line += 1;
-        let cursor_after = self.cursor_pos();
-        self.history_mut().record_insert(line_end, insert_text, cursor_before, cursor_after);
+        // Apply from the start of the document, tracking how far later
+        // selections have been pushed by earlier copies
+        let mut offset = 0;
+        let mut new_selections = Vec::new();
+        for (cursor_idx, start_idx, end_idx) in ranges {
+            let text: String = self.buffer().slice(start_idx + offset, end_idx + offset).chars().collect();
+            let len = end_idx - start_idx;
+            let insert_at = end_idx + offset;
+            self.buffer_mut().insert(insert_at, &text);
+            self.history_mut().record_insert(insert_at, text, cursor_before, cursor_before);
+            new_selections.push((cursor_idx, insert_at, insert_at + len));
+            offset += len;
+        }
+
+        for (cursor_idx, from, to) in new_selections {
+            let (from_line, from_col) = self.buffer().char_to_line_col(from);
+            let (to_line, to_col) = self.buffer().char_to_line_col(to);
+            let cursor = &mut self.cursors_mut().all_mut()[cursor_idx];
+            cursor.move_to(from_line, from_col, false);
+            cursor.move_to(to_line, to_col, true);
+            cursor.desired_col = to_col;
+        }
+
+        let cursors_after = self.all_cursor_positions();
+        self.history_mut().set_cursors_after(cursors_after);
         self.history_mut().end_group();
     }
 
@@ -4402,7 +8737,7 @@ impl Editor {
         let comment_prefix = match self.buffer_entry().highlighter.line_comment() {
             Some(prefix) => prefix,
             None => {
-                self.message = Some("No line comment syntax for this file type".to_string());
+                self.messages.info("No line comment syntax for this file type".to_string());
                 return;
             }
         };
@@ -4548,37 +8883,104 @@ impl Editor {
         if let Some(ref mut cb) = self.clipboard {
             let _ = cb.set_text(&text);
         }
+        self.workspace.remember_clipboard(&text);
         self.internal_clipboard = text;
     }
 
+    /// Copy the current file's path, or the explorer's selected entry's
+    /// while the explorer has focus, in the given form
+    fn copy_path(&mut self, form: PathForm) {
+        let from_tree = self.focus == Focus::FussMode && self.workspace.fuss.active;
+        let path = if from_tree { self.workspace.fuss.selected_path() } else { self.current_file_path() };
+        let Some(path) = path else {
+            self.messages.info("No file to copy the path of".to_string());
+            return;
+        };
+        let text = match form {
+            PathForm::Absolute => path.display().to_string(),
+            PathForm::Relative => self.display_path(&path),
+            PathForm::FileName => path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+            PathForm::WithLine if from_tree => self.display_path(&path),
+            PathForm::WithLine => {
+                let lines = match self.cursor().selection_bounds() {
+                    Some((start, end)) if end.line > start.line => {
+                        // A selection ending at a line's start leaves that line out
+                        let last = if end.col == 0 { end.line - 1 } else { end.line };
+                        if last > start.line {
+                            format!("{}-{}", start.line + 1, last + 1)
+                        } else {
+                            (start.line + 1).to_string()
+                        }
+                    }
+                    _ => (self.cursor().line + 1).to_string(),
+                };
+                format!("{}:{}", self.display_path(&path), lines)
+            }
+        };
+        self.messages.info(format!("Copied {}", text));
+        self.set_clipboard(text);
+    }
+
     /// Get clipboard text (system if available, internal fallback)
     fn get_clipboard(&mut self) -> String {
         if let Some(ref mut cb) = self.clipboard {
             if let Ok(text) = cb.get_text() {
+                // Copied in another application: keep it in the ring too
+                self.workspace.remember_clipboard(&text);
                 return text;
             }
         }
         self.internal_clipboard.clone()
     }
 
+    /// Selected text of every cursor, a line each, when there are several
+    fn selections_text(&self) -> Option<String> {
+        if self.cursors().is_single() {
+            return self.get_selection_text();
+        }
+        if !self.cursors().has_selection() {
+            return None;
+        }
+        let texts: Vec<String> = self.cursors().all().iter()
+            .map(|c| c.selection_bounds().map_or_else(String::new, |(start, end)| {
+                let start_idx = self.buffer().line_col_to_char(start.line, start.col);
+                let end_idx = self.buffer().line_col_to_char(end.line, end.col);
+                self.buffer().slice(start_idx, end_idx).chars().collect()
+            }))
+            .collect();
+        Some(texts.join("\n"))
+    }
+
+    /// Put the selections on the clipboard, remembering whether they were
+    /// a block
+    fn copy_selections(&mut self, text: String) {
+        let block = self.cursors().block().is_some().then(|| text.clone());
+        self.set_clipboard(text);
+        self.block_clipboard = block;
+    }
+
     fn copy(&mut self) {
-        if let Some(text) = self.get_selection_text() {
-            self.set_clipboard(text);
-            self.message = Some("Copied".to_string());
+        if let Some(text) = self.selections_text() {
+            self.copy_selections(text);
+            self.messages.info("Copied".to_string());
         } else {
             // Copy current line
             if let Some(line) = self.buffer().line_str(self.cursor().line) {
                 self.set_clipboard(format!("{}\n", line));
-                self.message = Some("Copied line".to_string());
+                self.messages.info("Copied line".to_string());
             }
         }
     }
 
     fn cut(&mut self) {
-        if let Some(text) = self.get_selection_text() {
-            self.set_clipboard(text);
-            self.delete_selection();
-            self.message = Some("Cut".to_string());
+        if let Some(text) = self.selections_text() {
+            self.copy_selections(text);
+            if self.cursors().is_single() {
+                self.delete_selection();
+            } else {
+                self.delete_selections_multi();
+            }
+            self.messages.info("Cut".to_string());
         } else {
             // Cut current line
             if let Some(line) = self.buffer().line_str(self.cursor().line) {
@@ -4618,7 +9020,7 @@ impl Editor {
                     }
                 }
 
-                self.message = Some("Cut line".to_string());
+                self.messages.info("Cut line".to_string());
             }
         }
         self.history_mut().maybe_break_group();
@@ -4626,11 +9028,197 @@ impl Editor {
 
     fn paste(&mut self) {
         let text = self.get_clipboard();
-        if !text.is_empty() {
-            self.insert_text(&text);
-            self.message = Some("Pasted".to_string());
-            self.history_mut().maybe_break_group();
+        self.paste_text(&text, PasteMode::Indented);
+    }
+
+    /// Paste Special: pick a clipboard ring entry and how to paste it
+    fn open_clipboard_ring(&mut self) {
+        self.get_clipboard();
+        if self.workspace.clipboard_entries().is_empty() {
+            self.messages.info("Clipboard ring is empty".to_string());
+            return;
         }
+        self.workspace.fuss.deactivate();
+        self.prompt = PromptState::ClipboardRing { selected_index: 0 };
+    }
+
+    /// Paste `text` at the cursors as `mode` says
+    fn paste_text(&mut self, text: &str, mode: PasteMode) {
+        if text.is_empty() {
+            return;
+        }
+        if text.len() > LARGE_PASTE_BYTES && text.len() / 10 > self.buffer().len_chars() {
+            self.messages.prompt(large_paste_question(text));
+            self.prompt = PromptState::LargePaste { text: text.to_string(), mode };
+            return;
+        }
+        if mode == PasteMode::OverLines {
+            self.paste_over_lines(text);
+            return;
+        }
+
+        // A line per cursor, or a copied block at the cursor as a block
+        if mode != PasteMode::Select {
+            let lines: Vec<&str> = text.strip_suffix('\n').unwrap_or(text).split('\n').collect();
+            if !self.cursors().is_single() && lines.len() == self.cursors().len() {
+                self.insert_each(&lines);
+                self.messages.info("Pasted a line at each cursor".to_string());
+                self.history_mut().maybe_break_group();
+                return;
+            }
+            if self.block_clipboard.as_deref() == Some(text) {
+                self.paste_block(&lines);
+                self.messages.info("Pasted block".to_string());
+                self.history_mut().maybe_break_group();
+                return;
+            }
+        }
+
+        // Re-indenting follows the one cursor's line
+        let text = if mode == PasteMode::Plain || !self.cursors().is_single() {
+            text.to_string()
+        } else {
+            let start = self.cursor().selection_bounds().map_or(self.cursor_pos(), |(start, _)| start);
+            let before: String = self.buffer().line_str(start.line).unwrap_or_default().chars().take(start.col).collect();
+            paste::reindent(text, &before)
+        };
+        self.insert_text(&text);
+
+        if mode == PasteMode::Select {
+            // Each cursor sits just after its copy of the text
+            let len = text.chars().count();
+            let anchors: Vec<(usize, usize)> = self
+                .cursors()
+                .all()
+                .iter()
+                .map(|c| {
+                    let idx = self.buffer().line_col_to_char(c.line, c.col);
+                    self.buffer().char_to_line_col(idx.saturating_sub(len))
+                })
+                .collect();
+            for (cursor, (line, col)) in self.cursors_mut().all_mut().iter_mut().zip(anchors) {
+                cursor.anchor_line = line;
+                cursor.anchor_col = col;
+                cursor.selecting = true;
+            }
+        }
+        self.messages.info("Pasted".to_string());
+        self.history_mut().maybe_break_group();
+    }
+
+    /// Paste `lines` as a block: each at the cursor's column on successive
+    /// lines, padding short lines with spaces and adding lines past the end
+    fn paste_block(&mut self, lines: &[&str]) {
+        self.cursors_mut().collapse_to_primary();
+        self.delete_selection();
+        let start = self.cursor_pos();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(vec![start]);
+        for (i, text) in lines.iter().enumerate() {
+            let line = start.line + i;
+            if line >= self.buffer().line_count() {
+                let end = self.buffer().len_chars();
+                self.buffer_mut().insert(end, "\n");
+                self.history_mut().record_insert(end, "\n".to_string(), start, start);
+            }
+            let len = self.buffer().line_len(line);
+            let padded = format!("{}{}", " ".repeat(start.col.saturating_sub(len)), text);
+            let idx = self.buffer().line_col_to_char(line, start.col.min(len));
+            self.buffer_mut().insert(idx, &padded);
+            self.history_mut().record_insert(idx, padded, start, start);
+        }
+        let last = lines.last().map_or(0, |text| text.chars().count());
+        self.cursor_mut().set(start.line + lines.len().saturating_sub(1), start.col + last);
+        let cursor_after = self.cursor_pos();
+        self.history_mut().set_cursors_after(vec![cursor_after]);
+        self.history_mut().end_group();
+        self.invalidate_highlight_cache(start.line);
+        self.invalidate_bracket_cache();
+    }
+
+    /// Insert a confirmed large paste as is at the primary cursor, a chunk
+    /// at a time with the progress on screen; it undoes as one edit
+    fn stream_paste(&mut self, text: &str) -> Result<()> {
+        self.cursors_mut().collapse_to_primary();
+        self.delete_selection();
+        let cursor_before = self.cursor_pos();
+        let start = self.buffer().line_col_to_char(cursor_before.line, cursor_before.col);
+
+        let (mut done, mut at) = (0, start);
+        while done < text.len() {
+            let mut end = (done + PASTE_CHUNK_BYTES).min(text.len());
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            let chunk = &text[done..end];
+            self.buffer_mut().insert(at, chunk);
+            at += chunk.chars().count();
+            done = end;
+            if done < text.len() {
+                self.messages.info(format!("Pasting... {}%", done * 100 / text.len()));
+                self.render()?;
+            }
+        }
+
+        let (line, col) = self.buffer().char_to_line_col(at);
+        self.cursor_mut().set(line, col);
+        let cursor_after = self.cursor_pos();
+        self.history_mut().record_insert(start, text.to_string(), cursor_before, cursor_after);
+        self.history_mut().maybe_break_group();
+        self.invalidate_highlight_cache(cursor_before.line);
+        self.invalidate_bracket_cache();
+        self.scroll_to_cursor();
+        self.messages.info(format!("Pasted {}", image::format_bytes(text.len() as u64)));
+        Ok(())
+    }
+
+    /// Open `text` in a new tab as an unsaved buffer instead of pasting it
+    fn paste_into_scratch(&mut self, text: &str) {
+        self.workspace.new_tab();
+        self.buffer_mut().set_contents(text);
+        self.invalidate_highlight_cache(0);
+        self.messages.info(format!("Opened {} in a scratch buffer", image::format_bytes(text.len() as u64)));
+    }
+
+    /// Replace each line of the selection with a line of `text`, starting
+    /// over at its first line when it runs out
+    fn paste_over_lines(&mut self, text: &str) {
+        let Some((start, end)) = self.cursor().selection_bounds() else {
+            self.messages.error("Select the lines to paste over".to_string());
+            return;
+        };
+        // A selection ending at a line's start leaves that line alone
+        let last = if end.col == 0 && end.line > start.line { end.line - 1 } else { end.line };
+        let count = last - start.line + 1;
+        let replacement = paste::cycle_lines(text, count);
+
+        let from = self.buffer().line_col_to_char(start.line, 0);
+        let last_len = self.buffer().line_str(last).unwrap_or_default().chars().count();
+        let to = self.buffer().line_col_to_char(last, last_len);
+        let old: String = self.buffer().slice(from, to).chars().collect();
+
+        self.cursors_mut().collapse_to_primary();
+        let cursor_before = self.cursor_pos();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(vec![cursor_before]);
+        self.invalidate_highlight_cache(start.line);
+        self.invalidate_bracket_cache();
+        self.buffer_mut().delete(from, to);
+        self.history_mut().record_delete(from, old, cursor_before, cursor_before);
+        self.buffer_mut().insert(from, &replacement);
+
+        // Select the pasted lines
+        let end_col = replacement.rsplit('\n').next().unwrap_or("").chars().count();
+        let cursor = self.cursor_mut();
+        cursor.set(last, end_col);
+        cursor.anchor_line = start.line;
+        cursor.anchor_col = 0;
+        cursor.selecting = true;
+        let cursor_after = self.cursor_pos();
+        self.history_mut().record_insert(from, replacement, cursor_before, cursor_after);
+        self.history_mut().set_cursors_after(vec![cursor_after]);
+        self.history_mut().end_group();
+        self.messages.info(format!("Pasted over {} lines", count));
     }
 
     // === Undo/Redo ===
@@ -4651,7 +9239,7 @@ impl Editor {
             // Restore cursor positions from before the operation
             self.cursors_mut().set_from_positions(&cursor_positions);
             self.cursors_mut().clear_selections();
-            self.message = Some("Undo".to_string());
+            self.messages.info("Undo".to_string());
         }
     }
 
@@ -4671,7 +9259,7 @@ impl Editor {
             // Restore cursor positions from after the operation
             self.cursors_mut().set_from_positions(&cursor_positions);
             self.cursors_mut().clear_selections();
-            self.message = Some("Redo".to_string());
+            self.messages.info("Redo".to_string());
         }
     }
 
@@ -4694,27 +9282,74 @@ impl Editor {
         let cursor_line = target_cursor.line;
         let cursor_col = target_cursor.col;
 
-        let viewport_line = self.viewport_line();
+        // A cursor never sits inside a fold: open any that hide it
+        while let Some(fold) = self.buffer().fold_hiding(cursor_line) {
+            self.buffer_mut().folds.retain(|&f| f != fold);
+        }
 
-        if cursor_line < viewport_line {
-            self.set_viewport_line(cursor_line);
+        let mut viewport_line = self.viewport_line();
+        if let Some((start, _)) = self.buffer().fold_hiding(viewport_line) {
+            viewport_line = start;
+            self.set_viewport_line(start);
         }
 
-        if cursor_line >= viewport_line + visible_rows {
-            self.set_viewport_line(cursor_line - visible_rows + 1);
+        // Code lenses take a row above their line in the single-pane view
+        let lens_lines: Vec<usize> = if self.workspace.active_tab().panes.len() == 1 {
+            self.code_lens_lines().into_iter().map(|(line, _)| line).collect()
+        } else {
+            Vec::new()
+        };
+        let wrap_width = self.wrap_width();
+
+        if wrap_width.is_some() || !lens_lines.is_empty() {
+            // Count wrapped rows and lens rows: walk back from the cursor's
+            // row until the screen is full
+            let starts = |line: usize| match wrap_width {
+                Some(width) => wrap::row_starts(&self.buffer().line_str(line).unwrap_or_default(), width),
+                None => vec![0],
+            };
+            let lens_rows = |line: usize| usize::from(lens_lines.contains(&line));
+            let row_count = |line: usize| starts(line).len() + lens_rows(line);
+            let mut rows = lens_rows(cursor_line) + wrap::row_of(&starts(cursor_line), cursor_col) + 1;
+            let mut top = cursor_line;
+            let mut fits = top <= viewport_line;
+            while !fits {
+                match self.buffer().prev_visible_line(top) {
+                    Some(prev) if rows + row_count(prev) <= visible_rows => {
+                        rows += row_count(prev);
+                        top = prev;
+                        fits = top <= viewport_line;
+                    }
+                    _ => break,
+                }
+            }
+            if cursor_line < viewport_line || !fits {
+                self.set_viewport_line(top);
+            }
+            if wrap_width.is_some() {
+                self.set_viewport_col(0);
+                return;
+            }
+        } else if cursor_line < viewport_line {
+            self.set_viewport_line(cursor_line);
+        } else if self.buffer().screen_row(viewport_line, cursor_line).unwrap_or(0) >= visible_rows {
+            // Walk back from the cursor so folded blocks count as one row
+            let mut top = cursor_line;
+            for _ in 1..visible_rows {
+                match self.buffer().prev_visible_line(top) {
+                    Some(prev) => top = prev,
+                    None => break,
+                }
+            }
+            self.set_viewport_line(top);
         }
 
         // Horizontal scrolling
         let line_num_width = self.screen.line_number_width(self.buffer().line_count());
-        let fuss_width = if self.workspace.fuss.active {
-            self.workspace.fuss.width(self.screen.cols)
-        } else {
-            0
-        };
-        // Available text columns = screen width - fuss sidebar - line numbers - 1 (separator)
-        let visible_cols = (self.screen.cols as usize)
-            .saturating_sub(fuss_width as usize)
-            .saturating_sub(line_num_width + 1);
+        // Available text columns = editor width - line numbers - 1 (separator)
+        let editor_width = self.layout().editor.width as usize;
+        let minimap_width = minimap::width(self.workspace.config.minimap, editor_width);
+        let visible_cols = editor_width.saturating_sub(line_num_width + 1 + minimap_width);
 
         let viewport_col = self.viewport_col();
 
@@ -4732,68 +9367,362 @@ impl Editor {
         }
     }
 
-    // === File operations ===
+    // === File operations ===
+
+    /// Save the current buffer, asking first if its file changed on disk
+    /// since it was loaded
+    fn save(&mut self) -> Result<()> {
+        if let Some(reason) = self.read_only_reason() {
+            self.messages.info(reason.to_string());
+            return Ok(());
+        }
+        let Some(full_path) = self.current_file_path() else {
+            return Ok(());
+        };
+        if self.buffer_entry_mut().changed_on_disk(&full_path) {
+            self.messages.prompt(self.save_conflict_question(&full_path));
+            self.prompt = PromptState::SaveConflict(full_path);
+            return Ok(());
+        }
+        self.save_to(&full_path)
+    }
+
+    /// Write the current buffer to its file, whatever is there. Permission
+    /// denied asks whether to retry as root or save elsewhere.
+    fn save_to(&mut self, full_path: &Path) -> Result<()> {
+        self.show_busy("Saving");
+        if let Err(e) = self.buffer_entry_mut().save_to(full_path) {
+            let denied = e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied);
+            if !denied {
+                return Err(e);
+            }
+            self.messages.prompt(self.save_permission_question(full_path));
+            self.prompt = PromptState::SavePermission(full_path.to_path_buf());
+            return Ok(());
+        }
+        let _ = self.workspace.delete_backup(full_path);
+        self.messages.info("Saved".to_string());
+        Ok(())
+    }
+
+    fn save_permission_question(&self, path: &Path) -> String {
+        let retry = match elevate::helper() {
+            Some(helper) => format!("Retry with [S]udo ({}) / ", helper),
+            None => String::new(),
+        };
+        format!(
+            "Permission denied writing {}. {}Save [A]s elsewhere / [C]ancel",
+            self.display_path(path),
+            retry
+        )
+    }
+
+    /// Write the current buffer to `full_path` as root, letting sudo or
+    /// pkexec ask for a password on the plain terminal
+    fn save_as_root(&mut self, full_path: &Path) {
+        self.publish_status(true);
+        let result = self.screen.leave_raw_mode().and_then(|()| {
+            println!("Saving {} as root", full_path.display());
+            self.buffer_entry_mut().save_as_root(full_path)
+        });
+        let restored = self.screen.enter_raw_mode().and_then(|()| self.screen.clear());
+        self.publish_status(false);
+        if let Err(e) = restored {
+            tracing::warn!("failed to restore the terminal: {}", e);
+        }
+        match result {
+            Ok(()) => {
+                let _ = self.workspace.delete_backup(full_path);
+                self.messages.info(format!("Saved {} as root", self.display_path(full_path)));
+            }
+            Err(e) => self.messages.error(format!("Save as root failed: {}", e)),
+        }
+    }
+
+    /// Ask where to save a copy of a file that can't be written, starting
+    /// from the same name in the home directory
+    fn prompt_save_elsewhere(&mut self, path: &Path) {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        self.prompt = PromptState::TextInput {
+            label: "Save as: ".to_string(),
+            buffer: format!("~/{}", name),
+            action: TextInputAction::SaveAs,
+        };
+        self.messages.info("Save as: ".to_string());
+    }
+
+    fn save_conflict_question(&self, path: &Path) -> String {
+        format!(
+            "{} changed on disk since it was loaded. [O]verwrite / [D]iff / Save [A]s / [C]ancel",
+            self.display_path(path)
+        )
+    }
+
+    fn prompt_save_as(&mut self) {
+        if let Some(reason) = self.read_only_reason() {
+            self.messages.info(reason.to_string());
+            return;
+        }
+        let buffer = self.filename().map(|p| p.display().to_string()).unwrap_or_default();
+        self.prompt = PromptState::TextInput {
+            label: "Save as: ".to_string(),
+            buffer,
+            action: TextInputAction::SaveAs,
+        };
+        self.messages.info("Save as: ".to_string());
+    }
+
+    /// Save the current buffer to the file `input` (relative to the root,
+    /// with `~` and `$VAR` expanded) and switch the buffer over to it
+    fn save_as(&mut self, input: &str) {
+        let input = input.trim();
+        if input.is_empty() {
+            return;
+        }
+        let path = resolve_path(&self.workspace.root, input);
+        if path.is_dir() {
+            self.messages.error(format!("Is a directory: {}", input));
+            return;
+        }
+        if Some(&path) != self.current_file_path().as_ref() && path.exists() {
+            self.messages.error(format!("Already exists: {}", input));
+            return;
+        }
+        let old_path = self.current_file_path();
+        self.show_busy("Saving");
+        let root = self.workspace.root.clone();
+        if let Err(e) = self.buffer_entry_mut().save_as(&path, &root) {
+            self.messages.error(format!("Save failed: {}", e));
+            return;
+        }
+        // The edits are safe in the new file
+        if let Some(old_path) = old_path {
+            let _ = self.workspace.delete_backup(&old_path);
+        }
+        self.messages.info(format!("Saved as {}", self.display_path(&path)));
+    }
+
+    // === Pane operations ===
+
+    fn split_vertical(&mut self) {
+        self.tab_mut().split_vertical();
+        self.messages.info("Split vertical".to_string());
+    }
+
+    fn split_horizontal(&mut self) {
+        self.tab_mut().split_horizontal();
+        self.messages.info("Split horizontal".to_string());
+    }
+
+    fn close_pane(&mut self) {
+        // Check if current buffer has unsaved changes
+        if self.buffer_entry_mut().is_modified() {
+            self.prompt = PromptState::CloseBufferConfirm;
+            self.messages.prompt("Unsaved changes. [S]ave / [D]iscard / [C]ancel".to_string());
+            return;
+        }
+        self.close_pane_force();
+    }
+
+    /// Close pane without checking for unsaved changes (used after save/discard)
+    fn close_pane_force(&mut self) {
+        if self.workspace.active_tab_mut().close_active_pane() {
+            // Last pane was closed - close the tab
+            if self.workspace.close_active_tab() {
+                // Last tab - quit the editor
+                self.running = false;
+            } else {
+                self.messages.info("Tab closed".to_string());
+            }
+        } else {
+            self.messages.info("Pane closed".to_string());
+        }
+    }
+
+    fn next_pane(&mut self) {
+        self.tab_mut().next_pane();
+    }
+
+    fn prev_pane(&mut self) {
+        self.tab_mut().prev_pane();
+    }
+
+    // === Jump list ===
+
+    /// Where the primary cursor is, if the buffer is a file
+    fn current_place(&self) -> Option<Jump> {
+        let path = self.current_file_path()?;
+        let cursor = self.cursor();
+        Some(Jump { path, line: cursor.line, col: cursor.col, viewport_line: self.viewport_line() })
+    }
+
+    /// Remember where the cursor is before jumping away from it
+    fn remember_jump(&mut self) {
+        let place = self.current_place();
+        self.remember_jump_from(place);
+    }
+
+    fn remember_jump_from(&mut self, place: Option<Jump>) {
+        if let Some(place) = place {
+            self.workspace.jumps.push(place);
+        }
+    }
+
+    /// After each event: another file showing is a jump from where the
+    /// cursor last was in the one before, however it came about
+    fn note_file_switch(&mut self) {
+        let place = self.current_place();
+        if let Some(last) = self.last_place.take() {
+            if place.as_ref().map(|p| &p.path) != Some(&last.path) {
+                self.workspace.jumps.push(last);
+            }
+        }
+        self.last_place = place;
+    }
+
+    /// Navigate Back (or Forward): return to the place last jumped away
+    /// from, with its file, cursor and scroll position
+    fn navigate(&mut self, back: bool) {
+        let current = self.current_place();
+        let target = if back { self.workspace.jumps.back(current) } else { self.workspace.jumps.forward(current) };
+        let Some(jump) = target else {
+            self.messages.info(if back { "No earlier location" } else { "No later location" }.to_string());
+            return;
+        };
+        if self.current_file_path().as_ref() != Some(&jump.path) {
+            if let Err(e) = self.workspace.open_file(&jump.path) {
+                self.messages.error(format!("Failed to open {}: {}", self.workspace.display_path(&jump.path), e));
+                return;
+            }
+        }
+        let line = jump.line.min(self.buffer().line_count().saturating_sub(1));
+        let col = jump.col.min(self.buffer().line_len(line));
+        self.cursors_mut().collapse_to_primary();
+        self.cursor_mut().set(line, col);
+        self.set_viewport_line(jump.viewport_line);
+        self.scroll_to_cursor();
+        self.last_place = self.current_place();
+    }
+
+    fn pane_back(&mut self) {
+        if self.tab_mut().active_pane_mut().go_back() {
+            self.tab_mut().clamp_active_view();
+        } else {
+            self.messages.info("No earlier buffer in this pane".to_string());
+        }
+    }
+
+    fn pane_forward(&mut self) {
+        if self.tab_mut().active_pane_mut().go_forward() {
+            self.tab_mut().clamp_active_view();
+        } else {
+            self.messages.info("No later buffer in this pane".to_string());
+        }
+    }
+
+    // === Folding ===
 
-    fn save(&mut self) -> Result<()> {
-        let path = self.filename();
-        if let Some(ref p) = path {
-            // Construct full path: orphan files use absolute path, workspace files need root prefix
-            let full_path = if self.buffer_entry().is_orphan {
-                p.clone()
-            } else {
-                self.workspace.root.join(p)
-            };
-            self.buffer_mut().save(&full_path)?;
-            self.buffer_entry_mut().mark_saved();
-            let _ = self.workspace.delete_backup(&full_path);
-            self.message = Some("Saved".to_string());
+    /// Foldable ranges in the current buffer: the language server's when it
+    /// reported any, otherwise by indentation
+    fn fold_ranges(&self) -> Vec<(usize, usize)> {
+        if self.lsp_state.folding_ranges.is_empty() {
+            self.buffer().indent_fold_ranges()
+        } else {
+            self.lsp_state.folding_ranges.clone()
         }
-        Ok(())
     }
 
-    // === Pane operations ===
-
-    fn split_vertical(&mut self) {
-        self.tab_mut().split_vertical();
-        self.message = Some("Split vertical".to_string());
+    /// Fold the innermost block around the cursor, or unfold the fold the
+    /// cursor's line heads
+    fn toggle_fold(&mut self) {
+        let line = self.cursor().line;
+        if self.buffer().fold_at(line).is_some() {
+            self.unfold();
+            return;
+        }
+        let innermost = self.fold_ranges()
+            .into_iter()
+            .filter(|&(start, end)| start <= line && line <= end)
+            .min_by_key(|&(start, end)| end - start);
+        let Some((start, end)) = innermost else {
+            self.messages.info("Nothing to fold here".to_string());
+            return;
+        };
+        self.buffer_mut().folds.push((start, end));
+        self.cursors_mut().collapse_to_primary();
+        let col = self.cursor().col.min(self.buffer().line_len(start));
+        self.cursors_mut().primary_mut().set(start, col);
+        self.fix_folded_viewport();
     }
 
-    fn split_horizontal(&mut self) {
-        self.tab_mut().split_horizontal();
-        self.message = Some("Split horizontal".to_string());
+    /// Open the folds headed by the cursor's line
+    fn unfold(&mut self) {
+        let line = self.cursor().line;
+        let before = self.buffer().folds.len();
+        self.buffer_mut().folds.retain(|&(start, _)| start != line);
+        if self.buffer().folds.len() == before {
+            self.messages.info("No fold on this line".to_string());
+        }
     }
 
-    fn close_pane(&mut self) {
-        // Check if current buffer has unsaved changes
-        if self.buffer_entry_mut().is_modified() {
-            self.prompt = PromptState::CloseBufferConfirm;
-            self.message = Some("Unsaved changes. [S]ave / [D]iscard / [C]ancel".to_string());
+    /// Fold every top-level block, leaving the cursor on its block's header
+    fn fold_all(&mut self) {
+        let mut folds: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in self.fold_ranges() {
+            // Ranges are sorted by start, so nested ones follow their parent
+            if folds.last().is_none_or(|&(_, last_end)| start > last_end) {
+                folds.push((start, end));
+            }
+        }
+        if folds.is_empty() {
+            self.messages.info("Nothing to fold".to_string());
             return;
         }
-        self.close_pane_force();
+        self.buffer_mut().folds = folds;
+        self.cursors_mut().collapse_to_primary();
+        if let Some((start, _)) = self.buffer().fold_hiding(self.cursor().line) {
+            let col = self.cursor().col.min(self.buffer().line_len(start));
+            self.cursors_mut().primary_mut().set(start, col);
+        }
+        self.fix_folded_viewport();
     }
 
-    /// Close pane without checking for unsaved changes (used after save/discard)
-    fn close_pane_force(&mut self) {
-        if self.workspace.active_tab_mut().close_active_pane() {
-            // Last pane was closed - close the tab
-            if self.workspace.close_active_tab() {
-                // Last tab - quit the editor
-                self.running = false;
-            } else {
-                self.message = Some("Tab closed".to_string());
-            }
-        } else {
-            self.message = Some("Pane closed".to_string());
-        }
+    fn unfold_all(&mut self) {
+        self.buffer_mut().folds.clear();
     }
 
-    fn next_pane(&mut self) {
-        self.tab_mut().next_pane();
+    fn toggle_wrap(&mut self) {
+        let pane = self.tab_mut().active_pane_mut();
+        pane.wrap = !pane.wrap;
+        pane.viewport_col = 0;
+        let wrap = pane.wrap;
+        self.messages.info(if wrap { "Word wrap on" } else { "Word wrap off" }.to_string());
+        self.scroll_to_cursor();
     }
 
-    fn prev_pane(&mut self) {
-        self.tab_mut().prev_pane();
+    /// Text columns lines wrap at in the active pane, or None when the pane
+    /// doesn't wrap
+    fn wrap_width(&self) -> Option<usize> {
+        let tab = self.workspace.active_tab();
+        let pane = &tab.panes[tab.active_pane];
+        if !pane.wrap {
+            return None;
+        }
+        let available = self.layout().editor.width as f32;
+        let width = if tab.panes.len() > 1 {
+            ((pane.bounds.x_end - pane.bounds.x_start) * available) as usize
+        } else {
+            available as usize
+        };
+        let line_num_width = self.screen.line_number_width(self.buffer().line_count());
+        Some(width.saturating_sub(line_num_width + 1 + minimap::width(self.workspace.config.minimap, width)))
+    }
+
+    /// Move the viewport's top line out of any fold that now hides it
+    fn fix_folded_viewport(&mut self) {
+        if let Some((start, _)) = self.buffer().fold_hiding(self.viewport_line()) {
+            self.set_viewport_line(start);
+        }
     }
 
     fn navigate_pane_left(&mut self) {
@@ -4919,6 +9848,11 @@ impl Editor {
                 self.workspace.fuss.toggle_hidden();
             }
 
+            // Copy the selected entry's relative path: Alt+C
+            (Key::Char('c'), Modifiers { alt: true, .. }) => {
+                self.copy_path(PathForm::Relative);
+            }
+
             // Toggle hints (Ctrl+/ may send different codes depending on terminal)
             // Different terminals send: Ctrl+/, Ctrl+_, \x1f (ASCII 31), or Ctrl+7
             (Key::Char('/'), Modifiers { ctrl: true, .. })
@@ -4948,10 +9882,22 @@ impl Editor {
                 }
             }
 
+            // Exclude the selected directory from workspace features: Alt+X
+            (Key::Char('x'), Modifiers { alt: true, .. }) => {
+                if let Some(path) = self.workspace.fuss.selected_path().filter(|_| self.workspace.fuss.is_dir_selected()) {
+                    let name = self.display_path(&path);
+                    match self.workspace.toggle_excluded(&path) {
+                        Ok(true) => self.messages.info(format!("Excluded {} from quick open and search", name)),
+                        Ok(false) => self.messages.info(format!("Included {} again", name)),
+                        Err(e) => self.messages.error(format!("Failed to save config: {}", e)),
+                    }
+                }
+            }
+
             // Enter git mode: Alt+G
             (Key::Char('g'), Modifiers { alt: true, .. }) => {
                 self.workspace.fuss.enter_git_mode();
-                self.message = Some("Git: [a]dd [u]nstage [d]iff [m]sg [p]ush pu[l]l [f]etch [t]ag".to_string());
+                self.messages.prompt("Git: [a]dd [u]nstage [d]iff lo[g] [m]sg [p]ush pu[l]l [f]etch [t]ag".to_string());
             }
 
             // Backspace: remove last filter character
@@ -4973,35 +9919,41 @@ impl Editor {
     fn handle_fuss_git_key(&mut self, key: Key, mods: Modifiers) -> Result<()> {
         // Any key exits git mode (after potentially doing an action)
         self.workspace.fuss.exit_git_mode();
-        self.message = None;
+        self.messages.clear_prompt();
 
         match (&key, &mods) {
             // Git: Stage file (a)
             (Key::Char('a'), _) => {
                 if self.workspace.fuss.stage_selected() {
-                    self.message = Some("Staged".to_string());
+                    self.messages.info("Staged".to_string());
                 } else {
-                    self.message = Some("Failed to stage".to_string());
+                    self.messages.error("Failed to stage".to_string());
                 }
             }
 
             // Git: Unstage file (u)
             (Key::Char('u'), _) => {
                 if self.workspace.fuss.unstage_selected() {
-                    self.message = Some("Unstaged".to_string());
+                    self.messages.info("Unstaged".to_string());
                 } else {
-                    self.message = Some("Failed to unstage".to_string());
+                    self.messages.error("Failed to unstage".to_string());
                 }
             }
 
             // Git: Show diff (d)
             (Key::Char('d'), _) => {
-                if let Some((filename, diff)) = self.workspace.fuss.get_diff_for_selected() {
-                    let display_name = format!("[diff] {}", filename);
-                    self.workspace.open_content_tab(&diff, &display_name);
-                    self.workspace.fuss.deactivate();
-                } else {
-                    self.message = Some("No diff available".to_string());
+                match self.fuss_selected_rel_path() {
+                    Some(path) if !self.workspace.fuss.is_dir_selected() => {
+                        self.open_virtual_document(VirtualDocument::GitDiff { path });
+                    }
+                    _ => self.messages.info("No diff available".to_string()),
+                }
+            }
+
+            // Git: Show log (g) for the selected file or directory
+            (Key::Char('g'), _) => {
+                if let Some(path) = self.fuss_selected_rel_path() {
+                    self.open_git_log(Some(path));
                 }
             }
 
@@ -5012,25 +9964,25 @@ impl Editor {
                     buffer: String::new(),
                     action: TextInputAction::GitCommit,
                 };
-                self.message = Some("Enter commit message (Enter to commit, Esc to cancel)".to_string());
+                self.messages.info("Enter commit message (Enter to commit, Esc to cancel)".to_string());
             }
 
             // Git: Push (p)
             (Key::Char('p'), _) => {
                 let (_, msg) = self.workspace.fuss.git_push();
-                self.message = Some(msg);
+                self.messages.info(msg);
             }
 
             // Git: Pull (l)
             (Key::Char('l'), _) => {
                 let (_, msg) = self.workspace.fuss.git_pull();
-                self.message = Some(msg);
+                self.messages.info(msg);
             }
 
             // Git: Fetch (f)
             (Key::Char('f'), _) => {
                 let (_, msg) = self.workspace.fuss.git_fetch();
-                self.message = Some(msg);
+                self.messages.info(msg);
             }
 
             // Git: Tag (t) - opens prompt for tag name
@@ -5040,7 +9992,7 @@ impl Editor {
                     buffer: String::new(),
                     action: TextInputAction::GitTag,
                 };
-                self.message = Some("Enter tag name (Enter to create, Esc to cancel)".to_string());
+                self.messages.info("Enter tag name (Enter to create, Esc to cancel)".to_string());
             }
 
             // Escape or any other key just cancels git mode
@@ -5053,25 +10005,213 @@ impl Editor {
         self.workspace.open_file(path)
     }
 
+    /// Open the workspace's scratchpad notes, creating them if needed
+    fn open_notes(&mut self) {
+        let result = self.workspace.prepare_notes().and_then(|path| self.open_file(&path));
+        if let Err(e) = result {
+            self.messages.error(format!("Failed to open notes: {}", e));
+        }
+    }
+
+    /// Jump to the current file's counterpart (header/source, test/code),
+    /// offering to create it when it doesn't exist
+    fn alternate_file(&mut self) {
+        let Some(path) = self.current_file_path().filter(|_| self.buffer_entry().virtual_doc.is_none()) else {
+            self.messages.info("No file to switch from".to_string());
+            return;
+        };
+        let Some(alternate) = self.workspace.alternate_of(&path) else {
+            self.messages.info("No alternate file pattern matches".to_string());
+            return;
+        };
+        if alternate.path.exists() {
+            if let Err(e) = self.open_file(&alternate.path) {
+                self.messages.error(format!("Failed to open: {}", e));
+            }
+            return;
+        }
+        self.messages.prompt(format!("{} doesn't exist. [C]reate / [Esc]", self.display_path(&alternate.path)));
+        self.prompt = PromptState::CreateAlternate(alternate);
+    }
+
+    /// `path` relative to the workspace root, for messages
+    fn display_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.workspace.root).unwrap_or(path).display().to_string()
+    }
+
+    /// Write a new alternate file from its template and open it
+    fn create_alternate(&mut self, alternate: &Alternate) -> Result<()> {
+        if let Some(dir) = alternate.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&alternate.path, &alternate.template)?;
+        self.workspace.fuss.reload();
+        self.open_file(&alternate.path)
+    }
+
+    /// Open the commit history panel, limited to `path` (relative to the
+    /// workspace root) when given
+    fn open_git_log(&mut self, path: Option<PathBuf>) {
+        let commits = match git::log::log(&self.workspace.root, path.as_deref()) {
+            Ok(commits) => commits,
+            Err(e) => {
+                self.messages.error(format!("Git log failed: {}", e));
+                return;
+            }
+        };
+        if commits.is_empty() {
+            self.messages.info("No commits".to_string());
+            return;
+        }
+        let title = match path {
+            Some(path) if path != Path::new(".") => format!("Git Log: {}", path.display()),
+            _ => "Git Log".to_string(),
+        };
+        self.workspace.fuss.deactivate();
+        self.prompt = PromptState::GitLog { commits, selected_index: 0, title };
+    }
+
+    /// Run a git action on a commit picked in the log panel
+    fn git_log_action(&mut self, commit: &Commit, cherry_pick: bool) {
+        let root = self.workspace.root.clone();
+        let (result, done) = if cherry_pick {
+            (git::log::cherry_pick(&root, commit), "Cherry-picked")
+        } else {
+            (git::log::checkout(&root, commit), "Checked out")
+        };
+        match result {
+            Ok(()) => {
+                self.prompt = PromptState::None;
+                self.workspace.fuss.refresh_git_status();
+                self.messages.info(format!("{} {} {}", done, commit.short, commit.subject));
+            }
+            Err(e) => self.messages.error(format!("{} failed: {}", if cherry_pick { "Cherry-pick" } else { "Checkout" }, e)),
+        }
+    }
+
+    /// Draw the active image preview tab's picture with the terminal's
+    /// graphics protocol. Skipped while anything could overlap it.
+    fn render_image_preview(&mut self, left_offset: u16, top_offset: u16) -> Result<()> {
+        let path = match &self.buffer_entry().virtual_doc {
+            Some(VirtualDocument::ImagePreview { path }) => path.clone(),
+            _ => return Ok(()),
+        };
+        if self.prompt != PromptState::None || self.terminal.visible || self.server_manager.visible {
+            return Ok(());
+        }
+        let Some(protocol) = GraphicsProtocol::from_setting(&self.workspace.config.image_protocol) else {
+            return Ok(());
+        };
+
+        let line_count = self.buffer().line_count();
+        let col = left_offset + self.screen.line_number_width(line_count) as u16 + 1;
+        let row = top_offset + line_count.saturating_sub(self.viewport_line()) as u16 + 1;
+        let cols = self.screen.cols.saturating_sub(col + 1);
+        let rows = self.screen.rows.saturating_sub(row + 2);
+        if cols < 4 || rows < 2 {
+            return Ok(());
+        }
+
+        let cached = matches!(&self.image_cache, Some((p, c, r, _)) if *p == path && *c == cols && *r == rows);
+        if !cached {
+            let sequence = image::encode(&path, protocol, cols, rows).unwrap_or_else(|e| {
+                self.messages.error(format!("Image preview failed: {}", e));
+                String::new()
+            });
+            self.image_cache = Some((path, cols, rows, sequence));
+        }
+        if let Some((_, _, _, sequence)) = &self.image_cache {
+            if !sequence.is_empty() {
+                self.screen.render_graphics(sequence, col, row)?;
+                self.kitty_images_shown = protocol == GraphicsProtocol::Kitty;
+            }
+        }
+        Ok(())
+    }
+
+    /// Path of the fuss selection relative to the workspace root ("." for the root)
+    fn fuss_selected_rel_path(&self) -> Option<PathBuf> {
+        let path = self.workspace.fuss.selected_path()?;
+        let rel = path.strip_prefix(&self.workspace.root).unwrap_or(&path);
+        Some(if rel.as_os_str().is_empty() { PathBuf::from(".") } else { rel.to_path_buf() })
+    }
+
+    /// Open (or switch to and refresh) a virtual document tab
+    fn open_virtual_document(&mut self, doc: VirtualDocument) {
+        match self.workspace.open_virtual_tab(doc) {
+            Ok(()) => self.workspace.fuss.deactivate(),
+            Err(e) => self.messages.error(format!("Failed to open: {}", e)),
+        }
+    }
+
+    /// Regenerate the active virtual document (diff, log, ...) from its provider
+    fn refresh_virtual_document(&mut self) {
+        if !self.buffer_entry().is_read_only() {
+            self.messages.info("Not a virtual document".to_string());
+            return;
+        }
+        match self.buffer_entry().virtual_doc {
+            Some(VirtualDocument::Statistics { .. }) => self.workspace.save_stats(true),
+            Some(VirtualDocument::Insights) => self.start_insights(),
+            _ => {}
+        }
+        let tab_idx = self.workspace.active_tab;
+        let buffer_idx = self.workspace.active_tab().active_pane().buffer_idx;
+        match self.workspace.refresh_virtual(tab_idx, buffer_idx) {
+            Ok(()) => self.messages.info("Refreshed".to_string()),
+            Err(e) => self.messages.error(format!("Refresh failed: {}", e)),
+        }
+    }
+
     fn open_file_in_vsplit(&mut self, path: &Path) -> Result<()> {
         self.workspace.open_file_in_vsplit(path)?;
-        self.message = Some("Opened in vertical split".to_string());
+        self.messages.info("Opened in vertical split".to_string());
         Ok(())
     }
 
     fn open_file_in_hsplit(&mut self, path: &Path) -> Result<()> {
         self.workspace.open_file_in_hsplit(path)?;
-        self.message = Some("Opened in horizontal split".to_string());
+        self.messages.info("Opened in horizontal split".to_string());
         Ok(())
     }
 
     // === Quit and prompt handling ===
 
+    /// Suspend to the parent shell like a regular job, then restore the
+    /// terminal and repaint once resumed with `fg`
+    #[cfg(unix)]
+    fn suspend(&mut self) -> Result<()> {
+        // Raw mode disables ISIG, so the tty never sends SIGTSTP itself
+        self.publish_status(true);
+        self.screen.leave_raw_mode()?;
+        // Stop the whole process group (language servers included), as the
+        // shell expects; kill() returns once we've been continued by SIGCONT
+        unsafe {
+            libc::kill(0, libc::SIGTSTP);
+        }
+        self.screen.enter_raw_mode()?;
+        self.screen.refresh_size()?;
+        self.terminal.update_screen_size(self.screen.cols, self.screen.rows);
+        self.screen.clear()?;
+        self.messages.clear_prompt();
+        self.publish_status(false);
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn suspend(&mut self) -> Result<()> {
+        self.messages.info("Suspend is not supported on this platform".to_string());
+        Ok(())
+    }
+
     fn try_quit(&mut self) {
-        if self.workspace.has_unsaved_changes() {
+        if let Err(e) = self.workspace.save_notes() {
+            self.messages.error(format!("Failed to save notes: {}", e));
+        }
+        if self.workspaces_mut().any(|workspace| workspace.has_unsaved_changes()) {
             // Show quit confirmation prompt
             self.prompt = PromptState::QuitConfirm;
-            self.message = Some("Unsaved changes. [S]ave all / [D]iscard / [C]ancel".to_string());
+            self.messages.prompt("Unsaved changes. [S]ave all / [D]iscard / [C]ancel".to_string());
         } else {
             // No unsaved changes, quit immediately
             self.running = false;
@@ -5082,64 +10222,265 @@ impl Editor {
         match self.prompt {
             PromptState::QuitConfirm => {
                 match key {
-                    Key::Char('s') | Key::Char('S') => {
-                        // Save all and quit
-                        if let Err(e) = self.workspace.save_all() {
-                            self.message = Some(format!("Save failed: {}", e));
-                        } else {
-                            self.running = false;
+                    Key::Char('s') | Key::Char('S') => {
+                        // Save all and quit
+                        let saved = self.workspaces_mut().try_for_each(|workspace| workspace.save_all());
+                        if let Err(e) = saved {
+                            self.messages.error(format!("Save failed: {}", e));
+                        } else {
+                            self.running = false;
+                        }
+                        self.prompt = PromptState::None;
+                    }
+                    Key::Char('d') | Key::Char('D') => {
+                        // Discard changes and quit - delete backups
+                        for workspace in self.workspaces_mut() {
+                            let _ = workspace.delete_all_backups();
+                        }
+                        self.running = false;
+                        self.prompt = PromptState::None;
+                    }
+                    Key::Char('c') | Key::Char('C') | Key::Escape => {
+                        // Cancel - return to editing
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                    }
+                    _ => {
+                        // Repeat the prompt
+                        self.messages.prompt("Unsaved changes. [S]ave all / [D]iscard / [C]ancel".to_string());
+                    }
+                }
+            }
+            PromptState::CloseBufferConfirm => {
+                match key {
+                    Key::Char('s') | Key::Char('S') => {
+                        // Save and close
+                        self.prompt = PromptState::None;
+                        if let Err(e) = self.save() {
+                            self.prompt = PromptState::CloseBufferConfirm;
+                            self.messages.error(format!("Save failed: {}", e));
+                        } else if self.prompt == PromptState::None {
+                            self.close_pane_force();
+                        }
+                    }
+                    Key::Char('d') | Key::Char('D') => {
+                        // Discard changes - delete backup for this buffer and close
+                        if let Some(path) = self.buffer_entry().path.clone() {
+                            let full_path = if self.buffer_entry().is_orphan {
+                                path.clone()
+                            } else {
+                                self.workspace.root.join(&path)
+                            };
+                            let _ = self.workspace.delete_backup(&full_path);
+                        }
+                        self.prompt = PromptState::None;
+                        self.close_pane_force();
+                    }
+                    Key::Char('c') | Key::Char('C') | Key::Escape => {
+                        // Cancel - return to editing
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                    }
+                    _ => {
+                        // Repeat the prompt
+                        self.messages.prompt("Unsaved changes. [S]ave / [D]iscard / [C]ancel".to_string());
+                    }
+                }
+            }
+            PromptState::DeepScan(ref command) => {
+                match key {
+                    Key::Char('y') | Key::Char('Y') => {
+                        let command = command.clone();
+                        self.prompt = PromptState::None;
+                        self.workspace.allow_deep_scan();
+                        self.execute_command(&command);
+                    }
+                    Key::Char('n') | Key::Char('N') | Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.info("Exclude big directories from the file tree (Alt+X) to scan the rest".to_string());
+                    }
+                    _ => self.messages.prompt(self.deep_scan_question()),
+                }
+            }
+            PromptState::LargePaste { ref text, mode } => {
+                let text = text.clone();
+                match key {
+                    Key::Char('p') | Key::Char('P') => {
+                        self.prompt = PromptState::None;
+                        if mode == PasteMode::OverLines {
+                            self.paste_over_lines(&text);
+                        } else {
+                            self.stream_paste(&text)?;
+                        }
+                    }
+                    Key::Char('s') | Key::Char('S') => {
+                        self.prompt = PromptState::None;
+                        self.paste_into_scratch(&text);
+                    }
+                    Key::Char('c') | Key::Char('C') | Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                    }
+                    _ => self.messages.prompt(large_paste_question(&text)),
+                }
+            }
+            PromptState::DiskConflict(ref path) => {
+                let path = path.clone();
+                match key {
+                    Key::Char('r') | Key::Char('R') => {
+                        self.prompt = PromptState::None;
+                        match self.reload_from_disk(&path) {
+                            Ok(()) => self.messages.info(format!("Reloaded {}; undo brings your edits back", self.display_path(&path))),
+                            Err(e) => self.messages.error(format!("Failed to reload: {}", e)),
+                        }
+                    }
+                    Key::Char('k') | Key::Char('K') | Key::Escape => {
+                        self.prompt = PromptState::None;
+                        for (tab_idx, buffer_idx) in self.workspace.buffers_of_file(&path) {
+                            self.workspace.tabs[tab_idx].buffers[buffer_idx].accept_disk(&path);
+                        }
+                        self.messages.info("Kept your edits; saving overwrites the file on disk".to_string());
+                    }
+                    Key::Char('d') | Key::Char('D') => {
+                        self.prompt = PromptState::None;
+                        self.diff_with_disk(&path);
+                    }
+                    _ => self.messages.prompt(self.disk_conflict_question(&path)),
+                }
+            }
+            PromptState::SaveConflict(ref path) => {
+                let path = path.clone();
+                match key {
+                    Key::Char('o') | Key::Char('O') => {
+                        self.prompt = PromptState::None;
+                        if let Err(e) = self.save_to(&path) {
+                            self.messages.error(format!("Save failed: {}", e));
+                        }
+                    }
+                    Key::Char('d') | Key::Char('D') => {
+                        self.prompt = PromptState::None;
+                        self.diff_with_disk(&path);
+                    }
+                    Key::Char('a') | Key::Char('A') => {
+                        self.prompt = PromptState::None;
+                        self.prompt_save_as();
+                    }
+                    Key::Char('c') | Key::Char('C') | Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                    }
+                    _ => self.messages.prompt(self.save_conflict_question(&path)),
+                }
+            }
+            PromptState::SavePermission(ref path) => {
+                let path = path.clone();
+                match key {
+                    Key::Char('s') | Key::Char('S') if elevate::helper().is_some() => {
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                        self.save_as_root(&path);
+                    }
+                    Key::Char('a') | Key::Char('A') => {
+                        self.prompt = PromptState::None;
+                        self.prompt_save_elsewhere(&path);
+                    }
+                    Key::Char('c') | Key::Char('C') | Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                    }
+                    _ => self.messages.prompt(self.save_permission_question(&path)),
+                }
+            }
+            PromptState::AnsiCodes => {
+                match key {
+                    Key::Char('c') | Key::Char('C') => {
+                        self.prompt = PromptState::None;
+                        self.toggle_ansi_colors();
+                    }
+                    Key::Char('s') | Key::Char('S') => {
+                        self.prompt = PromptState::None;
+                        self.strip_ansi_codes();
+                    }
+                    Key::Char('k') | Key::Char('K') | Key::Escape => {
+                        self.prompt = PromptState::None;
+                    }
+                    _ => self.messages.prompt(ANSI_PROMPT.to_string()),
+                }
+            }
+            PromptState::CreateAlternate(ref alternate) => {
+                match key {
+                    Key::Char('c') | Key::Char('C') => {
+                        let alternate = alternate.clone();
+                        self.prompt = PromptState::None;
+                        match self.create_alternate(&alternate) {
+                            Ok(()) => self.messages.info(format!("Created {}", self.display_path(&alternate.path))),
+                            Err(e) => self.messages.error(format!("Failed to create: {}", e)),
                         }
+                    }
+                    Key::Escape => {
                         self.prompt = PromptState::None;
                     }
-                    Key::Char('d') | Key::Char('D') => {
-                        // Discard changes and quit - delete backups
-                        let _ = self.workspace.delete_all_backups();
-                        self.running = false;
+                    _ => {
+                        let name = self.display_path(&alternate.path);
+                        self.messages.prompt(format!("{} doesn't exist. [C]reate / [Esc]", name));
+                    }
+                }
+            }
+            PromptState::WorkspaceEditConflict { ref edit, ref verb, ref conflicts } => {
+                match key {
+                    Key::Char('a') | Key::Char('A') => {
+                        let edit = edit.clone();
+                        let verb = verb.clone();
                         self.prompt = PromptState::None;
+                        let outcome = self.commit_workspace_edit(&edit, &verb);
+                        if let Some(reply) = self.lsp_state.edit_reply.take() {
+                            let _ = reply.send(outcome);
+                        }
                     }
                     Key::Char('c') | Key::Char('C') | Key::Escape => {
-                        // Cancel - return to editing
                         self.prompt = PromptState::None;
-                        self.message = None;
+                        self.messages.info("Edit cancelled".to_string());
+                        if let Some(reply) = self.lsp_state.edit_reply.take() {
+                            let _ = reply.send(Err("cancelled by the user".to_string()));
+                        }
                     }
                     _ => {
                         // Repeat the prompt
-                        self.message = Some("Unsaved changes. [S]ave all / [D]iscard / [C]ancel".to_string());
+                        self.messages.info(Self::workspace_edit_conflict_message(conflicts));
                     }
                 }
             }
-            PromptState::CloseBufferConfirm => {
+            PromptState::WorkspaceLocked(pid) => {
                 match key {
+                    Key::Char('r') | Key::Char('R') => {
+                        self.workspace.set_lock_mode(LockMode::ReadOnly);
+                        self.prompt = PromptState::None;
+                        self.messages.info("Opened read-only".to_string());
+                    }
                     Key::Char('s') | Key::Char('S') => {
-                        // Save and close
-                        if let Err(e) = self.save() {
-                            self.message = Some(format!("Save failed: {}", e));
-                        } else {
-                            self.prompt = PromptState::None;
-                            self.close_pane_force();
+                        self.prompt = PromptState::None;
+                        match self.workspace.steal_lock() {
+                            Ok(()) if self.workspace.has_backups() => {
+                                self.prompt = PromptState::RestoreBackup;
+                                self.messages.prompt("Recovered unsaved changes. [R]estore / [D]iscard / [Esc]".to_string());
+                            }
+                            Ok(()) => self.messages.info(format!("Took over workspace from PID {}", pid)),
+                            Err(e) => {
+                                self.workspace.set_lock_mode(LockMode::NoPersistence);
+                                self.messages.error(format!("Failed to take lock: {}", e));
+                            }
                         }
                     }
-                    Key::Char('d') | Key::Char('D') => {
-                        // Discard changes - delete backup for this buffer and close
-                        if let Some(path) = self.buffer_entry().path.clone() {
-                            let full_path = if self.buffer_entry().is_orphan {
-                                path.clone()
-                            } else {
-                                self.workspace.root.join(&path)
-                            };
-                            let _ = self.workspace.delete_backup(&full_path);
-                        }
+                    Key::Char('o') | Key::Char('O') | Key::Escape => {
+                        self.workspace.set_lock_mode(LockMode::NoPersistence);
                         self.prompt = PromptState::None;
-                        self.close_pane_force();
+                        self.messages.info("Workspace state and backups won't be saved".to_string());
                     }
-                    Key::Char('c') | Key::Char('C') | Key::Escape => {
-                        // Cancel - return to editing
-                        self.prompt = PromptState::None;
-                        self.message = None;
+                    Key::Char('q') | Key::Char('Q') => {
+                        self.running = false;
                     }
                     _ => {
-                        // Repeat the prompt
-                        self.message = Some("Unsaved changes. [S]ave / [D]iscard / [C]ancel".to_string());
+                        self.messages.info(workspace_locked_message(pid));
                     }
                 }
             }
@@ -5148,21 +10489,21 @@ impl Editor {
                     Key::Char('r') | Key::Char('R') => {
                         // Restore backups
                         if let Err(e) = self.restore_backups() {
-                            self.message = Some(format!("Restore failed: {}", e));
+                            self.messages.error(format!("Restore failed: {}", e));
                         } else {
-                            self.message = Some("Restored unsaved changes".to_string());
+                            self.messages.info("Restored unsaved changes".to_string());
                         }
                         self.prompt = PromptState::None;
                     }
                     Key::Char('d') | Key::Char('D') | Key::Escape => {
                         // Discard backups (Escape = discard)
                         let _ = self.workspace.delete_all_backups();
-                        self.message = Some("Discarded recovered changes".to_string());
+                        self.messages.info("Discarded recovered changes".to_string());
                         self.prompt = PromptState::None;
                     }
                     _ => {
                         // Repeat the prompt
-                        self.message = Some("Recovered unsaved changes. [R]estore / [D]iscard / [Esc]".to_string());
+                        self.messages.prompt("Recovered unsaved changes. [R]estore / [D]iscard / [Esc]".to_string());
                     }
                 }
             }
@@ -5176,67 +10517,430 @@ impl Editor {
                         self.execute_text_input_action(action, &buffer);
                     }
                     Key::Escape => {
-                        // Cancel
+                        // Cancel
+                        self.prompt = PromptState::None;
+                        self.messages.info("Cancelled".to_string());
+                    }
+                    Key::Backspace => {
+                        // Delete last character
+                        buffer.pop();
+                        self.messages.info(format!("{}{}", label, buffer));
+                    }
+                    Key::Up | Key::Down if action.is_shell_command() && !self.shell_history.is_empty() => {
+                        // Browse recent shell commands
+                        let last = self.shell_history.len() - 1;
+                        let pos = match (key, self.shell_history_pos) {
+                            (Key::Up, None) => Some(last),
+                            (Key::Up, Some(p)) => Some(p.saturating_sub(1)),
+                            (_, Some(p)) if p < last => Some(p + 1),
+                            _ => None,
+                        };
+                        self.shell_history_pos = pos;
+                        *buffer = pos.map(|p| self.shell_history[p].clone()).unwrap_or_default();
+                        self.messages.info(format!("{}{}", label, buffer));
+                    }
+                    Key::Char(c) => {
+                        // Add character to buffer
+                        buffer.push(c);
+                        self.messages.info(format!("{}{}", label, buffer));
+                    }
+                    _ => {
+                        // Update display
+                        self.messages.info(format!("{}{}", label, buffer));
+                    }
+                }
+            }
+            PromptState::RenameModal { ref original_name, ref mut new_name, ref path, line, col } => {
+                match key {
+                    Key::Enter => {
+                        // Clone values before modifying self.prompt
+                        let original = original_name.clone();
+                        let new = new_name.clone();
+                        let path = path.clone();
+
+                        // Execute rename
+                        if new.is_empty() {
+                            self.prompt = PromptState::None;
+                            self.messages.info("Rename cancelled: empty name".to_string());
+                        } else if new == original {
+                            self.prompt = PromptState::None;
+                            self.messages.info("Rename cancelled: name unchanged".to_string());
+                        } else {
+                            self.prompt = PromptState::None;
+                            match self.workspace.lsp.request_rename(&path, line, col, &new) {
+                                Ok(_id) => {
+                                    self.messages.progress(format!("Renaming '{}' to '{}'...", original, new));
+                                }
+                                Err(e) => {
+                                    self.messages.error(format!("Rename failed: {}", e));
+                                }
+                            }
+                        }
+                    }
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.info("Rename cancelled".to_string());
+                    }
+                    Key::Backspace => {
+                        new_name.pop();
+                    }
+                    Key::Char(c) => {
+                        new_name.push(c);
+                    }
+                    _ => {}
+                }
+            }
+            PromptState::MarkKey(action) => {
+                self.prompt = PromptState::None;
+                match key {
+                    Key::Char(c) if c.is_ascii_alphabetic() => match action {
+                        MarkAction::Set => self.set_mark(c),
+                        MarkAction::JumpLine => self.jump_to_mark(c, false),
+                        MarkAction::JumpExact => self.jump_to_mark(c, true),
+                    },
+                    Key::Escape => self.messages.clear_prompt(),
+                    _ => self.messages.info("Marks are letters a-z (local) or A-Z (global)".to_string()),
+                }
+            }
+            PromptState::MarksPanel { ref mut entries, ref mut selected_index } => {
+                match key {
+                    Key::Enter => {
+                        if let Some(&(name, _, _)) = entries.get(*selected_index) {
+                            self.prompt = PromptState::None;
+                            self.jump_to_mark(name, true);
+                        }
+                    }
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                    }
+                    Key::Up => {
+                        *selected_index = selected_index.saturating_sub(1);
+                    }
+                    Key::Down if *selected_index + 1 < entries.len() => {
+                        *selected_index += 1;
+                    }
+                    Key::Delete | Key::Backspace if *selected_index < entries.len() => {
+                        let (name, _, _) = entries.remove(*selected_index);
+                        *selected_index = (*selected_index).min(entries.len().saturating_sub(1));
+                        let now_empty = entries.is_empty();
+                        self.delete_mark(name);
+                        if now_empty {
+                            self.prompt = PromptState::None;
+                        }
+                    }
+                    // Jump straight to a listed mark by its letter
+                    Key::Char(c) if entries.iter().any(|(name, _, _)| *name == c) => {
+                        self.prompt = PromptState::None;
+                        self.jump_to_mark(c, true);
+                    }
+                    _ => {}
+                }
+            }
+            PromptState::BookmarksPanel { ref mut entries, ref mut selected_index } => {
+                match key {
+                    Key::Enter => {
+                        if let Some((bookmark, _)) = entries.get(*selected_index).cloned() {
+                            self.prompt = PromptState::None;
+                            self.goto_bookmark(&bookmark);
+                        }
+                    }
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                    }
+                    Key::Up => {
+                        *selected_index = selected_index.saturating_sub(1);
+                    }
+                    Key::Down if *selected_index + 1 < entries.len() => {
+                        *selected_index += 1;
+                    }
+                    Key::Delete | Key::Backspace if *selected_index < entries.len() => {
+                        let (bookmark, _) = entries.remove(*selected_index);
+                        *selected_index = (*selected_index).min(entries.len().saturating_sub(1));
+                        if entries.is_empty() {
+                            self.prompt = PromptState::None;
+                        }
+                        self.workspace.bookmarks.remove(&bookmark.path, bookmark.line);
+                        self.messages.info(format!("Removed bookmark {}:{}", bookmark.path.display(), bookmark.line + 1));
+                    }
+                    _ => {}
+                }
+            }
+            PromptState::CodeActions { ref actions, ref mut selected_index } => {
+                match key {
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                    }
+                    Key::Up => {
+                        *selected_index = selected_index.checked_sub(1).unwrap_or(actions.len() - 1);
+                    }
+                    Key::Down => {
+                        *selected_index = (*selected_index + 1) % actions.len();
+                    }
+                    Key::Enter => {
+                        let action = actions[*selected_index].clone();
+                        self.prompt = PromptState::None;
+                        self.apply_code_action(action);
+                    }
+                    // 1-9 picks an action directly
+                    Key::Char(c @ '1'..='9') => {
+                        if let Some(action) = actions.get(c as usize - '1' as usize).cloned() {
+                            self.prompt = PromptState::None;
+                            self.apply_code_action(action);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            PromptState::StringsPanel { ref mut literals, ref mut selected_index, ref mut template } => {
+                match key {
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                        return Ok(());
+                    }
+                    Key::Enter => {
+                        let literals = std::mem::take(literals);
+                        let template = std::mem::take(template);
+                        self.prompt = PromptState::None;
+                        self.replace_string_literals(&literals, template);
+                        return Ok(());
+                    }
+                    Key::Up => *selected_index = selected_index.saturating_sub(1),
+                    Key::Down if *selected_index + 1 < literals.len() => *selected_index += 1,
+                    Key::PageUp => *selected_index = selected_index.saturating_sub(10),
+                    Key::PageDown => {
+                        *selected_index = (*selected_index + 10).min(literals.len().saturating_sub(1));
+                    }
+                    Key::Tab => {
+                        if let Some(literal) = literals.get_mut(*selected_index) {
+                            literal.included = !literal.included;
+                        }
+                        if *selected_index + 1 < literals.len() {
+                            *selected_index += 1;
+                        }
+                    }
+                    Key::Backspace => {
+                        template.pop();
+                    }
+                    Key::Char(c) => template.push(c),
+                    _ => {}
+                }
+                // Follow the selection in the editor, quickfix style
+                if let Some(literal) = literals.get(*selected_index) {
+                    let (line, col) = (literal.line, literal.start_col);
+                    self.cursor_mut().set(line, col);
+                    self.scroll_to_cursor();
+                }
+            }
+            PromptState::ReplacedLines { ref mut lines, ref mut selected_index } => {
+                match key {
+                    Key::Enter => {
+                        if let Some(line) = lines.get(*selected_index).map(|l| l.line) {
+                            self.prompt = PromptState::None;
+                            self.cursors_mut().collapse_to_primary();
+                            self.cursor_mut().set(line, 0);
+                            self.scroll_to_cursor();
+                        }
+                    }
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                    }
+                    Key::Up => {
+                        *selected_index = selected_index.saturating_sub(1);
+                    }
+                    Key::Down if *selected_index + 1 < lines.len() => {
+                        *selected_index += 1;
+                    }
+                    Key::PageUp => {
+                        *selected_index = selected_index.saturating_sub(10);
+                    }
+                    Key::PageDown => {
+                        *selected_index = (*selected_index + 10).min(lines.len().saturating_sub(1));
+                    }
+                    Key::Char('r') | Key::Delete | Key::Backspace => {
+                        let index = *selected_index;
+                        if let Some(replaced) = lines.get(index).filter(|l| !l.reverted).cloned() {
+                            let mut lines = std::mem::take(lines);
+                            if self.revert_replaced_line(&replaced) {
+                                lines[index].reverted = true;
+                                self.messages.info(format!("Reverted line {}", replaced.line + 1));
+                            } else {
+                                self.messages.error(format!("Line {} changed since the replace", replaced.line + 1));
+                            }
+                            let selected_index = (index + 1).min(lines.len() - 1);
+                            self.prompt = PromptState::ReplacedLines { lines, selected_index };
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            PromptState::DiagnosticsPanel { ref diagnostics, ref mut selected_index } => {
+                match key {
+                    Key::Enter => {
+                        if let Some((uri, diagnostic)) = diagnostics.get(*selected_index) {
+                            let location = Location { uri: uri.clone(), range: diagnostic.range };
+                            self.prompt = PromptState::None;
+                            self.goto_location(&location);
+                        }
+                    }
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                    }
+                    Key::Up => {
+                        *selected_index = selected_index.saturating_sub(1);
+                    }
+                    Key::Down if *selected_index + 1 < diagnostics.len() => {
+                        *selected_index += 1;
+                    }
+                    Key::PageUp => {
+                        *selected_index = selected_index.saturating_sub(10);
+                    }
+                    Key::PageDown => {
+                        *selected_index = (*selected_index + 10).min(diagnostics.len().saturating_sub(1));
+                    }
+                    Key::Home => {
+                        *selected_index = 0;
+                    }
+                    Key::End => {
+                        *selected_index = diagnostics.len().saturating_sub(1);
+                    }
+                    _ => {}
+                }
+            }
+            PromptState::ClipboardRing { ref mut selected_index } => {
+                let count = self.workspace.clipboard_entries().len();
+                let chosen = match key {
+                    Key::Char('*') => {
+                        // Pin or unpin, keeping the entry selected where it moves to
+                        let entries = self.workspace.clipboard_entries();
+                        if let Some(text) = entries.get(*selected_index).map(|(text, _)| text.to_string()) {
+                            let pinned = self.workspace.toggle_pinned_clip(&text);
+                            let entries = self.workspace.clipboard_entries();
+                            *selected_index = entries.iter().position(|(t, _)| *t == text).unwrap_or(0);
+                            self.messages.info(if pinned { "Pinned" } else { "Unpinned" }.to_string());
+                        }
+                        None
+                    }
+                    Key::Enter => Some((*selected_index, PasteMode::Indented)),
+                    Key::Char('p') => Some((*selected_index, PasteMode::Plain)),
+                    Key::Char('s') => Some((*selected_index, PasteMode::Select)),
+                    Key::Char('l') => Some((*selected_index, PasteMode::OverLines)),
+                    Key::Char(c @ '1'..='9') => Some((c as usize - '1' as usize, PasteMode::Indented)),
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.messages.clear_prompt();
+                        None
+                    }
+                    Key::Up => {
+                        *selected_index = selected_index.saturating_sub(1);
+                        None
+                    }
+                    Key::Down if *selected_index + 1 < count => {
+                        *selected_index += 1;
+                        None
+                    }
+                    Key::PageUp => {
+                        *selected_index = selected_index.saturating_sub(10);
+                        None
+                    }
+                    Key::PageDown => {
+                        *selected_index = (*selected_index + 10).min(count.saturating_sub(1));
+                        None
+                    }
+                    Key::Home => {
+                        *selected_index = 0;
+                        None
+                    }
+                    Key::End => {
+                        *selected_index = count.saturating_sub(1);
+                        None
+                    }
+                    _ => None,
+                };
+                if let Some((index, mode)) = chosen {
+                    if let Some(text) = self.workspace.clipboard_entries().get(index).map(|(text, _)| text.to_string()) {
+                        self.prompt = PromptState::None;
+                        self.paste_text(&text, mode);
+                        self.scroll_to_cursor();
+                    }
+                }
+            }
+            PromptState::RecoverVersion { ref versions, ref mut selected_index } => {
+                match key {
+                    Key::Enter => {
+                        if let Some((generation, text)) = versions.get(*selected_index).cloned() {
+                            self.prompt = PromptState::None;
+                            self.restore_version(&generation, text);
+                        }
+                    }
+                    Key::Escape => {
                         self.prompt = PromptState::None;
-                        self.message = Some("Cancelled".to_string());
+                        self.messages.clear_prompt();
                     }
-                    Key::Backspace => {
-                        // Delete last character
-                        buffer.pop();
-                        self.message = Some(format!("{}{}", label, buffer));
+                    Key::Up => {
+                        *selected_index = selected_index.saturating_sub(1);
                     }
-                    Key::Char(c) => {
-                        // Add character to buffer
-                        buffer.push(c);
-                        self.message = Some(format!("{}{}", label, buffer));
+                    Key::Down if *selected_index + 1 < versions.len() => {
+                        *selected_index += 1;
                     }
-                    _ => {
-                        // Update display
-                        self.message = Some(format!("{}{}", label, buffer));
+                    Key::PageUp => {
+                        *selected_index = selected_index.saturating_sub(10);
+                    }
+                    Key::PageDown => {
+                        *selected_index = (*selected_index + 10).min(versions.len().saturating_sub(1));
+                    }
+                    Key::Home => {
+                        *selected_index = 0;
                     }
+                    Key::End => {
+                        *selected_index = versions.len().saturating_sub(1);
+                    }
+                    _ => {}
                 }
             }
-            PromptState::RenameModal { ref original_name, ref mut new_name, ref path, line, col } => {
+            PromptState::GitLog { ref commits, ref mut selected_index, .. } => {
                 match key {
                     Key::Enter => {
-                        // Clone values before modifying self.prompt
-                        let original = original_name.clone();
-                        let new = new_name.clone();
-                        let path = path.clone();
-
-                        // Execute rename
-                        if new.is_empty() {
-                            self.prompt = PromptState::None;
-                            self.message = Some("Rename cancelled: empty name".to_string());
-                        } else if new == original {
-                            self.prompt = PromptState::None;
-                            self.message = Some("Rename cancelled: name unchanged".to_string());
-                        } else {
+                        if let Some(commit) = commits.get(*selected_index) {
+                            let doc = VirtualDocument::GitShow { commit: commit.hash.clone() };
                             self.prompt = PromptState::None;
-                            match self.workspace.lsp.request_rename(&path, line, col, &new) {
-                                Ok(_id) => {
-                                    self.message = Some(format!("Renaming '{}' to '{}'...", original, new));
-                                }
-                                Err(e) => {
-                                    self.message = Some(format!("Rename failed: {}", e));
-                                }
-                            }
+                            self.open_virtual_document(doc);
                         }
                     }
                     Key::Escape => {
                         self.prompt = PromptState::None;
-                        self.message = Some("Rename cancelled".to_string());
+                        self.messages.clear_prompt();
                     }
-                    Key::Backspace => {
-                        new_name.pop();
+                    Key::Up => {
+                        *selected_index = selected_index.saturating_sub(1);
                     }
-                    Key::Char(c) => {
-                        new_name.push(c);
+                    Key::Down if *selected_index + 1 < commits.len() => {
+                        *selected_index += 1;
+                    }
+                    Key::PageUp => {
+                        *selected_index = selected_index.saturating_sub(10);
+                    }
+                    Key::PageDown => {
+                        *selected_index = (*selected_index + 10).min(commits.len().saturating_sub(1));
+                    }
+                    Key::Home => {
+                        *selected_index = 0;
+                    }
+                    Key::End => {
+                        *selected_index = commits.len().saturating_sub(1);
+                    }
+                    Key::Char(c @ ('c' | 'p')) => {
+                        if let Some(commit) = commits.get(*selected_index).cloned() {
+                            self.git_log_action(&commit, c == 'p');
+                        }
                     }
                     _ => {}
                 }
             }
-            PromptState::ReferencesPanel { ref locations, ref mut selected_index, ref mut query } => {
+            PromptState::ReferencesPanel { ref locations, ref mut selected_index, ref mut query, .. } => {
                 // Filter locations based on query
                 let filtered: Vec<(usize, &Location)> = if query.is_empty() {
                     locations.iter().enumerate().collect()
@@ -5260,7 +10964,7 @@ impl Editor {
                     }
                     Key::Escape => {
                         self.prompt = PromptState::None;
-                        self.message = None;
+                        self.messages.clear_prompt();
                     }
                     Key::Up => {
                         if *selected_index > 0 {
@@ -5310,7 +11014,7 @@ impl Editor {
                     Key::Escape => {
                         self.prompt = PromptState::None;
                         self.search_state.matches.clear();
-                        self.message = None;
+                        self.messages.clear_prompt();
                     }
                     Key::Enter => {
                         if *active_field == FindReplaceField::Find {
@@ -5384,6 +11088,18 @@ impl Editor {
                 };
 
                 match key {
+                    Key::Enter if is_rooted(filter) || filter.contains('/') => {
+                        // A typed path goes where it names, even outside the workspace
+                        let path = resolve_path(current_path, filter);
+                        if path.is_dir() {
+                            self.fortress_navigate_to(&path);
+                        } else if path.is_file() {
+                            self.prompt = PromptState::None;
+                            self.fortress_open_file(&path);
+                        } else {
+                            self.messages.error(format!("No such file or directory: {}", path.display()));
+                        }
+                    }
                     Key::Enter => {
                         // Open selected entry
                         if let Some((orig_idx, _entry)) = filtered.get(*selected_index) {
@@ -5400,7 +11116,7 @@ impl Editor {
                     }
                     Key::Escape => {
                         self.prompt = PromptState::None;
-                        self.message = None;
+                        self.messages.clear_prompt();
                     }
                     Key::Backspace if filter.is_empty() => {
                         // Go up one directory when filter is empty
@@ -5474,30 +11190,45 @@ impl Editor {
                 ref mut results,
                 ref mut selected_index,
                 ref mut scroll_offset,
-                searching: _,
+                ref mut searching,
+                ref mut open_files,
             } => {
                 match key {
+                    Key::Tab => {
+                        *open_files = !*open_files;
+                        results.clear();
+                        *selected_index = 0;
+                        *scroll_offset = 0;
+                        *searching = false;
+                        self.file_search = None;
+                    }
+                    Key::Enter if *open_files && !query.is_empty() => {
+                        let query = query.clone();
+                        self.find_in_open_files(&query);
+                    }
+                    Key::Enter if *open_files => {}
                     Key::Enter => {
-                        if !query.is_empty() && results.is_empty() {
-                            // Trigger search - clone query first to avoid borrow conflict
-                            let query_str = query.clone();
-                            let new_results = self.search_files(&query_str);
-                            // Re-borrow after search
-                            if let PromptState::FileSearch { results, selected_index, scroll_offset, .. } = &mut self.prompt {
-                                *results = new_results;
-                                *selected_index = 0;
-                                *scroll_offset = 0;
-                            }
-                        } else if !results.is_empty() {
+                        if !results.is_empty() {
                             // Open selected result
                             let result = results[*selected_index].clone();
                             self.prompt = PromptState::None;
                             self.file_search_open_result(&result);
+                        } else if !query.is_empty() && self.file_search.is_none() {
+                            let query = query.clone();
+                            self.start_file_search(&query);
                         }
                     }
                     Key::Escape => {
-                        self.prompt = PromptState::None;
-                        self.message = None;
+                        // Escape stops a running search, keeping what it found
+                        if let Some(search) = self.file_search.take() {
+                            search.cancel();
+                            if let PromptState::FileSearch { searching, .. } = &mut self.prompt {
+                                *searching = false;
+                            }
+                        } else {
+                            self.prompt = PromptState::None;
+                            self.messages.clear_prompt();
+                        }
                     }
                     Key::Backspace => {
                         if !query.is_empty() {
@@ -5506,6 +11237,8 @@ impl Editor {
                             results.clear();
                             *selected_index = 0;
                             *scroll_offset = 0;
+                            *searching = false;
+                            self.file_search = None;
                         }
                     }
                     Key::Up => {
@@ -5544,12 +11277,73 @@ impl Editor {
                         results.clear();
                         *selected_index = 0;
                         *scroll_offset = 0;
+                        *searching = false;
+                        self.file_search = None;
+                    }
+                    _ => {}
+                }
+            }
+            PromptState::LiveGrep {
+                ref mut query,
+                ref mut types,
+                ref mut editing_types,
+                ref results,
+                ref mut selected_index,
+                ref mut scroll_offset,
+                ..
+            } => {
+                let input = if *editing_types { types } else { query };
+                match key {
+                    Key::Tab => *editing_types = !*editing_types,
+                    Key::Enter if *selected_index < results.len() => {
+                        let result = results[*selected_index].clone();
+                        self.prompt = PromptState::None;
+                        self.file_search = None;
+                        self.file_search_open_result(&result);
+                    }
+                    // Escape stops a running search, keeping what it found
+                    Key::Escape if self.file_search.is_some() => {
+                        self.file_search = None;
+                        if let PromptState::LiveGrep { searching, .. } = &mut self.prompt {
+                            *searching = false;
+                        }
+                    }
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                        self.live_grep_due = None;
+                        self.messages.clear_prompt();
+                    }
+                    Key::Backspace if !input.is_empty() => {
+                        input.pop();
+                        self.live_grep_changed();
+                    }
+                    Key::Char(c) => {
+                        input.push(c);
+                        self.live_grep_changed();
+                    }
+                    Key::Up => {
+                        *selected_index = selected_index.saturating_sub(1);
+                        *scroll_offset = (*scroll_offset).min(*selected_index);
+                    }
+                    Key::Down => *selected_index = (*selected_index + 1).min(results.len().saturating_sub(1)),
+                    Key::PageUp => {
+                        *selected_index = selected_index.saturating_sub(10);
+                        *scroll_offset = scroll_offset.saturating_sub(10);
+                    }
+                    Key::PageDown => {
+                        *selected_index = (*selected_index + 10).min(results.len().saturating_sub(1));
+                    }
+                    Key::Home => {
+                        *selected_index = 0;
+                        *scroll_offset = 0;
                     }
+                    Key::End => *selected_index = results.len().saturating_sub(1),
                     _ => {}
                 }
             }
             PromptState::CommandPalette {
                 ref mut query,
+                ref context,
                 ref mut filtered,
                 ref mut selected_index,
                 ref mut scroll_offset,
@@ -5562,8 +11356,16 @@ impl Editor {
                         // Execute selected command
                         if let Some(cmd) = filtered.get(*selected_index) {
                             let cmd_id = cmd.id.to_string();
+                            let result = query.strip_prefix('=').and_then(|expr| calc::evaluate(expr).ok());
                             self.prompt = PromptState::None;
-                            self.execute_command(&cmd_id);
+                            match (cmd_id.as_str(), result) {
+                                ("calc-insert", Some(result)) => self.insert_calc_result(&result),
+                                ("calc-copy", Some(result)) => {
+                                    self.messages.info(format!("Copied {}", result));
+                                    self.set_clipboard(result);
+                                }
+                                _ => self.execute_command(&cmd_id),
+                            }
                             self.scroll_to_cursor(); // Ensure viewport follows cursor after command
                         } else {
                             self.prompt = PromptState::None;
@@ -5603,19 +11405,144 @@ impl Editor {
                     Key::Backspace => {
                         if !query.is_empty() {
                             query.pop();
-                            *filtered = filter_commands(query);
+                            *filtered = filter_commands(query, context, &self.workspace.keymap);
                             *selected_index = 0;
                             *scroll_offset = 0;
                         }
                     }
                     Key::Char(c) => {
                         query.push(c);
-                        *filtered = filter_commands(query);
+                        *filtered = filter_commands(query, context, &self.workspace.keymap);
+                        *selected_index = 0;
+                        *scroll_offset = 0;
+                    }
+                    _ => {}
+                }
+            }
+            PromptState::QuickOpen {
+                ref mut query,
+                ref files,
+                ref mut filtered,
+                ref mut selected_index,
+                ref mut scroll_offset,
+            } => {
+                let visible_rows = 15;
+                match key {
+                    Key::Escape => {
+                        self.prompt = PromptState::None;
+                    }
+                    Key::Enter => {
+                        // A typed path opens directly when it names its own
+                        // location or nothing indexed matches it
+                        let typed = (is_rooted(query) || filtered.is_empty())
+                            .then(|| resolve_path(&self.workspace.root, query))
+                            .filter(|path| path.is_file());
+                        let chosen = typed.or_else(|| filtered.get(*selected_index).cloned());
+                        self.prompt = PromptState::None;
+                        if let Some(path) = chosen {
+                            self.quick_open_file(&path, OpenTarget::Pane)?;
+                        }
+                    }
+                    Key::Up if *selected_index > 0 => {
+                        *selected_index -= 1;
+                        if *selected_index < *scroll_offset {
+                            *scroll_offset = *selected_index;
+                        }
+                    }
+                    Key::Down if *selected_index + 1 < filtered.len() => {
+                        *selected_index += 1;
+                        if *selected_index >= *scroll_offset + visible_rows {
+                            *scroll_offset = selected_index.saturating_sub(visible_rows - 1);
+                        }
+                    }
+                    Key::PageUp => {
+                        *selected_index = selected_index.saturating_sub(visible_rows);
+                        *scroll_offset = (*scroll_offset).min(*selected_index);
+                    }
+                    Key::PageDown => {
+                        *selected_index = (*selected_index + visible_rows).min(filtered.len().saturating_sub(1));
+                        if *selected_index >= *scroll_offset + visible_rows {
+                            *scroll_offset = selected_index.saturating_sub(visible_rows - 1);
+                        }
+                    }
+                    Key::Backspace if !query.is_empty() => {
+                        query.pop();
+                        *filtered = filter_files(query, files, &self.workspace.recent_files);
+                        *selected_index = 0;
+                        *scroll_offset = 0;
+                    }
+                    Key::Char(c) => {
+                        query.push(c);
+                        *filtered = filter_files(query, files, &self.workspace.recent_files);
+                        *selected_index = 0;
+                        *scroll_offset = 0;
+                    }
+                    _ => {}
+                }
+            }
+            PromptState::ThemePicker {
+                ref mut query,
+                ref mut filtered,
+                ref mut selected_index,
+                ref mut scroll_offset,
+                ref original,
+            } => {
+                let visible_rows = 15;
+                match key {
+                    Key::Escape => {
+                        let original = original.clone();
+                        self.prompt = PromptState::None;
+                        self.preview_theme(&original);
+                        return Ok(());
+                    }
+                    Key::Enter => {
+                        let chosen = filtered.get(*selected_index).map(|(name, _)| name.clone());
+                        let original = original.clone();
+                        self.prompt = PromptState::None;
+                        match chosen {
+                            Some(name) => {
+                                self.preview_theme(&name);
+                                self.workspace.config.theme = name.clone();
+                                let _ = self.workspace.save_config();
+                                self.messages.info(format!("Theme: {}", name));
+                            }
+                            None => self.preview_theme(&original),
+                        }
+                        return Ok(());
+                    }
+                    Key::Up if *selected_index > 0 => {
+                        *selected_index -= 1;
+                        if *selected_index < *scroll_offset {
+                            *scroll_offset = *selected_index;
+                        }
+                    }
+                    Key::Down if *selected_index + 1 < filtered.len() => {
+                        *selected_index += 1;
+                        if *selected_index >= *scroll_offset + visible_rows {
+                            *scroll_offset = selected_index.saturating_sub(visible_rows - 1);
+                        }
+                    }
+                    Key::Backspace if !query.is_empty() => {
+                        query.pop();
+                        *filtered = filter_themes(query);
+                        *selected_index = 0;
+                        *scroll_offset = 0;
+                    }
+                    Key::Char(c) => {
+                        query.push(c);
+                        *filtered = filter_themes(query);
                         *selected_index = 0;
                         *scroll_offset = 0;
                     }
                     _ => {}
                 }
+                // Preview whatever is now selected
+                if let PromptState::ThemePicker { ref filtered, selected_index, .. } = self.prompt {
+                    if let Some((name, _)) = filtered.get(selected_index) {
+                        let name = name.clone();
+                        self.preview_theme(&name);
+                    }
+                }
             }
             PromptState::HelpMenu {
                 ref mut query,
@@ -5670,7 +11597,7 @@ impl Editor {
                     Key::Backspace => {
                         if !query.is_empty() {
                             query.pop();
-                            *filtered = filter_keybinds(query);
+                            *filtered = filter_keybinds(query, &self.workspace.keymap);
                             *selected_index = 0;
                             *scroll_offset = 0;
                         }
@@ -5681,32 +11608,640 @@ impl Editor {
                     }
                     Key::Char(c) => {
                         query.push(c);
-                        *filtered = filter_keybinds(query);
+                        *filtered = filter_keybinds(query, &self.workspace.keymap);
                         *selected_index = 0;
                         *scroll_offset = 0;
                     }
                     _ => {}
                 }
             }
-            PromptState::None => {}
+            PromptState::None => {}
+        }
+        Ok(())
+    }
+
+    fn execute_text_input_action(&mut self, action: TextInputAction, buffer: &str) {
+        match action {
+            TextInputAction::GitCommit => {
+                let (_, msg) = self.workspace.fuss.git_commit(buffer);
+                self.messages.info(msg);
+            }
+            TextInputAction::GitTag => {
+                let (_, msg) = self.workspace.fuss.git_tag(buffer);
+                self.messages.info(msg);
+            }
+            TextInputAction::GotoLine => {
+                self.goto_line_col(buffer);
+            }
+            TextInputAction::FilterCommand => {
+                self.remember_shell_command(buffer);
+                self.filter_through_command(buffer);
+            }
+            TextInputAction::InsertCommandOutput => {
+                self.remember_shell_command(buffer);
+                self.insert_command_output(buffer);
+            }
+            TextInputAction::OpenWorkspace => self.open_workspace(buffer),
+            TextInputAction::SaveAs => self.save_as(buffer),
+            TextInputAction::RecordMacro => self.start_macro_recording(buffer),
+            TextInputAction::BookmarkLabel => self.set_bookmark(Some(buffer.trim()).filter(|l| !l.is_empty())),
+            TextInputAction::ReplayMacro => match macros::parse_replay(buffer, self.macros.last) {
+                Some((register, count)) => self.replay_macro(register, count),
+                None => self.messages.error("Replay takes a register (a-z) and a count, e.g. `a 5`".to_string()),
+            },
+        }
+    }
+
+    // === Workspaces ===
+
+    /// The current workspace, then the parked ones
+    fn workspaces_mut(&mut self) -> impl Iterator<Item = &mut Workspace> {
+        std::iter::once(&mut self.workspace).chain(self.parked.iter_mut().map(|parked| &mut parked.workspace))
+    }
+
+    fn prompt_open_workspace(&mut self) {
+        let parent = self.workspace.root.parent().unwrap_or(&self.workspace.root);
+        let buffer = format!("{}/", parent.display().to_string().trim_end_matches('/'));
+        self.prompt = PromptState::TextInput {
+            label: "Open workspace: ".to_string(),
+            buffer,
+            action: TextInputAction::OpenWorkspace,
+        };
+        self.messages.info("Open workspace: ".to_string());
+    }
+
+    /// Open the directory `input` (relative to the current root, with `~`
+    /// and `$VAR` expanded) as another workspace and switch to it; switch to
+    /// it instead if it's already open
+    fn open_workspace(&mut self, input: &str) {
+        let input = input.trim();
+        if input.is_empty() {
+            return;
+        }
+        let path = resolve_path(&self.workspace.root, input);
+        let Some(root) = path.canonicalize().ok().filter(|p| p.is_dir()) else {
+            self.messages.error(format!("Not a directory: {}", input));
+            return;
+        };
+        if root == self.workspace.root {
+            self.messages.info("That workspace is already the current one".to_string());
+            return;
+        }
+        if let Some(index) = self.parked.iter().position(|p| p.workspace.root == root) {
+            self.parked.rotate_left(index);
+            self.cycle_workspace(true);
+            return;
+        }
+
+        let mut workspace = match Workspace::open(root) {
+            Ok(workspace) => workspace,
+            Err(e) => {
+                self.messages.error(format!("Failed to open workspace: {}", e));
+                return;
+            }
+        };
+        if let Some(holder) = workspace.lock_conflict.take() {
+            self.messages.error(format!(
+                "{} is open in another fackr (PID {})",
+                workspace.root.display(),
+                holder.pid
+            ));
+            return;
+        }
+        let has_backups = workspace.has_backups();
+        let mut terminal = TerminalPanel::new(self.screen.cols, self.screen.rows);
+        terminal.set_docked_right(workspace.config.layout.terminal == Edge::Right);
+        if let Some(percent) = workspace.terminal_height {
+            terminal.set_height_percent(percent);
+        }
+        let current = self.swap_workspace(ParkedWorkspace {
+            workspace,
+            terminal,
+            lsp_state: LspState::default(),
+            search_state: SearchState::default(),
+            last_edit_time: None,
+            last_backup_time: None,
+            disk_conflicts: Vec::new(),
+        });
+        self.parked.push_front(current);
+        self.note_workspace_switch();
+        self.note_keymap_warnings();
+        self.note_degraded_workspace();
+        self.note_huge_workspace();
+        if has_backups {
+            self.prompt = PromptState::RestoreBackup;
+            self.messages.prompt("Recovered unsaved changes. [R]estore / [D]iscard / [Esc]".to_string());
+        }
+    }
+
+    /// Bring the next (or previous) open workspace to the front
+    fn cycle_workspace(&mut self, forward: bool) {
+        let next = if forward { self.parked.pop_front() } else { self.parked.pop_back() };
+        let Some(next) = next else {
+            self.messages.info("No other workspace is open (Open Workspace... adds one)".to_string());
+            return;
+        };
+        let current = self.swap_workspace(next);
+        if forward {
+            self.parked.push_back(current);
+        } else {
+            self.parked.push_front(current);
+        }
+        self.note_workspace_switch();
+    }
+
+    /// Close the current workspace and switch to the next one; unsaved
+    /// changes have to be saved or discarded first
+    fn close_workspace(&mut self) {
+        if self.parked.is_empty() {
+            self.messages.info("This is the only open workspace (Quit closes it)".to_string());
+            return;
+        }
+        if self.workspace.has_unsaved_changes() {
+            self.messages.error("Save or discard this workspace's changes before closing it".to_string());
+            return;
+        }
+        let Some(next) = self.parked.pop_front() else { return };
+        let mut closed = self.swap_workspace(next);
+        closed.workspace.save_stats(true);
+        closed.workspace.release_lock();
+        let name = closed.workspace.repo_name();
+        drop(closed);
+        self.note_workspace_switch();
+        self.messages.info(format!("Closed workspace {}", name));
+    }
+
+    /// Make `next` the current workspace and return the previous one with
+    /// its state, saved and backed up
+    fn swap_workspace(&mut self, next: ParkedWorkspace) -> ParkedWorkspace {
+        if self.last_edit_time.is_some() {
+            self.backup_modified();
+        }
+        if let Err(e) = self.workspace.save_notes() {
+            self.messages.error(format!("Failed to save notes: {}", e));
+        }
+        if let Err(e) = self.workspace.save() {
+            tracing::warn!("failed to save workspace state: {}", e);
+        }
+        // Editor state tied to the buffers on screen starts over
+        self.snippet = None;
+        self.ghost_text = GhostTextState::default();
+        self.bracket_cache = BracketMatchCache::default();
+        self.prefetch_key = (None, None, 0);
+        self.last_place = None;
+        self.tree_stale_since = None;
+        self.log_len = 0;
+        self.server_manager.visible = false;
+        self.focus = Focus::Editor;
+        ParkedWorkspace {
+            workspace: std::mem::replace(&mut self.workspace, next.workspace),
+            terminal: std::mem::replace(&mut self.terminal, next.terminal),
+            lsp_state: std::mem::replace(&mut self.lsp_state, next.lsp_state),
+            search_state: std::mem::replace(&mut self.search_state, next.search_state),
+            last_edit_time: std::mem::replace(&mut self.last_edit_time, next.last_edit_time),
+            last_backup_time: std::mem::replace(&mut self.last_backup_time, next.last_backup_time),
+            disk_conflicts: std::mem::replace(&mut self.disk_conflicts, next.disk_conflicts),
+        }
+    }
+
+    /// Apply the new current workspace's theme and log, and say which it is
+    fn note_workspace_switch(&mut self) {
+        self.terminal.update_screen_size(self.screen.cols, self.screen.rows);
+        self.apply_theme();
+        self.apply_logging();
+        self.macros.load_saved(&self.workspace.macro_file());
+        self.messages.info(format!("Workspace {} ({} open)", self.workspace.repo_name(), self.parked.len() + 1));
+    }
+
+    // === Marks ===
+
+    /// Prompt for a mark letter, then set or jump
+    fn prompt_mark(&mut self, action: MarkAction) {
+        self.prompt = PromptState::MarkKey(action);
+        self.messages.prompt(match action {
+            MarkAction::Set => "Set mark (a-z local, A-Z global):",
+            MarkAction::JumpLine => "Jump to mark line:",
+            MarkAction::JumpExact => "Jump to mark:",
+        }.to_string());
+    }
+
+    /// Set mark `name` at the primary cursor. Lowercase marks belong to the
+    /// pane; uppercase marks remember the file and persist with the workspace.
+    fn set_mark(&mut self, name: char) {
+        let (line, col) = (self.cursor().line, self.cursor().col);
+        if name.is_ascii_lowercase() {
+            let tab = self.workspace.active_tab_mut();
+            let pane = tab.active_pane;
+            tab.panes[pane].marks.insert(name, (line, col));
+        } else {
+            let Some(path) = self.workspace_path() else {
+                self.messages.info("Global marks need a file on disk".to_string());
+                return;
+            };
+            self.workspace.marks.insert(name, GlobalMark { path, line, col });
+        }
+        self.messages.info(format!("Mark '{}' set at line {}", name, line + 1));
+    }
+
+    /// Jump to mark `name`, either to its exact position or to the first
+    /// non-blank column of its line
+    fn jump_to_mark(&mut self, name: char, exact: bool) {
+        let from = self.current_place();
+        let (line, col) = if name.is_ascii_lowercase() {
+            match self.workspace.active_tab().active_pane().marks.get(&name) {
+                Some(&pos) => pos,
+                None => {
+                    self.messages.info(format!("Mark '{}' not set", name));
+                    return;
+                }
+            }
+        } else {
+            let Some(mark) = self.workspace.marks.get(&name).cloned() else {
+                self.messages.info(format!("Mark '{}' not set", name));
+                return;
+            };
+            let full_path = self.workspace.root.join(&mark.path);
+            if self.current_file_path().as_ref() != Some(&full_path) {
+                if let Err(e) = self.workspace.open_file(&full_path) {
+                    self.messages.error(format!("Failed to open {}: {}", mark.path.display(), e));
+                    return;
+                }
+            }
+            (mark.line, mark.col)
+        };
+
+        let line = line.min(self.buffer().line_count().saturating_sub(1));
+        let col = if exact {
+            col.min(self.buffer().line_len(line))
+        } else {
+            self.buffer().line_str(line)
+                .and_then(|l| l.chars().position(|c| !c.is_whitespace()))
+                .unwrap_or(0)
+        };
+
+        self.remember_jump_from(from);
+        self.cursors_mut().collapse_to_primary();
+        self.cursor_mut().set(line, col);
+        self.scroll_to_cursor();
+        self.messages.info(format!("Mark '{}'", name));
+    }
+
+    /// Clear the pane's local marks on `line`, or set the first free one
+    /// there if it has none (a click in the gutter's marker column)
+    fn toggle_line_mark(&mut self, line: usize) {
+        let tab = self.workspace.active_tab_mut();
+        let pane = tab.active_pane;
+        let marks = &mut tab.panes[pane].marks;
+        let on_line: Vec<char> = marks.iter().filter(|(_, &(l, _))| l == line).map(|(&name, _)| name).collect();
+        if !on_line.is_empty() {
+            for name in &on_line {
+                marks.remove(name);
+            }
+            let names: String = on_line.iter().collect();
+            self.messages.info(format!("Cleared mark{} '{}' at line {}", if names.len() == 1 { "" } else { "s" }, names, line + 1));
+            return;
         }
-        Ok(())
+        let Some(name) = ('a'..='z').find(|name| !marks.contains_key(name)) else {
+            self.messages.info("All local marks a-z are in use".to_string());
+            return;
+        };
+        marks.insert(name, (line, 0));
+        self.messages.info(format!("Mark '{}' set at line {}", name, line + 1));
     }
 
-    fn execute_text_input_action(&mut self, action: TextInputAction, buffer: &str) {
-        match action {
-            TextInputAction::GitCommit => {
-                let (_, msg) = self.workspace.fuss.git_commit(buffer);
-                self.message = Some(msg);
-            }
-            TextInputAction::GitTag => {
-                let (_, msg) = self.workspace.fuss.git_tag(buffer);
-                self.message = Some(msg);
+    fn delete_mark(&mut self, name: char) {
+        if name.is_ascii_lowercase() {
+            let tab = self.workspace.active_tab_mut();
+            let pane = tab.active_pane;
+            tab.panes[pane].marks.remove(&name);
+        } else {
+            self.workspace.marks.remove(&name);
+        }
+        self.messages.info(format!("Deleted mark '{}'", name));
+    }
+
+    /// Show local marks for this pane followed by global marks
+    fn open_marks_panel(&mut self) {
+        let mut entries = Vec::new();
+        let display_name = self.buffer_entry().display_name();
+        for (&name, &(line, _)) in &self.workspace.active_tab().active_pane().marks {
+            let preview = self.buffer().line_str(line).unwrap_or_default();
+            entries.push((name, format!("{}:{}", display_name, line + 1), preview));
+        }
+        for (&name, mark) in &self.workspace.marks {
+            let full_path = self.workspace.root.join(&mark.path);
+            let preview = std::fs::read_to_string(&full_path)
+                .ok()
+                .and_then(|text| text.lines().nth(mark.line).map(String::from))
+                .unwrap_or_default();
+            entries.push((name, format!("{}:{}", mark.path.display(), mark.line + 1), preview));
+        }
+
+        if entries.is_empty() {
+            self.messages.info("No marks set".to_string());
+            return;
+        }
+        self.prompt = PromptState::MarksPanel { entries, selected_index: 0 };
+    }
+
+    // === Bookmarks ===
+
+    /// The current file's path as bookmarks and global marks keep it:
+    /// relative to the workspace root unless the file is outside it
+    fn workspace_path(&self) -> Option<PathBuf> {
+        let path = self.filename()?;
+        if self.buffer_entry().is_orphan {
+            return Some(path);
+        }
+        Some(path.strip_prefix(&self.workspace.root).map(Path::to_path_buf).unwrap_or(path))
+    }
+
+    /// Bookmark the cursor's line, or remove the bookmark on it
+    fn toggle_bookmark(&mut self) {
+        let Some(path) = self.workspace_path() else {
+            self.messages.info("Bookmarks need a file on disk".to_string());
+            return;
+        };
+        let line = self.cursor().line;
+        if self.workspace.bookmarks.remove(&path, line) {
+            self.messages.info(format!("Removed bookmark at line {}", line + 1));
+        } else {
+            self.set_bookmark(None);
+        }
+    }
+
+    /// Ask for a label, then bookmark the cursor's line with it
+    fn open_bookmark_label(&mut self) {
+        let Some(path) = self.workspace_path() else {
+            self.messages.info("Bookmarks need a file on disk".to_string());
+            return;
+        };
+        let label = self.workspace.bookmarks.get(&path, self.cursor().line).and_then(|b| b.label.clone());
+        self.prompt = PromptState::TextInput {
+            label: "Bookmark label: ".to_string(),
+            buffer: label.unwrap_or_default(),
+            action: TextInputAction::BookmarkLabel,
+        };
+        self.messages.info("Bookmark label: ".to_string());
+    }
+
+    fn set_bookmark(&mut self, label: Option<&str>) {
+        let Some(path) = self.workspace_path() else { return };
+        let line = self.cursor().line;
+        self.workspace.bookmarks.set(&path, line, label.map(str::to_string));
+        match label {
+            Some(label) => self.messages.info(format!("Bookmarked line {} as \"{}\"", line + 1, label)),
+            None => self.messages.info(format!("Bookmarked line {}", line + 1)),
+        }
+    }
+
+    /// Go to the next (or previous) bookmark in file and line order
+    fn next_bookmark(&mut self, forward: bool) {
+        let path = self.workspace_path().unwrap_or_default();
+        let next = self.workspace.bookmarks.next(&path, self.cursor().line, forward).cloned();
+        match next {
+            Some(bookmark) => self.goto_bookmark(&bookmark),
+            None => self.messages.info("No bookmarks (Ctrl+Alt+K sets one)".to_string()),
+        }
+    }
+
+    fn goto_bookmark(&mut self, bookmark: &Bookmark) {
+        self.remember_jump();
+        if self.workspace_path().as_ref() != Some(&bookmark.path) {
+            let full_path = self.workspace.root.join(&bookmark.path);
+            if let Err(e) = self.workspace.open_file(&full_path) {
+                self.messages.error(format!("Failed to open {}: {}", bookmark.path.display(), e));
+                return;
             }
-            TextInputAction::GotoLine => {
-                self.goto_line_col(buffer);
+        }
+        let line = bookmark.line.min(self.buffer().line_count().saturating_sub(1));
+        let col = self.buffer().line_str(line)
+            .and_then(|l| l.chars().position(|c| !c.is_whitespace()))
+            .unwrap_or(0);
+        self.cursors_mut().collapse_to_primary();
+        self.cursor_mut().set(line, col);
+        self.scroll_to_cursor();
+        let label = bookmark.label.as_ref().map(|l| format!(" \"{}\"", l)).unwrap_or_default();
+        self.messages.info(format!("Bookmark {}:{}{}", bookmark.path.display(), bookmark.line + 1, label));
+    }
+
+    /// List the workspace's bookmarks with a preview of each line
+    fn open_bookmarks_panel(&mut self) {
+        if self.workspace.bookmarks.is_empty() {
+            self.messages.info("No bookmarks (Ctrl+Alt+K sets one)".to_string());
+            return;
+        }
+        let current = self.workspace_path();
+        let entries = self
+            .workspace
+            .bookmarks
+            .all()
+            .iter()
+            .map(|bookmark| {
+                let preview = if current.as_ref() == Some(&bookmark.path) {
+                    self.buffer().line_str(bookmark.line)
+                } else {
+                    std::fs::read_to_string(self.workspace.root.join(&bookmark.path))
+                        .ok()
+                        .and_then(|text| text.lines().nth(bookmark.line).map(String::from))
+                };
+                (bookmark.clone(), preview.unwrap_or_default())
+            })
+            .collect();
+        self.prompt = PromptState::BookmarksPanel { entries, selected_index: 0 };
+    }
+
+    // === Shell commands ===
+
+    /// Open the "filter through command" prompt (like vim's `!`)
+    fn open_filter_command(&mut self) {
+        let target = if self.cursor().has_selection() { "selection" } else { "buffer" };
+        self.open_shell_prompt(format!("Filter {} through: ", target), TextInputAction::FilterCommand);
+    }
+
+    /// Open the "insert command output" prompt
+    fn open_insert_command_output(&mut self) {
+        self.open_shell_prompt("Insert output of: ".to_string(), TextInputAction::InsertCommandOutput);
+    }
+
+    fn open_shell_prompt(&mut self, label: String, action: TextInputAction) {
+        self.shell_history_pos = None;
+        self.prompt = PromptState::TextInput {
+            label: label.clone(),
+            buffer: String::new(),
+            action,
+        };
+        self.messages.info(label);
+    }
+
+    /// Add a command to the shell history (most recent last, no duplicates)
+    fn remember_shell_command(&mut self, cmd: &str) {
+        let cmd = cmd.trim();
+        if cmd.is_empty() {
+            return;
+        }
+        self.shell_history.retain(|c| c != cmd);
+        self.shell_history.push(cmd.to_string());
+        if self.shell_history.len() > SHELL_HISTORY_LIMIT {
+            self.shell_history.remove(0);
+        }
+    }
+
+    /// Run a shell command and insert its stdout at every cursor
+    fn insert_command_output(&mut self, cmd: &str) {
+        let cmd = cmd.trim();
+        if cmd.is_empty() {
+            return;
+        }
+
+        let Some((mut text, stderr_line)) = self.shell_output(cmd, "") else {
+            return;
+        };
+        // `date`, `uuidgen` etc. end with a newline that shouldn't land in the buffer
+        if text.ends_with('\n') {
+            text.pop();
+        }
+        if text.is_empty() {
+            self.messages.info(format!("`{}` produced no output", cmd));
+            return;
+        }
+
+        self.history_mut().maybe_break_group();
+        self.insert_text(&text);
+        self.history_mut().maybe_break_group();
+
+        self.messages.info(if stderr_line.is_empty() {
+            format!("Inserted output of `{}`", cmd)
+        } else {
+            format!("Inserted output of `{}` (stderr: {})", cmd, stderr_line)
+        });
+    }
+
+    /// Run `cmd` through the platform shell in the workspace root, feeding `input` on stdin
+    fn run_shell_command(&self, cmd: &str, input: &str) -> Result<std::process::Output> {
+        use std::io::Write;
+        use std::process::Stdio;
+
+        let mut child = shell_command(cmd)
+            .current_dir(&self.workspace.root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Write stdin from a thread so a command that fills its stdout pipe
+        // before reading all input can't deadlock us
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = input.to_string();
+        let writer = std::thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+        let output = child.wait_with_output()?;
+        let _ = writer.join();
+        Ok(output)
+    }
+
+    /// Run a shell command and return (stdout, first stderr line).
+    /// On failure the error is shown in the status bar and None is returned.
+    fn shell_output(&mut self, cmd: &str, input: &str) -> Option<(String, String)> {
+        self.show_busy(&format!("Running `{}`", cmd));
+        let output = match self.run_shell_command(cmd, input) {
+            Ok(output) => output,
+            Err(e) => {
+                self.messages.error(format!("Failed to run `{}`: {}", cmd, e));
+                return None;
             }
+        };
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr_line = stderr.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim().to_string();
+
+        if !output.status.success() {
+            let code = output.status.code().map(|c| c.to_string()).unwrap_or_else(|| "signal".to_string());
+            self.messages.error(if stderr_line.is_empty() {
+                format!("`{}` failed (exit {})", cmd, code)
+            } else {
+                format!("`{}` failed (exit {}): {}", cmd, code, stderr_line)
+            });
+            return None;
+        }
+
+        Some((String::from_utf8_lossy(&output.stdout).into_owned(), stderr_line))
+    }
+
+    /// Replace the selection (or whole buffer) with the output of a shell command.
+    /// The replacement is a single undo step; stderr is shown in the status bar.
+    fn filter_through_command(&mut self, cmd: &str) {
+        let cmd = cmd.trim();
+        if cmd.is_empty() {
+            return;
+        }
+
+        self.cursors_mut().collapse_to_primary();
+        let selection = self.cursor().selection_bounds();
+        let (start_idx, end_idx) = match selection {
+            Some((start, end)) => (
+                self.buffer().line_col_to_char(start.line, start.col),
+                self.buffer().line_col_to_char(end.line, end.col),
+            ),
+            None => (0, self.buffer().len_chars()),
+        };
+        let input: String = self.buffer().slice(start_idx, end_idx).chars().collect();
+
+        let Some((mut text, stderr_line)) = self.shell_output(cmd, &input) else {
+            return;
+        };
+        // Most tools terminate their output with a newline; don't add one the input didn't have
+        if !input.ends_with('\n') && text.ends_with('\n') {
+            text.pop();
+        }
+        if text == input {
+            self.messages.info(format!("`{}`: no changes", cmd));
+            return;
+        }
+
+        let cursor_before = self.cursor_pos();
+        let start_line = self.buffer().char_to_line_col(start_idx).0;
+
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(vec![cursor_before]);
+        if end_idx > start_idx {
+            self.buffer_mut().delete(start_idx, end_idx);
+            self.history_mut().record_delete(start_idx, input, cursor_before, cursor_before);
+        }
+        if !text.is_empty() {
+            self.buffer_mut().insert(start_idx, &text);
+            self.history_mut().record_insert(start_idx, text.clone(), cursor_before, cursor_before);
+        }
+
+        if selection.is_some() {
+            // Select the replacement so it can be filtered again
+            let (start_line, start_col) = self.buffer().char_to_line_col(start_idx);
+            let (end_line, end_col) = self.buffer().char_to_line_col(start_idx + text.chars().count());
+            let cursor = self.cursor_mut();
+            cursor.anchor_line = start_line;
+            cursor.anchor_col = start_col;
+            cursor.line = end_line;
+            cursor.col = end_col;
+            cursor.desired_col = end_col;
+            cursor.selecting = true;
+        } else {
+            // Whole buffer: keep the cursor where it was, clamped to the new text
+            let line = cursor_before.line.min(self.buffer().line_count().saturating_sub(1));
+            let col = cursor_before.col.min(self.buffer().line_len(line));
+            self.cursor_mut().set(line, col);
         }
+
+        let cursor_after = self.cursor_pos();
+        self.history_mut().set_cursors_after(vec![cursor_after]);
+        self.history_mut().end_group();
+        self.invalidate_highlight_cache(start_line);
+        self.invalidate_bracket_cache();
+
+        self.messages.info(if stderr_line.is_empty() {
+            format!("Filtered through `{}`", cmd)
+        } else {
+            format!("Filtered through `{}` (stderr: {})", cmd, stderr_line)
+        });
     }
 
     /// Open the goto line prompt
@@ -5716,7 +12251,7 @@ impl Editor {
             buffer: String::new(),
             action: TextInputAction::GotoLine,
         };
-        self.message = Some("Go to line: ".to_string());
+        self.messages.info("Go to line: ".to_string());
     }
 
     /// Parse line:col input and jump to position
@@ -5736,11 +12271,11 @@ impl Editor {
         let line: usize = match line_str.parse::<usize>() {
             Ok(n) if n > 0 => n - 1, // Convert to 0-indexed
             Ok(_) => {
-                self.message = Some("Invalid line number".to_string());
+                self.messages.info("Invalid line number".to_string());
                 return;
             }
             Err(_) => {
-                self.message = Some("Invalid line number".to_string());
+                self.messages.info("Invalid line number".to_string());
                 return;
             }
         };
@@ -5762,6 +12297,7 @@ impl Editor {
         let col = col.min(line_len);
 
         // Move cursor
+        self.remember_jump();
         self.cursor_mut().line = line;
         self.cursor_mut().col = col;
         self.cursor_mut().desired_col = col;
@@ -5770,7 +12306,7 @@ impl Editor {
         // Center the view on the target line
         self.scroll_to_cursor();
 
-        self.message = Some(format!("Line {}, Column {}", line + 1, col + 1));
+        self.messages.info(format!("Line {}, Column {}", line + 1, col + 1));
     }
 
     fn restore_backups(&mut self) -> Result<()> {
@@ -5841,6 +12377,8 @@ impl Editor {
                 };
             }
             _ => {
+                // Searching moves the cursor as the query is typed
+                self.remember_jump();
                 // Open fresh find dialog, possibly with selected text
                 let initial_query = self.get_selection_text().unwrap_or_default();
                 self.prompt = PromptState::FindReplace {
@@ -5915,10 +12453,18 @@ impl Editor {
             return;
         }
 
-        // Collect all lines from buffer first to avoid borrow issues
+        // Collect all lines from buffer first to avoid borrow issues; in a
+        // large file, only those around the cursor
         let buffer = self.buffer();
         let line_count = buffer.line_count();
-        let lines: Vec<String> = (0..line_count)
+        let searched = if buffer.large {
+            let cursor_line = self.cursors().primary().line;
+            cursor_line.saturating_sub(LARGE_FILE_SEARCH_LINES / 2)..(cursor_line + LARGE_FILE_SEARCH_LINES / 2).min(line_count)
+        } else {
+            0..line_count
+        };
+        let first_line = searched.start;
+        let lines: Vec<String> = searched
             .filter_map(|i| buffer.line_str(i))
             .collect();
 
@@ -5927,20 +12473,14 @@ impl Editor {
 
         if regex_mode {
             // Regex search
-            let pattern = if case_insensitive {
-                format!("(?i){}", query)
-            } else {
-                query.clone()
-            };
-
-            if let Ok(re) = regex::Regex::new(&pattern) {
+            if let Some(re) = search_regex(&query, case_insensitive) {
                 for (line_idx, line) in lines.iter().enumerate() {
                     for mat in re.find_iter(line) {
                         // Convert byte positions to char positions for proper cursor placement
                         let start_col = line[..mat.start()].chars().count();
                         let match_char_len = line[mat.start()..mat.end()].chars().count();
                         matches.push(SearchMatch {
-                            line: line_idx,
+                            line: first_line + line_idx,
                             start_col,
                             end_col: start_col + match_char_len,
                         });
@@ -5983,7 +12523,7 @@ impl Editor {
                     let start_col = search_line[..abs_byte_pos].chars().count();
 
                     matches.push(SearchMatch {
-                        line: line_idx,
+                        line: first_line + line_idx,
                         start_col,
                         end_col: start_col + query_char_len,
                     });
@@ -6022,10 +12562,11 @@ impl Editor {
         self.update_search_matches();
 
         if self.search_state.matches.is_empty() {
-            self.message = Some("No matches found".to_string());
+            self.messages.info("No matches found".to_string());
             return;
         }
 
+        self.remember_jump();
         // Move to next match (wrap around)
         self.search_state.current_match =
             (self.search_state.current_match + 1) % self.search_state.matches.len();
@@ -6038,10 +12579,11 @@ impl Editor {
         self.update_search_matches();
 
         if self.search_state.matches.is_empty() {
-            self.message = Some("No matches found".to_string());
+            self.messages.info("No matches found".to_string());
             return;
         }
 
+        self.remember_jump();
         // Move to previous match (wrap around)
         if self.search_state.current_match == 0 {
             self.search_state.current_match = self.search_state.matches.len() - 1;
@@ -6075,7 +12617,7 @@ impl Editor {
             // Update message with match count
             let total = self.search_state.matches.len();
             let current = self.search_state.current_match + 1;
-            self.message = Some(format!("{}/{} matches", current, total));
+            self.messages.info(format!("{}/{} matches", current, total));
         }
     }
 
@@ -6087,13 +12629,17 @@ impl Editor {
         };
 
         if self.search_state.matches.is_empty() {
-            self.message = Some("No matches to replace".to_string());
+            self.messages.info("No matches to replace".to_string());
             return;
         }
 
         // Get current match
         let current_idx = self.search_state.current_match;
         if let Some(m) = self.search_state.matches.get(current_idx).cloned() {
+            let re = self.replace_regex();
+            let line = self.buffer().line_str(m.line).unwrap_or_default();
+            let replace_text = expand_replacement(re.as_ref(), &line, &m, &replace_text);
+
             // Delete the matched text and insert replacement
             let buffer = self.buffer_mut();
             let start_char = buffer.line_col_to_char(m.line, m.start_col);
@@ -6113,7 +12659,7 @@ impl Editor {
                 }
                 self.jump_to_current_match();
             } else {
-                self.message = Some("All matches replaced".to_string());
+                self.messages.info("All matches replaced".to_string());
             }
         }
     }
@@ -6128,25 +12674,126 @@ impl Editor {
         self.update_search_matches();
 
         if self.search_state.matches.is_empty() {
-            self.message = Some("No matches to replace".to_string());
+            self.messages.info("No matches to replace".to_string());
             return;
         }
 
         let count = self.search_state.matches.len();
 
-        // Replace from end to start to preserve positions
+        // Expand capture references against the text as it was searched,
+        // before earlier replacements on the same line change it
+        let re = self.replace_regex();
         let matches: Vec<_> = self.search_state.matches.iter().cloned().collect();
-        for m in matches.into_iter().rev() {
+        let replacements: Vec<String> = matches.iter()
+            .map(|m| {
+                let line = self.buffer().line_str(m.line).unwrap_or_default();
+                expand_replacement(re.as_ref(), &line, m, &replace_text)
+            })
+            .collect();
+
+        // Lines can only be listed for review while replacing keeps them in place
+        let mut changed_lines: Vec<usize> = matches.iter().map(|m| m.line).collect();
+        changed_lines.dedup();
+        let keeps_lines = !replacements.iter().any(|r| r.contains('\n'));
+        let before: Vec<String> = changed_lines
+            .iter()
+            .map(|&line| self.buffer().line_str(line).unwrap_or_default())
+            .collect();
+
+        // Replace from end to start to preserve positions, as one undo step
+        self.invalidate_highlight_cache(matches[0].line);
+        self.invalidate_bracket_cache();
+        let cursor = self.cursor_pos();
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(vec![cursor]);
+        for (m, replacement) in matches.into_iter().zip(replacements).rev() {
             let buffer = self.buffer_mut();
             let start_char = buffer.line_col_to_char(m.line, m.start_col);
             let end_char = buffer.line_col_to_char(m.line, m.end_col);
-            buffer.delete(start_char, end_char);
-            buffer.insert(start_char, &replace_text);
+            let matched: String = buffer.slice(start_char, end_char).chars().collect();
+            if !matched.is_empty() {
+                buffer.delete(start_char, end_char);
+                self.history_mut().record_delete(start_char, matched, cursor, cursor);
+            }
+            if !replacement.is_empty() {
+                self.buffer_mut().insert(start_char, &replacement);
+                self.history_mut().record_insert(start_char, replacement, cursor, cursor);
+            }
         }
+        self.history_mut().set_cursors_after(vec![cursor]);
+        self.history_mut().end_group();
 
         self.search_state.matches.clear();
         self.search_state.last_query.clear();
-        self.message = Some(format!("Replaced {} occurrences", count));
+        self.messages.info(format!("Replaced {} occurrences", count));
+
+        if self.workspace.config.review_replace_all && keeps_lines {
+            let lines = changed_lines
+                .into_iter()
+                .zip(before)
+                .map(|(line, before)| ReplacedLine {
+                    line,
+                    after: self.buffer().line_str(line).unwrap_or_default(),
+                    before,
+                    reverted: false,
+                })
+                .collect();
+            self.prompt = PromptState::ReplacedLines { lines, selected_index: 0 };
+        }
+    }
+
+    /// Put a line changed by Replace All back as it was, as its own undo
+    /// step; false if it has been edited since
+    fn revert_replaced_line(&mut self, replaced: &ReplacedLine) -> bool {
+        if self.buffer().line_str(replaced.line).as_deref() != Some(replaced.after.as_str()) {
+            return false;
+        }
+        let start = self.buffer().line_col_to_char(replaced.line, 0);
+        let end = start + replaced.after.chars().count();
+
+        self.invalidate_highlight_cache(replaced.line);
+        self.invalidate_bracket_cache();
+        let cursor_before = self.cursor_pos();
+        let cursor_after = Position::new(replaced.line, 0);
+        self.history_mut().begin_group();
+        self.history_mut().set_cursors_before(vec![cursor_before]);
+        self.buffer_mut().delete(start, end);
+        self.history_mut().record_delete(start, replaced.after.clone(), cursor_before, cursor_after);
+        self.buffer_mut().insert(start, &replaced.before);
+        self.history_mut().record_insert(start, replaced.before.clone(), cursor_after, cursor_after);
+        self.history_mut().set_cursors_after(vec![cursor_after]);
+        self.history_mut().end_group();
+
+        self.cursors_mut().collapse_to_primary();
+        self.cursor_mut().set(replaced.line, 0);
+        self.scroll_to_cursor();
+        true
+    }
+
+    /// The find regex when replacing in regex mode, for expanding `$1` /
+    /// `${name}` references in the replace field
+    fn replace_regex(&self) -> Option<regex::Regex> {
+        match &self.prompt {
+            PromptState::FindReplace { find_query, case_insensitive, regex_mode: true, .. } => {
+                search_regex(find_query, *case_insensitive)
+            }
+            _ => None,
+        }
+    }
+
+    /// What the current match would be replaced with, when the replace field
+    /// refers to capture groups
+    fn replace_preview(&self) -> Option<String> {
+        let PromptState::FindReplace { replace_text, .. } = &self.prompt else {
+            return None;
+        };
+        if !replace_text.contains('$') {
+            return None;
+        }
+        let re = self.replace_regex()?;
+        let m = self.search_state.matches.get(self.search_state.current_match)?;
+        let line = self.buffer().line_str(m.line)?;
+        Some(expand_replacement(Some(&re), &line, m, replace_text))
     }
 
     /// Toggle case sensitivity
@@ -6244,155 +12891,336 @@ impl Editor {
                 }
             }
 
-            // Sort alphabetically (case-insensitive)
-            dirs.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
-            files.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+            // Sort alphabetically (case-insensitive)
+            dirs.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+            files.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+
+            // Directories first, then files
+            entries.extend(dirs);
+            entries.extend(files);
+        }
+
+        entries
+    }
+
+    /// Navigate to a new directory in fortress mode
+    fn fortress_navigate_to(&mut self, path: &Path) {
+        let entries = self.read_directory(path);
+        self.prompt = PromptState::Fortress {
+            current_path: path.to_path_buf(),
+            entries,
+            selected_index: 0,
+            filter: String::new(),
+            scroll_offset: 0,
+        };
+    }
+
+    /// Open a file from fortress mode
+    fn fortress_open_file(&mut self, path: &Path) {
+        // Open file in current pane by reusing workspace method
+        if let Err(e) = self.workspace.open_file(path) {
+            self.messages.error(format!("Failed to open file: {}", e));
+        } else {
+            // Sync with LSP
+            self.sync_document_to_lsp();
+        }
+    }
+
+    /// List the string literals in the selection (or whole file) using the
+    /// highlighter's tokens, for a templated replace such as wrapping in `t!(…)`
+    fn open_strings_panel(&mut self) {
+        if !self.buffer_entry().highlighter.is_enabled() {
+            self.messages.info("No syntax highlighting for this file; can't find strings".to_string());
+            return;
+        }
+        let bounds = self.cursor().selection_bounds();
+        let entry = self.buffer_entry();
+        let buffer = &entry.buffer;
+        let highlighter = &entry.highlighter;
+
+        let (first, last) = bounds.map_or((0, buffer.line_count().saturating_sub(1)), |(s, e)| (s.line, e.line));
+        let mut state = highlighter.get_state_for_line(0);
+        let mut literals = Vec::new();
+        for line_idx in 0..=last {
+            let Some(line) = buffer.line_str(line_idx) else { break };
+            let tokens = highlighter.tokenize_line(&line, &mut state);
+            if line_idx < first {
+                continue;
+            }
+            let chars: Vec<char> = line.chars().collect();
+            for token in tokens.iter().filter(|t| t.token_type == TokenType::String) {
+                if let Some((start, end)) = bounds {
+                    let after_start = line_idx > start.line || token.start >= start.col;
+                    let before_end = line_idx < end.line || token.end <= end.col;
+                    if !after_start || !before_end {
+                        continue;
+                    }
+                }
+                let text: String = chars[token.start..token.end.min(chars.len())].iter().collect();
+                if is_complete_literal(&text) {
+                    literals.push(StringLiteral {
+                        line: line_idx,
+                        start_col: token.start,
+                        end_col: token.end,
+                        text,
+                        included: true,
+                    });
+                }
+            }
+        }
+
+        if literals.is_empty() {
+            self.messages.info("No string literals found".to_string());
+            return;
+        }
+        self.messages.info(format!("{} string literals", literals.len()));
+        let template = self.workspace.config.string_template.clone();
+        let (line, col) = (literals[0].line, literals[0].start_col);
+        self.prompt = PromptState::StringsPanel { literals, selected_index: 0, template };
+        self.cursor_mut().set(line, col);
+        self.scroll_to_cursor();
+    }
+
+    /// Replace each included literal with `template`, `{}` standing for the
+    /// literal, as one undo step; the template is remembered for next time
+    fn replace_string_literals(&mut self, literals: &[StringLiteral], template: String) {
 
-            // Directories first, then files
-            entries.extend(dirs);
-            entries.extend(files);
+        if let Some(reason) = self.read_only_reason() {
+            self.messages.info(reason.to_string());
+            return;
+        }
+        if !template.contains("{}") {
+            self.messages.info("Template needs {} where the string goes".to_string());
+            return;
         }
+        let edits: Vec<TextEdit> = literals
+            .iter()
+            .filter(|l| l.included)
+            .map(|l| TextEdit {
+                range: LspRange::new(
+                    LspPosition::new(l.line as u32, l.start_col as u32),
+                    LspPosition::new(l.line as u32, l.end_col as u32),
+                ),
+                new_text: template.replace("{}", &l.text),
+            })
+            .collect();
 
-        entries
+        self.apply_additional_edits(&edits);
+        self.messages.info(format!("Replaced {} string literals", edits.len()));
+        self.workspace.config.string_template = template;
+        let _ = self.workspace.save_config();
     }
 
-    /// Navigate to a new directory in fortress mode
-    fn fortress_navigate_to(&mut self, path: &Path) {
-        let entries = self.read_directory(path);
-        self.prompt = PromptState::Fortress {
-            current_path: path.to_path_buf(),
-            entries,
+    /// Open multi-file search modal (F4)
+    /// In a workspace too big to scan, ask before running `command`;
+    /// true when it can go ahead
+    fn deep_scan_allowed(&mut self, command: &str) -> bool {
+        if !self.workspace.scan_limited {
+            return true;
+        }
+        self.prompt = PromptState::DeepScan(command.to_string());
+        self.messages.prompt(self.deep_scan_question());
+        false
+    }
+
+    fn deep_scan_question(&self) -> String {
+        format!("{} is very large. Scan all of it anyway? [Y]es / [N]o", self.workspace.root.display())
+    }
+
+    fn open_file_search(&mut self, open_files: bool) {
+        if !open_files && !self.deep_scan_allowed("search-files") {
+            return;
+        }
+        self.prompt = PromptState::FileSearch {
+            query: String::new(),
+            results: Vec::new(),
             selected_index: 0,
-            filter: String::new(),
             scroll_offset: 0,
+            searching: false,
+            open_files,
         };
     }
 
-    /// Open a file from fortress mode
-    fn fortress_open_file(&mut self, path: &Path) {
-        // Open file in current pane by reusing workspace method
-        if let Err(e) = self.workspace.open_file(path) {
-            self.message = Some(format!("Failed to open file: {}", e));
-        } else {
-            // Sync with LSP
-            self.sync_document_to_lsp();
+    /// Start searching the workspace's files for the query in the F4 modal;
+    /// results stream in through `poll_file_search`
+    fn start_file_search(&mut self, query: &str) {
+        self.file_search = Some(Search::start(&self.workspace.root, &self.workspace.config.excluded_dirs, query));
+        if let PromptState::FileSearch { searching, .. } = &mut self.prompt {
+            *searching = true;
         }
     }
 
-    /// Open multi-file search modal (F4)
-    fn open_file_search(&mut self) {
-        self.prompt = PromptState::FileSearch {
+    /// Open live grep, which searches the workspace's files as the query
+    /// is typed
+    fn open_live_grep(&mut self) {
+        if !self.deep_scan_allowed("live-grep") {
+            return;
+        }
+        self.prompt = PromptState::LiveGrep {
             query: String::new(),
+            types: String::new(),
+            editing_types: false,
+            regex: false,
             results: Vec::new(),
             selected_index: 0,
             scroll_offset: 0,
             searching: false,
+            invalid: false,
         };
     }
 
-    /// Search files in workspace for query string (grep-like)
-    /// Uses streaming file reading to avoid loading entire files into memory
-    fn search_files(&self, query: &str) -> Vec<FileSearchResult> {
-        use std::io::{BufRead, BufReader};
-        use std::fs::File;
-
-        if query.is_empty() {
-            return Vec::new();
+    /// Drop live grep's results and search, and search again once typing
+    /// pauses
+    fn live_grep_changed(&mut self) {
+        let PromptState::LiveGrep { query, results, selected_index, scroll_offset, searching, invalid, .. } = &mut self.prompt else {
+            return;
+        };
+        results.clear();
+        *selected_index = 0;
+        *scroll_offset = 0;
+        *searching = false;
+        *invalid = false;
+        self.file_search = None;
+        self.live_grep_due = (!query.is_empty()).then(Instant::now);
+    }
+
+    /// Start live grep's search once its query has settled
+    fn start_live_grep_when_due(&mut self) {
+        if self.live_grep_due.is_none_or(|changed| changed.elapsed() < LIVE_GREP_DELAY) {
+            return;
         }
+        self.live_grep_due = None;
+        let PromptState::LiveGrep { query, types, regex, searching, invalid, .. } = &mut self.prompt else {
+            return;
+        };
+        let pattern = if *regex { Pattern::regex(query) } else { Ok(Pattern::text(query)) };
+        let Ok(pattern) = pattern else {
+            *invalid = true;
+            return;
+        };
+        let extensions = parse_extensions(types);
+        *searching = true;
+        self.file_search = Some(Search::start_pattern(
+            &self.workspace.root,
+            &self.workspace.config.excluded_dirs,
+            pattern,
+            &extensions,
+        ));
+    }
+
+    /// Lines of the file holding live grep's selected match, read when the
+    /// selection moves to another file
+    fn update_grep_preview(&mut self) {
+        let PromptState::LiveGrep { results, selected_index, .. } = &self.prompt else {
+            self.grep_preview = None;
+            return;
+        };
+        let Some(hit) = results.get(*selected_index) else {
+            return;
+        };
+        if self.grep_preview.as_ref().is_some_and(|(path, _)| *path == hit.path) {
+            return;
+        }
+        let text = std::fs::read_to_string(self.workspace.root.join(&hit.path)).unwrap_or_default();
+        self.grep_preview = Some((hit.path.clone(), text.lines().map(str::to_string).collect()));
+    }
 
-        let mut results = Vec::new();
-        let root = &self.workspace.root;
-        let query_lower = query.to_lowercase();
-
-        // Walk directory tree
-        fn walk_dir(
-            dir: &Path,
-            query_lower: &str,
-            results: &mut Vec<FileSearchResult>,
-            root: &Path,
-        ) {
-            let Ok(entries) = std::fs::read_dir(dir) else {
-                return;
+    /// Take in the results found since the last pass; a search is dropped
+    /// (which stops it) once it's done or the modal has closed. Returns
+    /// whether the modal changed.
+    fn poll_file_search(&mut self) -> bool {
+        let Some(search) = &self.file_search else {
+            return false;
+        };
+        let (PromptState::FileSearch { results, searching, .. } | PromptState::LiveGrep { results, searching, .. }) = &mut self.prompt else {
+            self.file_search = None;
+            return false;
+        };
+        let (hits, done) = search.poll();
+        let changed = !hits.is_empty() || done;
+        results.extend(hits);
+        if done {
+            *searching = false;
+            self.file_search = None;
+        }
+        changed
+    }
+
+    /// Lines of the open files containing `query`, ignoring case and
+    /// including unsaved changes, with a stamp of the buffers searched.
+    /// Buffers with no file behind them have nowhere to jump to and are
+    /// left out.
+    fn search_open_files(&mut self, query: &str) -> (Vec<Location>, u64) {
+        let query = query.to_lowercase();
+        let mut locations = Vec::new();
+        let mut searched: Vec<PathBuf> = Vec::new();
+        let mut hasher = DefaultHasher::new();
+        for entry in self.workspace.tabs.iter_mut().flat_map(|tab| tab.buffers.iter_mut()) {
+            let Some(path) = entry.path.as_ref().filter(|_| entry.virtual_doc.is_none()) else {
+                continue;
             };
-
-            for entry in entries.flatten() {
-                // Check result limit early to avoid unnecessary work
-                if results.len() >= 500 {
-                    return;
-                }
-
-                let path = entry.path();
-                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-
-                // Skip hidden files/dirs
-                if name.starts_with('.') {
-                    continue;
-                }
-
-                // Skip common non-text directories
-                if path.is_dir() {
-                    if matches!(name, "target" | "node_modules" | "build" | "dist" | "__pycache__") {
-                        continue;
-                    }
-                    walk_dir(&path, query_lower, results, root);
-                } else if path.is_file() {
-                    // Skip binary/large files by extension
-                    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                    if matches!(ext, "png" | "jpg" | "jpeg" | "gif" | "ico" | "woff" | "woff2" | "ttf" | "eot" | "pdf" | "zip" | "tar" | "gz" | "exe" | "dll" | "so" | "dylib" | "o" | "a" | "rlib") {
-                        continue;
-                    }
-
-                    // Stream file line-by-line instead of loading entire file
-                    let Ok(file) = File::open(&path) else {
-                        continue;
-                    };
-                    let reader = BufReader::new(file);
-                    let rel_path = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
-
-                    // Reusable buffer for lowercasing
-                    let mut line_lower = String::new();
-
-                    for (line_idx, line_result) in reader.lines().enumerate() {
-                        // Check result limit
-                        if results.len() >= 500 {
-                            return;
-                        }
-
-                        let Ok(line) = line_result else {
-                            // Non-UTF8 content - likely binary, skip file
-                            break;
-                        };
-
-                        // Reuse buffer for lowercase conversion
-                        line_lower.clear();
-                        for c in line.chars() {
-                            for lc in c.to_lowercase() {
-                                line_lower.push(lc);
-                            }
-                        }
-
-                        if line_lower.contains(query_lower) {
-                            results.push(FileSearchResult {
-                                path: rel_path.clone(),
-                                line_num: line_idx + 1,
-                                line_content: line.trim().to_string(),
-                            });
-                        }
-                    }
-                }
+            // A file open in several tabs is listed once
+            let full_path = self.workspace.root.join(path);
+            if searched.contains(&full_path) {
+                continue;
             }
+            (&full_path, entry.buffer.content_hash()).hash(&mut hasher);
+            let uri = path_to_uri(&full_path.to_string_lossy());
+            let buffer = &entry.buffer;
+            let lines = (0..buffer.line_count()).filter_map(|line| buffer.line_str(line));
+            for (line, col) in find_in_lines(lines, &query) {
+                let start = LspPosition::new(line as u32, col as u32);
+                let end = LspPosition::new(line as u32, (col + query.chars().count()) as u32);
+                locations.push(Location { uri: uri.clone(), range: LspRange::new(start, end) });
+            }
+            searched.push(full_path);
+        }
+        (locations, hasher.finish())
+    }
+
+    /// Search the open files for `query` and list the matches in the
+    /// locations panel, which keeps up as the buffers change
+    fn find_in_open_files(&mut self, query: &str) {
+        let (locations, stamp) = self.search_open_files(query);
+        if locations.is_empty() {
+            self.messages.info(format!("No matches for \"{}\" in open files", query));
+            return;
         }
+        self.prompt = PromptState::ReferencesPanel {
+            locations,
+            selected_index: 0,
+            query: String::new(),
+            live_search: Some((query.to_string(), stamp)),
+        };
+        self.messages.clear_prompt();
+    }
 
-        walk_dir(root, &query_lower, &mut results, root);
-        results
+    /// Search the open files again for the panel's query if any of them
+    /// changed since; returns whether the panel changed
+    fn refresh_open_files_search(&mut self) -> bool {
+        let PromptState::ReferencesPanel { live_search: Some((search, _)), .. } = &self.prompt else {
+            return false;
+        };
+        let search = search.clone();
+        let (found, new_stamp) = self.search_open_files(&search);
+        let PromptState::ReferencesPanel { locations, selected_index, live_search: Some((_, stamp)), .. } = &mut self.prompt else {
+            return false;
+        };
+        if *stamp == new_stamp {
+            return false;
+        }
+        *stamp = new_stamp;
+        *selected_index = (*selected_index).min(found.len().saturating_sub(1));
+        *locations = found;
+        true
     }
 
     /// Open file at the location from a file search result
-    fn file_search_open_result(&mut self, result: &FileSearchResult) {
+    fn file_search_open_result(&mut self, result: &SearchHit) {
         let full_path = self.workspace.root.join(&result.path);
 
         if let Err(e) = self.workspace.open_file(&full_path) {
-            self.message = Some(format!("Failed to open file: {}", e));
+            self.messages.error(format!("Failed to open file: {}", e));
             return;
         }
 
@@ -6405,9 +13233,11 @@ impl Editor {
         let max_line = tab.active_buffer().buffer.line_count().saturating_sub(1);
         let target_line = line.min(max_line);
 
+        let col = result.col.min(tab.active_buffer().buffer.line_len(target_line));
         let pane = tab.active_pane_mut();
         pane.cursors.primary_mut().line = target_line;
-        pane.cursors.primary_mut().col = 0;
+        pane.cursors.primary_mut().col = col;
+        pane.cursors.primary_mut().desired_col = col;
 
         // Center the line in viewport
         let viewport_height = self.screen.rows.saturating_sub(2) as usize;
@@ -6418,20 +13248,164 @@ impl Editor {
 
     /// Open the command palette
     fn open_command_palette(&mut self) {
-        let filtered = filter_commands("");
+        let context = self.context_commands();
+        let filtered = filter_commands("", &context, &self.workspace.keymap);
         self.prompt = PromptState::CommandPalette {
             query: String::new(),
+            context,
+            filtered,
+            selected_index: 0,
+            scroll_offset: 0,
+        };
+    }
+
+    /// Commands for what can be done right now, which the palette lists
+    /// ahead of the fixed ones: switching to another git branch, restarting
+    /// a running language server, reopening a recently closed file
+    fn context_commands(&self) -> Vec<PaletteCommand> {
+        let mut commands = Vec::new();
+        for branch in git::branch::other_branches(&self.workspace.root).unwrap_or_default() {
+            commands.push(PaletteCommand::contributed(
+                format!("Switch to Branch {}", branch),
+                "Git",
+                "switch-branch",
+                &branch,
+            ));
+        }
+        for server in self.workspace.lsp.server_statuses() {
+            commands.push(PaletteCommand::contributed(
+                format!("Restart {}", server.name),
+                "LSP",
+                "restart-server",
+                &server.command,
+            ));
+        }
+        let open: Vec<PathBuf> = self.workspace.tabs.iter()
+            .flat_map(|tab| &tab.buffers)
+            .filter_map(|entry| entry.path.clone())
+            .collect();
+        let closed = self.workspace.recent_files.iter().filter(|path| !open.contains(path));
+        for path in closed.take(PALETTE_RECENT_FILES) {
+            let path = path.to_string_lossy();
+            commands.push(PaletteCommand::contributed(format!("Reopen {}", path), "File", "reopen", &path));
+        }
+        commands
+    }
+
+    /// Run a command from `context_commands`
+    fn execute_contributed_command(&mut self, action: &str, argument: &str) {
+        match action {
+            "switch-branch" => match git::branch::switch(&self.workspace.root, argument) {
+                Ok(()) => {
+                    self.workspace.fuss.refresh_git_status();
+                    self.messages.info(format!("Switched to {}", argument));
+                }
+                Err(e) => self.messages.error(format!("Switch failed: {}", e)),
+            },
+            "restart-server" => {
+                let contents = self.open_document_contents();
+                match self.workspace.lsp.restart_server(argument, &contents) {
+                    Ok(_) => self.messages.info(format!("Restarted {}", argument)),
+                    Err(e) => self.messages.error(format!("Failed to restart {}: {}", argument, e)),
+                }
+            }
+            "reopen" => {
+                let _ = self.quick_open_file(Path::new(argument), OpenTarget::Pane);
+            }
+            _ => self.messages.info(format!("Unknown command: {}:{}", action, argument)),
+        }
+    }
+
+    /// Open quick open over a fresh index of the workspace's files
+    fn open_quick_open(&mut self) {
+        if !self.deep_scan_allowed("quick-open") {
+            return;
+        }
+        let files = crate::workspace::list_files(&self.workspace.root, &self.workspace.config.excluded_dirs);
+        let mut filtered = filter_files("", &files, &self.workspace.recent_files);
+        // The file being edited is the least likely target
+        if let Some(current) = self.current_file_path() {
+            if let Ok(relative) = current.strip_prefix(&self.workspace.root) {
+                if let Some(pos) = filtered.iter().position(|p| p == relative) {
+                    let path = filtered.remove(pos);
+                    filtered.push(path);
+                }
+            }
+        }
+        self.prompt = PromptState::QuickOpen {
+            query: String::new(),
+            files,
             filtered,
             selected_index: 0,
             scroll_offset: 0,
         };
     }
 
+    /// Open a quick open result (relative to the workspace root)
+    fn quick_open_file(&mut self, path: &Path, target: OpenTarget) -> Result<()> {
+        let path = self.workspace.root.join(path);
+        let result = match target {
+            OpenTarget::Pane if image::is_image(&path) => self.workspace.open_file(&path),
+            OpenTarget::Pane => self.workspace.open_file_in_pane(&path),
+            OpenTarget::VerticalSplit => self.open_file_in_vsplit(&path),
+            OpenTarget::HorizontalSplit => self.open_file_in_hsplit(&path),
+            OpenTarget::NewTab => self.workspace.open_file_in_new_tab(&path),
+        };
+        match result {
+            Ok(()) => self.sync_document_to_lsp(),
+            Err(e) => self.messages.error(format!("Failed to open file: {}", e)),
+        }
+        Ok(())
+    }
+
+    /// Insert a palette calculation's result at the cursor
+    fn insert_calc_result(&mut self, result: &str) {
+        if let Some(reason) = self.read_only_reason() {
+            self.messages.info(reason.to_string());
+            return;
+        }
+        self.insert_text(result);
+    }
+
+    /// Open the color theme picker with the current theme selected
+    fn open_theme_picker(&mut self) {
+        let original = self.screen.theme.name.clone();
+        let filtered = filter_themes("");
+        let selected_index = filtered.iter().position(|(name, _)| *name == original).unwrap_or(0);
+        self.prompt = PromptState::ThemePicker {
+            query: String::new(),
+            filtered,
+            selected_index,
+            scroll_offset: selected_index.saturating_sub(14),
+            original,
+        };
+    }
+
+    /// Show `name` without saving it to the workspace config
+    fn preview_theme(&mut self, name: &str) {
+        if self.screen.theme.name != name {
+            self.screen.theme = Theme::load(name).0;
+        }
+    }
+
     /// Execute a command by its ID
     fn execute_command(&mut self, command_id: &str) {
+        if let Some(reason) = self.read_only_reason().filter(|_| is_edit_command(command_id)) {
+            self.messages.info(reason.to_string());
+            return;
+        }
+        if let Some((action, argument)) = command_id.split_once(':') {
+            self.execute_contributed_command(action, argument);
+            return;
+        }
         match command_id {
             // File operations
-            "save" => { let _ = self.save(); }
+            "save" => {
+                if let Err(e) = self.save() {
+                    self.messages.error(format!("Save failed: {}", e));
+                }
+            }
+            "save-as" => self.prompt_save_as(),
             "save-all" => { let _ = self.workspace.save_all(); }
             "open" => self.open_fortress(),
             "new-tab" => self.workspace.new_tab(),
@@ -6439,6 +13413,19 @@ impl Editor {
             "next-tab" => self.workspace.next_tab(),
             "prev-tab" => self.workspace.prev_tab(),
             "quit" => self.try_quit(),
+            "suspend" => { let _ = self.suspend(); }
+            "refresh-document" => self.refresh_virtual_document(),
+            "toggle-recording" => self.toggle_recording(),
+            "macro-record" => self.toggle_macro_recording(),
+            "macro-replay" => match self.macros.last {
+                Some(register) => self.replay_macro(register, 1),
+                None => self.messages.info("No macro recorded (F7 records one)".to_string()),
+            },
+            "macro-replay-prompt" => self.open_replay_macro(),
+            "copy-path" => self.copy_path(PathForm::Absolute),
+            "copy-relative-path" => self.copy_path(PathForm::Relative),
+            "copy-path-line" => self.copy_path(PathForm::WithLine),
+            "copy-filename" => self.copy_path(PathForm::FileName),
 
             // Edit operations
             "undo" => self.undo(),
@@ -6446,6 +13433,19 @@ impl Editor {
             "cut" => self.cut(),
             "copy" => self.copy(),
             "paste" => self.paste(),
+            "paste-special" => self.open_clipboard_ring(),
+            "paste-plain" => {
+                let text = self.get_clipboard();
+                self.paste_text(&text, PasteMode::Plain);
+            }
+            "paste-select" => {
+                let text = self.get_clipboard();
+                self.paste_text(&text, PasteMode::Select);
+            }
+            "paste-over-lines" => {
+                let text = self.get_clipboard();
+                self.paste_text(&text, PasteMode::OverLines);
+            }
             "select-all" => {
                 // Select all text in current buffer
                 let line_count = self.buffer().line_count();
@@ -6459,45 +13459,51 @@ impl Editor {
             }
             "select-line" => self.select_line(),
             "select-word" => self.select_word(),
+            "select-all-occurrences" => self.select_all_occurrences(),
+            "cursors-to-line-ends" => self.add_cursors_to_line_ends(),
             "toggle-comment" => self.toggle_line_comment(),
             "join-lines" => self.join_lines(),
+            "filter-command" => self.open_filter_command(),
+            "insert-command-output" => self.open_insert_command_output(),
             "duplicate-line" => self.duplicate_line_down(),
+            "duplicate-line-up" => self.duplicate_line_up(),
+            "duplicate-selection" => self.duplicate_selection(),
             "move-line-up" => self.move_line_up(),
             "move-line-down" => self.move_line_down(),
-            "delete-line" => {
-                // Delete the current line
-                let line = self.cursor().line;
-                let line_count = self.buffer().line_count();
-                let line_start = self.buffer().line_col_to_char(line, 0);
-                let line_end = if line + 1 < line_count {
-                    self.buffer().line_col_to_char(line + 1, 0)
-                } else {
-                    self.buffer().len_chars()
-                };
-                if line_start < line_end {
-                    self.buffer_mut().delete(line_start, line_end);
-                    self.cursor_mut().col = 0;
-                    self.cursor_mut().desired_col = 0;
-                    // Clamp line if we deleted the last line
-                    let new_line_count = self.buffer().line_count();
-                    if self.cursor().line >= new_line_count {
-                        self.cursor_mut().line = new_line_count.saturating_sub(1);
-                    }
-                }
-            }
+            "delete-line" => self.delete_lines(),
             "indent" => self.insert_tab(),
             "outdent" => self.dedent(),
             "transpose" => self.transpose_chars(),
+            "delete-backward" => {
+                self.delete_backward();
+                self.update_ghost_text();
+            }
+            "delete-word-backward" => {
+                self.delete_word_backward();
+                self.update_ghost_text();
+            }
+            "delete-word-forward" => self.delete_word_forward(),
+            "kill-to-line-end" => self.kill_to_end_of_line(),
+            "kill-to-line-start" => self.kill_to_start_of_line(),
+            "yank" => self.yank(),
+            "yank-cycle" => self.yank_cycle(),
 
             // Search operations
             "find" => self.open_find(),
             "replace" => self.open_replace(),
             "find-next" => self.find_next(),
             "find-prev" => self.find_prev(),
-            "search-files" => self.open_file_search(),
+            "search-files" => self.open_file_search(false),
+            "live-grep" => self.open_live_grep(),
+            "search-open-files" => self.open_file_search(true),
+            "extract-strings" => self.open_strings_panel(),
 
             // Navigation
             "goto-line" => self.open_goto_line(),
+            "open-workspace" => self.prompt_open_workspace(),
+            "close-workspace" => self.close_workspace(),
+            "next-workspace" => self.cycle_workspace(true),
+            "prev-workspace" => self.cycle_workspace(false),
             "goto-start" => {
                 self.cursor_mut().line = 0;
                 self.cursor_mut().col = 0;
@@ -6513,41 +13519,134 @@ impl Editor {
                 self.cursor_mut().clear_selection();
             }
             "goto-bracket" => self.jump_to_matching_bracket(),
+            "word-left" => self.move_word_left(false),
+            "word-right" => self.move_word_right(false),
+            "select-word-left" => self.move_word_left(true),
+            "select-word-right" => self.move_word_right(true),
+            "line-start" => self.smart_home(false),
+            "line-end" => self.move_end(false),
+            "select-line-start" => self.smart_home(true),
+            "select-line-end" => self.move_end(true),
             "page-up" => self.page_up(false),
             "page-down" => self.page_down(false),
+            "set-mark" => self.prompt_mark(MarkAction::Set),
+            "jump-mark-line" => self.prompt_mark(MarkAction::JumpLine),
+            "jump-mark" => self.prompt_mark(MarkAction::JumpExact),
+            "list-marks" => self.open_marks_panel(),
+            "toggle-bookmark" => self.toggle_bookmark(),
+            "label-bookmark" => self.open_bookmark_label(),
+            "next-bookmark" => self.next_bookmark(true),
+            "prev-bookmark" => self.next_bookmark(false),
+            "list-bookmarks" => self.open_bookmarks_panel(),
 
             // Selection
-            "select-brackets" => self.jump_to_matching_bracket(), // TODO: implement select inside brackets
+            "select-brackets" => self.select_pair(false),
+            "select-inside" => self.select_pair(false),
+            "select-around" => self.select_pair(true),
+            "delete-inside" => self.delete_pair(false),
+            "delete-around" => self.delete_pair(true),
             "cursor-above" => self.add_cursor_above(),
+            "block-select" => self.toggle_block_select(),
             "cursor-below" => self.add_cursor_below(),
+            "cursor-remove-last" => self.remove_last_cursor(),
+            "cursor-rotate-next" => self.rotate_primary_cursor(true),
+            "cursor-rotate-prev" => self.rotate_primary_cursor(false),
 
             // View / Panes
             "split-vertical" => self.split_vertical(),
             "split-horizontal" => self.split_horizontal(),
             "close-pane" => self.close_pane(),
-            "next-pane" => self.tab_mut().navigate_pane(PaneDirection::Right),
-            "prev-pane" => self.tab_mut().navigate_pane(PaneDirection::Left),
-            "toggle-explorer" => self.workspace.fuss.toggle(),
+            "terminal-height" => self.cycle_terminal_height(),
+            "terminal-maximize" => self.toggle_terminal_maximize(),
+            "next-pane" => self.next_pane(),
+            "prev-pane" => self.prev_pane(),
+            "toggle-fold" => self.toggle_fold(),
+            "unfold" => self.unfold(),
+            "fold-all" => self.fold_all(),
+            "unfold-all" => self.unfold_all(),
+            "toggle-wrap" => self.toggle_wrap(),
+            "toggle-minimap" => self.toggle_minimap(),
+            "toggle-indent-guides" => self.toggle_indent_guides(),
+            "cycle-whitespace" => self.cycle_whitespace(),
+            "toggle-current-line" => self.toggle_current_line_highlight(),
+            "navigate-back" => self.navigate(true),
+            "navigate-forward" => self.navigate(false),
+            "pane-back" => self.pane_back(),
+            "pane-forward" => self.pane_forward(),
+            "pane-left" => self.navigate_pane_left(),
+            "pane-down" => self.navigate_pane_down(),
+            "pane-up" => self.navigate_pane_up(),
+            "pane-right" => self.navigate_pane_right(),
+            "toggle-explorer" => self.toggle_fuss_mode(),
+            "select-theme" => self.open_theme_picker(),
+            "view-log" => {
+                self.log_len = 0;
+                self.open_virtual_document(VirtualDocument::EditorLog { path: self.workspace.log_file() });
+            }
+            "cycle-log-level" => self.cycle_log_level(),
+            tab if tab.starts_with("tab-") => {
+                if let Some(n) = tab[4..].parse::<usize>().ok().filter(|n| *n > 0) {
+                    self.workspace.switch_to_tab(n - 1);
+                }
+            }
 
             // LSP operations
             "goto-definition" => self.lsp_goto_definition(),
             "find-references" => self.lsp_find_references(),
             "rename" => self.lsp_rename(),
             "hover" => self.lsp_hover(),
-            "completion" => self.filter_completions(),
+            "completion" => self.lsp_complete(),
+            "expand-snippet" => {
+                if !self.expand_snippet() {
+                    self.messages.info("No snippet before the cursor".to_string());
+                }
+            }
+            "code-lens" => self.run_code_lens(),
+            "code-actions" => self.lsp_code_actions(),
+            "refresh-code-lens" => self.request_code_lens(),
+            "open-link" => self.open_link_at_cursor(),
+            "toggle-error-lens" => self.toggle_error_lens(),
+            "diagnostics-panel" => self.open_diagnostics_panel(),
+            "toggle-inline-blame" => self.toggle_inline_blame(),
+            "toggle-ansi-colors" => self.toggle_ansi_colors(),
+            "strip-ansi" => self.strip_ansi_codes(),
+            "indent-spaces" => self.convert_indentation(Indent::Spaces(0)),
+            "indent-tabs" => self.convert_indentation(Indent::Tabs),
+            "show-statistics" => self.show_statistics(),
+            "toggle-statistics" => self.toggle_statistics(),
+            "show-insights" => self.show_insights(),
+            "toggle-insights" => self.toggle_insights(),
+            "toggle-auto-hide" => self.toggle_auto_hide(),
+            "pin-explorer" => self.toggle_pin_explorer(),
+            "pin-terminal" => self.toggle_pin_terminal(),
+            "next-hunk" => self.goto_hunk(true),
+            "prev-hunk" => self.goto_hunk(false),
+            "stage-hunk" => self.stage_hunk(),
+            "revert-hunk" => self.revert_hunk(),
+            "compare-head" => self.compare_with_head(),
+            "compare-backup" => self.compare_with_backup(),
+            "git-log" => self.open_git_log(None),
+            "alternate-file" => self.alternate_file(),
+            "recover-version" => self.open_recover_version(),
+            "reload-file" => self.reload_current_file(),
+            "project-notes" => self.open_notes(),
+            "toggle-vim" => self.toggle_vim_mode(),
             "server-manager" => self.toggle_server_manager(),
 
             // Bracket/Quote operations
             "jump-bracket" => self.jump_to_matching_bracket(),
             "cycle-brackets" => self.cycle_brackets(),
             "remove-surrounding" => self.remove_surrounding(),
+            "cycle-quotes" => self.cycle_quotes(),
+            "remove-surrounding-brackets" => self.remove_surrounding_brackets(),
 
             // Help
-            "command-palette" => {} // Already open
+            "command-palette" => self.open_command_palette(),
+            "quick-open" => self.open_quick_open(),
             "help" => self.open_help_menu(),
 
             _ => {
-                self.message = Some(format!("Unknown command: {}", command_id));
+                self.messages.info(format!("Unknown command: {}", command_id));
             }
         }
     }
@@ -6556,7 +13655,7 @@ impl Editor {
 
     /// Open the help menu with keybindings
     fn open_help_menu(&mut self) {
-        let filtered = filter_keybinds("");
+        let filtered = filter_keybinds("", &self.workspace.keymap);
         self.prompt = PromptState::HelpMenu {
             query: String::new(),
             filtered,
@@ -6567,71 +13666,40 @@ impl Editor {
     }
 }
 
-/// Fuzzy match scoring for command palette
-fn fuzzy_match_score(text: &str, pattern: &str) -> i32 {
-    if pattern.is_empty() {
-        return 100; // Empty pattern matches everything with base score
-    }
-
-    let text_lower = text.to_lowercase();
-    let pattern_lower = pattern.to_lowercase();
-
-    let mut score = 0i32;
-    let mut pattern_idx = 0;
-    let mut consecutive = 0;
-    let pattern_chars: Vec<char> = pattern_lower.chars().collect();
-    let text_chars: Vec<char> = text_lower.chars().collect();
+/// Palette rows offered for a `=` calculation with a valid result
+const CALC_COMMANDS: &[PaletteCommand] = &[
+    PaletteCommand::new("Insert Result", "Enter", "Calc", "calc-insert"),
+    PaletteCommand::new("Copy Result", "", "Calc", "calc-copy"),
+];
 
-    if pattern_chars.is_empty() {
-        return 100;
+/// Filter and sort the `context` commands, then the fixed ones, by fuzzy
+/// match score; a query starting with `=` is a calculation instead
+fn filter_commands(query: &str, context: &[PaletteCommand], keymap: &Keymap) -> Vec<PaletteCommand> {
+    if let Some(expr) = query.strip_prefix('=') {
+        return match calc::evaluate(expr) {
+            Ok(_) => CALC_COMMANDS.to_vec(),
+            Err(_) => Vec::new(),
+        };
     }
-
-    for (i, &tc) in text_chars.iter().enumerate() {
-        if pattern_idx >= pattern_chars.len() {
-            break;
-        }
-
-        if tc == pattern_chars[pattern_idx] {
-            score += 10;
-            consecutive += 1;
-
-            // Bonus for consecutive matches
-            if consecutive > 1 {
-                score += 5;
-            }
-
-            // Bonus for matching at start or after space/separator
-            if i == 0 || matches!(text_chars.get(i.wrapping_sub(1)), Some(' ' | ':' | '-' | '_' | '/')) {
-                score += 15;
-            }
-
-            pattern_idx += 1;
-        } else {
-            consecutive = 0;
+    let fixed = ALL_COMMANDS.iter().map(|cmd| {
+        let mut cmd = cmd.clone();
+        if keymap.covers(&cmd.id) {
+            cmd.shortcut = keymap.shortcut(&cmd.id).map(Cow::Owned).unwrap_or_default();
         }
-    }
-
-    // Only return positive score if all pattern characters matched
-    if pattern_idx == pattern_chars.len() {
-        score
-    } else {
-        0
-    }
-}
-
-/// Filter and sort commands by fuzzy match score
-fn filter_commands(query: &str) -> Vec<PaletteCommand> {
-    let mut filtered: Vec<PaletteCommand> = ALL_COMMANDS
+        cmd
+    });
+    let mut filtered: Vec<PaletteCommand> = context
         .iter()
-        .filter_map(|cmd| {
+        .cloned()
+        .chain(fixed)
+        .filter_map(|mut cmd| {
             // Match against name, category, or command ID
-            let name_score = fuzzy_match_score(cmd.name, query);
-            let category_score = fuzzy_match_score(cmd.category, query) / 2; // Category match worth less
-            let id_score = fuzzy_match_score(cmd.id, query) / 2;
+            let name_score = fuzzy::score(&cmd.name, query);
+            let category_score = fuzzy::score(cmd.category, query) / 2; // Category match worth less
+            let id_score = fuzzy::score(&cmd.id, query) / 2;
 
             let score = name_score.max(category_score).max(id_score);
             if score > 0 {
-                let mut cmd = cmd.clone();
                 cmd.score = score;
                 Some(cmd)
             } else {
@@ -6645,24 +13713,79 @@ fn filter_commands(query: &str) -> Vec<PaletteCommand> {
     filtered
 }
 
+/// Whether a highlighted string token holds a whole literal on one line
+/// (opening and closing quote), rather than part of a multi-line string
+fn is_complete_literal(text: &str) -> bool {
+    let Some(open) = text.find(['"', '\'', '`']) else {
+        return false;
+    };
+    let quote = text.as_bytes()[open];
+    text.len() >= open + 2 && text.as_bytes()[text.len() - 1] == quote
+}
+
+/// Most quick open rows listed at once
+const QUICK_OPEN_LIMIT: usize = 500;
+
+/// Quick open rows for `query`: recently opened files, then the rest, when
+/// the query is empty; otherwise best fuzzy match first, recent files first
+/// among equals
+fn filter_files(query: &str, files: &[PathBuf], recent: &[PathBuf]) -> Vec<PathBuf> {
+    let recent_rank = |path: &PathBuf| recent.iter().position(|r| r == path).unwrap_or(usize::MAX);
+    if query.is_empty() {
+        let indexed: std::collections::HashSet<&PathBuf> = files.iter().collect();
+        let recents = recent.iter().filter(|p| indexed.contains(p));
+        let others = files.iter().filter(|p| !recent.contains(p));
+        return recents.chain(others).take(QUICK_OPEN_LIMIT).cloned().collect();
+    }
+    let mut scored: Vec<(&PathBuf, i32)> = files
+        .iter()
+        .map(|path| (path, fuzzy::score_path(&path.to_string_lossy(), query)))
+        .filter(|(_, score)| *score > 0)
+        .collect();
+    scored.sort_by(|(a, a_score), (b, b_score)| {
+        b_score.cmp(a_score)
+            .then_with(|| recent_rank(a).cmp(&recent_rank(b)))
+            .then_with(|| a.as_os_str().len().cmp(&b.as_os_str().len()))
+    });
+    scored.into_iter().take(QUICK_OPEN_LIMIT).map(|(path, _)| path.clone()).collect()
+}
+
+/// Filter available themes by fuzzy match, best match first
+fn filter_themes(query: &str) -> Vec<(String, bool)> {
+    let themes = Theme::available();
+    if query.is_empty() {
+        return themes;
+    }
+    let mut scored: Vec<((String, bool), i32)> = themes
+        .into_iter()
+        .map(|theme| {
+            let score = fuzzy::score(&theme.0, query);
+            (theme, score)
+        })
+        .filter(|(_, score)| *score > 0)
+        .collect();
+    scored.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(theme, _)| theme).collect()
+}
+
 /// Filter keybinds by fuzzy match (for help menu)
-fn filter_keybinds(query: &str) -> Vec<HelpKeybind> {
+fn filter_keybinds(query: &str, keymap: &Keymap) -> Vec<HelpKeybind> {
+    let keybinds = ALL_KEYBINDS.iter().filter_map(|kb| resolve_keybind(kb, keymap));
     if query.is_empty() {
         // Return all keybinds in original order (grouped by category)
-        return ALL_KEYBINDS.to_vec();
+        return keybinds.collect();
     }
 
-    let mut filtered: Vec<(HelpKeybind, i32)> = ALL_KEYBINDS
-        .iter()
+    let mut filtered: Vec<(HelpKeybind, i32)> = keybinds
         .filter_map(|kb| {
             // Match against shortcut, description, or category
-            let shortcut_score = fuzzy_match_score(kb.shortcut, query);
-            let desc_score = fuzzy_match_score(kb.description, query);
-            let category_score = fuzzy_match_score(kb.category, query) / 2;
+            let shortcut_score = fuzzy::score(&kb.shortcut, query);
+            let desc_score = fuzzy::score(kb.description, query);
+            let category_score = fuzzy::score(kb.category, query) / 2;
 
             let score = shortcut_score.max(desc_score).max(category_score);
             if score > 0 {
-                Some((kb.clone(), score))
+                Some((kb, score))
             } else {
                 None
             }
@@ -6674,12 +13797,192 @@ fn filter_keybinds(query: &str) -> Vec<HelpKeybind> {
     filtered.into_iter().map(|(kb, _)| kb).collect()
 }
 
+/// Fill in a help entry's shortcuts from the keymap; None if its command is unbound
+fn resolve_keybind(kb: &HelpKeybind, keymap: &Keymap) -> Option<HelpKeybind> {
+    let mut kb = kb.clone();
+    if !kb.command.is_empty() {
+        let chords = keymap.chords_for(kb.command);
+        kb.shortcut = Cow::Owned(chords.first()?.to_string());
+        kb.alt_shortcut = chords.get(1).map(|c| Cow::Owned(c.to_string())).unwrap_or_default();
+    }
+    Some(kb)
+}
+
+/// What the scripted test harness drives and inspects (see `harness.rs`)
+#[cfg(any(test, feature = "harness"))]
+impl Editor {
+    /// Handle a key press as if typed, past the Escape-prefix handling
+    pub(super) fn press(&mut self, key: Key, mods: Modifiers) -> Result<()> {
+        self.handle_key_with_mods(key, mods)?;
+        self.auto_hide_panels();
+        self.note_file_switch();
+        self.sync_compare_scroll();
+        Ok(())
+    }
+
+    /// Open a file relative to the workspace root in the active pane
+    pub(super) fn open_relative(&mut self, path: &str) -> Result<()> {
+        let path = self.workspace.root.join(path);
+        self.workspace.open_file(&path)?;
+        self.note_file_switch();
+        Ok(())
+    }
+
+    pub(super) fn line_text(&self, line: usize) -> Option<String> {
+        self.buffer().line_str(line)
+    }
+
+    /// Cursor positions as (line, col), the primary first
+    pub(super) fn cursor_positions(&self) -> Vec<(usize, usize)> {
+        let cursors = self.cursors();
+        let primary = cursors.primary();
+        let others = cursors.all().iter().filter(|c| !std::ptr::eq(*c, primary));
+        std::iter::once(primary).chain(others).map(|c| (c.line, c.col)).collect()
+    }
+
+    /// Give the active file code lenses titled `title` on `lines`, as if
+    /// its server had sent them
+    pub(super) fn set_code_lenses(&mut self, lines: &[usize], title: &str) {
+        self.lsp_state.code_lenses = lines.iter().map(|&line| CodeLens {
+            range: LspRange::point(LspPosition::new(line as u32, 0)),
+            command: Some(Command { title: title.to_string(), command: String::new(), arguments: Vec::new() }),
+            data: None,
+        }).collect();
+    }
+
+    /// Accept a completion of `label` whose `edits` come from
+    /// completionItem/resolve, and answer the resolve as its server would
+    pub(super) fn accept_resolved_completion(&mut self, label: &str, edits: Vec<TextEdit>) {
+        let item = CompletionItem {
+            label: label.to_string(),
+            kind: None,
+            detail: None,
+            documentation: None,
+            insert_text: None,
+            is_snippet: false,
+            text_edit: None,
+            sort_text: None,
+            filter_text: None,
+            additional_text_edits: Vec::new(),
+            raw: None,
+        };
+        let insertion = self.insert_completion(&item);
+        let path = self.current_file_path().unwrap_or_default();
+        self.lsp_state.pending_completion_resolve = Some((1, path, insertion));
+        let resolved = CompletionItem { additional_text_edits: edits, ..item };
+        self.workspace.lsp.push_response(crate::lsp::LspResponse::CompletionResolved(1, resolved));
+        self.process_lsp_messages();
+    }
+
+    /// Hand `edit` over as a server's workspace/applyEdit request; returns
+    /// where the answer to the server arrives
+    pub(super) fn server_edit(&mut self, edit: WorkspaceEdit) -> std::sync::mpsc::Receiver<ApplyEditResult> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.workspace.lsp.push_response(crate::lsp::LspResponse::ApplyEdit(edit, tx));
+        self.process_lsp_messages();
+        rx
+    }
+
+    /// Status bar message, if any
+    pub(super) fn message(&self) -> Option<String> {
+        self.messages.current().map(|(text, _)| text.to_string())
+    }
+
+    /// Draw a frame and return what was written to the screen
+    pub(super) fn draw(&mut self) -> Result<Vec<u8>> {
+        self.screen.take_output();
+        self.render()?;
+        Ok(self.screen.take_output())
+    }
+}
+
 impl Drop for Editor {
     fn drop(&mut self) {
         let _ = self.screen.leave_raw_mode();
     }
 }
 
+/// Compile a find query for regex search; None while it isn't a valid regex
+fn search_regex(query: &str, case_insensitive: bool) -> Option<regex::Regex> {
+    let pattern = if case_insensitive {
+        format!("(?i){}", query)
+    } else {
+        query.to_string()
+    };
+    regex::Regex::new(&pattern).ok()
+}
+
+/// Replacement for match `m` on `line`. With a regex, `$1`, `${name}` and
+/// `$$` in `replace_text` expand from the match's captures; otherwise the
+/// text is used as is.
+fn expand_replacement(re: Option<&regex::Regex>, line: &str, m: &SearchMatch, replace_text: &str) -> String {
+    let Some(re) = re else {
+        return replace_text.to_string();
+    };
+    let start = line.char_indices().nth(m.start_col).map_or(line.len(), |(i, _)| i);
+    match re.captures_at(line, start) {
+        Some(caps) if caps.get(0).is_some_and(|whole| whole.start() == start) => {
+            let mut expanded = String::new();
+            caps.expand(replace_text, &mut expanded);
+            expanded
+        }
+        _ => replace_text.to_string(),
+    }
+}
+
+fn workspace_locked_message(pid: u32) -> String {
+    format!(
+        "Workspace is open in another fackr (PID {}). [R]ead-only / [S]teal lock / [O]pen without saving state / [Q]uit",
+        pid
+    )
+}
+
+/// Keys that modify the buffer, blocked in read-only virtual documents
+fn is_edit_key(key: &Key, mods: &Modifiers) -> bool {
+    match (key, mods) {
+        (Key::Char(_), Modifiers { ctrl: false, alt: false, .. }) => true,
+        (Key::Enter, Modifiers { alt: false, .. }) => true,
+        (Key::Backspace | Key::Delete | Key::Tab | Key::BackTab, _) => true,
+        (Key::Char(c), Modifiers { ctrl: true, alt: false, .. }) => "xvrjwkuyth/_7".contains(*c),
+        (Key::Char(c), Modifiers { ctrl: false, alt: true, .. }) => "dy'\"()!|".contains(*c),
+        (Key::Char('\x1f'), _) => true,
+        (Key::Up | Key::Down, Modifiers { ctrl: false, alt: true, .. }) => true,
+        _ => false,
+    }
+}
+
+/// Palette commands that modify the buffer, blocked in read-only virtual documents
+fn is_edit_command(id: &str) -> bool {
+    matches!(
+        id,
+        "cut" | "paste" | "paste-special" | "paste-plain" | "paste-select" | "paste-over-lines" | "toggle-comment" | "join-lines" | "filter-command"
+            | "insert-command-output" | "duplicate-line" | "move-line-up" | "move-line-down"
+            | "delete-line" | "indent" | "outdent" | "transpose" | "replace" | "rename"
+            | "cycle-brackets" | "remove-surrounding" | "duplicate-line-up" | "duplicate-selection" | "delete-backward"
+            | "delete-word-backward" | "delete-word-forward" | "kill-to-line-end"
+            | "kill-to-line-start" | "yank" | "yank-cycle" | "cycle-quotes"
+            | "remove-surrounding-brackets" | "revert-hunk" | "strip-ansi" | "expand-snippet" | "recover-version"
+            | "reload-file" | "indent-spaces" | "indent-tabs" | "delete-inside" | "delete-around"
+    )
+}
+
+/// Where buffer index `pos` ends up after `edits` (from, to, inserted text),
+/// which don't overlap; a position at an insertion moves past it when
+/// `after_insert`
+fn map_snippet_pos(pos: usize, edits: &[(usize, usize, String)], after_insert: bool) -> usize {
+    let mut shift: isize = 0;
+    for (from, to, inserted) in edits {
+        let inserted = inserted.chars().count() as isize;
+        if pos > *to || (pos == *to && *to > *from && after_insert) || (pos == *from && from == to && after_insert) {
+            shift += inserted - (to - from) as isize;
+        } else if pos > *from {
+            // Inside a deleted span: to its start, or past the new text
+            shift += if after_insert { inserted } else { 0 } - (pos - from) as isize;
+        }
+    }
+    (pos as isize + shift) as usize
+}
+
 /// Check if a character is a "word" character (alphanumeric or underscore)
 fn is_word_char(c: char) -> bool {
     c.is_alphanumeric() || c == '_'
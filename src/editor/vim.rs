@@ -0,0 +1,617 @@
+//! Vim emulation
+//!
+//! `Vim` turns keys typed in normal and visual mode into `VimAction`s:
+//! counts (`3dw`), operators (`d`, `c`, `y`), motions, text objects (`iw`,
+//! `i"`, `i(`) and registers (`"ayy`). The editor carries the actions out;
+//! keys typed in insert mode go to regular editing untouched.
+
+use std::collections::HashMap;
+
+use crate::input::{Key, Modifiers};
+
+/// Editing mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+impl Mode {
+    /// Mode indicator for the status bar
+    pub fn label(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+            Mode::VisualLine => "V-LINE",
+        }
+    }
+
+    pub fn is_visual(self) -> bool {
+        matches!(self, Mode::Visual | Mode::VisualLine)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FileStart,
+    FileEnd,
+    /// `{count}G` / `{count}gg`, 1-based
+    Line(usize),
+}
+
+impl Motion {
+    /// Operators on this motion act on whole lines (`dj`, `dG`)
+    pub fn is_linewise(self) -> bool {
+        matches!(self, Motion::Up | Motion::Down | Motion::FileStart | Motion::FileEnd | Motion::Line(_))
+    }
+
+    /// The character the motion lands on is part of the operated range (`de`)
+    pub fn is_inclusive(self) -> bool {
+        matches!(self, Motion::WordEnd | Motion::LineEnd)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Word,
+    Quote(char),
+    Bracket(char, char),
+}
+
+/// `iw`, `a"`, `i(`, ...
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextObject {
+    pub kind: ObjectKind,
+    /// `a` objects include the delimiters (or trailing whitespace for words)
+    pub around: bool,
+}
+
+/// What an operator acts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    /// `dd`, `cc`, `yy`: `count` whole lines
+    Lines,
+    /// `x`: `count` characters, stopping at the end of the line
+    Chars,
+}
+
+/// Where `i`, `a`, `o`, ... start inserting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertAt {
+    Cursor,
+    AfterCursor,
+    FirstNonBlank,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VimAction {
+    Move { motion: Motion, count: usize },
+    Operate { operator: Operator, target: Target, count: usize, register: Option<char> },
+    /// `d`, `c` or `y` on the visual selection
+    OperateSelection { operator: Operator, register: Option<char> },
+    /// Extend the visual selection over a text object
+    SelectObject(TextObject),
+    Put { before: bool, count: usize, register: Option<char> },
+    Insert(InsertAt),
+    SetMode(Mode),
+    Undo(usize),
+    Redo(usize),
+    /// Run an editor command (palette ID)
+    Command(&'static str),
+    /// Key consumed without effect (part of an unfinished or unknown command)
+    Nothing,
+}
+
+/// Contents of a register
+#[derive(Debug, Clone, PartialEq)]
+pub struct Register {
+    pub text: String,
+    /// Whole lines (`yy`, `dd`): put above/below instead of at the cursor
+    pub linewise: bool,
+}
+
+/// Half-typed command: `"a2d` waiting for a motion, `g` waiting for `g`, ...
+#[derive(Debug, Default)]
+struct Pending {
+    register: Option<char>,
+    count: Option<usize>,
+    operator: Option<(Operator, Option<usize>)>,
+    /// `"` (register name), `g`, or `i` / `a` (text object)
+    prefix: Option<char>,
+}
+
+#[derive(Debug)]
+pub struct Vim {
+    pub mode: Mode,
+    registers: HashMap<char, Register>,
+    pending: Pending,
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Normal,
+            registers: HashMap::new(),
+            pending: Pending::default(),
+        }
+    }
+
+    /// Drop any half-typed command
+    pub fn reset(&mut self) {
+        self.pending = Pending::default();
+    }
+
+    /// Typed-so-far command for the status bar, e.g. `"a2d`
+    pub fn pending_keys(&self) -> String {
+        let p = &self.pending;
+        let mut keys = String::new();
+        if let Some(register) = p.register {
+            keys.push('"');
+            keys.push(register);
+        }
+        if let Some((operator, count)) = p.operator {
+            if let Some(count) = count {
+                keys.push_str(&count.to_string());
+            }
+            keys.push(match operator {
+                Operator::Delete => 'd',
+                Operator::Change => 'c',
+                Operator::Yank => 'y',
+            });
+        }
+        if let Some(count) = p.count {
+            keys.push_str(&count.to_string());
+        }
+        if let Some(prefix) = p.prefix {
+            keys.push(prefix);
+        }
+        keys
+    }
+
+    /// Interpret a key. `None` means the key isn't vim's: insert mode typing
+    /// and Ctrl/Alt chords go through the regular keymap.
+    pub fn handle_key(&mut self, key: &Key, mods: &Modifiers) -> Option<VimAction> {
+        if self.mode == Mode::Insert {
+            return match key {
+                Key::Escape => Some(VimAction::SetMode(Mode::Normal)),
+                _ => None,
+            };
+        }
+
+        if mods.ctrl || mods.alt {
+            return match (key, mods.ctrl) {
+                (Key::Char('r'), true) => {
+                    let count = self.take_count();
+                    self.reset();
+                    Some(VimAction::Redo(count))
+                }
+                _ => {
+                    self.reset();
+                    None
+                }
+            };
+        }
+
+        let ch = match key {
+            Key::Char(c) => *c,
+            Key::Escape => {
+                self.reset();
+                return Some(VimAction::SetMode(Mode::Normal));
+            }
+            Key::Left | Key::Backspace => 'h',
+            Key::Right => 'l',
+            Key::Up => 'k',
+            Key::Down | Key::Enter => 'j',
+            Key::Home => '0',
+            Key::End => '$',
+            Key::Delete => 'x',
+            Key::Tab | Key::BackTab => {
+                self.reset();
+                return Some(VimAction::Nothing);
+            }
+            _ => {
+                self.reset();
+                return None;
+            }
+        };
+
+        let action = self.handle_char(ch);
+        if action.is_some() {
+            self.reset();
+        }
+        // Keys vim doesn't know are swallowed rather than typed
+        Some(action.unwrap_or(VimAction::Nothing))
+    }
+
+    /// Next step of a normal/visual mode command; `None` while it's incomplete
+    fn handle_char(&mut self, ch: char) -> Option<VimAction> {
+        match self.pending.prefix.take() {
+            Some('"') => {
+                self.pending.register = Some(ch);
+                return None;
+            }
+            Some('g') => {
+                if ch != 'g' {
+                    return self.cancel();
+                }
+                let motion = match self.pending.count.take() {
+                    Some(line) => Motion::Line(line),
+                    None => Motion::FileStart,
+                };
+                return self.motion(motion);
+            }
+            Some(prefix @ ('i' | 'a')) => {
+                let kind = match ch {
+                    'w' => ObjectKind::Word,
+                    '"' | '\'' | '`' => ObjectKind::Quote(ch),
+                    '(' | ')' | 'b' => ObjectKind::Bracket('(', ')'),
+                    '[' | ']' => ObjectKind::Bracket('[', ']'),
+                    '{' | '}' | 'B' => ObjectKind::Bracket('{', '}'),
+                    '<' | '>' => ObjectKind::Bracket('<', '>'),
+                    _ => return self.cancel(),
+                };
+                let object = TextObject { kind, around: prefix == 'a' };
+                return Some(match self.pending.operator.take() {
+                    Some((operator, count)) => {
+                        let count = self.total_count(count);
+                        VimAction::Operate {
+                            operator,
+                            target: Target::Object(object),
+                            count,
+                            register: self.pending.register,
+                        }
+                    }
+                    None => VimAction::SelectObject(object),
+                });
+            }
+            _ => {}
+        }
+
+        // Counts: `0` is a motion unless a count is being typed
+        if let Some(digit) = ch.to_digit(10) {
+            if digit != 0 || self.pending.count.is_some() {
+                let count = self.pending.count.unwrap_or(0);
+                self.pending.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+                return None;
+            }
+        }
+
+        let motion = match ch {
+            'h' => Some(Motion::Left),
+            'l' | ' ' => Some(Motion::Right),
+            'k' => Some(Motion::Up),
+            'j' => Some(Motion::Down),
+            'w' => Some(Motion::WordForward),
+            'b' => Some(Motion::WordBackward),
+            'e' => Some(Motion::WordEnd),
+            '0' => Some(Motion::LineStart),
+            '^' => Some(Motion::FirstNonBlank),
+            '$' => Some(Motion::LineEnd),
+            'G' => Some(match self.pending.count.take() {
+                Some(line) => Motion::Line(line),
+                None => Motion::FileEnd,
+            }),
+            _ => None,
+        };
+        if let Some(motion) = motion {
+            return self.motion(motion);
+        }
+
+        let visual = self.mode.is_visual();
+        let operator = match ch {
+            'd' | 'x' if visual => Some(Operator::Delete),
+            'c' | 's' if visual => Some(Operator::Change),
+            'y' if visual => Some(Operator::Yank),
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            if visual {
+                return Some(VimAction::OperateSelection { operator, register: self.pending.register });
+            }
+            return match self.pending.operator {
+                // `dd`, `cc`, `yy`
+                Some((pending, count)) if pending == operator => {
+                    let count = self.total_count(count);
+                    Some(self.operate(operator, Target::Lines, count))
+                }
+                Some(_) => self.cancel(),
+                None => {
+                    self.pending.operator = Some((operator, self.pending.count.take()));
+                    None
+                }
+            };
+        }
+
+        if matches!(ch, '"' | 'g') || (matches!(ch, 'i' | 'a') && (visual || self.pending.operator.is_some())) {
+            self.pending.prefix = Some(ch);
+            return None;
+        }
+        if self.pending.operator.is_some() {
+            return self.cancel();
+        }
+
+        let count = self.take_count();
+        Some(match ch {
+            'v' if self.mode == Mode::Visual => VimAction::SetMode(Mode::Normal),
+            'V' if self.mode == Mode::VisualLine => VimAction::SetMode(Mode::Normal),
+            'v' => VimAction::SetMode(Mode::Visual),
+            'V' => VimAction::SetMode(Mode::VisualLine),
+            _ if visual => return self.cancel(),
+            'i' => VimAction::Insert(InsertAt::Cursor),
+            'a' => VimAction::Insert(InsertAt::AfterCursor),
+            'I' => VimAction::Insert(InsertAt::FirstNonBlank),
+            'A' => VimAction::Insert(InsertAt::LineEnd),
+            'o' => VimAction::Insert(InsertAt::LineBelow),
+            'O' => VimAction::Insert(InsertAt::LineAbove),
+            'x' => self.operate(Operator::Delete, Target::Chars, count),
+            'D' => self.operate(Operator::Delete, Target::Motion(Motion::LineEnd), 1),
+            'C' => self.operate(Operator::Change, Target::Motion(Motion::LineEnd), 1),
+            'Y' => self.operate(Operator::Yank, Target::Lines, count),
+            'p' => VimAction::Put { before: false, count, register: self.pending.register },
+            'P' => VimAction::Put { before: true, count, register: self.pending.register },
+            'u' => VimAction::Undo(count),
+            'J' => VimAction::Command("join-lines"),
+            '/' => VimAction::Command("find"),
+            'n' => VimAction::Command("find-next"),
+            'N' => VimAction::Command("find-prev"),
+            ':' => VimAction::Command("command-palette"),
+            _ => return self.cancel(),
+        })
+    }
+
+    /// A motion on its own moves; after an operator it completes the command
+    fn motion(&mut self, motion: Motion) -> Option<VimAction> {
+        Some(match self.pending.operator.take() {
+            Some((operator, count)) => {
+                let count = self.total_count(count);
+                self.operate(operator, Target::Motion(motion), count)
+            }
+            None => VimAction::Move { motion, count: self.take_count() },
+        })
+    }
+
+    fn operate(&self, operator: Operator, target: Target, count: usize) -> VimAction {
+        VimAction::Operate { operator, target, count, register: self.pending.register }
+    }
+
+    /// Abandon the command; the key is swallowed
+    fn cancel(&mut self) -> Option<VimAction> {
+        Some(VimAction::Nothing)
+    }
+
+    fn take_count(&mut self) -> usize {
+        self.pending.count.take().unwrap_or(1).max(1)
+    }
+
+    /// Counts typed before and after an operator multiply: `2d3w` deletes 6 words
+    fn total_count(&mut self, operator_count: Option<usize>) -> usize {
+        operator_count.unwrap_or(1).max(1).saturating_mul(self.take_count())
+    }
+
+    /// Text to put: the named register, or the unnamed one
+    pub fn register(&self, name: Option<char>) -> Option<&Register> {
+        self.registers.get(&name.unwrap_or('"').to_ascii_lowercase())
+    }
+
+    /// Store deleted or yanked text. Uppercase names append, `_` discards,
+    /// and yanks also land in `0`.
+    pub fn store(&mut self, name: Option<char>, text: String, linewise: bool, yank: bool) {
+        let register = match name {
+            Some('_') => return,
+            Some(name) if name.is_ascii_uppercase() => {
+                let lower = name.to_ascii_lowercase();
+                let mut register = self.registers.remove(&lower).unwrap_or(Register { text: String::new(), linewise });
+                register.text.push_str(&text);
+                register.linewise |= linewise;
+                self.registers.insert(lower, register.clone());
+                register
+            }
+            Some(name) => {
+                let register = Register { text, linewise };
+                self.registers.insert(name, register.clone());
+                register
+            }
+            None => Register { text, linewise },
+        };
+        if yank {
+            self.registers.insert('0', register.clone());
+        }
+        self.registers.insert('"', register);
+    }
+}
+
+/// Character range `[start, end)` of a text object around `pos` in `text`
+pub fn text_object_range(text: &[char], pos: usize, object: TextObject) -> Option<(usize, usize)> {
+    if text.is_empty() {
+        return None;
+    }
+    let pos = pos.min(text.len() - 1);
+    match object.kind {
+        ObjectKind::Word => word_object(text, pos, object.around),
+        ObjectKind::Quote(quote) => quote_object(text, pos, quote, object.around),
+        ObjectKind::Bracket(open, close) => bracket_object(text, pos, open, close, object.around),
+    }
+}
+
+fn word_class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+fn word_object(text: &[char], pos: usize, around: bool) -> Option<(usize, usize)> {
+    if text[pos] == '\n' {
+        return None;
+    }
+    let class = word_class(text[pos]);
+    let same = |c: char| c != '\n' && word_class(c) == class;
+    let mut start = pos;
+    while start > 0 && same(text[start - 1]) {
+        start -= 1;
+    }
+    let mut end = pos + 1;
+    while end < text.len() && same(text[end]) {
+        end += 1;
+    }
+    if around && class != 0 {
+        let blank = |c: char| c == ' ' || c == '\t';
+        let trailing = end;
+        while end < text.len() && blank(text[end]) {
+            end += 1;
+        }
+        // No trailing whitespace: take the leading whitespace instead
+        if end == trailing {
+            while start > 0 && blank(text[start - 1]) {
+                start -= 1;
+            }
+        }
+    }
+    Some((start, end))
+}
+
+fn quote_object(text: &[char], pos: usize, quote: char, around: bool) -> Option<(usize, usize)> {
+    let line_start = text[..pos].iter().rposition(|&c| c == '\n').map_or(0, |i| i + 1);
+    let line_end = text[pos..].iter().position(|&c| c == '\n').map_or(text.len(), |i| pos + i);
+    let quotes: Vec<usize> = (line_start..line_end)
+        .filter(|&i| text[i] == quote && (i == line_start || text[i - 1] != '\\'))
+        .collect();
+    // The pair around the cursor, or else the first one after it
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| pos <= close)?;
+    Some(if around { (open, close + 1) } else { (open + 1, close) })
+}
+
+fn bracket_object(text: &[char], pos: usize, open: char, close: char, around: bool) -> Option<(usize, usize)> {
+    // Opening bracket enclosing the cursor (or under it)
+    let mut depth = 0usize;
+    let mut start = None;
+    let first = if text[pos] == close { pos.checked_sub(1) } else { Some(pos) };
+    if let Some(first) = first {
+        for i in (0..=first).rev() {
+            if text[i] == close {
+                depth += 1;
+            } else if text[i] == open {
+                if depth == 0 {
+                    start = Some(i);
+                    break;
+                }
+                depth -= 1;
+            }
+        }
+    }
+    let start = start?;
+    let mut depth = 0usize;
+    let end = (start + 1..text.len()).find(|&i| {
+        if text[i] == open {
+            depth += 1;
+        } else if text[i] == close {
+            if depth == 0 {
+                return true;
+            }
+            depth -= 1;
+        }
+        false
+    })?;
+    Some(if around { (start, end + 1) } else { (start + 1, end) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(vim: &mut Vim, typed: &str) -> Option<VimAction> {
+        let mut last = None;
+        for c in typed.chars() {
+            last = vim.handle_key(&Key::Char(c), &Modifiers::default());
+        }
+        last
+    }
+
+    #[test]
+    fn test_counts_operators_and_registers() {
+        let mut vim = Vim::new();
+        assert_eq!(
+            keys(&mut vim, "2d3w"),
+            Some(VimAction::Operate {
+                operator: Operator::Delete,
+                target: Target::Motion(Motion::WordForward),
+                count: 6,
+                register: None,
+            })
+        );
+        assert_eq!(
+            keys(&mut vim, "\"ayy"),
+            Some(VimAction::Operate { operator: Operator::Yank, target: Target::Lines, count: 1, register: Some('a') })
+        );
+        assert_eq!(keys(&mut vim, "12G"), Some(VimAction::Move { motion: Motion::Line(12), count: 1 }));
+        assert_eq!(keys(&mut vim, "gg"), Some(VimAction::Move { motion: Motion::FileStart, count: 1 }));
+        assert_eq!(
+            keys(&mut vim, "ci\""),
+            Some(VimAction::Operate {
+                operator: Operator::Change,
+                target: Target::Object(TextObject { kind: ObjectKind::Quote('"'), around: false }),
+                count: 1,
+                register: None,
+            })
+        );
+        assert_eq!(keys(&mut vim, "i"), Some(VimAction::Insert(InsertAt::Cursor)));
+    }
+
+    #[test]
+    fn test_registers_append_and_yank_register() {
+        let mut vim = Vim::new();
+        vim.store(Some('a'), "one\n".to_string(), true, true);
+        vim.store(Some('A'), "two\n".to_string(), true, true);
+        assert_eq!(vim.register(Some('a')).unwrap().text, "one\ntwo\n");
+        vim.store(None, "gone".to_string(), false, false);
+        assert_eq!(vim.register(None).unwrap().text, "gone");
+        assert_eq!(vim.register(Some('0')).unwrap().text, "one\ntwo\n");
+    }
+
+    #[test]
+    fn test_text_objects() {
+        let text: Vec<char> = "let s = f(a, \"hi there\");".chars().collect();
+        let word = TextObject { kind: ObjectKind::Word, around: false };
+        assert_eq!(text_object_range(&text, 1, word), Some((0, 3)));
+        let quote = TextObject { kind: ObjectKind::Quote('"'), around: false };
+        assert_eq!(text_object_range(&text, 16, quote), Some((14, 22)));
+        let paren = TextObject { kind: ObjectKind::Bracket('(', ')'), around: false };
+        assert_eq!(text_object_range(&text, 11, paren), Some((10, 23)));
+        let around = TextObject { around: true, ..paren };
+        assert_eq!(text_object_range(&text, 23, around), Some((9, 24)));
+    }
+}
@@ -181,7 +181,7 @@ impl WelcomeMenu {
             menu.update_viewport(visible_rows);
 
             // Render
-            screen.render_welcome(&menu.visible_items(), menu.scroll())?;
+            screen.render_welcome("Welcome to fackr", "Select a workspace:", &menu.visible_items(), menu.scroll())?;
 
             // Wait for input
             if let Event::Key(key_event) = event::read()? {
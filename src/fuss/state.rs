@@ -25,6 +25,8 @@ pub struct FussMode {
     pub width_percent: u8,
     /// Show hints expanded
     pub hints_expanded: bool,
+    /// Kept open when focus leaves it, with `layout.auto_hide` on
+    pub pinned: bool,
     /// Workspace root path
     root_path: Option<PathBuf>,
     /// Current fuzzy filter query
@@ -33,6 +35,11 @@ pub struct FussMode {
     filter_last_input: Option<Instant>,
     /// Whether git mode is active (after pressing Alt+G)
     pub git_mode: bool,
+    /// The workspace is too big to scan: no git status, which would walk
+    /// all of it and expand the directories with changes
+    pub limited: bool,
+    /// Directories excluded from workspace features (absolute paths)
+    excluded: Vec<PathBuf>,
 }
 
 impl Default for FussMode {
@@ -44,10 +51,13 @@ impl Default for FussMode {
             scroll: 0,
             width_percent: 30,
             hints_expanded: false,
+            pinned: false,
             root_path: None,
             filter: String::new(),
             filter_last_input: None,
             git_mode: false,
+            limited: false,
+            excluded: Vec::new(),
         }
     }
 }
@@ -62,7 +72,12 @@ impl FussMode {
     pub fn init(&mut self, root_path: &Path) {
         self.root_path = Some(root_path.to_path_buf());
         let mut tree = FileTree::new(root_path);
-        tree.update_git_status();
+        tree.excluded = self.excluded.clone();
+        if self.limited {
+            tree.rebuild_visible();
+        } else {
+            tree.update_git_status();
+        }
         self.tree = Some(tree);
         self.selected = 0;
         self.scroll = 0;
@@ -218,17 +233,44 @@ impl FussMode {
     pub fn reload(&mut self) {
         if let Some(ref mut tree) = self.tree {
             tree.reload();
-            tree.update_git_status();
+            if !self.limited {
+                tree.update_git_status();
+            }
+        }
+    }
+
+    /// Pick up files created or deleted outside the editor, keeping the
+    /// expanded directories and the selected entry
+    pub fn refresh_from_disk(&mut self) {
+        let selected = self.selected_path();
+        if let Some(ref mut tree) = self.tree {
+            tree.refresh();
+            if !self.limited {
+                tree.update_git_marks();
+            }
+            let position = selected.and_then(|path| tree.visible_items().iter().position(|item| item.path == path));
+            self.selected = position.unwrap_or(self.selected).min(tree.len().saturating_sub(1));
         }
     }
 
     /// Refresh git status without reloading file tree
     pub fn refresh_git_status(&mut self) {
         if let Some(ref mut tree) = self.tree {
-            tree.update_git_status();
+            if !self.limited {
+                tree.update_git_status();
+            }
         }
     }
 
+    /// Set the directories shown as excluded (absolute paths)
+    pub fn set_excluded(&mut self, dirs: Vec<PathBuf>) {
+        if let Some(ref mut tree) = self.tree {
+            tree.excluded = dirs.clone();
+            tree.rebuild_visible();
+        }
+        self.excluded = dirs;
+    }
+
     /// Stage the currently selected file
     /// Returns true on success, false on failure
     pub fn stage_selected(&mut self) -> bool {
@@ -452,44 +494,6 @@ impl FussMode {
         }
     }
 
-    /// Get git diff for the currently selected file
-    /// Returns (filename, diff_content) or None if no diff
-    pub fn get_diff_for_selected(&self) -> Option<(String, String)> {
-        let root = self.root_path.as_ref()?;
-        let path = self.selected_path()?;
-
-        // Don't diff directories
-        if self.is_dir_selected() {
-            return None;
-        }
-
-        // Get relative path for display
-        let rel_path = path.strip_prefix(root).unwrap_or(&path);
-        let filename = rel_path.to_string_lossy().to_string();
-
-        // Run git diff
-        let output = Command::new("git")
-            .arg("-C")
-            .arg(root)
-            .arg("diff")
-            .arg("HEAD")
-            .arg("--")
-            .arg(&path)
-            .output()
-            .ok()?;
-
-        if output.status.success() {
-            let diff = String::from_utf8_lossy(&output.stdout).to_string();
-            if diff.is_empty() {
-                Some((filename, "(no changes)".to_string()))
-            } else {
-                Some((filename, diff))
-            }
-        } else {
-            None
-        }
-    }
-
     /// Add a character to the filter and jump to first match
     /// Resets the filter if too much time has passed since last input
     pub fn filter_push(&mut self, c: char) {
@@ -119,6 +119,8 @@ pub struct FileTree {
     pub show_hidden: bool,
     /// Flattened visible items (for rendering and navigation)
     visible_items: Vec<VisibleItem>,
+    /// Directories excluded from workspace features, shown dimmed
+    pub excluded: Vec<PathBuf>,
 }
 
 /// A visible item in the flattened tree
@@ -136,6 +138,8 @@ pub struct VisibleItem {
     pub depth: usize,
     /// Git status
     pub git_status: GitStatus,
+    /// Excluded from workspace features (quick open, search)
+    pub excluded: bool,
 }
 
 impl FileTree {
@@ -148,6 +152,7 @@ impl FileTree {
             root,
             show_hidden: false,
             visible_items: Vec::new(),
+            excluded: Vec::new(),
         };
         tree.rebuild_visible();
         tree
@@ -169,6 +174,7 @@ impl FileTree {
                 expanded: node.expanded,
                 depth: node.depth,
                 git_status: node.git_status.clone(),
+                excluded: self.excluded.contains(&node.path),
             });
         }
 
@@ -250,6 +256,40 @@ impl FileTree {
         }
     }
 
+    /// Re-read expanded directories from disk, keeping what is expanded
+    /// and the git marks of entries still there
+    pub fn refresh(&mut self) {
+        Self::refresh_node(&mut self.root, self.show_hidden);
+        self.rebuild_visible();
+    }
+
+    fn refresh_node(node: &mut TreeNode, show_hidden: bool) {
+        if !node.is_dir || !node.expanded {
+            return;
+        }
+        let mut previous: HashMap<PathBuf, TreeNode> =
+            node.children.drain(..).map(|child| (child.path.clone(), child)).collect();
+        node.load_children(show_hidden);
+        for child in &mut node.children {
+            if let Some(old) = previous.remove(&child.path).filter(|old| old.is_dir == child.is_dir) {
+                *child = old;
+                if !child.expanded {
+                    // Loaded again when expanded
+                    child.children.clear();
+                }
+            }
+            Self::refresh_node(child, show_hidden);
+        }
+    }
+
+    /// Update git marks without collapsing anything
+    pub fn update_git_marks(&mut self) {
+        let root_path = self.root.path.clone();
+        let status_map = get_git_status(&root_path);
+        Self::apply_git_status(&mut self.root, &status_map, &root_path);
+        self.rebuild_visible();
+    }
+
     /// Update git status for all files in the tree
     pub fn update_git_status(&mut self) {
         let root_path = self.root.path.clone();
@@ -0,0 +1,160 @@
+//! Line blame
+//!
+//! Runs `git blame --porcelain` on a saved file and keeps, for every line,
+//! the commit that last touched it. Porcelain output names each commit's
+//! author and summary only the first time the commit appears, so headers
+//! are remembered by hash while the lines are read.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The commit that last changed one line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    /// Full commit hash (all zeros for uncommitted lines)
+    pub commit: String,
+    pub author: String,
+    /// Author time, seconds since the Unix epoch
+    pub time: u64,
+    /// First line of the commit message
+    pub summary: String,
+}
+
+impl BlameLine {
+    /// Whether the line has changes that aren't committed yet
+    pub fn is_uncommitted(&self) -> bool {
+        self.commit.bytes().all(|b| b == b'0')
+    }
+
+    /// Inline text for the line, e.g. "Ada Lovelace, 3 days ago • Fix parser"
+    pub fn annotation(&self) -> String {
+        if self.is_uncommitted() {
+            return "You, uncommitted changes".to_string();
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        format!("{}, {} • {}", self.author, relative_time(now.saturating_sub(self.time)), self.summary)
+    }
+}
+
+/// Blame for the file at `path`, one entry per line of the file as saved;
+/// None if git isn't available or the file isn't tracked
+pub fn blame_file(path: &Path) -> Option<Vec<BlameLine>> {
+    let dir = path.parent()?;
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["blame", "--porcelain", "--"])
+        .arg(path.file_name()?)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse_porcelain(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse `git blame --porcelain` output into per-line entries
+fn parse_porcelain(output: &str) -> Vec<BlameLine> {
+    let mut commits: HashMap<String, BlameLine> = HashMap::new();
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut current: Option<String> = None;
+
+    for line in output.lines() {
+        if line.starts_with('\t') {
+            // Line content ends the entry
+            current = None;
+            continue;
+        }
+        let Some(hash) = current.clone() else {
+            // Entry header: <hash> <orig line> <final line> [<group size>]
+            let mut fields = line.split(' ');
+            let (Some(hash), Some(_), Some(final_line)) = (fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            let Ok(final_line) = final_line.parse::<usize>() else { continue };
+            commits.entry(hash.to_string()).or_insert_with(|| BlameLine {
+                commit: hash.to_string(),
+                author: String::new(),
+                time: 0,
+                summary: String::new(),
+            });
+            lines.push((final_line, hash.to_string()));
+            current = Some(hash.to_string());
+            continue;
+        };
+        let Some(commit) = commits.get_mut(&hash) else { continue };
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "author" => commit.author = value.to_string(),
+            "author-time" => commit.time = value.parse().unwrap_or(0),
+            "summary" => commit.summary = value.to_string(),
+            _ => {}
+        }
+    }
+
+    let mut blame = vec![None; lines.iter().map(|(n, _)| *n).max().unwrap_or(0)];
+    for (final_line, hash) in lines {
+        blame[final_line - 1] = commits.get(&hash).cloned();
+    }
+    blame.into_iter().flatten().collect()
+}
+
+/// Rough age of something `secs` seconds old, e.g. "5 minutes ago"
+pub fn relative_time(secs: u64) -> String {
+    let (count, unit) = match secs {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86_399 => (secs / 3600, "hour"),
+        86_400..=2_591_999 => (secs / 86_400, "day"),
+        2_592_000..=31_535_999 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+    format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PORCELAIN: &str = "\
+a1b2c3d4e5f6a1b2c3d4e5f6a1b2c3d4e5f6a1b2 1 1 2
+author Ada Lovelace
+author-mail <ada@example.com>
+author-time 1700000000
+author-tz +0000
+summary Add parser
+filename src/parse.rs
+\tfn parse() {
+a1b2c3d4e5f6a1b2c3d4e5f6a1b2c3d4e5f6a1b2 2 2
+\t}
+0000000000000000000000000000000000000000 3 3 1
+author Not Committed Yet
+author-time 1700000500
+summary Version of src/parse.rs from src/parse.rs
+filename src/parse.rs
+\t// todo
+";
+
+    #[test]
+    fn test_parse_porcelain() {
+        let blame = parse_porcelain(PORCELAIN);
+        assert_eq!(blame.len(), 3);
+        assert_eq!(blame[0].author, "Ada Lovelace");
+        assert_eq!(blame[0].summary, "Add parser");
+        assert_eq!(blame[0].time, 1_700_000_000);
+        // Repeated commits carry the header from their first appearance
+        assert_eq!(blame[1], blame[0]);
+        assert!(blame[2].is_uncommitted());
+        assert!(!blame[0].is_uncommitted());
+    }
+
+    #[test]
+    fn test_relative_time() {
+        assert_eq!(relative_time(10), "just now");
+        assert_eq!(relative_time(60), "1 minute ago");
+        assert_eq!(relative_time(3 * 86_400), "3 days ago");
+        assert_eq!(relative_time(2 * 31_536_000), "2 years ago");
+    }
+}
@@ -0,0 +1,46 @@
+//! Local branches, offered for switching to in the command palette
+
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// The repository's local branches other than the current one, most
+/// recently committed to first
+pub fn other_branches(root: &Path) -> Result<Vec<String>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["for-each-ref", "--sort=-committerdate", "--format=%(HEAD)%(refname:short)", "refs/heads"])
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).lines().next().unwrap_or("git failed").trim());
+    }
+    Ok(parse_branches(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Switch the working tree to `branch`
+pub fn switch(root: &Path, branch: &str) -> Result<()> {
+    super::log::git(root, &["switch", "--quiet", branch])
+}
+
+/// Branch names from `for-each-ref` lines, each marked `*` if it's checked
+/// out and ` ` otherwise, leaving out the checked out one
+fn parse_branches(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| line.strip_prefix(' '))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_branches() {
+        let text = " feature/parser\n*main\n release-1.2\n";
+        assert_eq!(parse_branches(text), ["feature/parser", "release-1.2"]);
+        assert!(parse_branches("").is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+//! Diff algorithms and word-level changes
+//!
+//! Git does the line diffs, with the algorithm the workspace config names
+//! (histogram and patience keep moved blocks together better than the
+//! default). Within a diff, each removed line is compared word by word with
+//! the added line that replaces it, so a small change in a long line can be
+//! highlighted on its own. Two versions of a file compared side by side are
+//! matched line by line the same way, in process.
+
+use std::ops::Range;
+
+/// Algorithms `git diff --diff-algorithm` accepts
+const ALGORITHMS: &[&str] = &["myers", "minimal", "patience", "histogram"];
+
+/// Removed/added line pairs sharing less than this fraction of their
+/// characters are too different for word highlights to help
+const MIN_SHARED: f32 = 0.5;
+
+/// Changed character ranges of an old line and of the new line replacing it
+type WordChanges = (Vec<Range<usize>>, Vec<Range<usize>>);
+
+/// Lines left between the common start and end of two versions beyond
+/// which they aren't matched line by line, and all count as changed
+const MAX_DIFF_LINES: usize = 20_000;
+
+/// How two versions of a file match up line by line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineDiff {
+    /// Runs of the old version's lines that the new one doesn't have
+    pub old_changed: Vec<Range<usize>>,
+    /// Runs of the new version's lines that the old one doesn't have
+    pub new_changed: Vec<Range<usize>>,
+    /// Lines the versions share, as (old, new) pairs in order
+    shared: Vec<(usize, usize)>,
+}
+
+impl LineDiff {
+    /// Line of the other version at the same place as `line`; `from_old`
+    /// when `line` is in the old version. A line of a changed run maps to
+    /// the same offset into the other side's run, as far as it goes.
+    pub fn matching_line(&self, line: usize, from_old: bool) -> usize {
+        let pick = |&(old, new): &(usize, usize)| if from_old { (old, new) } else { (new, old) };
+        let before = self.shared.partition_point(|pair| pick(pair).0 <= line);
+        let (from, to) = match before {
+            0 => (0, 0),
+            i => pick(&self.shared[i - 1]),
+        };
+        let target = to + line.saturating_sub(from);
+        match self.shared.get(before) {
+            Some(next) => target.min(pick(next).1),
+            None => target,
+        }
+    }
+}
+
+/// Match the lines of `old` and `new`
+pub fn line_diff<T: AsRef<str>>(old: &[T], new: &[T]) -> LineDiff {
+    let old: Vec<&str> = old.iter().map(AsRef::as_ref).collect();
+    let new: Vec<&str> = new.iter().map(AsRef::as_ref).collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old_mid, new_mid) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let (old_flags, new_flags) = if old_mid.len() + new_mid.len() > MAX_DIFF_LINES {
+        (vec![true; old_mid.len()], vec![true; new_mid.len()])
+    } else {
+        myers(old_mid, new_mid)
+    };
+
+    let mut shared: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    let old_kept = old_flags.iter().enumerate().filter(|(_, &c)| !c).map(|(i, _)| prefix + i);
+    let new_kept = new_flags.iter().enumerate().filter(|(_, &c)| !c).map(|(i, _)| prefix + i);
+    shared.extend(old_kept.zip(new_kept));
+    shared.extend((0..suffix).map(|i| (old.len() - suffix + i, new.len() - suffix + i)));
+
+    LineDiff { old_changed: runs(&old_flags, prefix), new_changed: runs(&new_flags, prefix), shared }
+}
+
+/// Runs of set flags, as line ranges starting at `offset`
+fn runs(flags: &[bool], offset: usize) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for (i, _) in flags.iter().enumerate().filter(|(_, &changed)| changed) {
+        let line = offset + i;
+        match runs.last_mut() {
+            Some(last) if last.end == line => last.end = line + 1,
+            _ => runs.push(line..line + 1),
+        }
+    }
+    runs
+}
+
+/// `--diff-algorithm` flag for a configured algorithm name; unknown names
+/// get git's default
+pub fn algorithm_flag(name: &str) -> String {
+    let name = name.to_lowercase();
+    let name = if ALGORITHMS.contains(&name.as_str()) { name.as_str() } else { "myers" };
+    format!("--diff-algorithm={}", name)
+}
+
+/// Changed spans in the lines of unified diff text, as (line index,
+/// character range). In each run of removed lines followed by added lines,
+/// the n-th removed line is paired with the n-th added one; the ranges
+/// count the leading `-`/`+`.
+pub fn intra_line_changes(lines: &[&str]) -> Vec<(usize, Range<usize>)> {
+    let mut changes = Vec::new();
+    let mut in_hunk = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if line.starts_with("diff ") {
+            in_hunk = false;
+        } else if line.starts_with("@@") {
+            in_hunk = true;
+        }
+        if !in_hunk || !line.starts_with('-') {
+            i += 1;
+            continue;
+        }
+
+        let removed_start = i;
+        while i < lines.len() && lines[i].starts_with('-') {
+            i += 1;
+        }
+        let added_start = i;
+        while i < lines.len() && lines[i].starts_with('+') {
+            i += 1;
+        }
+        let pairs = (added_start - removed_start).min(i - added_start);
+        for n in 0..pairs {
+            let (old_idx, new_idx) = (removed_start + n, added_start + n);
+            let Some((old, new)) = word_diff(&lines[old_idx][1..], &lines[new_idx][1..]) else { continue };
+            let shift = |r: Range<usize>| r.start + 1..r.end + 1;
+            changes.extend(old.into_iter().map(|r| (old_idx, shift(r))));
+            changes.extend(new.into_iter().map(|r| (new_idx, shift(r))));
+        }
+    }
+    changes
+}
+
+/// Character ranges of `old` and `new` that differ, word by word; None if
+/// the lines have too little in common
+fn word_diff(old: &str, new: &str) -> Option<WordChanges> {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+    let old_words: Vec<&str> = old_tokens.iter().map(|(_, w)| *w).collect();
+    let new_words: Vec<&str> = new_tokens.iter().map(|(_, w)| *w).collect();
+    let (old_changed, new_changed) = myers(&old_words, &new_words);
+
+    let shared: usize = old_tokens
+        .iter()
+        .zip(&old_changed)
+        .filter(|(_, &changed)| !changed)
+        .map(|((range, _), _)| range.len())
+        .sum();
+    let longest = old.chars().count().max(new.chars().count());
+    if longest == 0 || (shared as f32) < longest as f32 * MIN_SHARED {
+        return None;
+    }
+    Some((spans(&old_tokens, &old_changed), spans(&new_tokens, &new_changed)))
+}
+
+/// Words, runs of whitespace and single punctuation characters, with their
+/// character ranges
+fn tokenize(text: &str) -> Vec<(Range<usize>, &str)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut tokens: Vec<(Range<usize>, &str)> = Vec::new();
+    let mut start: Option<(usize, usize, char)> = None; // (char index, byte index, first char)
+    for (i, (byte, c)) in text.char_indices().enumerate() {
+        if let Some((s, b, first)) = start {
+            let continues = (is_word(first) && is_word(c)) || (first.is_whitespace() && c.is_whitespace());
+            if continues {
+                continue;
+            }
+            tokens.push((s..i, &text[b..byte]));
+        }
+        start = Some((i, byte, c));
+    }
+    if let Some((s, b, _)) = start {
+        tokens.push((s..text.chars().count(), &text[b..]));
+    }
+    tokens
+}
+
+/// Merge changed tokens into character ranges; whitespace between two
+/// changes joins them
+fn spans(tokens: &[(Range<usize>, &str)], changed: &[bool]) -> Vec<Range<usize>> {
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    for (i, ((range, word), &is_changed)) in tokens.iter().zip(changed).enumerate() {
+        let bridges = word.trim().is_empty()
+            && i > 0
+            && changed[i - 1]
+            && changed.get(i + 1).copied().unwrap_or(false);
+        if !is_changed && !bridges {
+            continue;
+        }
+        match spans.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => spans.push(range.clone()),
+        }
+    }
+    spans
+}
+
+/// Which elements of `a` and `b` are not part of a longest common
+/// subsequence, by Myers' O(ND) algorithm
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> (Vec<bool>, Vec<bool>) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) { v[idx + 1] } else { v[idx - 1] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back through the saved rounds, marking the diagonal moves
+    let mut a_changed = vec![true; a.len()];
+    let mut b_changed = vec![true; b.len()];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            a_changed[x as usize] = false;
+            b_changed[y as usize] = false;
+        }
+        if d > 0 {
+            x = prev_x;
+            y = prev_y;
+        }
+    }
+    (a_changed, b_changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_myers() {
+        let (a, b) = myers(&['a', 'b', 'c', 'a', 'b', 'b', 'a'], &['c', 'b', 'a', 'b', 'a', 'c']);
+        // Edit distance 5: 4 common elements
+        assert_eq!(a.iter().filter(|&&c| !c).count(), 4);
+        assert_eq!(b.iter().filter(|&&c| !c).count(), 4);
+        assert_eq!(myers::<u8>(&[], &[1, 2]), (vec![], vec![true, true]));
+    }
+
+    #[test]
+    fn test_word_diff() {
+        let (old, new) = word_diff("let total = count * price;", "let total = count * unit_price;").unwrap();
+        assert_eq!(old, vec![20..25]);
+        assert_eq!(new, vec![20..30]);
+        assert!(word_diff("fn main() {}", "struct Point;").is_none());
+    }
+
+    #[test]
+    fn test_intra_line_changes() {
+        let diff = ["diff --git a/x b/x", "--- a/x", "+++ b/x", "@@ -1,2 +1,2 @@", "-    old(1);", "+    old(2);", " same"];
+        let changes = intra_line_changes(&diff);
+        assert_eq!(changes, vec![(4, 9..10), (5, 9..10)]);
+    }
+
+    #[test]
+    fn test_line_diff() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "y", "c", "e", "f"];
+        let diff = line_diff(&old, &new);
+        assert_eq!(diff.old_changed, vec![1..2, 3..4]);
+        assert_eq!(diff.new_changed, vec![1..3, 5..6]);
+
+        assert_eq!(diff.matching_line(3, false), 2);
+        assert_eq!(diff.matching_line(2, true), 3);
+        assert_eq!(diff.matching_line(1, false), 1);
+        assert_eq!(diff.matching_line(2, false), 2);
+        assert_eq!(diff.matching_line(5, false), 5);
+        assert_eq!(diff.matching_line(4, true), 4);
+    }
+
+    #[test]
+    fn test_algorithm_flag() {
+        assert_eq!(algorithm_flag("Histogram"), "--diff-algorithm=histogram");
+        assert_eq!(algorithm_flag("bogus"), "--diff-algorithm=myers");
+    }
+}
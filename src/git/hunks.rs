@@ -0,0 +1,168 @@
+//! Hunks of unstaged changes
+//!
+//! `git diff -U0` between the index and a saved file, split into hunks that
+//! can be staged one at a time with `git apply --cached` or reverted by
+//! putting the index lines back in the buffer. Without context lines each
+//! hunk applies on its own, whatever is staged around it.
+
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// One run of changed lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// First line of the hunk in the file (0-based); for a pure deletion,
+    /// the line that now follows the removed lines
+    pub start: usize,
+    /// Lines the hunk covers in the file (0 for a pure deletion)
+    pub count: usize,
+    /// The index's lines that the hunk replaces
+    pub old_lines: Vec<String>,
+    /// `@@` header and `-`/`+` lines, as they go in a patch
+    patch: Vec<String>,
+}
+
+impl Hunk {
+    /// Whether the hunk covers `line`; a deletion covers the line after it
+    pub fn contains(&self, line: usize) -> bool {
+        self.start <= line && line < self.start + self.count.max(1)
+    }
+}
+
+/// A file's unstaged hunks
+#[derive(Debug)]
+pub struct FileHunks {
+    /// Repository top level, where patches apply
+    top: PathBuf,
+    /// `diff --git`, `---` and `+++` lines heading the file's patch
+    header: Vec<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FileHunks {
+    /// Hunks between the index and the saved file at `path`, diffed with
+    /// the named algorithm
+    pub fn load(path: &Path, algorithm: &str) -> Result<Self> {
+        let dir = path.parent().context("file has no directory")?;
+        let top = git(dir, &["rev-parse", "--show-toplevel"])?;
+        let top = PathBuf::from(top.trim_end());
+        let relative = path.strip_prefix(&top).unwrap_or(path);
+        let flag = super::diff::algorithm_flag(algorithm);
+        let diff = git(&top, &["diff", "--no-color", "--no-ext-diff", &flag, "-U0", "--", &relative.to_string_lossy()])?;
+        let (header, hunks) = parse_diff(&diff);
+        Ok(Self { top, header, hunks })
+    }
+
+    /// The hunk at `line`
+    pub fn at_line(&self, line: usize) -> Option<&Hunk> {
+        self.hunks.iter().find(|h| h.contains(line))
+    }
+
+    /// Add `hunk` to the index
+    pub fn stage(&self, hunk: &Hunk) -> Result<()> {
+        let mut patch = self.header.join("\n");
+        for line in &hunk.patch {
+            patch.push('\n');
+            patch.push_str(line);
+        }
+        patch.push('\n');
+
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(&self.top)
+            .args(["apply", "--cached", "--unidiff-zero", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(patch.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+}
+
+/// Split `git diff -U0` output for one file into its header and hunks
+fn parse_diff(diff: &str) -> (Vec<String>, Vec<Hunk>) {
+    let mut header = Vec::new();
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for line in diff.lines() {
+        if let Some(ranges) = line.strip_prefix("@@ ") {
+            let mut parts = ranges.split(' ');
+            let old = parts.next().and_then(|r| r.strip_prefix('-')).map(parse_range);
+            let new = parts.next().and_then(|r| r.strip_prefix('+')).map(parse_range);
+            let (Some(_), Some((new_start, new_count))) = (old, new) else { continue };
+            // A deletion's range names the line before the gap
+            let start = if new_count == 0 { new_start } else { new_start.saturating_sub(1) };
+            hunks.push(Hunk { start, count: new_count, old_lines: Vec::new(), patch: vec![line.to_string()] });
+        } else if let Some(hunk) = hunks.last_mut() {
+            if let Some(old) = line.strip_prefix('-') {
+                hunk.old_lines.push(old.to_string());
+            }
+            hunk.patch.push(line.to_string());
+        } else {
+            header.push(line.to_string());
+        }
+    }
+    (header, hunks)
+}
+
+/// `start[,count]` from a hunk header; the count defaults to 1
+fn parse_range(range: &str) -> (usize, usize) {
+    let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+    (start.parse().unwrap_or(0), count.parse().unwrap_or(0))
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -2 +2,2 @@ fn main() {
+-    old();
++    new();
++    more();
+@@ -8,2 +8,0 @@ fn helper() {
+-    gone();
+-    also_gone();
+@@ -12,0 +11 @@ fn tail() {
++    added();
+";
+
+    #[test]
+    fn test_parse_diff() {
+        let (header, hunks) = parse_diff(DIFF);
+        assert_eq!(header.len(), 4);
+        assert_eq!(hunks.len(), 3);
+
+        assert_eq!((hunks[0].start, hunks[0].count), (1, 2));
+        assert_eq!(hunks[0].old_lines, vec!["    old();"]);
+        assert_eq!(hunks[0].patch.len(), 4);
+
+        // Deletions sit on the line after the removed ones
+        assert_eq!((hunks[1].start, hunks[1].count), (8, 0));
+        assert!(hunks[1].contains(8));
+        assert!(!hunks[1].contains(7));
+
+        assert_eq!((hunks[2].start, hunks[2].count), (10, 1));
+        assert!(hunks[2].old_lines.is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+//! Commit history
+//!
+//! `git log` one commit per line, for the history panel, plus the two
+//! actions it offers on a commit: checking it out and cherry-picking it onto
+//! the current branch.
+
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Most commits listed in the history panel
+const LOG_LIMIT: usize = 500;
+
+/// Field separator in the `--format` string; never appears in commit data
+const SEP: char = '\u{1f}';
+
+/// One commit in the history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub hash: String,
+    pub short: String,
+    pub author: String,
+    /// Author date, `YYYY-MM-DD`
+    pub date: String,
+    pub subject: String,
+}
+
+/// Newest commits first in the repository at `root`, limited to those
+/// touching `path` when given
+pub fn log(root: &Path, path: Option<&Path>) -> Result<Vec<Commit>> {
+    let limit = format!("-n{}", LOG_LIMIT);
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(root)
+        .args(["log", "--date=short", "--format=%H%x1f%h%x1f%an%x1f%ad%x1f%s", &limit, "--"]);
+    if let Some(path) = path {
+        command.arg(path);
+    }
+    let output = command.output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).lines().next().unwrap_or("git failed").trim());
+    }
+    Ok(parse_log(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Check out `commit` (leaving HEAD detached)
+pub fn checkout(root: &Path, commit: &Commit) -> Result<()> {
+    git(root, &["checkout", "--quiet", &commit.hash])
+}
+
+/// Apply `commit` on top of the current branch
+pub fn cherry_pick(root: &Path, commit: &Commit) -> Result<()> {
+    git(root, &["cherry-pick", &commit.hash])
+}
+
+fn parse_log(text: &str) -> Vec<Commit> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(5, SEP);
+            Some(Commit {
+                hash: fields.next()?.to_string(),
+                short: fields.next()?.to_string(),
+                author: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+pub(super) fn git(root: &Path, args: &[&str]) -> Result<()> {
+    let output = Command::new("git").arg("-C").arg(root).args(args).output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).lines().next().unwrap_or("git failed").trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log() {
+        let text = "abc123def\u{1f}abc123d\u{1f}Ada Lovelace\u{1f}2024-03-01\u{1f}Fix the engine\n\
+                    0011223344\u{1f}0011223\u{1f}Bob\u{1f}2024-02-28\u{1f}\n\
+                    truncated\u{1f}line\n";
+        let commits = parse_log(text);
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits[0].short, "abc123d");
+        assert_eq!(commits[0].author, "Ada Lovelace");
+        assert_eq!(commits[0].date, "2024-03-01");
+        assert_eq!(commits[0].subject, "Fix the engine");
+        assert_eq!(commits[1].subject, "");
+    }
+}
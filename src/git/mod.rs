@@ -0,0 +1,14 @@
+//! Git integration: blame and hunks for the file being edited, the
+//! repository's commit history and branches, word-level changes within
+//! diffs, and line matching for comparing two versions of a file
+
+pub mod blame;
+pub mod branch;
+pub mod diff;
+pub mod hunks;
+pub mod log;
+
+pub use blame::{blame_file, relative_time, BlameLine};
+pub use diff::LineDiff;
+pub use hunks::FileHunks;
+pub use log::Commit;
@@ -1,7 +1,8 @@
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
 
 /// Key modifiers
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Modifiers {
     pub ctrl: bool,
     pub alt: bool,
@@ -19,7 +20,7 @@ impl From<KeyModifiers> for Modifiers {
 }
 
 /// Abstracted key input
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Key {
     Char(char),
     Backspace,
@@ -42,7 +43,14 @@ pub enum Key {
 
 impl Key {
     pub fn from_crossterm(event: KeyEvent) -> (Self, Modifiers) {
-        let modifiers = Modifiers::from(event.modifiers);
+        let mut modifiers = Modifiers::from(event.modifiers);
+        // AltGr arrives as Ctrl+Alt on Windows (e.g. AltGr+Q = '@' on German
+        // layouts); anything but an ASCII letter is the typed character itself
+        if let KeyCode::Char(c) = event.code {
+            if modifiers.ctrl && modifiers.alt && !c.is_ascii_alphabetic() {
+                modifiers = Modifiers { shift: modifiers.shift, ..Default::default() };
+            }
+        }
         let key = match event.code {
             KeyCode::Char(c) => Key::Char(c),
             KeyCode::Backspace => Key::Backspace,
@@ -66,3 +74,32 @@ impl Key {
         (key, modifiers)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> (Key, Modifiers) {
+        Key::from_crossterm(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn test_altgr_characters_are_typed() {
+        let altgr = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        assert_eq!(key(KeyCode::Char('@'), altgr), (Key::Char('@'), Modifiers::default()));
+        assert_eq!(key(KeyCode::Char('\u{0105}'), altgr), (Key::Char('\u{0105}'), Modifiers::default()));
+
+        // Ctrl+Alt+letter stays a chord (e.g. Ctrl+Alt+H opens replace)
+        let (k, mods) = key(KeyCode::Char('h'), altgr);
+        assert_eq!(k, Key::Char('h'));
+        assert!(mods.ctrl && mods.alt);
+    }
+
+    #[test]
+    fn test_alt_modifier_without_escape_prefix() {
+        // Windows Terminal reports Alt as a modifier rather than an ESC prefix
+        let (k, mods) = key(KeyCode::Char('x'), KeyModifiers::ALT);
+        assert_eq!(k, Key::Char('x'));
+        assert_eq!(mods, Modifiers { alt: true, ..Default::default() });
+    }
+}
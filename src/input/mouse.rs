@@ -43,6 +43,8 @@ pub enum Mouse {
     ScrollUp { col: u16, row: u16 },
     /// Scroll down at (column, row)
     ScrollDown { col: u16, row: u16 },
+    /// Pointer moved to (column, row) with no button held
+    Moved { col: u16, row: u16 },
 }
 
 impl Mouse {
@@ -78,7 +80,8 @@ impl Mouse {
             }
             MouseEventKind::ScrollUp => Some(Mouse::ScrollUp { col, row }),
             MouseEventKind::ScrollDown => Some(Mouse::ScrollDown { col, row }),
-            _ => None, // Ignore Moved events for now
+            MouseEventKind::Moved => Some(Mouse::Moved { col, row }),
+            _ => None,
         }
     }
 
@@ -90,6 +93,7 @@ impl Mouse {
             Mouse::Up { col, .. } => *col,
             Mouse::ScrollUp { col, .. } => *col,
             Mouse::ScrollDown { col, .. } => *col,
+            Mouse::Moved { col, .. } => *col,
         }
     }
 
@@ -101,6 +105,7 @@ impl Mouse {
             Mouse::Up { row, .. } => *row,
             Mouse::ScrollUp { row, .. } => *row,
             Mouse::ScrollDown { row, .. } => *row,
+            Mouse::Moved { row, .. } => *row,
         }
     }
 }
@@ -6,17 +6,29 @@
 #![allow(dead_code)]
 
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use super::manager::LspManager;
+use super::manager::{LspManager, ServerStatus};
+use super::message::ApplyEditResult;
 use super::protocol;
 use super::types::{
-    detect_language, path_to_uri, CompletionItem, Diagnostic, DocumentSymbol, HoverInfo, Location,
-    Position, Range, TextEdit, WorkspaceEdit,
+    detect_language, path_to_uri, CodeLens, Command, CompletionItem, Diagnostic, DocumentLink,
+    DocumentSymbol, HoverInfo, Location, Position, PrepareRename, Range, SignatureHelp, TextEdit, WorkspaceEdit,
 };
 
+/// A language's servers stop once none of its files has been open for this long
+const IDLE_SHUTDOWN: Duration = Duration::from_secs(300);
+
+/// How often to look for idle servers
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Diagnostics by URI, then by the server that published them
+type DiagnosticsMap = HashMap<String, BTreeMap<String, Vec<Diagnostic>>>;
+
 /// Document state tracked by the LSP client
 #[derive(Debug)]
 struct DocumentInfo {
@@ -33,55 +45,86 @@ pub struct LspClient {
     /// Channel for receiving async responses
     response_rx: Receiver<LspResponse>,
     response_tx: Sender<LspResponse>,
-    /// Pending diagnostics by URI
-    diagnostics: Arc<Mutex<HashMap<String, Vec<Diagnostic>>>>,
+    /// Pending diagnostics by URI and server; each server's replace only
+    /// its own
+    diagnostics: Arc<Mutex<DiagnosticsMap>>,
+    /// When idle servers were last looked for
+    last_idle_check: Instant,
+    /// Languages whose servers were started ahead of their first document;
+    /// they count as in use until one is opened
+    warmed: HashSet<String>,
 }
 
 /// Response types that can be received asynchronously
 #[derive(Debug)]
 pub enum LspResponse {
     Completions(i64, Vec<CompletionItem>),
+    CompletionResolved(i64, CompletionItem),
     Hover(i64, Option<HoverInfo>),
+    SignatureHelp(i64, Option<SignatureHelp>),
+    /// None when the server says the position can't be renamed
+    PrepareRename(i64, Option<PrepareRename>),
     Definition(i64, Vec<Location>),
     References(i64, Vec<Location>),
     Symbols(i64, Vec<DocumentSymbol>),
     Formatting(i64, Vec<TextEdit>),
     Rename(i64, WorkspaceEdit),
     CodeActions(i64, Vec<CodeAction>),
+    CodeLens(i64, Vec<CodeLens>),
+    CodeLensResolved(i64, CodeLens),
+    ExecuteCommand(i64),
+    DocumentLinks(i64, Vec<DocumentLink>),
+    DocumentLinkResolved(i64, DocumentLink),
+    /// Foldable (start_line, end_line) ranges
+    FoldingRanges(i64, Vec<(usize, usize)>),
+    /// Server-initiated workspace/applyEdit (e.g. as a result of executeCommand),
+    /// with where to send the outcome
+    ApplyEdit(WorkspaceEdit, Sender<ApplyEditResult>),
     Error(i64, String),
 }
 
 /// Code action from the server
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CodeAction {
     pub title: String,
     pub kind: Option<String>,
     pub edit: Option<WorkspaceEdit>,
-    pub command: Option<String>,
+    /// Run after `edit` is applied (or on its own for bare commands)
+    pub command: Option<Command>,
+    /// The server's suggested fix for the diagnostics in range
+    pub is_preferred: bool,
 }
 
 impl LspClient {
     /// Create a new LSP client
     pub fn new(workspace_root: &str) -> Self {
         let (tx, rx) = mpsc::channel();
-        let diagnostics = Arc::new(Mutex::new(HashMap::new()));
+        let diagnostics = Arc::new(Mutex::new(DiagnosticsMap::new()));
         let diag_clone = Arc::clone(&diagnostics);
 
         let mut manager = LspManager::new(workspace_root);
 
         // Set up diagnostics callback
-        manager.set_diagnostics_callback(move |uri, diags| {
+        manager.set_diagnostics_callback(move |server, uri, diags| {
             if let Ok(mut map) = diag_clone.lock() {
-                map.insert(uri, diags);
+                map.entry(uri).or_default().insert(server, diags);
             }
         });
 
+        // Forward server-initiated edits to the editor
+        let edit_tx = tx.clone();
+        manager.set_apply_edit_callback(move |edit, reply| {
+            let _ = edit_tx.send(LspResponse::ApplyEdit(edit, reply));
+        });
+
         Self {
             manager,
             documents: HashMap::new(),
             response_rx: rx,
             response_tx: tx,
             diagnostics,
+            last_idle_check: Instant::now(),
+            warmed: HashSet::new(),
         }
     }
 
@@ -103,6 +146,8 @@ impl LspClient {
             return Ok(());
         }
 
+        self.warmed.remove(language_id);
+
         // Track the document
         self.documents.insert(
             path.to_string(),
@@ -121,18 +166,23 @@ impl LspClient {
         Ok(())
     }
 
-    /// Notify the server of document changes
-    pub fn document_changed(&mut self, path: &str, content: &str) -> Result<()> {
+    /// Notify the servers of document changes: the `edits` made since the
+    /// last notification, in order, to servers that sync incrementally, and
+    /// the whole `content` to the rest or when the edits weren't kept
+    pub fn document_changed(
+        &mut self,
+        path: &str,
+        edits: Option<&[TextEdit]>,
+        content: impl Fn() -> String,
+    ) -> Result<()> {
         let doc = match self.documents.get_mut(path) {
             Some(d) => d,
             None => return Ok(()), // Document not tracked
         };
 
         doc.version += 1;
-        let notification =
-            protocol::create_did_change_notification(&doc.uri, doc.version, content);
         self.manager
-            .send_notification(&doc.language_id, notification)?;
+            .send_document_change(&doc.language_id, &doc.uri, doc.version, edits, content)?;
 
         Ok(())
     }
@@ -151,6 +201,11 @@ impl LspClient {
         Ok(())
     }
 
+    /// Get the version last sent to the server for a tracked document
+    pub fn document_version(&self, path: &str) -> Option<i32> {
+        self.documents.get(path).map(|doc| doc.version)
+    }
+
     /// Close a document
     pub fn close_document(&mut self, path: &str) -> Result<()> {
         let doc = match self.documents.remove(path) {
@@ -202,6 +257,42 @@ impl LspClient {
         Ok(id)
     }
 
+    /// Resolve a completion item (fetches lazily computed fields like additionalTextEdits)
+    pub fn resolve_completion(&mut self, path: &str, item: &CompletionItem) -> Result<i64> {
+        let doc = self
+            .documents
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Document not open: {}", path))?;
+        let raw = item
+            .raw
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Completion item has no server data"))?;
+
+        let id = protocol::next_request_id();
+        let request = protocol::create_completion_resolve_request(id, raw);
+
+        let tx = self.response_tx.clone();
+        let original = item.clone();
+        self.manager.send_request(
+            &doc.language_id,
+            request,
+            Box::new(move |req_id, result| {
+                let response = match result {
+                    Ok(value) => {
+                        let resolved = protocol::parse_completion_items(&serde_json::Value::Array(vec![value]))
+                            .pop()
+                            .unwrap_or(original);
+                        LspResponse::CompletionResolved(req_id, resolved)
+                    }
+                    Err(e) => LspResponse::Error(req_id, e.message),
+                };
+                let _ = tx.send(response);
+            }),
+        )?;
+
+        Ok(id)
+    }
+
     /// Request hover information at a position
     pub fn request_hover(&mut self, path: &str, line: u32, character: u32) -> Result<i64> {
         let doc = self
@@ -229,6 +320,33 @@ impl LspClient {
         Ok(id)
     }
 
+    /// Request signature help for the call at a position
+    pub fn request_signature_help(&mut self, path: &str, line: u32, character: u32) -> Result<i64> {
+        let doc = self
+            .documents
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Document not open: {}", path))?;
+
+        let id = protocol::next_request_id();
+        let request =
+            protocol::create_signature_help_request(id, &doc.uri, Position::new(line, character));
+
+        let tx = self.response_tx.clone();
+        self.manager.send_request(
+            &doc.language_id,
+            request,
+            Box::new(move |req_id, result| {
+                let response = match result {
+                    Ok(value) => LspResponse::SignatureHelp(req_id, protocol::parse_signature_help(&value)),
+                    Err(e) => LspResponse::Error(req_id, e.message),
+                };
+                let _ = tx.send(response);
+            }),
+        )?;
+
+        Ok(id)
+    }
+
     /// Request go-to-definition at a position
     pub fn request_definition(&mut self, path: &str, line: u32, character: u32) -> Result<i64> {
         let doc = self
@@ -349,6 +467,40 @@ impl LspClient {
         Ok(id)
     }
 
+    /// Whether the server for the file at `path` answers prepareRename
+    pub fn supports_prepare_rename(&mut self, path: &str) -> bool {
+        let Some(doc) = self.documents.get(path) else { return false };
+        let language = doc.language_id.clone();
+        self.manager.get_server_with_capability(&language, |caps| caps.prepare_rename).is_some()
+    }
+
+    /// Ask whether a position can be renamed, and what symbol is there
+    pub fn request_prepare_rename(&mut self, path: &str, line: u32, character: u32) -> Result<i64> {
+        let doc = self
+            .documents
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Document not open: {}", path))?;
+
+        let id = protocol::next_request_id();
+        let request =
+            protocol::create_prepare_rename_request(id, &doc.uri, Position::new(line, character));
+
+        let tx = self.response_tx.clone();
+        self.manager.send_request(
+            &doc.language_id,
+            request,
+            Box::new(move |req_id, result| {
+                let response = match result {
+                    Ok(value) => LspResponse::PrepareRename(req_id, protocol::parse_prepare_rename(&value)),
+                    Err(e) => LspResponse::Error(req_id, e.message),
+                };
+                let _ = tx.send(response);
+            }),
+        )?;
+
+        Ok(id)
+    }
+
     /// Request rename refactoring
     pub fn request_rename(
         &mut self,
@@ -407,7 +559,12 @@ impl LspClient {
             Position::new(start_line, start_char),
             Position::new(end_line, end_char),
         );
-        let request = protocol::create_code_action_request(id, &doc.uri, range);
+        let diagnostics: Vec<Diagnostic> = self
+            .get_diagnostics(path)
+            .into_iter()
+            .filter(|d| d.range.start.line <= end_line && d.range.end.line >= start_line)
+            .collect();
+        let request = protocol::create_code_action_request(id, &doc.uri, range, &diagnostics);
 
         let tx = self.response_tx.clone();
         self.manager.send_request(
@@ -428,18 +585,190 @@ impl LspClient {
         Ok(id)
     }
 
+    /// Request code lenses for a document
+    pub fn request_code_lens(&mut self, path: &str) -> Result<i64> {
+        let doc = self
+            .documents
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Document not open: {}", path))?;
+
+        let id = protocol::next_request_id();
+        let request = protocol::create_code_lens_request(id, &doc.uri);
+
+        let tx = self.response_tx.clone();
+        self.manager.send_request(
+            &doc.language_id,
+            request,
+            Box::new(move |req_id, result| {
+                let response = match result {
+                    Ok(value) => LspResponse::CodeLens(req_id, protocol::parse_code_lenses(&value)),
+                    Err(e) => LspResponse::Error(req_id, e.message),
+                };
+                let _ = tx.send(response);
+            }),
+        )?;
+
+        Ok(id)
+    }
+
+    /// Resolve a code lens that was returned without a command
+    pub fn resolve_code_lens(&mut self, path: &str, lens: &CodeLens) -> Result<i64> {
+        let doc = self
+            .documents
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Document not open: {}", path))?;
+
+        let id = protocol::next_request_id();
+        let request = protocol::create_code_lens_resolve_request(id, lens);
+
+        let tx = self.response_tx.clone();
+        let original = lens.clone();
+        self.manager.send_request(
+            &doc.language_id,
+            request,
+            Box::new(move |req_id, result| {
+                let response = match result {
+                    Ok(value) => {
+                        let resolved = protocol::parse_code_lens(&value).unwrap_or(original);
+                        LspResponse::CodeLensResolved(req_id, resolved)
+                    }
+                    Err(e) => LspResponse::Error(req_id, e.message),
+                };
+                let _ = tx.send(response);
+            }),
+        )?;
+
+        Ok(id)
+    }
+
+    /// Request document links (import paths, URLs) for a document
+    pub fn request_document_links(&mut self, path: &str) -> Result<i64> {
+        let doc = self
+            .documents
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Document not open: {}", path))?;
+
+        let id = protocol::next_request_id();
+        let request = protocol::create_document_link_request(id, &doc.uri);
+
+        let tx = self.response_tx.clone();
+        self.manager.send_request(
+            &doc.language_id,
+            request,
+            Box::new(move |req_id, result| {
+                let response = match result {
+                    Ok(value) => {
+                        LspResponse::DocumentLinks(req_id, protocol::parse_document_links(&value))
+                    }
+                    Err(e) => LspResponse::Error(req_id, e.message),
+                };
+                let _ = tx.send(response);
+            }),
+        )?;
+
+        Ok(id)
+    }
+
+    /// Request folding ranges for a document
+    pub fn request_folding_ranges(&mut self, path: &str) -> Result<i64> {
+        let doc = self
+            .documents
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Document not open: {}", path))?;
+
+        let id = protocol::next_request_id();
+        let request = protocol::create_folding_range_request(id, &doc.uri);
+
+        let tx = self.response_tx.clone();
+        self.manager.send_request(
+            &doc.language_id,
+            request,
+            Box::new(move |req_id, result| {
+                let response = match result {
+                    Ok(value) => LspResponse::FoldingRanges(req_id, protocol::parse_folding_ranges(&value)),
+                    Err(e) => LspResponse::Error(req_id, e.message),
+                };
+                let _ = tx.send(response);
+            }),
+        )?;
+
+        Ok(id)
+    }
+
+    /// Resolve the target of a document link that was returned without one
+    pub fn resolve_document_link(&mut self, path: &str, link: &DocumentLink) -> Result<i64> {
+        let doc = self
+            .documents
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Document not open: {}", path))?;
+
+        let id = protocol::next_request_id();
+        let request = protocol::create_document_link_resolve_request(id, link);
+
+        let tx = self.response_tx.clone();
+        let original = link.clone();
+        self.manager.send_request(
+            &doc.language_id,
+            request,
+            Box::new(move |req_id, result| {
+                let response = match result {
+                    Ok(value) => {
+                        let resolved = protocol::parse_document_link(&value).unwrap_or(original);
+                        LspResponse::DocumentLinkResolved(req_id, resolved)
+                    }
+                    Err(e) => LspResponse::Error(req_id, e.message),
+                };
+                let _ = tx.send(response);
+            }),
+        )?;
+
+        Ok(id)
+    }
+
+    /// Ask the server responsible for `path` to execute a command
+    pub fn execute_command(&mut self, path: &str, command: &Command) -> Result<i64> {
+        let doc = self
+            .documents
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Document not open: {}", path))?;
+
+        let id = protocol::next_request_id();
+        let request = protocol::create_execute_command_request(id, command);
+
+        let tx = self.response_tx.clone();
+        self.manager.send_request(
+            &doc.language_id,
+            request,
+            Box::new(move |req_id, result| {
+                let response = match result {
+                    Ok(_) => LspResponse::ExecuteCommand(req_id),
+                    Err(e) => LspResponse::Error(req_id, e.message),
+                };
+                let _ = tx.send(response);
+            }),
+        )?;
+
+        Ok(id)
+    }
+
     /// Poll for responses (non-blocking)
     pub fn poll_response(&self) -> Option<LspResponse> {
         self.response_rx.try_recv().ok()
     }
 
-    /// Get diagnostics for a file
+    /// Queue `response` as if a server had sent it, for scripted tests
+    #[cfg(any(test, feature = "harness"))]
+    pub fn push_response(&self, response: LspResponse) {
+        let _ = self.response_tx.send(response);
+    }
+
+    /// Get diagnostics for a file, from all of its servers
     pub fn get_diagnostics(&self, path: &str) -> Vec<Diagnostic> {
         let uri = path_to_uri(path);
         self.diagnostics
             .lock()
             .ok()
-            .and_then(|map| map.get(&uri).cloned())
+            .and_then(|map| map.get(&uri).map(|by_server| by_server.values().flatten().cloned().collect()))
             .unwrap_or_default()
     }
 
@@ -448,13 +777,103 @@ impl LspClient {
         self.diagnostics
             .lock()
             .ok()
-            .map(|map| map.clone())
+            .map(|map| {
+                map.iter()
+                    .map(|(uri, by_server)| (uri.clone(), by_server.values().flatten().cloned().collect()))
+                    .collect()
+            })
             .unwrap_or_default()
     }
 
     /// Process pending server messages (call this regularly)
     pub fn process_messages(&mut self) {
         self.manager.process_messages();
+
+        if self.last_idle_check.elapsed() >= IDLE_CHECK_INTERVAL {
+            self.last_idle_check = Instant::now();
+            let in_use: HashSet<&str> = self
+                .documents
+                .values()
+                .map(|doc| doc.language_id.as_str())
+                .chain(self.warmed.iter().map(String::as_str))
+                .collect();
+            let stopped = self.manager.stop_idle(&in_use, IDLE_SHUTDOWN);
+            self.forget_diagnostics(&stopped);
+        }
+    }
+
+    /// Drop the diagnostics published by servers that were stopped
+    fn forget_diagnostics(&self, servers: &[String]) {
+        if servers.is_empty() {
+            return;
+        }
+        if let Ok(mut map) = self.diagnostics.lock() {
+            for by_server in map.values_mut() {
+                by_server.retain(|server, _| !servers.contains(server));
+            }
+            map.retain(|_, by_server| !by_server.is_empty());
+        }
+    }
+
+    /// Running language servers and their states
+    pub fn server_statuses(&self) -> Vec<ServerStatus> {
+        self.manager.statuses()
+    }
+
+    /// Log servers' stderr to files in `dir`
+    pub fn set_log_dir(&mut self, dir: PathBuf) {
+        self.manager.set_log_dir(dir);
+    }
+
+    /// The stderr log of the server that runs as `command`, if one was written
+    pub fn server_log(&self, command: &str) -> Option<PathBuf> {
+        self.manager.log_for_command(command)
+    }
+
+    /// Stop the servers running as `command`; returns how many stopped
+    pub fn stop_server(&mut self, command: &str) -> usize {
+        let stopped = self.manager.stop_command(command);
+        self.forget_diagnostics(&stopped);
+        stopped.len()
+    }
+
+    /// Start the servers that run as `command` and open the tracked
+    /// documents of their languages in them; `contents` has the text of
+    /// open documents by path. Returns how many started.
+    pub fn start_server(&mut self, command: &str, contents: &HashMap<String, String>) -> Result<usize> {
+        let started = self.manager.start_command(command)?;
+        for (name, language) in &started {
+            for (path, doc) in self.documents.iter().filter(|(_, doc)| &doc.language_id == language) {
+                let Some(content) = contents.get(path) else { continue };
+                let notification = protocol::create_did_open_notification(&doc.uri, language, doc.version, content);
+                self.manager.send_to(language, name, notification)?;
+            }
+        }
+        Ok(started.len())
+    }
+
+    /// Stop and start again the servers that run as `command`
+    pub fn restart_server(&mut self, command: &str, contents: &HashMap<String, String>) -> Result<usize> {
+        self.stop_server(command);
+        self.start_server(command, contents)
+    }
+
+    /// Start the servers of `languages` before any of their documents is
+    /// opened, so the first request doesn't wait for them to boot; languages
+    /// without a configured or installed server are skipped. Returns the
+    /// names of the servers started.
+    pub fn warm_up(&mut self, languages: &[&str]) -> Vec<String> {
+        let mut started = Vec::new();
+        for &language in languages {
+            if self.documents.values().any(|doc| doc.language_id == language) {
+                continue;
+            }
+            if let Ok(names) = self.manager.start_language(language) {
+                self.warmed.insert(language.to_string());
+                started.extend(names);
+            }
+        }
+        started
     }
 
     /// Check if LSP is available for a language
@@ -483,21 +902,32 @@ fn parse_code_actions(value: &serde_json::Value) -> Vec<CodeAction> {
             arr.iter()
                 .filter_map(|action| {
                     let title = action.get("title")?.as_str()?.to_string();
+                    // A bare Command has a string `command`; a CodeAction nests one
+                    if action.get("command").is_some_and(|c| c.is_string()) {
+                        return Some(CodeAction {
+                            title,
+                            kind: None,
+                            edit: None,
+                            command: protocol::parse_command(action),
+                            is_preferred: false,
+                        });
+                    }
                     let kind = action.get("kind").and_then(|v| v.as_str()).map(String::from);
                     let edit = action
                         .get("edit")
                         .map(|e| protocol::parse_workspace_edit(e));
-                    let command = action
-                        .get("command")
-                        .and_then(|c| c.get("command"))
-                        .and_then(|v| v.as_str())
-                        .map(String::from);
+                    let command = action.get("command").and_then(protocol::parse_command);
+                    let is_preferred = action
+                        .get("isPreferred")
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false);
 
                     Some(CodeAction {
                         title,
                         kind,
                         edit,
                         command,
+                        is_preferred,
                     })
                 })
                 .collect()
@@ -3,18 +3,30 @@
 //! Manages multiple language server instances, handles initialization,
 //! and routes requests to appropriate servers.
 //!
+//! Every server configured for a language runs side by side (pyright and
+//! ruff for Python): document notifications go to all of them, and each
+//! request goes to the first one that handles its method.
+//!
 //! Note: Some methods are planned features not yet wired to the UI.
 #![allow(dead_code)]
 
 use anyhow::{anyhow, Result};
-use serde_json::Value;
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, trace, warn};
 
-use super::message::{DiagnosticsCallback, MessageHandler, ResponseCallback};
+use super::message::{ApplyEditCallback, ApplyEditResult, MessageHandler, ResponseCallback};
 use super::process::ServerProcess;
 use super::protocol::{self, LspMessage};
-use super::types::{Capabilities, ServerConfig};
+use super::types::{Capabilities, Diagnostic, ServerConfig, TextEdit};
+
+/// Diagnostics callback told which server published them: (server name,
+/// uri, diagnostics)
+pub type ServerDiagnosticsCallback = Box<dyn Fn(String, String, Vec<Diagnostic>) + Send>;
 
 /// State of a language server
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,6 +47,22 @@ pub struct ManagedServer {
     pub handler: MessageHandler,
     /// Queued didOpen notifications (for files opened before initialization)
     pending_opens: Vec<LspMessage>,
+    /// When a request or notification was last sent
+    last_used: Instant,
+}
+
+/// A running server, for status displays
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerStatus {
+    pub name: String,
+    pub language: String,
+    /// Program the server runs as
+    pub command: String,
+    pub state: ServerState,
+    pub pid: u32,
+    pub started: Instant,
+    /// Resident memory in megabytes, where the OS reports it
+    pub memory_mb: Option<u64>,
 }
 
 impl ManagedServer {
@@ -46,8 +74,39 @@ impl ManagedServer {
             capabilities: Capabilities::default(),
             handler: MessageHandler::new(),
             pending_opens: Vec::new(),
+            last_used: Instant::now(),
         }
     }
+
+    /// Whether this server should be sent `method`: its config allows the
+    /// feature and, once it has said what it supports, so does the server
+    fn handles(&self, method: &str) -> bool {
+        let Some(check) = capability_for(method) else { return true };
+        check(&self.config.capabilities) && (self.state != ServerState::Ready || check(&self.capabilities))
+    }
+}
+
+/// The capability a request method needs, if it is one servers can lack
+fn capability_for(method: &str) -> Option<fn(&Capabilities) -> bool> {
+    let check: fn(&Capabilities) -> bool = match method {
+        "textDocument/completion" | "completionItem/resolve" => |c| c.completion,
+        "textDocument/hover" => |c| c.hover,
+        "textDocument/signatureHelp" => |c| c.signature_help,
+        "textDocument/definition" => |c| c.definition,
+        "textDocument/references" => |c| c.references,
+        "textDocument/rename" => |c| c.rename,
+        "textDocument/prepareRename" => |c| c.prepare_rename,
+        "textDocument/codeAction" => |c| c.code_actions,
+        "textDocument/formatting" => |c| c.formatting,
+        "textDocument/documentSymbol" => |c| c.document_symbols,
+        "workspace/symbol" => |c| c.workspace_symbols,
+        "textDocument/codeLens" | "codeLens/resolve" => |c| c.code_lens,
+        "workspace/executeCommand" => |c| c.execute_command,
+        "textDocument/documentLink" | "documentLink/resolve" => |c| c.document_link,
+        "textDocument/foldingRange" => |c| c.folding_range,
+        _ => return None,
+    };
+    Some(check)
 }
 
 /// Manager for multiple language servers
@@ -59,7 +118,11 @@ pub struct LspManager {
     /// Active servers by language
     servers: HashMap<String, Vec<ManagedServer>>,
     /// Global diagnostics callback
-    diagnostics_callback: Option<Arc<Mutex<DiagnosticsCallback>>>,
+    diagnostics_callback: Option<Arc<Mutex<ServerDiagnosticsCallback>>>,
+    /// Global workspace/applyEdit callback
+    apply_edit_callback: Option<Arc<Mutex<ApplyEditCallback>>>,
+    /// Directory servers' stderr is logged to
+    log_dir: Option<PathBuf>,
 }
 
 impl LspManager {
@@ -70,19 +133,30 @@ impl LspManager {
             configs: HashMap::new(),
             servers: HashMap::new(),
             diagnostics_callback: None,
+            apply_edit_callback: None,
+            log_dir: None,
         };
         manager.register_default_configs();
         manager
     }
 
-    /// Set the global diagnostics callback
+    /// Set the global diagnostics callback, which is told the name of the
+    /// server publishing them
     pub fn set_diagnostics_callback<F>(&mut self, callback: F)
     where
-        F: Fn(String, Vec<super::types::Diagnostic>) + Send + 'static,
+        F: Fn(String, String, Vec<Diagnostic>) + Send + 'static,
     {
         self.diagnostics_callback = Some(Arc::new(Mutex::new(Box::new(callback))));
     }
 
+    /// Set the global workspace/applyEdit callback
+    pub fn set_apply_edit_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(super::types::WorkspaceEdit, Sender<ApplyEditResult>) + Send + 'static,
+    {
+        self.apply_edit_callback = Some(Arc::new(Mutex::new(Box::new(callback))));
+    }
+
     /// Register default server configurations
     fn register_default_configs(&mut self) {
         // Rust - rust-analyzer
@@ -102,12 +176,18 @@ impl LspManager {
                     definition: false,
                     references: false,
                     rename: false,
+                    prepare_rename: false,
                     code_actions: true,
                     formatting: true,
                     diagnostics: true,
                     document_symbols: false,
                     workspace_symbols: false,
                     signature_help: false,
+                    code_lens: false,
+                    execute_command: false,
+                    document_link: false,
+                    folding_range: false,
+                    incremental_sync: true,
                 },
             ),
         );
@@ -372,6 +452,41 @@ impl LspManager {
         self.register_config(ServerConfig::new("ols", "odin", vec!["ols"]));
     }
 
+    /// Log each server's stderr to a file in `dir` from now on
+    pub fn set_log_dir(&mut self, dir: PathBuf) {
+        self.log_dir = Some(dir);
+    }
+
+    /// File a server's stderr is logged to, if logging is set up
+    pub fn log_path(&self, config: &ServerConfig) -> Option<PathBuf> {
+        let dir = self.log_dir.as_ref()?;
+        Some(dir.join(format!("lsp-{}-{}.log", config.name, config.language)))
+    }
+
+    /// Log of a server running as `command`: a running one's, else the
+    /// newest one written
+    pub fn log_for_command(&self, command: &str) -> Option<PathBuf> {
+        if let Some(server) = self.servers.values().flatten().find(|s| s.config.command.first().is_some_and(|c| c == command)) {
+            return self.log_path(&server.config);
+        }
+        self.configs_for_command(command)
+            .iter()
+            .filter_map(|config| self.log_path(config))
+            .filter_map(|path| Some((std::fs::metadata(&path).ok()?.modified().ok()?, path)))
+            .max()
+            .map(|(_, path)| path)
+    }
+
+    /// Configurations of the servers that run as `command`
+    fn configs_for_command(&self, command: &str) -> Vec<ServerConfig> {
+        self.configs
+            .values()
+            .flatten()
+            .filter(|config| config.command.first().is_some_and(|c| c == command))
+            .cloned()
+            .collect()
+    }
+
     /// Register a server configuration
     pub fn register_config(&mut self, config: ServerConfig) {
         self.configs
@@ -443,7 +558,13 @@ impl LspManager {
         }
 
         // Spawn the server process
-        let process = ServerProcess::spawn(&config.command)?;
+        info!("starting {} for {}", config.name, config.language);
+        let log = self.log_path(config);
+        if let Some(dir) = log.as_ref().and_then(|path| path.parent()) {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let process = ServerProcess::spawn(&config.command, log.as_deref())
+            .inspect_err(|e| warn!("failed to start {}: {}", config.name, e))?;
 
         // Create managed server
         let mut server = ManagedServer::new(config.clone(), process);
@@ -451,15 +572,26 @@ impl LspManager {
         // Set up diagnostics callback if configured
         if let Some(ref callback) = self.diagnostics_callback {
             let cb = Arc::clone(callback);
+            let name = config.name.clone();
             server.handler.set_diagnostics_callback(Box::new(
                 move |uri, diags| {
                     if let Ok(cb) = cb.lock() {
-                        cb(uri, diags);
+                        cb(name.clone(), uri, diags);
                     }
                 },
             ));
         }
 
+        // Set up applyEdit callback if configured
+        if let Some(ref callback) = self.apply_edit_callback {
+            let cb = Arc::clone(callback);
+            server.handler.set_apply_edit_callback(Box::new(move |edit, reply| {
+                if let Ok(cb) = cb.lock() {
+                    cb(edit, reply);
+                }
+            }));
+        }
+
         // Send initialize request
         let id = protocol::next_request_id();
         let init_msg = protocol::create_initialize_request(id, &self.workspace_root, "fackr");
@@ -476,18 +608,104 @@ impl LspManager {
         Ok(())
     }
 
-    /// Get a server for a language (start if needed)
-    pub fn get_or_start_server(&mut self, language: &str) -> Result<&mut ManagedServer> {
-        if !self.servers.contains_key(language) || self.servers.get(language).map_or(true, |s| s.is_empty()) {
-            self.start_server(language)?;
+    /// Start the servers configured for a language that aren't running;
+    /// returns the names of those started
+    pub fn start_language(&mut self, language: &str) -> Result<Vec<String>> {
+        let configs = self
+            .configs
+            .get(language)
+            .ok_or_else(|| anyhow!("No LSP server configured for language: {}", language))?
+            .clone();
+        let running = |manager: &Self, name: &str| {
+            manager.servers.get(language).is_some_and(|servers| servers.iter().any(|s| s.config.name == name))
+        };
+        let mut started = Vec::new();
+        for config in configs {
+            if !running(self, &config.name) && self.start_server_with_config(&config).is_ok() {
+                started.push(config.name);
+            }
+        }
+        if started.is_empty() {
+            return Err(anyhow!("No LSP server started for language: {}", language));
+        }
+        Ok(started)
+    }
+
+    /// Start every server configured to run as `command` that isn't
+    /// running; returns the (name, language) of each one started
+    pub fn start_command(&mut self, command: &str) -> Result<Vec<(String, String)>> {
+        let configs = self.configs_for_command(command);
+        if configs.is_empty() {
+            return Err(anyhow!("No LSP server runs as {}", command));
+        }
+        let mut started = Vec::new();
+        let mut error = None;
+        for config in configs {
+            let running = self.servers.get(&config.language).is_some_and(|servers| servers.iter().any(|s| s.config.name == config.name));
+            if running {
+                continue;
+            }
+            match self.start_server_with_config(&config) {
+                Ok(()) => started.push((config.name, config.language)),
+                Err(e) => error = Some(e),
+            }
+        }
+        match error {
+            Some(e) if started.is_empty() => Err(e),
+            _ => Ok(started),
+        }
+    }
+
+    /// Stop every server running as `command`; returns the names of those
+    /// stopped
+    pub fn stop_command(&mut self, command: &str) -> Vec<String> {
+        let mut stopped = Vec::new();
+        for servers in self.servers.values_mut() {
+            servers.retain_mut(|server| {
+                if server.config.command.first().is_none_or(|c| c != command) {
+                    return true;
+                }
+                Self::shutdown(server);
+                stopped.push(server.config.name.clone());
+                false
+            });
         }
+        stopped
+    }
 
+    /// Get a server for a language (starting all of the language's
+    /// servers if none runs)
+    pub fn get_or_start_server(&mut self, language: &str) -> Result<&mut ManagedServer> {
+        self.ensure_servers(language)?;
         self.servers
             .get_mut(language)
             .and_then(|servers| servers.first_mut())
             .ok_or_else(|| anyhow!("No server available for language: {}", language))
     }
 
+    /// Start the servers configured for a language unless some already run
+    fn ensure_servers(&mut self, language: &str) -> Result<()> {
+        if self.servers.get(language).map_or(true, |s| s.is_empty()) {
+            self.start_all_servers(language)?;
+        }
+        Ok(())
+    }
+
+    /// The server of a language that should answer `method` (starting the
+    /// language's servers if needed): the first that handles it, else the
+    /// first one
+    fn route(&mut self, language: &str, method: &str) -> Result<&mut ManagedServer> {
+        self.ensure_servers(language)?;
+        let servers = self
+            .servers
+            .get_mut(language)
+            .ok_or_else(|| anyhow!("No server available for language: {}", language))?;
+        let index = servers.iter().position(|s| s.handles(method)).unwrap_or(0);
+        servers
+            .get_mut(index)
+            .ok_or_else(|| anyhow!("No server available for language: {}", language))
+    }
+
     /// Get a server with a specific capability
     pub fn get_server_with_capability(
         &mut self,
@@ -512,6 +730,7 @@ impl LspManager {
     /// Process messages for a single server
     fn process_server_messages(server: &mut ManagedServer, _workspace_root: &str) {
         while let Some(json_str) = server.process.try_recv() {
+            trace!("{} -> {}", server.config.name, json_str);
             if let Ok(value) = serde_json::from_str::<Value>(&json_str) {
                 if let Some(msg) = LspMessage::from_json(value.clone()) {
                     // Handle initialization response specially
@@ -521,6 +740,7 @@ impl LspManager {
                                 // Parse capabilities
                                 server.capabilities = protocol::parse_capabilities(result);
                                 server.state = ServerState::Ready;
+                                info!("{} initialized", server.config.name);
 
                                 // Send initialized notification
                                 let init_notif = protocol::create_initialized_notification();
@@ -540,8 +760,15 @@ impl LspManager {
                         let _ = server.process.send(&response.to_string());
                     }
                 }
+            } else {
+                debug!("{} sent invalid JSON: {}", server.config.name, json_str);
             }
         }
+
+        // Answer the server's edits the editor has finished with
+        for response in server.handler.finished_edits() {
+            let _ = server.process.send(&response.to_string());
+        }
     }
 
     /// Send a request to a server and register callback
@@ -553,7 +780,12 @@ impl LspManager {
     ) -> Result<()> {
         // Clone workspace_root to avoid borrow conflict
         let workspace_root = self.workspace_root.clone();
-        let server = self.get_or_start_server(language)?;
+        let method = match &message {
+            LspMessage::Request { method, .. } => method.clone(),
+            _ => String::new(),
+        };
+        let server = self.route(language, &method)?;
+        server.last_used = Instant::now();
 
         // Wait for server to be ready
         if server.state != ServerState::Ready {
@@ -575,51 +807,173 @@ impl LspManager {
         Ok(())
     }
 
-    /// Send a notification to a server
+    /// Send a notification to one server of a language, queueing didOpen
+    /// until it is ready
+    pub fn send_to(&mut self, language: &str, name: &str, message: LspMessage) -> Result<()> {
+        let server = self
+            .servers
+            .get_mut(language)
+            .and_then(|servers| servers.iter_mut().find(|s| s.config.name == name))
+            .ok_or_else(|| anyhow!("{} is not running", name))?;
+        let is_open = matches!(message, LspMessage::Notification { ref method, .. } if method == "textDocument/didOpen");
+        if is_open && server.state != ServerState::Ready {
+            server.pending_opens.push(message);
+            return Ok(());
+        }
+        server.process.send(&message.to_string())
+    }
+
+    /// Send a notification to every server of a language
     pub fn send_notification(&mut self, language: &str, message: LspMessage) -> Result<()> {
-        let server = self.get_or_start_server(language)?;
+        self.ensure_servers(language)?;
+        let Some(servers) = self.servers.get_mut(language) else {
+            return Ok(());
+        };
 
-        // Queue didOpen if server not ready
-        if server.state != ServerState::Ready {
-            if let LspMessage::Notification { ref method, .. } = message {
-                if method == "textDocument/didOpen" {
-                    server.pending_opens.push(message);
-                    return Ok(());
+        let is_open = matches!(message, LspMessage::Notification { ref method, .. } if method == "textDocument/didOpen");
+        let mut result = Ok(());
+        for server in servers.iter_mut() {
+            server.last_used = Instant::now();
+            // Queue didOpen if server not ready
+            if is_open && server.state != ServerState::Ready {
+                server.pending_opens.push(message.clone());
+                continue;
+            }
+            if let Err(e) = server.process.send(&message.to_string()) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Send a document's change to every server of a language: only the
+    /// `edits` to a ready server that takes them, otherwise the whole
+    /// `text` (worked out once, if some server needs it). A server still
+    /// starting has the text put in its queued didOpen instead.
+    pub fn send_document_change(
+        &mut self,
+        language: &str,
+        uri: &str,
+        version: i32,
+        edits: Option<&[TextEdit]>,
+        text: impl Fn() -> String,
+    ) -> Result<()> {
+        self.ensure_servers(language)?;
+        let Some(servers) = self.servers.get_mut(language) else {
+            return Ok(());
+        };
+
+        let mut full_text: Option<String> = None;
+        let mut result = Ok(());
+        for server in servers.iter_mut() {
+            server.last_used = Instant::now();
+            if server.state != ServerState::Ready {
+                let queued = server.pending_opens.iter_mut().find_map(|message| match message {
+                    LspMessage::Notification { method, params: Some(params) }
+                        if method == "textDocument/didOpen" && params["textDocument"]["uri"] == uri =>
+                    {
+                        Some(params)
+                    }
+                    _ => None,
+                });
+                if let Some(params) = queued {
+                    params["textDocument"]["text"] = json!(full_text.get_or_insert_with(&text));
+                    params["textDocument"]["version"] = json!(version);
+                    continue;
                 }
             }
+            let incremental = server.state == ServerState::Ready
+                && server.config.capabilities.incremental_sync
+                && server.capabilities.incremental_sync;
+            let message = match edits {
+                Some(edits) if incremental => protocol::create_incremental_did_change_notification(uri, version, edits),
+                _ => protocol::create_did_change_notification(uri, version, full_text.get_or_insert_with(&text)),
+            };
+            if let Err(e) = server.process.send(&message.to_string()) {
+                result = Err(e);
+            }
         }
+        result
+    }
 
-        server.process.send(&message.to_string())?;
-        Ok(())
+    /// Stop the servers of languages outside `in_use` that nothing was sent
+    /// to for `idle`; returns the names of the servers stopped
+    pub fn stop_idle(&mut self, in_use: &HashSet<&str>, idle: Duration) -> Vec<String> {
+        let idle_languages: Vec<String> = self
+            .servers
+            .iter()
+            .filter(|(language, servers)| {
+                !servers.is_empty()
+                    && !in_use.contains(language.as_str())
+                    && servers.iter().all(|s| s.last_used.elapsed() >= idle)
+            })
+            .map(|(language, _)| language.clone())
+            .collect();
+
+        let mut stopped = Vec::new();
+        for language in idle_languages {
+            if let Some(servers) = self.servers.get(&language) {
+                stopped.extend(servers.iter().map(|s| s.config.name.clone()));
+            }
+            info!("stopping idle servers for {}", language);
+            let _ = self.stop_server(&language);
+        }
+        stopped
+    }
+
+    /// Running servers, by language then name
+    pub fn statuses(&self) -> Vec<ServerStatus> {
+        let mut statuses: Vec<ServerStatus> = self
+            .servers
+            .values()
+            .flatten()
+            .map(|s| ServerStatus {
+                name: s.config.name.clone(),
+                language: s.config.language.clone(),
+                command: s.config.command.first().cloned().unwrap_or_default(),
+                state: s.state,
+                pid: s.process.pid(),
+                started: s.process.started(),
+                memory_mb: s.process.memory().map(|bytes| bytes / (1024 * 1024)),
+            })
+            .collect();
+        statuses.sort_by(|a, b| (&a.language, &a.name).cmp(&(&b.language, &b.name)));
+        statuses
     }
 
     /// Stop a server for a language
     pub fn stop_server(&mut self, language: &str) -> Result<()> {
         if let Some(servers) = self.servers.get_mut(language) {
             for server in servers.iter_mut() {
-                server.state = ServerState::ShuttingDown;
-
-                // Send shutdown request
-                let id = protocol::next_request_id();
-                let shutdown = protocol::create_shutdown_request(id);
-                let _ = server.process.send(&shutdown.to_string());
-
-                // Wait briefly for shutdown acknowledgment
-                std::thread::sleep(std::time::Duration::from_millis(100));
-
-                // Send exit notification
-                let exit = protocol::create_exit_notification();
-                let _ = server.process.send(&exit.to_string());
-
-                // Kill the process
-                let _ = server.process.kill();
-                server.state = ServerState::Stopped;
+                Self::shutdown(server);
             }
             servers.clear();
         }
         Ok(())
     }
 
+    /// Ask a server to shut down and exit, then kill its process
+    fn shutdown(server: &mut ManagedServer) {
+        info!("stopping {}", server.config.name);
+        server.state = ServerState::ShuttingDown;
+
+        // Send shutdown request
+        let id = protocol::next_request_id();
+        let shutdown = protocol::create_shutdown_request(id);
+        let _ = server.process.send(&shutdown.to_string());
+
+        // Wait briefly for shutdown acknowledgment
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        // Send exit notification
+        let exit = protocol::create_exit_notification();
+        let _ = server.process.send(&exit.to_string());
+
+        // Kill the process
+        let _ = server.process.kill();
+        server.state = ServerState::Stopped;
+    }
+
     /// Stop all servers
     pub fn stop_all(&mut self) {
         let languages: Vec<String> = self.servers.keys().cloned().collect();
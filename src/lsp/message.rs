@@ -7,6 +7,7 @@
 
 use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 
 use super::protocol::{LspMessage, ResponseError};
 use super::types::{
@@ -22,12 +23,23 @@ pub type ResponseCallback = Box<dyn FnOnce(i64, LspResult<Value>) + Send>;
 /// Callback for diagnostics notifications
 pub type DiagnosticsCallback = Box<dyn Fn(String, Vec<Diagnostic>) + Send>;
 
+/// Outcome of a workspace/applyEdit: applied, or the reason it wasn't
+pub type ApplyEditResult = Result<(), String>;
+
+/// Callback for server-initiated workspace/applyEdit requests, with where to
+/// send the outcome once the edit has been applied or refused
+pub type ApplyEditCallback = Box<dyn Fn(WorkspaceEdit, Sender<ApplyEditResult>) + Send>;
+
 /// Tracks pending requests and their callbacks
 pub struct MessageHandler {
     /// Pending request callbacks indexed by request ID
     pending: HashMap<i64, ResponseCallback>,
     /// Callback for diagnostics notifications
     diagnostics_callback: Option<DiagnosticsCallback>,
+    /// Callback for workspace/applyEdit requests
+    apply_edit_callback: Option<ApplyEditCallback>,
+    /// workspace/applyEdit requests waiting for their outcome
+    pending_edits: Vec<(i64, Receiver<ApplyEditResult>)>,
 }
 
 impl MessageHandler {
@@ -35,6 +47,8 @@ impl MessageHandler {
         Self {
             pending: HashMap::new(),
             diagnostics_callback: None,
+            apply_edit_callback: None,
+            pending_edits: Vec::new(),
         }
     }
 
@@ -48,6 +62,26 @@ impl MessageHandler {
         self.diagnostics_callback = Some(callback);
     }
 
+    /// Set the workspace/applyEdit callback
+    pub fn set_apply_edit_callback(&mut self, callback: ApplyEditCallback) {
+        self.apply_edit_callback = Some(callback);
+    }
+
+    /// Responses to the workspace/applyEdit requests whose outcome is known
+    pub fn finished_edits(&mut self) -> Vec<LspMessage> {
+        let mut responses = Vec::new();
+        self.pending_edits.retain(|(id, rx)| {
+            let outcome = match rx.try_recv() {
+                Ok(outcome) => outcome,
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => Err("the editor dropped the edit".to_string()),
+            };
+            responses.push(apply_edit_response(*id, outcome));
+            false
+        });
+        responses
+    }
+
     /// Handle an incoming message
     pub fn handle_message(&mut self, message: LspMessage) -> Option<LspMessage> {
         match message {
@@ -103,9 +137,23 @@ impl MessageHandler {
         &mut self,
         id: i64,
         method: &str,
-        _params: Option<Value>,
+        params: Option<Value>,
     ) -> Option<LspMessage> {
         match method {
+            "workspace/applyEdit" => {
+                // Hand the edit to the editor; it is applied on the main thread,
+                // which may ask the user first, and the answer waits for it
+                match (params.as_ref().and_then(|p| p.get("edit")), &self.apply_edit_callback) {
+                    (Some(edit), Some(callback)) => {
+                        let (tx, rx) = mpsc::channel();
+                        callback(super::protocol::parse_workspace_edit(edit), tx);
+                        self.pending_edits.push((id, rx));
+                        None
+                    }
+                    (None, _) => Some(apply_edit_response(id, Err("no edit in the request".to_string()))),
+                    (_, None) => Some(apply_edit_response(id, Err("edits are not supported".to_string()))),
+                }
+            }
             "workspace/configuration" => {
                 // Return empty configuration
                 Some(LspMessage::Response {
@@ -162,6 +210,15 @@ impl Default for MessageHandler {
     }
 }
 
+/// Response to a workspace/applyEdit request
+fn apply_edit_response(id: i64, outcome: ApplyEditResult) -> LspMessage {
+    let result = match outcome {
+        Ok(()) => serde_json::json!({ "applied": true }),
+        Err(reason) => serde_json::json!({ "applied": false, "failureReason": reason }),
+    };
+    LspMessage::Response { id, result: Some(result), error: None }
+}
+
 /// Parsed LSP response types for convenience
 pub enum ParsedResponse {
     Completions(Vec<CompletionItem>),
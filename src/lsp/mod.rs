@@ -10,6 +10,8 @@
 //! - Document symbols
 //! - Rename refactoring
 //! - Document formatting
+//! - Code lenses and server-side commands
+//! - Document links
 
 mod client;
 mod manager;
@@ -19,8 +21,12 @@ mod protocol;
 pub mod server_manager;
 mod types;
 
-pub use client::{LspClient, LspResponse};
+pub use client::{CodeAction, LspClient, LspResponse};
+pub use message::ApplyEditResult;
+pub use protocol::parse_locations;
 pub use server_manager::ServerManagerPanel;
 pub use types::{
-    CompletionItem, Diagnostic, DiagnosticSeverity, HoverInfo, Location, TextEdit, uri_to_path,
+    CodeLens, Command, CompletionItem, Diagnostic, DiagnosticSeverity, DocumentChange, DocumentLink, HoverInfo,
+    Location, Position, PrepareRename, Range, SignatureHelp, TextEdit, WorkspaceEdit, detect_language, path_to_uri,
+    uri_to_path,
 };
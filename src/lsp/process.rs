@@ -6,10 +6,16 @@
 #![allow(dead_code)]
 
 use anyhow::{anyhow, Result};
-use std::io::{Read, Write};
-use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, Stdio};
 use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 use std::thread;
+use std::time::Instant;
+
+/// Size a server's stderr log may reach before it starts over
+const STDERR_LOG_LIMIT: u64 = 1024 * 1024;
 
 /// A running language server process
 pub struct ServerProcess {
@@ -18,11 +24,14 @@ pub struct ServerProcess {
     message_rx: Receiver<String>,
     /// Buffer for incomplete messages
     read_buffer: String,
+    /// When the process was spawned
+    started: Instant,
 }
 
 impl ServerProcess {
-    /// Spawn a new language server process
-    pub fn spawn(command: &[String]) -> Result<Self> {
+    /// Spawn a new language server process; its stderr is written to
+    /// `stderr_log` (emptied first), or discarded without one
+    pub fn spawn(command: &[String], stderr_log: Option<&Path>) -> Result<Self> {
         if command.is_empty() {
             return Err(anyhow!("Empty command"));
         }
@@ -41,16 +50,23 @@ impl ServerProcess {
 
         let stdin = child.stdin.take().ok_or_else(|| anyhow!("No stdin"))?;
         let stdout = child.stdout.take().ok_or_else(|| anyhow!("No stdout"))?;
+        let stderr = child.stderr.take().ok_or_else(|| anyhow!("No stderr"))?;
 
         // Spawn a thread to read from stdout asynchronously
         let (tx, rx) = mpsc::channel();
         spawn_reader_thread(stdout, tx);
 
+        // A server blocks once a full stderr pipe goes unread, so it is
+        // always drained
+        let log = stderr_log.and_then(|path| File::create(path).ok());
+        spawn_stderr_thread(stderr, log);
+
         Ok(Self {
             child,
             stdin,
             message_rx: rx,
             read_buffer: String::new(),
+            started: Instant::now(),
         })
     }
 
@@ -149,6 +165,19 @@ impl ServerProcess {
     pub fn pid(&self) -> u32 {
         self.child.id()
     }
+
+    /// When the process was spawned
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// Resident memory of the process in bytes, where the OS reports it
+    pub fn memory(&self) -> Option<u64> {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", self.pid())).ok()?;
+        let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+        let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kb * 1024)
+    }
 }
 
 impl Drop for ServerProcess {
@@ -179,3 +208,32 @@ fn spawn_reader_thread(mut stdout: ChildStdout, tx: Sender<String>) {
         }
     });
 }
+
+/// Spawn a thread copying the server's stderr to `log`, emptying the file
+/// whenever it outgrows `STDERR_LOG_LIMIT`
+fn spawn_stderr_thread(mut stderr: ChildStderr, mut log: Option<File>) {
+    use std::io::ErrorKind;
+
+    thread::spawn(move || {
+        let mut buffer = [0u8; 8192];
+        let mut written = 0u64;
+        loop {
+            match stderr.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let Some(file) = log.as_mut() else { continue };
+                    if written + n as u64 > STDERR_LOG_LIMIT {
+                        let _ = file.set_len(0);
+                        let _ = file.rewind();
+                        written = 0;
+                    }
+                    if file.write_all(&buffer[..n]).is_ok() {
+                        written += n as u64;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    });
+}
@@ -9,7 +9,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::sync::atomic::{AtomicI64, Ordering};
 
-use super::types::{Capabilities, Position, Range};
+use super::types::{Capabilities, CodeLens, Command, DocumentLink, Position, Range};
 
 /// Global request ID counter
 static NEXT_REQUEST_ID: AtomicI64 = AtomicI64::new(1);
@@ -136,10 +136,13 @@ pub fn create_initialize_request(
         "textDocument": {
             "completion": {
                 "completionItem": {
-                    "snippetSupport": false,
+                    "snippetSupport": true,
                     "documentationFormat": ["plaintext", "markdown"],
                     "deprecatedSupport": true,
-                    "labelDetailsSupport": true
+                    "labelDetailsSupport": true,
+                    "resolveSupport": {
+                        "properties": ["additionalTextEdits", "documentation", "detail"]
+                    }
                 },
                 "contextSupport": true
             },
@@ -186,6 +189,13 @@ pub fn create_initialize_request(
                 }
             },
             "formatting": {},
+            "codeLens": {},
+            "documentLink": {
+                "tooltipSupport": true
+            },
+            "foldingRange": {
+                "lineFoldingOnly": true
+            },
             "synchronization": {
                 "didSave": true,
                 "willSave": false,
@@ -202,8 +212,11 @@ pub fn create_initialize_request(
                 }
             },
             "applyEdit": true,
+            "executeCommand": {},
             "workspaceEdit": {
-                "documentChanges": true
+                "documentChanges": true,
+                "resourceOperations": ["create", "rename", "delete"],
+                "failureHandling": "abort"
             }
         }
     });
@@ -290,6 +303,33 @@ pub fn create_did_change_notification(uri: &str, version: i32, text: &str) -> Ls
     }
 }
 
+/// Create textDocument/didChange notification (incremental sync): `edits`
+/// apply one after another, each to the text the previous one left
+pub fn create_incremental_did_change_notification(uri: &str, version: i32, edits: &[super::types::TextEdit]) -> LspMessage {
+    let changes: Vec<Value> = edits
+        .iter()
+        .map(|edit| {
+            json!({
+                "range": {
+                    "start": { "line": edit.range.start.line, "character": edit.range.start.character },
+                    "end": { "line": edit.range.end.line, "character": edit.range.end.character }
+                },
+                "text": edit.new_text
+            })
+        })
+        .collect();
+    LspMessage::Notification {
+        method: "textDocument/didChange".to_string(),
+        params: Some(json!({
+            "textDocument": {
+                "uri": uri,
+                "version": version
+            },
+            "contentChanges": changes
+        })),
+    }
+}
+
 /// Create textDocument/didSave notification
 pub fn create_did_save_notification(uri: &str, text: Option<&str>) -> LspMessage {
     let mut params = json!({
@@ -374,6 +414,15 @@ pub fn create_references_request(
     }
 }
 
+/// Create textDocument/prepareRename request
+pub fn create_prepare_rename_request(id: i64, uri: &str, pos: Position) -> LspMessage {
+    LspMessage::Request {
+        id,
+        method: "textDocument/prepareRename".to_string(),
+        params: Some(position_params(uri, pos)),
+    }
+}
+
 /// Create textDocument/rename request
 pub fn create_rename_request(id: i64, uri: &str, pos: Position, new_name: &str) -> LspMessage {
     let mut params = position_params(uri, pos);
@@ -385,8 +434,15 @@ pub fn create_rename_request(id: i64, uri: &str, pos: Position, new_name: &str)
     }
 }
 
-/// Create textDocument/codeAction request
-pub fn create_code_action_request(id: i64, uri: &str, range: Range) -> LspMessage {
+/// Create textDocument/codeAction request. `diagnostics` are the ones
+/// overlapping `range`; servers need them to offer quick fixes.
+pub fn create_code_action_request(
+    id: i64,
+    uri: &str,
+    range: Range,
+    diagnostics: &[super::types::Diagnostic],
+) -> LspMessage {
+    let diagnostics: Vec<Value> = diagnostics.iter().map(diagnostic_to_json).collect();
     LspMessage::Request {
         id,
         method: "textDocument/codeAction".to_string(),
@@ -397,12 +453,35 @@ pub fn create_code_action_request(id: i64, uri: &str, range: Range) -> LspMessag
                 "end": { "line": range.end.line, "character": range.end.character }
             },
             "context": {
-                "diagnostics": []
+                "diagnostics": diagnostics
             }
         })),
     }
 }
 
+/// Serialize a diagnostic back to the form the server published it in
+fn diagnostic_to_json(diagnostic: &super::types::Diagnostic) -> Value {
+    let range = &diagnostic.range;
+    let mut value = json!({
+        "range": {
+            "start": { "line": range.start.line, "character": range.start.character },
+            "end": { "line": range.end.line, "character": range.end.character }
+        },
+        "message": diagnostic.message,
+    });
+    if let Some(severity) = diagnostic.severity {
+        value["severity"] = json!(severity as u32);
+    }
+    if let Some(code) = &diagnostic.code {
+        // Codes arrive as numbers or strings; numbers were stringified when parsed
+        value["code"] = code.parse::<i64>().map_or_else(|_| json!(code), |n| json!(n));
+    }
+    if let Some(source) = &diagnostic.source {
+        value["source"] = json!(source);
+    }
+    value
+}
+
 /// Create textDocument/documentSymbol request
 pub fn create_document_symbols_request(id: i64, uri: &str) -> LspMessage {
     LspMessage::Request {
@@ -449,6 +528,96 @@ pub fn create_formatting_request(id: i64, uri: &str, tab_size: u32, use_spaces:
     }
 }
 
+/// Create completionItem/resolve request (the params are the original item)
+pub fn create_completion_resolve_request(id: i64, item: &Value) -> LspMessage {
+    LspMessage::Request {
+        id,
+        method: "completionItem/resolve".to_string(),
+        params: Some(item.clone()),
+    }
+}
+
+/// Create textDocument/codeLens request
+pub fn create_code_lens_request(id: i64, uri: &str) -> LspMessage {
+    LspMessage::Request {
+        id,
+        method: "textDocument/codeLens".to_string(),
+        params: Some(json!({
+            "textDocument": { "uri": uri }
+        })),
+    }
+}
+
+/// Create codeLens/resolve request
+pub fn create_code_lens_resolve_request(id: i64, lens: &CodeLens) -> LspMessage {
+    let mut params = json!({
+        "range": {
+            "start": { "line": lens.range.start.line, "character": lens.range.start.character },
+            "end": { "line": lens.range.end.line, "character": lens.range.end.character }
+        }
+    });
+    if let Some(ref data) = lens.data {
+        params["data"] = data.clone();
+    }
+    LspMessage::Request {
+        id,
+        method: "codeLens/resolve".to_string(),
+        params: Some(params),
+    }
+}
+
+/// Create workspace/executeCommand request
+pub fn create_execute_command_request(id: i64, command: &Command) -> LspMessage {
+    LspMessage::Request {
+        id,
+        method: "workspace/executeCommand".to_string(),
+        params: Some(json!({
+            "command": command.command,
+            "arguments": command.arguments
+        })),
+    }
+}
+
+/// Create textDocument/documentLink request
+pub fn create_document_link_request(id: i64, uri: &str) -> LspMessage {
+    LspMessage::Request {
+        id,
+        method: "textDocument/documentLink".to_string(),
+        params: Some(json!({
+            "textDocument": { "uri": uri }
+        })),
+    }
+}
+
+/// Create textDocument/foldingRange request
+pub fn create_folding_range_request(id: i64, uri: &str) -> LspMessage {
+    LspMessage::Request {
+        id,
+        method: "textDocument/foldingRange".to_string(),
+        params: Some(json!({
+            "textDocument": { "uri": uri }
+        })),
+    }
+}
+
+/// Create documentLink/resolve request
+pub fn create_document_link_resolve_request(id: i64, link: &DocumentLink) -> LspMessage {
+    let mut params = json!({
+        "range": {
+            "start": { "line": link.range.start.line, "character": link.range.start.character },
+            "end": { "line": link.range.end.line, "character": link.range.end.character }
+        }
+    });
+    if let Some(ref data) = link.data {
+        params["data"] = data.clone();
+    }
+    LspMessage::Request {
+        id,
+        method: "documentLink/resolve".to_string(),
+        params: Some(params),
+    }
+}
+
 // ============================================================================
 // Response Parsing
 // ============================================================================
@@ -463,12 +632,26 @@ pub fn parse_capabilities(result: &Value) -> Capabilities {
         definition: caps.get("definitionProvider").map_or(false, |v| !v.is_null()),
         references: caps.get("referencesProvider").map_or(false, |v| !v.is_null()),
         rename: caps.get("renameProvider").map_or(false, |v| !v.is_null()),
+        prepare_rename: caps
+            .get("renameProvider")
+            .and_then(|v| v.get("prepareProvider"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
         code_actions: caps.get("codeActionProvider").map_or(false, |v| !v.is_null()),
         formatting: caps.get("documentFormattingProvider").map_or(false, |v| !v.is_null()),
         diagnostics: true, // Always assume diagnostics are supported
         document_symbols: caps.get("documentSymbolProvider").map_or(false, |v| !v.is_null()),
         workspace_symbols: caps.get("workspaceSymbolProvider").map_or(false, |v| !v.is_null()),
         signature_help: caps.get("signatureHelpProvider").is_some(),
+        code_lens: caps.get("codeLensProvider").is_some(),
+        execute_command: caps.get("executeCommandProvider").is_some(),
+        document_link: caps.get("documentLinkProvider").is_some(),
+        folding_range: caps.get("foldingRangeProvider").is_some_and(|v| !v.is_null()),
+        // A bare kind, or an options object with one under "change"
+        incremental_sync: caps
+            .get("textDocumentSync")
+            .and_then(|v| v.as_u64().or_else(|| v.get("change")?.as_u64()))
+            == Some(2),
     }
 }
 
@@ -527,6 +710,8 @@ pub fn parse_completion_items(result: &Value) -> Vec<super::types::CompletionIte
                         }
                     }),
                 insert_text: item.get("insertText").and_then(|v| v.as_str()).map(String::from),
+                // insertTextFormat 2: the text is a snippet
+                is_snippet: item.get("insertTextFormat").and_then(|v| v.as_u64()) == Some(2),
                 text_edit: item.get("textEdit").and_then(|te| {
                     Some(super::types::TextEdit {
                         range: parse_range(te.get("range")?)?,
@@ -535,6 +720,11 @@ pub fn parse_completion_items(result: &Value) -> Vec<super::types::CompletionIte
                 }),
                 sort_text: item.get("sortText").and_then(|v| v.as_str()).map(String::from),
                 filter_text: item.get("filterText").and_then(|v| v.as_str()).map(String::from),
+                additional_text_edits: item
+                    .get("additionalTextEdits")
+                    .map(parse_text_edits)
+                    .unwrap_or_default(),
+                raw: Some(item.clone()),
             })
         })
         .collect()
@@ -568,6 +758,71 @@ pub fn parse_hover(result: &Value) -> Option<super::types::HoverInfo> {
     })
 }
 
+/// Parse signature help from response, keeping only the active signature
+pub fn parse_signature_help(result: &Value) -> Option<super::types::SignatureHelp> {
+    let signatures = result.get("signatures")?.as_array()?;
+    let active = result.get("activeSignature").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    let signature = signatures.get(active).or(signatures.first())?;
+    let label = signature.get("label")?.as_str()?.to_string();
+
+    // A signature's own activeParameter takes precedence over the result's
+    let active_parameter = signature
+        .get("activeParameter")
+        .or(result.get("activeParameter"))
+        .and_then(|v| v.as_u64())
+        .and_then(|i| signature.get("parameters")?.as_array()?.get(i as usize))
+        .and_then(|param| match param.get("label")? {
+            // Offsets count UTF-16 code units
+            Value::Array(offsets) => {
+                let start = utf16_to_char(&label, offsets.first()?.as_u64()? as usize);
+                let end = utf16_to_char(&label, offsets.get(1)?.as_u64()? as usize);
+                Some((start, end))
+            }
+            Value::String(text) => {
+                let byte = label.find(text.as_str())?;
+                let start = label[..byte].chars().count();
+                Some((start, start + text.chars().count()))
+            }
+            _ => None,
+        });
+
+    let documentation = signature.get("documentation").and_then(|v| {
+        if let Some(s) = v.as_str() {
+            Some(s.to_string())
+        } else {
+            v.get("value").and_then(|v| v.as_str()).map(String::from)
+        }
+    });
+
+    Some(super::types::SignatureHelp { label, active_parameter, documentation })
+}
+
+/// Character index in `text` of the UTF-16 offset `offset`
+fn utf16_to_char(text: &str, offset: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.chars().enumerate() {
+        if units >= offset {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.chars().count()
+}
+
+/// Parse a prepareRename response; None when the position can't be renamed
+pub fn parse_prepare_rename(result: &Value) -> Option<super::types::PrepareRename> {
+    use super::types::PrepareRename;
+
+    if result.get("defaultBehavior").and_then(|v| v.as_bool()) == Some(true) {
+        return Some(PrepareRename::DefaultBehavior);
+    }
+    if let Some(range) = result.get("range").and_then(parse_range) {
+        let placeholder = result.get("placeholder").and_then(|v| v.as_str()).map(String::from);
+        return Some(PrepareRename::Range { range, placeholder });
+    }
+    parse_range(result).map(|range| PrepareRename::Range { range, placeholder: None })
+}
+
 /// Parse locations from definition/references response
 pub fn parse_locations(result: &Value) -> Vec<super::types::Location> {
     if let Some(loc) = parse_location(result) {
@@ -675,52 +930,131 @@ pub fn parse_text_edits(result: &Value) -> Vec<super::types::TextEdit> {
         .unwrap_or_default()
 }
 
-/// Parse workspace edit from rename response
+/// Parse workspace edit (rename, code actions, workspace/applyEdit)
 pub fn parse_workspace_edit(result: &Value) -> super::types::WorkspaceEdit {
+    use super::types::DocumentChange;
+
     let mut edit = super::types::WorkspaceEdit::default();
 
     if let Some(changes) = result.get("changes").and_then(|v| v.as_object()) {
         for (uri, edits) in changes {
-            let text_edits = edits
-                .as_array()
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|e| {
-                            Some(super::types::TextEdit {
-                                range: parse_range(e.get("range")?)?,
-                                new_text: e.get("newText")?.as_str()?.to_string(),
-                            })
-                        })
-                        .collect()
-                })
-                .unwrap_or_default();
-            edit.changes.insert(uri.clone(), text_edits);
+            edit.changes.insert(uri.clone(), parse_text_edits(edits));
         }
     }
 
-    // Handle documentChanges format
+    // Handle documentChanges format (text edits interleaved with resource operations)
     if let Some(doc_changes) = result.get("documentChanges").and_then(|v| v.as_array()) {
         for change in doc_changes {
-            if let Some(text_doc) = change.get("textDocument") {
-                let uri = text_doc.get("uri").and_then(|v| v.as_str()).unwrap_or("");
-                let text_edits = change
-                    .get("edits")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| {
-                        arr.iter()
-                            .filter_map(|e| {
-                                Some(super::types::TextEdit {
-                                    range: parse_range(e.get("range")?)?,
-                                    new_text: e.get("newText")?.as_str()?.to_string(),
-                                })
-                            })
-                            .collect()
-                    })
-                    .unwrap_or_default();
-                edit.changes.insert(uri.to_string(), text_edits);
+            let str_field = |key: &str| change.get(key).and_then(|v| v.as_str()).map(String::from);
+            let option = |key: &str| {
+                change
+                    .get("options")
+                    .and_then(|o| o.get(key))
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+            };
+
+            let parsed = if let Some(text_doc) = change.get("textDocument") {
+                text_doc.get("uri").and_then(|v| v.as_str()).map(|uri| DocumentChange::Edit {
+                    uri: uri.to_string(),
+                    version: text_doc.get("version").and_then(|v| v.as_i64()).map(|v| v as i32),
+                    edits: change.get("edits").map(parse_text_edits).unwrap_or_default(),
+                })
+            } else {
+                match change.get("kind").and_then(|v| v.as_str()) {
+                    Some("create") => str_field("uri").map(|uri| DocumentChange::Create {
+                        uri,
+                        overwrite: option("overwrite"),
+                        ignore_if_exists: option("ignoreIfExists"),
+                    }),
+                    Some("rename") => match (str_field("oldUri"), str_field("newUri")) {
+                        (Some(old_uri), Some(new_uri)) => Some(DocumentChange::Rename {
+                            old_uri,
+                            new_uri,
+                            overwrite: option("overwrite"),
+                            ignore_if_exists: option("ignoreIfExists"),
+                        }),
+                        _ => None,
+                    },
+                    Some("delete") => str_field("uri").map(|uri| DocumentChange::Delete {
+                        uri,
+                        recursive: option("recursive"),
+                        ignore_if_not_exists: option("ignoreIfNotExists"),
+                    }),
+                    _ => None,
+                }
+            };
+
+            if let Some(parsed) = parsed {
+                edit.document_changes.push(parsed);
             }
         }
     }
 
     edit
 }
+
+/// Parse a Command object
+pub fn parse_command(value: &Value) -> Option<Command> {
+    Some(Command {
+        title: value.get("title").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        command: value.get("command")?.as_str()?.to_string(),
+        arguments: value
+            .get("arguments")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default(),
+    })
+}
+
+/// Parse a single code lens
+pub fn parse_code_lens(value: &Value) -> Option<CodeLens> {
+    Some(CodeLens {
+        range: parse_range(value.get("range")?)?,
+        command: value.get("command").and_then(parse_command),
+        data: value.get("data").cloned(),
+    })
+}
+
+/// Parse code lenses from response
+pub fn parse_code_lenses(result: &Value) -> Vec<CodeLens> {
+    result
+        .as_array()
+        .map(|arr| arr.iter().filter_map(parse_code_lens).collect())
+        .unwrap_or_default()
+}
+
+/// Parse a single document link
+pub fn parse_document_link(value: &Value) -> Option<DocumentLink> {
+    Some(DocumentLink {
+        range: parse_range(value.get("range")?)?,
+        target: value.get("target").and_then(|v| v.as_str()).map(String::from),
+        tooltip: value.get("tooltip").and_then(|v| v.as_str()).map(String::from),
+        data: value.get("data").cloned(),
+    })
+}
+
+/// Parse document links from response
+pub fn parse_document_links(result: &Value) -> Vec<DocumentLink> {
+    result
+        .as_array()
+        .map(|arr| arr.iter().filter_map(parse_document_link).collect())
+        .unwrap_or_default()
+}
+
+/// Parse folding ranges from response as (start_line, end_line), skipping
+/// ranges that don't span more than one line
+pub fn parse_folding_ranges(result: &Value) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = result
+        .as_array()
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|r| Some((r.get("startLine")?.as_u64()? as usize, r.get("endLine")?.as_u64()? as usize)))
+                .filter(|(start, end)| end > start)
+                .collect()
+        })
+        .unwrap_or_default();
+    ranges.sort_unstable();
+    ranges.dedup();
+    ranges
+}
@@ -9,6 +9,10 @@ use std::collections::HashSet;
 use std::process::Command;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
+use std::time::Duration;
+
+use super::manager::{ServerState, ServerStatus};
+use crate::util::shell::shell_command;
 
 /// Result of an install operation
 pub struct InstallResult {
@@ -505,9 +509,7 @@ pub fn run_install_command(cmd: &str) -> Result<String, String> {
         return Err("Manual installation required. See instructions.".to_string());
     }
 
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(cmd)
+    let output = shell_command(cmd)
         .output()
         .map_err(|e| format!("Failed to run command: {}", e))?;
 
@@ -519,6 +521,16 @@ pub fn run_install_command(cmd: &str) -> Result<String, String> {
     }
 }
 
+/// Short form of how long a server has run: `45s`, `12m`, `3h07m`
+fn uptime(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
 /// LSP Server Manager Panel state
 pub struct ServerManagerPanel {
     pub visible: bool,
@@ -535,6 +547,8 @@ pub struct ServerManagerPanel {
     pub manual_info_index: usize,
     /// Text that was copied to clipboard (for status message)
     pub copied_to_clipboard: bool,
+    /// Servers the workspace is running, refreshed while the panel shows
+    pub running: Vec<ServerStatus>,
     /// Channel to receive install completion results
     install_rx: Option<Receiver<InstallResult>>,
     install_tx: Option<Sender<InstallResult>>,
@@ -561,6 +575,7 @@ impl ServerManagerPanel {
             manual_info_mode: false,
             manual_info_index: 0,
             copied_to_clipboard: false,
+            running: Vec::new(),
             install_rx: Some(rx),
             install_tx: Some(tx),
         }
@@ -571,6 +586,23 @@ impl ServerManagerPanel {
         self.installing_indices.contains(&index)
     }
 
+    /// State of a known server if the workspace is running it: "running"
+    /// with its uptime and memory, "starting" or "stopping"
+    pub fn running_state(&self, server: &KnownServer) -> Option<String> {
+        let status = self.running.iter().find(|s| s.command == server.check_cmd)?;
+        Some(match status.state {
+            ServerState::Ready => {
+                let mut text = format!("running {}", uptime(status.started.elapsed()));
+                if let Some(mb) = status.memory_mb {
+                    text.push_str(&format!(" {}MB", mb));
+                }
+                text
+            }
+            ServerState::Starting | ServerState::Initializing => "starting".to_string(),
+            ServerState::ShuttingDown | ServerState::Stopped => "stopping".to_string(),
+        })
+    }
+
     /// Check if any installs are in progress
     pub fn has_active_installs(&self) -> bool {
         !self.installing_indices.is_empty()
@@ -750,6 +782,18 @@ impl ServerManagerPanel {
         });
     }
 
+    /// Install a known server by name without asking, e.g. one picked in
+    /// the setup wizard
+    pub fn install_named(&mut self, name: &str) {
+        if self.servers.is_empty() {
+            self.refresh();
+        }
+        if let Some(index) = self.servers.iter().position(|s| s.name == name && !s.is_installed) {
+            self.confirm_index = index;
+            self.start_install();
+        }
+    }
+
     pub fn selected_server(&self) -> Option<&KnownServer> {
         self.servers.get(self.selected_index)
     }
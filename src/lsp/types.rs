@@ -8,6 +8,8 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+use crate::util::path::{file_url_path, file_url_to_path};
+
 /// Position in a document (0-based line and character)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Position {
@@ -67,9 +69,45 @@ pub struct TextEdit {
 }
 
 /// Workspace edit (multiple file edits)
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct WorkspaceEdit {
+    /// Unversioned edits keyed by URI (the `changes` format)
     pub changes: HashMap<String, Vec<TextEdit>>,
+    /// Ordered edits and resource operations (the `documentChanges` format)
+    pub document_changes: Vec<DocumentChange>,
+}
+
+impl WorkspaceEdit {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.document_changes.is_empty()
+    }
+}
+
+/// One entry of a `documentChanges` workspace edit
+#[derive(Debug, Clone, PartialEq)]
+pub enum DocumentChange {
+    /// Text edits against a specific document version (None = any version)
+    Edit {
+        uri: String,
+        version: Option<i32>,
+        edits: Vec<TextEdit>,
+    },
+    Create {
+        uri: String,
+        overwrite: bool,
+        ignore_if_exists: bool,
+    },
+    Rename {
+        old_uri: String,
+        new_uri: String,
+        overwrite: bool,
+        ignore_if_exists: bool,
+    },
+    Delete {
+        uri: String,
+        recursive: bool,
+        ignore_if_not_exists: bool,
+    },
 }
 
 /// Diagnostic severity levels
@@ -94,7 +132,7 @@ impl DiagnosticSeverity {
 }
 
 /// A diagnostic message from the language server
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Diagnostic {
     pub range: Range,
     pub severity: Option<DiagnosticSeverity>,
@@ -204,9 +242,31 @@ pub struct CompletionItem {
     pub detail: Option<String>,
     pub documentation: Option<String>,
     pub insert_text: Option<String>,
+    /// `insert_text` / `text_edit` use snippet syntax (`$1`, `${2:name}`)
+    pub is_snippet: bool,
     pub text_edit: Option<TextEdit>,
     pub sort_text: Option<String>,
     pub filter_text: Option<String>,
+    /// Extra edits applied on accept (e.g. an auto-import `use` line)
+    pub additional_text_edits: Vec<TextEdit>,
+    /// The item as the server sent it, echoed back for completionItem/resolve
+    pub raw: Option<serde_json::Value>,
+}
+
+impl CompletionItem {
+    /// Whether accepting this item adds an import (eagerly or via resolve)
+    pub fn needs_import(&self) -> bool {
+        if !self.additional_text_edits.is_empty() {
+            return true;
+        }
+        // rust-analyzer lists pending imports in the opaque data field
+        self.raw
+            .as_ref()
+            .and_then(|r| r.get("data"))
+            .and_then(|d| d.get("imports"))
+            .and_then(|i| i.as_array())
+            .is_some_and(|i| !i.is_empty())
+    }
 }
 
 /// Symbol kind (for document/workspace symbols)
@@ -322,6 +382,54 @@ pub struct HoverInfo {
     pub range: Option<Range>,
 }
 
+/// Signature help: the signature of the call the cursor is in
+#[derive(Debug, Clone)]
+pub struct SignatureHelp {
+    /// The whole signature, e.g. `fn push(&mut self, value: T)`
+    pub label: String,
+    /// Character range of the active parameter within `label`
+    pub active_parameter: Option<(usize, usize)>,
+    pub documentation: Option<String>,
+}
+
+/// Answer to `textDocument/prepareRename` at a valid position
+#[derive(Debug, Clone)]
+pub enum PrepareRename {
+    /// The symbol's exact range, with the text to show for it
+    Range { range: Range, placeholder: Option<String> },
+    /// The server leaves finding the symbol to the editor
+    DefaultBehavior,
+}
+
+/// A command reference attached to a code lens or code action
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub title: String,
+    pub command: String,
+    pub arguments: Vec<serde_json::Value>,
+}
+
+/// A code lens: an actionable annotation attached to a range (e.g. "Run Test")
+#[derive(Debug, Clone)]
+pub struct CodeLens {
+    pub range: Range,
+    /// Missing until the lens is resolved with codeLens/resolve
+    pub command: Option<Command>,
+    /// Opaque server data echoed back on resolve
+    pub data: Option<serde_json::Value>,
+}
+
+/// A link inside a document (import path, URL in a comment, ...)
+#[derive(Debug, Clone)]
+pub struct DocumentLink {
+    pub range: Range,
+    /// Missing until the link is resolved with documentLink/resolve
+    pub target: Option<String>,
+    pub tooltip: Option<String>,
+    /// Opaque server data echoed back on resolve
+    pub data: Option<serde_json::Value>,
+}
+
 /// Server capabilities
 #[derive(Debug, Clone, Default)]
 pub struct Capabilities {
@@ -330,12 +438,21 @@ pub struct Capabilities {
     pub definition: bool,
     pub references: bool,
     pub rename: bool,
+    /// Checks a rename position and names the symbol (`textDocument/prepareRename`)
+    pub prepare_rename: bool,
     pub code_actions: bool,
     pub formatting: bool,
     pub diagnostics: bool,
     pub document_symbols: bool,
     pub workspace_symbols: bool,
     pub signature_help: bool,
+    pub code_lens: bool,
+    pub execute_command: bool,
+    pub document_link: bool,
+    pub folding_range: bool,
+    /// Takes didChange as ranged edits rather than the whole text
+    /// (`textDocumentSync` change kind 2)
+    pub incremental_sync: bool,
 }
 
 impl Capabilities {
@@ -346,12 +463,18 @@ impl Capabilities {
             definition: true,
             references: true,
             rename: true,
+            prepare_rename: true,
             code_actions: true,
             formatting: true,
             diagnostics: true,
             document_symbols: true,
             workspace_symbols: true,
             signature_help: true,
+            code_lens: true,
+            execute_command: true,
+            document_link: true,
+            folding_range: true,
+            incremental_sync: true,
         }
     }
 }
@@ -457,18 +580,13 @@ pub fn detect_language(path: &str) -> Option<&'static str> {
 
 /// Convert file path to LSP URI
 pub fn path_to_uri(path: &str) -> String {
-    if path.starts_with('/') {
-        format!("file://{}", path)
-    } else {
-        format!("file:///{}", path)
-    }
+    format!("file://{}", file_url_path(path))
 }
 
 /// Convert LSP URI to file path
 pub fn uri_to_path(uri: &str) -> Option<String> {
-    if uri.starts_with("file://") {
-        Some(uri[7..].to_string())
-    } else {
-        None
-    }
+    let rest = uri.strip_prefix("file://")?;
+    // Skip the authority (usually empty, sometimes "localhost")
+    let path = &rest[rest.find('/')?..];
+    Some(file_url_to_path(path))
 }
@@ -1,6 +1,8 @@
 mod buffer;
+mod config;
 mod editor;
 mod fuss;
+mod git;
 mod input;
 mod lsp;
 mod render;
@@ -10,19 +12,105 @@ mod util;
 mod workspace;
 
 use anyhow::Result;
-use editor::{Editor, WelcomeMenu};
+use config::Settings;
+use editor::{Editor, SetupWizard, WelcomeMenu};
 use render::Screen;
 use std::env;
-use workspace::recents_add_or_update;
+use std::path::PathBuf;
+use util::completions::completion_script;
+use util::logging;
+use util::status;
+use workspace::{recents_add_or_update, recents_get};
+
+/// Command-line options besides the file/directory argument
+#[derive(Default)]
+struct Options {
+    /// `--record <file>`: log every input event to a file
+    record: Option<PathBuf>,
+    /// `--replay <file>`: feed a recorded session back as input
+    replay: Option<PathBuf>,
+    /// `--last`: reopen the most recent workspace instead of the welcome menu
+    last: bool,
+}
+
+impl Options {
+    /// Start recording/replay on a freshly created editor
+    fn apply(&self, editor: &mut Editor) -> Result<()> {
+        if let Some(path) = &self.record {
+            editor.start_recording(path)?;
+        }
+        if let Some(path) = &self.replay {
+            editor.start_replay(path)?;
+        }
+        Ok(())
+    }
+}
 
 fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let filename = args.get(1).map(|s| s.as_str());
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" | "--replay" => {
+                let value = args.next()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| anyhow::anyhow!("{} requires a file argument", arg))?;
+                if arg == "--record" {
+                    options.record = Some(value);
+                } else {
+                    options.replay = Some(value);
+                }
+            }
+            "--list-recents" => {
+                // One path per line, most recent first (for fzf and friends)
+                for recent in recents_get() {
+                    println!("{}", recent.path.display());
+                }
+                return Ok(());
+            }
+            "--last" => options.last = true,
+            #[cfg(feature = "harness")]
+            "--script" => {
+                // Smoke test: drive an offscreen editor from a script
+                let path = args.next().ok_or_else(|| anyhow::anyhow!("--script requires a file argument"))?;
+                if let Err(e) = editor::run_script(std::path::Path::new(&path)) {
+                    eprintln!("{}: {:#}", path, e);
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            "--status" => {
+                // For shell prompts: exit status 0 when something is unsaved
+                std::process::exit(if status::print_status() { 0 } else { 1 });
+            }
+            "completions" if positional.is_empty() => {
+                let shell = args.next()
+                    .ok_or_else(|| anyhow::anyhow!("completions requires a shell: bash, zsh or fish"))?;
+                let script = completion_script(&shell)
+                    .ok_or_else(|| anyhow::anyhow!("unsupported shell '{}': expected bash, zsh or fish", shell))?;
+                print!("{}", script);
+                return Ok(());
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    if options.last && positional.is_empty() {
+        let recent = recents_get().into_iter().next()
+            .ok_or_else(|| anyhow::anyhow!("no recent workspaces"))?;
+        positional.push(recent.path.to_string_lossy().into_owned());
+    }
+    let filename = positional.first().map(|s| s.as_str());
+
+    // Events go to the workspace's .fackr/logs/fackr.log once one is open
+    logging::init();
 
     if let Some(path) = filename {
         // File/directory provided - open directly
         let mut editor = Editor::new()?;
         editor.open(path)?;
+        options.apply(&mut editor)?;
 
         // Track this workspace in recents
         let _ = recents_add_or_update(&editor.workspace_root());
@@ -33,6 +121,15 @@ fn main() -> Result<()> {
         let mut screen = Screen::new()?;
         screen.enter_raw_mode()?;
 
+        // First launch: set up the per-user config before picking a workspace
+        let mut servers = Vec::new();
+        if !Settings::path().exists() {
+            let setup = SetupWizard::run(&mut screen)?;
+            let _ = setup.settings.save();
+            screen.set_mouse_capture(setup.settings.mouse)?;
+            servers = setup.servers;
+        }
+
         match WelcomeMenu::run(&mut screen)? {
             Some(workspace_path) => {
                 // Track this workspace in recents
@@ -40,6 +137,8 @@ fn main() -> Result<()> {
 
                 // Create editor with selected workspace, reusing the screen
                 let mut editor = Editor::new_with_screen_and_workspace(screen, workspace_path)?;
+                options.apply(&mut editor)?;
+                editor.install_servers(&servers);
                 editor.run()
             }
             None => {
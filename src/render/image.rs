@@ -0,0 +1,283 @@
+//! Inline image previews
+//!
+//! Draws PNG/JPEG files with the kitty, iTerm2 or sixel graphics protocol.
+//! Terminals without one (and tmux, which needs passthrough) get a text
+//! summary of the image instead.
+
+use anyhow::{Context, Result};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader};
+use std::io::Cursor;
+use std::path::Path;
+
+/// Fallback cell size in pixels when the terminal doesn't report one
+const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+
+/// Base64 payload bytes per kitty escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+/// Terminal graphics protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    Kitty,
+    Iterm2,
+    Sixel,
+}
+
+impl GraphicsProtocol {
+    /// Protocol for the `image_protocol` setting: "kitty", "iterm2", "sixel",
+    /// "none", or anything else to detect it from the environment
+    pub fn from_setting(setting: &str) -> Option<Self> {
+        match setting {
+            "kitty" => Some(Self::Kitty),
+            "iterm2" => Some(Self::Iterm2),
+            "sixel" => Some(Self::Sixel),
+            "none" => None,
+            _ => Self::detect(),
+        }
+    }
+
+    /// Guess the protocol from TERM / TERM_PROGRAM
+    fn detect() -> Option<Self> {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        if std::env::var_os("TMUX").is_some() {
+            return None;
+        }
+        let term = var("TERM");
+        let program = var("TERM_PROGRAM");
+        if term.contains("kitty")
+            || std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || matches!(program.as_str(), "WezTerm" | "ghostty")
+        {
+            Some(Self::Kitty)
+        } else if program == "iTerm.app" {
+            Some(Self::Iterm2)
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Some(Self::Sixel)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `path` looks like an image we can preview
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| matches!(e.to_ascii_lowercase().as_str(), "png" | "jpg" | "jpeg"))
+        .unwrap_or(false)
+}
+
+/// Text shown above (or instead of) the image: name, format, size
+pub fn describe(path: &Path) -> Result<String> {
+    let bytes = std::fs::metadata(path)?.len();
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+    let format = match reader.format() {
+        Some(ImageFormat::Png) => "PNG",
+        Some(ImageFormat::Jpeg) => "JPEG",
+        _ => "Unknown",
+    };
+    let (width, height) = reader.into_dimensions().context("Unreadable image")?;
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    Ok(format!(
+        "{}\n{} image, {} x {} pixels, {}\n",
+        name,
+        format,
+        width,
+        height,
+        format_bytes(bytes)
+    ))
+}
+
+/// Escape sequence drawing the image at the cursor, scaled to fit within
+/// `cols` x `rows` cells without upscaling
+pub fn encode(path: &Path, protocol: GraphicsProtocol, cols: u16, rows: u16) -> Result<String> {
+    let data = std::fs::read(path)?;
+    let image = image::load_from_memory(&data).context("Unreadable image")?;
+    let (cell_w, cell_h) = cell_size();
+    let (fit_w, fit_h) = fit(
+        (image.width(), image.height()),
+        (cols as u32 * cell_w, rows as u32 * cell_h),
+    );
+    let cells = (fit_w.div_ceil(cell_w).max(1), fit_h.div_ceil(cell_h).max(1));
+
+    Ok(match protocol {
+        GraphicsProtocol::Kitty => {
+            // Kitty only takes PNG (or raw pixels), so JPEGs are re-encoded
+            let png = if data.starts_with(b"\x89PNG") {
+                data
+            } else {
+                let mut png = Vec::new();
+                image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+                png
+            };
+            kitty(&png, cells)
+        }
+        GraphicsProtocol::Iterm2 => format!(
+            "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+            data.len(),
+            cells.0,
+            cells.1,
+            base64(&data)
+        ),
+        GraphicsProtocol::Sixel => sixel(&image.resize(fit_w, fit_h, FilterType::Triangle)),
+    })
+}
+
+/// Remove every image kitty has placed on screen
+pub fn kitty_clear() -> &'static str {
+    "\x1b_Ga=d,d=A,q=2\x1b\\"
+}
+
+/// Pixel size of one terminal cell
+fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            (size.width / size.columns).max(1) as u32,
+            (size.height / size.rows).max(1) as u32,
+        ),
+        _ => DEFAULT_CELL_SIZE,
+    }
+}
+
+/// Largest size with the image's aspect ratio that fits `bounds`, never larger than the image
+fn fit((width, height): (u32, u32), (max_w, max_h): (u32, u32)) -> (u32, u32) {
+    let scale = (max_w as f64 / width.max(1) as f64)
+        .min(max_h as f64 / height.max(1) as f64)
+        .min(1.0);
+    (
+        ((width as f64 * scale) as u32).max(1),
+        ((height as f64 * scale) as u32).max(1),
+    )
+}
+
+/// Kitty graphics command transmitting and displaying a PNG over `cells`
+fn kitty(png: &[u8], (cols, rows): (u32, u32)) -> String {
+    let payload = base64(png);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::with_capacity(payload.len() + chunks.len() * 32);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            out.push_str(&format!("\x1b_Ga=T,f=100,c={},r={},C=1,q=2,m={};", cols, rows, more));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};", more));
+        }
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\x1b\\");
+    }
+    out
+}
+
+/// Sixel encoding using a 6x6x6 color cube
+fn sixel(image: &DynamicImage) -> String {
+    let rgb = image.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let level = |c: u8| (c as u32 * 5 + 127) / 255;
+    let index: Vec<u8> = rgb
+        .pixels()
+        .map(|p| (level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])) as u8)
+        .collect();
+
+    let mut out = format!("\x1bP0;1q\"1;1;{};{}", width, height);
+    for i in 0..216u32 {
+        let percent = |v: u32| v * 100 / 5;
+        out.push_str(&format!("#{};2;{};{};{}", i, percent(i / 36), percent(i / 6 % 6), percent(i % 6)));
+    }
+
+    for band in (0..height).step_by(6) {
+        let band_rows = (height - band).min(6);
+        let mut used = [false; 216];
+        for y in band..band + band_rows {
+            for x in 0..width {
+                used[index[(y * width + x) as usize] as usize] = true;
+            }
+        }
+        for color in (0..216).filter(|&c| used[c]) {
+            out.push_str(&format!("#{}", color));
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let mut bits = 0u8;
+                for dy in 0..band_rows {
+                    if index[((band + dy) * width + x) as usize] as usize == color {
+                        bits |= 1 << dy;
+                    }
+                }
+                let ch = (63 + bits) as char;
+                run = match run {
+                    Some((c, n)) if c == ch => Some((c, n + 1)),
+                    Some((c, n)) => {
+                        push_run(&mut out, c, n);
+                        Some((ch, 1))
+                    }
+                    None => Some((ch, 1)),
+                };
+            }
+            if let Some((c, n)) = run {
+                push_run(&mut out, c, n);
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Append a sixel run, using `!n` repeats for longer ones
+fn push_run(out: &mut String, ch: char, count: usize) {
+    if count > 3 {
+        out.push_str(&format!("!{}{}", count, ch));
+    } else {
+        out.extend(std::iter::repeat_n(ch, count));
+    }
+}
+
+/// Standard base64 with padding
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Size for people, e.g. "2.5 MB"
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_fit_keeps_aspect_and_never_upscales() {
+        assert_eq!(fit((1600, 800), (400, 400)), (400, 200));
+        assert_eq!(fit((100, 50), (400, 400)), (100, 50));
+    }
+}
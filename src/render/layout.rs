@@ -0,0 +1,159 @@
+//! Panel placement
+//!
+//! The `layout` section of the workspace config names a place for each
+//! panel. `Layout::compute` turns it into screen rectangles once per frame,
+//! and rendering and mouse handling read those rather than assuming the
+//! explorer on the left and the terminal at the bottom.
+
+use serde::{Deserialize, Serialize};
+
+/// Rows a docked panel takes at most
+const DOCK_MAX_ROWS: u16 = 14;
+
+/// Side of the screen the explorer docks on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    #[default]
+    Left,
+    Right,
+}
+
+/// Edge of the screen the terminal docks to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    #[default]
+    Bottom,
+    Right,
+}
+
+/// How a panel shows: centered over the text, or along its bottom
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Placement {
+    #[default]
+    Modal,
+    Docked,
+}
+
+/// Where each panel goes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// File explorer: `left` or `right`
+    pub explorer: Side,
+    /// Terminal panel: `bottom` or `right`
+    pub terminal: Edge,
+    /// Language server manager: `modal` or `docked`
+    pub server_manager: Placement,
+    /// Workspace diagnostics list: `modal` or `docked`
+    pub problems: Placement,
+    /// Hide the explorer and the terminal when focus leaves them, unless
+    /// pinned; they come back on their key or with the pointer at their
+    /// edge of the screen
+    pub auto_hide: bool,
+}
+
+/// A screen area in columns and rows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    /// Column just past the right edge
+    pub fn right(&self) -> u16 {
+        self.x + self.width
+    }
+
+    pub fn contains(&self, col: u16, row: u16) -> bool {
+        col >= self.x && col < self.right() && row >= self.y && row < self.y + self.height
+    }
+}
+
+/// The panels' rectangles for one frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Layout {
+    /// Tab bar, text and status bar: every row, minus the explorer's columns
+    pub editor: Rect,
+    /// The explorer, above the status bar (None while hidden)
+    pub explorer: Option<Rect>,
+    /// The terminal, drawn over the text (None while hidden)
+    pub terminal: Option<Rect>,
+    /// Docked panels, along the bottom of the text above the status bar
+    /// and a bottom terminal
+    pub dock: Rect,
+}
+
+impl Layout {
+    /// Rectangles on a `cols` by `rows` screen. `explorer_width` is 0 while
+    /// the explorer is hidden; `terminal_size` is the terminal's rows when
+    /// docked at the bottom or its columns when docked right, None while
+    /// hidden.
+    pub fn compute(config: &LayoutConfig, cols: u16, rows: u16, explorer_width: u16, terminal_size: Option<u16>) -> Self {
+        let explorer_width = explorer_width.min(cols);
+        let (explorer_x, editor_x) = match config.explorer {
+            Side::Left => (0, explorer_width),
+            Side::Right => (cols - explorer_width, 0),
+        };
+        let explorer = (explorer_width > 0).then_some(Rect {
+            x: explorer_x,
+            y: 0,
+            width: explorer_width,
+            height: rows.saturating_sub(1),
+        });
+        let editor = Rect { x: editor_x, y: 0, width: cols - explorer_width, height: rows };
+
+        let terminal = terminal_size.map(|size| match config.terminal {
+            Edge::Bottom => {
+                let height = size.min(rows);
+                Rect { x: editor.x, y: rows - height, width: editor.width, height }
+            }
+            Edge::Right => {
+                let width = size.min(editor.width);
+                Rect { x: editor.right() - width, y: 0, width, height: rows }
+            }
+        });
+
+        let dock_bottom = match terminal {
+            Some(terminal) if config.terminal == Edge::Bottom => terminal.y,
+            _ => rows.saturating_sub(1),
+        };
+        let dock_height = (dock_bottom / 2).min(DOCK_MAX_ROWS);
+        let dock = Rect { x: editor.x, y: dock_bottom - dock_height, width: editor.width, height: dock_height };
+
+        Self { editor, explorer, terminal, dock }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_layout() {
+        let layout = Layout::compute(&LayoutConfig::default(), 100, 40, 25, Some(12));
+        assert_eq!(layout.explorer, Some(Rect { x: 0, y: 0, width: 25, height: 39 }));
+        assert_eq!(layout.editor, Rect { x: 25, y: 0, width: 75, height: 40 });
+        assert_eq!(layout.terminal, Some(Rect { x: 25, y: 28, width: 75, height: 12 }));
+        assert_eq!(layout.dock, Rect { x: 25, y: 14, width: 75, height: 14 });
+    }
+
+    #[test]
+    fn test_right_side_layout() {
+        let config = LayoutConfig { explorer: Side::Right, terminal: Edge::Right, ..Default::default() };
+        let layout = Layout::compute(&config, 100, 40, 25, Some(30));
+        assert_eq!(layout.explorer, Some(Rect { x: 75, y: 0, width: 25, height: 39 }));
+        assert_eq!(layout.editor, Rect { x: 0, y: 0, width: 75, height: 40 });
+        assert_eq!(layout.terminal, Some(Rect { x: 45, y: 0, width: 30, height: 40 }));
+        assert_eq!(layout.dock.y + layout.dock.height, 39);
+
+        let hidden = Layout::compute(&config, 100, 40, 0, None);
+        assert_eq!(hidden.explorer, None);
+        assert_eq!(hidden.editor.width, 100);
+    }
+}
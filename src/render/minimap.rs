@@ -0,0 +1,87 @@
+//! Minimap
+//!
+//! A compressed view of the whole buffer in a narrow column at the right of
+//! a pane. Each map row covers two lines, drawn with half blocks, and each
+//! cell four characters of them; a cell is inked when any of those
+//! characters isn't whitespace. A buffer taller than the map scrolls it in
+//! proportion to the viewport. Only panes wide enough to spare the columns
+//! get one.
+
+/// Columns taken from the pane, including a blank one before the map
+pub const WIDTH: usize = 12;
+/// Narrowest pane that gets a minimap
+pub const MIN_PANE_WIDTH: usize = 100;
+/// Characters of a line per map cell
+const CHARS_PER_CELL: usize = 4;
+/// Lines per map row
+pub const LINES_PER_ROW: usize = 2;
+
+/// Columns the minimap takes from a pane `pane_cols` wide
+pub fn width(enabled: bool, pane_cols: usize) -> usize {
+    if enabled && pane_cols >= MIN_PANE_WIDTH { WIDTH } else { 0 }
+}
+
+/// First line shown on a map `height` rows tall, with the viewport showing
+/// `visible` lines from `viewport_line`
+pub fn first_line(line_count: usize, viewport_line: usize, visible: usize, height: usize) -> usize {
+    let capacity = height * LINES_PER_ROW;
+    if line_count <= capacity {
+        return 0;
+    }
+    let max_first = line_count - capacity;
+    let max_viewport = line_count.saturating_sub(visible).max(1);
+    (viewport_line.min(max_viewport) * max_first / max_viewport).min(max_first)
+}
+
+/// First line covered by map row `row`
+pub fn line_at_row(first: usize, row: usize) -> usize {
+    first + row * LINES_PER_ROW
+}
+
+/// Map row for two lines, `cells` wide
+pub fn row_cells(upper: Option<&str>, lower: Option<&str>, cells: usize) -> String {
+    let inked = |line: Option<&str>| -> Vec<bool> {
+        let chars: Vec<char> = line.unwrap_or("").chars().collect();
+        (0..cells)
+            .map(|cell| {
+                chars
+                    .iter()
+                    .skip(cell * CHARS_PER_CELL)
+                    .take(CHARS_PER_CELL)
+                    .any(|c| !c.is_whitespace())
+            })
+            .collect()
+    };
+    inked(upper)
+        .into_iter()
+        .zip(inked(lower))
+        .map(|cell| match cell {
+            (true, true) => '█',
+            (true, false) => '▀',
+            (false, true) => '▄',
+            (false, false) => ' ',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_cells() {
+        assert_eq!(row_cells(Some("fn main() {"), Some("    x"), 4), "▀█▀ ");
+        assert_eq!(row_cells(Some(""), None, 2), "  ");
+    }
+
+    #[test]
+    fn test_scrolls_with_viewport() {
+        assert_eq!(first_line(30, 10, 20, 20), 0);
+        assert_eq!(first_line(1000, 0, 40, 40), 0);
+        assert_eq!(first_line(1000, 960, 40, 40), 920);
+        assert_eq!(first_line(1000, 480, 40, 40), 460);
+        assert_eq!(width(true, 99), 0);
+        assert_eq!(width(true, 120), WIDTH);
+        assert_eq!(width(false, 120), 0);
+    }
+}
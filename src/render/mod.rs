@@ -1,3 +1,11 @@
+pub mod image;
+mod layout;
+pub mod minimap;
 mod screen;
+mod theme;
+pub mod whitespace;
+pub mod wrap;
 
-pub use screen::{PaneBounds, PaneInfo, Screen, TabInfo};
+pub use layout::{Edge, Layout, LayoutConfig, Placement, Rect, Side};
+pub use screen::{LiveGrepView, MessageLevel, PaneBounds, PaneInfo, Screen, TabInfo, VirtualTextStyle};
+pub use theme::{Theme, DEFAULT_THEME};
@@ -1,44 +1,48 @@
 use anyhow::Result;
 use crossterm::{
-    cursor::{Hide, MoveTo, Show},
+    cursor::{Hide, MoveTo, SetCursorStyle, Show},
     event::{
-        DisableMouseCapture, EnableMouseCapture,
+        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
         KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
     },
     execute,
-    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor},
-    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+    style::{
+        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor, SetUnderlineColor,
+    },
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, SetTitle},
 };
-use std::io::{stdout, Stdout, Write};
+use std::io::{self, stdout, Stdout, Write};
+use std::path::{Path, PathBuf};
 use unicode_width::UnicodeWidthStr;
 
+use crate::config::Settings;
 use crate::buffer::Buffer;
 use crate::editor::{Cursors, Position};
 use crate::fuss::VisibleItem;
-use crate::lsp::{CompletionItem, Diagnostic, DiagnosticSeverity, HoverInfo, Location, ServerManagerPanel};
+use crate::lsp::{CodeAction, CompletionItem, Diagnostic, DiagnosticSeverity, HoverInfo, Location, ServerManagerPanel, SignatureHelp};
 use crate::syntax::{Highlighter, Token};
 use crate::terminal::TerminalPanel;
+use crate::util::logging;
+use crate::util::path::file_url_path;
+use crate::util::process::hostname;
+
+use super::image;
+use super::layout::{Layout, LayoutConfig, Rect};
+use super::minimap;
+use super::whitespace::{self, Whitespace};
+use super::theme::{DiagnosticColors, Theme};
+use super::wrap;
 
-// Editor color scheme (256-color palette)
-const BG_COLOR: Color = Color::AnsiValue(234);           // Off-black editor background
-const CURRENT_LINE_BG: Color = Color::AnsiValue(236);    // Slightly lighter for current line
-const LINE_NUM_COLOR: Color = Color::AnsiValue(243);     // Gray for line numbers
-const CURRENT_LINE_NUM_COLOR: Color = Color::Yellow;     // Yellow for active line number
-const BRACKET_MATCH_BG: Color = Color::AnsiValue(240);   // Highlight for matching brackets
-// Secondary cursors use Color::Magenta for visibility
-
-// Tab bar colors
-const TAB_BAR_BG: Color = Color::AnsiValue(235);         // Slightly lighter than editor bg
-const TAB_ACTIVE_BG: Color = Color::AnsiValue(238);      // Active tab background
-const TAB_INACTIVE_FG: Color = Color::AnsiValue(245);    // Inactive tab text
-const TAB_ACTIVE_FG: Color = Color::White;               // Active tab text
-const TAB_MODIFIED_FG: Color = Color::Yellow;            // Modified indicator
+/// Drawn before the name of a tab whose file is outside the workspace
+const ORPHAN_MARKER: &str = "↗";
 
 /// Tab information for rendering
 pub struct TabInfo {
     pub name: String,
     pub is_active: bool,
     pub is_modified: bool,
+    /// The file is outside the workspace root
+    pub is_orphan: bool,
     pub index: usize,
 }
 
@@ -51,6 +55,27 @@ pub struct PaneInfo<'a> {
     pub is_active: bool,
     pub bracket_match: Option<(usize, usize)>,
     pub is_modified: bool,
+    pub wrap: bool,
+    /// Lines that differ from the other side of a comparison, and whether
+    /// this pane shows the older version
+    pub compare: Option<(&'a [std::ops::Range<usize>], bool)>,
+}
+
+/// What the live grep modal shows
+pub struct LiveGrepView<'a> {
+    pub query: &'a str,
+    /// File type filter
+    pub types: &'a str,
+    pub editing_types: bool,
+    pub regex: bool,
+    /// (path, line number, trimmed line)
+    pub results: &'a [(PathBuf, usize, String)],
+    pub selected_index: usize,
+    pub scroll_offset: usize,
+    pub searching: bool,
+    pub invalid: bool,
+    /// Lines of the selected match's file, and its line (0-indexed)
+    pub preview: Option<(&'a [String], usize)>,
 }
 
 /// Normalized pane bounds (0.0 to 1.0)
@@ -62,16 +87,51 @@ pub struct PaneBounds {
     pub y_end: f32,
 }
 
-// Pane colors
-const PANE_SEPARATOR_FG: Color = Color::AnsiValue(240);
-const PANE_ACTIVE_SEPARATOR_FG: Color = Color::AnsiValue(250);
-// Inactive pane uses darker colors
-const INACTIVE_BG_COLOR: Color = Color::AnsiValue(233);        // Darker than active
-const INACTIVE_CURRENT_LINE_BG: Color = Color::AnsiValue(234); // Dimmed current line
-const INACTIVE_LINE_NUM_COLOR: Color = Color::AnsiValue(240);  // Dimmed line numbers
-const INACTIVE_TEXT_COLOR: Color = Color::AnsiValue(245);      // Dimmed text
+/// Kind of virtual text annotation drawn after the end of a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtualTextStyle {
+    /// Error lens diagnostic messages, by severity
+    Error,
+    Warning,
+    Info,
+    Hint,
+    /// Inline git blame for the cursor line
+    Blame,
+}
+
+impl VirtualTextStyle {
+    fn color(self, colors: &DiagnosticColors) -> Color {
+        match self {
+            Self::Blame => colors.code_lens,
+            Self::Error => colors.lens_error,
+            Self::Warning => colors.lens_warning,
+            Self::Info => colors.lens_info,
+            Self::Hint => colors.lens_hint,
+        }
+    }
+}
+
+/// Severity of a status message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageLevel {
+    Info,
+    Error,
+}
+
+/// Text drawn after a fold's header line
+fn fold_marker(hidden: usize) -> String {
+    format!("  ⋯ {} line{}", hidden, if hidden == 1 { "" } else { "s" })
+}
 
 /// Extract the last component of a path for display
+/// `text` cut or padded with spaces to `width` characters
+fn fit_width(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().map(|c| if c == '\t' { ' ' } else { c }).take(width).collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
+
 fn extract_dirname(path: &str) -> String {
     // Handle home directory
     if path == "/" {
@@ -92,73 +152,335 @@ fn extract_dirname(path: &str) -> String {
         .unwrap_or_else(|| path.to_string())
 }
 
+/// The part of a line shown on one screen row: columns `start..end`, with
+/// `end` unbounded on the line's last row
+struct RowSpan {
+    line: usize,
+    start: usize,
+    end: usize,
+}
+
+/// Indent guides, whitespace marks and rulers of a row, in row columns
+#[derive(Default)]
+struct RowMarks {
+    /// Guide columns; on a blank line they go on past its end
+    guides: Vec<usize>,
+    /// Whitespace from this column on is drawn as `·` and `→`
+    marks_from: Option<usize>,
+    /// Columns with the ruler background
+    rulers: Vec<usize>,
+}
+
+impl RowSpan {
+    /// Column on the row of buffer column `col`, if the row shows it
+    fn col(&self, col: usize) -> Option<usize> {
+        (self.start <= col && col < self.end).then(|| col - self.start)
+    }
+
+    /// The row's characters
+    fn text(&self, line: &str) -> String {
+        line.chars().skip(self.start).take(self.end - self.start).collect()
+    }
+
+    /// Selections crossing the row, clipped to it, in row columns
+    fn selections(&self, selections: &[(Position, Position)]) -> Vec<(Position, Position)> {
+        selections
+            .iter()
+            .filter(|(from, to)| from.line <= self.line && self.line <= to.line)
+            .map(|(from, to)| {
+                let from_col = if from.line == self.line { from.col } else { 0 };
+                let to_col = if to.line == self.line { to.col } else { usize::MAX };
+                (
+                    Position::new(self.line, from_col.saturating_sub(self.start)),
+                    Position::new(self.line, to_col.min(self.end).saturating_sub(self.start)),
+                )
+            })
+            .collect()
+    }
+
+    /// Tokens overlapping the row, in row columns
+    fn tokens(&self, tokens: &[Token]) -> Vec<Token> {
+        tokens
+            .iter()
+            .filter(|t| t.end > self.start && t.start < self.end)
+            .map(|t| Token {
+                start: t.start.saturating_sub(self.start),
+                end: t.end.min(self.end) - self.start,
+                token_type: t.token_type,
+            })
+            .collect()
+    }
+}
+
+/// Where a screen draws
+enum Output {
+    Terminal(Stdout),
+    /// Kept in memory, for an offscreen screen
+    #[cfg_attr(not(any(test, feature = "harness")), allow(dead_code))]
+    Memory(Vec<u8>),
+}
+
+impl Output {
+    fn is_memory(&self) -> bool {
+        matches!(self, Output::Memory(_))
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Terminal(stdout) => stdout.write(buf),
+            Output::Memory(bytes) => bytes.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Terminal(stdout) => stdout.flush(),
+            Output::Memory(_) => Ok(()),
+        }
+    }
+}
+
 /// Terminal screen renderer
 pub struct Screen {
-    stdout: Stdout,
+    stdout: Output,
     pub rows: u16,
     pub cols: u16,
     keyboard_enhanced: bool,
+    /// Capture the mouse in raw mode (the per-user `mouse` setting)
+    mouse_capture: bool,
+    /// Hardware cursor is a block (marks the primary when there are several cursors)
+    block_cursor: bool,
+    /// Last-backup note shown after the modified indicator, e.g. "backup 2m ago"
+    pub backup_status: Option<String>,
+    /// Where workspace state is (not) saved, e.g. ".fackr read-only"
+    pub state_note: Option<&'static str>,
+    /// Vim mode and any half-typed command, e.g. "NORMAL 2d" (None when vim is off)
+    pub vim_mode: Option<String>,
+    /// Register a keyboard macro is being recorded into
+    pub macro_recording: Option<char>,
+    /// How the current buffer indents, e.g. "Spaces: 4"
+    pub indent: String,
+    /// Level of the status bar message, for its color
+    pub message_level: MessageLevel,
+    /// Older messages stacked above the status bar, newest first
+    pub stacked_messages: Vec<(String, MessageLevel)>,
+    /// Wrap long lines at the text area's width (single-pane view)
+    pub wrap: bool,
+    /// Draw a minimap at the right of panes wide enough for one
+    pub minimap: bool,
+    /// Columns per indentation level, when indent guides are drawn
+    pub indent_guides: Option<usize>,
+    /// Which whitespace is drawn as marks
+    pub whitespace: Whitespace,
+    /// Buffer columns marked with a dim vertical band
+    pub rulers: Vec<usize>,
+    /// Give the line with the cursor its own background
+    pub highlight_current_line: bool,
+    /// Code lens titles by line, drawn on a row above their line in the
+    /// single-pane view
+    pub code_lenses: Vec<(usize, String)>,
+    /// Line and first column shown on each text row of the last single-pane
+    /// render, for placing overlays and mapping clicks; None for a code lens row
+    text_layout: Vec<Option<(usize, usize)>>,
+    /// Window title last sent to the terminal
+    title: Option<String>,
+    /// Working directory last reported via OSC 7
+    reported_cwd: Option<PathBuf>,
+    /// Colors for everything drawn on screen
+    pub theme: Theme,
+    /// Where the panels are this frame
+    pub layout: Layout,
 }
 
 impl Screen {
     pub fn new() -> Result<Self> {
         let (cols, rows) = terminal::size()?;
-        Ok(Self {
-            stdout: stdout(),
+        let mut screen = Self::with_output(Output::Terminal(stdout()), cols, rows);
+        screen.mouse_capture = Settings::load().mouse;
+        Ok(screen)
+    }
+
+    /// A screen of `cols` x `rows` that draws into memory instead of the
+    /// terminal, for scripted tests (see `editor/harness.rs`)
+    #[cfg(any(test, feature = "harness"))]
+    pub fn offscreen(cols: u16, rows: u16) -> Self {
+        Self::with_output(Output::Memory(Vec::new()), cols, rows)
+    }
+
+    /// Everything drawn since the last call, for an offscreen screen
+    #[cfg(any(test, feature = "harness"))]
+    pub fn take_output(&mut self) -> Vec<u8> {
+        match &mut self.stdout {
+            Output::Memory(bytes) => std::mem::take(bytes),
+            Output::Terminal(_) => Vec::new(),
+        }
+    }
+
+    fn with_output(stdout: Output, cols: u16, rows: u16) -> Self {
+        Self {
+            stdout,
             rows,
             cols,
             keyboard_enhanced: false,
-        })
+            mouse_capture: true,
+            block_cursor: false,
+            backup_status: None,
+            state_note: None,
+            vim_mode: None,
+            macro_recording: None,
+            indent: String::new(),
+            message_level: MessageLevel::Info,
+            stacked_messages: Vec::new(),
+            wrap: false,
+            minimap: false,
+            indent_guides: None,
+            whitespace: Whitespace::None,
+            rulers: Vec::new(),
+            highlight_current_line: true,
+            code_lenses: Vec::new(),
+            text_layout: Vec::new(),
+            title: None,
+            reported_cwd: None,
+            theme: Theme::default(),
+            layout: Layout::compute(&LayoutConfig::default(), cols, rows, 0, None),
+        }
     }
 
     pub fn enter_raw_mode(&mut self) -> Result<()> {
+        if self.stdout.is_memory() {
+            return Ok(());
+        }
         terminal::enable_raw_mode()?;
-        execute!(self.stdout, EnterAlternateScreen, Hide, EnableMouseCapture)?;
-
-        // Try to enable keyboard enhancement for better modifier key detection
-        // This enables the kitty keyboard protocol on supporting terminals.
-        // We use REPORT_ALTERNATE_KEYS so crossterm receives the shifted character
-        // (e.g., 'A' instead of 'a' with shift modifier) for consistent behavior.
+        execute!(self.stdout, EnterAlternateScreen, Hide, EnableBracketedPaste)?;
+        if self.mouse_capture {
+            execute!(self.stdout, EnableMouseCapture)?;
+        }
+        // Save the current window title on the terminal's title stack (xterm)
+        write!(self.stdout, "\x1b[22;0t")?;
+
+        // Opt into the kitty keyboard protocol where the terminal answers the
+        // query for it: chords like Ctrl+Shift+Z, Ctrl+Enter and Ctrl+I (not
+        // Tab) then arrive intact, and Alt comes as a modifier instead of an
+        // Escape prefix. We use REPORT_ALTERNATE_KEYS so crossterm receives the
+        // shifted character (e.g., 'A' instead of 'a' with shift modifier) for
+        // consistent behavior.
         // See: https://github.com/helix-editor/helix/pull/4939
-        if execute!(
-            self.stdout,
-            PushKeyboardEnhancementFlags(
-                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
-                    | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
+        self.keyboard_enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false)
+            && execute!(
+                self.stdout,
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS
+                )
             )
-        )
-        .is_ok()
-        {
-            self.keyboard_enhanced = true;
-        }
+            .is_ok();
 
         Ok(())
     }
 
+    /// Start or stop capturing the mouse
+    pub fn set_mouse_capture(&mut self, capture: bool) -> Result<()> {
+        self.mouse_capture = capture;
+        if self.stdout.is_memory() {
+            return Ok(());
+        }
+        if capture {
+            execute!(self.stdout, EnableMouseCapture)?;
+        } else {
+            execute!(self.stdout, DisableMouseCapture)?;
+        }
+        Ok(())
+    }
+
     pub fn leave_raw_mode(&mut self) -> Result<()> {
+        if self.stdout.is_memory() {
+            return Ok(());
+        }
         if self.keyboard_enhanced {
             let _ = execute!(self.stdout, PopKeyboardEnhancementFlags);
+            self.keyboard_enhanced = false;
         }
-        execute!(self.stdout, Show, DisableMouseCapture, LeaveAlternateScreen)?;
+        execute!(
+            self.stdout,
+            SetCursorStyle::DefaultUserShape,
+            Show,
+            DisableMouseCapture,
+            DisableBracketedPaste,
+            LeaveAlternateScreen
+        )?;
+        self.block_cursor = false;
+        // Restore the window title saved on entry
+        write!(self.stdout, "\x1b[23;0t")?;
+        self.stdout.flush()?;
+        self.title = None;
+        self.reported_cwd = None;
         terminal::disable_raw_mode()?;
         Ok(())
     }
 
+    /// Whether keys arrive through the kitty keyboard protocol, so Escape
+    /// is never an Alt prefix
+    pub fn keyboard_enhanced(&self) -> bool {
+        self.keyboard_enhanced
+    }
+
+    /// Column just past the text area: the screen's edge, or the
+    /// explorer's when it docks on the right
+    fn text_end(&self) -> u16 {
+        self.layout.editor.right().min(self.cols)
+    }
+
     pub fn refresh_size(&mut self) -> Result<()> {
+        if self.stdout.is_memory() {
+            return Ok(());
+        }
         let (cols, rows) = terminal::size()?;
         self.cols = cols;
         self.rows = rows;
         Ok(())
     }
 
+    /// Set the terminal window title (only written when it changes)
+    pub fn set_title(&mut self, title: &str) -> Result<()> {
+        if self.title.as_deref() != Some(title) {
+            execute!(self.stdout, SetTitle(title))?;
+            self.title = Some(title.to_string());
+        }
+        Ok(())
+    }
+
+    /// Report the working directory with OSC 7 so new terminal tabs and
+    /// splits open there (only written when it changes)
+    pub fn set_working_directory(&mut self, dir: &Path) -> Result<()> {
+        if self.reported_cwd.as_deref() != Some(dir) {
+            let url_path = file_url_path(&dir.to_string_lossy());
+            write!(self.stdout, "\x1b]7;file://{}{}\x1b\\", hostname(), url_path)?;
+            self.stdout.flush()?;
+            self.reported_cwd = Some(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Set terminal user variables (OSC 1337 SetUserVar, read by WezTerm and
+    /// iTerm2 for tab titles and status bars)
+    pub fn set_user_vars(&mut self, vars: &[(&str, String)]) -> Result<()> {
+        for (name, value) in vars {
+            write!(self.stdout, "\x1b]1337;SetUserVar={}={}\x07", name, image::base64(value.as_bytes()))?;
+        }
+        self.stdout.flush()?;
+        Ok(())
+    }
+
     /// Position and show the hardware cursor at the given screen coordinates
     pub fn show_cursor_at(&mut self, col: u16, row: u16) -> Result<()> {
+        self.set_block_cursor(self.vim_block_cursor())?;
         execute!(self.stdout, MoveTo(col, row), Show)?;
         self.stdout.flush()?;
         Ok(())
     }
 
-    #[allow(dead_code)]
     pub fn clear(&mut self) -> Result<()> {
         execute!(self.stdout, Clear(ClearType::All))?;
         Ok(())
@@ -170,11 +492,11 @@ impl Screen {
         execute!(self.stdout, MoveTo(left_offset, 0))?;
 
         // Fill the tab bar background
-        let available_width = self.cols.saturating_sub(left_offset) as usize;
+        let available_width = self.text_end().saturating_sub(left_offset) as usize;
         execute!(
             self.stdout,
-            SetBackgroundColor(TAB_BAR_BG),
-            SetForegroundColor(TAB_INACTIVE_FG),
+            SetBackgroundColor(self.theme.ui.tab_bar_bg),
+            SetForegroundColor(self.theme.ui.tab_inactive_fg),
         )?;
 
         // Calculate max width per tab
@@ -195,8 +517,11 @@ impl Screen {
 
             let modified_str = if tab.is_modified { "*" } else { "" };
 
-            // Calculate available space for name
-            let prefix_len = if index_str.is_empty() { 0 } else { index_str.len() + 1 }; // "1 "
+            // Calculate available space for name; files outside the
+            // workspace get a marker before it
+            let orphan_str = if tab.is_orphan { ORPHAN_MARKER } else { "" };
+            let prefix_len = if index_str.is_empty() { 0 } else { index_str.len() + 1 } // "1 "
+                + orphan_str.chars().count();
             let suffix_len = modified_str.len();
             let name_max = max_tab_width.saturating_sub(prefix_len + suffix_len);
 
@@ -209,9 +534,9 @@ impl Screen {
 
             // Set colors based on active state
             let (bg, fg) = if tab.is_active {
-                (TAB_ACTIVE_BG, TAB_ACTIVE_FG)
+                (self.theme.ui.tab_active_bg, self.theme.ui.tab_active_fg)
             } else {
-                (TAB_BAR_BG, TAB_INACTIVE_FG)
+                (self.theme.ui.tab_bar_bg, self.theme.ui.tab_inactive_fg)
             };
 
             execute!(
@@ -224,12 +549,20 @@ impl Screen {
             if !index_str.is_empty() {
                 execute!(
                     self.stdout,
-                    SetForegroundColor(LINE_NUM_COLOR),
+                    SetForegroundColor(self.theme.ui.line_number),
                     Print(&index_str),
                     Print(" "),
                 )?;
             }
 
+            if tab.is_orphan {
+                execute!(
+                    self.stdout,
+                    SetForegroundColor(self.theme.ui.tab_modified_fg),
+                    Print(orphan_str),
+                )?;
+            }
+
             // Print tab name
             execute!(
                 self.stdout,
@@ -241,7 +574,7 @@ impl Screen {
             if tab.is_modified {
                 execute!(
                     self.stdout,
-                    SetForegroundColor(TAB_MODIFIED_FG),
+                    SetForegroundColor(self.theme.ui.tab_modified_fg),
                     Print(modified_str),
                 )?;
             }
@@ -252,8 +585,8 @@ impl Screen {
             if i + 1 < tab_count {
                 execute!(
                     self.stdout,
-                    SetBackgroundColor(TAB_BAR_BG),
-                    SetForegroundColor(LINE_NUM_COLOR),
+                    SetBackgroundColor(self.theme.ui.tab_bar_bg),
+                    SetForegroundColor(self.theme.ui.line_number),
                     Print("│"),
                 )?;
                 current_col += 1;
@@ -263,7 +596,7 @@ impl Screen {
         // Fill the rest of the line
         execute!(
             self.stdout,
-            SetBackgroundColor(TAB_BAR_BG),
+            SetBackgroundColor(self.theme.ui.tab_bar_bg),
             Clear(ClearType::UntilNewLine),
             ResetColor,
         )?;
@@ -282,9 +615,10 @@ impl Screen {
         top_offset: u16,
     ) -> Result<()> {
         execute!(self.stdout, Hide)?;
+        self.text_layout.clear();
 
         // Calculate available screen area
-        let available_width = self.cols.saturating_sub(left_offset) as f32;
+        let available_width = self.text_end().saturating_sub(left_offset) as f32;
         let available_height = self.rows.saturating_sub(2 + top_offset) as f32; // -2 for gap + status bar
 
         // Track where to place the hardware cursor (active pane's primary cursor)
@@ -314,12 +648,12 @@ impl Screen {
             // Draw separator on the left edge if not at left boundary
             if pane.bounds.x_start > 0.01 {
                 let sep_x = pane_x.saturating_sub(1);
-                let sep_color = if pane.is_active { PANE_ACTIVE_SEPARATOR_FG } else { PANE_SEPARATOR_FG };
+                let sep_color = if pane.is_active { self.theme.ui.pane_active_separator } else { self.theme.ui.pane_separator };
                 for row in 0..pane_height {
                     execute!(
                         self.stdout,
                         MoveTo(sep_x, pane_y + row),
-                        SetBackgroundColor(BG_COLOR),
+                        SetBackgroundColor(self.theme.ui.bg),
                         SetForegroundColor(sep_color),
                         Print("│"),
                     )?;
@@ -329,12 +663,12 @@ impl Screen {
             // Draw separator on the top edge if not at top boundary
             if pane.bounds.y_start > 0.01 {
                 let sep_y = pane_y.saturating_sub(1);
-                let sep_color = if pane.is_active { PANE_ACTIVE_SEPARATOR_FG } else { PANE_SEPARATOR_FG };
+                let sep_color = if pane.is_active { self.theme.ui.pane_active_separator } else { self.theme.ui.pane_separator };
                 for col in 0..pane_width {
                     execute!(
                         self.stdout,
                         MoveTo(pane_x + col, sep_y),
-                        SetBackgroundColor(BG_COLOR),
+                        SetBackgroundColor(self.theme.ui.bg),
                         SetForegroundColor(sep_color),
                         Print("─"),
                     )?;
@@ -347,7 +681,7 @@ impl Screen {
         execute!(
             self.stdout,
             MoveTo(left_offset, gap_row),
-            SetBackgroundColor(BG_COLOR),
+            SetBackgroundColor(self.theme.ui.bg),
             Clear(ClearType::UntilNewLine),
             ResetColor
         )?;
@@ -363,7 +697,10 @@ impl Screen {
             )?;
         }
 
-        // Position hardware cursor
+        // Position hardware cursor; with several cursors the primary becomes
+        // a steady block so it stands out from the magenta secondaries
+        let multi_cursor = panes.iter().any(|p| p.is_active && p.cursors.len() > 1);
+        self.set_block_cursor(multi_cursor || self.vim_block_cursor())?;
         if let Some((col, row)) = cursor_screen_pos {
             execute!(self.stdout, MoveTo(col, row), Show)?;
         }
@@ -372,6 +709,21 @@ impl Screen {
         Ok(())
     }
 
+    /// Vim's normal and visual modes draw the cursor as a block
+    fn vim_block_cursor(&self) -> bool {
+        self.vim_mode.as_deref().is_some_and(|mode| !mode.starts_with("INSERT"))
+    }
+
+    /// Switch the hardware cursor between a steady block and the user's default shape
+    fn set_block_cursor(&mut self, block: bool) -> Result<()> {
+        if block != self.block_cursor {
+            let style = if block { SetCursorStyle::SteadyBlock } else { SetCursorStyle::DefaultUserShape };
+            execute!(self.stdout, style)?;
+            self.block_cursor = block;
+        }
+        Ok(())
+    }
+
     /// Render a single pane within its screen bounds
     /// Returns the screen position of the primary cursor if this is the active pane
     fn render_single_pane(
@@ -387,14 +739,15 @@ impl Screen {
         let is_active = pane.is_active;
 
         // Choose colors based on active state
-        let bg_color = if is_active { BG_COLOR } else { INACTIVE_BG_COLOR };
-        let current_line_bg = if is_active { CURRENT_LINE_BG } else { INACTIVE_CURRENT_LINE_BG };
-        let line_num_color = if is_active { LINE_NUM_COLOR } else { INACTIVE_LINE_NUM_COLOR };
-        let current_line_num_color = if is_active { CURRENT_LINE_NUM_COLOR } else { INACTIVE_LINE_NUM_COLOR };
-        let text_color = if is_active { Color::Reset } else { INACTIVE_TEXT_COLOR };
+        let bg_color = if is_active { self.theme.ui.bg } else { self.theme.ui.inactive_bg };
+        let current_line_bg = if is_active { self.theme.ui.current_line_bg } else { self.theme.ui.inactive_current_line_bg };
+        let line_num_color = if is_active { self.theme.ui.line_number } else { self.theme.ui.inactive_line_number };
+        let current_line_num_color = if is_active { self.theme.ui.current_line_number } else { self.theme.ui.inactive_line_number };
+        let text_color = if is_active { self.theme.ui.text } else { self.theme.ui.inactive_text };
 
         let line_num_width = self.line_number_width(buffer.line_count());
-        let text_cols = (width as usize).saturating_sub(line_num_width + 1);
+        let minimap_width = minimap::width(self.minimap, width as usize);
+        let text_cols = (width as usize).saturating_sub(line_num_width + 1 + minimap_width);
 
         let primary = cursors.primary();
 
@@ -419,98 +772,191 @@ impl Screen {
             Vec::new()
         };
 
-        // Draw text area
-        for row in 0..height as usize {
-            let line_idx = pane.viewport_line + row;
+        // Draw text area; a folded block takes one row, and with wrapping on
+        // a long line takes several
+        let mut cursor_pos = None;
+        let mut next_line = pane.viewport_line;
+        let mut row = 0;
+        while row < height as usize {
+            let line_idx = next_line;
             let is_current_line = line_idx == primary.line;
-            execute!(self.stdout, MoveTo(x, y + row as u16))?;
 
-            if line_idx < buffer.line_count() {
-                let line_num_fg = if is_current_line {
-                    current_line_num_color
-                } else {
-                    line_num_color
-                };
-                let line_bg = if is_current_line { current_line_bg } else { bg_color };
+            let line = buffer.line_str(line_idx).filter(|_| line_idx < buffer.line_count());
+            let Some(line) = line else {
+                execute!(
+                    self.stdout,
+                    MoveTo(x, y + row as u16),
+                    SetBackgroundColor(bg_color),
+                    SetForegroundColor(if is_active { self.theme.ui.empty_line } else { self.theme.ui.inactive_line_number }),
+                    Print(format!("{:>width$} ", "~", width = line_num_width)),
+                )?;
+                // Fill rest of line within pane bounds
+                let remaining = width.saturating_sub(line_num_width as u16 + 1);
+                execute!(self.stdout, Print(" ".repeat(remaining as usize)))?;
+                let rulers = self.row_rulers(0);
+                self.draw_rulers(x + line_num_width as u16 + 1, y + row as u16, &rulers, "", 0..text_cols, text_color)?;
+                execute!(self.stdout, ResetColor)?;
+                row += 1;
+                continue;
+            };
+            next_line = buffer.next_visible_line(line_idx);
+            let line_num_fg = if is_current_line {
+                current_line_num_color
+            } else {
+                line_num_color
+            };
+            let changed_bg = pane.compare.and_then(|(changed, is_old)| {
+                let bg = if is_old { self.theme.ui.diff_removed_bg } else { self.theme.ui.diff_added_bg };
+                changed.iter().any(|run| run.contains(&line_idx)).then_some(bg)
+            });
+            let line_bg = match changed_bg {
+                Some(bg) if !(is_active && is_current_line) => bg,
+                _ if is_current_line && self.highlight_current_line => current_line_bg,
+                _ => bg_color,
+            };
+
+            let starts = if pane.wrap { wrap::row_starts(&line, text_cols) } else { vec![0] };
+            for (segment, &start) in starts.iter().enumerate() {
+                if row >= height as usize {
+                    break;
+                }
+                let is_last = segment + 1 == starts.len();
+                let span = RowSpan { line: line_idx, start, end: starts.get(segment + 1).copied().unwrap_or(usize::MAX) };
+                let display_line = span.text(&line);
+                let rulers = self.row_rulers(start);
 
+                // The line number goes on the line's first row only
+                let number = if segment == 0 { (line_idx + 1).to_string() } else { String::new() };
                 execute!(
                     self.stdout,
+                    MoveTo(x, y + row as u16),
                     SetBackgroundColor(line_bg),
                     SetForegroundColor(line_num_fg),
-                    Print(format!("{:>width$} ", line_idx + 1, width = line_num_width)),
+                    Print(format!("{:>width$} ", number, width = line_num_width)),
                 )?;
 
-                if let Some(line) = buffer.line_str(line_idx) {
-                    if is_active {
-                        // Active pane: full highlighting
-                        let bracket_col = pane.bracket_match
-                            .filter(|(bl, _)| *bl == line_idx)
-                            .map(|(_, bc)| bc);
-
-                        let secondary_cursors: Vec<usize> = cursor_positions.iter()
-                            .filter(|(l, _, is_primary)| *l == line_idx && !*is_primary)
-                            .map(|(_, c, _)| *c)
-                            .collect();
-
-                        self.render_line_with_cursors_bounded(
-                            &line,
-                            line_idx,
-                            text_cols,
-                            &selections,
-                            is_current_line,
-                            bracket_col,
-                            &secondary_cursors,
-                        )?;
-                    } else {
-                        // Inactive pane: simple dimmed text
-                        let chars: String = line.chars().take(text_cols).collect();
-                        execute!(
-                            self.stdout,
-                            SetBackgroundColor(line_bg),
-                            SetForegroundColor(text_color),
-                            Print(&chars),
-                        )?;
+                let drawn = if is_active {
+                    // Active pane: full highlighting
+                    let bracket_col = pane.bracket_match
+                        .filter(|(bl, _)| *bl == line_idx)
+                        .and_then(|(_, bc)| span.col(bc));
+
+                    let secondary_cursors: Vec<usize> = cursor_positions.iter()
+                        .filter(|(l, _, is_primary)| *l == line_idx && !*is_primary)
+                        .filter_map(|(_, c, _)| span.col(*c))
+                        .collect();
+
+                    let marks = self.row_marks(buffer, line_idx, &line, start);
+                    let drawn = self.render_line_with_cursors_bounded(
+                        &display_line,
+                        line_idx,
+                        text_cols,
+                        &span.selections(&selections),
+                        is_current_line,
+                        bracket_col,
+                        &secondary_cursors,
+                        &marks,
+                    )?;
+
+                    if is_current_line && (segment == 0 || primary.col >= start) && primary.col < span.end {
+                        cursor_pos = Some((row, primary.col.saturating_sub(start)));
                     }
-                }
+                    drawn
+                } else {
+                    // Inactive pane: simple dimmed text; rulers go over it below
+                    let chars: String = display_line.chars().take(text_cols).collect();
+                    execute!(
+                        self.stdout,
+                        SetBackgroundColor(line_bg),
+                        SetForegroundColor(text_color),
+                        Print(&chars),
+                    )?;
+                    0
+                };
 
                 // Fill rest of pane width
                 execute!(
                     self.stdout,
                     SetBackgroundColor(line_bg),
                 )?;
-                let line_len = buffer.line_str(line_idx).map(|l| l.len()).unwrap_or(0);
-                let current_col = x + line_num_width as u16 + 1 + text_cols.min(line_len) as u16;
-                let remaining = (x + width).saturating_sub(current_col);
+                let text_x = x + line_num_width as u16 + 1;
+                let mut used = drawn.max(display_line.chars().count()).min(text_cols);
+                let mut remaining = (x + width).saturating_sub(text_x + used as u16);
+                if is_last && next_line > line_idx + 1 {
+                    let marker = self.render_fold_marker(next_line - line_idx - 1, remaining as usize, line_bg)?;
+                    remaining -= marker as u16;
+                    used += marker;
+                }
                 if remaining > 0 {
                     execute!(self.stdout, Print(" ".repeat(remaining as usize)))?;
                 }
+                // The active pane's text already has the rulers behind it
+                let from = if is_active { used } else { 0 };
+                self.draw_rulers(text_x, y + row as u16, &rulers, &display_line, from..text_cols, text_color)?;
                 execute!(self.stdout, ResetColor)?;
-            } else {
-                execute!(
-                    self.stdout,
-                    SetBackgroundColor(bg_color),
-                    SetForegroundColor(if is_active { Color::DarkBlue } else { INACTIVE_LINE_NUM_COLOR }),
-                    Print(format!("{:>width$} ", "~", width = line_num_width)),
-                )?;
-                // Fill rest of line within pane bounds
-                let remaining = width.saturating_sub(line_num_width as u16 + 1);
-                execute!(self.stdout, Print(" ".repeat(remaining as usize)), ResetColor)?;
+                row += 1;
             }
         }
 
+        if minimap_width > 0 {
+            let map_x = x + (width as usize - minimap_width) as u16;
+            self.draw_minimap(buffer, pane.viewport_line..next_line, map_x, y, height as usize)?;
+        }
+
         // Return cursor position if this is the active pane
-        if pane.is_active {
-            let cursor_row = primary.line.saturating_sub(pane.viewport_line);
-            if cursor_row < height as usize {
-                let cursor_screen_row = y + cursor_row as u16;
-                let cursor_screen_col = x + line_num_width as u16 + 1 + primary.col as u16;
-                return Ok(Some((cursor_screen_col, cursor_screen_row)));
-            }
+        if let Some((cursor_row, cursor_x)) = cursor_pos {
+            let cursor_screen_row = y + cursor_row as u16;
+            let cursor_screen_col = x + line_num_width as u16 + 1 + cursor_x as u16;
+            return Ok(Some((cursor_screen_col, cursor_screen_row)));
         }
 
         Ok(None)
     }
 
+    /// Indent guides and whitespace marks for the row of line `line_idx`
+    /// that starts at column `start`
+    fn row_marks(&self, buffer: &Buffer, line_idx: usize, line: &str, start: usize) -> RowMarks {
+        let guides = match self.indent_guides {
+            Some(width) => whitespace::line_guides(line_idx, buffer.line_count(), line, width, |i| buffer.line_str(i))
+                .into_iter()
+                .filter(|&col| col >= start)
+                .map(|col| col - start)
+                .collect(),
+            None => Vec::new(),
+        };
+        let marks_from = self.whitespace.marks_from(line).map(|col| col.saturating_sub(start));
+        RowMarks { guides, marks_from, rulers: self.row_rulers(start) }
+    }
+
+    /// Ruler columns on a row that starts at buffer column `start`
+    fn row_rulers(&self, start: usize) -> Vec<usize> {
+        self.rulers.iter().filter(|&&col| col >= start).map(|col| col - start).collect()
+    }
+
+    /// Draw the ruler band over row columns `cols` of the text starting at
+    /// screen column `x`, keeping the characters of `text` under it
+    fn draw_rulers(&mut self, x: u16, y: u16, rulers: &[usize], text: &str, cols: std::ops::Range<usize>, fg: Color) -> Result<()> {
+        for &col in rulers.iter().filter(|col| cols.contains(col)) {
+            let ch = match text.chars().nth(col) {
+                Some(ch) if !ch.is_control() => ch,
+                _ => ' ',
+            };
+            execute!(
+                self.stdout,
+                MoveTo(x + col as u16, y),
+                SetBackgroundColor(self.theme.ui.ruler_bg),
+                SetForegroundColor(fg),
+                Print(ch),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Background of a text row: the current line's own when it is highlighted
+    fn line_bg(&self, is_current_line: bool) -> Color {
+        if is_current_line && self.highlight_current_line { self.theme.ui.current_line_bg } else { self.theme.ui.bg }
+    }
+
     /// Render line with cursors, bounded to a specific width
     fn render_line_with_cursors_bounded(
         &mut self,
@@ -521,9 +967,10 @@ impl Screen {
         is_current_line: bool,
         bracket_col: Option<usize>,
         secondary_cursors: &[usize],
-    ) -> Result<()> {
+        marks: &RowMarks,
+    ) -> Result<usize> {
         // Delegate to existing method - it already handles max_cols
-        self.render_line_with_cursors(
+        self.render_line_with_syntax(
             line,
             line_idx,
             max_cols,
@@ -531,6 +978,8 @@ impl Screen {
             is_current_line,
             bracket_col,
             secondary_cursors,
+            &[],
+            marks,
         )
     }
 
@@ -580,11 +1029,11 @@ impl Screen {
             if line_idx < buffer.line_count() {
                 // Line number with appropriate color
                 let line_num_fg = if is_current_line {
-                    CURRENT_LINE_NUM_COLOR
+                    self.theme.ui.current_line_number
                 } else {
-                    LINE_NUM_COLOR
+                    self.theme.ui.line_number
                 };
-                let line_bg = if is_current_line { CURRENT_LINE_BG } else { BG_COLOR };
+                let line_bg = self.line_bg(is_current_line);
 
                 execute!(
                     self.stdout,
@@ -628,8 +1077,8 @@ impl Screen {
                 // Empty line indicator
                 execute!(
                     self.stdout,
-                    SetBackgroundColor(BG_COLOR),
-                    SetForegroundColor(Color::DarkBlue),
+                    SetBackgroundColor(self.theme.ui.bg),
+                    SetForegroundColor(self.theme.ui.empty_line),
                     Print(format!("{:>width$} ", "~", width = line_num_width)),
                     Clear(ClearType::UntilNewLine),
                     ResetColor
@@ -642,7 +1091,7 @@ impl Screen {
         execute!(
             self.stdout,
             MoveTo(0, gap_row),
-            SetBackgroundColor(BG_COLOR),
+            SetBackgroundColor(self.theme.ui.bg),
             Clear(ClearType::UntilNewLine),
             ResetColor
         )?;
@@ -683,7 +1132,9 @@ impl Screen {
             bracket_col,
             secondary_cursors,
             &[],
-        )
+            &RowMarks::default(),
+        )?;
+        Ok(())
     }
 
     fn render_line_with_syntax(
@@ -696,9 +1147,12 @@ impl Screen {
         bracket_col: Option<usize>,
         secondary_cursors: &[usize],
         tokens: &[Token],
-    ) -> Result<()> {
-        let line_bg = if is_current_line { CURRENT_LINE_BG } else { BG_COLOR };
-        let default_fg = Color::Reset; // Default terminal foreground
+        marks: &RowMarks,
+    ) -> Result<usize> {
+        let line_bg = self.line_bg(is_current_line);
+        let default_fg = self.theme.ui.text;
+        let ruler_bg = self.theme.ui.ruler_bg;
+        let plain_bg = |col: usize| if marks.rulers.contains(&col) { ruler_bg } else { line_bg };
 
         // Pre-compute selection ranges for this line (small fixed array to avoid allocation)
         // Most users have at most a few cursors with selections
@@ -750,28 +1204,42 @@ impl Screen {
                 None
             };
 
-            // Determine background color (priority: selection > cursor > bracket > syntax/line)
+            // Determine background color (priority: selection > cursor > bracket > ruler/line)
             let bg = if in_selection {
-                Color::Blue
+                self.theme.ui.selection_bg
             } else if is_secondary_cursor {
-                Color::Magenta
+                self.theme.ui.secondary_cursor_bg
             } else if is_bracket_match {
-                BRACKET_MATCH_BG
+                self.theme.ui.bracket_match_bg
             } else {
-                line_bg
+                plain_bg(col)
             };
 
             // Determine foreground color and boldness
             let (fg, bold) = if in_selection {
-                (Color::White, false)
+                (self.theme.ui.selection_fg, false)
             } else if is_secondary_cursor {
-                (Color::White, false)
+                (self.theme.ui.selection_fg, false)
             } else if let Some(token) = current_token {
-                (token.token_type.color(), token.token_type.bold())
+                (self.theme.syntax.color(token.token_type), token.token_type.bold())
             } else {
                 (default_fg, false)
             };
 
+            // Indent guides and whitespace marks, dim unless highlighted
+            let marked = marks.marks_from.is_some_and(|from| col >= from);
+            let mark = match ch {
+                ' ' | '\t' if marks.guides.contains(&col) => Some(whitespace::GUIDE),
+                ' ' if marked => Some(whitespace::SPACE_MARK),
+                '\t' if marked => Some(whitespace::TAB_MARK),
+                _ => None,
+            };
+            let (ch, fg) = match mark {
+                Some(mark) if bg == plain_bg(col) => (mark, self.theme.ui.whitespace),
+                Some(mark) => (mark, fg),
+                None => (ch, fg),
+            };
+
             // Apply styling
             if bold {
                 execute!(
@@ -801,29 +1269,43 @@ impl Screen {
             .max()
             .copied();
 
+        // A blank line's guides go on past its end
+        let mut end_col = char_count;
+        let last_guide = marks.guides.iter().copied().filter(|&c| c >= char_count && c < max_cols).max();
+        if let (Some(last), None) = (last_guide, max_cursor_past_text) {
+            execute!(self.stdout, SetForegroundColor(self.theme.ui.whitespace))?;
+            for col in char_count..=last {
+                let ch = if marks.guides.contains(&col) { whitespace::GUIDE } else { ' ' };
+                execute!(self.stdout, SetBackgroundColor(plain_bg(col)), Print(ch))?;
+            }
+            execute!(self.stdout, SetBackgroundColor(line_bg), SetForegroundColor(default_fg))?;
+            end_col = last + 1;
+        }
+
         if let Some(max_cursor) = max_cursor_past_text {
             if max_cursor < max_cols {
                 for col in char_count..=max_cursor {
                     if secondary_cursors.contains(&col) {
                         execute!(
                             self.stdout,
-                            SetBackgroundColor(Color::Magenta),
-                            SetForegroundColor(Color::White),
+                            SetBackgroundColor(self.theme.ui.secondary_cursor_bg),
+                            SetForegroundColor(self.theme.ui.selection_fg),
                             Print(" ")
                         )?;
                     } else {
                         execute!(
                             self.stdout,
-                            SetBackgroundColor(line_bg),
+                            SetBackgroundColor(plain_bg(col)),
                             Print(" ")
                         )?;
                     }
                 }
                 execute!(self.stdout, SetBackgroundColor(line_bg), SetForegroundColor(default_fg))?;
+                end_col = max_cursor + 1;
             }
         }
 
-        Ok(())
+        Ok(end_col)
     }
 
     #[allow(dead_code)]
@@ -840,27 +1322,36 @@ impl Screen {
         // Status bar background
         execute!(
             self.stdout,
-            SetBackgroundColor(Color::DarkGrey),
-            SetForegroundColor(Color::White)
+            SetBackgroundColor(self.theme.ui.status_bg),
+            SetForegroundColor(self.theme.ui.status_fg)
         )?;
 
         // Left side: filename + modified indicator + cursor count
         let name = filename.unwrap_or("[No Name]");
-        let modified = if buffer.modified { " [+]" } else { "" };
+        let modified = match (buffer.modified, &self.backup_status) {
+            (true, Some(backup)) => format!(" [+] ({})", backup),
+            (true, None) => " [+]".to_string(),
+            (false, _) => String::new(),
+        };
         let cursor_count = if cursors.len() > 1 {
-            format!(" ({} cursors)", cursors.len())
+            format!(" ({} cursors, primary {})", cursors.len(), cursors.primary_index() + 1)
         } else {
             String::new()
         };
-        let left = format!(" {}{}{}", name, modified, cursor_count);
+        let note = self.state_note.map(|n| format!(" [{}]", n)).unwrap_or_default();
+        let mut mode = self.vim_mode.as_ref().map(|m| format!(" {} |", m)).unwrap_or_default();
+        if let Some(register) = self.macro_recording {
+            mode.push_str(&format!(" REC @{} |", register));
+        }
+        let left = format!("{} {}{}{}{}", mode, name, modified, note, cursor_count);
 
         // Right side: help hint, position, and message if any
         let primary = cursors.primary();
         let pos = format!("Ln {}, Col {}", primary.line + 1, primary.col + 1);
         let right = if let Some(msg) = message {
-            format!(" {} | Shift+F1: Help | {} ", msg, pos)
+            format!(" {} | Shift+F1: Help | {} | {} ", msg, self.indent, pos)
         } else {
-            format!(" Shift+F1: Help | {} ", pos)
+            format!(" Shift+F1: Help | {} | {} ", self.indent, pos)
         };
 
         // Pad middle
@@ -900,6 +1391,7 @@ impl Screen {
         git_mode: bool,
     ) -> Result<()> {
         let width = width as usize;
+        let x = self.layout.explorer.map_or(0, |explorer| explorer.x);
         let text_rows = self.rows.saturating_sub(1) as usize;
         let hint_rows = if hints_expanded { 4 } else { 1 };
         // Header line + separator + optional git mode line
@@ -907,7 +1399,7 @@ impl Screen {
         let tree_rows = text_rows.saturating_sub(hint_rows + header_rows);
 
         // Draw header: repo_name:branch
-        execute!(self.stdout, MoveTo(0, 0))?;
+        execute!(self.stdout, MoveTo(x, 0))?;
         let header_text = if let Some(b) = branch {
             format!("{}:{}", repo_name, b)
         } else {
@@ -919,17 +1411,17 @@ impl Screen {
         // Render header with cyan repo name, yellow branch
         execute!(
             self.stdout,
-            SetBackgroundColor(BG_COLOR),
-            SetForegroundColor(Color::Cyan),
+            SetBackgroundColor(self.theme.ui.bg),
+            SetForegroundColor(self.theme.ui.sidebar_header),
         )?;
         if let Some(b) = branch {
             let repo_display: String = repo_name.chars().take(width.saturating_sub(1)).collect();
             execute!(self.stdout, Print(&repo_display))?;
             execute!(
                 self.stdout,
-                SetForegroundColor(Color::DarkGrey),
+                SetForegroundColor(self.theme.ui.sidebar_muted),
                 Print(":"),
-                SetForegroundColor(Color::Yellow),
+                SetForegroundColor(self.theme.ui.sidebar_branch),
             )?;
             let remaining = width.saturating_sub(repo_display.len() + 1);
             let branch_display: String = b.chars().take(remaining).collect();
@@ -941,12 +1433,12 @@ impl Screen {
         execute!(self.stdout, ResetColor)?;
 
         // Draw separator
-        execute!(self.stdout, MoveTo(0, 1))?;
+        execute!(self.stdout, MoveTo(x, 1))?;
         let separator = "─".repeat(width);
         execute!(
             self.stdout,
-            SetBackgroundColor(BG_COLOR),
-            SetForegroundColor(Color::DarkGrey),
+            SetBackgroundColor(self.theme.ui.bg),
+            SetForegroundColor(self.theme.ui.sidebar_muted),
             Print(&separator),
             ResetColor,
         )?;
@@ -954,13 +1446,13 @@ impl Screen {
         // Draw git mode indicator line
         if git_mode {
             let git_row = 2u16;
-            execute!(self.stdout, MoveTo(0, git_row))?;
-            let git_hint = "Git: a/u/d/m/p/l/f/t";
+            execute!(self.stdout, MoveTo(x, git_row))?;
+            let git_hint = "Git: a/u/d/g/m/p/l/f/t";
             let padded = format!("{:<width$}", git_hint, width = width);
             execute!(
                 self.stdout,
-                SetBackgroundColor(Color::AnsiValue(235)),
-                SetForegroundColor(Color::Yellow),
+                SetBackgroundColor(self.theme.ui.sidebar_git_bg),
+                SetForegroundColor(self.theme.ui.sidebar_branch),
                 Print(&padded),
                 ResetColor,
             )?;
@@ -969,7 +1461,7 @@ impl Screen {
         // Draw file tree (starting after header)
         for row in 0..tree_rows {
             let screen_row = (row + header_rows) as u16;
-            execute!(self.stdout, MoveTo(0, screen_row))?;
+            execute!(self.stdout, MoveTo(x, screen_row))?;
 
             let item_idx = scroll + row;
             if item_idx < items.len() {
@@ -1012,31 +1504,32 @@ impl Screen {
                     let padded = format!("{:<width$}", display_base, width = padded_len);
                     execute!(
                         self.stdout,
-                        SetBackgroundColor(Color::DarkGrey),
-                        SetForegroundColor(Color::White),
+                        SetBackgroundColor(self.theme.ui.sidebar_selected_bg),
+                        SetForegroundColor(self.theme.ui.sidebar_selected_fg),
                         Print(&padded),
                     )?;
                     if !git_indicator.is_empty() {
                         // Git indicator with selection background
                         if item.git_status.staged {
-                            execute!(self.stdout, SetForegroundColor(Color::Green), Print(" ↑"))?;
+                            execute!(self.stdout, SetForegroundColor(self.theme.ui.git_staged), Print(" ↑"))?;
                         } else if item.git_status.unstaged {
-                            execute!(self.stdout, SetForegroundColor(Color::Red), Print(" ✗"))?;
+                            execute!(self.stdout, SetForegroundColor(self.theme.ui.git_unstaged), Print(" ✗"))?;
                         } else if item.git_status.untracked {
-                            execute!(self.stdout, SetForegroundColor(Color::DarkGrey), Print(" ?"))?;
+                            execute!(self.stdout, SetForegroundColor(self.theme.ui.git_untracked), Print(" ?"))?;
                         } else if item.git_status.incoming {
-                            execute!(self.stdout, SetForegroundColor(Color::Blue), Print(" ↓"))?;
+                            execute!(self.stdout, SetForegroundColor(self.theme.ui.git_incoming), Print(" ↓"))?;
                         }
                     }
                     execute!(self.stdout, ResetColor)?;
                 } else if item.is_dir {
-                    // Directories in blue
+                    // Directories in blue, excluded ones dimmed
                     let padded_len = width.saturating_sub(indicator_display_len);
                     let padded = format!("{:<width$}", display_base, width = padded_len);
+                    let dir_color = if item.excluded { self.theme.ui.sidebar_muted } else { self.theme.ui.sidebar_dir };
                     execute!(
                         self.stdout,
-                        SetBackgroundColor(BG_COLOR),
-                        SetForegroundColor(Color::Blue),
+                        SetBackgroundColor(self.theme.ui.bg),
+                        SetForegroundColor(dir_color),
                         Print(&padded),
                         ResetColor
                     )?;
@@ -1045,8 +1538,8 @@ impl Screen {
                     let padded = format!("{:<width$}", display_base, width = width);
                     execute!(
                         self.stdout,
-                        SetBackgroundColor(BG_COLOR),
-                        SetForegroundColor(Color::DarkGrey),
+                        SetBackgroundColor(self.theme.ui.bg),
+                        SetForegroundColor(self.theme.ui.sidebar_muted),
                         Print(&padded),
                         ResetColor
                     )?;
@@ -1056,19 +1549,19 @@ impl Screen {
                     let padded = format!("{:<width$}", display_base, width = padded_len);
                     execute!(
                         self.stdout,
-                        SetBackgroundColor(BG_COLOR),
-                        SetForegroundColor(Color::Reset),
+                        SetBackgroundColor(self.theme.ui.bg),
+                        SetForegroundColor(self.theme.ui.text),
                         Print(&padded),
                     )?;
                     // Add git status indicator
                     if item.git_status.staged {
-                        execute!(self.stdout, SetForegroundColor(Color::Green), Print(" ↑"))?;
+                        execute!(self.stdout, SetForegroundColor(self.theme.ui.git_staged), Print(" ↑"))?;
                     } else if item.git_status.unstaged {
-                        execute!(self.stdout, SetForegroundColor(Color::Red), Print(" ✗"))?;
+                        execute!(self.stdout, SetForegroundColor(self.theme.ui.git_unstaged), Print(" ✗"))?;
                     } else if item.git_status.untracked {
-                        execute!(self.stdout, SetForegroundColor(Color::DarkGrey), Print(" ?"))?;
+                        execute!(self.stdout, SetForegroundColor(self.theme.ui.git_untracked), Print(" ?"))?;
                     } else if item.git_status.incoming {
-                        execute!(self.stdout, SetForegroundColor(Color::Blue), Print(" ↓"))?;
+                        execute!(self.stdout, SetForegroundColor(self.theme.ui.git_incoming), Print(" ↓"))?;
                     }
                     execute!(self.stdout, ResetColor)?;
                 }
@@ -1077,7 +1570,7 @@ impl Screen {
                 let empty = " ".repeat(width);
                 execute!(
                     self.stdout,
-                    SetBackgroundColor(BG_COLOR),
+                    SetBackgroundColor(self.theme.ui.bg),
                     Print(&empty),
                     ResetColor
                 )?;
@@ -1091,16 +1584,16 @@ impl Screen {
                 "type:jump  spc:toggle  enter:open",
                 "alt-.:hidden  alt-g:git  ctrl-v/s:split",
                 "ctrl-b:close  ctrl-/:hints",
-                "",
+                "alt-x:exclude dir  alt-c:copy path",
             ];
             for (i, hint) in hints.iter().enumerate() {
                 if hint_start + i < text_rows {
-                    execute!(self.stdout, MoveTo(0, (hint_start + i) as u16))?;
+                    execute!(self.stdout, MoveTo(x, (hint_start + i) as u16))?;
                     let padded = format!("{:<width$}", hint, width = width);
                     execute!(
                         self.stdout,
-                        SetBackgroundColor(BG_COLOR),
-                        SetForegroundColor(Color::DarkGrey),
+                        SetBackgroundColor(self.theme.ui.bg),
+                        SetForegroundColor(self.theme.ui.sidebar_muted),
                         Print(&padded),
                         ResetColor
                     )?;
@@ -1108,13 +1601,13 @@ impl Screen {
             }
         } else {
             if hint_start < text_rows {
-                execute!(self.stdout, MoveTo(0, hint_start as u16))?;
+                execute!(self.stdout, MoveTo(x, hint_start as u16))?;
                 let hint = "ctrl-/:hints";
                 let padded = format!("{:<width$}", hint, width = width);
                 execute!(
                     self.stdout,
-                    SetBackgroundColor(BG_COLOR),
-                    SetForegroundColor(Color::DarkGrey),
+                    SetBackgroundColor(self.theme.ui.bg),
+                    SetForegroundColor(self.theme.ui.sidebar_muted),
                     Print(&padded),
                     ResetColor
                 )?;
@@ -1123,11 +1616,11 @@ impl Screen {
 
         // Fill the status bar row for fuss mode column (prevents terminal bleed-through)
         let status_row = self.rows.saturating_sub(1);
-        execute!(self.stdout, MoveTo(0, status_row))?;
+        execute!(self.stdout, MoveTo(x, status_row))?;
         let status_fill = " ".repeat(width);
         execute!(
             self.stdout,
-            SetBackgroundColor(BG_COLOR),
+            SetBackgroundColor(self.theme.ui.bg),
             Print(&status_fill),
             ResetColor
         )?;
@@ -1152,7 +1645,7 @@ impl Screen {
         // Hide cursor during render to prevent flicker
         execute!(self.stdout, Hide)?;
 
-        let available_cols = self.cols.saturating_sub(left_offset) as usize;
+        let available_cols = self.text_end().saturating_sub(left_offset) as usize;
         let line_num_width = self.line_number_width(buffer.line_count());
         let text_cols = available_cols.saturating_sub(line_num_width + 1);
 
@@ -1184,11 +1677,11 @@ impl Screen {
 
             if line_idx < buffer.line_count() {
                 let line_num_fg = if is_current_line {
-                    CURRENT_LINE_NUM_COLOR
+                    self.theme.ui.current_line_number
                 } else {
-                    LINE_NUM_COLOR
+                    self.theme.ui.line_number
                 };
-                let line_bg = if is_current_line { CURRENT_LINE_BG } else { BG_COLOR };
+                let line_bg = self.line_bg(is_current_line);
 
                 execute!(
                     self.stdout,
@@ -1227,8 +1720,8 @@ impl Screen {
             } else {
                 execute!(
                     self.stdout,
-                    SetBackgroundColor(BG_COLOR),
-                    SetForegroundColor(Color::DarkBlue),
+                    SetBackgroundColor(self.theme.ui.bg),
+                    SetForegroundColor(self.theme.ui.empty_line),
                     Print(format!("{:>width$} ", "~", width = line_num_width)),
                     Clear(ClearType::UntilNewLine),
                     ResetColor
@@ -1241,7 +1734,7 @@ impl Screen {
         execute!(
             self.stdout,
             MoveTo(left_offset, gap_row),
-            SetBackgroundColor(BG_COLOR),
+            SetBackgroundColor(self.theme.ui.bg),
             Clear(ClearType::UntilNewLine),
             ResetColor
         )?;
@@ -1280,152 +1773,214 @@ impl Screen {
     ) -> Result<()> {
         execute!(self.stdout, Hide)?;
 
-        let available_cols = self.cols.saturating_sub(left_offset) as usize;
+        let available_cols = self.text_end().saturating_sub(left_offset) as usize;
         let line_num_width = self.line_number_width(buffer.line_count());
-        let text_cols = available_cols.saturating_sub(line_num_width + 1);
+        let text_cols = available_cols.saturating_sub(line_num_width + 1 + minimap::width(self.minimap, available_cols));
 
         let primary = cursors.primary();
+        // Wrapped lines never scroll sideways
+        let viewport_col = if self.wrap { 0 } else { viewport_col };
 
-        // Adjust selections for horizontal scroll
         let selections: Vec<(Position, Position)> = cursors.all()
             .iter()
             .filter_map(|c| c.selection_bounds())
-            .map(|(start, end)| {
-                (
-                    Position { line: start.line, col: start.col.saturating_sub(viewport_col) },
-                    Position { line: end.line, col: end.col.saturating_sub(viewport_col) },
-                )
-            })
             .collect();
 
         let primary_idx = cursors.primary_index();
-        // Adjust cursor positions for horizontal scroll
         let cursor_positions: Vec<(usize, usize, bool)> = cursors.all()
             .iter()
             .enumerate()
-            .map(|(i, c)| (c.line, c.col.saturating_sub(viewport_col), i == primary_idx))
+            .map(|(i, c)| (c.line, c.col, i == primary_idx))
             .collect();
 
         // Reserve 2 rows: 1 for gap above status bar, 1 for status bar itself
         let text_rows = self.rows.saturating_sub(2 + top_offset) as usize;
 
         // Get the starting highlight state for the viewport using the cache.
-        // Only tokenize lines from the last cached point if needed.
-        let cache_valid = highlighter.cache_valid_from();
-        let start_line = cache_valid.min(viewport_line);
-        let mut highlight_state = highlighter.get_state_for_line(start_line);
-
-        // Build cache from last valid point up to viewport (only if needed)
-        for line_idx in start_line..viewport_line {
-            if let Some(line) = buffer.line_str(line_idx) {
-                let _ = highlighter.tokenize_line(&line, &mut highlight_state);
-                highlighter.update_cache(line_idx, &highlight_state);
+        // Only tokenize lines from the last cached point if needed. A large
+        // file skips the cache, which would have to be filled from the top,
+        // and starts from just above the viewport instead.
+        let cached = !buffer.large;
+        let mut highlight_state = if cached {
+            let cache_valid = highlighter.cache_valid_from();
+            let start_line = cache_valid.min(viewport_line);
+            let mut state = highlighter.get_state_for_line(start_line);
+
+            // Build cache from last valid point up to viewport (only if needed)
+            for line_idx in start_line..viewport_line {
+                if let Some(line) = buffer.line_str(line_idx) {
+                    let _ = highlighter.tokenize_line(&line, &mut state);
+                    highlighter.update_cache(line_idx, &state);
+                }
             }
-        }
+            state
+        } else {
+            highlighter.state_near(viewport_line, |idx| buffer.line_str(idx))
+        };
+
+        // Draw text area with syntax highlighting; a folded block takes one
+        // row, and with wrapping on a long line takes several
+        self.text_layout.clear();
+        let mut cursor_pos = None;
+        let mut next_line = viewport_line;
+        let mut row = 0;
+        while row < text_rows {
+            let line_idx = next_line;
+            let line = buffer.line_str(line_idx).filter(|_| line_idx < buffer.line_count());
+            let Some(line) = line else {
+                execute!(
+                    self.stdout,
+                    MoveTo(left_offset, (row as u16) + top_offset),
+                    SetBackgroundColor(self.theme.ui.bg),
+                    SetForegroundColor(self.theme.ui.empty_line),
+                    Print(format!("{:>width$} ", "~", width = line_num_width)),
+                    Clear(ClearType::UntilNewLine),
+                )?;
+                let text_x = left_offset + line_num_width as u16 + 1;
+                let rulers = self.row_rulers(viewport_col);
+                self.draw_rulers(text_x, row as u16 + top_offset, &rulers, "", 0..text_cols, self.theme.ui.text)?;
+                execute!(self.stdout, ResetColor)?;
+                row += 1;
+                continue;
+            };
+            next_line = buffer.next_visible_line(line_idx);
 
-        // Draw text area with syntax highlighting
-        for row in 0..text_rows {
-            let line_idx = viewport_line + row;
             let is_current_line = line_idx == primary.line;
-            execute!(self.stdout, MoveTo(left_offset, (row as u16) + top_offset))?;
+            let line_num_fg = if is_current_line {
+                self.theme.ui.current_line_number
+            } else {
+                self.theme.ui.line_number
+            };
+            let line_bg = self.line_bg(is_current_line);
+
+            // A code lens goes on a row of its own above its line, at the
+            // line's indentation
+            if let Some(title) = self.code_lenses.iter().find(|(l, _)| *l == line_idx).map(|(_, t)| t.clone()) {
+                let indent = line.chars().take_while(|c| c.is_whitespace()).count().saturating_sub(viewport_col);
+                let lens: String = " ".repeat(indent).chars().chain(title.chars()).take(text_cols).collect();
+                let lens_fg = self.theme.diagnostics.code_lens;
+                execute!(
+                    self.stdout,
+                    MoveTo(left_offset, (row as u16) + top_offset),
+                    SetBackgroundColor(self.theme.ui.bg),
+                    Print(format!("{:>width$} ", "", width = line_num_width)),
+                    SetForegroundColor(lens_fg),
+                    Print(&lens),
+                    Clear(ClearType::UntilNewLine),
+                )?;
+                let text_x = left_offset + line_num_width as u16 + 1;
+                let rulers = self.row_rulers(viewport_col);
+                self.draw_rulers(text_x, row as u16 + top_offset, &rulers, &lens, 0..text_cols, lens_fg)?;
+                execute!(self.stdout, ResetColor)?;
+                self.text_layout.push(None);
+                row += 1;
+            }
 
-            if line_idx < buffer.line_count() {
-                let line_num_fg = if is_current_line {
-                    CURRENT_LINE_NUM_COLOR
-                } else {
-                    LINE_NUM_COLOR
-                };
-                let line_bg = if is_current_line { CURRENT_LINE_BG } else { BG_COLOR };
+            // Tokenize this line and update cache
+            let tokens = highlighter.tokens_for_line(line_idx, &line, &mut highlight_state);
+            if cached {
+                highlighter.update_cache(line_idx, &highlight_state);
+            }
+
+            let starts = if self.wrap { wrap::row_starts(&line, text_cols) } else { vec![viewport_col] };
+            for (segment, &start) in starts.iter().enumerate() {
+                if row >= text_rows {
+                    break;
+                }
+                let is_last = segment + 1 == starts.len();
+                let span = RowSpan { line: line_idx, start, end: starts.get(segment + 1).copied().unwrap_or(usize::MAX) };
 
+                // The line number goes on the line's first row only
+                let number = if segment == 0 { (line_idx + 1).to_string() } else { String::new() };
                 execute!(
                     self.stdout,
+                    MoveTo(left_offset, (row as u16) + top_offset),
                     SetBackgroundColor(line_bg),
                     SetForegroundColor(line_num_fg),
-                    Print(format!("{:>width$} ", line_idx + 1, width = line_num_width)),
+                    Print(format!("{:>width$} ", number, width = line_num_width)),
                 )?;
 
-                if let Some(line) = buffer.line_str(line_idx) {
-                    // Tokenize this line and update cache
-                    let tokens = highlighter.tokenize_line(&line, &mut highlight_state);
-                    highlighter.update_cache(line_idx, &highlight_state);
+                // Only show the bracket match if it is on this row
+                let bracket_col = bracket_match
+                    .filter(|(bl, _)| *bl == line_idx)
+                    .and_then(|(_, bc)| span.col(bc));
+
+                let secondary_cursors: Vec<usize> = cursor_positions.iter()
+                    .filter(|(l, _, is_primary)| *l == line_idx && !*is_primary)
+                    .filter_map(|(_, c, _)| span.col(*c))
+                    .collect();
+
+                let display_line = span.text(&line);
+                let mut marks = self.row_marks(buffer, line_idx, &line, start);
+                if is_current_line && ghost_text.is_some() {
+                    // Ghost text goes right after the cursor, not the guides
+                    marks.guides.retain(|&col| col < display_line.chars().count());
+                }
+                let mut used = self.render_line_with_syntax(
+                    &display_line,
+                    line_idx,
+                    text_cols,
+                    &span.selections(&selections),
+                    is_current_line,
+                    bracket_col,
+                    &secondary_cursors,
+                    &span.tokens(&tokens),
+                    &marks,
+                )?;
 
-                    // Apply horizontal scroll to bracket match column
-                    // Only show if the bracket is in the visible area
-                    let bracket_col = bracket_match
-                        .filter(|(bl, bc)| *bl == line_idx && *bc >= viewport_col)
-                        .map(|(_, bc)| bc - viewport_col);
+                if is_current_line && (segment == 0 || primary.col >= start) && primary.col < span.end {
+                    cursor_pos = Some((row, primary.col.saturating_sub(start)));
+                }
 
-                    let secondary_cursors: Vec<usize> = cursor_positions.iter()
-                        .filter(|(l, _, is_primary)| *l == line_idx && !*is_primary)
-                        .map(|(_, c, _)| *c)
-                        .collect();
-
-                    // Skip characters before viewport_col
-                    let display_line: String = line.chars().skip(viewport_col).collect();
-
-                    // Adjust tokens for horizontal scroll
-                    let adjusted_tokens: Vec<Token> = tokens.iter()
-                        .filter_map(|t| {
-                            let new_start = t.start.saturating_sub(viewport_col);
-                            let new_end = t.end.saturating_sub(viewport_col);
-                            if t.end <= viewport_col {
-                                None // Token is entirely before viewport
-                            } else {
-                                Some(Token {
-                                    start: new_start,
-                                    end: new_end,
-                                    token_type: t.token_type,
-                                })
-                            }
-                        })
-                        .collect();
-
-                    self.render_line_with_syntax(
-                        &display_line,
-                        line_idx,
-                        text_cols,
-                        &selections,
-                        is_current_line,
-                        bracket_col,
-                        &secondary_cursors,
-                        &adjusted_tokens,
-                    )?;
-
-                    // Render ghost text on the current line after the cursor
-                    if is_current_line {
-                        if let Some(ghost) = ghost_text {
-                            // Calculate remaining space for ghost text
-                            let line_len = display_line.chars().count();
-                            let remaining_cols = text_cols.saturating_sub(line_len);
-                            if remaining_cols > 0 {
-                                // Truncate ghost text if it doesn't fit
-                                let ghost_display: String = ghost.chars().take(remaining_cols).collect();
-                                execute!(
-                                    self.stdout,
-                                    SetBackgroundColor(line_bg),
-                                    SetForegroundColor(Color::AnsiValue(240)), // Dim gray
-                                    Print(&ghost_display),
-                                )?;
-                            }
+                // Render ghost text on the current line after the cursor
+                if is_current_line && is_last {
+                    if let Some(ghost) = ghost_text {
+                        // Calculate remaining space for ghost text
+                        let remaining_cols = text_cols.saturating_sub(used);
+                        if remaining_cols > 0 {
+                            // Truncate ghost text if it doesn't fit
+                            let ghost_display: String = ghost.chars().take(remaining_cols).collect();
+                            used += ghost_display.chars().count();
+                            execute!(
+                                self.stdout,
+                                SetBackgroundColor(line_bg),
+                                SetForegroundColor(self.theme.ui.whitespace),
+                                Print(&ghost_display),
+                            )?;
                         }
                     }
                 }
 
+                if is_last && next_line > line_idx + 1 {
+                    used += self.render_fold_marker(next_line - line_idx - 1, text_cols.saturating_sub(used), line_bg)?;
+                }
+
                 execute!(
                     self.stdout,
                     SetBackgroundColor(line_bg),
                     Clear(ClearType::UntilNewLine),
-                    ResetColor
-                )?;
-            } else {
-                execute!(
-                    self.stdout,
-                    SetBackgroundColor(BG_COLOR),
-                    SetForegroundColor(Color::DarkBlue),
-                    Print(format!("{:>width$} ", "~", width = line_num_width)),
-                    Clear(ClearType::UntilNewLine),
-                    ResetColor
                 )?;
+                let text_x = left_offset + line_num_width as u16 + 1;
+                self.draw_rulers(text_x, row as u16 + top_offset, &marks.rulers, "", used..text_cols, self.theme.ui.text)?;
+                execute!(self.stdout, ResetColor)?;
+                self.text_layout.push(Some((line_idx, start)));
+                row += 1;
+            }
+
+            // Carry the highlight state over lines hidden in a fold
+            if next_line > line_idx + 1 {
+                if !cached {
+                    highlight_state = highlighter.state_near(next_line, |idx| buffer.line_str(idx));
+                } else if next_line <= highlighter.cache_valid_from() {
+                    highlight_state = highlighter.get_state_for_line(next_line);
+                } else {
+                    for hidden in line_idx + 1..next_line {
+                        if let Some(text) = buffer.line_str(hidden) {
+                            let _ = highlighter.tokenize_line(&text, &mut highlight_state);
+                            highlighter.update_cache(hidden, &highlight_state);
+                        }
+                    }
+                }
             }
         }
 
@@ -1434,7 +1989,7 @@ impl Screen {
         execute!(
             self.stdout,
             MoveTo(left_offset, gap_row),
-            SetBackgroundColor(BG_COLOR),
+            SetBackgroundColor(self.theme.ui.bg),
             Clear(ClearType::UntilNewLine),
             ResetColor
         )?;
@@ -1442,9 +1997,10 @@ impl Screen {
         // Status bar
         self.render_status_bar_with_offset(cursors, filename, message, left_offset, is_modified)?;
 
-        // Position hardware cursor (adjusted for horizontal scroll)
-        let cursor_row = (primary.line.saturating_sub(viewport_line) as u16) + top_offset;
-        let cursor_col = left_offset as usize + line_num_width + 1 + primary.col.saturating_sub(viewport_col);
+        // Position hardware cursor (adjusted for horizontal scroll and wrapping)
+        let (cursor_row, cursor_x) = cursor_pos.unwrap_or((0, primary.col.saturating_sub(viewport_col)));
+        let cursor_row = cursor_row as u16 + top_offset;
+        let cursor_col = left_offset as usize + line_num_width + 1 + cursor_x;
         execute!(
             self.stdout,
             MoveTo(cursor_col as u16, cursor_row),
@@ -1455,6 +2011,20 @@ impl Screen {
         Ok(())
     }
 
+    /// Draw the "⋯ N lines" marker after a fold's header line, within
+    /// `max_cols`; returns the columns used
+    fn render_fold_marker(&mut self, hidden: usize, max_cols: usize, line_bg: Color) -> Result<usize> {
+        let marker: String = fold_marker(hidden).chars().take(max_cols).collect();
+        let width = marker.chars().count();
+        execute!(
+            self.stdout,
+            SetBackgroundColor(line_bg),
+            SetForegroundColor(self.theme.ui.whitespace),
+            Print(marker),
+        )?;
+        Ok(width)
+    }
+
     fn render_status_bar_with_offset(
         &mut self,
         cursors: &Cursors,
@@ -1464,50 +2034,97 @@ impl Screen {
         is_modified: bool,
     ) -> Result<()> {
         let status_row = self.rows.saturating_sub(1);
-        let available_cols = self.cols.saturating_sub(offset) as usize;
+        let available_cols = self.text_end().saturating_sub(offset) as usize;
         execute!(self.stdout, MoveTo(offset, status_row))?;
 
         execute!(
             self.stdout,
-            SetBackgroundColor(Color::DarkGrey),
-            SetForegroundColor(Color::White)
+            SetBackgroundColor(self.theme.ui.status_bg),
+            SetForegroundColor(self.theme.ui.status_fg)
         )?;
 
         let name = filename.unwrap_or("[No Name]");
-        let modified = if is_modified { " [+]" } else { "" };
+        let modified = match (is_modified, &self.backup_status) {
+            (true, Some(backup)) => format!(" [+] ({})", backup),
+            (true, None) => " [+]".to_string(),
+            (false, _) => String::new(),
+        };
         let cursor_count = if cursors.len() > 1 {
-            format!(" ({} cursors)", cursors.len())
+            format!(" ({} cursors, primary {})", cursors.len(), cursors.primary_index() + 1)
         } else {
             String::new()
         };
-        let left = format!(" {}{}{}", name, modified, cursor_count);
+        let note = self.state_note.map(|n| format!(" [{}]", n)).unwrap_or_default();
+        let mut mode = self.vim_mode.as_ref().map(|m| format!(" {} |", m)).unwrap_or_default();
+        if let Some(register) = self.macro_recording {
+            mode.push_str(&format!(" REC @{} |", register));
+        }
+        let left = format!("{} {}{}{}{}", mode, name, modified, note, cursor_count);
 
         let primary = cursors.primary();
         let pos = format!("Ln {}, Col {}", primary.line + 1, primary.col + 1);
-        let right = if let Some(msg) = message {
-            format!(" {} | Shift+F1: Help | {} ", msg, pos)
-        } else {
-            format!(" Shift+F1: Help | {} ", pos)
-        };
+        let msg = message.map(|m| format!(" {} |", m)).unwrap_or_default();
+        let right = format!(" Shift+F1: Help | {} | {} ", self.indent, pos);
 
-        let padding = available_cols.saturating_sub(left.len() + right.len());
+        let padding = available_cols.saturating_sub(left.len() + msg.len() + right.len());
         let middle = " ".repeat(padding);
+        let msg_fg = self.message_color(self.message_level);
 
         execute!(
             self.stdout,
             Print(&left),
             Print(&middle),
+            SetForegroundColor(msg_fg),
+            Print(&msg),
+            SetForegroundColor(self.theme.ui.status_fg),
             Print(&right),
             ResetColor
         )?;
+        self.render_message_stack(offset)?;
+
+        Ok(())
+    }
 
+    fn message_color(&self, level: MessageLevel) -> Color {
+        match level {
+            MessageLevel::Info => self.theme.ui.status_fg,
+            MessageLevel::Error => self.theme.diagnostics.error,
+        }
+    }
+
+    /// Draw the stacked messages above the status bar, newest lowest,
+    /// right-aligned
+    fn render_message_stack(&mut self, left_offset: u16) -> Result<()> {
+        let status_row = self.rows.saturating_sub(1);
+        let available_cols = self.text_end().saturating_sub(left_offset) as usize;
+        let messages = std::mem::take(&mut self.stacked_messages);
+        for (i, (text, level)) in messages.iter().enumerate() {
+            let Some(row) = status_row.checked_sub(i as u16 + 1).filter(|&row| row > 0) else {
+                break;
+            };
+            let display: String = format!(" {} ", text).chars().take(available_cols).collect();
+            let col = self.text_end().saturating_sub(display.width() as u16).max(left_offset);
+            let fg = self.message_color(*level);
+            execute!(
+                self.stdout,
+                MoveTo(col, row),
+                SetBackgroundColor(self.theme.ui.status_bg),
+                SetForegroundColor(fg),
+                Print(display),
+                ResetColor,
+            )?;
+        }
+        self.stacked_messages = messages;
         Ok(())
     }
 
-    /// Render the welcome menu
+    /// Render the welcome menu, or a step of the setup wizard in its style.
+    /// The detail of the selected item shows below the list.
     pub fn render_welcome(
         &mut self,
-        items: &[(String, String, bool, bool)], // (label, path, is_selected, is_current_dir)
+        title: &str,
+        subtitle: &str,
+        items: &[(String, String, bool, bool)], // (label, detail, is_selected, is_highlighted)
         scroll: usize,
     ) -> Result<()> {
         execute!(self.stdout, Hide)?;
@@ -1520,7 +2137,7 @@ impl Screen {
             execute!(
                 self.stdout,
                 MoveTo(0, row as u16),
-                SetBackgroundColor(BG_COLOR),
+                SetBackgroundColor(self.theme.ui.bg),
                 Clear(ClearType::UntilNewLine),
             )?;
         }
@@ -1538,13 +2155,12 @@ impl Screen {
         execute!(
             self.stdout,
             MoveTo(box_x as u16, box_y as u16),
-            SetBackgroundColor(BG_COLOR),
+            SetBackgroundColor(self.theme.ui.bg),
             SetForegroundColor(Color::DarkGrey),
             Print(&top_border),
         )?;
 
         // Title
-        let title = "Welcome to fackr";
         let title_row = box_y + 1;
         let title_x = box_x + (box_width.saturating_sub(title.len())) / 2;
         execute!(
@@ -1566,7 +2182,6 @@ impl Screen {
         )?;
 
         // Subtitle
-        let subtitle = "Select a workspace:";
         let subtitle_row = box_y + 2;
         execute!(
             self.stdout,
@@ -1626,7 +2241,7 @@ impl Screen {
                         SetBackgroundColor(Color::DarkGrey),
                         SetForegroundColor(Color::White),
                         Print(&padded),
-                        SetBackgroundColor(BG_COLOR),
+                        SetBackgroundColor(self.theme.ui.bg),
                     )?;
                 } else if *is_current_dir {
                     execute!(
@@ -1742,10 +2357,11 @@ impl Screen {
         // Popup settings
         let max_items = 10.min(completions.len());
         let popup_width = 40;
-        let popup_bg = Color::AnsiValue(237);
-        let selected_bg = Color::AnsiValue(24);
-        let item_fg = Color::AnsiValue(252);
-        let detail_fg = Color::AnsiValue(244);
+        let popup_bg = self.theme.ui.popup_bg;
+        let selected_bg = self.theme.ui.popup_selected_bg;
+        let item_fg = self.theme.ui.popup_fg;
+        let detail_fg = self.theme.ui.popup_muted;
+        let import_fg = self.theme.ui.git_staged; // Auto-import hint
 
         // Position popup below cursor, or above if not enough space
         let popup_row = if cursor_row + (max_items as u16) + 2 < self.rows {
@@ -1754,7 +2370,7 @@ impl Screen {
             cursor_row.saturating_sub(max_items as u16 + 1)
         };
 
-        let popup_col = (cursor_col + left_offset).min(self.cols.saturating_sub(popup_width as u16));
+        let popup_col = (cursor_col + left_offset).min(self.text_end().saturating_sub(popup_width as u16));
 
         // Calculate scroll offset to keep selection visible
         let scroll_offset = if selected_index >= max_items {
@@ -1788,8 +2404,21 @@ impl Screen {
                 label.clone()
             };
 
+            // Items that add a `use`/import on accept get a hint before the detail
+            let import_hint = if item.needs_import() { "+use " } else { "" };
+
             write!(self.stdout, " {} ", icon)?;
-            write!(self.stdout, "{:<width$}", truncated_label, width = label_width - detail.len().min(15))?;
+            write!(
+                self.stdout,
+                "{:<width$}",
+                truncated_label,
+                width = (label_width - detail.len().min(15)).saturating_sub(import_hint.len())
+            )?;
+
+            if !import_hint.is_empty() {
+                execute!(self.stdout, SetForegroundColor(import_fg))?;
+                write!(self.stdout, "{}", import_hint)?;
+            }
 
             if !detail.is_empty() {
                 execute!(self.stdout, SetForegroundColor(detail_fg))?;
@@ -1821,31 +2450,185 @@ impl Screen {
         Ok(())
     }
 
+    /// Render the code actions popup below (or above) the cursor
+    pub fn render_code_actions_popup(
+        &mut self,
+        actions: &[CodeAction],
+        selected_index: usize,
+        cursor_row: u16,
+        cursor_col: u16,
+        left_offset: u16,
+    ) -> Result<()> {
+        let max_items = 10.min(actions.len());
+        let longest = actions.iter().map(|a| a.title.width()).max().unwrap_or(0);
+        let popup_width = (longest + 6).clamp(24, 60).min(self.cols as usize);
+        let popup_bg = self.theme.ui.popup_bg;
+        let selected_bg = self.theme.ui.popup_selected_bg;
+        let item_fg = self.theme.ui.popup_fg;
+        let number_fg = self.theme.ui.popup_muted;
+        let preferred_fg = self.theme.ui.git_staged;
+
+        let popup_row = if cursor_row + (max_items as u16) + 2 < self.rows {
+            cursor_row + 1
+        } else {
+            cursor_row.saturating_sub(max_items as u16 + 1)
+        };
+        let popup_col = (cursor_col + left_offset).min(self.text_end().saturating_sub(popup_width as u16));
+
+        let scroll_offset = (selected_index + 1).saturating_sub(max_items);
+
+        for (i, action) in actions.iter().enumerate().skip(scroll_offset).take(max_items) {
+            let row = popup_row + (i - scroll_offset) as u16;
+            let bg = if i == selected_index { selected_bg } else { popup_bg };
+            let number = if i < 9 { format!("{} ", i + 1) } else { "  ".to_string() };
+            let marker = if action.is_preferred { "* " } else { "  " };
+
+            let title_width = popup_width.saturating_sub(number.len() + marker.len() + 2);
+            let mut title = String::new();
+            for ch in action.title.chars() {
+                if title.width() + ch.to_string().width() > title_width {
+                    break;
+                }
+                title.push(ch);
+            }
+            let padding = title_width.saturating_sub(title.width());
+
+            execute!(
+                self.stdout,
+                MoveTo(popup_col, row),
+                SetBackgroundColor(bg),
+                Print(" "),
+                SetForegroundColor(number_fg),
+                Print(&number),
+                SetForegroundColor(preferred_fg),
+                Print(marker),
+                SetForegroundColor(item_fg),
+                Print(&title),
+                Print(format!("{:width$} ", "", width = padding)),
+                ResetColor,
+            )?;
+        }
+
+        if actions.len() > max_items {
+            execute!(
+                self.stdout,
+                MoveTo(popup_col, popup_row + max_items as u16),
+                SetBackgroundColor(popup_bg),
+                SetForegroundColor(number_fg),
+                Print(format!(" {}/{} actions ", selected_index + 1, actions.len())),
+                ResetColor,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Text row showing column `col` of `line` in the last single-pane
+    /// render, with the first column of that row; None if the line is
+    /// scrolled off or folded away
+    pub fn text_position(&self, line: usize, col: usize) -> Option<(usize, usize)> {
+        let first = self.text_layout.iter().position(|r| matches!(r, Some((l, _)) if *l == line))?;
+        let row = self.text_layout[first..]
+            .iter()
+            .take_while(|r| matches!(r, Some((l, start)) if *l == line && *start <= col))
+            .count()
+            .max(1)
+            + first
+            - 1;
+        self.text_layout[row].map(|(_, start)| (row, start))
+    }
+
+    /// Line and first column shown on text row `row` of the last
+    /// single-pane render; a code lens row gives the line below it
+    pub fn text_row(&self, row: usize) -> Option<(usize, usize)> {
+        self.text_layout.get(row..)?.iter().flatten().next().copied()
+    }
+
+    /// Draw the minimap at the right of the single-pane view, after
+    /// everything else in the text area
+    pub fn render_minimap(&mut self, buffer: &Buffer, left_offset: u16, top_offset: u16) -> Result<()> {
+        let available_cols = self.text_end().saturating_sub(left_offset) as usize;
+        let map_width = minimap::width(self.minimap, available_cols);
+        let (Some(&(first, _)), Some(&(last, _))) = (self.text_layout.iter().flatten().next(), self.text_layout.iter().flatten().last()) else {
+            return Ok(());
+        };
+        if map_width == 0 {
+            return Ok(());
+        }
+        let height = self.rows.saturating_sub(2 + top_offset) as usize;
+        let x = self.text_end() - map_width as u16;
+        self.draw_minimap(buffer, first..last + 1, x, top_offset, height)
+    }
+
+    /// Draw a minimap `height` rows tall with its blank column at `x`,
+    /// shading the rows of the `visible` lines
+    fn draw_minimap(&mut self, buffer: &Buffer, visible: std::ops::Range<usize>, x: u16, y: u16, height: usize) -> Result<()> {
+        let line_count = buffer.line_count();
+        // Scrolled as if the viewport were `height` lines, like the mouse handling
+        let first = minimap::first_line(line_count, visible.start, height, height);
+        let cells = minimap::WIDTH - 1;
+        for row in 0..height {
+            let line = minimap::line_at_row(first, row);
+            let lines = line..line + minimap::LINES_PER_ROW;
+            let text = |line: usize| buffer.line_str(line).filter(|_| line < line_count);
+            let map = if line < line_count {
+                minimap::row_cells(text(line).as_deref(), text(line + 1).as_deref(), cells)
+            } else {
+                " ".repeat(cells)
+            };
+            let in_view = lines.start < visible.end && visible.start < lines.end;
+            execute!(
+                self.stdout,
+                MoveTo(x, y + row as u16),
+                SetBackgroundColor(self.theme.ui.bg),
+                Print(' '),
+                SetBackgroundColor(if in_view { self.theme.ui.current_line_bg } else { self.theme.ui.bg }),
+                SetForegroundColor(self.theme.ui.line_number),
+                Print(map),
+                ResetColor,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Draw each mark's letter at the start of its line, before the number
+    pub fn render_marks_gutter(&mut self, marks: &[(char, usize)], left_offset: u16, top_offset: u16) -> Result<()> {
+        for &(name, line) in marks {
+            if let Some((row, _)) = self.text_position(line, 0) {
+                execute!(
+                    self.stdout,
+                    MoveTo(left_offset, row as u16 + top_offset),
+                    SetForegroundColor(self.theme.ui.mark),
+                    Print(name),
+                    ResetColor,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
     /// Render diagnostics in the gutter or inline
     pub fn render_diagnostics_gutter(
         &mut self,
         diagnostics: &[Diagnostic],
-        viewport_line: usize,
         left_offset: u16,
         top_offset: u16,
     ) -> Result<()> {
-        // Match text_rows calculation from render functions
-        let text_rows = self.rows.saturating_sub(2 + top_offset) as usize;
-
         for diagnostic in diagnostics {
             let line = diagnostic.range.start.line as usize;
 
-            // Only render if in visible viewport
-            if line >= viewport_line && line < viewport_line + text_rows {
-                let row = (line - viewport_line) as u16 + top_offset;
+            // Only render if in visible viewport (and not folded away)
+            let row = self.text_position(line, 0).map(|(row, _)| row);
+            if let Some(row) = row {
+                let row = row as u16 + top_offset;
 
                 // Determine color based on severity
                 let color = match diagnostic.severity {
-                    Some(DiagnosticSeverity::Error) => Color::Red,
-                    Some(DiagnosticSeverity::Warning) => Color::Yellow,
-                    Some(DiagnosticSeverity::Information) => Color::Blue,
-                    Some(DiagnosticSeverity::Hint) => Color::Cyan,
-                    None => Color::Yellow,
+                    Some(DiagnosticSeverity::Error) => self.theme.diagnostics.error,
+                    Some(DiagnosticSeverity::Warning) => self.theme.diagnostics.warning,
+                    Some(DiagnosticSeverity::Information) => self.theme.diagnostics.info,
+                    Some(DiagnosticSeverity::Hint) => self.theme.diagnostics.hint,
+                    None => self.theme.diagnostics.warning,
                 };
 
                 // Draw indicator at the start of the line (before line number)
@@ -1862,6 +2645,229 @@ impl Screen {
         Ok(())
     }
 
+    /// Redraw the text each diagnostic covers underlined in its severity
+    /// color. Less severe diagnostics are drawn first so errors win overlaps;
+    /// an empty range marks the character it starts at.
+    pub fn render_diagnostic_underlines(
+        &mut self,
+        buffer: &Buffer,
+        diagnostics: &[Diagnostic],
+        current_line: usize,
+        left_offset: u16,
+        top_offset: u16,
+    ) -> Result<()> {
+        let line_num_width = self.line_number_width(buffer.line_count());
+        let text_start = left_offset as usize + line_num_width + 1;
+        let text_cols = (self.text_end() as usize).saturating_sub(text_start);
+
+        let rank = |sev: Option<DiagnosticSeverity>| match sev {
+            Some(DiagnosticSeverity::Error) => 0,
+            Some(DiagnosticSeverity::Warning) | None => 1,
+            Some(DiagnosticSeverity::Information) => 2,
+            Some(DiagnosticSeverity::Hint) => 3,
+        };
+        let mut ordered: Vec<&Diagnostic> = diagnostics.iter().collect();
+        ordered.sort_by_key(|d| std::cmp::Reverse(rank(d.severity)));
+
+        for diagnostic in ordered {
+            let color = match diagnostic.severity {
+                Some(DiagnosticSeverity::Error) => self.theme.diagnostics.error,
+                Some(DiagnosticSeverity::Warning) | None => self.theme.diagnostics.warning,
+                Some(DiagnosticSeverity::Information) => self.theme.diagnostics.info,
+                Some(DiagnosticSeverity::Hint) => self.theme.diagnostics.hint,
+            };
+            let (start, end) = (diagnostic.range.start, diagnostic.range.end);
+
+            for row in 0..self.text_layout.len() {
+                let Some((line_idx, row_start)) = self.text_layout[row] else { continue };
+                if line_idx < start.line as usize || line_idx > end.line as usize {
+                    continue;
+                }
+                let Some(line) = buffer.line_str(line_idx) else { continue };
+                let chars: Vec<char> = line.chars().collect();
+                let from = if line_idx == start.line as usize { start.character as usize } else { 0 };
+                let mut to = if line_idx == end.line as usize { end.character as usize } else { chars.len() };
+                if start == end {
+                    to = from + 1;
+                }
+                let to = to.min(chars.len());
+
+                // The part of the range on this row
+                let row_end = match self.text_layout.get(row + 1) {
+                    Some(&Some((next_line, next_start))) if next_line == line_idx => next_start,
+                    _ => row_start + text_cols,
+                };
+                let (from, to) = (from.max(row_start), to.min(row_end));
+                if from >= to {
+                    continue;
+                }
+
+                let bg = self.line_bg(line_idx == current_line);
+                let text: String = chars[from..to].iter().map(|&c| if c == '\t' { ' ' } else { c }).collect();
+                execute!(
+                    self.stdout,
+                    MoveTo((text_start + from - row_start) as u16, row as u16 + top_offset),
+                    SetBackgroundColor(bg),
+                    SetForegroundColor(color),
+                    SetUnderlineColor(color),
+                    SetAttribute(Attribute::Underlined),
+                    Print(text),
+                    SetAttribute(Attribute::NoUnderline),
+                    ResetColor,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a graphics protocol sequence with the cursor at `col`, `row`
+    pub fn render_graphics(&mut self, sequence: &str, col: u16, row: u16) -> Result<()> {
+        execute!(self.stdout, MoveTo(col, row), Print(sequence))?;
+        Ok(())
+    }
+
+    /// Redraw the level column of editor log lines in its severity color
+    pub fn render_log_levels(
+        &mut self,
+        buffer: &Buffer,
+        left_offset: u16,
+        top_offset: u16,
+    ) -> Result<()> {
+        let line_num_width = self.line_number_width(buffer.line_count());
+        let text_start = left_offset as usize + line_num_width + 1;
+
+        for row in 0..self.text_layout.len() {
+            let Some((line_idx, start)) = self.text_layout[row] else { continue };
+            let Some(line) = buffer.line_str(line_idx) else { break };
+            let Some((level, offset)) = logging::line_level(&line) else { continue };
+            // The timestamp before the level is ASCII, so bytes are columns
+            if self.text_position(line_idx, offset) != Some((row, start)) || offset < start {
+                continue;
+            }
+            let col = text_start + offset - start;
+            if col + level.len() > self.text_end() as usize {
+                continue;
+            }
+            let color = match level {
+                "error" => self.theme.diagnostics.error,
+                "warn" => self.theme.diagnostics.warning,
+                "info" => self.theme.diagnostics.info,
+                _ => self.theme.diagnostics.hint,
+            };
+            execute!(
+                self.stdout,
+                MoveTo(col as u16, row as u16 + top_offset),
+                SetForegroundColor(color),
+                SetAttribute(crossterm::style::Attribute::Bold),
+                Print(&line[offset..offset + level.len()]),
+                SetAttribute(crossterm::style::Attribute::Reset),
+                ResetColor,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Redraw the changed words of a diff's removed and added lines on
+    /// their highlight background
+    pub fn render_word_diff(
+        &mut self,
+        buffer: &Buffer,
+        changes: &[(usize, std::ops::Range<usize>)],
+        left_offset: u16,
+        top_offset: u16,
+    ) -> Result<()> {
+        let line_num_width = self.line_number_width(buffer.line_count());
+        let text_start = left_offset as usize + line_num_width + 1;
+        let text_cols = (self.text_end() as usize).saturating_sub(text_start);
+
+        for row in 0..self.text_layout.len() {
+            let Some((line_idx, row_start)) = self.text_layout[row] else { continue };
+            let row_end = match self.text_layout.get(row + 1) {
+                Some(&Some((next_line, next_start))) if next_line == line_idx => next_start,
+                _ => row_start + text_cols,
+            };
+            let Some(line) = buffer.line_str(line_idx) else { continue };
+            let bg = if line.starts_with('+') { self.theme.ui.diff_added_bg } else { self.theme.ui.diff_removed_bg };
+            let chars: Vec<char> = line.chars().collect();
+
+            for (_, range) in changes.iter().filter(|(l, _)| *l == line_idx) {
+                let from = range.start.max(row_start);
+                let to = range.end.min(row_end).min(chars.len());
+                if from >= to {
+                    continue;
+                }
+                let text: String = chars[from..to].iter().map(|&c| if c == '\t' { ' ' } else { c }).collect();
+                execute!(
+                    self.stdout,
+                    MoveTo((text_start + from - row_start) as u16, row as u16 + top_offset),
+                    SetBackgroundColor(bg),
+                    SetForegroundColor(self.theme.ui.text),
+                    Print(text),
+                    ResetColor,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Render virtual text annotations after the end of their lines.
+    /// Multiple annotations on the same line are drawn one after another.
+    pub fn render_virtual_text(
+        &mut self,
+        buffer: &Buffer,
+        annotations: &[(usize, String, VirtualTextStyle)],
+        current_line: usize,
+        left_offset: u16,
+        top_offset: u16,
+    ) -> Result<()> {
+        let line_num_width = self.line_number_width(buffer.line_count());
+        let text_start = left_offset as usize + line_num_width + 1;
+        // Next free column per line, for lines with several annotations
+        let mut next_col: std::collections::HashMap<usize, usize> = std::collections::HashMap::new();
+
+        for (line, text, style) in annotations {
+            let line = *line;
+            if line >= buffer.line_count() {
+                continue;
+            }
+            // Annotations go on the line's last row
+            let line_len = buffer.line_len(line);
+            let Some((row, row_start)) = self.text_position(line, line_len) else {
+                continue;
+            };
+            let row = row as u16 + top_offset;
+
+            // Leave a two-column gap after the line content (or previous
+            // annotation, or fold marker)
+            let col = *next_col.entry(line).or_insert_with(|| {
+                let marker = buffer.fold_at(line).map_or(0, |(start, end)| fold_marker(end - start).chars().count());
+                text_start + line_len.saturating_sub(row_start) + marker + 2
+            });
+            if col >= self.text_end() as usize {
+                continue;
+            }
+            let available = self.text_end() as usize - col;
+            let display: String = text.chars().take(available).collect();
+            let width = display.width();
+            let bg = self.line_bg(line == current_line);
+
+            execute!(
+                self.stdout,
+                MoveTo(col as u16, row),
+                SetBackgroundColor(bg),
+                SetForegroundColor(style.color(&self.theme.diagnostics)),
+                Print(display),
+                ResetColor,
+            )?;
+            next_col.insert(line, col + width + 2);
+        }
+
+        Ok(())
+    }
+
     /// Render a hover info popup at the given screen position
     pub fn render_hover_popup(
         &mut self,
@@ -1913,8 +2919,8 @@ impl Screen {
             execute!(
                 self.stdout,
                 MoveTo(popup_col, row),
-                SetBackgroundColor(Color::AnsiValue(238)),
-                SetForegroundColor(Color::White),
+                SetBackgroundColor(self.theme.ui.input_bg),
+                SetForegroundColor(self.theme.ui.popup_fg_bright),
             )?;
 
             // Truncate line if needed
@@ -1933,8 +2939,8 @@ impl Screen {
             execute!(
                 self.stdout,
                 MoveTo(popup_col, row),
-                SetBackgroundColor(Color::AnsiValue(238)),
-                SetForegroundColor(Color::DarkGrey),
+                SetBackgroundColor(self.theme.ui.input_bg),
+                SetForegroundColor(self.theme.ui.popup_muted),
                 Print(format!(" [{} more lines] ", lines.len() - popup_height)),
                 ResetColor
             )?;
@@ -1946,6 +2952,85 @@ impl Screen {
         Ok(())
     }
 
+    /// Render signature help above the cursor (below it when there's no
+    /// room), with the active parameter highlighted
+    pub fn render_signature_help(
+        &mut self,
+        help: &SignatureHelp,
+        cursor_row: u16,
+        cursor_col: u16,
+        left_offset: u16,
+        top_offset: u16,
+    ) -> Result<()> {
+        let width = self.text_end() as usize;
+        let max_width = width.saturating_sub(left_offset as usize + 2).min(100);
+        if max_width < 10 {
+            return Ok(());
+        }
+
+        // Scroll a long label so the active parameter stays in view
+        let label: Vec<char> = help.label.chars().collect();
+        let (param_start, param_end) = help.active_parameter.unwrap_or((0, 0));
+        let skip = param_end.saturating_sub(max_width).min(param_start);
+        let shown: Vec<char> = label.iter().skip(skip).take(max_width).copied().collect();
+
+        let docs: Vec<String> = help
+            .documentation
+            .as_deref()
+            .unwrap_or("")
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .take(3)
+            .map(|l| l.chars().take(max_width).collect())
+            .collect();
+
+        let popup_height = 1 + docs.len() as u16;
+        let popup_width = docs.iter().map(|d| d.chars().count()).chain([shown.len()]).max().unwrap_or(0);
+        let popup_row = if cursor_row >= top_offset + popup_height {
+            cursor_row - popup_height
+        } else {
+            cursor_row + 1
+        };
+        let popup_col = (cursor_col.max(left_offset) as usize).min(width.saturating_sub(popup_width + 2)) as u16;
+
+        execute!(
+            self.stdout,
+            MoveTo(popup_col, popup_row),
+            SetBackgroundColor(self.theme.ui.input_bg),
+            SetForegroundColor(self.theme.ui.popup_fg),
+            Print(" "),
+        )?;
+        for (i, c) in shown.iter().enumerate() {
+            let active = (param_start..param_end).contains(&(i + skip));
+            if active {
+                execute!(
+                    self.stdout,
+                    SetForegroundColor(self.theme.ui.popup_accent),
+                    SetAttribute(Attribute::Bold),
+                    Print(c),
+                    SetAttribute(Attribute::NormalIntensity),
+                    SetForegroundColor(self.theme.ui.popup_fg),
+                )?;
+            } else {
+                execute!(self.stdout, Print(c))?;
+            }
+        }
+        execute!(self.stdout, Print(" ".repeat(popup_width - shown.len() + 1)), ResetColor)?;
+
+        for (i, doc) in docs.iter().enumerate() {
+            execute!(
+                self.stdout,
+                MoveTo(popup_col, popup_row + 1 + i as u16),
+                SetBackgroundColor(self.theme.ui.input_bg),
+                SetForegroundColor(self.theme.ui.popup_muted),
+                Print(format!(" {:width$} ", doc, width = popup_width)),
+                ResetColor
+            )?;
+        }
+
+        Ok(())
+    }
+
     /// Render a centered rename modal dialog
     pub fn render_rename_modal(&mut self, original_name: &str, new_name: &str) -> Result<()> {
         let (width, height) = (self.cols, self.rows);
@@ -1962,11 +3047,11 @@ impl Screen {
         let start_col = ((width as usize).saturating_sub(modal_width)) / 2;
         let start_row = ((height as usize).saturating_sub(modal_height)) / 2;
 
-        let bg = Color::AnsiValue(236);
-        let border_color = Color::AnsiValue(244);
-        let label_color = Color::AnsiValue(248);
-        let value_color = Color::White;
-        let input_bg = Color::AnsiValue(238);
+        let bg = self.theme.ui.popup_bg;
+        let border_color = self.theme.ui.popup_border;
+        let label_color = self.theme.ui.popup_fg;
+        let value_color = self.theme.ui.popup_fg_bright;
+        let input_bg = self.theme.ui.input_bg;
 
         // Draw top border
         execute!(
@@ -1985,7 +3070,7 @@ impl Screen {
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("│"),
-            SetForegroundColor(Color::Cyan),
+            SetForegroundColor(self.theme.ui.popup_title),
             Print(format!("{:>pad$}{}{:<rpad$}", "", title, "", pad = title_padding, rpad = modal_width - 2 - title_padding - title.len())),
             SetForegroundColor(border_color),
             Print("│"),
@@ -2026,7 +3111,7 @@ impl Screen {
             SetForegroundColor(label_color),
             Print(to_label),
             SetBackgroundColor(input_bg),
-            SetForegroundColor(Color::White),
+            SetForegroundColor(self.theme.ui.popup_fg_bright),
             Print(format!("{:<width$}", new_name, width = input_width)),
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
@@ -2065,21 +3150,22 @@ impl Screen {
         regex_mode: bool,
         match_count: usize,
         current_match: usize,
+        replace_preview: Option<&str>,
         left_offset: u16,
     ) -> Result<()> {
         let status_row = self.rows.saturating_sub(1);
-        let available_cols = (self.cols.saturating_sub(left_offset)) as usize;
+        let available_cols = (self.text_end().saturating_sub(left_offset)) as usize;
 
         execute!(self.stdout, MoveTo(left_offset, status_row))?;
 
         // Colors
-        let bg = Color::DarkGrey;
-        let active_bg = Color::AnsiValue(238);
-        let inactive_bg = Color::AnsiValue(236);
-        let label_color = Color::AnsiValue(250);
-        let active_label = Color::White;
-        let toggle_on = Color::Yellow;
-        let toggle_off = Color::AnsiValue(243);
+        let bg = self.theme.ui.status_bg;
+        let active_bg = self.theme.ui.input_bg;
+        let inactive_bg = self.theme.ui.popup_bg;
+        let label_color = self.theme.ui.popup_fg;
+        let active_label = self.theme.ui.popup_fg_bright;
+        let toggle_on = self.theme.ui.popup_accent;
+        let toggle_off = self.theme.ui.popup_muted;
 
         // Calculate widths
         // Layout: Find: [____] Replace: [____] [.*] [Aa] | N/M matches
@@ -2101,7 +3187,7 @@ impl Screen {
             SetForegroundColor(find_label_color),
             Print(find_label),
             SetBackgroundColor(find_bg),
-            SetForegroundColor(Color::White),
+            SetForegroundColor(self.theme.ui.popup_fg_bright),
         )?;
 
         // Truncate or pad find query
@@ -2122,7 +3208,7 @@ impl Screen {
             SetForegroundColor(replace_label_color),
             Print(replace_label),
             SetBackgroundColor(replace_bg),
-            SetForegroundColor(Color::White),
+            SetForegroundColor(self.theme.ui.popup_fg_bright),
         )?;
 
         // Truncate or pad replace text
@@ -2166,10 +3252,24 @@ impl Screen {
         }
 
         // Fill remaining space
-        let used = find_label.len() + input_width + replace_label.len() + input_width + 5 + 5 +
+        let mut used = find_label.len() + input_width + replace_label.len() + input_width + 5 + 5 +
             if match_count > 0 { format!(" {}/{}", current_match + 1, match_count).len() }
             else if !find_query.is_empty() { 11 }
             else { 0 };
+
+        // What the current match becomes once capture groups are expanded
+        if let Some(preview) = replace_preview {
+            let space = available_cols.saturating_sub(used + 3);
+            if space > 0 {
+                let shown: String = preview.chars().take(space).collect();
+                execute!(
+                    self.stdout,
+                    SetForegroundColor(toggle_on),
+                    Print(format!(" → {}", shown)),
+                )?;
+                used += 3 + shown.width();
+            }
+        }
         let remaining = available_cols.saturating_sub(used);
         execute!(
             self.stdout,
@@ -2220,13 +3320,13 @@ impl Screen {
         };
 
         // Colors
-        let bg = Color::AnsiValue(235);
-        let border_color = Color::AnsiValue(244);
-        let header_color = Color::Cyan;
-        let dir_color = Color::Blue;
-        let file_color = Color::AnsiValue(252);
-        let selected_bg = Color::AnsiValue(240);
-        let input_bg = Color::AnsiValue(238);
+        let bg = self.theme.ui.modal_bg;
+        let border_color = self.theme.ui.popup_border;
+        let header_color = self.theme.ui.popup_title;
+        let dir_color = self.theme.ui.popup_link;
+        let file_color = self.theme.ui.popup_fg;
+        let selected_bg = self.theme.ui.modal_selected_bg;
+        let input_bg = self.theme.ui.input_bg;
 
         // Draw top border with title
         let path_str = current_path.to_string_lossy();
@@ -2257,10 +3357,10 @@ impl Screen {
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("│ "),
-            SetForegroundColor(Color::AnsiValue(248)),
+            SetForegroundColor(self.theme.ui.popup_fg),
             Print("Filter: "),
             SetBackgroundColor(input_bg),
-            SetForegroundColor(Color::White),
+            SetForegroundColor(self.theme.ui.popup_fg_bright),
             Print(format!("{:<width$}", filter, width = modal_width.saturating_sub(12))),
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
@@ -2345,7 +3445,7 @@ impl Screen {
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("├"),
-            SetForegroundColor(Color::AnsiValue(243)),
+            SetForegroundColor(self.theme.ui.popup_muted),
             Print(format!(" {:<width$}", help_text, width = modal_width.saturating_sub(3))),
             SetForegroundColor(border_color),
             Print("┤"),
@@ -2369,7 +3469,8 @@ impl Screen {
         Ok(())
     }
 
-    /// Render the multi-file search modal (F4)
+    /// Render the multi-file search modal (F4), over the workspace's files
+    /// or, with `open_files`, the open buffers
     pub fn render_file_search_modal(
         &mut self,
         query: &str,
@@ -2377,6 +3478,7 @@ impl Screen {
         selected_index: usize,
         scroll_offset: usize,
         searching: bool,
+        open_files: bool,
     ) -> Result<()> {
         let (width, height) = (self.cols as usize, self.rows as usize);
 
@@ -2387,17 +3489,17 @@ impl Screen {
         let start_row = (height.saturating_sub(modal_height)) / 2;
 
         // Colors
-        let bg = Color::AnsiValue(235);
-        let border_color = Color::AnsiValue(244);
-        let header_color = Color::Cyan;
-        let path_color = Color::Blue;
-        let line_num_color = Color::Yellow;
-        let content_color = Color::AnsiValue(252);
-        let selected_bg = Color::AnsiValue(240);
-        let input_bg = Color::AnsiValue(238);
+        let bg = self.theme.ui.modal_bg;
+        let border_color = self.theme.ui.popup_border;
+        let header_color = self.theme.ui.popup_title;
+        let path_color = self.theme.ui.popup_link;
+        let line_num_color = self.theme.ui.popup_accent;
+        let content_color = self.theme.ui.popup_fg;
+        let selected_bg = self.theme.ui.modal_selected_bg;
+        let input_bg = self.theme.ui.input_bg;
 
         // Draw top border with title
-        let title = " Search in Files (F4) ";
+        let title = if open_files { " Search in Open Files (F4) " } else { " Search in Files (F4) " };
         execute!(
             self.stdout,
             MoveTo(start_col as u16, start_row as u16),
@@ -2413,8 +3515,8 @@ impl Screen {
 
         // Draw search input row
         let status = if searching {
-            "Searching..."
-        } else if results.is_empty() && !query.is_empty() {
+            "Searching... (Esc to stop)"
+        } else if results.is_empty() && !query.is_empty() && !open_files {
             "No results"
         } else if !results.is_empty() {
             ""
@@ -2428,13 +3530,13 @@ impl Screen {
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("│ "),
-            SetForegroundColor(Color::AnsiValue(248)),
+            SetForegroundColor(self.theme.ui.popup_fg),
             Print("Search: "),
             SetBackgroundColor(input_bg),
-            SetForegroundColor(Color::White),
+            SetForegroundColor(self.theme.ui.popup_fg_bright),
             Print(format!("{:<width$}", query, width = input_width)),
             SetBackgroundColor(bg),
-            SetForegroundColor(Color::AnsiValue(243)),
+            SetForegroundColor(self.theme.ui.popup_muted),
             Print(format!(" {}", status)),
             SetForegroundColor(border_color),
             Print(" │"),
@@ -2453,7 +3555,7 @@ impl Screen {
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("├"),
-            SetForegroundColor(Color::AnsiValue(243)),
+            SetForegroundColor(self.theme.ui.popup_muted),
             Print(&count_str),
             SetForegroundColor(border_color),
             Print(format!("{:─<width$}┤", "", width = modal_width.saturating_sub(2 + count_str.len()))),
@@ -2509,11 +3611,11 @@ impl Screen {
                 Print("│ "),
                 SetForegroundColor(path_color),
                 Print(&display_path),
-                SetForegroundColor(Color::AnsiValue(243)),
+                SetForegroundColor(self.theme.ui.popup_muted),
                 Print(":"),
                 SetForegroundColor(line_num_color),
                 Print(&line_str),
-                SetForegroundColor(Color::AnsiValue(243)),
+                SetForegroundColor(self.theme.ui.popup_muted),
                 Print(": "),
                 SetForegroundColor(content_color),
             )?;
@@ -2546,14 +3648,20 @@ impl Screen {
 
         // Draw help text row
         let help_row = (start_row + 3 + visible_rows) as u16;
-        let help_text = "Enter:search/open  ↑↓:nav  PgUp/Dn:scroll  Esc:close";
+        let help_text = if searching {
+            "Enter:open  ↑↓:nav  PgUp/Dn:scroll  Esc:stop search"
+        } else if open_files {
+            "Enter:search  Tab:all files  Esc:close"
+        } else {
+            "Enter:search/open  ↑↓:nav  Tab:open files  Esc:close"
+        };
         execute!(
             self.stdout,
             MoveTo(start_col as u16, help_row),
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("├"),
-            SetForegroundColor(Color::AnsiValue(243)),
+            SetForegroundColor(self.theme.ui.popup_muted),
             Print(format!(" {:<width$}", help_text, width = modal_width.saturating_sub(3))),
             SetForegroundColor(border_color),
             Print("┤"),
@@ -2577,6 +3685,179 @@ impl Screen {
         Ok(())
     }
 
+    /// Render the live grep modal (Shift+F4): the query and file types,
+    /// the matches so far, and the selected match in its file
+    pub fn render_live_grep_modal(&mut self, view: &LiveGrepView) -> Result<()> {
+        let (width, height) = (self.cols as usize, self.rows as usize);
+        let modal_width = 120.min(width.saturating_sub(4));
+        let modal_height = height.saturating_sub(4).max(12);
+        let inner = modal_width.saturating_sub(4);
+        let start_col = (width.saturating_sub(modal_width) / 2) as u16;
+        let start_row = (height.saturating_sub(modal_height) / 2) as u16;
+
+        let bg = self.theme.ui.modal_bg;
+        let border = self.theme.ui.popup_border;
+        let muted = self.theme.ui.popup_muted;
+        let selected_bg = self.theme.ui.modal_selected_bg;
+
+        // Title, then the query and file type inputs
+        let title = if view.regex { " Live Grep (regex) " } else { " Live Grep " };
+        let rule = modal_width.saturating_sub(title.chars().count() + 2);
+        execute!(
+            self.stdout,
+            MoveTo(start_col, start_row),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border),
+            Print("┌"),
+            SetForegroundColor(self.theme.ui.popup_title),
+            Print(title),
+            SetForegroundColor(border),
+            Print(format!("{}┐", "─".repeat(rule))),
+        )?;
+        let status = if view.invalid {
+            "Invalid regex"
+        } else if view.searching {
+            "Searching..."
+        } else if view.results.is_empty() && !view.query.is_empty() {
+            "No results"
+        } else {
+            ""
+        };
+        let inputs = [("Search: ", view.query, !view.editing_types, status), ("Files:  ", view.types, view.editing_types, "e.g. rs, toml")];
+        for (i, (label, text, active, note)) in inputs.into_iter().enumerate() {
+            let input_width = inner.saturating_sub(label.len() + note.chars().count() + 1);
+            execute!(
+                self.stdout,
+                MoveTo(start_col, start_row + 1 + i as u16),
+                SetBackgroundColor(bg),
+                SetForegroundColor(border),
+                Print("│ "),
+                SetForegroundColor(if active { self.theme.ui.popup_fg } else { muted }),
+                Print(label),
+                SetBackgroundColor(self.theme.ui.input_bg),
+                SetForegroundColor(self.theme.ui.popup_fg_bright),
+                Print(fit_width(text, input_width)),
+                SetBackgroundColor(bg),
+                SetForegroundColor(muted),
+                Print(format!(" {}", note)),
+                SetForegroundColor(border),
+                Print(" │"),
+            )?;
+        }
+
+        // Half the rows left list the matches, the rest preview one
+        let body = modal_height.saturating_sub(7);
+        let list_rows = body / 2;
+        let preview_rows = body - list_rows;
+        let count = if view.results.is_empty() { String::new() } else { format!(" {} results ", view.results.len()) };
+        self.render_modal_rule(start_col, start_row + 3, modal_width, &count)?;
+
+        let scroll = if view.selected_index < view.scroll_offset {
+            view.selected_index
+        } else if view.selected_index >= view.scroll_offset + list_rows {
+            view.selected_index + 1 - list_rows
+        } else {
+            view.scroll_offset
+        };
+        for row in 0..list_rows {
+            let idx = scroll + row;
+            let item_bg = if idx == view.selected_index && idx < view.results.len() { selected_bg } else { bg };
+            execute!(
+                self.stdout,
+                MoveTo(start_col, start_row + 4 + row as u16),
+                SetBackgroundColor(item_bg),
+                SetForegroundColor(border),
+                Print("│ "),
+            )?;
+            match view.results.get(idx) {
+                Some((path, line_num, content)) => {
+                    let place = format!("{}:{}: ", path.display(), line_num);
+                    let place = fit_width(&place, place.chars().count().min(inner / 2));
+                    execute!(
+                        self.stdout,
+                        SetForegroundColor(self.theme.ui.popup_link),
+                        Print(&place),
+                        SetForegroundColor(self.theme.ui.popup_fg),
+                        Print(fit_width(content, inner - place.chars().count())),
+                    )?;
+                }
+                None => execute!(self.stdout, Print(" ".repeat(inner)))?,
+            }
+            execute!(self.stdout, SetForegroundColor(border), Print(" │"))?;
+        }
+
+        // The selected match with the lines around it
+        let preview_top = start_row + 5 + list_rows as u16;
+        let heading = view.results.get(view.selected_index).map(|(path, _, _)| format!(" {} ", path.display())).unwrap_or_default();
+        self.render_modal_rule(start_col, preview_top - 1, modal_width, &heading)?;
+        let (lines, target) = view.preview.unwrap_or((&[], 0));
+        let first = target.saturating_sub(preview_rows / 2);
+        let number_width = (first + preview_rows).to_string().len();
+        for row in 0..preview_rows {
+            let idx = first + row;
+            let line_bg = if idx == target && !lines.is_empty() { selected_bg } else { bg };
+            let (number, text) = match lines.get(idx) {
+                Some(text) => (format!("{:>w$} ", idx + 1, w = number_width), text.as_str()),
+                None => (" ".repeat(number_width + 1), ""),
+            };
+            execute!(
+                self.stdout,
+                MoveTo(start_col, preview_top + row as u16),
+                SetBackgroundColor(line_bg),
+                SetForegroundColor(border),
+                Print("│ "),
+                SetForegroundColor(self.theme.ui.popup_accent),
+                Print(&number),
+                SetForegroundColor(self.theme.ui.popup_fg),
+                Print(fit_width(text, inner.saturating_sub(number.len()))),
+                SetForegroundColor(border),
+                Print(" │"),
+            )?;
+        }
+
+        let help_row = preview_top + preview_rows as u16;
+        let help = "Enter:open  ↑↓:nav  Tab:search/files  Alt+X:regex  Esc:stop/close";
+        execute!(
+            self.stdout,
+            MoveTo(start_col, help_row),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border),
+            Print("├ "),
+            SetForegroundColor(muted),
+            Print(fit_width(help, inner)),
+            SetForegroundColor(border),
+            Print(" ┤"),
+            MoveTo(start_col, help_row + 1),
+            Print(format!("└{}┘", "─".repeat(modal_width.saturating_sub(2)))),
+            ResetColor,
+        )?;
+
+        // The cursor sits in the input being typed into
+        let (input_row, input_text) = if view.editing_types { (2, view.types) } else { (1, view.query) };
+        let input_col = start_col as usize + 2 + 8 + input_text.chars().count();
+        execute!(self.stdout, MoveTo(input_col as u16, start_row + input_row), Show)?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    /// A modal's separator row at `row`, with `label` at its left
+    fn render_modal_rule(&mut self, col: u16, row: u16, width: usize, label: &str) -> Result<()> {
+        let label = fit_width(label, label.chars().count().min(width.saturating_sub(2)));
+        execute!(
+            self.stdout,
+            MoveTo(col, row),
+            SetBackgroundColor(self.theme.ui.modal_bg),
+            SetForegroundColor(self.theme.ui.popup_border),
+            Print("├"),
+            SetForegroundColor(self.theme.ui.popup_muted),
+            Print(&label),
+            SetForegroundColor(self.theme.ui.popup_border),
+            Print(format!("{}┤", "─".repeat(width.saturating_sub(2 + label.chars().count())))),
+            ResetColor,
+        )?;
+        Ok(())
+    }
+
     /// Render the command palette modal (Ctrl+P)
     pub fn render_command_palette(
         &mut self,
@@ -2584,6 +3865,8 @@ impl Screen {
         commands: &[(String, String, String, String)], // (name, shortcut, category, id)
         selected_index: usize,
         scroll_offset: usize,
+        noun: &str, // what the entries are, for the match count
+        help_text: &str,
     ) -> Result<()> {
         let (width, height) = (self.cols as usize, self.rows as usize);
 
@@ -2594,16 +3877,16 @@ impl Screen {
         let start_row = 2; // Near top of screen
 
         // Colors - sleek dark theme
-        let bg = Color::AnsiValue(236);
-        let border_color = Color::AnsiValue(240);
-        let _header_color = Color::Cyan; // reserved for future header styling
-        let category_color = Color::AnsiValue(243);
-        let name_color = Color::White;
-        let shortcut_color = Color::AnsiValue(245);
-        let selected_bg = Color::AnsiValue(24); // Blue highlight
-        let selected_name = Color::White;
-        let input_bg = Color::AnsiValue(238);
-        let prompt_color = Color::Yellow;
+        let bg = self.theme.ui.popup_bg;
+        let border_color = self.theme.ui.popup_border_dim;
+        let _header_color = self.theme.ui.popup_title; // reserved for future header styling
+        let category_color = self.theme.ui.popup_muted;
+        let name_color = self.theme.ui.popup_fg_bright;
+        let shortcut_color = self.theme.ui.popup_muted;
+        let selected_bg = self.theme.ui.popup_selected_bg;
+        let selected_name = self.theme.ui.popup_fg_bright;
+        let input_bg = self.theme.ui.input_bg;
+        let prompt_color = self.theme.ui.popup_accent;
 
         // Draw top border with subtle styling
         execute!(
@@ -2629,7 +3912,7 @@ impl Screen {
             Print(">"),
             SetAttribute(crossterm::style::Attribute::Reset),
             SetBackgroundColor(input_bg),
-            SetForegroundColor(Color::White),
+            SetForegroundColor(self.theme.ui.popup_fg_bright),
             Print(format!(" {:<width$}", display_query, width = input_display_width - 1)),
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
@@ -2725,11 +4008,10 @@ impl Screen {
 
         // Draw help text row
         let help_row = (start_row + 3 + visible_rows) as u16;
-        let help_text = "↑↓:select  Enter:run  Esc:close";
         let result_count = if commands.is_empty() {
             "No matches".to_string()
         } else {
-            format!("{} commands", commands.len())
+            format!("{} {}", commands.len(), noun)
         };
         execute!(
             self.stdout,
@@ -2737,7 +4019,7 @@ impl Screen {
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("├"),
-            SetForegroundColor(Color::AnsiValue(243)),
+            SetForegroundColor(self.theme.ui.popup_muted),
             Print(format!(" {} ", result_count)),
             SetForegroundColor(border_color),
             Print(format!("{:─<width$}", "", width = modal_width.saturating_sub(result_count.len() + 4))),
@@ -2759,7 +4041,7 @@ impl Screen {
         execute!(
             self.stdout,
             MoveTo(start_col as u16, help_row + 2),
-            SetForegroundColor(Color::AnsiValue(243)),
+            SetForegroundColor(self.theme.ui.popup_muted),
             Print(format!("{:^width$}", help_text, width = modal_width)),
             ResetColor,
         )?;
@@ -2789,14 +4071,14 @@ impl Screen {
         let start_row = 1; // Near top of screen
 
         // Colors - sleek dark theme matching command palette
-        let bg = Color::AnsiValue(236);
-        let border_color = Color::AnsiValue(240);
-        let title_color = Color::Cyan;
-        let category_color = Color::AnsiValue(243);
-        let shortcut_color = if show_alt { Color::Magenta } else { Color::Yellow };
-        let desc_color = Color::White;
-        let selected_bg = Color::AnsiValue(24); // Blue highlight
-        let input_bg = Color::AnsiValue(238);
+        let bg = self.theme.ui.popup_bg;
+        let border_color = self.theme.ui.popup_border_dim;
+        let title_color = self.theme.ui.popup_title;
+        let category_color = self.theme.ui.popup_muted;
+        let shortcut_color = if show_alt { self.theme.ui.popup_link } else { self.theme.ui.popup_accent };
+        let desc_color = self.theme.ui.popup_fg_bright;
+        let selected_bg = self.theme.ui.popup_selected_bg;
+        let input_bg = self.theme.ui.input_bg;
 
         // Draw top border with title (show indicator when viewing alternates)
         let title = if show_alt { " Keybindings [/] " } else { " Keybindings " };
@@ -2821,7 +4103,7 @@ impl Screen {
         // Draw search input row: "│ " + " {query}" + " │" = 2 + 1 + width + 2 = modal_width
         let display_query = if query.is_empty() { "Type to filter..." } else { query };
         let input_display_width = modal_width.saturating_sub(5);
-        let placeholder_color = if query.is_empty() { Color::AnsiValue(243) } else { Color::White };
+        let placeholder_color = if query.is_empty() { self.theme.ui.popup_muted } else { self.theme.ui.popup_fg_bright };
         execute!(
             self.stdout,
             MoveTo(start_col as u16, (start_row + 1) as u16),
@@ -2939,7 +4221,7 @@ impl Screen {
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("├"),
-            SetForegroundColor(Color::AnsiValue(243)),
+            SetForegroundColor(self.theme.ui.popup_muted),
             Print(format!(" {} ", result_count)),
             SetForegroundColor(border_color),
             Print(format!("{:─<width$}", "", width = modal_width.saturating_sub(result_count.len() + 4))),
@@ -2966,7 +4248,7 @@ impl Screen {
         execute!(
             self.stdout,
             MoveTo(start_col as u16, info_row + 2),
-            SetForegroundColor(Color::AnsiValue(243)),
+            SetForegroundColor(self.theme.ui.popup_muted),
             Print(format!("{:^width$}", help_text, width = modal_width)),
             ResetColor,
         )?;
@@ -2978,9 +4260,11 @@ impl Screen {
         Ok(())
     }
 
-    /// Render the LSP references panel (sidebar style)
+    /// Render the locations panel (sidebar style) under `title`, e.g.
+    /// "References"
     pub fn render_references_panel(
         &mut self,
+        title: &str,
         locations: &[Location],
         selected_index: usize,
         query: &str,
@@ -3005,16 +4289,16 @@ impl Screen {
         };
 
         // Colors
-        let bg = Color::AnsiValue(235);
-        let border_color = Color::AnsiValue(244);
-        let header_color = Color::Cyan;
-        let file_color = Color::AnsiValue(252);
-        let line_num_color = Color::AnsiValue(243);
-        let selected_bg = Color::AnsiValue(240);
-        let input_bg = Color::AnsiValue(238);
+        let bg = self.theme.ui.modal_bg;
+        let border_color = self.theme.ui.popup_border;
+        let header_color = self.theme.ui.popup_title;
+        let file_color = self.theme.ui.popup_fg;
+        let line_num_color = self.theme.ui.popup_muted;
+        let selected_bg = self.theme.ui.modal_selected_bg;
+        let input_bg = self.theme.ui.input_bg;
 
         // Draw top border with title
-        let title = format!(" References ({}) ", filtered.len());
+        let title = format!(" {} ({}) ", title, filtered.len());
         execute!(
             self.stdout,
             MoveTo(start_col as u16, start_row),
@@ -3035,10 +4319,10 @@ impl Screen {
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("│ "),
-            SetForegroundColor(Color::AnsiValue(248)),
+            SetForegroundColor(self.theme.ui.popup_fg),
             Print("Filter: "),
             SetBackgroundColor(input_bg),
-            SetForegroundColor(Color::White),
+            SetForegroundColor(self.theme.ui.popup_fg_bright),
             Print(format!("{:<width$}", query, width = panel_width.saturating_sub(12))),
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
@@ -3143,7 +4427,7 @@ impl Screen {
             SetBackgroundColor(bg),
             SetForegroundColor(border_color),
             Print("├"),
-            SetForegroundColor(Color::AnsiValue(243)),
+            SetForegroundColor(self.theme.ui.popup_muted),
             Print(format!(" {:<width$}", help_text, width = panel_width.saturating_sub(3))),
             SetForegroundColor(border_color),
             Print("┤"),
@@ -3167,19 +4451,419 @@ impl Screen {
         Ok(())
     }
 
+    /// Render a scrollable list panel spanning the screen below the tab bar.
+    /// Each row is a list of columns: the leading ones are padded to their
+    /// widest visible entry and dimmed, the last fills the remaining width.
+    pub fn render_list_panel(
+        &mut self,
+        title: &str,
+        rows: &[Vec<String>],
+        selected_index: usize,
+        help_text: &str,
+    ) -> Result<()> {
+        let (width, height) = (self.cols, self.rows);
+        let panel_width = width.saturating_sub(4).min(120);
+        let area = Rect {
+            x: (width - panel_width) / 2,
+            y: 1, // Below tab bar
+            width: panel_width,
+            height: height.saturating_sub(3), // Leave room for tab bar and status bar
+        };
+        self.render_list_panel_in(area, title, rows, selected_index, help_text)
+    }
+
+    /// Render a list panel filling `area`, e.g. the layout's dock
+    pub fn render_list_panel_in(
+        &mut self,
+        area: Rect,
+        title: &str,
+        rows: &[Vec<String>],
+        selected_index: usize,
+        help_text: &str,
+    ) -> Result<()> {
+        let panel_width = area.width as usize;
+        let panel_height = area.height as usize;
+        let start_col = area.x as usize;
+        let start_row = area.y;
+        let inner_width = panel_width.saturating_sub(4);
+
+        let bg = self.theme.ui.modal_bg;
+        let border_color = self.theme.ui.popup_border;
+        let header_color = self.theme.ui.popup_title;
+        let muted_color = self.theme.ui.popup_muted;
+        let text_color = self.theme.ui.popup_fg_bright;
+        let selected_bg = self.theme.ui.modal_selected_bg;
+
+        // Draw top border with title
+        let title = format!(" {} ({}) ", title, rows.len());
+        execute!(
+            self.stdout,
+            MoveTo(start_col as u16, start_row),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print("┌"),
+            SetForegroundColor(header_color),
+            Print(&title),
+            SetForegroundColor(border_color),
+            Print(format!("{:─<width$}┐", "", width = panel_width.saturating_sub(title.chars().count() + 2))),
+            ResetColor,
+        )?;
+
+        // Keep the selection in view
+        let visible_rows = panel_height.saturating_sub(3); // Title, help and bottom border
+        let scroll_offset = (selected_index + 1).saturating_sub(visible_rows);
+        let visible = rows.iter().skip(scroll_offset).take(visible_rows);
+
+        // Leading columns are as wide as their widest visible entry
+        let mut widths: Vec<usize> = Vec::new();
+        for row in visible.clone() {
+            for (i, column) in row.iter().enumerate().take(row.len().saturating_sub(1)) {
+                let len = column.chars().count();
+                match widths.get_mut(i) {
+                    Some(w) => *w = (*w).max(len),
+                    None => widths.push(len),
+                }
+            }
+        }
+
+        for (i, row) in visible.enumerate() {
+            let screen_row = start_row + 1 + i as u16;
+            let item_bg = if scroll_offset + i == selected_index { selected_bg } else { bg };
+            execute!(
+                self.stdout,
+                MoveTo(start_col as u16, screen_row),
+                SetBackgroundColor(item_bg),
+                SetForegroundColor(border_color),
+                Print("│ "),
+            )?;
+            let mut remaining = inner_width;
+            for (col, column) in row.iter().enumerate() {
+                let is_last = col + 1 == row.len();
+                let cell = if is_last {
+                    column.clone()
+                } else {
+                    format!("{:<width$} ", column, width = widths[col])
+                };
+                let cell: String = cell.chars().take(remaining).collect();
+                remaining -= cell.chars().count();
+                execute!(
+                    self.stdout,
+                    SetForegroundColor(if is_last { text_color } else { muted_color }),
+                    Print(&cell),
+                )?;
+            }
+            execute!(
+                self.stdout,
+                Print(format!("{:width$}", "", width = remaining)),
+                SetForegroundColor(border_color),
+                Print(" │"),
+                ResetColor,
+            )?;
+        }
+
+        // Fill remaining rows with empty space
+        let items_drawn = rows.len().saturating_sub(scroll_offset).min(visible_rows);
+        for i in items_drawn..visible_rows {
+            execute!(
+                self.stdout,
+                MoveTo(start_col as u16, start_row + 1 + i as u16),
+                SetBackgroundColor(bg),
+                SetForegroundColor(border_color),
+                Print(format!("│{:width$}│", "", width = panel_width.saturating_sub(2))),
+                ResetColor,
+            )?;
+        }
+
+        // Draw help text row and bottom border
+        let help_row = start_row + 1 + visible_rows as u16;
+        execute!(
+            self.stdout,
+            MoveTo(start_col as u16, help_row),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print("├"),
+            SetForegroundColor(muted_color),
+            Print(format!(" {:<width$}", help_text, width = panel_width.saturating_sub(3))),
+            SetForegroundColor(border_color),
+            Print("┤"),
+            MoveTo(start_col as u16, help_row + 1),
+            Print(format!("└{:─<width$}┘", "", width = panel_width.saturating_sub(2))),
+            ResetColor,
+        )?;
+
+        execute!(self.stdout, Hide)?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    /// Render the string literals panel (sidebar on the right, like references).
+    /// Each entry is ("line:col", literal text, included in the replace).
+    pub fn render_strings_panel(
+        &mut self,
+        entries: &[(String, String, bool)],
+        selected_index: usize,
+        template: &str,
+    ) -> Result<()> {
+        let (width, height) = (self.cols as usize, self.rows as usize);
+
+        let panel_width = 50.min(width / 2);
+        let panel_height = height.saturating_sub(3);
+        let start_col = width.saturating_sub(panel_width);
+        let start_row = 1u16;
+
+        let bg = self.theme.ui.modal_bg;
+        let border_color = self.theme.ui.popup_border;
+        let header_color = self.theme.ui.popup_title;
+        let text_color = self.theme.syntax.string;
+        let muted = self.theme.ui.popup_muted;
+        let selected_bg = self.theme.ui.modal_selected_bg;
+        let input_bg = self.theme.ui.input_bg;
+
+        let included = entries.iter().filter(|e| e.2).count();
+        let title = format!(" Strings ({}/{}) ", included, entries.len());
+        execute!(
+            self.stdout,
+            MoveTo(start_col as u16, start_row),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print("┌"),
+            SetForegroundColor(header_color),
+            Print(&title),
+            SetForegroundColor(border_color),
+            Print(format!("{:─<width$}┐", "", width = panel_width.saturating_sub(title.width() + 2))),
+            ResetColor,
+        )?;
+
+        // Template input row
+        execute!(
+            self.stdout,
+            MoveTo(start_col as u16, start_row + 1),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print("│ "),
+            SetForegroundColor(self.theme.ui.popup_fg),
+            Print("Replace with: "),
+            SetBackgroundColor(input_bg),
+            SetForegroundColor(self.theme.ui.popup_fg_bright),
+            Print(format!("{:<width$}", template, width = panel_width.saturating_sub(18))),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print("│"),
+            ResetColor,
+        )?;
+
+        execute!(
+            self.stdout,
+            MoveTo(start_col as u16, start_row + 2),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print(format!("├{:─<width$}┤", "", width = panel_width.saturating_sub(2))),
+            ResetColor,
+        )?;
+
+        let visible_rows = panel_height.saturating_sub(5);
+        let scroll_offset = (selected_index + 1).saturating_sub(visible_rows);
+
+        for (idx, (position, text, included)) in entries.iter().enumerate().skip(scroll_offset).take(visible_rows) {
+            let row = start_row + 3 + (idx - scroll_offset) as u16;
+            let item_bg = if idx == selected_index { selected_bg } else { bg };
+            let check = if *included { "[x] " } else { "[ ] " };
+            let position = format!("{:<8}", position);
+
+            let text_width = panel_width.saturating_sub(4 + check.len() + position.len());
+            let mut shown = String::new();
+            for ch in text.chars() {
+                if shown.width() + ch.to_string().width() > text_width {
+                    break;
+                }
+                shown.push(ch);
+            }
+            let padding = text_width.saturating_sub(shown.width());
+
+            execute!(
+                self.stdout,
+                MoveTo(start_col as u16, row),
+                SetBackgroundColor(item_bg),
+                SetForegroundColor(border_color),
+                Print("│ "),
+                SetForegroundColor(muted),
+                Print(check),
+                Print(&position),
+                SetForegroundColor(text_color),
+                Print(&shown),
+                Print(format!("{:width$}", "", width = padding)),
+                SetForegroundColor(border_color),
+                Print(" │"),
+                ResetColor,
+            )?;
+        }
+
+        let items_drawn = entries.len().saturating_sub(scroll_offset).min(visible_rows);
+        for i in items_drawn..visible_rows {
+            execute!(
+                self.stdout,
+                MoveTo(start_col as u16, start_row + 3 + i as u16),
+                SetBackgroundColor(bg),
+                SetForegroundColor(border_color),
+                Print(format!("│{:width$}│", "", width = panel_width.saturating_sub(2))),
+                ResetColor,
+            )?;
+        }
+
+        let help_row = start_row + 3 + visible_rows as u16;
+        let help_text = "↑↓:go  Tab:toggle  Enter:replace  Esc:close";
+        execute!(
+            self.stdout,
+            MoveTo(start_col as u16, help_row),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print("├"),
+            SetForegroundColor(muted),
+            Print(format!(" {:<width$}", help_text, width = panel_width.saturating_sub(3))),
+            SetForegroundColor(border_color),
+            Print("┤"),
+            ResetColor,
+        )?;
+
+        execute!(
+            self.stdout,
+            MoveTo(start_col as u16, help_row + 1),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print(format!("└{:─<width$}┘", "", width = panel_width.saturating_sub(2))),
+            ResetColor,
+        )?;
+
+        execute!(self.stdout, Hide)?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    /// Render the marks panel (sidebar on the right, like references).
+    /// Each entry is (mark letter, "file:line", preview text).
+    pub fn render_marks_panel(&mut self, entries: &[(char, String, String)], selected_index: usize) -> Result<()> {
+        let (width, height) = (self.cols as usize, self.rows as usize);
+
+        let panel_width = 50.min(width / 2);
+        let panel_height = height.saturating_sub(3);
+        let start_col = width.saturating_sub(panel_width) as u16;
+        let start_row = 1u16;
+        let inner = panel_width.saturating_sub(4);
+
+        let bg = self.theme.ui.modal_bg;
+        let border_color = self.theme.ui.popup_border;
+        let header_color = self.theme.ui.popup_title;
+        let mark_color = self.theme.ui.popup_accent;
+        let file_color = self.theme.ui.popup_fg;
+        let preview_color = self.theme.ui.popup_muted;
+        let selected_bg = self.theme.ui.modal_selected_bg;
+
+        let title = format!(" Marks ({}) ", entries.len());
+        execute!(
+            self.stdout,
+            MoveTo(start_col, start_row),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print("┌"),
+            SetForegroundColor(header_color),
+            Print(&title),
+            SetForegroundColor(border_color),
+            Print(format!("{:─<width$}┐", "", width = panel_width.saturating_sub(title.len() + 2))),
+            ResetColor,
+        )?;
+
+        let visible_rows = panel_height.saturating_sub(3);
+        let scroll_offset = (selected_index + 1).saturating_sub(visible_rows);
+
+        for i in 0..visible_rows {
+            let row = start_row + 1 + i as u16;
+            let item_bg = if i + scroll_offset == selected_index { selected_bg } else { bg };
+            execute!(
+                self.stdout,
+                MoveTo(start_col, row),
+                SetBackgroundColor(item_bg),
+                SetForegroundColor(border_color),
+                Print("│ "),
+            )?;
+            match entries.get(i + scroll_offset) {
+                Some((name, location, preview)) => {
+                    let location: String = location.chars().take(inner.saturating_sub(2)).collect();
+                    let preview_width = inner.saturating_sub(location.chars().count() + 3);
+                    let preview: String = preview.trim().chars().take(preview_width).collect();
+                    let used = 2 + location.chars().count() + 1 + preview.chars().count();
+                    execute!(
+                        self.stdout,
+                        SetForegroundColor(mark_color),
+                        Print(format!("{} ", name)),
+                        SetForegroundColor(file_color),
+                        Print(&location),
+                        Print(" "),
+                        SetForegroundColor(preview_color),
+                        Print(&preview),
+                        Print(" ".repeat(inner.saturating_sub(used))),
+                    )?;
+                }
+                None => {
+                    execute!(self.stdout, Print(" ".repeat(inner)))?;
+                }
+            }
+            execute!(
+                self.stdout,
+                SetBackgroundColor(item_bg),
+                SetForegroundColor(border_color),
+                Print(" │"),
+                ResetColor,
+            )?;
+        }
+
+        let help_row = start_row + 1 + visible_rows as u16;
+        let help_text = "↑↓:nav  Enter:go  Del:remove  Esc:close";
+        execute!(
+            self.stdout,
+            MoveTo(start_col, help_row),
+            SetBackgroundColor(bg),
+            SetForegroundColor(border_color),
+            Print("├"),
+            SetForegroundColor(self.theme.ui.popup_muted),
+            Print(format!(" {:<width$}", help_text, width = panel_width.saturating_sub(3))),
+            SetForegroundColor(border_color),
+            Print("┤"),
+            MoveTo(start_col, help_row + 1),
+            Print(format!("└{:─<width$}┘", "", width = panel_width.saturating_sub(2))),
+            ResetColor,
+            Hide,
+        )?;
+
+        self.stdout.flush()?;
+        Ok(())
+    }
+
     /// Render the LSP server manager panel
-    pub fn render_server_manager_panel(&mut self, panel: &ServerManagerPanel) -> Result<()> {
+    /// Left column, top row, width and list rows of the server manager,
+    /// centered near the top or along the layout's dock
+    fn server_manager_area(&self, docked: bool) -> (usize, u16, usize, usize) {
+        if docked {
+            let dock = self.layout.dock;
+            let max_visible = (dock.height as usize).saturating_sub(5).max(1);
+            return (dock.x as usize, dock.y, (dock.width as usize).max(40), max_visible);
+        }
+        let panel_width = 64.min(self.cols as usize - 4);
+        let max_visible = 10.min(self.rows as usize - 8);
+        let start_col = ((self.cols as usize).saturating_sub(panel_width)) / 2;
+        (start_col, 2, panel_width, max_visible)
+    }
+
+    /// Server rows the manager shows at once
+    pub fn server_manager_rows(&self, docked: bool) -> usize {
+        self.server_manager_area(docked).3
+    }
+
+    pub fn render_server_manager_panel(&mut self, panel: &ServerManagerPanel, docked: bool) -> Result<()> {
         if !panel.visible {
             return Ok(());
         }
 
-        let (width, height) = (self.cols, self.rows);
-        let panel_width = 64.min(width as usize - 4);
-        let max_visible = 10.min(height as usize - 8);
-
-        // Center the panel
-        let start_col = ((width as usize).saturating_sub(panel_width)) / 2;
-        let start_row = 2u16;
+        let (start_col, start_row, panel_width, max_visible) = self.server_manager_area(docked);
 
         // Draw confirm dialog if in confirm mode
         if panel.confirm_mode {
@@ -3280,16 +4964,13 @@ impl Screen {
             } else {
                 format!(" {} ({})", server.name, server.language)
             };
-            let name_len = name_lang.len().min(panel_width - 20);
-            execute!(
-                self.stdout,
-                SetForegroundColor(if is_installing { Color::Yellow } else { Color::White }),
-                Print(&name_lang[..name_len]),
-            )?;
 
             // Status text
+            let running = panel.running_state(server);
             let status = if is_installing {
                 ""
+            } else if let Some(ref state) = running {
+                state.as_str()
             } else if server.is_installed {
                 "installed"
             } else if server.install_cmd.starts_with('#') {
@@ -3297,6 +4978,13 @@ impl Screen {
             } else {
                 "Enter to install"
             };
+            let name_len = name_lang.len().min(panel_width - 20).min(panel_width.saturating_sub(status.len() + 6));
+            execute!(
+                self.stdout,
+                SetForegroundColor(if is_installing { Color::Yellow } else { Color::White }),
+                Print(&name_lang[..name_len]),
+            )?;
+
             // Content width is panel_width - 2 (for the two │ borders)
             // We've printed: 1 space + 1 icon + name_len chars
             // We need to print: status + 1 trailing space before │
@@ -3305,7 +4993,13 @@ impl Screen {
             let status_padding = content_width.saturating_sub(used);
             execute!(self.stdout, Print(" ".repeat(status_padding)))?;
 
-            if server.is_installed {
+            if running.is_some() {
+                execute!(
+                    self.stdout,
+                    SetForegroundColor(Color::Green),
+                    Print(status),
+                )?;
+            } else if server.is_installed {
                 execute!(
                     self.stdout,
                     SetForegroundColor(Color::DarkGrey),
@@ -3397,7 +5091,7 @@ impl Screen {
             let pad = content_width.saturating_sub(1 + display_width);
             execute!(self.stdout, Print(" ".repeat(pad)))?;
         } else {
-            let help_text = " ↑↓ Navigate  Enter Install  r Refresh  Esc Close ";
+            let help_text = " ↑↓ Move  Enter Install  s Start/Stop  t Restart  l Log  Esc ";
             let help_width = help_text.width();
             execute!(
                 self.stdout,
@@ -3770,13 +5464,15 @@ impl Screen {
     }
 
     /// Render the integrated terminal panel
-    pub fn render_terminal(&mut self, terminal: &TerminalPanel, left_offset: u16) -> Result<()> {
+    pub fn render_terminal(&mut self, terminal: &TerminalPanel) -> Result<()> {
+        let Some(area) = self.layout.terminal else {
+            return Ok(());
+        };
         // Hide cursor during render to prevent flicker
         execute!(self.stdout, Hide)?;
 
-        let start_row = terminal.render_start_row(self.rows);
-        let height = terminal.height;
-        let terminal_width = self.cols.saturating_sub(left_offset) as usize;
+        let (left_offset, start_row, height) = (area.x, area.y, area.height);
+        let terminal_width = area.width as usize;
 
         // Draw terminal border (top line with title)
         execute!(
@@ -3908,7 +5604,7 @@ impl Screen {
             SetForegroundColor(default_fg)
         )?;
 
-        for row in 0..(height - 1) {
+        for row in 0..height.saturating_sub(1) {
             execute!(self.stdout, MoveTo(left_offset, start_row + 1 + row))?;
 
             // Build a string of characters with same attributes to batch print
@@ -0,0 +1,352 @@
+//! Color themes
+//!
+//! A theme is a TOML file whose `[ui]`, `[syntax]` and `[diagnostics]` tables
+//! override colors of the default theme; keys left out keep their default.
+//! Colors are 256-color palette indices (`234`), hex RGB (`"#1e1e2e"`) or
+//! crossterm names (`"dark_grey"`, `"reset"`):
+//!
+//! ```toml
+//! [ui]
+//! bg = "#1e1e2e"
+//! selection_bg = 60
+//!
+//! [syntax]
+//! keyword = "magenta"
+//! ```
+//!
+//! Built-in themes ship in `render/themes/`; user themes are read from
+//! `~/.config/fackr/themes/<name>.toml` and shadow built-ins of the same name.
+
+use crossterm::style::Color;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::toml::{self, Value};
+use crate::syntax::TokenType;
+
+/// Name of the theme used when none is configured
+pub const DEFAULT_THEME: &str = "fackr-dark";
+
+/// Built-in themes: (name, TOML overrides of the default)
+const BUILTIN_THEMES: &[(&str, &str)] = &[
+    (DEFAULT_THEME, ""),
+    ("fackr-light", include_str!("themes/fackr-light.toml")),
+    ("solarized-dark", include_str!("themes/solarized-dark.toml")),
+    ("gruvbox-dark", include_str!("themes/gruvbox-dark.toml")),
+];
+
+/// Declare a group of theme colors with their TOML keys and defaults
+macro_rules! color_group {
+    (
+        $(#[$meta:meta])*
+        $name:ident {
+            $($(#[$field_meta:meta])* $field:ident = $key:literal, $default:expr;)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $name {
+            $($(#[$field_meta])* pub $field: Color,)*
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self { $($field: $default,)* }
+            }
+        }
+
+        impl $name {
+            /// Set the color called `key` in theme files; false if there is none
+            fn set(&mut self, key: &str, color: Color) -> bool {
+                match key {
+                    $($key => self.$field = color,)*
+                    _ => return false,
+                }
+                true
+            }
+        }
+    };
+}
+
+color_group! {
+    /// Editor chrome: panes, tab bar, status bar, sidebar and popups
+    UiColors {
+        // Editor panes
+        bg = "bg", Color::AnsiValue(234);
+        current_line_bg = "current_line_bg", Color::AnsiValue(236);
+        line_number = "line_number", Color::AnsiValue(243);
+        current_line_number = "current_line_number", Color::Yellow;
+        /// Mark letters in the gutter
+        mark = "mark", Color::Cyan;
+        bracket_match_bg = "bracket_match_bg", Color::AnsiValue(240);
+        text = "text", Color::Reset;
+        selection_bg = "selection_bg", Color::Blue;
+        selection_fg = "selection_fg", Color::White;
+        secondary_cursor_bg = "secondary_cursor_bg", Color::Magenta;
+        /// `~` markers past the end of the buffer
+        empty_line = "empty_line", Color::DarkBlue;
+        /// Rendered tabs and other invisible characters
+        whitespace = "whitespace", Color::AnsiValue(240);
+        /// Columns marked by the `rulers` setting
+        ruler_bg = "ruler_bg", Color::AnsiValue(235);
+        inactive_bg = "inactive_bg", Color::AnsiValue(233);
+        inactive_current_line_bg = "inactive_current_line_bg", Color::AnsiValue(234);
+        inactive_line_number = "inactive_line_number", Color::AnsiValue(240);
+        inactive_text = "inactive_text", Color::AnsiValue(245);
+        pane_separator = "pane_separator", Color::AnsiValue(240);
+        pane_active_separator = "pane_active_separator", Color::AnsiValue(250);
+        /// Changed words within a diff's removed and added lines
+        diff_removed_bg = "diff_removed_bg", Color::AnsiValue(52);
+        diff_added_bg = "diff_added_bg", Color::AnsiValue(22);
+
+        // Tab bar
+        tab_bar_bg = "tab_bar_bg", Color::AnsiValue(235);
+        tab_active_bg = "tab_active_bg", Color::AnsiValue(238);
+        tab_inactive_fg = "tab_inactive_fg", Color::AnsiValue(245);
+        tab_active_fg = "tab_active_fg", Color::White;
+        tab_modified_fg = "tab_modified_fg", Color::Yellow;
+
+        // Status bar
+        status_bg = "status_bg", Color::DarkGrey;
+        status_fg = "status_fg", Color::White;
+
+        // Fuss sidebar
+        sidebar_header = "sidebar_header", Color::Cyan;
+        sidebar_branch = "sidebar_branch", Color::Yellow;
+        sidebar_dir = "sidebar_dir", Color::Blue;
+        sidebar_muted = "sidebar_muted", Color::DarkGrey;
+        sidebar_selected_bg = "sidebar_selected_bg", Color::DarkGrey;
+        sidebar_selected_fg = "sidebar_selected_fg", Color::White;
+        sidebar_git_bg = "sidebar_git_bg", Color::AnsiValue(235);
+        git_staged = "git_staged", Color::Green;
+        git_unstaged = "git_unstaged", Color::Red;
+        git_untracked = "git_untracked", Color::DarkGrey;
+        git_incoming = "git_incoming", Color::Blue;
+
+        // Popups (completion, hover, palette) and modals (file browser, search)
+        popup_bg = "popup_bg", Color::AnsiValue(236);
+        modal_bg = "modal_bg", Color::AnsiValue(235);
+        popup_border = "popup_border", Color::AnsiValue(244);
+        popup_border_dim = "popup_border_dim", Color::AnsiValue(240);
+        popup_fg = "popup_fg", Color::AnsiValue(252);
+        popup_fg_bright = "popup_fg_bright", Color::White;
+        popup_muted = "popup_muted", Color::AnsiValue(243);
+        popup_selected_bg = "popup_selected_bg", Color::AnsiValue(24);
+        modal_selected_bg = "modal_selected_bg", Color::AnsiValue(240);
+        input_bg = "input_bg", Color::AnsiValue(238);
+        popup_title = "popup_title", Color::Cyan;
+        popup_accent = "popup_accent", Color::Yellow;
+        popup_link = "popup_link", Color::Blue;
+    }
+}
+
+color_group! {
+    /// Syntax highlighting, one color per token type
+    SyntaxColors {
+        plain = "plain", Color::Reset;
+        keyword = "keyword", Color::Blue;
+        string = "string", Color::Green;
+        number = "number", Color::Magenta;
+        comment = "comment", Color::DarkGrey;
+        operator = "operator", Color::Yellow;
+        type_name = "type", Color::Cyan;
+        function = "function", Color::Cyan;
+        preprocessor = "preprocessor", Color::Magenta;
+        attribute = "attribute", Color::Yellow;
+        punctuation = "punctuation", Color::DarkGrey;
+    }
+}
+
+color_group! {
+    /// Diagnostic gutter markers and error lens / code lens virtual text
+    DiagnosticColors {
+        error = "error", Color::Red;
+        warning = "warning", Color::Yellow;
+        info = "info", Color::Blue;
+        hint = "hint", Color::Cyan;
+        lens_error = "lens_error", Color::AnsiValue(167);
+        lens_warning = "lens_warning", Color::AnsiValue(179);
+        lens_info = "lens_info", Color::AnsiValue(74);
+        lens_hint = "lens_hint", Color::AnsiValue(73);
+        code_lens = "code_lens", Color::AnsiValue(244);
+    }
+}
+
+impl SyntaxColors {
+    /// Foreground color for a token type
+    pub fn color(&self, token_type: TokenType) -> Color {
+        match token_type {
+            TokenType::Plain => self.plain,
+            TokenType::Keyword => self.keyword,
+            TokenType::String => self.string,
+            TokenType::Number => self.number,
+            TokenType::Comment => self.comment,
+            TokenType::Operator => self.operator,
+            TokenType::Type => self.type_name,
+            TokenType::Function => self.function,
+            TokenType::Preprocessor => self.preprocessor,
+            TokenType::Attribute => self.attribute,
+            TokenType::Punctuation => self.punctuation,
+            TokenType::Ansi(n) => Color::AnsiValue(n),
+            TokenType::Rgb(r, g, b) => Color::Rgb { r, g, b },
+        }
+    }
+}
+
+/// A complete set of colors for the screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub ui: UiColors,
+    pub syntax: SyntaxColors,
+    pub diagnostics: DiagnosticColors,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_THEME.to_string(),
+            ui: UiColors::default(),
+            syntax: SyntaxColors::default(),
+            diagnostics: DiagnosticColors::default(),
+        }
+    }
+}
+
+impl Theme {
+    /// Load a theme by name, preferring a user theme file over a built-in.
+    /// Unknown themes fall back to the default; problems are returned as warnings.
+    pub fn load(name: &str) -> (Self, Vec<String>) {
+        let user_file = themes_dir().join(format!("{}.toml", name));
+        if let Ok(content) = fs::read_to_string(&user_file) {
+            return Self::parse(name, &content);
+        }
+        match BUILTIN_THEMES.iter().find(|(builtin, _)| *builtin == name) {
+            Some((_, content)) => Self::parse(name, content),
+            None => (Self::default(), vec![format!("Unknown theme '{}'", name)]),
+        }
+    }
+
+    /// Build a theme from TOML overrides of the default colors
+    pub fn parse(name: &str, content: &str) -> (Self, Vec<String>) {
+        let mut theme = Self { name: name.to_string(), ..Self::default() };
+        let (entries, bad_lines) = toml::parse(content);
+        let mut warnings: Vec<String> = bad_lines
+            .into_iter()
+            .map(|line| format!("{}.toml:{}: expected key = color", name, line))
+            .collect();
+
+        for entry in entries {
+            let Some(color) = parse_color(&entry.value) else {
+                warnings.push(format!("{}.toml:{}: bad color for '{}'", name, entry.line, entry.key));
+                continue;
+            };
+            let known = match entry.section.as_str() {
+                "ui" => theme.ui.set(&entry.key, color),
+                "syntax" => theme.syntax.set(&entry.key, color),
+                "diagnostics" => theme.diagnostics.set(&entry.key, color),
+                _ => false,
+            };
+            if !known {
+                warnings.push(format!(
+                    "{}.toml:{}: unknown color '{}.{}'",
+                    name, entry.line, entry.section, entry.key
+                ));
+            }
+        }
+        (theme, warnings)
+    }
+
+    /// Names of all themes with whether each is a user theme, built-ins first
+    pub fn available() -> Vec<(String, bool)> {
+        let mut themes: Vec<(String, bool)> = BUILTIN_THEMES
+            .iter()
+            .map(|(name, _)| (name.to_string(), false))
+            .collect();
+
+        let mut user: Vec<String> = fs::read_dir(themes_dir())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+            .collect();
+        user.sort();
+
+        for name in user {
+            match themes.iter_mut().find(|(existing, _)| *existing == name) {
+                Some(existing) => existing.1 = true,
+                None => themes.push((name, true)),
+            }
+        }
+        themes
+    }
+}
+
+/// Directory holding user theme files
+fn themes_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("fackr")
+        .join("themes")
+}
+
+/// Palette index, `"#rrggbb"` or a crossterm color name
+fn parse_color(value: &Value) -> Option<Color> {
+    match value {
+        Value::Integer(n) => u8::try_from(*n).ok().map(Color::AnsiValue),
+        Value::String(s) => {
+            if let Some(hex) = s.strip_prefix('#') {
+                if hex.len() != 6 {
+                    return None;
+                }
+                let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+                Some(Color::Rgb { r: channel(0)?, g: channel(2)?, b: channel(4)? })
+            } else {
+                Color::try_from(s.as_str()).ok()
+            }
+        }
+        Value::Boolean(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_themes_parse_cleanly() {
+        for (name, content) in BUILTIN_THEMES {
+            let (theme, warnings) = Theme::parse(name, content);
+            assert!(warnings.is_empty(), "{}: {:?}", name, warnings);
+            assert_eq!(theme.name, *name);
+        }
+    }
+
+    #[test]
+    fn test_overrides_keep_unset_defaults() {
+        let (theme, warnings) = Theme::parse(
+            "custom",
+            "[ui]\nbg = \"#1e1e2e\"\nselection_bg = 60\n[syntax]\ntype = \"dark_cyan\"\n",
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(theme.ui.bg, Color::Rgb { r: 0x1e, g: 0x1e, b: 0x2e });
+        assert_eq!(theme.ui.selection_bg, Color::AnsiValue(60));
+        assert_eq!(theme.syntax.color(TokenType::Type), Color::DarkCyan);
+        assert_eq!(theme.ui.status_bg, UiColors::default().status_bg);
+    }
+
+    #[test]
+    fn test_bad_entries_warn() {
+        let (_, warnings) = Theme::parse("t", "[ui]\nbg = 300\nnope = 1\n[other]\nx = 2\n");
+        assert_eq!(
+            warnings,
+            vec![
+                "t.toml:2: bad color for 'bg'".to_string(),
+                "t.toml:3: unknown color 'ui.nope'".to_string(),
+                "t.toml:5: unknown color 'other.x'".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Indent guides and whitespace marks
+//!
+//! Guides are drawn in a line's leading whitespace at the start of each
+//! indentation level; blank lines carry the guides of the shallower of the
+//! lines around them, so a block's guides don't break at its blank lines.
+//! Whitespace can be shown as dim `·` for spaces and `→` for tabs, either
+//! everywhere or only where it trails at the end of a line.
+
+use serde::{Deserialize, Serialize};
+
+pub const GUIDE: char = '│';
+pub const SPACE_MARK: char = '·';
+pub const TAB_MARK: char = '→';
+
+/// Lines looked through either way for the indentation around a blank line
+const CONTEXT_LINES: usize = 50;
+
+/// Which whitespace is drawn as marks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Whitespace {
+    #[default]
+    None,
+    /// Only whitespace at the end of a line
+    Trailing,
+    All,
+}
+
+impl Whitespace {
+    /// The setting after this one, for cycling through them
+    pub fn next(self) -> Self {
+        match self {
+            Whitespace::None => Whitespace::Trailing,
+            Whitespace::Trailing => Whitespace::All,
+            Whitespace::All => Whitespace::None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Whitespace::None => "none",
+            Whitespace::Trailing => "trailing",
+            Whitespace::All => "all",
+        }
+    }
+
+    /// First column of `line` whose whitespace gets a mark, if any does
+    pub fn marks_from(self, line: &str) -> Option<usize> {
+        match self {
+            Whitespace::None => None,
+            Whitespace::Trailing => Some(line.trim_end().chars().count()),
+            Whitespace::All => Some(0),
+        }
+    }
+}
+
+/// Columns in `line`'s leading whitespace where an indentation level
+/// `width` columns wide starts; a tab starts a level of its own
+pub fn guide_cols(line: &str, width: usize) -> Vec<usize> {
+    let width = width.max(1);
+    let mut cols = Vec::new();
+    let mut visual = 0;
+    for (col, ch) in line.chars().enumerate() {
+        match ch {
+            ' ' => {
+                if visual % width == 0 {
+                    cols.push(col);
+                }
+                visual += 1;
+            }
+            '\t' => {
+                cols.push(col);
+                visual += width - visual % width;
+            }
+            _ => break,
+        }
+    }
+    // Whitespace ending a level that nothing is indented into isn't one
+    if line.trim().is_empty() {
+        cols.clear();
+    }
+    cols
+}
+
+/// Guides for line `idx`: its own, or for a blank line those of the
+/// shallower of the nearest lines with text above and below
+pub fn line_guides(idx: usize, line_count: usize, line: &str, width: usize, text: impl Fn(usize) -> Option<String>) -> Vec<usize> {
+    if !line.trim().is_empty() {
+        return guide_cols(line, width);
+    }
+    let has_text = |i: usize| text(i).filter(|t| !t.trim().is_empty());
+    let above = (idx.saturating_sub(CONTEXT_LINES)..idx).rev().find_map(has_text);
+    let below = (idx + 1..line_count.min(idx + 1 + CONTEXT_LINES)).find_map(has_text);
+    match (above, below) {
+        (Some(above), Some(below)) => {
+            let (above, below) = (guide_cols(&above, width), guide_cols(&below, width));
+            if above.len() <= below.len() { above } else { below }
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guide_cols() {
+        assert_eq!(guide_cols("        x", 4), vec![0, 4]);
+        assert_eq!(guide_cols("      x", 4), vec![0, 4]);
+        assert_eq!(guide_cols("\t\tx", 4), vec![0, 1]);
+        assert_eq!(guide_cols("x", 4), Vec::<usize>::new());
+        assert_eq!(guide_cols("    ", 4), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_blank_lines_take_the_shallower_side() {
+        let lines = ["fn f() {", "        a", "", "    b", "}"];
+        let text = |i: usize| lines.get(i).map(|l| l.to_string());
+        assert_eq!(line_guides(2, lines.len(), "", 4, text), vec![0]);
+        assert_eq!(line_guides(1, lines.len(), lines[1], 4, text), vec![0, 4]);
+    }
+
+    #[test]
+    fn test_marks_from() {
+        assert_eq!(Whitespace::Trailing.marks_from("a b  "), Some(3));
+        assert_eq!(Whitespace::All.marks_from("a b"), Some(0));
+        assert_eq!(Whitespace::None.marks_from("a "), None);
+    }
+}
@@ -0,0 +1,64 @@
+//! Soft wrapping
+//!
+//! A wrapped line is split into rows no wider than the text area. Rows break
+//! after the last space that fits, and mid-word only when a word is longer
+//! than a whole row. Columns are characters, like everywhere else in the
+//! renderer.
+
+/// Start column of each row `line` wraps into at `width` columns; always at
+/// least one row, starting at 0
+pub fn row_starts(line: &str, width: usize) -> Vec<usize> {
+    let chars: Vec<char> = line.chars().collect();
+    let width = width.max(1);
+    let mut starts = vec![0];
+    let mut start = 0;
+    while chars.len() - start > width {
+        let limit = start + width;
+        // Break after the last space that still fits on the row, if any
+        let end = chars[start + 1..limit]
+            .iter()
+            .rposition(|c| *c == ' ' || *c == '\t')
+            .map_or(limit, |i| start + 1 + i + 1);
+        starts.push(end);
+        start = end;
+    }
+    starts
+}
+
+/// Index of the row in `starts` that shows column `col`
+pub fn row_of(starts: &[usize], col: usize) -> usize {
+    starts.iter().rposition(|&start| start <= col).unwrap_or(0)
+}
+
+/// Last column a cursor can sit at in `row`: just before the next row's
+/// start, or the end of the line on the last row
+pub fn row_end(starts: &[usize], row: usize, line_len: usize) -> usize {
+    starts.get(row + 1).map_or(line_len, |next| next - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaks_after_spaces() {
+        assert_eq!(row_starts("hello world foo", 8), vec![0, 6, 12]);
+        assert_eq!(row_starts("short", 8), vec![0]);
+        assert_eq!(row_starts("", 8), vec![0]);
+    }
+
+    #[test]
+    fn test_long_words_break_mid_word() {
+        assert_eq!(row_starts("abcdefghij", 4), vec![0, 4, 8]);
+    }
+
+    #[test]
+    fn test_row_lookup() {
+        let starts = row_starts("hello world foo", 8);
+        assert_eq!(row_of(&starts, 0), 0);
+        assert_eq!(row_of(&starts, 6), 1);
+        assert_eq!(row_of(&starts, 15), 2);
+        assert_eq!(row_end(&starts, 0, 15), 5);
+        assert_eq!(row_end(&starts, 2, 15), 15);
+    }
+}
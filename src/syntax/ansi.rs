@@ -0,0 +1,150 @@
+//! ANSI escape codes
+//!
+//! Build and CI logs color their output with SGR sequences (`ESC[31m`).
+//! This finds them, strips them, or turns the foreground colors into
+//! highlighting tokens for a colored read-only view. Other escape sequences
+//! (cursor movement, erase line, OSC titles) are dropped.
+
+use super::highlight::{Token, TokenType};
+
+const ESC: char = '\u{1b}';
+
+/// Escape sequences that mark a file as colored output
+const HEAVY_MIN_SEQUENCES: usize = 5;
+
+/// Characters at the start of a file worth examining with `is_ansi_heavy`
+pub const SAMPLE_CHARS: usize = 64 * 1024;
+
+/// Text without escape codes, with each line's colored runs
+#[derive(Debug, Default)]
+pub struct Styled {
+    pub text: String,
+    pub lines: Vec<Vec<Token>>,
+}
+
+/// Whether `text`, the start of a file, looks like colored terminal output
+/// rather than a file that happens to contain an escape character
+pub fn is_ansi_heavy(text: &str) -> bool {
+    text.matches("\u{1b}[").take(HEAVY_MIN_SEQUENCES).count() >= HEAVY_MIN_SEQUENCES
+}
+
+/// `text` without escape codes
+pub fn strip(text: &str) -> String {
+    parse(text).text
+}
+
+/// Split `text` into plain text and tokens for its foreground colors
+pub fn parse(text: &str) -> Styled {
+    let mut styled = Styled::default();
+    let mut line_tokens: Vec<Token> = Vec::new();
+    let mut color: Option<TokenType> = None;
+    let mut col = 0;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ESC => match chars.next() {
+                // CSI: parameters, then a final letter
+                Some('[') => {
+                    let mut params = String::new();
+                    for c in chars.by_ref() {
+                        if ('\u{40}'..='\u{7e}').contains(&c) {
+                            if c == 'm' {
+                                color = apply_sgr(&params, color);
+                            }
+                            break;
+                        }
+                        params.push(c);
+                    }
+                }
+                // OSC: up to BEL or ESC backslash
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\u{7}' || (c == ESC && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\n' => {
+                styled.text.push(c);
+                styled.lines.push(std::mem::take(&mut line_tokens));
+                col = 0;
+            }
+            _ => {
+                styled.text.push(c);
+                if let Some(token_type) = color {
+                    match line_tokens.last_mut() {
+                        Some(last) if last.end == col && last.token_type == token_type => last.end += 1,
+                        _ => line_tokens.push(Token { token_type, start: col, end: col + 1 }),
+                    }
+                }
+                col += 1;
+            }
+        }
+    }
+    styled.lines.push(line_tokens);
+    styled
+}
+
+/// The foreground color after an SGR sequence with parameters `params`
+fn apply_sgr(params: &str, color: Option<TokenType>) -> Option<TokenType> {
+    let codes: Vec<u16> = params.split(';').map(|p| p.parse().unwrap_or(0)).collect();
+    let mut color = color;
+    let mut i = 0;
+    while i < codes.len() {
+        match codes[i] {
+            0 | 39 => color = None,
+            code @ 30..=37 => color = Some(TokenType::Ansi((code - 30) as u8)),
+            code @ 90..=97 => color = Some(TokenType::Ansi((code - 90 + 8) as u8)),
+            38 => match codes.get(i + 1) {
+                Some(5) => {
+                    color = codes.get(i + 2).map(|&n| TokenType::Ansi(n as u8));
+                    i += 2;
+                }
+                Some(2) => {
+                    if let [r, g, b] = codes.get(i + 2..i + 5).unwrap_or_default() {
+                        color = Some(TokenType::Rgb(*r as u8, *g as u8, *b as u8));
+                    }
+                    i += 4;
+                }
+                _ => {}
+            },
+            // Background colors carry their own arguments to skip
+            48 => i += if codes.get(i + 1) == Some(&2) { 4 } else { 2 },
+            _ => {}
+        }
+        i += 1;
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip() {
+        assert_eq!(strip("\u{1b}[1;31merror\u{1b}[0m: failed\u{1b}[K"), "error: failed");
+        assert_eq!(strip("\u{1b}]0;title\u{7}plain"), "plain");
+        assert_eq!(strip("no codes"), "no codes");
+    }
+
+    #[test]
+    fn test_parse_colors() {
+        let styled = parse("ok \u{1b}[32mpass\u{1b}[0m\n\u{1b}[38;5;208mwarn\u{1b}[39m!");
+        assert_eq!(styled.text, "ok pass\nwarn!");
+        assert_eq!(styled.lines.len(), 2);
+        let first = &styled.lines[0];
+        assert_eq!((first[0].start, first[0].end, first[0].token_type), (3, 7, TokenType::Ansi(2)));
+        let second = &styled.lines[1];
+        assert_eq!((second[0].start, second[0].end, second[0].token_type), (0, 4, TokenType::Ansi(208)));
+    }
+
+    #[test]
+    fn test_is_ansi_heavy() {
+        assert!(is_ansi_heavy(&"\u{1b}[31mx\u{1b}[0m\n".repeat(3)));
+        assert!(!is_ansi_heavy("one \u{1b}[1m escape"));
+    }
+}
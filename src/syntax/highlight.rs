@@ -2,8 +2,10 @@
 
 #![allow(dead_code)]
 
-use super::languages::{Language, LanguageDef};
-use crossterm::style::Color;
+use super::languages::{self, Language, LanguageDef};
+
+/// Lines tokenized ahead of the one asked for by `Highlighter::state_near`
+const NEAR_LINES: usize = 200;
 
 /// Token types for syntax highlighting
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -19,26 +21,13 @@ pub enum TokenType {
     Preprocessor,
     Attribute,
     Punctuation,
+    /// Terminal palette color from an ANSI escape code
+    Ansi(u8),
+    /// 24-bit color from an ANSI escape code
+    Rgb(u8, u8, u8),
 }
 
 impl TokenType {
-    /// Get the foreground color for this token type
-    pub fn color(&self) -> Color {
-        match self {
-            TokenType::Plain => Color::Reset,
-            TokenType::Keyword => Color::Blue,
-            TokenType::String => Color::Green,
-            TokenType::Number => Color::Magenta,
-            TokenType::Comment => Color::DarkGrey,
-            TokenType::Operator => Color::Yellow,
-            TokenType::Type => Color::Cyan,
-            TokenType::Function => Color::Cyan,
-            TokenType::Preprocessor => Color::Magenta,
-            TokenType::Attribute => Color::Yellow,
-            TokenType::Punctuation => Color::DarkGrey,
-        }
-    }
-
     /// Whether this token type should be bold
     pub fn bold(&self) -> bool {
         matches!(self, TokenType::Keyword | TokenType::Function)
@@ -77,6 +66,8 @@ pub struct Highlighter {
     state_cache: Vec<HighlightState>,
     /// Line index from which cache is invalid (everything from this line onward needs recalc)
     cache_valid_until: usize,
+    /// Fixed tokens per line (an ANSI colored view), used instead of the language
+    line_tokens: Option<Vec<Vec<Token>>>,
 }
 
 impl Default for Highlighter {
@@ -93,18 +84,19 @@ impl Highlighter {
             state: HighlightState::default(),
             state_cache: Vec::new(),
             cache_valid_until: 0,
+            line_tokens: None,
         }
     }
 
     /// Detect and set language based on filename
     pub fn detect_language(&mut self, filename: &str) {
-        self.language = Language::detect(filename).map(|l| l.definition());
+        self.language = languages::definition_for(filename);
         self.invalidate_cache(0);
     }
 
     /// Set language explicitly
     pub fn set_language(&mut self, lang: Language) {
-        self.language = Some(lang.definition());
+        self.language = Some(languages::definition_of(lang));
         self.invalidate_cache(0);
     }
 
@@ -114,6 +106,19 @@ impl Highlighter {
         self.invalidate_cache(0);
     }
 
+    /// Color each line with the given tokens instead of tokenizing it
+    pub fn set_line_tokens(&mut self, tokens: Vec<Vec<Token>>) {
+        self.line_tokens = Some(tokens);
+    }
+
+    /// Tokens for line `line_idx`: the fixed ones if set, else `tokenize_line`
+    pub fn tokens_for_line(&self, line_idx: usize, line: &str, state: &mut HighlightState) -> Vec<Token> {
+        match &self.line_tokens {
+            Some(lines) => lines.get(line_idx).cloned().unwrap_or_default(),
+            None => self.tokenize_line(line, state),
+        }
+    }
+
     /// Check if highlighting is enabled
     pub fn is_enabled(&self) -> bool {
         self.language.is_some()
@@ -129,6 +134,46 @@ impl Highlighter {
         self.language.as_ref().and_then(|l| l.line_comment)
     }
 
+    /// Line comment prefixes that Enter carries onto the next line, longest
+    /// first: the language's doc comment forms, then the plain prefix
+    pub fn continued_comments(&self) -> Vec<&'static str> {
+        match self.line_comment() {
+            Some("//") => vec!["///", "//!", "//"],
+            Some("--") => vec!["---", "--"],
+            Some("#") => vec!["##", "#"],
+            Some(prefix) => vec![prefix],
+            None => Vec::new(),
+        }
+    }
+
+    /// Closing character to auto-insert after typing `c`, from the language's
+    /// extra bracket pairs and string delimiters
+    pub fn auto_pair(&self, c: char) -> Option<char> {
+        let lang = self.language.as_ref()?;
+        lang.brackets.iter()
+            .find(|(open, _)| *open == c)
+            .map(|(_, close)| *close)
+            .or_else(|| lang.string_delimiters.contains(&c).then_some(c))
+    }
+
+    /// Whether `c` closes one of the language's extra bracket pairs or a string
+    pub fn closes_pair(&self, c: char) -> bool {
+        self.language.as_ref().is_some_and(|l| {
+            l.brackets.iter().any(|(_, close)| *close == c) || l.string_delimiters.contains(&c)
+        })
+    }
+
+    /// Whether a line ending in `:` opens an indented block (Python's
+    /// `def f():`, a YAML mapping key)
+    pub fn colon_opens_block(&self) -> bool {
+        matches!(self.language_name(), Some("Python" | "Nim" | "YAML"))
+    }
+
+    /// Whether the language's block comments are C-style `/* ... */`
+    pub fn has_c_block_comments(&self) -> bool {
+        self.language.as_ref().is_some_and(|l| l.block_comment_start == Some("/*"))
+    }
+
     /// Reset multiline state (call when buffer changes significantly)
     pub fn reset_state(&mut self) {
         self.invalidate_cache(0);
@@ -141,6 +186,20 @@ impl Highlighter {
         self.state = HighlightState::default();
     }
 
+    /// Approximate state at the start of `line_idx`, from tokenizing just
+    /// the `NEAR_LINES` before it (fetched with `line`) and leaving the cache
+    /// alone. For large files, where filling the cache from the top is too
+    /// slow; a comment or string opened further up is missed.
+    pub fn state_near(&self, line_idx: usize, line: impl Fn(usize) -> Option<String>) -> HighlightState {
+        let mut state = HighlightState::default();
+        for idx in line_idx.saturating_sub(NEAR_LINES)..line_idx {
+            if let Some(text) = line(idx) {
+                let _ = self.tokenize_line(&text, &mut state);
+            }
+        }
+        state
+    }
+
     /// Get the starting highlight state for a given line by looking up the cache.
     /// Returns the state after processing (line_idx - 1), or default state for line 0.
     pub fn get_state_for_line(&self, line_idx: usize) -> HighlightState {
@@ -690,4 +749,38 @@ mod tests {
         assert_eq!(tokens.len(), 1);
         assert_eq!(tokens[0].token_type, TokenType::Comment);
     }
+
+    #[test]
+    fn test_continued_comments() {
+        let mut hl = Highlighter::new();
+        assert!(hl.continued_comments().is_empty());
+        hl.set_language(Language::Rust);
+        assert_eq!(hl.continued_comments(), vec!["///", "//!", "//"]);
+        assert!(hl.has_c_block_comments());
+    }
+
+    #[test]
+    fn test_config_defined_language() {
+        let config = languages::LanguageOverride {
+            extensions: vec!["zork".to_string()],
+            line_comment: Some("%".to_string()),
+            brackets: vec!["<>".to_string()],
+            string_delimiters: Some("$".to_string()),
+            ..Default::default()
+        };
+        languages::set_overrides(&[("zork".to_string(), config)].into_iter().collect());
+
+        let mut hl = Highlighter::new();
+        hl.detect_language("level1.zork");
+        assert_eq!(hl.language_name(), Some("zork"));
+        assert_eq!(hl.line_comment(), Some("%"));
+        assert_eq!(hl.auto_pair('<'), Some('>'));
+        assert_eq!(hl.auto_pair('$'), Some('$'));
+        assert!(hl.closes_pair('>'));
+
+        let mut state = HighlightState::default();
+        let tokens = hl.tokenize_line("$lamp$ % take it", &mut state);
+        assert_eq!(tokens[0].token_type, TokenType::String);
+        assert_eq!(tokens.last().unwrap().token_type, TokenType::Comment);
+    }
 }
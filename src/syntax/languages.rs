@@ -2,7 +2,9 @@
 
 #![allow(dead_code)]
 
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::RwLock;
 
 /// Supported programming languages
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -58,6 +60,20 @@ pub enum Language {
 }
 
 impl Language {
+    pub const ALL: [Language; 48] = [
+        Language::Rust, Language::Python, Language::JavaScript, Language::TypeScript,
+        Language::C, Language::Cpp, Language::Go, Language::Java, Language::Kotlin,
+        Language::Swift, Language::Ruby, Language::Php, Language::CSharp, Language::Scala,
+        Language::Haskell, Language::Lua, Language::Perl, Language::R, Language::Julia,
+        Language::Elixir, Language::Erlang, Language::Clojure, Language::Fortran,
+        Language::Zig, Language::Nim, Language::Odin, Language::V, Language::D,
+        Language::Bash, Language::Fish, Language::Zsh, Language::PowerShell,
+        Language::Sql, Language::Html, Language::Css, Language::Json, Language::Yaml,
+        Language::Toml, Language::Xml, Language::Markdown, Language::Makefile,
+        Language::Dockerfile, Language::Terraform, Language::Nix, Language::Ocaml,
+        Language::Fsharp, Language::Dart, Language::Groovy,
+    ];
+
     /// Detect language from filename/extension
     pub fn detect(filename: &str) -> Option<Language> {
         let lower = filename.to_lowercase();
@@ -222,6 +238,11 @@ impl Language {
         }
     }
 
+    /// Built-in language with display name `name`, ignoring case
+    pub fn from_name(name: &str) -> Option<Language> {
+        Self::ALL.into_iter().find(|lang| lang.name().eq_ignore_ascii_case(name))
+    }
+
     /// Get display name
     pub fn name(&self) -> &'static str {
         match self {
@@ -292,6 +313,8 @@ pub struct LanguageDef {
     pub punctuation: Vec<char>,
     pub has_preprocessor: bool,
     pub case_sensitive: bool,
+    /// Bracket pairs auto-closed besides (), [] and {}, e.g. `<>` for templates
+    pub brackets: Vec<(char, char)>,
 }
 
 impl Default for LanguageDef {
@@ -309,7 +332,122 @@ impl Default for LanguageDef {
             punctuation: vec![],
             has_preprocessor: false,
             case_sensitive: true,
+            brackets: Vec::new(),
+        }
+    }
+}
+
+/// Per-language settings from the workspace config, keyed by lowercase
+/// language name. A name that isn't a built-in language defines a new one
+/// (without keyword highlighting) for its `extensions`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanguageOverride {
+    /// File extensions, without the dot, that open as this language
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_comment: Option<String>,
+    /// Block comment start and end, e.g. `["{-", "-}"]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_comment: Option<(String, String)>,
+    /// Extra bracket pairs as two-character strings, e.g. `["<>"]`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub brackets: Vec<String>,
+    /// Characters that open and close strings, replacing the built-in set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub string_delimiters: Option<String>,
+}
+
+/// A config override ready to apply. Its strings are leaked into the
+/// `&'static str` fields the built-in definitions use; the config is loaded
+/// once per session, so that's a few bytes.
+#[derive(Debug)]
+struct Customization {
+    name: &'static str,
+    extensions: Vec<String>,
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    brackets: Vec<(char, char)>,
+    string_delimiters: Option<Vec<char>>,
+}
+
+impl Customization {
+    fn new(name: &str, config: &LanguageOverride) -> Self {
+        let leak = |s: &str| -> &'static str { Box::leak(s.to_string().into_boxed_str()) };
+        Self {
+            name: leak(name),
+            extensions: config.extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect(),
+            line_comment: config.line_comment.as_deref().map(leak),
+            block_comment: config.block_comment.as_ref().map(|(start, end)| (leak(start), leak(end))),
+            brackets: config.brackets.iter().filter_map(|pair| {
+                let mut chars = pair.chars();
+                match (chars.next(), chars.next(), chars.next()) {
+                    (Some(open), Some(close), None) => Some((open, close)),
+                    _ => None,
+                }
+            }).collect(),
+            string_delimiters: config.string_delimiters.as_ref().map(|d| d.chars().collect()),
+        }
+    }
+
+    fn apply(&self, def: &mut LanguageDef) {
+        if let Some(comment) = self.line_comment {
+            def.line_comment = Some(comment);
         }
+        if let Some((start, end)) = self.block_comment {
+            def.block_comment_start = Some(start);
+            def.block_comment_end = Some(end);
+        }
+        if let Some(ref delimiters) = self.string_delimiters {
+            def.string_delimiters = delimiters.clone();
+        }
+        def.brackets.extend(&self.brackets);
+    }
+}
+
+/// Overrides from the workspace config
+static CUSTOMIZATIONS: RwLock<Vec<Customization>> = RwLock::new(Vec::new());
+
+/// Install the workspace config's language overrides; definitions created
+/// afterwards include them
+pub fn set_overrides(overrides: &BTreeMap<String, LanguageOverride>) {
+    let customizations = overrides.iter().map(|(name, config)| Customization::new(name, config)).collect();
+    if let Ok(mut current) = CUSTOMIZATIONS.write() {
+        *current = customizations;
+    }
+}
+
+/// Definition for a file: a language the config assigns its extension to,
+/// else the detected built-in language, with overrides applied
+pub fn definition_for(filename: &str) -> Option<LanguageDef> {
+    let customizations = CUSTOMIZATIONS.read().ok()?;
+    let lower = filename.to_lowercase();
+    let ext = lower.rsplit_once('.').map(|(_, ext)| ext);
+    let configured = customizations.iter().find(|c| ext.is_some_and(|ext| c.extensions.iter().any(|e| e == ext)));
+    let mut def = match configured {
+        Some(custom) => match Language::from_name(custom.name) {
+            Some(lang) => lang.definition(),
+            None => LanguageDef { name: custom.name, ..LanguageDef::default() },
+        },
+        None => Language::detect(filename)?.definition(),
+    };
+    customize(&customizations, &mut def);
+    Some(def)
+}
+
+/// A built-in language's definition with overrides applied
+pub fn definition_of(lang: Language) -> LanguageDef {
+    let mut def = lang.definition();
+    if let Ok(customizations) = CUSTOMIZATIONS.read() {
+        customize(&customizations, &mut def);
+    }
+    def
+}
+
+fn customize(customizations: &[Customization], def: &mut LanguageDef) {
+    if let Some(custom) = customizations.iter().find(|c| c.name.eq_ignore_ascii_case(def.name)) {
+        custom.apply(def);
     }
 }
 
@@ -352,6 +490,7 @@ fn rust_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -384,6 +523,7 @@ fn python_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ':', ',', '.', ';'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -414,6 +554,7 @@ fn javascript_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -461,6 +602,7 @@ fn c_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: true,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -517,6 +659,7 @@ fn go_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -549,6 +692,7 @@ fn java_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -582,6 +726,7 @@ fn kotlin_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -617,6 +762,7 @@ fn swift_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -654,6 +800,7 @@ fn ruby_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', '.', ':', '@', '$'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -687,6 +834,7 @@ fn php_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', '.', ':', '$', '@'],
         has_preprocessor: false,
         case_sensitive: false,
+        brackets: Vec::new(),
     }
 }
 
@@ -724,6 +872,7 @@ fn csharp_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: true,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -754,6 +903,7 @@ fn scala_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -782,6 +932,7 @@ fn haskell_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', '`'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -806,6 +957,7 @@ fn lua_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', '.', ':'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -832,6 +984,7 @@ fn perl_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', ':', '$', '@', '%'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -861,6 +1014,7 @@ fn r_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ','],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -894,6 +1048,7 @@ fn julia_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', '.', ':'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -926,6 +1081,7 @@ fn elixir_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', '.', ':', '@', '%'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -951,6 +1107,7 @@ fn erlang_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', '.', ':', '|'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -976,6 +1133,7 @@ fn clojure_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ':', '\''],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1019,6 +1177,7 @@ fn fortran_def() -> LanguageDef {
         punctuation: vec!['(', ')', '[', ']', ',', ':', '%'],
         has_preprocessor: false,
         case_sensitive: false,
+        brackets: Vec::new(),
     }
 }
 
@@ -1050,6 +1209,7 @@ fn zig_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1086,6 +1246,7 @@ fn nim_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', '.', ':'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1117,6 +1278,7 @@ fn odin_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1145,6 +1307,7 @@ fn v_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1180,6 +1343,7 @@ fn d_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1208,6 +1372,7 @@ fn bash_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', '$', '`'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1231,6 +1396,7 @@ fn fish_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', '$'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1276,6 +1442,7 @@ fn powershell_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', '$', '@', ',', '.'],
         has_preprocessor: false,
         case_sensitive: false,
+        brackets: Vec::new(),
     }
 }
 
@@ -1313,6 +1480,7 @@ fn sql_def() -> LanguageDef {
         punctuation: vec!['(', ')', ',', '.', ';', ':'],
         has_preprocessor: false,
         case_sensitive: false,
+        brackets: Vec::new(),
     }
 }
 
@@ -1330,6 +1498,7 @@ fn html_def() -> LanguageDef {
         punctuation: vec!['<', '>', '/', '!'],
         has_preprocessor: false,
         case_sensitive: false,
+        brackets: Vec::new(),
     }
 }
 
@@ -1350,6 +1519,7 @@ fn css_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', '.', '#'],
         has_preprocessor: false,
         case_sensitive: false,
+        brackets: Vec::new(),
     }
 }
 
@@ -1367,6 +1537,7 @@ fn json_def() -> LanguageDef {
         punctuation: vec!['{', '}', '[', ']', ','],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1386,6 +1557,7 @@ fn yaml_def() -> LanguageDef {
         punctuation: vec!['{', '}', '[', ']', ','],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1403,6 +1575,7 @@ fn toml_def() -> LanguageDef {
         punctuation: vec!['{', '}', '[', ']', ',', '.'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1420,6 +1593,7 @@ fn xml_def() -> LanguageDef {
         punctuation: vec!['<', '>', '/', '?', '!'],
         has_preprocessor: true, // <?xml ... ?>
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1437,6 +1611,7 @@ fn markdown_def() -> LanguageDef {
         punctuation: vec![],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1461,6 +1636,7 @@ fn makefile_def() -> LanguageDef {
         punctuation: vec!['$', '(', ')', '{', '}', '%', '*', '?', '<', '>'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1482,6 +1658,7 @@ fn dockerfile_def() -> LanguageDef {
         punctuation: vec!['[', ']', '{', '}', '$'],
         has_preprocessor: false,
         case_sensitive: false,
+        brackets: Vec::new(),
     }
 }
 
@@ -1507,6 +1684,7 @@ fn terraform_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ',', '.'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1527,6 +1705,7 @@ fn nix_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ',', '.', ';'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1560,6 +1739,7 @@ fn ocaml_def() -> LanguageDef {
         punctuation: vec!['{', '}', '(', ')', '[', ']', ';', ',', '.', ':'],
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
@@ -1604,6 +1784,7 @@ fn dart_def() -> LanguageDef {
         punctuation: C_PUNCTUATION.to_vec(),
         has_preprocessor: false,
         case_sensitive: true,
+        brackets: Vec::new(),
     }
 }
 
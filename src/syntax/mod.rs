@@ -1,6 +1,8 @@
 //! Syntax highlighting module
 
+pub mod ansi;
 mod highlight;
 mod languages;
 
-pub use highlight::{Highlighter, Token};
+pub use highlight::{HighlightState, Highlighter, Token, TokenType};
+pub use languages::{set_overrides, LanguageOverride};
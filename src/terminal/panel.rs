@@ -13,6 +13,10 @@ const DEFAULT_HEIGHT_PERCENT: u16 = 30;
 const MAX_HEIGHT_PERCENT: u16 = 80;
 /// Minimum terminal height in rows
 const MIN_HEIGHT_ROWS: u16 = 3;
+/// Minimum terminal width in columns when docked right
+const MIN_WIDTH_COLS: u16 = 20;
+/// Height presets cycled from the keyboard, as percentages of screen
+const HEIGHT_PRESETS: [u16; 3] = [25, 50, 75];
 
 /// A single terminal session (PTY + screen buffer)
 pub struct TerminalSession {
@@ -102,6 +106,18 @@ pub struct TerminalPanel {
     pub visible: bool,
     /// Terminal height in rows
     pub height: u16,
+    /// Terminal width in columns
+    pub width: u16,
+    /// Docked to the right edge instead of the bottom: full height, with
+    /// the size percentage applying to the width
+    docked_right: bool,
+    /// Height (or width, docked right) as a percentage of the screen, kept
+    /// across screen resizes
+    height_percent: u16,
+    /// Whether the terminal covers the whole screen (height_percent is kept for restore)
+    maximized: bool,
+    /// Kept open when focus leaves it, with `layout.auto_hide` on
+    pub pinned: bool,
     /// Total screen height (for percentage calculations)
     screen_height: u16,
     /// Total screen width
@@ -117,6 +133,11 @@ impl TerminalPanel {
             active_session: 0,
             visible: false,
             height,
+            width: screen_width,
+            docked_right: false,
+            height_percent: DEFAULT_HEIGHT_PERCENT,
+            maximized: false,
+            pinned: false,
             screen_height,
             screen_width,
         }
@@ -142,8 +163,8 @@ impl TerminalPanel {
     /// Create a new terminal session
     pub fn new_session(&mut self) -> Result<()> {
         let content_height = self.content_height();
-        let mut session = TerminalSession::new(self.screen_width, content_height);
-        session.spawn(self.screen_width, content_height)?;
+        let mut session = TerminalSession::new(self.width, content_height);
+        session.spawn(self.width, content_height)?;
         self.sessions.push(session);
         self.active_session = self.sessions.len() - 1;
         Ok(())
@@ -227,6 +248,20 @@ impl TerminalPanel {
         Ok(())
     }
 
+    /// Paste text into the active terminal, bracketed if its program asked
+    /// for that; line breaks go in as carriage returns, as typed
+    pub fn paste(&mut self, text: &str) -> Result<()> {
+        let Some(session) = self.sessions.get_mut(self.active_session) else {
+            return Ok(());
+        };
+        let text = text.replace('\n', "\r");
+        if session.screen.bracketed_paste() {
+            session.send_input(format!("\x1b[200~{}\x1b[201~", text).as_bytes())
+        } else {
+            session.send_input(text.as_bytes())
+        }
+    }
+
     /// Send a key to the active terminal
     pub fn send_key(&mut self, key: &crossterm::event::KeyEvent) -> Result<()> {
         use crossterm::event::{KeyCode, KeyModifiers};
@@ -339,14 +374,28 @@ impl TerminalPanel {
         self.screen_height = height;
 
         // Recalculate terminal height (maintain percentage)
-        let max_height = height * MAX_HEIGHT_PERCENT / 100;
-        self.height = self.height.min(max_height).max(MIN_HEIGHT_ROWS);
+        self.apply_height();
+    }
 
-        let content_height = self.content_height();
+    /// Dock to the right edge, or back to the bottom
+    pub fn set_docked_right(&mut self, right: bool) {
+        if self.docked_right != right {
+            self.docked_right = right;
+            self.apply_height();
+        }
+    }
 
-        // Resize all sessions
-        for session in &mut self.sessions {
-            session.resize(width, content_height);
+    /// Whether the panel is docked to the right edge
+    pub fn is_docked_right(&self) -> bool {
+        self.docked_right
+    }
+
+    /// Rows (or columns, docked right) the panel takes along its edge
+    pub fn size(&self) -> u16 {
+        if self.docked_right {
+            self.width
+        } else {
+            self.height
         }
     }
 
@@ -354,18 +403,76 @@ impl TerminalPanel {
     pub fn resize_height(&mut self, new_height: u16) {
         let max_height = self.screen_height * MAX_HEIGHT_PERCENT / 100;
         self.height = new_height.min(max_height).max(MIN_HEIGHT_ROWS);
+        self.maximized = false;
+        // Round to the nearest percent so the height survives a restart
+        let percent = (self.height as u32 * 100 + self.screen_height as u32 / 2) / self.screen_height.max(1) as u32;
+        self.height_percent = (percent as u16).max(1);
+        self.resize_sessions();
+    }
 
-        let content_height = self.content_height();
+    /// Current height as a percentage of the screen (ignoring maximize)
+    pub fn height_percent(&self) -> u16 {
+        self.height_percent
+    }
 
-        // Resize all sessions
-        for session in &mut self.sessions {
-            session.resize(self.screen_width, content_height);
+    /// Set height as a percentage of the screen
+    pub fn set_height_percent(&mut self, percent: u16) {
+        self.maximized = false;
+        self.height_percent = percent.clamp(1, MAX_HEIGHT_PERCENT);
+        self.apply_height();
+    }
+
+    /// Switch to the next height preset above the current height (wrapping).
+    /// Returns the new percentage.
+    pub fn cycle_height_preset(&mut self) -> u16 {
+        let next = if self.maximized {
+            HEIGHT_PRESETS[0]
+        } else {
+            HEIGHT_PRESETS.iter()
+                .copied()
+                .find(|&p| p > self.height_percent)
+                .unwrap_or(HEIGHT_PRESETS[0])
+        };
+        self.set_height_percent(next);
+        next
+    }
+
+    /// Toggle covering the whole screen. Returns true if now maximized.
+    pub fn toggle_maximize(&mut self) -> bool {
+        self.maximized = !self.maximized;
+        self.apply_height();
+        self.maximized
+    }
+
+    /// Recompute the size from the percentage and resize sessions
+    fn apply_height(&mut self) {
+        if self.docked_right {
+            self.height = self.screen_height.max(MIN_HEIGHT_ROWS);
+            self.width = if self.maximized {
+                self.screen_width
+            } else {
+                let max_width = self.screen_width * MAX_HEIGHT_PERCENT / 100;
+                (self.screen_width * self.height_percent / 100).min(max_width).max(MIN_WIDTH_COLS)
+            };
+            self.resize_sessions();
+            return;
         }
+        self.width = self.screen_width;
+        self.height = if self.maximized {
+            self.screen_height.max(MIN_HEIGHT_ROWS)
+        } else {
+            let max_height = self.screen_height * MAX_HEIGHT_PERCENT / 100;
+            (self.screen_height * self.height_percent / 100).min(max_height).max(MIN_HEIGHT_ROWS)
+        };
+        self.resize_sessions();
     }
 
-    /// Get the starting row for rendering (from bottom of screen)
-    pub fn render_start_row(&self, total_rows: u16) -> u16 {
-        total_rows.saturating_sub(self.height)
+    /// Resize all sessions to the current content height
+    fn resize_sessions(&mut self) {
+        let content_height = self.content_height();
+        for session in &mut self.sessions {
+            session.resize(self.width, content_height);
+        }
     }
 
     /// Convert terminal Color to crossterm Color
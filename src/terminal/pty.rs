@@ -10,6 +10,27 @@ use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
 
+use crate::util::status;
+
+/// The user's interactive shell: $SHELL as a login shell (falling back to
+/// /bin/sh), or PowerShell on Windows unless $SHELL is set (Git Bash, MSYS)
+fn shell_command() -> CommandBuilder {
+    if let Ok(shell) = std::env::var("SHELL") {
+        let mut cmd = CommandBuilder::new(&shell);
+        cmd.arg("-l");
+        return cmd;
+    }
+    if cfg!(windows) {
+        let mut cmd = CommandBuilder::new("powershell.exe");
+        cmd.arg("-NoLogo");
+        cmd
+    } else {
+        let mut cmd = CommandBuilder::new("/bin/sh");
+        cmd.arg("-l");
+        cmd
+    }
+}
+
 /// Manages a PTY connection to a shell process
 pub struct Pty {
     pair: PtyPair,
@@ -32,20 +53,22 @@ impl Pty {
             pixel_height: 0,
         })?;
 
-        // Get the user's shell from $SHELL, fallback to /bin/sh
-        let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-
-        let mut cmd = CommandBuilder::new(&shell);
-        // Start shell as login shell
-        cmd.arg("-l");
+        // On Windows portable-pty drives a ConPTY, so only the shell differs
+        let mut cmd = shell_command();
 
         // Set working directory to current directory
         if let Ok(cwd) = std::env::current_dir() {
+            cmd.env(status::WORKSPACE_VAR, &cwd);
             cmd.cwd(cwd);
         }
+        // Lets `fackr --status` in this shell report on this instance only
+        cmd.env(status::PID_VAR, std::process::id().to_string());
 
         // Spawn the shell
-        let _child = pair.slave.spawn_command(cmd)?;
+        let _child = pair.slave
+            .spawn_command(cmd)
+            .inspect_err(|e| tracing::warn!("failed to spawn shell: {}", e))?;
+        tracing::info!("spawned shell in a {}x{} pty", cols, rows);
 
         // Get writer for sending input to the PTY
         let writer = pair.master.take_writer()?;
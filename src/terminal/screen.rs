@@ -151,6 +151,11 @@ impl TerminalScreen {
         self.parser = parser;
     }
 
+    /// Whether the program asked for pastes wrapped in ESC [200~ / ESC [201~
+    pub fn bracketed_paste(&self) -> bool {
+        self.bracketed_paste
+    }
+
     /// Get a reference to the cell grid
     pub fn cells(&self) -> &Vec<Vec<Cell>> {
         &self.cells
@@ -0,0 +1,319 @@
+//! Palette calculator
+//!
+//! Evaluates the arithmetic typed after `=` in the command palette:
+//! `+ - * / % ^` and parentheses over decimal, `0x`, `0b` and `0o` numbers.
+//! Numbers may carry a data size or time unit (`1.5gb`, `90min`), and a
+//! trailing `in <unit>` / `to <unit>` converts the result, where the unit
+//! can also be `hex`, `bin`, `oct` or `dec`.
+
+/// A unit numbers can be written in, scaled to its dimension's base unit
+struct Unit {
+    names: &'static [&'static str],
+    dimension: Dimension,
+    scale: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Bytes,
+    Seconds,
+}
+
+const UNITS: &[Unit] = &[
+    Unit { names: &["b", "byte", "bytes"], dimension: Dimension::Bytes, scale: 1.0 },
+    Unit { names: &["kb", "kib"], dimension: Dimension::Bytes, scale: 1024.0 },
+    Unit { names: &["mb", "mib"], dimension: Dimension::Bytes, scale: 1048576.0 },
+    Unit { names: &["gb", "gib"], dimension: Dimension::Bytes, scale: 1073741824.0 },
+    Unit { names: &["tb", "tib"], dimension: Dimension::Bytes, scale: 1099511627776.0 },
+    Unit { names: &["ms"], dimension: Dimension::Seconds, scale: 0.001 },
+    Unit { names: &["s", "sec", "secs"], dimension: Dimension::Seconds, scale: 1.0 },
+    Unit { names: &["min", "mins"], dimension: Dimension::Seconds, scale: 60.0 },
+    Unit { names: &["h", "hr", "hrs"], dimension: Dimension::Seconds, scale: 3600.0 },
+    Unit { names: &["d", "day", "days"], dimension: Dimension::Seconds, scale: 86400.0 },
+];
+
+fn find_unit(name: &str) -> Option<&'static Unit> {
+    let name = name.to_ascii_lowercase();
+    UNITS.iter().find(|u| u.names.contains(&name.as_str()))
+}
+
+/// A value in its dimension's base unit, remembering the first unit it was
+/// written in for display
+#[derive(Clone, Copy)]
+struct Quantity {
+    value: f64,
+    unit: Option<&'static Unit>,
+}
+
+impl Quantity {
+    fn dimension(&self) -> Option<Dimension> {
+        self.unit.map(|u| u.dimension)
+    }
+}
+
+/// How the result should be written
+enum Radix {
+    Hex,
+    Bin,
+    Oct,
+    Dec,
+}
+
+/// Evaluate `input` (without the leading `=`) to its display string
+pub fn evaluate(input: &str) -> Result<String, String> {
+    let (expr, target) = split_conversion(input);
+    let mut parser = Parser { chars: expr.chars().collect(), pos: 0 };
+    let result = parser.expr()?;
+    parser.skip_spaces();
+    if parser.pos < parser.chars.len() {
+        return Err(format!("Unexpected '{}'", parser.chars[parser.pos]));
+    }
+    if !result.value.is_finite() {
+        return Err("Result is not a number".to_string());
+    }
+
+    match target {
+        None => Ok(format_quantity(result.value, result.unit)),
+        Some(name) => match name.to_ascii_lowercase().as_str() {
+            "hex" => format_radix(result, Radix::Hex),
+            "bin" => format_radix(result, Radix::Bin),
+            "oct" => format_radix(result, Radix::Oct),
+            "dec" => format_radix(result, Radix::Dec),
+            _ => {
+                let unit = find_unit(name).ok_or_else(|| format!("Unknown unit '{}'", name))?;
+                if result.dimension().is_some_and(|d| d != unit.dimension) {
+                    return Err(format!("Can't convert to {}", name));
+                }
+                Ok(format_quantity(result.value, Some(unit)))
+            }
+        },
+    }
+}
+
+/// Split off a trailing `in <target>` / `to <target>`
+fn split_conversion(input: &str) -> (&str, Option<&str>) {
+    let trimmed = input.trim_end();
+    let Some((expr, target)) = trimmed.rsplit_once(char::is_whitespace) else {
+        return (trimmed, None);
+    };
+    let expr = expr.trim_end();
+    for keyword in ["in", "to"] {
+        if let Some(rest) = expr.strip_suffix(keyword) {
+            if rest.ends_with(|c: char| c.is_whitespace()) && target.chars().all(|c| c.is_ascii_alphabetic()) {
+                return (rest, Some(target));
+            }
+        }
+    }
+    (trimmed, None)
+}
+
+fn format_radix(result: Quantity, radix: Radix) -> Result<String, String> {
+    if result.unit.is_some_and(|u| u.scale < 1.0) || result.value.fract() != 0.0 || result.value.abs() > i64::MAX as f64 {
+        return Err("Only whole numbers convert to another base".to_string());
+    }
+    let n = result.value as i64;
+    let sign = if n < 0 { "-" } else { "" };
+    let abs = n.unsigned_abs();
+    Ok(match radix {
+        Radix::Hex => format!("{}0x{:x}", sign, abs),
+        Radix::Bin => format!("{}0b{:b}", sign, abs),
+        Radix::Oct => format!("{}0o{:o}", sign, abs),
+        Radix::Dec => n.to_string(),
+    })
+}
+
+fn format_quantity(value: f64, unit: Option<&'static Unit>) -> String {
+    match unit {
+        Some(unit) => format!("{} {}", format_number(value / unit.scale), unit.names[0]),
+        None => format_number(value),
+    }
+}
+
+/// Whole numbers without a fraction, others to at most ten decimals
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        return format!("{}", value as i64);
+    }
+    let text = format!("{:.10}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Recursive descent over the expression's characters
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_spaces(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expr(&mut self) -> Result<Quantity, String> {
+        let mut left = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            let right = self.term()?;
+            let unit = match (left.dimension(), right.dimension()) {
+                (Some(a), Some(b)) if a != b => return Err("Mismatched units".to_string()),
+                _ => left.unit.or(right.unit),
+            };
+            let value = if op == '+' { left.value + right.value } else { left.value - right.value };
+            left = Quantity { value, unit };
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Quantity, String> {
+        let mut left = self.unary()?;
+        while let Some(op @ ('*' | '/' | '%')) = self.peek() {
+            self.pos += 1;
+            let right = self.unary()?;
+            if op != '*' && right.value == 0.0 {
+                return Err("Division by zero".to_string());
+            }
+            left = match op {
+                '*' if left.unit.is_some() && right.unit.is_some() => {
+                    return Err("Can't multiply two units".to_string())
+                }
+                '*' => Quantity { value: left.value * right.value, unit: left.unit.or(right.unit) },
+                '/' if left.dimension().is_some() && left.dimension() == right.dimension() => {
+                    Quantity { value: left.value / right.value, unit: None }
+                }
+                '/' if right.unit.is_some() => return Err("Can't divide by a unit".to_string()),
+                '/' => Quantity { value: left.value / right.value, unit: left.unit },
+                _ => Quantity { value: left.value % right.value, unit: left.unit },
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Quantity, String> {
+        if self.peek() == Some('-') {
+            self.pos += 1;
+            let inner = self.unary()?;
+            return Ok(Quantity { value: -inner.value, unit: inner.unit });
+        }
+        if self.peek() == Some('+') {
+            self.pos += 1;
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Quantity, String> {
+        let base = self.atom()?;
+        if self.peek() == Some('^') {
+            self.pos += 1;
+            let exponent = self.unary()?;
+            if base.unit.is_some() || exponent.unit.is_some() {
+                return Err("Can't raise units to a power".to_string());
+            }
+            return Ok(Quantity { value: base.value.powf(exponent.value), unit: None });
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Quantity, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                if self.peek() != Some(')') {
+                    return Err("Missing ')'".to_string());
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let value = self.number()?;
+                let unit = self.unit()?;
+                Ok(Quantity { value: value * unit.map_or(1.0, |u| u.scale), unit })
+            }
+            Some(c) => Err(format!("Unexpected '{}'", c)),
+            None => Err("Incomplete expression".to_string()),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        let radix = match (self.chars.get(self.pos), self.chars.get(self.pos + 1)) {
+            (Some('0'), Some('x' | 'X')) => 16,
+            (Some('0'), Some('b' | 'B')) => 2,
+            (Some('0'), Some('o' | 'O')) => 8,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.pos += 2;
+            let start = self.pos;
+            while self.chars.get(self.pos).is_some_and(|c| c.is_digit(radix) || *c == '_') {
+                self.pos += 1;
+            }
+            let digits: String = self.chars[start..self.pos].iter().filter(|c| **c != '_').collect();
+            return i64::from_str_radix(&digits, radix)
+                .map(|n| n as f64)
+                .map_err(|_| "Invalid number".to_string());
+        }
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit() || *c == '.' || *c == '_') {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().filter(|c| **c != '_').collect();
+        digits.parse().map_err(|_| format!("Invalid number '{}'", digits))
+    }
+
+    /// Unit name directly after a number, if any
+    fn unit(&mut self) -> Result<Option<&'static Unit>, String> {
+        let start = self.pos;
+        let mut end = start;
+        while self.chars.get(end).is_some_and(|c| c.is_ascii_alphabetic()) {
+            end += 1;
+        }
+        if end == start {
+            return Ok(None);
+        }
+        let name: String = self.chars[start..end].iter().collect();
+        let unit = find_unit(&name).ok_or_else(|| format!("Unknown unit '{}'", name))?;
+        self.pos = end;
+        Ok(Some(unit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(evaluate("1920/3 + 42").unwrap(), "682");
+        assert_eq!(evaluate("2 ^ 10 - (3 * -2)").unwrap(), "1030");
+        assert_eq!(evaluate("10 / 4").unwrap(), "2.5");
+        assert_eq!(evaluate("7 % 4").unwrap(), "3");
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("2 *").is_err());
+    }
+
+    #[test]
+    fn test_bases() {
+        assert_eq!(evaluate("0xff").unwrap(), "255");
+        assert_eq!(evaluate("255 in hex").unwrap(), "0xff");
+        assert_eq!(evaluate("0b1010 + 0o10 to bin").unwrap(), "0b10010");
+        assert!(evaluate("1.5 in hex").is_err());
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(evaluate("1.5gb in mb").unwrap(), "1536 mb");
+        assert_eq!(evaluate("90min to h").unwrap(), "1.5 h");
+        assert_eq!(evaluate("2 * 512kb").unwrap(), "1024 kb");
+        assert_eq!(evaluate("1gb / 256mb").unwrap(), "4");
+        assert!(evaluate("1gb + 1s").is_err());
+        assert!(evaluate("1gb in ms").is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! Shell completion scripts for the `fackr` command line
+
+/// Completion script for `shell` (`bash`, `zsh` or `fish`), or None if unsupported
+pub fn completion_script(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(BASH),
+        "zsh" => Some(ZSH),
+        "fish" => Some(FISH),
+        _ => None,
+    }
+}
+
+const BASH: &str = r#"_fackr() {
+    local cur prev
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    case "$prev" in
+        completions)
+            COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur"))
+            return ;;
+        --record|--replay)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return ;;
+    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "--list-recents --last --status --record --replay" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "completions" -- "$cur") $(compgen -f -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}
+complete -o filenames -F _fackr fackr
+"#;
+
+const ZSH: &str = r#"#compdef fackr
+
+_fackr() {
+    if (( CURRENT == 3 )) && [[ ${words[2]} == completions ]]; then
+        _values 'shell' bash zsh fish
+        return
+    fi
+    _arguments \
+        '--list-recents[print recent workspaces, most recent first]' \
+        '--last[reopen the most recent workspace]' \
+        '--status[print unsaved buffer counts of running instances]' \
+        '--record[log every input event to a file]:file:_files' \
+        '--replay[feed a recorded session back as input]:file:_files' \
+        '1:file or directory:{_alternative "commands:command:(completions)" "files:file:_files"}'
+}
+
+_fackr "$@"
+"#;
+
+const FISH: &str = r#"complete -c fackr -l list-recents -d 'Print recent workspaces, most recent first'
+complete -c fackr -l last -d 'Reopen the most recent workspace'
+complete -c fackr -l status -d 'Print unsaved buffer counts of running instances'
+complete -c fackr -l record -r -F -d 'Log every input event to a file'
+complete -c fackr -l replay -r -F -d 'Feed a recorded session back as input'
+complete -c fackr -n '__fish_use_subcommand' -a completions -d 'Generate a shell completion script'
+complete -c fackr -n '__fish_seen_subcommand_from completions' -x -a 'bash zsh fish'
+"#;
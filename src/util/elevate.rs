@@ -0,0 +1,46 @@
+//! Writing files the user can't write
+//!
+//! A save refused for lack of permission can be retried as root through
+//! `sudo tee`, or polkit's `pkexec tee` where there's no sudo. Either asks
+//! for a password on the terminal itself. Since tee writes into the
+//! existing file rather than replacing it, the file keeps its owner and mode.
+
+use anyhow::{bail, Result};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Program used to write as root: sudo, or else pkexec
+pub fn helper() -> Option<&'static str> {
+    ["sudo", "pkexec"].into_iter().find(|name| on_path(name))
+}
+
+fn on_path(name: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(name).is_file()))
+}
+
+/// Write `contents` to `path` as root. The helper may ask for a password,
+/// so the terminal must be out of raw mode.
+pub fn write_as_root(path: &Path, contents: &[u8]) -> Result<()> {
+    let Some(helper) = helper() else {
+        bail!("neither sudo nor pkexec is installed");
+    };
+    let mut child = Command::new(helper)
+        .arg("tee")
+        .arg("--")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    // Dropping stdin once written closes it, so tee sees the end
+    let written = child.stdin.take().map(|mut stdin| stdin.write_all(contents));
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{} tee failed ({})", helper, status);
+    }
+    if let Some(Err(e)) = written {
+        bail!("writing to {} tee failed: {}", helper, e);
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+//! Fuzzy matching
+//!
+//! Scores how well a typed pattern matches some text: every pattern
+//! character must appear in order, with bonuses for runs of consecutive
+//! characters and for matches at word starts. Shared by the command palette,
+//! pickers and quick open.
+
+/// Score `text` against `pattern`, case-insensitively; 0 means no match
+pub fn score(text: &str, pattern: &str) -> i32 {
+    if pattern.is_empty() {
+        return 100; // Empty pattern matches everything with base score
+    }
+
+    let text_lower = text.to_lowercase();
+    let pattern_lower = pattern.to_lowercase();
+
+    let mut score = 0i32;
+    let mut pattern_idx = 0;
+    let mut consecutive = 0;
+    let pattern_chars: Vec<char> = pattern_lower.chars().collect();
+    let text_chars: Vec<char> = text_lower.chars().collect();
+
+    if pattern_chars.is_empty() {
+        return 100;
+    }
+
+    for (i, &tc) in text_chars.iter().enumerate() {
+        if pattern_idx >= pattern_chars.len() {
+            break;
+        }
+
+        if tc == pattern_chars[pattern_idx] {
+            score += 10;
+            consecutive += 1;
+
+            // Bonus for consecutive matches
+            if consecutive > 1 {
+                score += 5;
+            }
+
+            // Bonus for matching at start or after space/separator
+            if i == 0 || matches!(text_chars.get(i.wrapping_sub(1)), Some(' ' | ':' | '-' | '_' | '/' | '.')) {
+                score += 15;
+            }
+
+            pattern_idx += 1;
+        } else {
+            consecutive = 0;
+        }
+    }
+
+    // Only return positive score if all pattern characters matched
+    if pattern_idx == pattern_chars.len() {
+        score
+    } else {
+        0
+    }
+}
+
+/// Score a relative file path. A pattern found in the file name ranks above
+/// one that needs the directories, and a pattern with a `/` is matched
+/// against the whole path.
+pub fn score_path(path: &str, pattern: &str) -> i32 {
+    let whole = score(path, pattern);
+    if pattern.contains('/') {
+        return whole;
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match score(name, pattern) {
+        0 => whole,
+        name_score => name_score * 2 + whole / 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_requires_all_chars_in_order() {
+        assert!(score("Toggle Word Wrap", "tww") > 0);
+        assert_eq!(score("Toggle Word Wrap", "wwt"), 0);
+        assert!(score("select-line", "sl") > score("pasteless", "sl"));
+    }
+
+    #[test]
+    fn test_score_path_prefers_file_name() {
+        let by_name = score_path("src/editor/state.rs", "state");
+        let by_dir = score_path("src/state/mod.rs", "state");
+        assert!(by_name > by_dir);
+        assert!(score_path("src/editor/state.rs", "ed/st") > 0);
+        assert_eq!(score_path("src/editor/state.rs", "xyz"), 0);
+    }
+}
@@ -0,0 +1,142 @@
+//! Editor log
+//!
+//! Events from `tracing` macros are appended to the active workspace's
+//! `logs/fackr.log`. The level starts at `FACKR_LOG` (or the workspace
+//! config) and can be changed while the editor runs.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+
+/// Levels in increasing verbosity, as accepted by `set_level`
+pub const LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+/// Level used when neither `FACKR_LOG` nor the config names a valid one
+pub const DEFAULT_LEVEL: &str = "info";
+
+/// Log files larger than this are truncated when opened
+const MAX_LOG_BYTES: u64 = 4 * 1024 * 1024;
+
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/// Install the global subscriber. Events are dropped until `set_file` is called.
+pub fn init() {
+    let (filter, handle) = reload::Layer::new(LevelFilter::INFO);
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_ansi(false).with_writer(|| LogWriter))
+        .try_init()
+        .is_ok();
+    if installed {
+        let _ = LEVEL.set(handle);
+        if let Ok(level) = std::env::var("FACKR_LOG") {
+            set_level(&level);
+        }
+    }
+}
+
+/// Start appending to `path`, creating its directory if needed
+pub fn set_file(path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let too_big = std::fs::metadata(path).map(|m| m.len() > MAX_LOG_BYTES).unwrap_or(false);
+    let file = OpenOptions::new()
+        .create(true)
+        .append(!too_big)
+        .write(true)
+        .truncate(too_big)
+        .open(path)?;
+    if let Ok(mut current) = LOG_FILE.lock() {
+        *current = Some(file);
+    }
+    Ok(())
+}
+
+/// Change the level; returns false if `level` isn't one of `LEVELS`
+pub fn set_level(level: &str) -> bool {
+    if !LEVELS.iter().any(|l| l.eq_ignore_ascii_case(level)) {
+        return false;
+    }
+    let Ok(filter) = level.parse::<LevelFilter>() else {
+        return false;
+    };
+    if let Some(handle) = LEVEL.get() {
+        let _ = handle.modify(|current| *current = filter);
+    }
+    true
+}
+
+/// Current level name, lowercase
+pub fn level() -> String {
+    LEVEL
+        .get()
+        .and_then(|handle| handle.clone_current())
+        .map(|filter| filter.to_string().to_ascii_lowercase())
+        .unwrap_or_else(|| DEFAULT_LEVEL.to_string())
+}
+
+/// `FACKR_LOG`, when set, wins over the configured level
+pub fn level_from_env() -> bool {
+    std::env::var_os("FACKR_LOG").is_some()
+}
+
+/// The last `max_lines` lines of the log at `path`
+pub fn tail(path: &Path, max_lines: usize) -> io::Result<String> {
+    let content = std::fs::read_to_string(path)?;
+    let lines: Vec<&str> = content.lines().collect();
+    let start = lines.len().saturating_sub(max_lines);
+    Ok(lines[start..].join("\n"))
+}
+
+/// Severity of a log line written by this module, from its level column
+pub fn line_level(line: &str) -> Option<(&'static str, usize)> {
+    // "2026-10-16T09:12:01.123456Z  WARN fackr::lsp: message"
+    let timestamp_end = line.find(' ')?;
+    let rest = &line[timestamp_end..];
+    let offset = timestamp_end + (rest.len() - rest.trim_start().len());
+    let word = rest.trim_start().split(' ').next()?;
+    let level = LEVELS.iter().find(|l| l.eq_ignore_ascii_case(word))?;
+    Some((level, offset))
+}
+
+/// Writer handed to the fmt layer; forwards to whichever file is current
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match LOG_FILE.lock() {
+            Ok(mut file) => match file.as_mut() {
+                Some(file) => file.write(buf),
+                None => Ok(buf.len()),
+            },
+            Err(_) => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match LOG_FILE.lock() {
+            Ok(mut file) => file.as_mut().map_or(Ok(()), |file| file.flush()),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_level() {
+        let line = "2026-10-16T09:12:01.123456Z  WARN fackr::lsp::manager: failed to start";
+        assert_eq!(line_level(line), Some(("warn", 29)));
+        assert_eq!(&line[29..33], "WARN");
+        assert_eq!(line_level("    at src/main.rs:10"), None);
+        assert_eq!(line_level(""), None);
+    }
+}
@@ -1 +1,10 @@
+pub mod calc;
+pub mod completions;
+pub mod elevate;
+pub mod fuzzy;
+pub mod logging;
+pub mod path;
+pub mod process;
+pub mod shell;
+pub mod status;
 pub mod unicode;
@@ -0,0 +1,158 @@
+//! Path helpers: file:// URLs (LSP URIs, OSC 7) and paths typed into prompts
+
+use std::path::{Path, PathBuf};
+
+/// Path part of a file:// URL: forward slashes, a leading slash before
+/// Windows drive letters, and percent-encoding.
+/// `/home/me/a b.rs` -> `/home/me/a%20b.rs`, `C:\src\main.rs` -> `/C:/src/main.rs`
+pub fn file_url_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut out = String::with_capacity(path.len() + 1);
+    if !path.starts_with('/') {
+        out.push('/');
+    }
+    for &b in path.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~:".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Inverse of `file_url_path`: decode and turn `/C:/src` back into a native
+/// drive path (`C:\src` on Windows)
+pub fn file_url_to_path(url_path: &str) -> String {
+    let decoded = percent_decode(url_path);
+    let bytes = decoded.as_bytes();
+    let has_drive = bytes.len() >= 3
+        && bytes[0] == b'/'
+        && bytes[1].is_ascii_alphabetic()
+        && bytes[2] == b':'
+        && (bytes.len() == 3 || bytes[3] == b'/');
+    if !has_drive {
+        return decoded;
+    }
+    let path = &decoded[1..];
+    if cfg!(windows) {
+        path.replace('/', "\\")
+    } else {
+        path.to_string()
+    }
+}
+
+/// Decode %XX escapes; invalid escapes are kept as-is
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |b: u8| (b as char).to_digit(16);
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Expand a leading `~` and `$VAR` / `${VAR}` references in a typed path;
+/// references to unset variables are kept as written
+pub fn expand_path(input: &str) -> String {
+    let home = dirs::home_dir();
+    expand_with(input, home.as_deref(), |name| match std::env::var(name) {
+        Ok(value) => Some(value),
+        Err(_) if name == "HOME" => home.as_ref().map(|h| h.display().to_string()),
+        Err(_) => None,
+    })
+}
+
+fn expand_with(input: &str, home: Option<&Path>, var: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    if let (Some(home), Some(after)) = (home, input.strip_prefix('~')) {
+        if after.is_empty() || after.starts_with('/') {
+            out.push_str(&home.display().to_string());
+            rest = after;
+        }
+    }
+    while let Some(dollar) = rest.find('$') {
+        out.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match var(name).filter(|_| !name.is_empty()) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[dollar..dollar + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Whether a typed path names its own location (`~`, `$VAR` or an absolute
+/// path) rather than one relative to wherever it's typed
+pub fn is_rooted(input: &str) -> bool {
+    let input = input.trim();
+    input.starts_with('~') || input.starts_with('$') || Path::new(input).is_absolute()
+}
+
+/// The file a prompt's `input` names: expanded, and relative to `base`
+/// unless it names its own location, which may be outside `base`
+pub fn resolve_path(base: &Path, input: &str) -> PathBuf {
+    base.join(expand_path(input.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_url_round_trip() {
+        assert_eq!(file_url_path("/home/me/a b.rs"), "/home/me/a%20b.rs");
+        assert_eq!(file_url_to_path("/home/me/a%20b.rs"), "/home/me/a b.rs");
+
+        assert_eq!(file_url_path("C:\\src\\main.rs"), "/C:/src/main.rs");
+        let drive = if cfg!(windows) { "C:\\src\\main.rs" } else { "C:/src/main.rs" };
+        assert_eq!(file_url_to_path("/C:/src/main.rs"), drive);
+        // Some clients encode the drive colon
+        assert_eq!(file_url_to_path("/c%3A/src/main.rs"), drive.replacen('C', "c", 1));
+    }
+
+    #[test]
+    fn test_expand() {
+        let home = Path::new("/home/me");
+        let var = |name: &str| (name == "SRC").then(|| "/srv/src".to_string());
+        assert_eq!(expand_with("~/notes.md", Some(home), var), "/home/me/notes.md");
+        assert_eq!(expand_with("~", Some(home), var), "/home/me");
+        assert_eq!(expand_with("~other/x", Some(home), var), "~other/x");
+        assert_eq!(expand_with("$SRC/main.rs", Some(home), var), "/srv/src/main.rs");
+        assert_eq!(expand_with("${SRC}lib/a.rs", Some(home), var), "/srv/srclib/a.rs");
+        assert_eq!(expand_with("$NOPE/a ${NOPE} ${x", Some(home), var), "$NOPE/a ${NOPE} ${x");
+        assert_eq!(expand_with("cost$", Some(home), var), "cost$");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let base = Path::new("/work");
+        assert_eq!(resolve_path(base, "src/a.rs"), Path::new("/work/src/a.rs"));
+        assert_eq!(resolve_path(base, "/etc/hosts"), Path::new("/etc/hosts"));
+        assert!(is_rooted("~/a") && is_rooted("$HOME/a") && is_rooted("/a"));
+        assert!(!is_rooted("src/a.rs"));
+    }
+}
@@ -0,0 +1,17 @@
+//! Running shell command lines on the host platform
+
+use std::process::Command;
+
+/// Command that runs `script` through the platform shell
+/// (`sh -c` on Unix, `cmd /C` on Windows)
+pub fn shell_command(script: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(script);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        cmd
+    }
+}
@@ -0,0 +1,136 @@
+//! Editor status for shell prompts
+//!
+//! Each running instance keeps a small JSON file (workspace, unsaved buffer
+//! count, whether it's suspended) in a per-user directory, rewritten when
+//! any of it changes and removed on exit. `fackr --status` reads them back
+//! for a prompt or a tmux status line: it prints e.g. "fackr: 3 unsaved" and
+//! exits 0 when something is unsaved, prints nothing and exits 1 otherwise.
+//! Inside the integrated terminal `FACKR_PID` narrows it to that instance.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::util::process::pid_alive;
+
+/// Set in the integrated terminal's shells: the editor's process id
+pub const PID_VAR: &str = "FACKR_PID";
+/// Set in the integrated terminal's shells: the workspace root
+pub const WORKSPACE_VAR: &str = "FACKR_WORKSPACE";
+
+/// What one instance reports
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    pub pid: u32,
+    /// Root of the active workspace
+    pub workspace: PathBuf,
+    /// Modified buffers across all open workspaces
+    pub unsaved: usize,
+    /// Stopped with Suspend to Shell
+    pub suspended: bool,
+}
+
+/// Directory of the status files: the runtime directory where there is
+/// one, otherwise the cache directory
+fn dir() -> Option<PathBuf> {
+    Some(dirs::runtime_dir().or_else(dirs::cache_dir)?.join("fackr").join("instances"))
+}
+
+fn file(pid: u32) -> Option<PathBuf> {
+    Some(dir()?.join(format!("{}.json", pid)))
+}
+
+/// Write this instance's status (atomically, like the workspace lock)
+pub fn publish(status: &Status) -> Result<()> {
+    let path = file(status.pid).ok_or_else(|| anyhow::anyhow!("no directory for status files"))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string(status)?)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+/// Remove this instance's status file
+pub fn withdraw() {
+    if let Some(path) = file(std::process::id()) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Statuses of the running instances, clearing out files left by ones
+/// that died
+pub fn running() -> Vec<Status> {
+    let Some(entries) = dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut statuses: Vec<Status> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| {
+            let json = std::fs::read_to_string(entry.path()).ok()?;
+            let status: Status = serde_json::from_str(&json).ok()?;
+            if !pid_alive(status.pid) {
+                let _ = std::fs::remove_file(entry.path());
+                return None;
+            }
+            Some(status)
+        })
+        .collect();
+    statuses.sort_by_key(|status| status.pid);
+    statuses
+}
+
+/// Prompt text for `statuses`, e.g. "fackr: 3 unsaved" or, with several
+/// instances, "fackr: 3 unsaved (api, web)"; None when nothing is unsaved
+pub fn summary(statuses: &[Status]) -> Option<String> {
+    let dirty: Vec<&Status> = statuses.iter().filter(|status| status.unsaved > 0).collect();
+    let unsaved: usize = dirty.iter().map(|status| status.unsaved).sum();
+    if unsaved == 0 {
+        return None;
+    }
+    if statuses.len() == 1 {
+        return Some(format!("fackr: {} unsaved", unsaved));
+    }
+    let names: Vec<String> = dirty.iter().map(|status| workspace_name(&status.workspace)).collect();
+    Some(format!("fackr: {} unsaved ({})", unsaved, names.join(", ")))
+}
+
+fn workspace_name(root: &Path) -> String {
+    root.file_name().map_or_else(|| root.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// `fackr --status`: the summary for the instance named by `FACKR_PID`, or
+/// for all of them; returns whether anything is unsaved
+pub fn print_status() -> bool {
+    let mut statuses = running();
+    if let Some(pid) = std::env::var(PID_VAR).ok().and_then(|pid| pid.parse::<u32>().ok()) {
+        statuses.retain(|status| status.pid == pid);
+    }
+    match summary(&statuses) {
+        Some(text) => {
+            println!("{}", text);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(pid: u32, workspace: &str, unsaved: usize) -> Status {
+        Status { pid, workspace: PathBuf::from(workspace), unsaved, suspended: false }
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(summary(&[]), None);
+        assert_eq!(summary(&[status(1, "/src/api", 0)]), None);
+        assert_eq!(summary(&[status(1, "/src/api", 3)]), Some("fackr: 3 unsaved".to_string()));
+        let both = [status(1, "/src/api", 2), status(2, "/src/web", 1), status(3, "/src/docs", 0)];
+        assert_eq!(summary(&both), Some("fackr: 3 unsaved (api, web)".to_string()));
+    }
+}
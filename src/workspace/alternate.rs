@@ -0,0 +1,139 @@
+//! Alternate files
+//!
+//! Each rule maps files matching one pattern to their counterpart, e.g.
+//! `*.h` to `*.c` or `*.rs` to `*_test.rs`. A pattern has a single `*`
+//! standing for the part the two names share. Patterns without a `/` match
+//! the file name alone; if either side of a rule has one, both match the
+//! path relative to the workspace root (`*/mod.rs` to `*.rs`).
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Files matching `file` have their alternate at `alternate`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlternateRule {
+    pub file: String,
+    pub alternate: String,
+    /// Initial text of a newly created alternate: `{name}` is the shared
+    /// part's last component, `{file}` the name of the file switched from
+    #[serde(default)]
+    pub template: String,
+}
+
+impl AlternateRule {
+    fn new(file: &str, alternate: &str, template: &str) -> Self {
+        Self { file: file.to_string(), alternate: alternate.to_string(), template: template.to_string() }
+    }
+
+    fn by_path(&self) -> bool {
+        self.file.contains('/') || self.alternate.contains('/')
+    }
+}
+
+/// Rules used when the workspace config doesn't list its own
+pub fn default_rules() -> Vec<AlternateRule> {
+    vec![
+        AlternateRule::new("*.h", "*.c", "#include \"{file}\"\n"),
+        AlternateRule::new("*.h", "*.cpp", "#include \"{file}\"\n"),
+        AlternateRule::new("*.hpp", "*.cpp", "#include \"{file}\"\n"),
+        AlternateRule::new("*.c", "*.h", "#pragma once\n"),
+        AlternateRule::new("*.cpp", "*.h", "#pragma once\n"),
+        AlternateRule::new("*.cpp", "*.hpp", "#pragma once\n"),
+        AlternateRule::new("*_test.rs", "*.rs", ""),
+        AlternateRule::new("*.rs", "*_test.rs", "use super::*;\n"),
+        AlternateRule::new("*/mod.rs", "*.rs", ""),
+        AlternateRule::new("*.rs", "*/mod.rs", ""),
+        AlternateRule::new("*_test.go", "*.go", ""),
+        AlternateRule::new("*.go", "*_test.go", ""),
+        AlternateRule::new("test_*.py", "*.py", ""),
+        AlternateRule::new("*.py", "test_*.py", ""),
+        AlternateRule::new("*.test.ts", "*.ts", ""),
+        AlternateRule::new("*.ts", "*.test.ts", ""),
+    ]
+}
+
+/// A counterpart of a file, which may not exist yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alternate {
+    pub path: PathBuf,
+    /// Text to create it with
+    pub template: String,
+}
+
+/// The alternate of `path`: the first candidate that exists, else the most
+/// likely one to create
+pub fn find(rules: &[AlternateRule], root: &Path, path: &Path) -> Option<Alternate> {
+    let candidates = candidates(rules, root, path);
+    candidates.iter().find(|c| c.path.exists()).or(candidates.first()).cloned()
+}
+
+/// Alternates of `path` by every rule that matches it, most specific
+/// pattern first, then in rule order
+fn candidates(rules: &[AlternateRule], root: &Path, path: &Path) -> Vec<Alternate> {
+    let Some(file_name) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+        return Vec::new();
+    };
+    let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy().into_owned();
+
+    let mut found: Vec<(usize, Alternate)> = Vec::new();
+    for rule in rules {
+        let text = if rule.by_path() { &relative } else { &file_name };
+        let Some(stem) = capture(&rule.file, text) else { continue };
+        let alternate = rule.alternate.replacen('*', stem, 1);
+        let path = if rule.by_path() {
+            root.join(&alternate)
+        } else {
+            path.with_file_name(&alternate)
+        };
+        if found.iter().any(|(_, a)| a.path == path) {
+            continue;
+        }
+        let name = stem.rsplit('/').next().unwrap_or(stem);
+        let template = rule.template.replace("{name}", name).replace("{file}", &file_name);
+        // Literal characters in the pattern measure how specific it is
+        found.push((rule.file.len(), Alternate { path, template }));
+    }
+    found.sort_by_key(|(specificity, _)| std::cmp::Reverse(*specificity));
+    found.into_iter().map(|(_, alternate)| alternate).collect()
+}
+
+/// The text `*` stands for when `pattern` matches all of `text`
+fn capture<'a>(pattern: &str, text: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = pattern.split_once('*')?;
+    let rest = text.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    (!rest.is_empty()).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(root: &str, file: &str) -> Vec<String> {
+        candidates(&default_rules(), Path::new(root), &Path::new(root).join(file))
+            .into_iter()
+            .map(|a| a.path.strip_prefix(root).unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_capture() {
+        assert_eq!(capture("*_test.rs", "foo_test.rs"), Some("foo"));
+        assert_eq!(capture("test_*.py", "test_app.py"), Some("app"));
+        assert_eq!(capture("*.rs", "main.c"), None);
+        assert_eq!(capture("*_test.rs", "_test.rs"), None);
+    }
+
+    #[test]
+    fn test_most_specific_pattern_first() {
+        assert_eq!(paths("/ws", "src/foo_test.rs")[0], "src/foo.rs");
+        assert_eq!(paths("/ws", "src/foo/mod.rs")[0], "src/foo.rs");
+        assert_eq!(paths("/ws", "src/foo.rs"), vec!["src/foo_test.rs", "src/foo/mod.rs"]);
+        assert_eq!(paths("/ws", "lib/util.h"), vec!["lib/util.c", "lib/util.cpp"]);
+    }
+
+    #[test]
+    fn test_template_placeholders() {
+        let found = candidates(&default_rules(), Path::new("/ws"), Path::new("/ws/lib/util.h"));
+        assert_eq!(found[0].template, "#include \"util.h\"\n");
+    }
+}
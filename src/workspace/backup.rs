@@ -0,0 +1,135 @@
+//! Backup files and generations
+//!
+//! An idle backup writes the crash-recovery copy of a modified file, which
+//! a save deletes, and also keeps the content as a timestamped generation
+//! in a directory of the file's own. The newest few generations outlive
+//! saves, so an earlier state of the file can be brought back later.
+
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One kept version of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generation {
+    /// When it was written, in milliseconds since the Unix epoch
+    pub time: u64,
+    pub path: PathBuf,
+}
+
+impl Generation {
+    /// Seconds since it was written
+    pub fn age(&self) -> u64 {
+        now_millis().saturating_sub(self.time) / 1000
+    }
+}
+
+/// Write `content` to `path`, gzipped if `compress`
+pub fn write_file(path: &Path, content: &str, compress: bool) -> Result<()> {
+    if compress {
+        let file = std::fs::File::create(path)?;
+        let mut encoder = GzEncoder::new(file, Compression::fast());
+        encoder.write_all(content.as_bytes())?;
+        encoder.finish()?;
+    } else {
+        std::fs::write(path, content)?;
+    }
+    Ok(())
+}
+
+/// Read a file written by `write_file`, compressed or not
+pub fn read_file(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)?;
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut content = String::new();
+        GzDecoder::new(bytes.as_slice()).read_to_string(&mut content)?;
+        Ok(content)
+    } else {
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+/// Generations kept in `dir`, newest first
+pub fn generations(dir: &Path) -> Vec<Generation> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut generations: Vec<Generation> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let time = path.file_name()?.to_str()?.strip_suffix(".bak")?.parse().ok()?;
+            Some(Generation { time, path })
+        })
+        .collect();
+    generations.sort_by_key(|g| std::cmp::Reverse(g.time));
+    generations
+}
+
+/// Keep `content` as the newest generation in `dir` unless it matches the
+/// current newest, then delete all but the newest `keep`. Returns whether
+/// a generation was written.
+pub fn save_generation(dir: &Path, content: &str, compress: bool, keep: usize) -> Result<bool> {
+    let existing = generations(dir);
+    if existing.first().is_some_and(|newest| read_file(&newest.path).is_ok_and(|text| text == content)) {
+        return Ok(false);
+    }
+    std::fs::create_dir_all(dir)?;
+    // Never reuse or go behind the newest name, even within a millisecond
+    let time = existing.first().map_or(0, |newest| newest.time + 1).max(now_millis());
+    write_file(&dir.join(format!("{}.bak", time)), content, compress)?;
+    for old in existing.iter().skip(keep.saturating_sub(1)) {
+        let _ = std::fs::remove_file(&old.path);
+    }
+    Ok(true)
+}
+
+/// Lines `new` adds and removes relative to `old`, compared as sets of
+/// lines so that moved lines don't count
+pub fn line_changes(old: &str, new: &str) -> (usize, usize) {
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    for line in old.lines() {
+        *counts.entry(line).or_default() -= 1;
+    }
+    for line in new.lines() {
+        *counts.entry(line).or_default() += 1;
+    }
+    let added = counts.values().filter(|n| **n > 0).sum::<isize>();
+    let removed = -counts.values().filter(|n| **n < 0).sum::<isize>();
+    (added as usize, removed as usize)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_generation() {
+        let dir = std::env::temp_dir().join(format!("fackr-generations-{}", std::process::id()));
+        assert!(save_generation(&dir, "one", true, 2).unwrap());
+        assert!(!save_generation(&dir, "one", true, 2).unwrap());
+        assert!(save_generation(&dir, "two", false, 2).unwrap());
+        assert!(save_generation(&dir, "three", true, 2).unwrap());
+
+        let kept = generations(&dir);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(read_file(&kept[0].path).unwrap(), "three");
+        assert_eq!(read_file(&kept[1].path).unwrap(), "two");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_line_changes() {
+        assert_eq!(line_changes("a\nb\nc\n", "a\nc\nd\ne\n"), (2, 1));
+        assert_eq!(line_changes("x\ny\n", "y\nx\n"), (0, 0));
+    }
+}
@@ -0,0 +1,91 @@
+//! Bookmarks
+//!
+//! Lines bookmarked in any file of the workspace, optionally labelled,
+//! persisted in workspace.json. Next/Previous Bookmark walk them in file and
+//! line order, across files. Like global marks they stay on the line number
+//! they were set at; edits above one don't move it.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A bookmarked line. The path is relative to the workspace root unless
+/// the file lives outside it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub path: PathBuf,
+    pub line: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// The workspace's bookmarks, in file and line order
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bookmarks(Vec<Bookmark>);
+
+impl Bookmarks {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn all(&self) -> &[Bookmark] {
+        &self.0
+    }
+
+    pub fn get(&self, path: &Path, line: usize) -> Option<&Bookmark> {
+        self.0.iter().find(|b| b.path == path && b.line == line)
+    }
+
+    /// Bookmark a line, replacing the label of one already there
+    pub fn set(&mut self, path: &Path, line: usize, label: Option<String>) {
+        let bookmark = Bookmark { path: path.to_path_buf(), line, label };
+        match self.0.binary_search_by(|b| (b.path.as_path(), b.line).cmp(&(path, line))) {
+            Ok(i) => self.0[i] = bookmark,
+            Err(i) => self.0.insert(i, bookmark),
+        }
+    }
+
+    /// Remove the bookmark on a line; false if there wasn't one
+    pub fn remove(&mut self, path: &Path, line: usize) -> bool {
+        let before = self.0.len();
+        self.0.retain(|b| !(b.path == path && b.line == line));
+        self.0.len() != before
+    }
+
+    /// The first bookmark after (or before) a line, wrapping around the
+    /// workspace
+    pub fn next(&self, path: &Path, line: usize, forward: bool) -> Option<&Bookmark> {
+        let here = (path, line);
+        if forward {
+            let after = self.0.iter().find(|b| (b.path.as_path(), b.line) > here);
+            after.or_else(|| self.0.first())
+        } else {
+            let before = self.0.iter().rev().find(|b| (b.path.as_path(), b.line) < here);
+            before.or_else(|| self.0.last())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_wraps_across_files() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.set(Path::new("b.rs"), 3, None);
+        bookmarks.set(Path::new("a.rs"), 10, Some("setup".to_string()));
+        bookmarks.set(Path::new("a.rs"), 2, None);
+        let at = |b: Option<&Bookmark>| b.map(|b| (b.path.to_string_lossy().into_owned(), b.line));
+
+        assert_eq!(at(bookmarks.next(Path::new("a.rs"), 2, true)), Some(("a.rs".to_string(), 10)));
+        assert_eq!(at(bookmarks.next(Path::new("a.rs"), 10, true)), Some(("b.rs".to_string(), 3)));
+        assert_eq!(at(bookmarks.next(Path::new("b.rs"), 3, true)), Some(("a.rs".to_string(), 2)));
+        assert_eq!(at(bookmarks.next(Path::new("a.rs"), 2, false)), Some(("b.rs".to_string(), 3)));
+        assert_eq!(at(bookmarks.next(Path::new("a.rs"), 5, false)), Some(("a.rs".to_string(), 2)));
+
+        assert!(bookmarks.remove(Path::new("a.rs"), 2));
+        assert!(!bookmarks.remove(Path::new("a.rs"), 2));
+        assert_eq!(bookmarks.get(Path::new("a.rs"), 10).and_then(|b| b.label.as_deref()), Some("setup"));
+    }
+}
@@ -0,0 +1,454 @@
+//! Applying LSP workspace edits
+//!
+//! Every multi-file edit coming from a language server (rename, code
+//! actions, `workspace/applyEdit`) goes through `Workspace::apply_workspace_edit`.
+//! Open buffers are edited in place as a single undoable step; files that are
+//! not open are edited on disk. Resource operations (create/rename/delete)
+//! are applied in the order the server sent them.
+
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::state::{Pane, Workspace};
+use crate::buffer::Buffer;
+use crate::editor::Position;
+use crate::lsp::{uri_to_path, DocumentChange, TextEdit, WorkspaceEdit};
+use crate::syntax::Highlighter;
+
+/// Outcome of applying a workspace edit, used for the status bar summary
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WorkspaceEditSummary {
+    /// Number of text edits applied
+    pub edits: usize,
+    /// Number of documents that received text edits
+    pub files: usize,
+    pub created: usize,
+    pub renamed: usize,
+    pub deleted: usize,
+    /// Changes that were not applied, with a short reason
+    pub skipped: Vec<String>,
+}
+
+impl WorkspaceEditSummary {
+    /// One-line summary, e.g. "Renamed: 4 edits in 2 file(s), 1 renamed"
+    pub fn message(&self, verb: &str) -> String {
+        let mut parts = Vec::new();
+        if self.edits > 0 {
+            parts.push(format!("{} edits in {} file(s)", self.edits, self.files));
+        }
+        if self.created > 0 {
+            parts.push(format!("{} created", self.created));
+        }
+        if self.renamed > 0 {
+            parts.push(format!("{} renamed", self.renamed));
+        }
+        if self.deleted > 0 {
+            parts.push(format!("{} deleted", self.deleted));
+        }
+
+        let mut msg = if parts.is_empty() {
+            format!("{}: no changes", verb)
+        } else {
+            format!("{}: {}", verb, parts.join(", "))
+        };
+        match self.skipped.len() {
+            0 => {}
+            1 => msg.push_str(&format!(" (skipped {})", self.skipped[0])),
+            n => msg.push_str(&format!(" (skipped {}: {}, ...)", n, self.skipped[0])),
+        }
+        msg
+    }
+}
+
+impl Workspace {
+    /// Absolute path of a buffer entry, if it has one
+    fn entry_full_path(&self, tab_idx: usize, buffer_idx: usize) -> Option<PathBuf> {
+        let entry = &self.tabs[tab_idx].buffers[buffer_idx];
+        entry.path.as_ref().map(|p| {
+            if entry.is_orphan {
+                p.clone()
+            } else {
+                self.root.join(p)
+            }
+        })
+    }
+
+    /// Find an open buffer by absolute path, returns (tab index, buffer index)
+    pub fn find_buffer_by_path(&self, path: &Path) -> Option<(usize, usize)> {
+        for (tab_idx, tab) in self.tabs.iter().enumerate() {
+            for buffer_idx in 0..tab.buffers.len() {
+                if self.entry_full_path(tab_idx, buffer_idx).as_deref() == Some(path) {
+                    return Some((tab_idx, buffer_idx));
+                }
+            }
+        }
+        None
+    }
+
+    /// Path relative to the workspace root for display
+    pub fn display_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root).unwrap_or(path).display().to_string()
+    }
+
+    /// Files whose versioned edits no longer match the document we last sent
+    /// to the language server (the user kept typing after the request went out).
+    pub fn workspace_edit_conflicts(&self, edit: &WorkspaceEdit) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        for change in &edit.document_changes {
+            if let DocumentChange::Edit { uri, version: Some(version), .. } = change {
+                let Some(path) = uri_to_path(uri) else { continue };
+                if let Some(current) = self.lsp.document_version(&path) {
+                    if current != *version {
+                        conflicts.push(self.display_path(Path::new(&path)));
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Apply a workspace edit across open buffers and files on disk.
+    ///
+    /// `documentChanges` takes precedence over `changes` when both are present.
+    /// Processing stops at the first resource operation that fails, matching the
+    /// "abort" failure handling we advertise to servers.
+    pub fn apply_workspace_edit(&mut self, edit: &WorkspaceEdit) -> WorkspaceEditSummary {
+        let mut summary = WorkspaceEditSummary::default();
+
+        if edit.document_changes.is_empty() {
+            // Sort URIs so the result doesn't depend on HashMap order
+            let mut uris: Vec<&String> = edit.changes.keys().collect();
+            uris.sort();
+            for uri in uris {
+                self.apply_document_edits(uri, &edit.changes[uri], &mut summary);
+            }
+            return summary;
+        }
+
+        for change in &edit.document_changes {
+            let result = match change {
+                DocumentChange::Edit { uri, edits, .. } => {
+                    self.apply_document_edits(uri, edits, &mut summary);
+                    Ok(())
+                }
+                DocumentChange::Create { uri, overwrite, ignore_if_exists } => {
+                    self.create_file(uri, *overwrite, *ignore_if_exists, &mut summary)
+                }
+                DocumentChange::Rename { old_uri, new_uri, overwrite, ignore_if_exists } => {
+                    self.rename_file(old_uri, new_uri, *overwrite, *ignore_if_exists, &mut summary)
+                }
+                DocumentChange::Delete { uri, recursive, ignore_if_not_exists } => {
+                    self.delete_file(uri, *recursive, *ignore_if_not_exists, &mut summary)
+                }
+            };
+            if let Err(e) = result {
+                summary.skipped.push(e.to_string());
+                break;
+            }
+        }
+
+        summary
+    }
+
+    /// Apply text edits for one document, to the open buffer if there is one
+    fn apply_document_edits(&mut self, uri: &str, edits: &[TextEdit], summary: &mut WorkspaceEditSummary) {
+        if edits.is_empty() {
+            return;
+        }
+        let Some(path_str) = uri_to_path(uri) else {
+            summary.skipped.push(format!("{} (not a file)", uri));
+            return;
+        };
+        let path = PathBuf::from(path_str);
+
+        let applied = match self.find_buffer_by_path(&path) {
+            Some((tab_idx, buffer_idx)) => Ok(self.apply_text_edits(tab_idx, buffer_idx, edits)),
+            None => apply_edits_on_disk(&path, edits),
+        };
+
+        match applied {
+            Ok(count) => {
+                summary.edits += count;
+                summary.files += 1;
+            }
+            Err(e) => summary.skipped.push(format!("{} ({})", self.display_path(&path), e)),
+        }
+    }
+
+    /// Apply LSP text edits to an open buffer as a single undo step.
+    ///
+    /// Edits are interpreted against the original document, so they are applied
+    /// from the end backward. Cursors of every pane showing the buffer are
+    /// shifted to stay on the same text. Returns the number of edits applied.
+    pub fn apply_text_edits(&mut self, tab_idx: usize, buffer_idx: usize, edits: &[TextEdit]) -> usize {
+        self.tabs[tab_idx].buffers[buffer_idx].history.begin_group();
+        let count = self.apply_text_edits_in_group(tab_idx, buffer_idx, edits);
+        self.tabs[tab_idx].buffers[buffer_idx].history.end_group();
+        count
+    }
+
+    /// Apply LSP text edits like `apply_text_edits`, recording them in the
+    /// buffer's open undo group rather than one of their own
+    pub fn apply_text_edits_in_group(&mut self, tab_idx: usize, buffer_idx: usize, edits: &[TextEdit]) -> usize {
+        if edits.is_empty() {
+            return 0;
+        }
+
+        let order = reverse_order(edits);
+
+        let tab = &mut self.tabs[tab_idx];
+        let pane_idxs: Vec<usize> = (0..tab.panes.len())
+            .filter(|&i| tab.panes[i].buffer_idx == buffer_idx)
+            .collect();
+        let entry = &mut tab.buffers[buffer_idx];
+
+        // Char indices of every cursor and anchor, per pane
+        let mut cursor_idxs: Vec<Vec<(usize, usize)>> = pane_idxs
+            .iter()
+            .map(|&i| {
+                tab.panes[i].cursors.all().iter().map(|c| {
+                    (
+                        entry.buffer.line_col_to_char(c.line, c.col),
+                        entry.buffer.line_col_to_char(c.anchor_line, c.anchor_col),
+                    )
+                }).collect()
+            })
+            .collect();
+
+        let cursor_positions = |panes: &[Pane]| -> Vec<Position> {
+            pane_idxs.first()
+                .map(|&i| panes[i].cursors.all().iter().map(|c| Position::new(c.line, c.col)).collect())
+                .unwrap_or_default()
+        };
+        let cursors_before = cursor_positions(&tab.panes);
+        let cursor_pos = cursors_before.first().copied().unwrap_or_default();
+
+        entry.history.set_cursors_before(cursors_before);
+
+        let mut first_line = usize::MAX;
+        for &i in &order {
+            let edit = &edits[i];
+            let start = entry.buffer.line_col_to_char(edit.range.start.line as usize, edit.range.start.character as usize);
+            let end = entry.buffer.line_col_to_char(edit.range.end.line as usize, edit.range.end.character as usize).max(start);
+            first_line = first_line.min(edit.range.start.line as usize);
+
+            if end > start {
+                let deleted: String = entry.buffer.slice(start, end).chars().collect();
+                entry.buffer.delete(start, end);
+                entry.history.record_delete(start, deleted, cursor_pos, cursor_pos);
+            }
+            if !edit.new_text.is_empty() {
+                entry.buffer.insert(start, &edit.new_text);
+                entry.history.record_insert(start, edit.new_text.clone(), cursor_pos, cursor_pos);
+            }
+
+            let delta = edit.new_text.chars().count() as isize - (end - start) as isize;
+            let shift = |idx: &mut usize| {
+                if *idx >= end {
+                    *idx = (*idx as isize + delta).max(start as isize) as usize;
+                } else if *idx > start {
+                    // Cursor was inside the replaced range
+                    *idx = start;
+                }
+            };
+            for pane in cursor_idxs.iter_mut() {
+                for (cursor, anchor) in pane.iter_mut() {
+                    shift(cursor);
+                    shift(anchor);
+                }
+            }
+        }
+
+        for (&pane_idx, idxs) in pane_idxs.iter().zip(cursor_idxs) {
+            let cursors = tab.panes[pane_idx].cursors.all_mut();
+            for (cursor, (idx, anchor_idx)) in cursors.iter_mut().zip(idxs) {
+                let (line, col) = entry.buffer.char_to_line_col(idx);
+                let (anchor_line, anchor_col) = entry.buffer.char_to_line_col(anchor_idx);
+                cursor.line = line;
+                cursor.col = col;
+                cursor.desired_col = col;
+                cursor.anchor_line = anchor_line;
+                cursor.anchor_col = anchor_col;
+            }
+        }
+
+        let cursors_after = cursor_positions(&tab.panes);
+        let entry = &mut tab.buffers[buffer_idx];
+        entry.history.set_cursors_after(cursors_after);
+        entry.highlighter.invalidate_cache(first_line);
+
+        edits.len()
+    }
+
+    fn create_file(&mut self, uri: &str, overwrite: bool, ignore_if_exists: bool, summary: &mut WorkspaceEditSummary) -> Result<()> {
+        let path = uri_path(uri)?;
+        if path.exists() {
+            if ignore_if_exists && !overwrite {
+                return Ok(());
+            }
+            if !overwrite {
+                bail!("{} already exists", self.display_path(&path));
+            }
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, "")?;
+        if let Some((tab_idx, buffer_idx)) = self.find_buffer_by_path(&path) {
+            // Overwritten while open: the buffer now mirrors an empty file
+            let entry = &mut self.tabs[tab_idx].buffers[buffer_idx];
+            entry.buffer.set_contents("");
+            entry.mark_saved();
+        }
+        summary.created += 1;
+        Ok(())
+    }
+
+    fn rename_file(
+        &mut self,
+        old_uri: &str,
+        new_uri: &str,
+        overwrite: bool,
+        ignore_if_exists: bool,
+        summary: &mut WorkspaceEditSummary,
+    ) -> Result<()> {
+        let old_path = uri_path(old_uri)?;
+        let new_path = uri_path(new_uri)?;
+        if new_path.exists() {
+            if ignore_if_exists && !overwrite {
+                return Ok(());
+            }
+            if !overwrite {
+                bail!("{} already exists", self.display_path(&new_path));
+            }
+        }
+        if let Some(parent) = new_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&old_path, &new_path)?;
+
+        // Point open buffers at the new location
+        if let Some((tab_idx, buffer_idx)) = self.find_buffer_by_path(&old_path) {
+            let is_orphan = !new_path.starts_with(&self.root);
+            let stored_path = if is_orphan {
+                new_path.clone()
+            } else {
+                new_path.strip_prefix(&self.root).unwrap_or(&new_path).to_path_buf()
+            };
+            let entry = &mut self.tabs[tab_idx].buffers[buffer_idx];
+            entry.path = Some(stored_path);
+            entry.is_orphan = is_orphan;
+            if let Some(filename) = new_path.file_name().and_then(|n| n.to_str()) {
+                entry.highlighter = Highlighter::new();
+                entry.highlighter.detect_language(filename);
+            }
+        }
+        summary.renamed += 1;
+        Ok(())
+    }
+
+    fn delete_file(&mut self, uri: &str, recursive: bool, ignore_if_not_exists: bool, summary: &mut WorkspaceEditSummary) -> Result<()> {
+        let path = uri_path(uri)?;
+        if !path.exists() {
+            if ignore_if_not_exists {
+                return Ok(());
+            }
+            bail!("{} does not exist", self.display_path(&path));
+        }
+        if path.is_dir() {
+            if recursive {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_dir(&path)?;
+            }
+        } else {
+            fs::remove_file(&path)?;
+        }
+        // Open buffers are left alone so their contents aren't lost;
+        // they show as new files until saved or closed.
+        summary.deleted += 1;
+        Ok(())
+    }
+}
+
+/// Convert a file URI to a path, failing for non-file URIs
+fn uri_path(uri: &str) -> Result<PathBuf> {
+    match uri_to_path(uri) {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => bail!("{} is not a file URI", uri),
+    }
+}
+
+/// Indices of `edits` in the order they must be applied: descending by start,
+/// and for equal starts later edits first so same-position inserts end up in
+/// the order the server sent them
+fn reverse_order(edits: &[TextEdit]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..edits.len()).collect();
+    order.sort_by(|&a, &b| {
+        let ka = (edits[a].range.start.line, edits[a].range.start.character, a);
+        let kb = (edits[b].range.start.line, edits[b].range.start.character, b);
+        kb.cmp(&ka)
+    });
+    order
+}
+
+/// Apply text edits directly to a file that isn't open in the editor
+fn apply_edits_on_disk(path: &Path, edits: &[TextEdit]) -> Result<usize> {
+    let mut buffer = Buffer::load(path)?;
+    for i in reverse_order(edits) {
+        let edit = &edits[i];
+        let start = buffer.line_col_to_char(edit.range.start.line as usize, edit.range.start.character as usize);
+        let end = buffer.line_col_to_char(edit.range.end.line as usize, edit.range.end.character as usize).max(start);
+        if end > start {
+            buffer.delete(start, end);
+        }
+        if !edit.new_text.is_empty() {
+            buffer.insert(start, &edit.new_text);
+        }
+    }
+    buffer.save(path)?;
+    Ok(edits.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::{Position as LspPosition, Range};
+    use crate::workspace::Tab;
+
+    fn edit(sl: u32, sc: u32, el: u32, ec: u32, text: &str) -> TextEdit {
+        TextEdit {
+            range: Range::new(LspPosition::new(sl, sc), LspPosition::new(el, ec)),
+            new_text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apply_text_edits_against_original_positions() {
+        let mut workspace = Workspace::new(std::env::temp_dir());
+        let mut tab = Tab::new();
+        tab.buffers[0].buffer.set_contents("let foo = 1;\nfoo + foo\n");
+        workspace.tabs = vec![tab];
+        workspace.tabs[0].panes[0].cursors.primary_mut().set(1, 9);
+
+        let edits = vec![
+            edit(0, 4, 0, 7, "bar"),
+            edit(1, 6, 1, 9, "bar"),
+            edit(1, 0, 1, 3, "bar"),
+            edit(0, 0, 0, 0, "// a\n"),
+            edit(0, 0, 0, 0, "// b\n"),
+        ];
+        assert_eq!(workspace.apply_text_edits(0, 0, &edits), 5);
+
+        let entry = &mut workspace.tabs[0].buffers[0];
+        assert_eq!(entry.buffer.contents(), "// a\n// b\nlet bar = 1;\nbar + bar\n");
+        let cursor = workspace.tabs[0].panes[0].cursors.primary();
+        assert_eq!((cursor.line, cursor.col), (3, 9));
+
+        // All edits undo as one step
+        let entry = &mut workspace.tabs[0].buffers[0];
+        assert!(entry.history.undo().is_some());
+        assert!(!entry.history.can_undo());
+    }
+}
@@ -0,0 +1,192 @@
+//! Workspace file index for quick open
+//!
+//! Lists the workspace's files relative to its root. In a git repository
+//! `git ls-files` supplies tracked files plus untracked ones that aren't
+//! ignored, so .gitignore rules apply exactly. Elsewhere the tree is walked,
+//! skipping hidden entries and names the root .gitignore lists. Directories
+//! the workspace config excludes are left out either way.
+//!
+//! The same walk, cut short, tells whether a root is too big to index
+//! without asking (a home directory opened by accident).
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Most files indexed, so huge trees stay responsive
+const MAX_FILES: usize = 50_000;
+
+/// Directory levels the size check looks into
+const HUGE_DEPTH: usize = 6;
+
+/// Files under `root`, as paths relative to it, outside the `excluded`
+/// directories (also relative to it)
+pub fn list_files(root: &Path, excluded: &[PathBuf]) -> Vec<PathBuf> {
+    if let Some(mut files) = git_files(root) {
+        files.retain(|path| !excluded.iter().any(|dir| path.starts_with(dir)));
+        return files;
+    }
+    let ignore = IgnoreList::load(root);
+    let mut files = Vec::new();
+    walk(root, root, &ignore, excluded, &mut files);
+    files.sort();
+    files
+}
+
+/// Whether `root` has more than `limit` entries in its first few levels,
+/// not counting what the root .gitignore, hidden names and `excluded`
+/// leave out. The filesystem root and the home directory always count as
+/// huge; a limit of 0 turns the check off.
+pub fn is_huge(root: &Path, excluded: &[PathBuf], limit: usize) -> bool {
+    if limit == 0 {
+        return false;
+    }
+    if root.parent().is_none() || dirs::home_dir().is_some_and(|home| home == root) {
+        return true;
+    }
+    let ignore = IgnoreList::load(root);
+    let mut count = 0;
+    let mut level = vec![root.to_path_buf()];
+    for _ in 0..HUGE_DEPTH {
+        let mut next = Vec::new();
+        for dir in &level {
+            let Ok(entries) = std::fs::read_dir(dir) else { continue };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') || ignore.matches(&name) {
+                    continue;
+                }
+                let path = entry.path();
+                if path.strip_prefix(root).is_ok_and(|rel| excluded.iter().any(|dir| rel == dir)) {
+                    continue;
+                }
+                count += 1;
+                if count > limit {
+                    return true;
+                }
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    next.push(path);
+                }
+            }
+        }
+        level = next;
+    }
+    false
+}
+
+fn git_files(root: &Path) -> Option<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["ls-files", "--cached", "--others", "--exclude-standard", "-z"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let mut files: Vec<PathBuf> = output
+        .stdout
+        .split(|&b| b == 0)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(String::from_utf8_lossy(entry).into_owned()))
+        // Deleted but still tracked files, and submodule directories
+        .filter(|path| root.join(path).is_file())
+        .take(MAX_FILES)
+        .collect();
+    files.sort();
+    files.dedup();
+    Some(files)
+}
+
+fn walk(dir: &Path, root: &Path, ignore: &IgnoreList, excluded: &[PathBuf], files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if files.len() >= MAX_FILES {
+            return;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || ignore.matches(&name) {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            if !path.strip_prefix(root).is_ok_and(|rel| excluded.iter().any(|dir| rel == dir)) {
+                walk(&path, root, ignore, excluded, files);
+            }
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_path_buf());
+        }
+    }
+}
+
+/// The root .gitignore's patterns, in the simple forms the walk
+/// understands: plain names (`target`, `/dist/`) and extension globs
+/// (`*.log`), matched against any path component
+#[derive(Debug, Default)]
+struct IgnoreList {
+    names: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl IgnoreList {
+    fn load(root: &Path) -> Self {
+        std::fs::read_to_string(root.join(".gitignore"))
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut list = Self::default();
+        for line in text.lines() {
+            let pattern = line.trim().trim_start_matches('/').trim_end_matches('/');
+            if pattern.is_empty() || pattern.starts_with('#') || pattern.starts_with('!') {
+                continue;
+            }
+            if let Some(ext) = pattern.strip_prefix("*.") {
+                if !ext.contains(['*', '?', '[', '/']) {
+                    list.extensions.push(ext.to_string());
+                }
+            } else if !pattern.contains(['*', '?', '[', '/']) {
+                list.names.push(pattern.to_string());
+            }
+        }
+        list
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+            || name.rsplit_once('.').is_some_and(|(_, ext)| self.extensions.iter().any(|e| e == ext))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_list() {
+        let ignore = IgnoreList::parse("# build output\n/target/\nnode_modules\n*.log\n!keep.log\nsrc/gen/*.rs\n");
+        assert!(ignore.matches("target"));
+        assert!(ignore.matches("node_modules"));
+        assert!(ignore.matches("debug.log"));
+        assert!(!ignore.matches("main.rs"));
+        assert!(!ignore.matches("logs"));
+    }
+
+    #[test]
+    fn test_is_huge() {
+        let root = std::env::temp_dir().join(format!("fackr-huge-{}", std::process::id()));
+        for dir in ["src", "vendor"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            for i in 0..4 {
+                std::fs::write(root.join(dir).join(format!("{}.rs", i)), "").unwrap();
+            }
+        }
+        assert!(is_huge(&root, &[], 8));
+        assert!(!is_huge(&root, &[PathBuf::from("vendor")], 8));
+        assert!(!is_huge(&root, &[], 0));
+        assert_eq!(list_files(&root, &[PathBuf::from("vendor")]).len(), 4);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
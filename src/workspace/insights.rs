@@ -0,0 +1,290 @@
+//! Workspace insights
+//!
+//! A report for repository hygiene sweeps: lines of code per language, the
+//! largest and the most recently modified files, and files nothing else
+//! mentions by name. Working it out reads the whole workspace, so it runs on
+//! a background thread, either on demand or, with `"insights": true` in
+//! config.json, once per session after the editor has sat idle a while.
+//!
+//! "Not referenced" is a text search, not an analysis: a file counts as
+//! referenced when another file contains its stem as a word (the directory
+//! name for `mod.rs`, `index.js` and `__init__.py`). Entry points, and files
+//! loaded by pattern or by a build script, show up in it too.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::render::image::format_bytes;
+use crate::syntax::Highlighter;
+
+use super::files::list_files;
+
+/// Files in each of the largest and recently modified lists
+const TOP_FILES: usize = 15;
+
+/// Unreferenced files listed at most
+const MAX_UNREFERENCED: usize = 50;
+
+/// Files larger than this are sized but not read
+const MAX_READ_BYTES: u64 = 1024 * 1024;
+
+/// Stems of entry points, which nothing is expected to mention
+const ENTRY_STEMS: &[&str] = &["main", "lib", "build", "setup", "conftest", "manage"];
+
+/// Stems that stand for their directory
+const DIRECTORY_STEMS: &[&str] = &["mod", "index", "__init__"];
+
+/// Analyses by workspace root
+static ANALYSES: Mutex<BTreeMap<PathBuf, Analysis>> = Mutex::new(BTreeMap::new());
+
+enum Analysis {
+    Running,
+    Done(String),
+}
+
+/// One workspace file as the report sees it
+#[derive(Debug, Clone)]
+struct FileInfo {
+    /// Relative to the workspace root
+    path: PathBuf,
+    bytes: u64,
+    modified: Option<SystemTime>,
+    /// Language name, for source files the highlighter knows
+    language: Option<String>,
+    /// Non-blank lines, for files that were read
+    lines: usize,
+    /// Words of the text, for files that were read
+    words: HashSet<String>,
+}
+
+/// Start analysing the workspace at `root` in the background, leaving out
+/// the `excluded` directories; does nothing while an analysis is running
+pub fn start(root: &Path, excluded: &[PathBuf]) {
+    let Ok(mut analyses) = ANALYSES.lock() else { return };
+    if matches!(analyses.get(root), Some(Analysis::Running)) {
+        return;
+    }
+    analyses.insert(root.to_path_buf(), Analysis::Running);
+
+    let (root, excluded) = (root.to_path_buf(), excluded.to_vec());
+    std::thread::spawn(move || {
+        let files = scan(&root, &excluded);
+        let report = format_report(&files, SystemTime::now());
+        if let Ok(mut analyses) = ANALYSES.lock() {
+            analyses.insert(root, Analysis::Done(report));
+        }
+    });
+}
+
+/// Whether an analysis of `root` is under way
+pub fn is_running(root: &Path) -> bool {
+    ANALYSES.lock().is_ok_and(|analyses| matches!(analyses.get(root), Some(Analysis::Running)))
+}
+
+/// Whether `root` has been analysed, or is being analysed, this session
+pub fn has_started(root: &Path) -> bool {
+    ANALYSES.lock().is_ok_and(|analyses| analyses.contains_key(root))
+}
+
+/// The latest report for `root`, or a note saying where it stands
+pub fn report(root: &Path) -> String {
+    match ANALYSES.lock().ok().as_ref().and_then(|analyses| analyses.get(root)) {
+        Some(Analysis::Done(report)) => report.clone(),
+        Some(Analysis::Running) => "(analysing the workspace...)".to_string(),
+        None => "(the workspace hasn't been analysed yet)".to_string(),
+    }
+}
+
+fn scan(root: &Path, excluded: &[PathBuf]) -> Vec<FileInfo> {
+    let mut highlighter = Highlighter::new();
+    list_files(root, excluded)
+        .into_iter()
+        .filter_map(|path| {
+            let meta = std::fs::metadata(root.join(&path)).ok()?;
+            if !meta.is_file() {
+                return None;
+            }
+            highlighter.detect_language(&path.to_string_lossy());
+            let mut info = FileInfo {
+                language: highlighter.language_name().map(str::to_string),
+                bytes: meta.len(),
+                modified: meta.modified().ok(),
+                lines: 0,
+                words: HashSet::new(),
+                path,
+            };
+            if info.bytes <= MAX_READ_BYTES {
+                if let Ok(text) = std::fs::read_to_string(root.join(&info.path)) {
+                    info.lines = text.lines().filter(|line| !line.trim().is_empty()).count();
+                    info.words = words(&text);
+                }
+            }
+            Some(info)
+        })
+        .collect()
+}
+
+/// Identifier-like words of `text` (letters, digits, `_` and `-`)
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The name other files would mention `path` by, if it's expected to be
+/// mentioned at all
+fn reference_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    if DIRECTORY_STEMS.contains(&stem) {
+        return path.parent()?.file_name()?.to_str().map(str::to_string);
+    }
+    // Entry points, and README, LICENSE and the like
+    if ENTRY_STEMS.contains(&stem) || !stem.chars().any(|c| c.is_lowercase()) {
+        return None;
+    }
+    Some(stem.to_string())
+}
+
+/// Source files no other file mentions by name
+fn unreferenced(files: &[FileInfo]) -> Vec<&Path> {
+    // Who mentions each name, by index into `files`
+    let mut names: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, file) in files.iter().enumerate().filter(|(_, f)| f.language.is_some()) {
+        if let Some(name) = reference_name(&file.path) {
+            names.entry(name).or_default().push(i);
+        }
+    }
+    let mut referenced: HashSet<usize> = HashSet::new();
+    for (i, file) in files.iter().enumerate() {
+        for word in &file.words {
+            if let Some(owners) = names.get(word) {
+                referenced.extend(owners.iter().filter(|&&owner| owner != i));
+            }
+        }
+    }
+    let mut paths: Vec<&Path> = names
+        .values()
+        .flatten()
+        .filter(|i| !referenced.contains(i))
+        .map(|&i| files[i].path.as_path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn format_report(files: &[FileInfo], now: SystemTime) -> String {
+    let mut out = String::from("Workspace insights\n\n");
+    if files.is_empty() {
+        out.push_str("(no files)\n");
+        return out;
+    }
+    let total: u64 = files.iter().map(|f| f.bytes).sum();
+    out.push_str(&format!("Files: {}, {}\n", files.len(), format_bytes(total)));
+
+    let mut languages: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for file in files {
+        if let Some(language) = &file.language {
+            let (count, lines) = languages.entry(language).or_default();
+            *count += 1;
+            *lines += file.lines;
+        }
+    }
+    let mut languages: Vec<(&str, (usize, usize))> = languages.into_iter().collect();
+    languages.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then(a.0.cmp(b.0)));
+    if !languages.is_empty() {
+        out.push_str("\nLines of code by language\n\n");
+        for (language, (count, lines)) in &languages {
+            let plural = if *count == 1 { "" } else { "s" };
+            out.push_str(&format!("{:>10}  {} ({} file{})\n", lines, language, count, plural));
+        }
+    }
+
+    let mut largest: Vec<&FileInfo> = files.iter().collect();
+    largest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.path.cmp(&b.path)));
+    out.push_str("\nLargest files\n\n");
+    for file in largest.iter().take(TOP_FILES) {
+        out.push_str(&format!("{:>10}  {}\n", format_bytes(file.bytes), file.path.display()));
+    }
+
+    let mut recent: Vec<(&FileInfo, SystemTime)> = files.iter().filter_map(|f| Some((f, f.modified?))).collect();
+    recent.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.path.cmp(&b.0.path)));
+    if !recent.is_empty() {
+        out.push_str("\nRecently modified\n\n");
+        for (file, modified) in recent.iter().take(TOP_FILES) {
+            let age = now.duration_since(*modified).unwrap_or_default();
+            out.push_str(&format!("{:>10}  {}\n", format_age(age), file.path.display()));
+        }
+    }
+
+    let unreferenced = unreferenced(files);
+    out.push_str("\nNot referenced by name in any other file\n\n");
+    if unreferenced.is_empty() {
+        out.push_str("  (none)\n");
+    }
+    for path in unreferenced.iter().take(MAX_UNREFERENCED) {
+        out.push_str(&format!("  {}\n", path.display()));
+    }
+    if unreferenced.len() > MAX_UNREFERENCED {
+        out.push_str(&format!("  ... and {} more\n", unreferenced.len() - MAX_UNREFERENCED));
+    }
+    out
+}
+
+/// e.g. "just now", "12m ago", "3h ago", "5d ago"
+fn format_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    match minutes {
+        0 => "just now".to_string(),
+        1..=59 => format!("{}m ago", minutes),
+        60..=1439 => format!("{}h ago", minutes / 60),
+        _ => format!("{}d ago", minutes / 1440),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, language: Option<&str>, text: &str) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(path),
+            bytes: text.len() as u64,
+            modified: None,
+            language: language.map(str::to_string),
+            lines: text.lines().filter(|line| !line.trim().is_empty()).count(),
+            words: words(text),
+        }
+    }
+
+    #[test]
+    fn test_unreferenced() {
+        let files = [
+            file("src/main.rs", Some("Rust"), "mod parser;\nmod net;\nuse util::helper;"),
+            file("src/parser.rs", Some("Rust"), "// parser"),
+            file("src/net/mod.rs", Some("Rust"), "mod socket;"),
+            file("src/net/socket.rs", Some("Rust"), ""),
+            file("src/orphan.rs", Some("Rust"), "fn orphan() {}"),
+            file("src/util/helper.rs", Some("Rust"), ""),
+            file("README.md", Some("Markdown"), "See orphan"),
+            file("notes.txt", None, ""),
+        ];
+        assert_eq!(unreferenced(&files), Vec::<&Path>::new());
+
+        let files = [file("src/main.rs", Some("Rust"), ""), file("src/orphan.rs", Some("Rust"), "fn orphan() {}")];
+        assert_eq!(unreferenced(&files), [Path::new("src/orphan.rs")]);
+    }
+
+    #[test]
+    fn test_report() {
+        let files = [file("src/main.rs", Some("Rust"), "fn main() {\n\n}\n"), file("data.bin", None, "xxxxxxxxxxxxxxxxxxxx")];
+        let report = format_report(&files, SystemTime::now());
+        assert!(report.contains("Files: 2, 35 B"));
+        assert!(report.contains("         2  Rust (1 file)"));
+        let largest = report.split("Largest files\n\n").nth(1).unwrap_or_default();
+        assert!(largest.lines().next().is_some_and(|line| line.ends_with("data.bin")));
+        assert_eq!(format_age(Duration::from_secs(3 * 3600)), "3h ago");
+    }
+}
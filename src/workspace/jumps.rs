@@ -0,0 +1,110 @@
+//! Jump list
+//!
+//! Where the cursor was before each jump (go to definition, a search hit or
+//! reference, go to line, a mark, switching files), so Navigate Back and
+//! Navigate Forward can return to it. One list per workspace, kept for the
+//! session; locations are files and lines, so they outlive the tab or pane
+//! they were in.
+
+use std::path::PathBuf;
+
+/// Most locations kept in each direction
+const JUMP_LIST_LIMIT: usize = 100;
+
+/// A place to return to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jump {
+    /// Absolute path of the file
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
+    /// First visible line
+    pub viewport_line: usize,
+}
+
+impl Jump {
+    /// Whether `other` is the same place, for not listing it twice in a row
+    fn same_place(&self, other: &Jump) -> bool {
+        self.path == other.path && self.line == other.line
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct JumpList {
+    /// Most recent last
+    back: Vec<Jump>,
+    /// Left with `back`, most recent last
+    forward: Vec<Jump>,
+}
+
+impl JumpList {
+    /// Remember `jump` as a place jumped away from; forgets the locations
+    /// left by going back
+    pub fn push(&mut self, jump: Jump) {
+        self.forward.clear();
+        push_to(&mut self.back, jump);
+    }
+
+    /// The place before `current`, which is kept for going forward
+    pub fn back(&mut self, current: Option<Jump>) -> Option<Jump> {
+        step(&mut self.back, &mut self.forward, current)
+    }
+
+    /// Undo a `back`; `current` is kept for going back again
+    pub fn forward(&mut self, current: Option<Jump>) -> Option<Jump> {
+        step(&mut self.forward, &mut self.back, current)
+    }
+}
+
+/// Push onto a history, replacing its last entry when that's the same place
+fn push_to(list: &mut Vec<Jump>, jump: Jump) {
+    if list.last().is_some_and(|last| last.same_place(&jump)) {
+        list.pop();
+    }
+    list.push(jump);
+    if list.len() > JUMP_LIST_LIMIT {
+        list.remove(0);
+    }
+}
+
+/// Take the latest place from `from` that isn't `current`, keeping
+/// `current` in `to`
+fn step(from: &mut Vec<Jump>, to: &mut Vec<Jump>, current: Option<Jump>) -> Option<Jump> {
+    while let Some(jump) = from.pop() {
+        if current.as_ref().is_some_and(|current| current.same_place(&jump)) {
+            continue;
+        }
+        if let Some(current) = current {
+            push_to(to, current);
+        }
+        return Some(jump);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jump(path: &str, line: usize) -> Jump {
+        Jump { path: PathBuf::from(path), line, col: 0, viewport_line: 0 }
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut jumps = JumpList::default();
+        jumps.push(jump("/a", 1));
+        jumps.push(jump("/a", 1));
+        jumps.push(jump("/b", 5));
+        assert_eq!(jumps.back(Some(jump("/c", 9))), Some(jump("/b", 5)));
+        assert_eq!(jumps.back(Some(jump("/b", 5))), Some(jump("/a", 1)));
+        assert_eq!(jumps.back(Some(jump("/a", 1))), None);
+        assert_eq!(jumps.forward(Some(jump("/a", 1))), Some(jump("/b", 5)));
+        assert_eq!(jumps.forward(Some(jump("/b", 5))), Some(jump("/c", 9)));
+
+        // A new jump drops what going back left behind
+        jumps.back(Some(jump("/c", 9)));
+        jumps.push(jump("/b", 7));
+        assert_eq!(jumps.forward(None), None);
+    }
+}
@@ -0,0 +1,200 @@
+//! Workspace lock
+//!
+//! The instance that owns a workspace keeps `lock.json` (PID, host and a
+//! heartbeat timestamp) in the state directory and refreshes it while
+//! running. A second instance finds a live lock and asks the user whether to
+//! open read-only, steal the lock, or run without saving state. Locks whose
+//! process is gone or whose heartbeat stopped are treated as stale and taken over.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::state::Workspace;
+use crate::util::process::{hostname, pid_alive};
+
+/// How often the owner refreshes the lock
+const HEARTBEAT_SECS: u64 = 10;
+/// A lock without a heartbeat for this long is stale
+const STALE_SECS: u64 = 3 * HEARTBEAT_SECS;
+
+/// Contents of lock.json
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub pid: u32,
+    pub host: String,
+    /// Seconds since the Unix epoch of the last heartbeat
+    pub heartbeat: u64,
+}
+
+impl LockInfo {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            host: hostname(),
+            heartbeat: unix_now(),
+        }
+    }
+
+    fn is_ours(&self) -> bool {
+        self.pid == std::process::id() && self.host == hostname()
+    }
+
+    /// The owning process is gone or stopped refreshing the lock
+    fn is_stale(&self) -> bool {
+        if unix_now().saturating_sub(self.heartbeat) > STALE_SECS {
+            return true;
+        }
+        self.host == hostname() && !pid_alive(self.pid)
+    }
+}
+
+/// How this instance relates to the workspace lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// We hold the lock and persist state and backups
+    Owner,
+    /// Another instance holds it; files can be viewed but not edited
+    ReadOnly,
+    /// Another instance holds it; files can be edited, workspace state and
+    /// backups are not written
+    NoPersistence,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn read_lock(path: &Path) -> Option<LockInfo> {
+    let json = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Replace the lock file atomically (write to a temp file, then rename)
+fn write_lock(path: &Path, info: &LockInfo) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string(info)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+impl Workspace {
+    fn lock_path(&self) -> PathBuf {
+        self.state_dir.join("lock.json")
+    }
+
+    /// Take the workspace lock. Returns the holder if another live instance
+    /// has it; the workspace then runs without persistence until the user
+    /// decides (see `steal_lock` / `set_lock_mode`).
+    pub fn acquire_lock(&mut self) -> Option<LockInfo> {
+        let path = self.lock_path();
+        let ours = LockInfo::current();
+        self.lock_mode = LockMode::Owner;
+        self.last_heartbeat = Some(Instant::now());
+
+        // create_new makes the common case (no lock) race-free
+        if let Ok(mut file) = OpenOptions::new().write(true).create_new(true).open(&path) {
+            let _ = file.write_all(serde_json::to_string(&ours).unwrap_or_default().as_bytes());
+            return None;
+        }
+        match read_lock(&path) {
+            Some(holder) if !holder.is_ours() && !holder.is_stale() => {
+                self.lock_mode = LockMode::NoPersistence;
+                Some(holder)
+            }
+            _ => {
+                // Stale or unreadable lock: clean it up and take over
+                let _ = write_lock(&path, &ours);
+                None
+            }
+        }
+    }
+
+    /// Take the lock from another instance, which stops persisting on its
+    /// next heartbeat
+    pub fn steal_lock(&mut self) -> Result<()> {
+        write_lock(&self.lock_path(), &LockInfo::current())?;
+        self.lock_mode = LockMode::Owner;
+        self.last_heartbeat = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Continue without the lock (read-only or without persistence)
+    pub fn set_lock_mode(&mut self, mode: LockMode) {
+        self.lock_mode = mode;
+    }
+
+    /// Refresh the lock periodically. Returns false if another instance
+    /// took the lock over since the last heartbeat (we stop persisting).
+    pub fn heartbeat(&mut self) -> bool {
+        if self.lock_mode != LockMode::Owner {
+            return true;
+        }
+        if self.last_heartbeat.is_some_and(|t| t.elapsed() < Duration::from_secs(HEARTBEAT_SECS)) {
+            return true;
+        }
+        self.last_heartbeat = Some(Instant::now());
+
+        let path = self.lock_path();
+        if let Some(holder) = read_lock(&path) {
+            if !holder.is_ours() {
+                self.lock_mode = LockMode::NoPersistence;
+                return false;
+            }
+        }
+        let _ = write_lock(&path, &LockInfo::current());
+        true
+    }
+
+    /// Remove the lock file if we own it (on exit or when switching workspace)
+    pub fn release_lock(&mut self) {
+        if self.lock_mode != LockMode::Owner {
+            return;
+        }
+        let path = self.lock_path();
+        if read_lock(&path).is_some_and(|holder| holder.is_ours()) {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    /// Whether workspace state and backups are written
+    pub fn persists(&self) -> bool {
+        self.lock_mode == LockMode::Owner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_instance_sees_live_lock() {
+        let root = std::env::temp_dir().join(format!("fackr-lock-{}", std::process::id()));
+        let mut first = Workspace::new(root.clone());
+        first.init().unwrap();
+        assert_eq!(first.acquire_lock(), None);
+        assert!(first.persists());
+
+        // Pretend the lock belongs to another live process
+        let other = LockInfo { pid: std::process::id(), host: "elsewhere".to_string(), heartbeat: unix_now() };
+        write_lock(&first.lock_path(), &other).unwrap();
+
+        let mut second = Workspace::new(root.clone());
+        second.init().unwrap();
+        assert_eq!(second.acquire_lock(), Some(other.clone()));
+        assert!(!second.persists());
+
+        // A lock whose heartbeat stopped long ago is taken over
+        let stale = LockInfo { heartbeat: 0, ..other };
+        write_lock(&first.lock_path(), &stale).unwrap();
+        assert_eq!(second.acquire_lock(), None);
+        assert!(second.persists());
+
+        second.release_lock();
+        assert!(!second.lock_path().exists());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
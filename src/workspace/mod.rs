@@ -8,17 +8,49 @@
 //! <workspace_root>/
 //!   .fackr/
 //!     workspace.json    # Persisted state (tabs, panes, cursors)
+//!     config.json       # Workspace settings
+//!     keymap.toml       # Key bindings (over ~/.config/fackr/keymap.toml)
 //!     backups/          # Auto-backups of dirty files
+//!       history/        # Earlier versions kept per file
+//!     stats/            # Editing statistics per session (opt-in)
 //! ```
 //!
+//! If `.fackr/` can't be written, the same layout lives in the per-user cache
+//! directory (`~/.cache/fackr/workspaces/<name>-<hash>/`) instead.
+//!
 //! Workspace initialization:
 //! - `fackr <dir>` - Opens directory as workspace
 //! - `fackr <file>` - Implicitly opens containing directory as workspace
 //! - `fackr` (no args) - Opens current directory as workspace
 
+mod alternate;
+mod backup;
+mod bookmarks;
+mod edit;
+mod files;
+mod insights;
+mod jumps;
+mod lock;
+mod prefetch;
 mod recents;
+mod search;
 mod state;
+mod stats;
+mod virtual_doc;
+mod warmup;
+mod watcher;
 
+pub use alternate::Alternate;
+pub use backup::{line_changes, Generation};
+pub use bookmarks::Bookmark;
+#[allow(unused_imports)]
+pub use edit::WorkspaceEditSummary;
+pub use files::list_files;
+pub use jumps::Jump;
 pub use recents::{recents_add_or_update, recents_get, Recent};
+pub use search::{find_in_lines, parse_extensions, Pattern, Search, SearchHit};
 #[allow(unused_imports)]
-pub use state::{BufferEntry, Pane, PaneBounds, PaneDirection, Tab, Workspace, WorkspaceConfig};
+pub use state::{BufferEntry, GlobalMark, Pane, PaneBounds, PaneDirection, Tab, Workspace, WorkspaceConfig};
+pub use lock::{LockInfo, LockMode};
+pub use stats::SessionStats;
+pub use virtual_doc::VirtualDocument;
@@ -0,0 +1,203 @@
+//! Background prefetch of files likely to be opened next
+//!
+//! The editor names the files a jump could land on (reference panel
+//! entries, the file under the explorer's selection, the current file's
+//! siblings); a worker thread reads each one and highlights its first
+//! screen ahead of time. Opening a prefetched file takes the ready buffer
+//! instead of going to disk, as long as the file hasn't changed since.
+//! What is kept is bounded by a memory budget, oldest dropped first.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use super::state::BufferEntry;
+
+/// Bytes of file content kept prefetched at most
+const MEMORY_BUDGET: usize = 16 * 1024 * 1024;
+
+/// Files larger than this aren't worth holding ahead of time
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Lines highlighted ahead of time: about a screen
+const HIGHLIGHT_LINES: usize = 120;
+
+/// Siblings of the current file prefetched at most
+const MAX_SIBLINGS: usize = 8;
+
+static CACHE: Mutex<Cache<Prefetched>> = Mutex::new(Cache::new(MEMORY_BUDGET));
+static QUEUE: OnceLock<Mutex<Sender<(PathBuf, PathBuf)>>> = OnceLock::new();
+
+/// A file read ahead of time, with what it looked like on disk then
+struct Prefetched {
+    entry: BufferEntry,
+    root: PathBuf,
+    stamp: (SystemTime, u64),
+}
+
+/// Queue `paths` (absolute), and the nearest siblings of `current`, for
+/// prefetching into buffers of the workspace at `root`; files already kept
+/// or too big are skipped
+pub fn request(current: Option<&Path>, paths: &[PathBuf], root: &Path) {
+    let sender = QUEUE.get_or_init(|| Mutex::new(spawn_worker()));
+    let Ok(sender) = sender.lock() else { return };
+    let siblings = current.map(|path| siblings(path, MAX_SIBLINGS)).unwrap_or_default();
+    for path in paths.iter().chain(&siblings) {
+        let _ = sender.send((path.clone(), root.to_path_buf()));
+    }
+}
+
+/// The prefetched buffer for `path` in the workspace at `root`, if the file
+/// hasn't changed since it was read
+pub fn take(path: &Path, root: &Path) -> Option<BufferEntry> {
+    let key = path.canonicalize().ok()?;
+    let prefetched = CACHE.lock().ok()?.take(&key)?;
+    (prefetched.root == root && stamp(&key) == Some(prefetched.stamp)).then_some(prefetched.entry)
+}
+
+/// Up to `limit` other files in `path`'s directory with its extension,
+/// nearest to it by name first
+fn siblings(path: &Path, limit: usize) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|p| p.extension() == path.extension() && p.file_name() != Some(name) && p.is_file())
+        .collect();
+    files.sort();
+    // Nearest first, alternating after and before the file's own position
+    let at = files.partition_point(|p| p.file_name() < Some(name));
+    let (before, after) = files.split_at(at);
+    let mut nearest = Vec::new();
+    let (mut before, mut after) = (before.iter().rev(), after.iter());
+    while nearest.len() < limit {
+        match (after.next(), before.next()) {
+            (None, None) => break,
+            (a, b) => nearest.extend(a.into_iter().chain(b).cloned()),
+        }
+    }
+    nearest.truncate(limit);
+    nearest
+}
+
+fn spawn_worker() -> Sender<(PathBuf, PathBuf)> {
+    let (tx, rx) = mpsc::channel::<(PathBuf, PathBuf)>();
+    std::thread::spawn(move || {
+        for (path, root) in rx {
+            let Ok(key) = path.canonicalize() else { continue };
+            let Some(stamp) = stamp(&key).filter(|(_, len)| *len <= MAX_FILE_BYTES) else { continue };
+            let fresh = CACHE.lock().is_ok_and(|cache| cache.get(&key).is_some_and(|p| p.stamp == stamp));
+            if fresh {
+                continue;
+            }
+            let Ok(mut entry) = BufferEntry::read_file(&path, &root) else { continue };
+            warm_highlighter(&mut entry);
+            if let Ok(mut cache) = CACHE.lock() {
+                cache.insert(key, stamp.1 as usize, Prefetched { entry, root, stamp });
+            }
+        }
+    });
+    tx
+}
+
+/// Fill the highlighter's state cache for the first screen
+fn warm_highlighter(entry: &mut BufferEntry) {
+    if !entry.highlighter.is_enabled() {
+        return;
+    }
+    let mut state = entry.highlighter.get_state_for_line(0);
+    for line in 0..entry.buffer.line_count().min(HIGHLIGHT_LINES) {
+        let text = entry.buffer.line_str(line).unwrap_or_default();
+        let _ = entry.highlighter.tokenize_line(&text, &mut state);
+        entry.highlighter.update_cache(line, &state);
+    }
+}
+
+/// Modification time and length of a file
+pub(super) fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// Values by path within a byte budget, evicting the oldest first
+struct Cache<T> {
+    entries: VecDeque<(PathBuf, usize, T)>,
+    bytes: usize,
+    budget: usize,
+}
+
+impl<T> Cache<T> {
+    const fn new(budget: usize) -> Self {
+        Self { entries: VecDeque::new(), bytes: 0, budget }
+    }
+
+    fn get(&self, path: &Path) -> Option<&T> {
+        self.entries.iter().find(|(p, _, _)| p == path).map(|(_, _, value)| value)
+    }
+
+    fn take(&mut self, path: &Path) -> Option<T> {
+        let index = self.entries.iter().position(|(p, _, _)| p == path)?;
+        let (_, size, value) = self.entries.remove(index)?;
+        self.bytes -= size;
+        Some(value)
+    }
+
+    fn insert(&mut self, path: PathBuf, size: usize, value: T) {
+        self.take(&path);
+        if size > self.budget {
+            return;
+        }
+        while self.bytes + size > self.budget {
+            let Some((_, evicted, _)) = self.entries.pop_front() else { break };
+            self.bytes -= evicted;
+        }
+        self.bytes += size;
+        self.entries.push_back((path, size, value));
+    }
+
+    #[cfg(test)]
+    fn paths(&self) -> std::collections::HashSet<&Path> {
+        self.entries.iter().map(|(p, _, _)| p.as_path()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_cache_budget() {
+        let mut cache = Cache::new(10);
+        cache.insert(PathBuf::from("a"), 4, 'a');
+        cache.insert(PathBuf::from("b"), 4, 'b');
+        cache.insert(PathBuf::from("c"), 4, 'c');
+        assert_eq!(cache.paths(), HashSet::from([Path::new("b"), Path::new("c")]));
+        assert_eq!(cache.take(Path::new("b")), Some('b'));
+        assert_eq!(cache.bytes, 4);
+        cache.insert(PathBuf::from("huge"), 11, 'h');
+        assert!(cache.get(Path::new("huge")).is_none());
+    }
+
+    #[test]
+    fn test_siblings() {
+        let dir = std::env::temp_dir().join(format!("fackr-prefetch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.rs", "b.rs", "c.rs", "d.rs", "e.rs", "notes.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let names: Vec<String> = siblings(&dir.join("c.rs"), 3)
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["d.rs", "b.rs", "e.rs"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
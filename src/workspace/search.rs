@@ -0,0 +1,281 @@
+//! Search in files (F4) and live grep
+//!
+//! A search runs off the UI thread. One thread lists the workspace's files
+//! the way quick open does (so .gitignore applies) and hands them to a pool
+//! of workers, which grep a file each at a time and send its matching lines
+//! back as soon as it's done. The editor takes in whatever has arrived on
+//! each pass of its loop. Cancelling, or dropping the `Search`, stops the
+//! workers at their next line. Live grep starts a new one, dropping the
+//! last, each time its query settles, and can look for a regex and only in
+//! files with some extensions.
+
+use regex::{Regex, RegexBuilder};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+use super::files::list_files;
+
+/// Matching lines found at most
+pub const MAX_RESULTS: usize = 500;
+
+/// Worker threads grepping files at most
+const MAX_WORKERS: usize = 8;
+
+/// Extensions of files that aren't text
+const BINARY_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "ico", "woff", "woff2", "ttf", "eot", "pdf", "zip", "tar", "gz", "exe", "dll", "so",
+    "dylib", "o", "a", "rlib",
+];
+
+/// A line containing the query
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    /// Relative to the workspace root
+    pub path: PathBuf,
+    /// 1-indexed
+    pub line_num: usize,
+    /// Character column of the first match in the untrimmed line
+    pub col: usize,
+    /// The line, trimmed
+    pub line_content: String,
+}
+
+/// What a search looks for in each line, ignoring case
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Text, lowercased
+    Text(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn text(query: &str) -> Self {
+        Pattern::Text(query.to_lowercase())
+    }
+
+    pub fn regex(query: &str) -> Result<Self, regex::Error> {
+        RegexBuilder::new(query).case_insensitive(true).build().map(Pattern::Regex)
+    }
+
+    /// Character column of the first match in `line`; `lower` is a buffer
+    /// for lowercasing it
+    fn find(&self, line: &str, lower: &mut String) -> Option<usize> {
+        match self {
+            Pattern::Text(query) => find_text(line, query, lower),
+            Pattern::Regex(re) => re.find(line).map(|m| line[..m.start()].chars().count()),
+        }
+    }
+}
+
+/// Extensions named in a file type filter such as `rs, *.toml .md`
+pub fn parse_extensions(types: &str) -> Vec<String> {
+    types
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .map(|t| t.trim_start_matches('*').trim_start_matches('.').to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// A search under way
+#[derive(Debug)]
+pub struct Search {
+    /// Matches of one file at a time, closed once every worker is done
+    hits: Receiver<Vec<SearchHit>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Search {
+    /// Search the files under `root`, outside the `excluded` directories,
+    /// for lines containing `query`, ignoring case
+    pub fn start(root: &Path, excluded: &[PathBuf], query: &str) -> Self {
+        Self::start_pattern(root, excluded, Pattern::text(query), &[])
+    }
+
+    /// Search like `start` for lines matching `pattern`, only in files
+    /// with one of `extensions` (any file when there are none)
+    pub fn start_pattern(root: &Path, excluded: &[PathBuf], pattern: Pattern, extensions: &[String]) -> Self {
+        let (tx, hits) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let found = Arc::new(AtomicUsize::new(0));
+        let query = Arc::new(pattern);
+        let (root, excluded, extensions) = (root.to_path_buf(), excluded.to_vec(), extensions.to_vec());
+
+        let cancel = cancelled.clone();
+        std::thread::spawn(move || {
+            let (path_tx, paths) = mpsc::channel::<PathBuf>();
+            let paths = Arc::new(Mutex::new(paths));
+            let workers = std::thread::available_parallelism().map_or(4, |n| n.get()).min(MAX_WORKERS);
+            for _ in 0..workers {
+                let (paths, tx, cancel, found) = (paths.clone(), tx.clone(), cancel.clone(), found.clone());
+                let (root, query) = (root.clone(), query.clone());
+                std::thread::spawn(move || {
+                    // The lock is let go as soon as a path is taken
+                    while let Some(path) = paths.lock().ok().and_then(|paths| paths.recv().ok()) {
+                        let hits = grep(&root, &path, &query, &cancel, &found);
+                        if !hits.is_empty() && tx.send(hits).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            for path in list_files(&root, &excluded) {
+                if cancel.load(Ordering::Relaxed) || found.load(Ordering::Relaxed) >= MAX_RESULTS {
+                    break;
+                }
+                let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                let wanted = extensions.is_empty() || extensions.iter().any(|e| e.eq_ignore_ascii_case(ext));
+                if wanted && !BINARY_EXTENSIONS.contains(&ext) {
+                    let _ = path_tx.send(path);
+                }
+            }
+        });
+
+        Self { hits, cancelled }
+    }
+
+    /// Matches found since the last call, and whether the search has
+    /// finished
+    pub fn poll(&self) -> (Vec<SearchHit>, bool) {
+        let mut hits = Vec::new();
+        loop {
+            match self.hits.try_recv() {
+                Ok(file_hits) => hits.extend(file_hits),
+                Err(TryRecvError::Empty) => return (hits, false),
+                Err(TryRecvError::Disconnected) => return (hits, true),
+            }
+        }
+    }
+
+    /// Stop the workers
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Lines of `lines` containing `query` (lowercase), as the line's index and
+/// the character column of its first match. For searching text already in
+/// memory, such as open buffers with unsaved changes.
+pub fn find_in_lines<S: AsRef<str>>(lines: impl IntoIterator<Item = S>, query: &str) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    let mut line_lower = String::new();
+    for (line_idx, line) in lines.into_iter().enumerate() {
+        let Some(col) = find_text(line.as_ref(), query, &mut line_lower) else {
+            continue;
+        };
+        found.push((line_idx, col));
+        if found.len() >= MAX_RESULTS {
+            break;
+        }
+    }
+    found
+}
+
+/// Character column of `query` (lowercase) in `line`, ignoring case;
+/// `lower` is a buffer for lowercasing the line
+fn find_text(line: &str, query: &str, lower: &mut String) -> Option<usize> {
+    lower.clear();
+    lower.extend(line.chars().flat_map(char::to_lowercase));
+    let at = lower.find(query)?;
+    // Lowercasing can change a line's length; the match's column is only
+    // known when it didn't
+    Some(if lower.len() == line.len() { line.get(..at).map_or(0, |s| s.chars().count()) } else { 0 })
+}
+
+/// Lines of `path` matching `query`, while the search runs and fewer than
+/// `MAX_RESULTS` have been found in all
+fn grep(root: &Path, path: &Path, query: &Pattern, cancelled: &AtomicBool, found: &AtomicUsize) -> Vec<SearchHit> {
+    let mut hits = Vec::new();
+    let Ok(file) = File::open(root.join(path)) else {
+        return hits;
+    };
+    // Reusable buffer for lowercasing
+    let mut line_lower = String::new();
+    for (line_idx, line) in BufReader::new(file).lines().enumerate() {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        // Non-UTF8 content - likely binary, skip the rest of the file
+        let Ok(line) = line else { break };
+        let Some(col) = query.find(&line, &mut line_lower) else {
+            continue;
+        };
+        if found.fetch_add(1, Ordering::Relaxed) >= MAX_RESULTS {
+            break;
+        }
+        hits.push(SearchHit { path: path.to_path_buf(), line_num: line_idx + 1, col, line_content: line.trim().to_string() });
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_find_in_lines() {
+        let lines = ["fn Needle() {}", "other", "  éé needle again"];
+        assert_eq!(find_in_lines(lines, "needle"), [(0, 3), (2, 5)]);
+        assert_eq!(find_in_lines(lines, "absent"), []);
+    }
+
+    #[test]
+    fn test_search_streams_all_hits() {
+        let dir = std::env::temp_dir().join(format!("fackr-search-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/a.rs"), "fn Needle() {}\nother\nneedle again\n").unwrap();
+        std::fs::write(dir.join("b.txt"), "no match here\n").unwrap();
+        std::fs::write(dir.join("c.png"), "needle\n").unwrap();
+
+        let search = Search::start(&dir, &[], "NEEDLE");
+        let mut hits = Vec::new();
+        let started = Instant::now();
+        loop {
+            let (new, done) = search.poll();
+            hits.extend(new);
+            if done || started.elapsed() > Duration::from_secs(10) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        hits.sort_by_key(|hit| hit.line_num);
+        let lines: Vec<(&Path, usize)> = hits.iter().map(|hit| (hit.path.as_path(), hit.line_num)).collect();
+        assert_eq!(lines, [(Path::new("src/a.rs"), 1), (Path::new("src/a.rs"), 3)]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_regex_and_extensions() {
+        let dir = std::env::temp_dir().join(format!("fackr-grep-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.rs"), "let x = 1;\nfn main() {}\n").unwrap();
+        std::fs::write(dir.join("b.md"), "fn in prose\n").unwrap();
+        assert_eq!(parse_extensions("*.RS, .toml md"), ["rs", "toml", "md"]);
+
+        let search = Search::start_pattern(&dir, &[], Pattern::regex(r"fn \w+\(").unwrap(), &parse_extensions("rs"));
+        let mut hits = Vec::new();
+        let started = Instant::now();
+        loop {
+            let (new, done) = search.poll();
+            hits.extend(new);
+            if done || started.elapsed() > Duration::from_secs(10) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let lines: Vec<(&Path, usize, usize)> = hits.iter().map(|hit| (hit.path.as_path(), hit.line_num, hit.col)).collect();
+        assert_eq!(lines, [(Path::new("a.rs"), 2, 0)]);
+        assert!(Pattern::regex("(").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -7,13 +7,33 @@
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::time::{Instant, SystemTime};
 
-use crate::buffer::Buffer;
-use crate::editor::{Cursor, Cursors, History};
+use crate::buffer::{Buffer, Indent};
+use crate::config::{Keymap, Settings};
+use crate::editor::{Cursor, Cursors, History, Position};
 use crate::fuss::FussMode;
+use crate::git::{diff, BlameLine};
 use crate::lsp::LspClient;
-use crate::syntax::Highlighter;
+use crate::render::whitespace::Whitespace;
+use crate::render::{image, LayoutConfig, Rect, DEFAULT_THEME};
+use crate::util::{elevate, logging};
+use crate::syntax::{self, ansi, Highlighter, LanguageOverride};
+
+use super::alternate::{self, Alternate, AlternateRule};
+use super::backup::{self, Generation};
+use super::bookmarks::Bookmarks;
+use super::files;
+use super::jumps::JumpList;
+use super::lock::{LockInfo, LockMode};
+use super::{insights, prefetch, warmup};
+use super::stats::SessionStats;
+use super::virtual_doc::VirtualDocument;
+use super::watcher::{FileEvents, FileWatcher};
 
 // ============================================================================
 // Serializable state structures for workspace persistence
@@ -24,6 +44,30 @@ use crate::syntax::Highlighter;
 struct WorkspaceState {
     active_tab: usize,
     tabs: Vec<TabState>,
+    /// Terminal panel height as a percentage of the screen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    terminal_height: Option<u16>,
+    /// Uppercase (file-global) marks
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    marks: BTreeMap<char, GlobalMark>,
+    /// Recently opened files, most recent first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    recent_files: Vec<PathBuf>,
+    /// Pinned clipboard entries
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pinned_clips: Vec<String>,
+    /// Bookmarked lines across files
+    #[serde(default, skip_serializing_if = "Bookmarks::is_empty")]
+    bookmarks: Bookmarks,
+}
+
+/// A file-global mark (A-Z). Paths are relative to the workspace root
+/// unless the file lives outside it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalMark {
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
 }
 
 /// Serializable tab state
@@ -44,6 +88,9 @@ struct FileState {
     path: Option<PathBuf>,
     /// Whether file is outside workspace
     is_orphan: bool,
+    /// Collapsed fold regions (start_line, end_line)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    folds: Vec<(usize, usize)>,
 }
 
 /// Serializable pane state
@@ -59,6 +106,24 @@ struct PaneState {
     viewport_col: usize,
     /// Pane bounds (normalized 0.0-1.0)
     bounds: BoundsState,
+    /// Selection anchor of the primary cursor, if it has a selection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    anchor: Option<(usize, usize)>,
+    /// Cursors besides the primary one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extra_cursors: Vec<CursorState>,
+    /// Soft wrap is on
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    wrap: bool,
+}
+
+/// Serializable secondary cursor
+#[derive(Debug, Serialize, Deserialize)]
+struct CursorState {
+    line: usize,
+    col: usize,
+    anchor_line: usize,
+    anchor_col: usize,
 }
 
 /// Serializable pane bounds
@@ -109,8 +174,20 @@ pub struct BufferEntry {
     saved_hash: Option<u64>,
     /// Length of buffer at last save (sentinel for quick modified check)
     saved_len: Option<usize>,
+    /// Modification time and size of the file when it was last loaded or
+    /// saved, to notice someone else writing it before we save
+    disk_stamp: Option<(SystemTime, u64)>,
     /// Whether current modifications have been backed up (reset on save)
     pub backed_up: bool,
+    /// Provider for read-only generated content (diffs, logs); None for files
+    pub virtual_doc: Option<VirtualDocument>,
+    /// Git blame of the saved file, loaded on demand (None until loaded)
+    pub blame: Option<Vec<BlameLine>>,
+    /// The file is colored terminal output, not yet offered as a colored view
+    pub ansi_offer: bool,
+    /// Changed spans within paired removed/added lines of a diff document,
+    /// as (line, character range)
+    pub word_diff: Vec<(usize, Range<usize>)>,
 }
 
 impl BufferEntry {
@@ -126,7 +203,12 @@ impl BufferEntry {
             is_orphan: false,
             saved_hash,
             saved_len,
+            disk_stamp: None,
             backed_up: false, // Will backup on first edit
+            virtual_doc: None,
+            blame: None,
+            ansi_offer: false,
+            word_diff: Vec::new(),
         }
     }
 
@@ -151,23 +233,54 @@ impl BufferEntry {
             is_orphan: true, // Mark as orphan so path isn't prefixed with workspace root
             saved_hash,
             saved_len,
+            disk_stamp: None,
             backed_up: true, // Content buffers (like diffs) don't need backup
+            virtual_doc: None,
+            blame: None,
+            ansi_offer: false,
+            word_diff: Vec::new(),
+        }
+    }
+
+    /// Create a read-only buffer for a virtual document
+    pub fn from_virtual(doc: VirtualDocument, content: &str) -> Self {
+        let mut entry = Self::from_content("", doc.language_hint().as_deref());
+        entry.path = None;
+        entry.virtual_doc = Some(doc);
+        entry.set_virtual_content(content);
+        entry
+    }
+
+    /// Show a virtual document's freshly loaded content. An ANSI colored
+    /// view drops the escape codes from the text and is colored by them;
+    /// a diff's changed words are found for highlighting.
+    fn set_virtual_content(&mut self, content: &str) {
+        if matches!(self.virtual_doc, Some(VirtualDocument::AnsiColors { .. })) {
+            let styled = ansi::parse(content);
+            self.buffer.set_contents(&styled.text);
+            self.highlighter.set_line_tokens(styled.lines);
+        } else {
+            self.buffer.set_contents(content);
+        }
+        if self.virtual_doc.as_ref().is_some_and(|doc| doc.is_diff()) {
+            let lines: Vec<&str> = content.lines().collect();
+            self.word_diff = diff::intra_line_changes(&lines);
         }
+        self.buffer.modified = false;
+        self.mark_saved();
+        self.backed_up = true; // Generated content needs no backup
+        self.highlighter.invalidate_cache(0);
+    }
+
+    /// Virtual documents can't be edited or saved
+    pub fn is_read_only(&self) -> bool {
+        self.virtual_doc.is_some()
     }
 
     /// Create an empty buffer for a new file that doesn't exist yet
     pub fn new_file(path: &Path, workspace_root: &Path) -> Self {
         let buffer = Buffer::new();
-        let is_orphan = !path.starts_with(workspace_root);
-
-        // Store relative path for workspace files, absolute for orphans
-        let stored_path = if is_orphan {
-            path.to_path_buf()
-        } else {
-            path.strip_prefix(workspace_root)
-                .unwrap_or(path)
-                .to_path_buf()
-        };
+        let (stored_path, is_orphan) = stored_path(path, workspace_root);
 
         // Detect language for syntax highlighting
         let mut highlighter = Highlighter::new();
@@ -183,24 +296,29 @@ impl BufferEntry {
             is_orphan,
             saved_hash: None, // Not saved yet - will prompt on close
             saved_len: None,
+            disk_stamp: None,
             backed_up: false, // Will backup on first edit
+            virtual_doc: None,
+            blame: None,
+            ansi_offer: false,
+            word_diff: Vec::new(),
         }
     }
 
     pub fn from_file(path: &Path, workspace_root: &Path) -> Result<Self> {
+        match prefetch::take(path, workspace_root) {
+            Some(entry) => Ok(entry),
+            None => Self::read_file(path, workspace_root),
+        }
+    }
+
+    /// Load a file from disk, skipping the prefetched copies
+    pub(super) fn read_file(path: &Path, workspace_root: &Path) -> Result<Self> {
+        let disk_stamp = prefetch::stamp(path);
         let mut buffer = Buffer::load(path)?;
         let saved_hash = Some(buffer.content_hash()); // Hash at load time
         let saved_len = Some(buffer.len_chars());
-        let is_orphan = !path.starts_with(workspace_root);
-
-        // Store relative path for workspace files, absolute for orphans
-        let stored_path = if is_orphan {
-            path.to_path_buf()
-        } else {
-            path.strip_prefix(workspace_root)
-                .unwrap_or(path)
-                .to_path_buf()
-        };
+        let (stored_path, is_orphan) = stored_path(path, workspace_root);
 
         // Detect language for syntax highlighting
         let mut highlighter = Highlighter::new();
@@ -208,6 +326,8 @@ impl BufferEntry {
             highlighter.detect_language(filename);
         }
 
+        let ansi_offer = ansi::is_ansi_heavy(&buffer.slice(0, ansi::SAMPLE_CHARS).to_string());
+
         Ok(Self {
             path: Some(stored_path),
             buffer,
@@ -216,12 +336,20 @@ impl BufferEntry {
             is_orphan,
             saved_hash,
             saved_len,
+            disk_stamp,
             backed_up: false, // Will backup on first edit
+            virtual_doc: None,
+            blame: None,
+            ansi_offer,
+            word_diff: Vec::new(),
         })
     }
 
     /// Get the display name for the tab bar
     pub fn display_name(&self) -> String {
+        if let Some(doc) = &self.virtual_doc {
+            return doc.title();
+        }
         match &self.path {
             Some(p) => p.file_name()
                 .and_then(|n| n.to_str())
@@ -246,11 +374,86 @@ impl BufferEntry {
         }
     }
 
+    /// Whether `disk` holds what the buffer was last loaded or saved as
+    pub fn saved_as(&self, disk: &mut Buffer) -> bool {
+        self.saved_hash == Some(disk.content_hash()) && self.saved_len == Some(disk.len_chars())
+    }
+
     /// Mark the buffer as saved (updates hash and length for change detection)
     pub fn mark_saved(&mut self) {
         self.saved_hash = Some(self.buffer.content_hash());
         self.saved_len = Some(self.buffer.len_chars());
         self.backed_up = false; // Reset - will backup on next edit
+        self.blame = None; // Lines may have moved; reload on next use
+    }
+
+    /// Write the buffer to `full_path` (its own file) and mark it saved
+    pub fn save_to(&mut self, full_path: &Path) -> Result<()> {
+        self.buffer.save(full_path)?;
+        self.mark_saved();
+        self.disk_stamp = prefetch::stamp(full_path);
+        Ok(())
+    }
+
+    /// Write the buffer to `full_path` as root (see `util::elevate`) and
+    /// mark it saved
+    pub fn save_as_root(&mut self, full_path: &Path) -> Result<()> {
+        let mut contents = Vec::new();
+        self.buffer.write_to(&mut contents)?;
+        elevate::write_as_root(full_path, &contents)?;
+        self.buffer.modified = false;
+        self.mark_saved();
+        self.disk_stamp = prefetch::stamp(full_path);
+        Ok(())
+    }
+
+    /// Write the buffer to `full_path` and make that its file from now on
+    pub fn save_as(&mut self, full_path: &Path, workspace_root: &Path) -> Result<()> {
+        if let Some(parent) = full_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        self.save_to(full_path)?;
+        let (stored_path, is_orphan) = stored_path(full_path, workspace_root);
+        self.path = Some(stored_path);
+        self.is_orphan = is_orphan;
+        if let Some(filename) = full_path.file_name().and_then(|n| n.to_str()) {
+            self.highlighter.detect_language(filename);
+        }
+        Ok(())
+    }
+
+    /// Take the file at `full_path` as it is on disk now as seen, so that
+    /// saving over it doesn't ask
+    pub fn accept_disk(&mut self, full_path: &Path) {
+        self.disk_stamp = prefetch::stamp(full_path);
+    }
+
+    /// Whether someone else wrote `full_path` since this buffer loaded or
+    /// saved it: its time or size moved and it no longer holds the text
+    /// last saved. A missing file hasn't changed (saving recreates it).
+    pub fn changed_on_disk(&mut self, full_path: &Path) -> bool {
+        let stamp = prefetch::stamp(full_path);
+        if stamp.is_none() || stamp == self.disk_stamp {
+            return false;
+        }
+        let Ok(mut disk) = Buffer::load(full_path) else {
+            return false;
+        };
+        if self.saved_as(&mut disk) {
+            // Touched, or our own reload: nothing to lose
+            self.disk_stamp = stamp;
+            return false;
+        }
+        true
+    }
+}
+
+/// How a buffer stores the file at `path`: relative to the workspace root
+/// for workspace files, absolute for orphans (true)
+fn stored_path(path: &Path, workspace_root: &Path) -> (PathBuf, bool) {
+    match path.strip_prefix(workspace_root) {
+        Ok(relative) => (relative.to_path_buf(), false),
+        Err(_) => (path.to_path_buf(), true),
     }
 }
 
@@ -271,10 +474,40 @@ pub struct Pane {
     pub viewport_line: usize,
     /// First visible column (for horizontal scrolling)
     pub viewport_col: usize,
+    /// Soft-wrap long lines instead of scrolling horizontally
+    pub wrap: bool,
     /// Normalized bounds within the tab area
     pub bounds: PaneBounds,
+    /// Local marks (a-z) as (line, col) in this pane's buffer
+    pub marks: BTreeMap<char, (usize, usize)>,
+    /// Buffers this pane showed before the current one (most recent last)
+    pub back: Vec<PaneView>,
+    /// Buffers left with `go_back`, for `go_forward`
+    pub forward: Vec<PaneView>,
+}
+
+/// What a pane showed of one buffer, kept in its back/forward history
+#[derive(Debug, Clone)]
+pub struct PaneView {
+    pub buffer_idx: usize,
+    pub cursors: Cursors,
+    pub viewport_line: usize,
+    pub viewport_col: usize,
+    pub marks: BTreeMap<char, (usize, usize)>,
 }
 
+/// Most buffers a pane remembers in each direction
+const PANE_HISTORY_LIMIT: usize = 50;
+
+/// Most files kept in the workspace's recent files list
+const RECENT_FILES_LIMIT: usize = 50;
+
+/// Most entries kept in the workspace's clipboard ring
+const CLIPBOARD_RING_LIMIT: usize = 30;
+
+/// Scratchpad notes file in the state directory
+const NOTES_FILE: &str = "notes.md";
+
 impl Default for Pane {
     fn default() -> Self {
         Self {
@@ -282,7 +515,11 @@ impl Default for Pane {
             cursors: Cursors::new(),
             viewport_line: 0,
             viewport_col: 0,
+            wrap: false,
             bounds: PaneBounds::default(),
+            marks: BTreeMap::new(),
+            back: Vec::new(),
+            forward: Vec::new(),
         }
     }
 }
@@ -298,6 +535,69 @@ impl Pane {
             ..Default::default()
         }
     }
+
+    /// Show another buffer, remembering the current one for `go_back`. A
+    /// buffer this pane showed before comes back with the cursors,
+    /// selections and scroll position it was left with.
+    pub fn show_buffer(&mut self, buffer_idx: usize) {
+        if buffer_idx == self.buffer_idx {
+            return;
+        }
+        let view = self.last_view_of(buffer_idx).unwrap_or_else(|| PaneView {
+            buffer_idx,
+            cursors: Cursors::new(),
+            viewport_line: 0,
+            viewport_col: 0,
+            marks: BTreeMap::new(),
+        });
+        let previous = self.replace_view(view);
+        self.back.push(previous);
+        if self.back.len() > PANE_HISTORY_LIMIT {
+            self.back.remove(0);
+        }
+        self.forward.clear();
+    }
+
+    /// Return to the previously shown buffer; false if there is none
+    pub fn go_back(&mut self) -> bool {
+        let Some(view) = self.back.pop() else {
+            return false;
+        };
+        let current = self.replace_view(view);
+        self.forward.push(current);
+        true
+    }
+
+    /// Undo a `go_back`; false if there is nothing to go forward to
+    pub fn go_forward(&mut self) -> bool {
+        let Some(view) = self.forward.pop() else {
+            return false;
+        };
+        let current = self.replace_view(view);
+        self.back.push(current);
+        true
+    }
+
+    /// The most recent view of `buffer_idx` in this pane's history
+    fn last_view_of(&self, buffer_idx: usize) -> Option<PaneView> {
+        self.back
+            .iter()
+            .rev()
+            .chain(self.forward.iter().rev())
+            .find(|view| view.buffer_idx == buffer_idx)
+            .cloned()
+    }
+
+    /// Swap in `view`, returning what the pane showed before
+    fn replace_view(&mut self, view: PaneView) -> PaneView {
+        PaneView {
+            buffer_idx: std::mem::replace(&mut self.buffer_idx, view.buffer_idx),
+            cursors: std::mem::replace(&mut self.cursors, view.cursors),
+            viewport_line: std::mem::replace(&mut self.viewport_line, view.viewport_line),
+            viewport_col: std::mem::replace(&mut self.viewport_col, view.viewport_col),
+            marks: std::mem::replace(&mut self.marks, view.marks),
+        }
+    }
 }
 
 /// A tab represents a view group with one or more panes viewing buffers
@@ -341,9 +641,9 @@ impl Tab {
         }
     }
 
-    /// Create a tab from string content (for diff views, etc.)
-    pub fn from_content(content: &str, display_name: &str) -> Self {
-        let buffer_entry = BufferEntry::from_content(content, Some(display_name));
+    /// Create a tab showing a virtual document
+    pub fn from_virtual(doc: VirtualDocument, content: &str) -> Self {
+        let buffer_entry = BufferEntry::from_virtual(doc, content);
         Self {
             buffers: vec![buffer_entry],
             panes: vec![Pane::new()],
@@ -446,9 +746,13 @@ impl Tab {
     /// Split vertical with a new file in the new pane
     pub fn split_vertical_with_file(&mut self, path: &Path, workspace_root: &Path) -> Result<()> {
         let buffer_entry = BufferEntry::from_file(path, workspace_root)?;
-        let new_buffer_idx = self.buffers.len();
         self.buffers.push(buffer_entry);
+        self.split_vertical_with_buffer(self.buffers.len() - 1);
+        Ok(())
+    }
 
+    /// Split vertical with one of the tab's buffers in the new pane
+    pub fn split_vertical_with_buffer(&mut self, buffer_idx: usize) {
         let active = &self.panes[self.active_pane];
         let old_bounds = active.bounds.clone();
         let mid_x = (old_bounds.x_start + old_bounds.x_end) / 2.0;
@@ -456,8 +760,8 @@ impl Tab {
         // Shrink active pane
         self.panes[self.active_pane].bounds.x_end = mid_x;
 
-        // Create new pane to the right with the new buffer
-        let mut new_pane = Pane::with_buffer_idx(new_buffer_idx);
+        // Create new pane to the right with the buffer
+        let mut new_pane = Pane::with_buffer_idx(buffer_idx);
         new_pane.bounds = PaneBounds {
             x_start: mid_x,
             y_start: old_bounds.y_start,
@@ -467,7 +771,6 @@ impl Tab {
 
         self.panes.push(new_pane);
         self.active_pane = self.panes.len() - 1;
-        Ok(())
     }
 
     /// Split horizontal with a new file in the new pane
@@ -497,6 +800,80 @@ impl Tab {
         Ok(())
     }
 
+    /// Index of the buffer holding `path`, if this tab has it loaded
+    fn buffer_index_for(&self, path: &Path, workspace_root: &Path) -> Option<usize> {
+        let target = path.canonicalize().ok()?;
+        self.buffers.iter().position(|entry| {
+            entry.path.as_ref().is_some_and(|p| {
+                let full = if entry.is_orphan { p.clone() } else { workspace_root.join(p) };
+                full.canonicalize().ok().as_ref() == Some(&target)
+            })
+        })
+    }
+
+    /// Show a file in the active pane, loading it into this tab if needed.
+    /// Returns the new buffer entry's index when the file was loaded.
+    pub fn show_file_in_active_pane(&mut self, path: &Path, workspace_root: &Path) -> Result<Option<usize>> {
+        let (buffer_idx, loaded) = match self.buffer_index_for(path, workspace_root) {
+            Some(idx) => (idx, None),
+            None => {
+                self.buffers.push(BufferEntry::from_file(path, workspace_root)?);
+                (self.buffers.len() - 1, Some(self.buffers.len() - 1))
+            }
+        };
+        self.active_pane_mut().show_buffer(buffer_idx);
+        self.clamp_active_view();
+        Ok(loaded)
+    }
+
+    /// Keep the active pane's cursors and scroll position inside its buffer
+    /// after it switched views, as another pane may have edited the buffer
+    /// in the meantime
+    pub fn clamp_active_view(&mut self) {
+        let pane = &mut self.panes[self.active_pane];
+        let buffer = &self.buffers[pane.buffer_idx].buffer;
+        pane.cursors.clamp_to(buffer);
+        pane.viewport_line = pane.viewport_line.min(buffer.line_count().saturating_sub(1));
+    }
+
+    /// Replace a buffer's contents with `text` read from its file, in one
+    /// undoable step, and count the buffer as saved
+    pub fn reload_buffer(&mut self, buffer_idx: usize, text: &str) {
+        let cursor = self
+            .panes
+            .iter()
+            .find(|pane| pane.buffer_idx == buffer_idx)
+            .map_or(Position { line: 0, col: 0 }, |pane| {
+                let primary = pane.cursors.primary();
+                Position { line: primary.line, col: primary.col }
+            });
+        let entry = &mut self.buffers[buffer_idx];
+        let old = entry.buffer.contents();
+        if old == text {
+            entry.mark_saved();
+            return;
+        }
+        entry.history.begin_group();
+        entry.history.set_cursors_before(vec![cursor]);
+        entry.buffer.delete(0, entry.buffer.len_chars());
+        entry.history.record_delete(0, old, cursor, cursor);
+        if !text.is_empty() {
+            entry.buffer.insert(0, text);
+            entry.history.record_insert(0, text.to_string(), cursor, cursor);
+        }
+        entry.history.set_cursors_after(vec![cursor]);
+        entry.history.end_group();
+        entry.buffer.indent = Indent::detect(text).or(entry.buffer.indent);
+        entry.mark_saved();
+        entry.highlighter.invalidate_cache(0);
+
+        let buffer = &self.buffers[buffer_idx].buffer;
+        for pane in self.panes.iter_mut().filter(|pane| pane.buffer_idx == buffer_idx) {
+            pane.cursors.clamp_to(buffer);
+            pane.viewport_line = pane.viewport_line.min(buffer.line_count().saturating_sub(1));
+        }
+    }
+
     /// Close the active pane
     /// Returns true if the tab should be closed (no panes left)
     pub fn close_active_pane(&mut self) -> bool {
@@ -631,6 +1008,14 @@ impl Tab {
     pub fn is_orphan(&self) -> bool {
         self.buffers.first().map(|b| b.is_orphan).unwrap_or(false)
     }
+
+    /// Whether the primary buffer is a file outside the workspace root, as
+    /// opposed to generated content such as a diff (also kept as orphans)
+    pub fn is_outside_workspace(&self) -> bool {
+        self.buffers.first().is_some_and(|b| {
+            b.is_orphan && b.virtual_doc.is_none() && b.path.as_deref().is_some_and(Path::is_absolute)
+        })
+    }
 }
 
 /// Direction for pane navigation
@@ -648,13 +1033,86 @@ impl Default for Tab {
     }
 }
 
-/// Workspace configuration
-#[derive(Debug, Clone)]
+/// Workspace configuration, persisted in .fackr/config.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WorkspaceConfig {
     /// Tab width in spaces
     pub tab_width: usize,
     /// Use spaces instead of tabs
     pub use_spaces: bool,
+    /// Show the first diagnostic for each line as virtual text after the code
+    pub error_lens: bool,
+    /// Show who last changed the cursor line, and when, after the code
+    pub inline_blame: bool,
+    /// Seconds of idle time after an edit before modified buffers are backed up
+    pub backup_idle_secs: u64,
+    /// Buffers larger than this many bytes are not backed up (0 = no limit)
+    pub backup_max_bytes: u64,
+    /// Gzip backup files
+    pub backup_compress: bool,
+    /// Earlier versions kept per file from idle backups, for Recover
+    /// Previous Version (0 = keep none)
+    pub backup_generations: usize,
+    /// Ctrl+Z suspends to the shell instead of undoing
+    pub ctrl_z_suspends: bool,
+    /// Color theme name: a built-in theme or `~/.config/fackr/themes/<name>.toml`
+    pub theme: String,
+    /// Editor log level: error, warn, info, debug or trace (`FACKR_LOG` overrides)
+    pub log_level: String,
+    /// Inline image protocol: kitty, iterm2, sixel, none, or auto to detect it
+    pub image_protocol: String,
+    /// Replacement for each literal in the string extraction panel; `{}` is the literal
+    pub string_template: String,
+    /// Vim-style modal editing (normal/insert/visual modes)
+    pub vim_mode: bool,
+    /// Whether Enter continues comments, per lowercase language name
+    /// (e.g. `"python": false`); languages not listed continue them
+    pub comment_continuation: BTreeMap<String, bool>,
+    /// Comment syntax, extra bracket pairs and string delimiters per
+    /// lowercase language name; unknown names define new languages
+    pub languages: BTreeMap<String, LanguageOverride>,
+    /// Record editing statistics in .fackr/stats/
+    pub stats: bool,
+    /// Analyse the workspace (sizes, lines per language, unreferenced
+    /// files) in the background once the editor has been idle a while
+    pub insights: bool,
+    /// Patterns pairing files with their alternates (header/source,
+    /// test/implementation); replaces the built-in list when given
+    pub alternate_files: Vec<AlternateRule>,
+    /// After Replace All, list the changed lines so each can be reverted
+    pub review_replace_all: bool,
+    /// Directories (relative to the root) left out of quick open, search
+    /// and the size check
+    pub excluded_dirs: Vec<PathBuf>,
+    /// A root with more entries than this in its first levels asks before
+    /// being scanned (0 = never ask)
+    pub huge_workspace_entries: usize,
+    /// Line diff algorithm for diffs and hunks: myers, minimal, patience or
+    /// histogram (the last two handle moved blocks better)
+    pub diff_algorithm: String,
+    /// Snippets by lowercase language name, then prefix: typing a prefix
+    /// and pressing Tab expands its body (`$1`, `${2:default}`, `$0`)
+    pub snippets: BTreeMap<String, BTreeMap<String, String>>,
+    /// Let git see .fackr/notes.md; otherwise .fackr/.gitignore lists it
+    pub commit_notes: bool,
+    /// Reopen the tabs, panes and cursors of the last session
+    pub restore_session: bool,
+    /// Show a minimap of the buffer at the right of panes wide enough
+    pub minimap: bool,
+    /// Draw vertical guides at each indentation level
+    pub indent_guides: bool,
+    /// Draw whitespace as dim marks: none, trailing or all
+    pub whitespace: Whitespace,
+    /// Columns marked with a dim vertical band in every pane, e.g. `[80, 120]`
+    pub rulers: Vec<usize>,
+    /// Give the line with the cursor its own background
+    pub highlight_current_line: bool,
+    /// Start the language servers of the workspace's main languages when
+    /// it opens, before any of their files is
+    pub lsp_warmup: bool,
+    /// Where the explorer, terminal and panels go
+    pub layout: LayoutConfig,
     // Add more config options as needed
 }
 
@@ -663,10 +1121,87 @@ impl Default for WorkspaceConfig {
         Self {
             tab_width: 4,
             use_spaces: true,
+            error_lens: true,
+            inline_blame: false,
+            backup_idle_secs: 30,
+            backup_max_bytes: 16 * 1024 * 1024,
+            backup_compress: true,
+            backup_generations: 10,
+            ctrl_z_suspends: false,
+            theme: DEFAULT_THEME.to_string(),
+            log_level: logging::DEFAULT_LEVEL.to_string(),
+            image_protocol: "auto".to_string(),
+            string_template: "t!({})".to_string(),
+            vim_mode: false,
+            comment_continuation: BTreeMap::new(),
+            languages: BTreeMap::new(),
+            stats: false,
+            insights: false,
+            alternate_files: alternate::default_rules(),
+            review_replace_all: false,
+            excluded_dirs: Vec::new(),
+            huge_workspace_entries: 20_000,
+            diff_algorithm: "myers".to_string(),
+            snippets: BTreeMap::new(),
+            commit_notes: false,
+            restore_session: true,
+            minimap: false,
+            indent_guides: false,
+            whitespace: Whitespace::None,
+            rulers: Vec::new(),
+            highlight_current_line: true,
+            lsp_warmup: true,
+            layout: LayoutConfig::default(),
         }
     }
 }
 
+/// Whether files can be created in `dir` (creating it if needed)
+fn dir_is_writable(dir: &Path) -> bool {
+    if std::fs::create_dir_all(dir).is_err() {
+        return false;
+    }
+    let probe = dir.join(".write-test");
+    let ok = std::fs::write(&probe, b"").is_ok();
+    let _ = std::fs::remove_file(&probe);
+    ok
+}
+
+/// Make sure the `.gitignore` in `dir` lists `name`. A new one lists
+/// itself too, so the directory adds nothing to `git status`.
+fn ignore_in_dir(dir: &Path, name: &str) -> Result<()> {
+    let path = dir.join(".gitignore");
+    let mut text = std::fs::read_to_string(&path).unwrap_or_default();
+    if text.lines().any(|line| line.trim() == name) {
+        return Ok(());
+    }
+    if text.is_empty() {
+        text.push_str(".gitignore\n");
+    } else if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(name);
+    text.push('\n');
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// Per-user state directory for a workspace whose `.fackr/` is read-only,
+/// e.g. `~/.cache/fackr/workspaces/project-1a2b3c4d5e6f7a8b`
+fn fallback_state_dir(root: &Path) -> Option<PathBuf> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    root.hash(&mut hasher);
+    let name = root.file_name().and_then(|n| n.to_str()).unwrap_or("root");
+    let dir = dirs::cache_dir()?
+        .join("fackr")
+        .join("workspaces")
+        .join(format!("{}-{:016x}", name, hasher.finish()));
+    dir_is_writable(&dir).then_some(dir)
+}
+
 /// The Workspace - defining unit of fackr
 ///
 /// Every editing session operates within a workspace context.
@@ -682,37 +1217,111 @@ pub struct Workspace {
     pub fuss: FussMode,
     /// Workspace configuration
     pub config: WorkspaceConfig,
+    /// Key bindings (defaults, then ~/.config/fackr/keymap.toml, then .fackr/keymap.toml)
+    pub keymap: Keymap,
     /// LSP client for language server support
     pub lsp: LspClient,
+    /// Remembered terminal panel height (percent of screen)
+    pub terminal_height: Option<u16>,
+    /// File-global marks (A-Z), persisted in workspace.json
+    pub marks: BTreeMap<char, GlobalMark>,
+    /// Bookmarked lines, persisted in workspace.json
+    pub bookmarks: Bookmarks,
+    /// Places jumped away from, for Navigate Back/Forward
+    pub jumps: JumpList,
+    /// Recently opened files relative to the root, most recent first,
+    /// persisted in workspace.json
+    pub recent_files: Vec<PathBuf>,
+    /// Text copied or cut in any tab or pane, or found on the system
+    /// clipboard when pasting, most recent first
+    pub clipboard_ring: Vec<String>,
+    /// Clipboard entries pinned for this workspace (license headers, debug
+    /// macros), offered before the ring; persisted in workspace.json
+    pub pinned_clips: Vec<String>,
+    /// Where state, config and backups are written: `<root>/.fackr`, or a
+    /// per-user cache directory when that isn't writable
+    pub state_dir: PathBuf,
+    /// `.fackr/` is not writable and state lives in the cache directory
+    pub degraded: bool,
+    /// Whether we own the workspace lock (see `lock.rs`)
+    pub lock_mode: LockMode,
+    /// Live lock held by another instance, found when opening
+    pub lock_conflict: Option<LockInfo>,
+    /// Last time the lock heartbeat was written
+    pub(super) last_heartbeat: Option<Instant>,
+    /// Statistics of this session, when enabled in the config
+    pub stats: Option<SessionStats>,
+    /// The root looked too big to scan when opened, and scanning it
+    /// anyway hasn't been allowed yet
+    pub scan_limited: bool,
+    /// Notices changes made outside the editor, where the OS supports it
+    watcher: Option<FileWatcher>,
+    /// The main languages, from the warm-up's background scan
+    warmup: Option<Receiver<Vec<&'static str>>>,
 }
 
 impl Workspace {
     /// Create a new workspace for a directory
     pub fn new(root: PathBuf) -> Self {
-        let mut fuss = FussMode::new();
-        fuss.init(&root);
+        let fuss = FussMode::new();
         let root_str = root.to_string_lossy().to_string();
         let lsp = LspClient::new(&root_str);
+        let state_dir = root.join(".fackr");
         Self {
             root,
             tabs: vec![Tab::new()],
             active_tab: 0,
             fuss,
             config: WorkspaceConfig::default(),
+            keymap: Keymap::default(),
             lsp,
+            terminal_height: None,
+            marks: BTreeMap::new(),
+            bookmarks: Bookmarks::default(),
+            jumps: JumpList::default(),
+            recent_files: Vec::new(),
+            clipboard_ring: Vec::new(),
+            pinned_clips: Vec::new(),
+            state_dir,
+            degraded: false,
+            lock_mode: LockMode::Owner,
+            lock_conflict: None,
+            last_heartbeat: None,
+            stats: None,
+            scan_limited: false,
+            watcher: None,
+            warmup: None,
         }
     }
 
-    /// Initialize workspace directory structure (.fackr/)
-    pub fn init(&self) -> Result<()> {
+    /// Initialize workspace directory structure (.fackr/).
+    /// If it can't be written (read-only mount, protected directory), state
+    /// is redirected to a per-user cache directory and the workspace runs degraded.
+    pub fn init(&mut self) -> Result<()> {
         let fackr_dir = self.root.join(".fackr");
-        if !fackr_dir.exists() {
-            std::fs::create_dir_all(&fackr_dir)?;
-            std::fs::create_dir_all(fackr_dir.join("backups"))?;
+        if dir_is_writable(&fackr_dir) {
+            self.state_dir = fackr_dir;
+            self.degraded = false;
+        } else {
+            self.state_dir = fallback_state_dir(&self.root)
+                .ok_or_else(|| anyhow::anyhow!("No writable location for workspace state"))?;
+            self.degraded = true;
         }
+        std::fs::create_dir_all(self.state_dir.join("backups"))?;
+        self.lsp.set_log_dir(self.state_dir.join("logs"));
         Ok(())
     }
 
+    /// Path of a state file for reading. In degraded mode a file not yet
+    /// written to the cache falls back to the (read-only) `.fackr/` copy.
+    fn state_file(&self, name: &str) -> PathBuf {
+        let path = self.state_dir.join(name);
+        if self.degraded && !path.exists() {
+            return self.root.join(".fackr").join(name);
+        }
+        path
+    }
+
     /// Check if a directory has an existing workspace
     pub fn exists(dir: &Path) -> bool {
         dir.join(".fackr").join("workspace.json").exists()
@@ -735,7 +1344,27 @@ impl Workspace {
     /// Open a workspace, creating .fackr/ if needed
     pub fn open(root: PathBuf) -> Result<Self> {
         let mut workspace = Self::new(root);
-        workspace.init()?;
+        // Without any writable state location, still open (nothing persists)
+        let _ = workspace.init();
+        workspace.lock_conflict = workspace.acquire_lock();
+
+        // Missing or unreadable config falls back to defaults
+        let _ = workspace.load_config();
+        workspace.load_keymap();
+        if workspace.config.stats {
+            workspace.stats = Some(SessionStats::new());
+        }
+
+        // Hold back scanning a root like the home directory until asked
+        let config = &workspace.config;
+        workspace.scan_limited = files::is_huge(&workspace.root, &config.excluded_dirs, config.huge_workspace_entries);
+        workspace.fuss.limited = workspace.scan_limited;
+        workspace.fuss.set_excluded(workspace.excluded_paths());
+        workspace.fuss.init(&workspace.root);
+        workspace.start_watching();
+        if workspace.config.lsp_warmup && !workspace.scan_limited {
+            workspace.warmup = Some(warmup::start(&workspace.root, &workspace.config.excluded_dirs));
+        }
 
         // Try to load existing state
         if let Err(_e) = workspace.load() {
@@ -746,6 +1375,217 @@ impl Workspace {
         Ok(workspace)
     }
 
+    /// Load workspace configuration from .fackr/config.json, over the
+    /// theme and indentation of the per-user settings
+    pub fn load_config(&mut self) -> Result<()> {
+        let settings = Settings::load();
+        self.config = WorkspaceConfig {
+            theme: settings.theme,
+            tab_width: settings.tab_width,
+            use_spaces: settings.use_spaces,
+            ..WorkspaceConfig::default()
+        };
+        let config_path = self.state_file("config.json");
+        if !config_path.exists() {
+            return Ok(());
+        }
+        let json = std::fs::read_to_string(&config_path)?;
+        // Fields the file leaves out keep the values above
+        let mut config = serde_json::to_value(&self.config)?;
+        if let (Some(base), serde_json::Value::Object(fields)) = (config.as_object_mut(), serde_json::from_str(&json)?) {
+            base.extend(fields);
+        }
+        self.config = serde_json::from_value(config)?;
+        syntax::set_overrides(&self.config.languages);
+        Ok(())
+    }
+
+    /// Excluded directories as absolute paths
+    fn excluded_paths(&self) -> Vec<PathBuf> {
+        self.config.excluded_dirs.iter().map(|dir| self.root.join(dir)).collect()
+    }
+
+    /// Exclude the directory at `path` from workspace features, or include
+    /// it again; returns whether it is now excluded
+    pub fn toggle_excluded(&mut self, path: &Path) -> Result<bool> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path).to_path_buf();
+        let dirs = &mut self.config.excluded_dirs;
+        let excluded = match dirs.iter().position(|dir| *dir == relative) {
+            Some(pos) => {
+                dirs.remove(pos);
+                false
+            }
+            None => {
+                dirs.push(relative);
+                true
+            }
+        };
+        self.fuss.set_excluded(self.excluded_paths());
+        let ignored = self.unwatched_paths();
+        if let Some(watcher) = &mut self.watcher {
+            watcher.set_ignored(ignored);
+            if !self.scan_limited {
+                watcher.watch_tree(&self.root);
+            }
+        }
+        self.save_config()?;
+        Ok(excluded)
+    }
+
+    /// Allow scanning a huge root after all, for the rest of the session
+    pub fn allow_deep_scan(&mut self) {
+        self.scan_limited = false;
+        self.fuss.limited = false;
+        self.fuss.refresh_git_status();
+        if let Some(watcher) = &mut self.watcher {
+            watcher.watch_tree(&self.root);
+        }
+    }
+
+    /// Directories whose changes don't matter to the editor
+    fn unwatched_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.root.join(".git"), self.state_dir.clone()];
+        paths.extend(self.excluded_paths());
+        paths
+    }
+
+    /// Once the warm-up's scan has found the workspace's main languages,
+    /// start their servers
+    pub fn poll_warmup(&mut self) {
+        let Some(languages) = self.warmup.as_ref().and_then(|rx| rx.try_recv().ok()) else {
+            return;
+        };
+        self.warmup = None;
+        let started = self.lsp.warm_up(&languages);
+        tracing::info!("main languages {:?}; warmed up {:?}", languages, started);
+    }
+
+    /// Start noticing changes made outside the editor; without OS support
+    /// for it they just go unnoticed
+    fn start_watching(&mut self) {
+        let Ok(mut watcher) = FileWatcher::new(&self.root, self.unwatched_paths()) else {
+            return;
+        };
+        if !self.scan_limited {
+            watcher.watch_tree(&self.root);
+        }
+        self.watcher = Some(watcher);
+    }
+
+    /// Changes made outside the editor since the last call. The directories
+    /// of open files are watched too, for files outside the root and for
+    /// roots too big to watch whole.
+    pub fn file_events(&mut self) -> FileEvents {
+        let open: Vec<PathBuf> = self
+            .tabs
+            .iter()
+            .flat_map(|tab| &tab.buffers)
+            .filter_map(|entry| self.buffer_file(entry))
+            .collect();
+        let Some(watcher) = &mut self.watcher else {
+            return FileEvents::default();
+        };
+        watcher.watch_parents(open.iter().map(PathBuf::as_path));
+        watcher.poll()
+    }
+
+    /// Absolute path of the file a buffer holds, if it holds one
+    fn buffer_file(&self, entry: &BufferEntry) -> Option<PathBuf> {
+        let path = entry.path.as_ref().filter(|_| entry.virtual_doc.is_none())?;
+        Some(if entry.is_orphan { path.clone() } else { self.root.join(path) })
+    }
+
+    /// Tab and buffer indices of the buffers holding the file at `path`
+    pub fn buffers_of_file(&self, path: &Path) -> Vec<(usize, usize)> {
+        let mut found = Vec::new();
+        for (tab_idx, tab) in self.tabs.iter().enumerate() {
+            for (buffer_idx, entry) in tab.buffers.iter().enumerate() {
+                if self.buffer_file(entry).is_some_and(|file| file == path) {
+                    found.push((tab_idx, buffer_idx));
+                }
+            }
+        }
+        found
+    }
+
+    /// The alternate of the file at `path` under the configured rules
+    pub fn alternate_of(&self, path: &Path) -> Option<Alternate> {
+        alternate::find(&self.config.alternate_files, &self.root, path)
+    }
+
+    /// Where editing statistics are kept
+    pub fn stats_dir(&self) -> PathBuf {
+        self.state_dir.join("stats")
+    }
+
+    /// Start analysing the workspace in the background, unless an analysis
+    /// is already running
+    pub fn analyse(&self) {
+        insights::start(&self.root, &self.config.excluded_dirs);
+    }
+
+    /// Whether the workspace analysis is running
+    pub fn analysing(&self) -> bool {
+        insights::is_running(&self.root)
+    }
+
+    /// Whether the workspace has been analysed (or is being) this session
+    pub fn analysed(&self) -> bool {
+        insights::has_started(&self.root)
+    }
+
+    /// Reload every open insights tab with the latest report
+    pub fn refresh_insights(&mut self) {
+        for tab_idx in 0..self.tabs.len() {
+            for buffer_idx in 0..self.tabs[tab_idx].buffers.len() {
+                if self.tabs[tab_idx].buffers[buffer_idx].virtual_doc == Some(VirtualDocument::Insights) {
+                    let _ = self.refresh_virtual(tab_idx, buffer_idx);
+                }
+            }
+        }
+    }
+
+    /// Write the session's statistics, if they're on; unless `force`, only
+    /// once they're due
+    pub fn save_stats(&mut self, force: bool) {
+        if !self.persists() {
+            return;
+        }
+        let dir = self.stats_dir();
+        if let Some(stats) = self.stats.as_mut().filter(|s| force || s.save_due()) {
+            if let Err(e) = stats.save(&dir) {
+                tracing::warn!("failed to save statistics: {}", e);
+            }
+        }
+    }
+
+    /// Load key bindings, layering the user and workspace keymap.toml over the defaults
+    pub fn load_keymap(&mut self) {
+        self.keymap = Keymap::load(&self.state_file("keymap.toml"));
+    }
+
+    /// The last keyboard macro recorded in this workspace
+    pub fn macro_file(&self) -> PathBuf {
+        self.state_file("macro.json")
+    }
+
+    /// Editor log for this workspace, `.fackr/logs/fackr.log`
+    pub fn log_file(&self) -> PathBuf {
+        self.state_dir.join("logs").join("fackr.log")
+    }
+
+    /// Save workspace configuration to .fackr/config.json
+    pub fn save_config(&self) -> Result<()> {
+        if !self.persists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.state_dir)?;
+        let config_path = self.state_dir.join("config.json");
+        let json = serde_json::to_string_pretty(&self.config)?;
+        std::fs::write(&config_path, json)?;
+        Ok(())
+    }
+
     /// Open a workspace with a specific file
     pub fn open_with_file(file_path: &Path) -> Result<Self> {
         // Canonicalize the path to handle relative paths
@@ -772,7 +1612,7 @@ impl Workspace {
 
     /// Load workspace state from .fackr/workspace.json
     pub fn load(&mut self) -> Result<()> {
-        let state_path = self.root.join(".fackr").join("workspace.json");
+        let state_path = self.state_file("workspace.json");
         if !state_path.exists() {
             return Ok(());
         }
@@ -783,14 +1623,24 @@ impl Workspace {
             Ok(s) => s,
             Err(e) => {
                 // If JSON is corrupted, log and continue with empty workspace
-                eprintln!("Warning: Failed to parse workspace.json: {}", e);
+                tracing::warn!("failed to parse workspace.json: {}", e);
                 return Ok(());
             }
         };
 
+        self.terminal_height = state.terminal_height;
+        self.marks = state.marks;
+        self.recent_files = state.recent_files;
+        self.pinned_clips = state.pinned_clips;
+        self.bookmarks = state.bookmarks;
+        if !self.config.restore_session {
+            return Ok(());
+        }
+
         // Restore tabs from state
         let mut restored_tabs = Vec::new();
-        for tab_state in state.tabs {
+        let mut active_tab = 0;
+        for (tab_idx, tab_state) in state.tabs.into_iter().enumerate() {
             // Try to open each file in the tab
             let mut buffers = Vec::new();
             let mut valid_buffer_map: Vec<Option<usize>> = Vec::new(); // Maps old index to new index
@@ -807,7 +1657,13 @@ impl Workspace {
                     // Only restore if file still exists
                     if full_path.exists() {
                         match BufferEntry::from_file(&full_path, &self.root) {
-                            Ok(entry) => {
+                            Ok(mut entry) => {
+                                // Restore folds that still fit the file
+                                let line_count = entry.buffer.line_count();
+                                entry.buffer.folds = file_state.folds.iter()
+                                    .copied()
+                                    .filter(|&(start, end)| start < end && end < line_count)
+                                    .collect();
                                 valid_buffer_map.push(Some(buffers.len()));
                                 buffers.push(entry);
                             }
@@ -832,41 +1688,44 @@ impl Workspace {
             // Restore panes, mapping buffer indices
             let mut panes = Vec::new();
             for pane_state in &tab_state.panes {
-                // Check if this pane's buffer was successfully loaded
-                if let Some(Some(new_idx)) = valid_buffer_map.get(pane_state.buffer_idx) {
-                    let mut pane = Pane::with_buffer_idx(*new_idx);
-
-                    // Restore cursor position (clamped to buffer bounds)
-                    let buffer = &buffers[*new_idx].buffer;
-                    let line = pane_state.cursor_line.min(buffer.line_count().saturating_sub(1));
-                    let col = if line < buffer.line_count() {
-                        pane_state.cursor_col.min(buffer.line_len(line))
-                    } else {
-                        0
-                    };
+                // A pane whose file is gone shows the tab's first file, so
+                // the split layout keeps its shape
+                let restored = valid_buffer_map.get(pane_state.buffer_idx).copied().flatten();
+                let mut pane = Pane::with_buffer_idx(restored.unwrap_or(0));
+                pane.bounds = PaneBounds {
+                    x_start: pane_state.bounds.x_start,
+                    y_start: pane_state.bounds.y_start,
+                    x_end: pane_state.bounds.x_end,
+                    y_end: pane_state.bounds.y_end,
+                };
+                pane.wrap = pane_state.wrap;
+
+                if let Some(new_idx) = restored {
+                    let buffer = &buffers[new_idx].buffer;
+                    let (anchor_line, anchor_col) = pane_state.anchor.unwrap_or((pane_state.cursor_line, pane_state.cursor_col));
                     pane.cursors = Cursors::from_cursor(Cursor {
-                        line,
-                        col,
-                        desired_col: col,
-                        anchor_line: line,
-                        anchor_col: col,
-                        selecting: false,
+                        line: pane_state.cursor_line,
+                        col: pane_state.cursor_col,
+                        desired_col: pane_state.cursor_col,
+                        anchor_line,
+                        anchor_col,
+                        selecting: pane_state.anchor.is_some(),
                     });
+                    for c in &pane_state.extra_cursors {
+                        if (c.anchor_line, c.anchor_col) == (c.line, c.col) {
+                            pane.cursors.add(c.line, c.col);
+                        } else {
+                            pane.cursors.add_with_selection(c.line, c.col, c.anchor_line, c.anchor_col);
+                        }
+                    }
+                    // The file may have changed since
+                    pane.cursors.clamp_to(buffer);
 
-                    // Restore viewport
                     pane.viewport_line = pane_state.viewport_line.min(buffer.line_count().saturating_sub(1));
                     pane.viewport_col = pane_state.viewport_col;
-
-                    // Restore bounds
-                    pane.bounds = PaneBounds {
-                        x_start: pane_state.bounds.x_start,
-                        y_start: pane_state.bounds.y_start,
-                        x_end: pane_state.bounds.x_end,
-                        y_end: pane_state.bounds.y_end,
-                    };
-
-                    panes.push(pane);
                 }
+
+                panes.push(pane);
             }
 
             // Ensure at least one pane exists
@@ -877,6 +1736,9 @@ impl Workspace {
             // Clamp active_pane to valid range
             let active_pane = tab_state.active_pane.min(panes.len().saturating_sub(1));
 
+            if tab_idx <= state.active_tab {
+                active_tab = restored_tabs.len();
+            }
             restored_tabs.push(Tab {
                 buffers,
                 panes,
@@ -887,7 +1749,7 @@ impl Workspace {
         // Only replace tabs if we successfully restored at least one
         if !restored_tabs.is_empty() {
             self.tabs = restored_tabs;
-            self.active_tab = state.active_tab.min(self.tabs.len().saturating_sub(1));
+            self.active_tab = active_tab;
         }
 
         Ok(())
@@ -895,18 +1757,23 @@ impl Workspace {
 
     /// Save workspace state to .fackr/workspace.json
     pub fn save(&self) -> Result<()> {
-        self.init()?; // Ensure .fackr/ exists
+        if !self.persists() {
+            return Ok(()); // Another instance owns the workspace state
+        }
+        std::fs::create_dir_all(&self.state_dir)?; // Ensure .fackr/ exists
 
-        let state_path = self.root.join(".fackr").join("workspace.json");
+        let state_path = self.state_dir.join("workspace.json");
 
         // Build serializable state
         let mut tabs = Vec::new();
-        for tab in &self.tabs {
+        let mut active_tab = 0;
+        for (tab_idx, tab) in self.tabs.iter().enumerate() {
             // Collect file states
             let files: Vec<FileState> = tab.buffers.iter().map(|b| {
                 FileState {
                     path: b.path.clone(),
                     is_orphan: b.is_orphan,
+                    folds: b.buffer.folds.clone(),
                 }
             }).collect();
 
@@ -918,6 +1785,15 @@ impl Workspace {
             // Collect pane states
             let panes: Vec<PaneState> = tab.panes.iter().map(|p| {
                 let cursor = p.cursors.primary();
+                let extra_cursors = p.cursors.all().iter().enumerate()
+                    .filter(|(i, _)| *i != p.cursors.primary_index())
+                    .map(|(_, c)| CursorState {
+                        line: c.line,
+                        col: c.col,
+                        anchor_line: c.anchor_line,
+                        anchor_col: c.anchor_col,
+                    })
+                    .collect();
                 PaneState {
                     buffer_idx: p.buffer_idx,
                     cursor_line: cursor.line,
@@ -930,9 +1806,15 @@ impl Workspace {
                         x_end: p.bounds.x_end,
                         y_end: p.bounds.y_end,
                     },
+                    anchor: cursor.has_selection().then_some((cursor.anchor_line, cursor.anchor_col)),
+                    extra_cursors,
+                    wrap: p.wrap,
                 }
             }).collect();
 
+            if tab_idx <= self.active_tab {
+                active_tab = tabs.len();
+            }
             tabs.push(TabState {
                 files,
                 active_pane: tab.active_pane,
@@ -950,8 +1832,13 @@ impl Workspace {
         }
 
         let state = WorkspaceState {
-            active_tab: self.active_tab.min(tabs.len().saturating_sub(1)),
+            active_tab,
             tabs,
+            terminal_height: self.terminal_height,
+            marks: self.marks.clone(),
+            recent_files: self.recent_files.clone(),
+            pinned_clips: self.pinned_clips.clone(),
+            bookmarks: self.bookmarks.clone(),
         };
 
         // Serialize and write
@@ -982,8 +1869,31 @@ impl Workspace {
 
     /// Open a file in a new tab
     pub fn open_file(&mut self, path: &Path) -> Result<()> {
+        // A split tab keeps the file in its active pane, whose history leads back
+        if self.tabs[self.active_tab].pane_count() > 1 && !image::is_image(path) {
+            return self.open_file_in_pane(path);
+        }
+        self.open_file_in_new_tab(path)
+    }
+
+    /// Read ahead the files likely to be opened next: `paths` (absolute)
+    /// and the siblings of the `current` file
+    pub fn prefetch(&self, current: Option<&Path>, paths: &[PathBuf]) {
+        prefetch::request(current, paths, &self.root);
+    }
+
+    /// Open a file in a new tab even when the current tab is split, or
+    /// switch to the tab that already has it
+    pub fn open_file_in_new_tab(&mut self, path: &Path) -> Result<()> {
         // Check if file is already open in any tab's primary buffer
         let abs_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+        // Images open as a preview rather than as (binary) text
+        if image::is_image(&abs_path) && abs_path.is_file() {
+            return self.open_virtual_tab(VirtualDocument::ImagePreview { path: abs_path });
+        }
+
+        self.remember_recent_file(&abs_path);
         for (i, tab) in self.tabs.iter().enumerate() {
             if let Some(tab_path) = tab.path() {
                 let full_path = if tab.is_orphan() {
@@ -1040,27 +1950,201 @@ impl Workspace {
         Ok(())
     }
 
+    /// Show a file in the current tab's active pane (see `Pane::show_buffer`)
+    pub fn open_file_in_pane(&mut self, path: &Path) -> Result<()> {
+        self.remember_recent_file(path);
+        let tab = &mut self.tabs[self.active_tab];
+        if let Some(buffer_idx) = tab.show_file_in_active_pane(path, &self.root)? {
+            let entry = &tab.buffers[buffer_idx];
+            if let Some(file_path) = &entry.path {
+                let full_path = if entry.is_orphan { file_path.clone() } else { self.root.join(file_path) };
+                let content = entry.buffer.contents();
+                let _ = self.lsp.open_document(&full_path.to_string_lossy(), &content);
+            }
+        }
+        Ok(())
+    }
+
     /// Open a file in a vertical split pane in the current tab
     pub fn open_file_in_vsplit(&mut self, path: &Path) -> Result<()> {
+        self.remember_recent_file(path);
         self.tabs[self.active_tab].split_vertical_with_file(path, &self.root)
     }
 
     /// Open a file in a horizontal split pane in the current tab
     pub fn open_file_in_hsplit(&mut self, path: &Path) -> Result<()> {
+        self.remember_recent_file(path);
         self.tabs[self.active_tab].split_horizontal_with_file(path, &self.root)
     }
 
+    /// Move a file to the front of the recent files list
+    fn remember_recent_file(&mut self, path: &Path) {
+        let abs_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let root = self.root.canonicalize().unwrap_or_else(|_| self.root.clone());
+        let Ok(relative) = abs_path.strip_prefix(&root) else {
+            return; // Quick open only lists workspace files
+        };
+        let relative = relative.to_path_buf();
+        self.recent_files.retain(|p| *p != relative);
+        self.recent_files.insert(0, relative);
+        self.recent_files.truncate(RECENT_FILES_LIMIT);
+    }
+
+    /// The workspace's scratchpad notes
+    pub fn notes_path(&self) -> PathBuf {
+        self.state_dir.join(NOTES_FILE)
+    }
+
+    /// Create the notes file if it doesn't exist and, unless the config
+    /// says to commit it, keep it out of git
+    pub fn prepare_notes(&self) -> Result<PathBuf> {
+        let path = self.notes_path();
+        std::fs::create_dir_all(&self.state_dir)?;
+        if !path.exists() {
+            std::fs::write(&path, "")?;
+        }
+        // The per-user cache directory of a degraded workspace is outside git
+        if !self.config.commit_notes && !self.degraded {
+            ignore_in_dir(&self.state_dir, NOTES_FILE)?;
+        }
+        Ok(path)
+    }
+
+    /// Write open notes buffers with unsaved changes; true if any was
+    pub fn save_notes(&mut self) -> Result<bool> {
+        let notes = self.notes_path();
+        let mut saved = false;
+        for tab in &mut self.tabs {
+            for entry in &mut tab.buffers {
+                let Some(path) = &entry.path else { continue };
+                let full_path = if entry.is_orphan { path.clone() } else { self.root.join(path) };
+                if full_path == notes && entry.is_modified() {
+                    entry.save_to(&full_path)?;
+                    saved = true;
+                }
+            }
+        }
+        if saved {
+            let _ = self.delete_backup(&notes);
+        }
+        Ok(saved)
+    }
+
+    /// Move text to the front of the clipboard ring
+    pub fn remember_clipboard(&mut self, text: &str) {
+        if text.is_empty() || self.clipboard_ring.first().is_some_and(|t| t == text) {
+            return;
+        }
+        self.clipboard_ring.retain(|t| t != text);
+        self.clipboard_ring.insert(0, text.to_string());
+        self.clipboard_ring.truncate(CLIPBOARD_RING_LIMIT);
+    }
+
+    /// What Paste Special offers: the pinned entries, then the rest of the
+    /// clipboard ring, each with whether it's pinned
+    pub fn clipboard_entries(&self) -> Vec<(&str, bool)> {
+        let pinned = self.pinned_clips.iter().map(|text| (text.as_str(), true));
+        let ring = self.clipboard_ring.iter().filter(|text| !self.pinned_clips.contains(text));
+        pinned.chain(ring.map(|text| (text.as_str(), false))).collect()
+    }
+
+    /// Pin `text`, or unpin it if it's pinned; returns whether it's pinned now
+    pub fn toggle_pinned_clip(&mut self, text: &str) -> bool {
+        if let Some(i) = self.pinned_clips.iter().position(|t| t == text) {
+            self.pinned_clips.remove(i);
+            return false;
+        }
+        self.pinned_clips.push(text.to_string());
+        true
+    }
+
     /// Create a new empty tab
     pub fn new_tab(&mut self) {
         self.tabs.push(Tab::new());
         self.active_tab = self.tabs.len() - 1;
     }
 
-    /// Open a content tab (for diff views, etc.)
-    pub fn open_content_tab(&mut self, content: &str, display_name: &str) {
-        let tab = Tab::from_content(content, display_name);
-        self.tabs.push(tab);
+    /// Open a virtual document in a new tab, or switch to and refresh the
+    /// tab already showing it
+    pub fn open_virtual_tab(&mut self, doc: VirtualDocument) -> Result<()> {
+        let existing = self.tabs.iter().enumerate().find_map(|(tab_idx, tab)| {
+            tab.buffers.iter()
+                .position(|b| b.virtual_doc.as_ref() == Some(&doc))
+                .map(|buffer_idx| (tab_idx, buffer_idx))
+        });
+        if let Some((tab_idx, buffer_idx)) = existing {
+            self.active_tab = tab_idx;
+            return self.refresh_virtual(tab_idx, buffer_idx);
+        }
+
+        let content = doc.load(&self.root, &self.config.diff_algorithm)?;
+        self.tabs.push(Tab::from_virtual(doc, &content));
         self.active_tab = self.tabs.len() - 1;
+        Ok(())
+    }
+
+    /// Show `doc`, another version of the active file, read-only in a
+    /// vertical split beside it; a pane already comparing the file shows it
+    /// instead. The file's pane stays active.
+    pub fn open_compare(&mut self, doc: VirtualDocument) -> Result<()> {
+        let content = doc.load(&self.root, &self.config.diff_algorithm)?;
+        let tab = &mut self.tabs[self.active_tab];
+        let comparing = tab.panes.iter().position(|pane| {
+            let shown = tab.buffers[pane.buffer_idx].virtual_doc.as_ref();
+            shown.and_then(VirtualDocument::compared_file) == doc.compared_file()
+        });
+        let existing = tab.buffers.iter().position(|b| b.virtual_doc.as_ref() == Some(&doc));
+        let buffer_idx = match existing {
+            Some(buffer_idx) => {
+                let entry = &mut tab.buffers[buffer_idx];
+                entry.set_virtual_content(&content);
+                entry.history = History::new();
+                buffer_idx
+            }
+            None => {
+                tab.buffers.push(BufferEntry::from_virtual(doc, &content));
+                tab.buffers.len() - 1
+            }
+        };
+
+        let active = tab.active_pane;
+        match comparing {
+            Some(pane_idx) => tab.panes[pane_idx].show_buffer(buffer_idx),
+            None => tab.split_vertical_with_buffer(buffer_idx),
+        }
+        tab.active_pane = active;
+        for pane in tab.panes.iter_mut().filter(|p| p.buffer_idx == buffer_idx) {
+            pane.cursors.clamp_to(&tab.buffers[buffer_idx].buffer);
+        }
+        Ok(())
+    }
+
+    /// Regenerate a virtual document's content from its provider
+    pub fn refresh_virtual(&mut self, tab_idx: usize, buffer_idx: usize) -> Result<()> {
+        let root = self.root.clone();
+        let algorithm = self.config.diff_algorithm.clone();
+        let tab = &mut self.tabs[tab_idx];
+        let entry = &mut tab.buffers[buffer_idx];
+        let doc = match &entry.virtual_doc {
+            Some(doc) => doc.clone(),
+            None => anyhow::bail!("Not a virtual document"),
+        };
+        let content = doc.load(&root, &algorithm)?;
+
+        entry.set_virtual_content(&content);
+        entry.history = History::new();
+
+        // Keep each view's cursor where it was, clamped to the new text
+        let last_line = entry.buffer.line_count().saturating_sub(1);
+        for pane in tab.panes.iter_mut().filter(|p| p.buffer_idx == buffer_idx) {
+            pane.cursors.collapse_to_primary();
+            let cursor = pane.cursors.primary_mut();
+            let line = cursor.line.min(last_line);
+            let col = cursor.col.min(entry.buffer.line_len(line));
+            cursor.set(line, col);
+            pane.viewport_line = pane.viewport_line.min(last_line);
+        }
+        Ok(())
     }
 
     /// Close the active tab
@@ -1107,22 +2191,52 @@ impl Workspace {
 
     /// Get the backups directory path
     fn backups_dir(&self) -> PathBuf {
-        self.root.join(".fackr").join("backups")
+        self.state_dir.join("backups")
     }
 
     /// Generate a backup filename for a buffer path
     /// Uses a hash of the path to create a unique but deterministic name
     fn backup_filename(&self, path: &Path) -> String {
+        format!("{}.bak", Self::path_hash(path))
+    }
+
+    fn path_hash(path: &Path) -> String {
         use std::collections::hash_map::DefaultHasher;
         use std::hash::{Hash, Hasher};
 
         let mut hasher = DefaultHasher::new();
         path.hash(&mut hasher);
-        format!("{:016x}.bak", hasher.finish())
+        format!("{:016x}", hasher.finish())
     }
 
-    /// Write a backup for a modified buffer
-    pub fn write_backup(&self, path: &Path, content: &str) -> Result<()> {
+    /// Directory of the backup generations kept for a file
+    fn generations_dir(&self, path: &Path) -> PathBuf {
+        self.backups_dir().join("history").join(Self::path_hash(path))
+    }
+
+    /// Earlier versions of a file kept from idle backups, newest first
+    pub fn backup_generations(&self, path: &Path) -> Vec<Generation> {
+        backup::generations(&self.generations_dir(path))
+    }
+
+    /// Content of a backup generation
+    pub fn read_generation(&self, generation: &Generation) -> Result<String> {
+        backup::read_file(&generation.path)
+    }
+
+    /// Write a backup for a modified buffer.
+    /// Returns false if the buffer exceeds `backup_max_bytes` and was skipped.
+    pub fn write_backup(&self, path: &Path, content: &str) -> Result<bool> {
+        if !self.persists() {
+            return Ok(true); // Backups belong to the instance holding the lock
+        }
+        let limit = self.config.backup_max_bytes;
+        if limit > 0 && content.len() as u64 > limit {
+            // Drop any stale backup so a restore can't resurrect old content
+            let _ = self.delete_backup(path);
+            return Ok(false);
+        }
+
         let backups_dir = self.backups_dir();
         std::fs::create_dir_all(&backups_dir)?;
 
@@ -1130,13 +2244,21 @@ impl Workspace {
 
         // Store as simple format: first line is original path, rest is content
         let backup_content = format!("{}\n{}", path.display(), content);
-        std::fs::write(&backup_path, backup_content)?;
+        backup::write_file(&backup_path, &backup_content, self.config.backup_compress)?;
 
-        Ok(())
+        let keep = self.config.backup_generations;
+        if keep > 0 {
+            backup::save_generation(&self.generations_dir(path), content, self.config.backup_compress, keep)?;
+        }
+
+        Ok(true)
     }
 
     /// Delete backup for a buffer (called after successful save)
     pub fn delete_backup(&self, path: &Path) -> Result<()> {
+        if !self.persists() {
+            return Ok(());
+        }
         let backup_path = self.backups_dir().join(self.backup_filename(path));
         if backup_path.exists() {
             std::fs::remove_file(backup_path)?;
@@ -1146,6 +2268,9 @@ impl Workspace {
 
     /// Delete all backups (called on discard)
     pub fn delete_all_backups(&self) -> Result<()> {
+        if !self.persists() {
+            return Ok(());
+        }
         let backups_dir = self.backups_dir();
         if backups_dir.exists() {
             for entry in std::fs::read_dir(&backups_dir)? {
@@ -1160,6 +2285,9 @@ impl Workspace {
 
     /// Check if there are any backups to restore
     pub fn has_backups(&self) -> bool {
+        if !self.persists() {
+            return false;
+        }
         let backups_dir = self.backups_dir();
         if !backups_dir.exists() {
             return false;
@@ -1188,7 +2316,7 @@ impl Workspace {
                 let backup_path = entry.path();
                 if backup_path.extension().map_or(false, |e| e == "bak") {
                     // Read first line to get original path
-                    if let Ok(content) = std::fs::read_to_string(&backup_path) {
+                    if let Ok(content) = backup::read_file(&backup_path) {
                         if let Some(first_line) = content.lines().next() {
                             backups.push((PathBuf::from(first_line), backup_path));
                         }
@@ -1203,7 +2331,7 @@ impl Workspace {
     /// Restore a backup into its buffer
     /// Returns the original path and content
     pub fn read_backup(&self, backup_path: &Path) -> Result<(PathBuf, String)> {
-        let content = std::fs::read_to_string(backup_path)?;
+        let content = backup::read_file(backup_path)?;
         let mut lines = content.lines();
 
         let original_path = lines.next()
@@ -1269,8 +2397,7 @@ impl Workspace {
 
         // Now save each buffer
         for (tab_idx, buf_idx, full_path) in to_save {
-            self.tabs[tab_idx].buffers[buf_idx].buffer.save(&full_path)?;
-            self.tabs[tab_idx].buffers[buf_idx].mark_saved();
+            self.tabs[tab_idx].buffers[buf_idx].save_to(&full_path)?;
             // Delete backup after successful save
             let _ = self.delete_backup(&full_path);
         }
@@ -1278,8 +2405,9 @@ impl Workspace {
         Ok(())
     }
 
-    /// Write backups for all modified buffers
-    pub fn backup_all_modified(&mut self) -> Result<()> {
+    /// Write backups for all modified buffers.
+    /// Returns the paths skipped for exceeding the size limit.
+    pub fn backup_all_modified(&mut self) -> Result<Vec<PathBuf>> {
         // Collect backup info first to avoid borrow issues
         let mut to_backup: Vec<(PathBuf, String)> = Vec::new();
 
@@ -1299,10 +2427,13 @@ impl Workspace {
             }
         }
 
+        let mut skipped = Vec::new();
         for (full_path, content) in to_backup {
-            self.write_backup(&full_path, &content)?;
+            if !self.write_backup(&full_path, &content)? {
+                skipped.push(full_path);
+            }
         }
-        Ok(())
+        Ok(skipped)
     }
 
     /// Get the workspace directory name (repo name)
@@ -1360,74 +2491,14 @@ impl Workspace {
         self.root.join(".git").exists()
     }
 
-    /// Find a tab by file path, returns tab index if found
-    pub fn find_tab_by_path(&self, path: &std::path::Path) -> Option<usize> {
-        for (tab_idx, tab) in self.tabs.iter().enumerate() {
-            for buffer_entry in &tab.buffers {
-                if let Some(buf_path) = &buffer_entry.path {
-                    // Get full path for comparison
-                    let full_path = if buffer_entry.is_orphan {
-                        buf_path.clone()
-                    } else {
-                        self.root.join(buf_path)
-                    };
-                    if full_path == path {
-                        return Some(tab_idx);
-                    }
-                }
-            }
-        }
-        None
-    }
-
-    /// Apply a text edit to a buffer in a specific tab
-    pub fn apply_text_edit(&mut self, tab_idx: usize, edit: &crate::lsp::TextEdit) {
-        if tab_idx >= self.tabs.len() {
-            return;
-        }
-
-        let tab = &mut self.tabs[tab_idx];
-        if tab.buffers.is_empty() {
-            return;
-        }
-
-        let buffer = &mut tab.buffers[0].buffer;
-
-        // Convert LSP range to buffer char indices
-        let start_line = edit.range.start.line as usize;
-        let start_col = edit.range.start.character as usize;
-        let end_line = edit.range.end.line as usize;
-        let end_col = edit.range.end.character as usize;
-
-        let start_char = buffer.line_col_to_char(start_line, start_col);
-        let end_char = buffer.line_col_to_char(end_line, end_col);
-
-        // Delete the old text first (if range is non-empty)
-        if start_char < end_char {
-            buffer.delete(start_char, end_char);
-        }
-
-        // Insert the new text at start position
-        if !edit.new_text.is_empty() {
-            buffer.insert(start_char, &edit.new_text);
-        }
-        // Buffer automatically tracks modifications via content hash
-    }
-
     /// Find which pane in the active tab contains a screen coordinate
     /// Returns the pane index
-    pub fn pane_at_position(&self, col: u16, row: u16, screen_cols: u16, screen_rows: u16) -> usize {
-        // Calculate offsets for fuss mode and tab bar
-        let fuss_width = if self.fuss.active {
-            self.fuss.width(screen_cols)
-        } else {
-            0
-        };
+    pub fn pane_at_position(&self, col: u16, row: u16, editor: Rect, screen_rows: u16) -> usize {
         // Tab bar is always rendered (takes 1 row)
         let top_offset = 1u16;
 
         self.tabs[self.active_tab].pane_at_screen_position(
-            col, row, screen_cols, screen_rows, fuss_width, top_offset
+            col, row, editor.right(), screen_rows, editor.x, top_offset
         )
     }
 }
@@ -0,0 +1,240 @@
+//! Editing statistics
+//!
+//! Opt-in with `"stats": true` in config.json. A session counts active
+//! time, keystrokes and lines added or removed, in total and per file, and
+//! is written to `.fackr/stats/session-<start>.json`. Time between two key
+//! presses counts as active unless the gap is longer than `IDLE_LIMIT`.
+//! Nothing leaves the machine.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Longest pause between key presses still counted as working
+const IDLE_LIMIT: Duration = Duration::from_secs(60);
+
+/// How often a running session is written out
+const SAVE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Files listed in the report
+const TOP_FILES: usize = 10;
+
+/// Counters for a session or one file in it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Counts {
+    pub active_ms: u64,
+    pub keystrokes: u64,
+    pub lines_added: u64,
+    pub lines_removed: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.active_ms += other.active_ms;
+        self.keystrokes += other.keystrokes;
+        self.lines_added += other.lines_added;
+        self.lines_removed += other.lines_removed;
+    }
+
+    fn record(&mut self, active_ms: u64, line_delta: isize) {
+        self.active_ms += active_ms;
+        self.keystrokes += 1;
+        if line_delta > 0 {
+            self.lines_added += line_delta as u64;
+        } else {
+            self.lines_removed += line_delta.unsigned_abs() as u64;
+        }
+    }
+}
+
+/// One editing session as stored on disk
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Session {
+    /// Unix timestamps
+    started: u64,
+    ended: u64,
+    total: Counts,
+    /// Per file, by path relative to the workspace root
+    files: BTreeMap<String, Counts>,
+}
+
+/// Statistics of the running session
+#[derive(Debug)]
+pub struct SessionStats {
+    session: Session,
+    last_key: Option<Instant>,
+    last_save: Instant,
+}
+
+impl SessionStats {
+    pub fn new() -> Self {
+        Self {
+            session: Session { started: now(), ..Session::default() },
+            last_key: None,
+            last_save: Instant::now(),
+        }
+    }
+
+    /// Count a key press in `file` after which it has `line_delta` more
+    /// (or fewer) lines
+    pub fn record_key(&mut self, file: Option<&str>, line_delta: isize) {
+        let now = Instant::now();
+        let active_ms = self.last_key
+            .map(|last| now.duration_since(last))
+            .filter(|gap| *gap <= IDLE_LIMIT)
+            .map_or(0, |gap| gap.as_millis() as u64);
+        self.last_key = Some(now);
+
+        self.session.total.record(active_ms, line_delta);
+        if let Some(file) = file {
+            self.session.files.entry(file.to_string()).or_default().record(active_ms, line_delta);
+        }
+    }
+
+    /// Whether the session hasn't been written for a while
+    pub fn save_due(&self) -> bool {
+        self.last_save.elapsed() >= SAVE_INTERVAL
+    }
+
+    /// Write the session into `dir` (`.fackr/stats/`), replacing its
+    /// previous save
+    pub fn save(&mut self, dir: &Path) -> Result<()> {
+        self.last_save = Instant::now();
+        if self.session.total.keystrokes == 0 {
+            return Ok(());
+        }
+        std::fs::create_dir_all(dir)?;
+        self.session.ended = now();
+        let json = serde_json::to_string_pretty(&self.session)?;
+        std::fs::write(dir.join(format!("session-{}.json", self.session.started)), json)?;
+        Ok(())
+    }
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Plain-text summary of the sessions saved in `dir`
+pub fn report(dir: &Path) -> Result<String> {
+    let mut sessions: Vec<Session> = Vec::new();
+    if dir.exists() {
+        for entry in std::fs::read_dir(dir)?.flatten() {
+            let Ok(json) = std::fs::read_to_string(entry.path()) else { continue };
+            if let Ok(session) = serde_json::from_str(&json) {
+                sessions.push(session);
+            }
+        }
+    }
+    Ok(format_report(&sessions, now()))
+}
+
+fn format_report(sessions: &[Session], now: u64) -> String {
+    if sessions.is_empty() {
+        return "(no statistics recorded yet)".to_string();
+    }
+
+    // Sessions that ended within `secs` of now, or all of them
+    let since = |secs: Option<u64>| {
+        let mut counts = Counts::default();
+        for session in sessions.iter().filter(|s| secs.is_none_or(|secs| s.ended + secs >= now)) {
+            counts.add(&session.total);
+        }
+        counts
+    };
+    let mut out = String::from("Editing statistics\n\n");
+    out.push_str(&format!("{:<16}{:>10}{:>12}{:>10}{:>10}\n", "", "Active", "Keystrokes", "Added", "Removed"));
+    for (label, counts) in [
+        ("Last 24 hours", since(Some(86_400))),
+        ("Last 7 days", since(Some(7 * 86_400))),
+        ("All time", since(None)),
+    ] {
+        out.push_str(&format!(
+            "{:<16}{:>10}{:>12}{:>10}{:>10}\n",
+            label,
+            format_duration(counts.active_ms),
+            counts.keystrokes,
+            counts.lines_added,
+            counts.lines_removed,
+        ));
+    }
+    out.push_str(&format!("\nSessions: {}\n", sessions.len()));
+
+    let mut files: BTreeMap<&str, Counts> = BTreeMap::new();
+    for session in sessions {
+        for (path, counts) in &session.files {
+            files.entry(path).or_default().add(counts);
+        }
+    }
+    let mut files: Vec<(&str, Counts)> = files.into_iter().collect();
+    files.sort_by(|a, b| b.1.active_ms.cmp(&a.1.active_ms).then(a.0.cmp(b.0)));
+    if !files.is_empty() {
+        out.push_str("\nMost time, all time\n\n");
+        for (path, counts) in files.iter().take(TOP_FILES) {
+            out.push_str(&format!(
+                "{:>10}  +{:<6} -{:<6} {}\n",
+                format_duration(counts.active_ms),
+                counts.lines_added,
+                counts.lines_removed,
+                path,
+            ));
+        }
+    }
+    out
+}
+
+/// e.g. "2h 05m", "12m", "<1m"
+fn format_duration(ms: u64) -> String {
+    let minutes = ms / 60_000;
+    match minutes {
+        0 => "<1m".to_string(),
+        1..=59 => format!("{}m", minutes),
+        _ => format!("{}h {:02}m", minutes / 60, minutes % 60),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_record_line_changes() {
+        let mut counts = Counts::default();
+        counts.record(500, 3);
+        counts.record(250, -1);
+        counts.record(0, 0);
+        assert_eq!(counts, Counts { active_ms: 750, keystrokes: 3, lines_added: 3, lines_removed: 1 });
+    }
+
+    #[test]
+    fn test_report_windows() {
+        let day = 86_400;
+        let recent = Session {
+            started: 10 * day - 100,
+            ended: 10 * day,
+            total: Counts { active_ms: 90 * 60_000, keystrokes: 40, lines_added: 5, lines_removed: 2 },
+            files: [("src/main.rs".to_string(), Counts { active_ms: 90 * 60_000, ..Counts::default() })].into(),
+        };
+        let old = Session { started: day, ended: day, total: Counts { keystrokes: 2, ..Counts::default() }, ..Session::default() };
+        let report = format_report(&[recent, old], 10 * day + 60);
+        let row = |label: &str| -> Vec<String> {
+            let line = report.lines().find(|l| l.starts_with(label)).unwrap_or_default();
+            line[label.len()..].split_whitespace().map(str::to_string).collect()
+        };
+        assert_eq!(row("Last 24 hours"), ["1h", "30m", "40", "5", "2"]);
+        assert_eq!(row("All time"), ["1h", "30m", "42", "5", "2"]);
+        assert!(report.contains("Sessions: 2"));
+        assert!(report.contains("src/main.rs"));
+        assert_eq!(format_duration(30_000), "<1m");
+    }
+}
@@ -0,0 +1,203 @@
+//! Virtual documents
+//!
+//! Read-only buffers whose text comes from a provider (git, a language
+//! server, ...) rather than a file on disk. Each is identified by a URI,
+//! can be regenerated on demand, and never prompts to save.
+
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::git::diff;
+use crate::render::image;
+use crate::util::logging;
+
+use super::{backup, insights, stats};
+
+/// Number of trailing lines shown in the editor log document
+const EDITOR_LOG_LINES: usize = 2000;
+
+/// Where a virtual document's content comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VirtualDocument {
+    /// `git diff HEAD` for a path relative to the workspace root
+    GitDiff { path: PathBuf },
+    /// How a file changed on disk compared with a snapshot of its buffer
+    DiskChanges { path: PathBuf, snapshot: PathBuf },
+    /// `git show` of one commit: its message, stat and patch
+    GitShow { commit: String },
+    /// Tail of the editor's own log file
+    EditorLog { path: PathBuf },
+    /// Tail of a language server's stderr log
+    ServerLog { name: String, path: PathBuf },
+    /// Details of an image file, drawn inline when the terminal supports it
+    ImagePreview { path: PathBuf },
+    /// A log file's text colored by its ANSI escape codes (the raw text is
+    /// loaded; the buffer drops the codes)
+    AnsiColors { path: PathBuf },
+    /// Summary of the editing statistics saved in a stats directory
+    Statistics { dir: PathBuf },
+    /// The latest background analysis of the workspace
+    Insights,
+    /// A file as committed at HEAD, compared side by side with its buffer
+    GitHead { path: PathBuf },
+    /// A backup generation of a file, compared side by side with its buffer
+    Backup { path: PathBuf, generation: PathBuf },
+}
+
+impl VirtualDocument {
+    /// Stable identifier, e.g. `git-diff:src/main.rs`
+    pub fn uri(&self) -> String {
+        match self {
+            VirtualDocument::GitDiff { path } => format!("git-diff:{}", path.display()),
+            VirtualDocument::DiskChanges { path, .. } => format!("disk-changes:{}", path.display()),
+            VirtualDocument::GitShow { commit } => format!("git-show:{}", commit),
+            VirtualDocument::EditorLog { path } => format!("editor-log:{}", path.display()),
+            VirtualDocument::ServerLog { path, .. } => format!("server-log:{}", path.display()),
+            VirtualDocument::ImagePreview { path } => format!("image:{}", path.display()),
+            VirtualDocument::AnsiColors { path } => format!("ansi:{}", path.display()),
+            VirtualDocument::Statistics { dir } => format!("stats:{}", dir.display()),
+            VirtualDocument::Insights => "insights:".to_string(),
+            VirtualDocument::GitHead { path } => format!("git-head:{}", path.display()),
+            VirtualDocument::Backup { generation, .. } => format!("backup:{}", generation.display()),
+        }
+    }
+
+    /// Name shown in the tab bar and status bar
+    pub fn title(&self) -> String {
+        match self {
+            VirtualDocument::GitDiff { path } => format!("[diff] {}", path.display()),
+            VirtualDocument::DiskChanges { path, .. } => {
+                let name = path.file_name().unwrap_or(path.as_os_str());
+                format!("[on disk] {}", name.to_string_lossy())
+            }
+            VirtualDocument::GitShow { commit } => format!("[commit] {}", &commit[..commit.len().min(7)]),
+            VirtualDocument::EditorLog { .. } => "[editor log]".to_string(),
+            VirtualDocument::ServerLog { name, .. } => format!("[log] {}", name),
+            VirtualDocument::ImagePreview { path } => {
+                let name = path.file_name().unwrap_or(path.as_os_str());
+                format!("[image] {}", name.to_string_lossy())
+            }
+            VirtualDocument::Statistics { .. } => "[statistics]".to_string(),
+            VirtualDocument::Insights => "[insights]".to_string(),
+            VirtualDocument::AnsiColors { path } => {
+                let name = path.file_name().unwrap_or(path.as_os_str());
+                format!("[colors] {}", name.to_string_lossy())
+            }
+            VirtualDocument::GitHead { path } => {
+                let name = path.file_name().unwrap_or(path.as_os_str());
+                format!("[HEAD] {}", name.to_string_lossy())
+            }
+            VirtualDocument::Backup { path, .. } => {
+                let name = path.file_name().unwrap_or(path.as_os_str());
+                format!("[backup] {}", name.to_string_lossy())
+            }
+        }
+    }
+
+    /// Log file the document follows as it grows, if any
+    pub fn followed_log(&self) -> Option<&Path> {
+        match self {
+            VirtualDocument::EditorLog { path } | VirtualDocument::ServerLog { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Whether the content is unified diff text
+    pub fn is_diff(&self) -> bool {
+        matches!(
+            self,
+            VirtualDocument::GitDiff { .. } | VirtualDocument::DiskChanges { .. } | VirtualDocument::GitShow { .. }
+        )
+    }
+
+    /// The workspace file another version of which this is, for comparing
+    /// the two side by side
+    pub fn compared_file(&self) -> Option<&Path> {
+        match self {
+            VirtualDocument::GitHead { path } | VirtualDocument::Backup { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// File name used to pick a syntax highlighter, if any
+    pub fn language_hint(&self) -> Option<String> {
+        match self {
+            VirtualDocument::GitDiff { path }
+            | VirtualDocument::DiskChanges { path, .. }
+            | VirtualDocument::GitHead { path }
+            | VirtualDocument::Backup { path, .. } => Some(path.to_string_lossy().into_owned()),
+            VirtualDocument::GitShow { .. }
+            | VirtualDocument::EditorLog { .. }
+            | VirtualDocument::ServerLog { .. }
+            | VirtualDocument::ImagePreview { .. }
+            | VirtualDocument::AnsiColors { .. }
+            | VirtualDocument::Statistics { .. }
+            | VirtualDocument::Insights => None,
+        }
+    }
+
+    /// Generate the document's current content; diffs use the named
+    /// algorithm
+    pub fn load(&self, root: &Path, diff_algorithm: &str) -> Result<String> {
+        let flag = diff::algorithm_flag(diff_algorithm);
+        match self {
+            VirtualDocument::GitDiff { path } => {
+                let diff = git(root, &["diff", &flag, "HEAD", "--"], path)?;
+                Ok(if diff.is_empty() { "(no changes)".to_string() } else { diff })
+            }
+            VirtualDocument::DiskChanges { path, snapshot } => {
+                let diff = diff_files(snapshot, path, &flag)?;
+                Ok(if diff.is_empty() { "(no changes)".to_string() } else { diff })
+            }
+            VirtualDocument::GitShow { commit } => {
+                git(root, &["show", "--stat", "--patch", "--no-color", "--date=short", &flag], Path::new(commit))
+            }
+            VirtualDocument::EditorLog { path } | VirtualDocument::ServerLog { path, .. } => {
+                if !path.exists() {
+                    return Ok("(nothing logged yet)".to_string());
+                }
+                let log = logging::tail(path, EDITOR_LOG_LINES)?;
+                Ok(if log.is_empty() { "(nothing logged yet)".to_string() } else { log })
+            }
+            VirtualDocument::ImagePreview { path } => image::describe(path),
+            VirtualDocument::AnsiColors { path } => Ok(std::fs::read_to_string(root.join(path))?),
+            VirtualDocument::Statistics { dir } => stats::report(dir),
+            VirtualDocument::Insights => Ok(insights::report(root)),
+            VirtualDocument::GitHead { path } => {
+                git(root, &["show"], Path::new(&format!("HEAD:./{}", path.display())))
+            }
+            VirtualDocument::Backup { generation, .. } => backup::read_file(generation),
+        }
+    }
+}
+
+/// `git diff --no-index` from file `old` to file `new`
+fn diff_files(old: &Path, new: &Path, algorithm_flag: &str) -> Result<String> {
+    let output = Command::new("git")
+        .args(["diff", "--no-index", "--no-color", algorithm_flag, "--"])
+        .arg(old)
+        .arg(new)
+        .output()?;
+    // Exit code 1 just means the files differ
+    if !matches!(output.status.code(), Some(0 | 1)) {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{}", stderr.lines().next().unwrap_or("git failed").trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Run a git subcommand in `root` with a trailing path (or revision) argument
+fn git(root: &Path, args: &[&str], path: &Path) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{}", stderr.lines().next().unwrap_or("git failed").trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,110 @@
+//! Language server warm-up
+//!
+//! Language servers can take seconds to boot, which the first go to
+//! definition or hover would otherwise wait out. When a workspace opens, a
+//! background thread works out its main languages: those named by build
+//! files at the root (`Cargo.toml`, `package.json`, `go.mod`, ...), then
+//! those with a good share of its source files. The editor starts their
+//! servers as soon as the answer arrives, before any of their files is
+//! opened. `"lsp_warmup": false` in config.json turns this off.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+use crate::lsp::detect_language;
+
+use super::files::list_files;
+
+/// Build files at the root and the language each one means
+const MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "rust"),
+    ("go.mod", "go"),
+    ("tsconfig.json", "typescript"),
+    ("package.json", "javascript"),
+    ("pyproject.toml", "python"),
+    ("setup.py", "python"),
+    ("requirements.txt", "python"),
+    ("Gemfile", "ruby"),
+    ("pom.xml", "java"),
+    ("build.gradle", "java"),
+    ("build.gradle.kts", "kotlin"),
+    ("composer.json", "php"),
+    ("mix.exs", "elixir"),
+    ("build.zig", "zig"),
+    ("CMakeLists.txt", "cpp"),
+];
+
+/// Languages of docs, data and config, which don't make a project's language
+const NOT_PROJECT_LANGUAGES: &[&str] =
+    &["markdown", "json", "jsonc", "yaml", "toml", "xml", "html", "css", "dockerfile", "shellscript", "sql"];
+
+/// Languages warmed up at most
+const MAX_LANGUAGES: usize = 3;
+
+/// Share of the source files a language needs to count as a main one, in percent
+const MIN_SHARE: usize = 20;
+
+/// Work out the main languages of the workspace at `root` in the
+/// background, leaving out the `excluded` directories
+pub fn start(root: &Path, excluded: &[PathBuf]) -> Receiver<Vec<&'static str>> {
+    let (tx, rx) = mpsc::channel();
+    let (root, excluded) = (root.to_path_buf(), excluded.to_vec());
+    std::thread::spawn(move || {
+        let markers: Vec<&'static str> = MARKERS.iter().filter(|(name, _)| root.join(name).is_file()).map(|&(_, lang)| lang).collect();
+        let _ = tx.send(main_languages(&markers, &list_files(&root, &excluded)));
+    });
+    rx
+}
+
+/// Languages named by the `markers` found, then those of the `files` with
+/// the largest shares, most first
+fn main_languages(markers: &[&'static str], files: &[PathBuf]) -> Vec<&'static str> {
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    for file in files {
+        let language = file.to_str().and_then(detect_language);
+        if let Some(language) = language.filter(|l| !NOT_PROJECT_LANGUAGES.contains(l)) {
+            *counts.entry(language).or_default() += 1;
+        }
+    }
+    let total: usize = counts.values().sum();
+    let mut by_count: Vec<(&'static str, usize)> = counts.into_iter().filter(|&(_, n)| n * 100 >= total * MIN_SHARE).collect();
+    by_count.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let mut languages: Vec<&'static str> = Vec::new();
+    for language in markers.iter().copied().chain(by_count.into_iter().map(|(l, _)| l)) {
+        if !languages.contains(&language) {
+            languages.push(language);
+        }
+    }
+    // A TypeScript project's JavaScript goes to the same server
+    if languages.contains(&"typescript") {
+        languages.retain(|&l| l != "javascript");
+    }
+    languages.truncate(MAX_LANGUAGES);
+    languages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_markers_come_first() {
+        let files = paths(&["a.py", "b.py", "c.py", "src/main.rs", "README.md", "x.md", "y.md"]);
+        assert_eq!(main_languages(&["rust"], &files), vec!["rust", "python"]);
+    }
+
+    #[test]
+    fn test_small_shares_and_docs_are_left_out() {
+        let mut names = vec!["one.go"; 9];
+        names.extend(["tool.py", "README.md", "config.yaml"]);
+        assert_eq!(main_languages(&[], &paths(&names)), vec!["go"]);
+        assert!(main_languages(&[], &paths(&["notes.md"])).is_empty());
+        assert_eq!(main_languages(&["typescript", "javascript"], &[]), vec!["typescript"]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Watching the workspace for changes made outside the editor
+//!
+//! Directories are watched one by one rather than recursively, so `.git`,
+//! the state directory and excluded directories (build output, vendored
+//! dependencies) cost no watches and raise no events. Directories created
+//! later are watched as they appear. When the root is too big to scan, only
+//! the directories of open files are watched.
+
+use anyhow::Result;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+/// Directories watched at most, well within the usual inotify limit
+const MAX_WATCHED_DIRS: usize = 4096;
+
+/// What changed on disk since the last poll
+#[derive(Debug, Default)]
+pub struct FileEvents {
+    /// Files written, created, deleted or renamed (absolute paths)
+    pub changed: HashSet<PathBuf>,
+    /// Entries under the root were created, deleted or renamed
+    pub tree_changed: bool,
+}
+
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    root: PathBuf,
+    /// Directories never watched nor reported (absolute)
+    ignored: Vec<PathBuf>,
+    watched: HashSet<PathBuf>,
+    /// Watch new directories under the root as they're created
+    follow_new_dirs: bool,
+}
+
+impl FileWatcher {
+    pub fn new(root: &Path, ignored: Vec<PathBuf>) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+        Ok(Self {
+            watcher,
+            events,
+            root: root.to_path_buf(),
+            ignored,
+            watched: HashSet::new(),
+            follow_new_dirs: false,
+        })
+    }
+
+    /// Set the directories left unwatched; ones already watched stay
+    /// watched but are no longer reported
+    pub fn set_ignored(&mut self, ignored: Vec<PathBuf>) {
+        self.ignored = ignored;
+    }
+
+    /// Watch `dir` and every directory below it that isn't ignored, and
+    /// from now on new directories under the root as well
+    pub fn watch_tree(&mut self, dir: &Path) {
+        self.follow_new_dirs = true;
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            if self.is_ignored(&dir) || !self.watch_dir(&dir) {
+                continue;
+            }
+            let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+            // Symlinked directories aren't followed, so loops can't happen
+            pending.extend(entries.flatten().filter(|e| e.file_type().is_ok_and(|t| t.is_dir())).map(|e| e.path()));
+        }
+    }
+
+    /// Watch the directory of each of `files`
+    pub fn watch_parents<'a>(&mut self, files: impl IntoIterator<Item = &'a Path>) {
+        for file in files {
+            if let Some(dir) = file.parent().filter(|dir| !self.is_ignored(dir)) {
+                self.watch_dir(dir);
+            }
+        }
+    }
+
+    /// Returns whether `dir` is watched now
+    fn watch_dir(&mut self, dir: &Path) -> bool {
+        if self.watched.contains(dir) {
+            return true;
+        }
+        if self.watched.len() >= MAX_WATCHED_DIRS
+            || self.watcher.watch(dir, RecursiveMode::NonRecursive).is_err()
+        {
+            return false;
+        }
+        self.watched.insert(dir.to_path_buf());
+        true
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        self.ignored.iter().any(|dir| path.starts_with(dir))
+    }
+
+    /// Collect the changes reported since the last call
+    pub fn poll(&mut self) -> FileEvents {
+        let mut changes = FileEvents::default();
+        let events: Vec<Event> = self.events.try_iter().flatten().collect();
+        for event in events {
+            let entries_changed = match event.kind {
+                EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) => true,
+                EventKind::Modify(ModifyKind::Metadata(_)) => continue,
+                EventKind::Modify(_) => false,
+                _ => continue,
+            };
+            for path in event.paths {
+                if self.is_ignored(&path) {
+                    continue;
+                }
+                if entries_changed {
+                    changes.tree_changed |= path.starts_with(&self.root);
+                    if path.is_dir() {
+                        if self.follow_new_dirs && path.starts_with(&self.root) {
+                            self.watch_tree(&path);
+                        }
+                        continue;
+                    }
+                    // A deleted directory's watch went with it
+                    self.watched.remove(&path);
+                }
+                changes.changed.insert(path);
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Poll until something is reported or a second has passed
+    fn wait_for_events(watcher: &mut FileWatcher) -> FileEvents {
+        let start = Instant::now();
+        let mut all = FileEvents::default();
+        while start.elapsed() < Duration::from_secs(1) {
+            let events = watcher.poll();
+            all.changed.extend(events.changed);
+            all.tree_changed |= events.tree_changed;
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        all
+    }
+
+    #[test]
+    fn test_watch_tree() {
+        let root = std::env::temp_dir().join(format!("fackr-watcher-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+        let mut watcher = FileWatcher::new(&root, vec![root.join("target")]).unwrap();
+        watcher.watch_tree(&root);
+        assert!(watcher.watched.contains(&root.join("src")));
+        assert!(!watcher.watched.contains(&root.join("target")));
+
+        std::fs::write(root.join("src/main.rs"), "fn main() { run() }\n").unwrap();
+        std::fs::write(root.join("target/out"), "").unwrap();
+        let events = wait_for_events(&mut watcher);
+        assert!(events.changed.contains(&root.join("src/main.rs")));
+        assert!(!events.changed.contains(&root.join("target/out")));
+
+        std::fs::create_dir(root.join("tests")).unwrap();
+        wait_for_events(&mut watcher);
+        std::fs::write(root.join("tests/new.rs"), "").unwrap();
+        let events = wait_for_events(&mut watcher);
+        assert!(events.tree_changed);
+        assert!(events.changed.contains(&root.join("tests/new.rs")));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}